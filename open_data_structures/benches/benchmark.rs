@@ -1,6 +1,18 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, Criterion};
+use open_data_structures::data_structure::array_deque::ArrayDeque;
+use open_data_structures::data_structure::array_queue::ArrayQueue;
 use open_data_structures::data_structure::array_stack::ArrayStack;
+use open_data_structures::data_structure::dl_list::DLList;
+use open_data_structures::data_structure::dual_array_deque::DualArrayDeque;
+use open_data_structures::data_structure::fixed_array_stack::FixedArrayStack;
+use open_data_structures::data_structure::fixed_deque::FixedDeque;
+use open_data_structures::data_structure::indexed_binary_heap::IndexedBinaryHeap;
+use open_data_structures::data_structure::sl_list::SLList;
+use open_data_structures::interface::clone_list::CloneList;
+use open_data_structures::interface::heap_size::HeapSize;
 use open_data_structures::interface::list::List;
+use open_data_structures::interface::queue::Queue;
+use open_data_structures::interface::stack::Stack;
 
 fn array_stack_bench(c: &mut Criterion) {
     c.bench_function("ArrayStack Bench", |b| {
@@ -16,5 +28,85 @@ fn array_stack_bench(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, array_stack_bench);
-criterion_main!(benches);
+fn fixed_array_stack_bench(c: &mut Criterion) {
+    c.bench_function("FixedArrayStack Bench", |b| {
+        b.iter(|| {
+            let mut array: FixedArrayStack<String, 100> = FixedArrayStack::new();
+            for i in 0..100 {
+                array.add(i, i.to_string()).unwrap();
+            }
+            for _i in 0..100 {
+                array.remove(0);
+            }
+        })
+    });
+}
+
+fn fixed_deque_bench(c: &mut Criterion) {
+    c.bench_function("FixedDeque Bench", |b| {
+        b.iter(|| {
+            let mut deque: FixedDeque<String, 100> = FixedDeque::new();
+            for i in 0..100 {
+                deque.add_last(i.to_string()).unwrap();
+            }
+            for _i in 0..100 {
+                deque.remove_first();
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    array_stack_bench,
+    fixed_array_stack_bench,
+    fixed_deque_bench
+);
+/// 各データ構造にn個のu64を追加したときの、ヒープ上のメモリ量を表示する
+fn heap_size_report(n: usize) {
+    let report = |name: &str, size: usize| {
+        println!(
+            "{name:<20} {size:>8}バイト (要素あたり {:.1}バイト)",
+            size as f64 / n as f64
+        );
+    };
+    println!("ヒープ使用量 (u64を{n}個追加)");
+
+    let mut array = ArrayStack::new(1);
+    let mut queue = ArrayQueue::new(1);
+    let mut deque = ArrayDeque::new(1);
+    let mut dual = DualArrayDeque::new(1);
+    let mut dl = DLList::new();
+    let mut sl = SLList::new();
+    let mut heap = IndexedBinaryHeap::new();
+    let mut fixed_array: FixedArrayStack<u64, 1000> = FixedArrayStack::new();
+    let mut fixed_deque: FixedDeque<u64, 1000> = FixedDeque::new();
+    for i in 0..n as u64 {
+        array.push(i);
+        queue.add(i);
+        deque.add(deque.size(), i);
+        dual.add(dual.size(), i);
+        dl.add(i as usize, i);
+        sl.push(i);
+        heap.push(i);
+        fixed_array.push(i).unwrap();
+        fixed_deque.add_last(i).unwrap();
+    }
+    report("ArrayStack", array.heap_size());
+    report("ArrayQueue", queue.heap_size());
+    report("ArrayDeque", deque.heap_size());
+    report("DualArrayDeque", dual.heap_size());
+    report("DLList", dl.heap_size());
+    report("SLList", sl.heap_size());
+    report("IndexedBinaryHeap", heap.heap_size());
+    report("FixedArrayStack", fixed_array.heap_size());
+    report("FixedDeque", fixed_deque.heap_size());
+    println!();
+}
+
+// ベンチマークの前にメモリ量を表示するため、criterion_main!の代わりにmainを定義する
+fn main() {
+    heap_size_report(1000);
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
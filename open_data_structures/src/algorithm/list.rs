@@ -0,0 +1,311 @@
+//! Listに対する探索と整列
+//!
+//! Listのget、setだけを使うため、Listを実装するどのデータ構造にも適用できる。
+//! 計算量はget、setの呼び出し回数で表す。
+//! 1回のget、setにかかる時間はデータ構造によって異なり、ArrayStackならO(1)、DLListならO(1+min(i,n-i))となる
+
+use std::cmp::Ordering;
+
+use crate::interface::list::List;
+
+/// 昇順に整列されたlistからxを探す
+///
+/// xと等しい要素があれば、そのうち最も前にある要素の位置をOkで返す。
+/// なければ、整列を保ったままxを追加できる位置をErrで返す
+///
+/// # 計算量
+/// getの呼び出しは高々ceil(log2(n+1))回
+pub fn binary_search<T: Ord, L: List<T>>(list: &L, x: &T) -> Result<usize, usize> {
+    binary_search_by(list, |y| y.cmp(x))
+}
+
+/// 整列されたlistから、比較関数fがEqualを返す要素を探す
+///
+/// fは要素と探している値の比較結果を返す。
+/// 返り値はbinary_searchと同じ
+pub fn binary_search_by<T, L, F>(list: &L, mut f: F) -> Result<usize, usize>
+where
+    L: List<T>,
+    F: FnMut(&T) -> Ordering,
+{
+    // [lo, hi)の範囲に、f(y)がLessでない最初の要素がある
+    let mut lo = 0;
+    let mut hi = list.size();
+    let mut found = false;
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        match f(list.get(mid).unwrap()) {
+            Ordering::Less => lo = mid + 1,
+            Ordering::Equal => {
+                found = true;
+                hi = mid;
+            }
+            Ordering::Greater => hi = mid,
+        }
+    }
+
+    // 等しい要素を一度でも見つけていれば、最も前にある等しい要素はloにある
+    if found {
+        Ok(lo)
+    } else {
+        Err(lo)
+    }
+}
+
+/// 挿入ソートでlistを昇順に整列する。等しい要素の順序は保たれる(安定)
+///
+/// # 計算量
+/// getとsetの呼び出しはそれぞれO(n^2)回
+/// ほとんど整列済みのリストではO(n)回に近くなる
+pub fn insertion_sort<T: Ord + Clone, L: List<T>>(list: &mut L) {
+    insertion_sort_by(list, T::cmp)
+}
+
+/// 比較関数compareに従い、挿入ソートでlistを整列する
+pub fn insertion_sort_by<T, L, F>(list: &mut L, mut compare: F)
+where
+    T: Clone,
+    L: List<T>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    for i in 1..list.size() {
+        let x = list.get(i).unwrap().clone();
+
+        // xより大きい要素を1つずつ後ろにずらし、空いた位置にxを入れる
+        let mut j = i;
+        while j > 0 {
+            let y = list.get(j - 1).unwrap();
+            if compare(y, &x) != Ordering::Greater {
+                break;
+            }
+            let y = y.clone();
+            list.set(j, y);
+            j -= 1;
+        }
+        if j != i {
+            list.set(j, x);
+        }
+    }
+}
+
+/// マージソートでlistを昇順に整列する。等しい要素の順序は保たれる(安定)
+///
+/// # 計算量
+/// getとsetの呼び出しはそれぞれ高々n * ceil(log2 n)回
+/// 長さ1の整列済みの列から始めて、隣り合う列を併合して長さを倍にしていく(ボトムアップ)
+/// 併合する前半の列を退避するため、O(n)の領域を使う
+pub fn merge_sort<T: Ord + Clone, L: List<T>>(list: &mut L) {
+    merge_sort_by(list, T::cmp)
+}
+
+/// 比較関数compareに従い、マージソートでlistを整列する
+pub fn merge_sort_by<T, L, F>(list: &mut L, mut compare: F)
+where
+    T: Clone,
+    L: List<T>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let n = list.size();
+    let mut buf = Vec::with_capacity(n / 2 + 1);
+    let mut width = 1;
+    while width < n {
+        let mut lo = 0;
+        while lo + width < n {
+            let mid = lo + width;
+            let hi = (mid + width).min(n);
+            merge(list, &mut buf, lo, mid, hi, &mut compare);
+            lo = hi;
+        }
+        width *= 2;
+    }
+}
+
+/// 整列済みの列[lo, mid)と[mid, hi)を併合する
+///
+/// 前半の列をbufに退避し、前から順に書き込む。
+/// 書き込む位置は後半の列の未読の位置を追い越さないため、後半の列は退避しなくて良い。
+/// 前半の列を書き終えた時点で、残りの後半の列はすでに正しい位置にある
+fn merge<T, L, F>(list: &mut L, buf: &mut Vec<T>, lo: usize, mid: usize, hi: usize, compare: &mut F)
+where
+    T: Clone,
+    L: List<T>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    buf.clear();
+    buf.extend((lo..mid).map(|i| list.get(i).unwrap().clone()));
+
+    let mut left = buf.drain(..).peekable();
+    let mut j = mid; // 後半の列の次に読む位置
+    let mut right = None; // 後半の列から読んだ、まだ書き込んでいない要素
+    let mut k = lo; // 次に書き込む位置
+    while let Some(x) = left.peek() {
+        if right.is_none() && j < hi {
+            right = Some(list.get(j).unwrap().clone());
+            j += 1;
+        }
+        // 等しい場合は前半の要素を先にすることで、安定にする
+        let y = match right.take() {
+            Some(y) if compare(&y, x) == Ordering::Less => y,
+            y => {
+                right = y;
+                left.next().unwrap()
+            }
+        };
+        list.set(k, y);
+        k += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::data_structure::array_stack::ArrayStack;
+    use crate::testing::Rng;
+    use pretty_assertions::assert_eq;
+    use std::cell::Cell;
+
+    /// getとsetの呼び出し回数を数えるList
+    struct CountingList<T> {
+        v: Vec<T>,
+        gets: Cell<usize>,
+        sets: usize,
+    }
+
+    impl<T> CountingList<T> {
+        fn new(v: Vec<T>) -> Self {
+            Self {
+                v,
+                gets: Cell::new(0),
+                sets: 0,
+            }
+        }
+    }
+
+    impl<T> List<T> for CountingList<T> {
+        fn size(&self) -> usize {
+            self.v.len()
+        }
+
+        fn get(&self, i: usize) -> Option<&T> {
+            self.gets.set(self.gets.get() + 1);
+            self.v.get(i)
+        }
+
+        fn set(&mut self, i: usize, x: T) -> T {
+            self.sets += 1;
+            std::mem::replace(&mut self.v[i], x)
+        }
+
+        fn add(&mut self, i: usize, x: T) {
+            self.v.insert(i, x)
+        }
+
+        fn remove(&mut self, i: usize) -> T {
+            self.v.remove(i)
+        }
+    }
+
+    fn random_vec(rng: &mut Rng, n: usize) -> Vec<i32> {
+        (0..n).map(|_| rng.below(n / 2 + 1) as i32).collect()
+    }
+
+    /// ceil(log2 n)
+    fn ceil_log2(n: usize) -> usize {
+        n.next_power_of_two().trailing_zeros() as usize
+    }
+
+    #[test]
+    fn test_binary_search() {
+        let mut list: ArrayStack<i32> = ArrayStack::new(1);
+        for (i, x) in [1, 3, 3, 3, 5, 8].into_iter().enumerate() {
+            list.add(i, x);
+        }
+        assert_eq!(binary_search(&list, &3), Ok(1));
+        assert_eq!(binary_search(&list, &8), Ok(5));
+        assert_eq!(binary_search(&list, &0), Err(0));
+        assert_eq!(binary_search(&list, &4), Err(4));
+        assert_eq!(binary_search(&list, &9), Err(6));
+        assert_eq!(binary_search(&ArrayStack::new(1), &1), Err(0));
+
+        // 最も前にある等しい要素を見つけ、getはceil(log2(n+1))回まで
+        let mut rng = Rng::new(0);
+        for n in [1, 2, 7, 8, 100, 1000] {
+            let mut v = random_vec(&mut rng, n);
+            v.sort();
+            let list = CountingList::new(v.clone());
+            for x in -1..=(n / 2 + 1) as i32 {
+                list.gets.set(0);
+                let expected = match v.iter().position(|&y| y >= x) {
+                    Some(i) if v[i] == x => Ok(i),
+                    Some(i) => Err(i),
+                    None => Err(n),
+                };
+                assert_eq!(binary_search(&list, &x), expected);
+                assert!(list.gets.get() <= ceil_log2(n + 1), "n = {n}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_insertion_sort() {
+        let mut rng = Rng::new(1);
+        for n in [0, 1, 2, 10, 100] {
+            let v = random_vec(&mut rng, n);
+            let mut list = CountingList::new(v.clone());
+            insertion_sort(&mut list);
+            let mut expected = v;
+            expected.sort();
+            assert_eq!(list.v, expected);
+            assert!(list.gets.get() <= n * (n + 1) / 2, "n = {n}");
+            assert!(list.sets <= n * (n + 1) / 2, "n = {n}");
+        }
+
+        // 整列済みならgetは2(n-1)回、setは0回
+        let mut list = CountingList::new((0..100).collect());
+        insertion_sort(&mut list);
+        assert_eq!(list.gets.get(), 99 + 99);
+        assert_eq!(list.sets, 0);
+    }
+
+    #[test]
+    fn test_merge_sort() {
+        let mut rng = Rng::new(2);
+        for n in [0, 1, 2, 3, 10, 64, 100, 1000] {
+            let v = random_vec(&mut rng, n);
+            let mut list = CountingList::new(v.clone());
+            merge_sort(&mut list);
+            let mut expected = v;
+            expected.sort();
+            assert_eq!(list.v, expected);
+            assert!(list.gets.get() <= n * ceil_log2(n), "n = {n}");
+            assert!(list.sets <= n * ceil_log2(n), "n = {n}");
+        }
+
+        // ArrayStackでも整列できる
+        let mut list: ArrayStack<i32> = ArrayStack::new(1);
+        for (i, x) in [5, 2, 9, 1, 5, 6].into_iter().enumerate() {
+            list.add(i, x);
+        }
+        merge_sort(&mut list);
+        let actual: Vec<i32> = (0..list.size()).map(|i| *list.get(i).unwrap()).collect();
+        assert_eq!(actual, vec![1, 2, 5, 5, 6, 9]);
+    }
+
+    #[test]
+    fn test_stable() {
+        // キーだけで比較し、等しいキーの要素が元の順序を保つかを検査する
+        let mut rng = Rng::new(3);
+        let v: Vec<(i32, usize)> = (0..200).map(|i| (rng.below(10) as i32, i)).collect();
+        let mut expected = v.clone();
+        expected.sort_by_key(|&(k, _)| k);
+
+        let mut list = CountingList::new(v.clone());
+        merge_sort_by(&mut list, |a, b| a.0.cmp(&b.0));
+        assert_eq!(list.v, expected);
+
+        let mut list = CountingList::new(v);
+        insertion_sort_by(&mut list, |a, b| a.0.cmp(&b.0));
+        assert_eq!(list.v, expected);
+    }
+}
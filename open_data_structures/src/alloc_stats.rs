@@ -0,0 +1,119 @@
+//! 連結リストなどのノードの確保と解放の計数
+//!
+//! `alloc-stats`機能を有効にすると、ノードを持つデータ構造はノードごとにNodeTokenを持ち、
+//! その生成と破棄をデータ構造の名前ごとに数える。テストで確保した数と解放した数が一致することを確かめ、
+//! Rcの循環参照によるリークなどを早いうちに見つけるために使う。
+//! 機能が無効な場合、NodeTokenは大きさ0の型で何も数えない。
+//!
+//! 計数はスレッドごとに行うため、並列に実行される他のテストの影響を受けない
+
+/// ノードの確保と解放の回数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// 確保したノードの数
+    pub allocs: usize,
+    /// 解放したノードの数
+    pub frees: usize,
+    /// 同時に生きていたノードの数の最大値
+    pub peak: usize,
+}
+
+impl AllocStats {
+    /// 現在生きているノードの数
+    pub fn live(&self) -> usize {
+        self.allocs - self.frees
+    }
+}
+
+/// ノードに埋め込み、確保と解放を数えるための値
+#[derive(Debug)]
+pub struct NodeToken {
+    #[cfg(feature = "alloc-stats")]
+    name: &'static str,
+}
+
+impl NodeToken {
+    /// 名前がnameのデータ構造のノードを1つ確保したことを記録する
+    #[cfg(feature = "alloc-stats")]
+    pub fn new(name: &'static str) -> Self {
+        imp::record(name, |s| {
+            s.allocs += 1;
+            s.peak = s.peak.max(s.live());
+        });
+        Self { name }
+    }
+
+    /// 機能が無効な場合は何も記録しない
+    #[cfg(not(feature = "alloc-stats"))]
+    pub fn new(_name: &'static str) -> Self {
+        Self {}
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+impl Drop for NodeToken {
+    fn drop(&mut self) {
+        imp::record(self.name, |s| {
+            assert!(
+                s.live() > 0,
+                "{}のノードを確保した数より多く解放しました",
+                self.name
+            );
+            s.frees += 1;
+        });
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+pub use imp::{reset, stats};
+
+#[cfg(feature = "alloc-stats")]
+mod imp {
+    use super::AllocStats;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    thread_local! {
+        static STATS: RefCell<HashMap<&'static str, AllocStats>> = RefCell::new(HashMap::new());
+    }
+
+    pub(super) fn record(name: &'static str, f: impl FnOnce(&mut AllocStats)) {
+        STATS.with(|stats| f(stats.borrow_mut().entry(name).or_default()));
+    }
+
+    /// 名前がnameのデータ構造について、このスレッドで数えた回数を返す
+    pub fn stats(name: &str) -> AllocStats {
+        STATS.with(|stats| stats.borrow().get(name).copied().unwrap_or_default())
+    }
+
+    /// このスレッドで数えた回数をすべて0に戻す
+    pub fn reset() {
+        STATS.with(|stats| stats.borrow_mut().clear());
+    }
+}
+
+#[cfg(all(test, feature = "alloc-stats"))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_node_token() {
+        reset();
+        let a = NodeToken::new("test");
+        let b = NodeToken::new("test");
+        drop(a);
+        let c = NodeToken::new("test");
+        assert_eq!(
+            stats("test"),
+            AllocStats {
+                allocs: 3,
+                frees: 1,
+                peak: 2
+            }
+        );
+        drop((b, c));
+        assert_eq!(stats("test").live(), 0);
+        assert_eq!(stats("other"), AllocStats::default());
+    }
+}
@@ -1,6 +1,12 @@
 pub mod array_deque;
 pub mod array_queue;
 pub mod array_stack;
+pub mod b_tree;
+pub mod block_store;
 pub mod dl_list;
 pub mod dual_array_deque;
+pub mod fixed_array_stack;
+pub mod fixed_deque;
+pub mod indexed_binary_heap;
 pub mod sl_list;
+pub mod work_stealing;
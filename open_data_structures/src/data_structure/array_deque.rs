@@ -1,7 +1,10 @@
+use crate::interface::dequeue::Deque;
+use crate::interface::heap_size::HeapSize;
 use crate::interface::list::List;
 
 /// 両端に対して追加と削除が効率的にできる
-struct ArrayDeque<T> {
+#[derive(Debug)]
+pub struct ArrayDeque<T> {
     a: Box<[T]>,
     j: usize,
     n: usize,
@@ -115,12 +118,54 @@ where
     }
 }
 
+/// 両端への追加と削除は、Listのaddとremoveでi = 0またはi = nを指定したものになる
+///
+/// ## 計算量
+/// * O(1)(resizeを除く)
+impl<T> Deque<T> for ArrayDeque<T>
+where
+    T: Default + Clone,
+{
+    fn add_first(&mut self, x: T) {
+        self.add(0, x);
+    }
+
+    fn remove_first(&mut self) -> Option<T> {
+        if self.n == 0 {
+            return None;
+        }
+        Some(self.remove(0))
+    }
+
+    fn add_last(&mut self, x: T) {
+        self.add(self.n, x);
+    }
+
+    fn remove_last(&mut self) -> Option<T> {
+        if self.n == 0 {
+            return None;
+        }
+        Some(self.remove(self.n - 1))
+    }
+}
+
+/// 配列の使われていない部分も含める
+impl<T> HeapSize for ArrayDeque<T> {
+    fn heap_size(&self) -> usize {
+        self.a.len() * std::mem::size_of::<T>()
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::testing::{check_list, gen_list_op, quickcheck};
     use pretty_assertions::assert_eq;
 
+    crate::testing::conformance::test_list_impl!(ArrayDeque::new(1));
+    crate::testing::conformance::test_deque_impl!(ArrayDeque::new(1));
+
     #[test]
     fn test_list() {
         let mut array = ArrayDeque::new(12);
@@ -172,4 +217,23 @@ mod tests {
         assert_eq!(array.j, 11);
         assert_eq!(array.n, 10);
     }
+
+    #[test]
+    fn test_deque() {
+        let mut deque = ArrayDeque::new(1);
+        deque.add_last(2);
+        deque.add_first(1);
+        deque.add_last(3);
+        assert_eq!(deque.size(), 3);
+        assert_eq!(deque.remove_first(), Some(1));
+        assert_eq!(deque.remove_last(), Some(3));
+        assert_eq!(deque.remove_last(), Some(2));
+        assert_eq!(deque.remove_first(), None);
+        assert_eq!(deque.remove_last(), None);
+    }
+
+    #[test]
+    fn test_list_random() {
+        quickcheck(1000, gen_list_op, |ops| check_list(ArrayDeque::new(1), ops));
+    }
 }
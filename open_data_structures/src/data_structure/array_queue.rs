@@ -1,3 +1,4 @@
+use crate::interface::heap_size::HeapSize;
 use crate::interface::queue::Queue;
 
 /// Queueインタフェースの実装
@@ -6,7 +7,7 @@ use crate::interface::queue::Queue;
 /// add(x), remove()の実行時間はO(1)
 /// 空のArrayQueueに対して任意のm個のadd(i,x)およびremove(i)からなる操作の列を実行する。
 /// このときreizeにかかる時間はO(m)
-struct ArrayQueue<T> {
+pub struct ArrayQueue<T> {
     a: Box<[T]>, // 循環配列
     j: usize,    // 次に削除する要素を追跡するインデックス
     n: usize,    // キューの要素数
@@ -44,6 +45,9 @@ where
     }
 
     fn remove(&mut self) -> Option<T> {
+        if self.n == 0 {
+            return None;
+        }
         let x = self.a[self.j].clone();
         self.j = (self.j + 1) % self.a.len();
         self.n -= 1;
@@ -54,12 +58,21 @@ where
     }
 }
 
+/// 配列の使われていない部分も含める
+impl<T> HeapSize for ArrayQueue<T> {
+    fn heap_size(&self) -> usize {
+        self.a.len() * std::mem::size_of::<T>()
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
     use pretty_assertions::assert_eq;
 
+    crate::testing::conformance::test_queue_impl!(ArrayQueue::new(1));
+
     #[test]
     fn test_queue() {
         let mut array = ArrayQueue::new(6);
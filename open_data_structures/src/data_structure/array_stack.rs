@@ -1,25 +1,48 @@
+use std::rc::Rc;
 use std::vec;
 
+use crate::interface::heap_size::{rc_alloc_size, HeapSize};
 use crate::interface::list::List;
 use crate::interface::stack::Stack;
 
 /// 配列を使ったスタック
+///
+/// clone_cowで作った複製とは配列を共有し、どちらかが変更するときに初めて配列をコピーする(コピーオンライト)
 #[derive(Debug)]
 pub struct ArrayStack<T> {
-    pub a: Box<[T]>, // 通常はVecで良いが、Vecは自動的に配列の長さが変わるため、resizeを実装するためにあえてBoxで持っている
-    pub n: usize,    // 要素に入っているリストの要素数
+    pub a: Rc<[T]>, // 通常はVecで良いが、Vecは自動的に配列の長さが変わるため、resizeを実装するためにあえて固定長の配列で持っている。複製と共有するためにRcで包む
+    pub n: usize,   // 要素に入っているリストの要素数
 }
 
 impl<T: Default + Clone> ArrayStack<T> {
     pub fn new(size: usize) -> Self {
         Self {
-            // ベクターで割り付けてから、Boxに変換する
+            // ベクターで割り付けてから、Rcに変換する
             // 参考: https://mmi.hatenablog.com/entry/2017/08/06/230823
-            a: vec![T::default(); size].into_boxed_slice(),
+            a: vec![T::default(); size].into(),
             n: 0,
         }
     }
 
+    /// 配列を共有する複製を作る
+    ///
+    /// # 計算量
+    /// O(1)の時間がかかる
+    /// 配列のコピーは、複製か元のどちらかが最初に変更されるときまで遅延される
+    pub fn clone_cow(&self) -> Self {
+        Self {
+            a: Rc::clone(&self.a),
+            n: self.n,
+        }
+    }
+
+    /// 変更のために配列への可変参照を返す
+    ///
+    /// 配列を他の複製と共有している場合は、ここで配列をコピーして共有を解除する
+    fn a_mut(&mut self) -> &mut [T] {
+        Rc::make_mut(&mut self.a)
+    }
+
     /// 配列の長さを変更する
     ///
     /// # 計算量
@@ -29,11 +52,10 @@ impl<T: Default + Clone> ArrayStack<T> {
     /// 空のArrayStackに対して任意のm個のadd(i,x)およびremove(i)からなる操作の列を実行する。
     /// このときreizeにかかる時間はO(m)
     fn resize(&mut self) {
-        let mut b = vec![T::default(); std::cmp::max(2 * self.n, 1)].into_boxed_slice();
-        for i in 0..self.n {
-            b[i] = self.a[i].clone();
-        }
-        self.a = b;
+        // 新しい配列を割り当てるため、共有していた配列は変更されない
+        let mut b = vec![T::default(); std::cmp::max(2 * self.n, 1)];
+        b[..self.n].clone_from_slice(&self.a[..self.n]);
+        self.a = b.into();
     }
 }
 
@@ -52,7 +74,7 @@ where
 
     // 実行時間はO(1)
     fn set(&mut self, i: usize, x: T) -> T {
-        std::mem::replace(&mut self.a[i], x)
+        std::mem::replace(&mut self.a_mut()[i], x)
     }
 
     // resize()にかかる時間を無視した場合の実行時間 O(1+n-i)
@@ -62,18 +84,22 @@ where
             self.resize();
         }
 
-        for j in (i + 1..=self.n).rev().step_by(1) {
-            self.a[j] = self.a[j - 1].clone();
+        let n = self.n;
+        let a = self.a_mut();
+        for j in (i + 1..=n).rev().step_by(1) {
+            a[j] = a[j - 1].clone();
         }
-        self.a[i] = x;
+        a[i] = x;
         self.n += 1;
     }
 
     // resize()にかかる時間を無視した場合の実行時間 O(1+n-i)
     fn remove(&mut self, i: usize) -> T {
         let x = self.a[i].clone();
-        for j in i..(self.n - 1) {
-            self.a[j] = self.a[j + 1].clone();
+        let n = self.n;
+        let a = self.a_mut();
+        for j in i..(n - 1) {
+            a[j] = a[j + 1].clone();
         }
         self.n -= 1;
         // 配列の長さに対して要素が少なすぎる場合はresizeする
@@ -93,7 +119,19 @@ where
     }
 
     fn pop(&mut self) -> Option<T> {
-        Some(ArrayStack::remove(self, self.n - 1)) // TODO: fix
+        if self.n == 0 {
+            return None;
+        }
+        Some(ArrayStack::remove(self, self.n - 1))
+    }
+}
+
+/// 配列の使われていない部分も含める
+///
+/// clone_cowで配列を共有している場合も、それぞれが配列全体の大きさを返す
+impl<T> HeapSize for ArrayStack<T> {
+    fn heap_size(&self) -> usize {
+        rc_alloc_size(self.a.len() * std::mem::size_of::<T>())
     }
 }
 
@@ -101,8 +139,25 @@ where
 mod tests {
 
     use super::*;
+    use crate::testing::{check_list, gen_list_op, quickcheck, ListOp};
     use pretty_assertions::assert_eq;
 
+    crate::testing::conformance::test_list_impl!(ArrayStack::new(1));
+    crate::testing::conformance::test_stack_impl!(ArrayStack::new(1));
+
+    #[test]
+    fn test_heap_size() {
+        use std::mem::size_of;
+        let mut array: ArrayStack<u64> = ArrayStack::new(4);
+        assert_eq!(array.heap_size(), rc_alloc_size(4 * size_of::<u64>()));
+
+        // 配列の長さを超えるとresizeで2倍になる
+        for i in 0..5 {
+            array.push(i);
+        }
+        assert_eq!(array.heap_size(), rc_alloc_size(size_of::<[u64; 8]>()));
+    }
+
     #[test]
     fn test_resize() {
         let mut array: ArrayStack<i32> = ArrayStack::new(1);
@@ -119,19 +174,19 @@ mod tests {
     fn test_stack() {
         let mut array = ArrayStack::new(2);
         array.push(1);
-        assert_eq!(array.a, vec![1, 0].into_boxed_slice());
+        assert_eq!(*array.a, [1, 0]);
         assert_eq!(array.n, 1);
 
         array.push(2);
-        assert_eq!(array.a, vec![1, 2].into_boxed_slice());
+        assert_eq!(*array.a, [1, 2]);
         assert_eq!(array.n, 2);
 
         assert_eq!(array.pop(), Some(2));
-        assert_eq!(array.a, vec![1, 2].into_boxed_slice());
+        assert_eq!(*array.a, [1, 2]);
         assert_eq!(array.n, 1);
 
         assert_eq!(array.pop(), Some(1));
-        assert_eq!(array.a, vec![0].into_boxed_slice());
+        assert_eq!(*array.a, [0]);
         assert_eq!(array.n, 0);
     }
 
@@ -143,56 +198,120 @@ mod tests {
         array.add(1, "r");
         array.add(2, "e");
         array.add(3, "d");
-        assert_eq!(array.a, vec!["b", "r", "e", "d", "", ""].into_boxed_slice());
+        assert_eq!(*array.a, ["b", "r", "e", "d", "", ""]);
         assert_eq!(array.n, 4);
 
         array.add(2, "e");
-        assert_eq!(
-            array.a,
-            vec!["b", "r", "e", "e", "d", ""].into_boxed_slice()
-        );
+        assert_eq!(*array.a, ["b", "r", "e", "e", "d", ""]);
         assert_eq!(array.n, 5);
 
         array.add(5, "r");
-        assert_eq!(
-            array.a,
-            vec!["b", "r", "e", "e", "d", "r"].into_boxed_slice()
-        );
+        assert_eq!(*array.a, ["b", "r", "e", "e", "d", "r"]);
         assert_eq!(array.n, 6);
 
         array.add(5, "e");
         assert_eq!(
-            array.a,
-            vec!["b", "r", "e", "e", "d", "e", "r", "", "", "", "", ""].into_boxed_slice()
+            *array.a,
+            ["b", "r", "e", "e", "d", "e", "r", "", "", "", "", ""]
         );
         assert_eq!(array.n, 7);
 
         array.remove(4);
         assert_eq!(
-            array.a,
-            vec!["b", "r", "e", "e", "e", "r", "r", "", "", "", "", ""].into_boxed_slice()
+            *array.a,
+            ["b", "r", "e", "e", "e", "r", "r", "", "", "", "", ""]
         );
         assert_eq!(array.n, 6);
 
         array.remove(4);
         assert_eq!(
-            array.a,
-            vec!["b", "r", "e", "e", "r", "r", "r", "", "", "", "", ""].into_boxed_slice()
+            *array.a,
+            ["b", "r", "e", "e", "r", "r", "r", "", "", "", "", ""]
         );
         assert_eq!(array.n, 5);
 
         array.remove(4);
-        assert_eq!(
-            array.a,
-            vec!["b", "r", "e", "e", "", "", "", ""].into_boxed_slice()
-        );
+        assert_eq!(*array.a, ["b", "r", "e", "e", "", "", "", ""]);
         assert_eq!(array.n, 4);
 
         array.set(2, "i");
-        assert_eq!(
-            array.a,
-            vec!["b", "r", "i", "e", "", "", "", ""].into_boxed_slice()
-        );
+        assert_eq!(*array.a, ["b", "r", "i", "e", "", "", "", ""]);
         assert_eq!(array.n, 4);
     }
+
+    #[test]
+    fn test_clone_cow() {
+        let mut array = ArrayStack::new(4);
+        array.push(1);
+        array.push(2);
+
+        // 複製した直後は配列を共有する
+        let mut snapshot = array.clone_cow();
+        assert!(Rc::ptr_eq(&array.a, &snapshot.a));
+
+        // 元を変更すると配列がコピーされ、複製は変更されない
+        array.set(0, 10);
+        assert!(!Rc::ptr_eq(&array.a, &snapshot.a));
+        assert_eq!(*array.a, [10, 2, 0, 0]);
+        assert_eq!(*snapshot.a, [1, 2, 0, 0]);
+
+        // 複製を変更しても元は変更されない
+        let other = snapshot.clone_cow();
+        snapshot.push(3);
+        assert_eq!(snapshot.size(), 3);
+        assert_eq!(*snapshot.a, [1, 2, 3, 0]);
+        assert_eq!(*other.a, [1, 2, 0, 0]);
+        assert_eq!(other.size(), 2);
+        assert_eq!(array.size(), 2);
+    }
+
+    #[test]
+    fn test_clone_cow_random() {
+        // 各操作の前に複製を作り、操作後も複製の内容が変わらないことを確認する
+        quickcheck(1000, gen_list_op, |ops| {
+            let mut array = ArrayStack::new(1);
+            let mut model: Vec<i32> = Vec::new();
+            for (n, op) in ops.iter().enumerate() {
+                let snapshot = array.clone_cow();
+                let before = model.clone();
+                match *op {
+                    ListOp::Add(i, x) => {
+                        let i = i % (model.len() + 1);
+                        model.insert(i, x);
+                        array.add(i, x);
+                    }
+                    ListOp::Remove(i) if !model.is_empty() => {
+                        let i = i % model.len();
+                        model.remove(i);
+                        array.remove(i);
+                    }
+                    ListOp::Set(i, x) if !model.is_empty() => {
+                        let i = i % model.len();
+                        model[i] = x;
+                        array.set(i, x);
+                    }
+                    _ => (),
+                }
+
+                let actual: Vec<i32> = (0..snapshot.size()).map(|i| snapshot.a[i]).collect();
+                if actual != before {
+                    return Err(format!(
+                        "{n}番目の操作 {op:?} の後の複製の要素: {actual:?}, 期待値: {before:?}"
+                    ));
+                }
+                let actual: Vec<i32> = (0..array.size()).map(|i| array.a[i]).collect();
+                if actual != model {
+                    return Err(format!(
+                        "{n}番目の操作 {op:?} の後の要素: {actual:?}, 期待値: {model:?}"
+                    ));
+                }
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_list_random() {
+        quickcheck(1000, gen_list_op, |ops| check_list(ArrayStack::new(1), ops));
+    }
 }
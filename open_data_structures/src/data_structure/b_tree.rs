@@ -0,0 +1,556 @@
+use std::io;
+use std::path::Path;
+
+use crate::data_structure::block_store::{BlockStore, HEADER_SIZE};
+
+/// B木のメタデータのブロックに書き込む識別子
+const MAGIC: u64 = u64::from_le_bytes(*b"odsbtr01");
+
+/// メタデータを保存するブロックの番号。ファイルを作るときに最初に確保する
+const META_BLOCK: usize = 1;
+
+/// B木のノード
+///
+/// キーと値をkeys[0] < keys[1] < ... の順に持つ。
+/// 内部ノードはキーの数+1個の子を持ち、children[i]の部分木のキーはkeys[i-1]とkeys[i]の間にある
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Node {
+    id: usize,            // ノードを保存しているブロックの番号
+    keys: Vec<u64>,       // キー
+    vals: Vec<u64>,       // keys[i]に対応する値
+    children: Vec<usize>, // 子のブロックの番号。葉なら空
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// ブロックに書き込むバイト列に変換する
+    ///
+    /// キーの数(8バイト)、子の数(8バイト)、キー、値、子の順に並べる
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for n in [self.keys.len(), self.children.len()] {
+            data.extend_from_slice(&(n as u64).to_le_bytes());
+        }
+        for x in self.keys.iter().chain(self.vals.iter()) {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        for c in self.children.iter() {
+            data.extend_from_slice(&(*c as u64).to_le_bytes());
+        }
+        data
+    }
+
+    /// ブロックidから読み込んだバイト列dataを変換する
+    fn decode(id: usize, data: &[u8]) -> io::Result<Self> {
+        let words: Vec<u64> = data
+            .chunks_exact(8)
+            .map(|w| u64::from_le_bytes(w.try_into().unwrap()))
+            .collect();
+        let (n, m) = (words[0] as usize, words[1] as usize);
+        if 2 + 2 * n + m > words.len() || (m != 0 && m != n + 1) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("ブロック{id}はB木のノードではない"),
+            ));
+        }
+        Ok(Self {
+            id,
+            keys: words[2..2 + n].to_vec(),
+            vals: words[2 + n..2 + 2 * n].to_vec(),
+            children: words[2 + 2 * n..2 + 2 * n + m]
+                .iter()
+                .map(|c| *c as usize)
+                .collect(),
+        })
+    }
+}
+
+/// BlockStoreに保存するB木による、u64のキーと値の順序付きマップ
+///
+/// 各ノードは1つのブロックに保存し、根以外のノードはb-1個以上2b-1個以下のキーを持つ。
+/// 探索、追加、削除はいずれも根から葉への1回の下降で行い、読み書きするブロックはO(log_b n)個である。
+/// 追加では満杯のノードを下降する前に分割し、削除ではキーがb-1個のノードを下降する前に
+/// 兄弟から借りるか併合することで、親に戻って修正する必要をなくしている。
+///
+/// 変更はflushを呼び出すまでメモリ上にあり、flushするとBlockStoreのWALを経由してファイルに反映される。
+/// flushせずに破棄した変更は失われる
+pub struct BTree {
+    store: BlockStore,
+    b: usize,    // ノードのキーの最小数+1
+    root: usize, // 根のブロックの番号
+    n: usize,    // キーの数
+}
+
+impl BTree {
+    /// pathのファイルを開く。存在しない場合は、パラメータbのB木を作る
+    ///
+    /// 既存のファイルの場合、bはファイルに保存されたものを使う
+    pub fn open(path: impl AsRef<Path>, b: usize) -> io::Result<Self> {
+        assert!(b >= 2, "bは2以上でなければならない");
+        // キーが2b-1個、子が2b個のノードが入る大きさ
+        let block_size = (2 + 2 * (2 * b - 1) + 2 * b) * 8;
+        let mut store = BlockStore::open(path, block_size.max(HEADER_SIZE))?;
+
+        if store.num_blocks() == 1 {
+            let meta = store.place_block(&[])?;
+            debug_assert_eq!(meta, META_BLOCK);
+            let mut tree = Self {
+                store,
+                b,
+                root: 0,
+                n: 0,
+            };
+            tree.root = tree.place(Vec::new(), Vec::new(), Vec::new())?.id;
+            tree.write_meta();
+            tree.flush()?;
+            return Ok(tree);
+        }
+
+        let meta = store.read_block(META_BLOCK)?;
+        let field = |i: usize| u64::from_le_bytes(meta[i * 8..(i + 1) * 8].try_into().unwrap());
+        if field(0) != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "B木のファイルではない",
+            ));
+        }
+        Ok(Self {
+            store,
+            b: field(1) as usize,
+            root: field(2) as usize,
+            n: field(3) as usize,
+        })
+    }
+
+    /// キーの数nを返す
+    pub fn size(&self) -> usize {
+        self.n
+    }
+
+    /// キーkeyに対応する値を返す
+    ///
+    /// # 計算量
+    /// O(log_b n)個のブロックを読み込む
+    pub fn get(&mut self, key: u64) -> io::Result<Option<u64>> {
+        let mut u = self.read(self.root)?;
+        loop {
+            match u.keys.binary_search(&key) {
+                Ok(i) => return Ok(Some(u.vals[i])),
+                Err(_) if u.is_leaf() => return Ok(None),
+                Err(i) => u = self.read(u.children[i])?,
+            }
+        }
+    }
+
+    /// キーkeyに値valを対応付け、以前の値があればそれを返す
+    ///
+    /// # 計算量
+    /// O(log_b n)個のブロックを読み書きする
+    pub fn put(&mut self, key: u64, val: u64) -> io::Result<Option<u64>> {
+        let mut u = self.read(self.root)?;
+        if u.keys.len() == 2 * self.b - 1 {
+            // 根が満杯の場合は、新しい根の下で分割して木を1段高くする
+            let mut r = self.place(Vec::new(), Vec::new(), vec![u.id])?;
+            self.split_child(&mut r, 0)?;
+            self.root = r.id;
+            self.write_meta();
+            u = r;
+        }
+
+        loop {
+            let mut i = match u.keys.binary_search(&key) {
+                Ok(i) => {
+                    let old = std::mem::replace(&mut u.vals[i], val);
+                    self.write(&u);
+                    return Ok(Some(old));
+                }
+                Err(i) => i,
+            };
+            if u.is_leaf() {
+                u.keys.insert(i, key);
+                u.vals.insert(i, val);
+                self.write(&u);
+                self.n += 1;
+                self.write_meta();
+                return Ok(None);
+            }
+
+            let child = self.read(u.children[i])?;
+            if child.keys.len() == 2 * self.b - 1 {
+                // 分割で上がってきたキーと比較し直す
+                self.split_child(&mut u, i)?;
+                if key == u.keys[i] {
+                    continue;
+                }
+                if key > u.keys[i] {
+                    i += 1;
+                }
+            }
+            u = self.read(u.children[i])?;
+        }
+    }
+
+    /// キーkeyを削除し、対応していた値を返す
+    ///
+    /// # 計算量
+    /// O(log_b n)個のブロックを読み書きする
+    pub fn remove(&mut self, key: u64) -> io::Result<Option<u64>> {
+        let root = self.read(self.root)?;
+        let val = self.remove_from(root, key)?;
+        if val.is_some() {
+            self.n -= 1;
+        }
+
+        // 根のキーがなくなった場合は、唯一の子を新しい根にして木を1段低くする
+        let root = self.read(self.root)?;
+        if root.keys.is_empty() && !root.is_leaf() {
+            self.root = root.children[0];
+            self.store.free_block(root.id);
+        }
+        self.write_meta();
+        Ok(val)
+    }
+
+    /// 変更をファイルに反映する
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.store.flush()
+    }
+
+    /// uを根とする部分木からkeyを削除する。uが根でなければ、uはb個以上のキーを持つ
+    fn remove_from(&mut self, mut u: Node, key: u64) -> io::Result<Option<u64>> {
+        match u.keys.binary_search(&key) {
+            Ok(i) if u.is_leaf() => {
+                u.keys.remove(i);
+                let val = u.vals.remove(i);
+                self.write(&u);
+                Ok(Some(val))
+            }
+            Ok(i) => {
+                // 内部ノードのキーは、左右の部分木の最大か最小のキーで置き換えてから、それを部分木から削除する
+                let val = u.vals[i];
+                let left = self.read(u.children[i])?;
+                if left.keys.len() >= self.b {
+                    let (k, v) = self.max_entry(left.clone())?;
+                    (u.keys[i], u.vals[i]) = (k, v);
+                    self.write(&u);
+                    self.remove_from(left, k)?;
+                    return Ok(Some(val));
+                }
+                let right = self.read(u.children[i + 1])?;
+                if right.keys.len() >= self.b {
+                    let (k, v) = self.min_entry(right.clone())?;
+                    (u.keys[i], u.vals[i]) = (k, v);
+                    self.write(&u);
+                    self.remove_from(right, k)?;
+                    return Ok(Some(val));
+                }
+                // 左右ともにb-1個の場合は、keyを含めて併合してから削除する
+                let merged = self.merge(&mut u, i)?;
+                self.remove_from(merged, key)
+            }
+            Err(_) if u.is_leaf() => Ok(None),
+            Err(i) => {
+                let child = self.read(u.children[i])?;
+                let child = if child.keys.len() < self.b {
+                    self.fill(&mut u, i)?
+                } else {
+                    child
+                };
+                self.remove_from(child, key)
+            }
+        }
+    }
+
+    /// uを根とする部分木の最大のキーと値を返す
+    fn max_entry(&mut self, mut u: Node) -> io::Result<(u64, u64)> {
+        while !u.is_leaf() {
+            u = self.read(*u.children.last().unwrap())?;
+        }
+        Ok((*u.keys.last().unwrap(), *u.vals.last().unwrap()))
+    }
+
+    /// uを根とする部分木の最小のキーと値を返す
+    fn min_entry(&mut self, mut u: Node) -> io::Result<(u64, u64)> {
+        while !u.is_leaf() {
+            u = self.read(u.children[0])?;
+        }
+        Ok((u.keys[0], u.vals[0]))
+    }
+
+    /// キーがb-1個のu.children[i]に、兄弟から借りるか兄弟と併合してキーを補い、補った子を返す
+    fn fill(&mut self, u: &mut Node, i: usize) -> io::Result<Node> {
+        let mut child = self.read(u.children[i])?;
+        if i > 0 {
+            let mut left = self.read(u.children[i - 1])?;
+            if left.keys.len() >= self.b {
+                // 左の兄弟の最大のキーを親に上げ、親のキーを子の先頭に下ろす
+                child.keys.insert(0, u.keys[i - 1]);
+                child.vals.insert(0, u.vals[i - 1]);
+                u.keys[i - 1] = left.keys.pop().unwrap();
+                u.vals[i - 1] = left.vals.pop().unwrap();
+                if let Some(c) = left.children.pop() {
+                    child.children.insert(0, c);
+                }
+                self.write(&left);
+                self.write(u);
+                self.write(&child);
+                return Ok(child);
+            }
+        }
+        if i < u.keys.len() {
+            let mut right = self.read(u.children[i + 1])?;
+            if right.keys.len() >= self.b {
+                // 右の兄弟の最小のキーを親に上げ、親のキーを子の末尾に下ろす
+                child.keys.push(u.keys[i]);
+                child.vals.push(u.vals[i]);
+                u.keys[i] = right.keys.remove(0);
+                u.vals[i] = right.vals.remove(0);
+                if !right.is_leaf() {
+                    child.children.push(right.children.remove(0));
+                }
+                self.write(&right);
+                self.write(u);
+                self.write(&child);
+                return Ok(child);
+            }
+        }
+        if i < u.keys.len() {
+            self.merge(u, i)
+        } else {
+            self.merge(u, i - 1)
+        }
+    }
+
+    /// u.children[i]、u.keys[i]、u.children[i+1]を1つのノードに併合し、併合したノードを返す
+    fn merge(&mut self, u: &mut Node, i: usize) -> io::Result<Node> {
+        let mut left = self.read(u.children[i])?;
+        let right = self.read(u.children[i + 1])?;
+        left.keys.push(u.keys.remove(i));
+        left.vals.push(u.vals.remove(i));
+        left.keys.extend(right.keys);
+        left.vals.extend(right.vals);
+        left.children.extend(right.children);
+        u.children.remove(i + 1);
+        self.store.free_block(right.id);
+        self.write(&left);
+        self.write(u);
+        Ok(left)
+    }
+
+    /// 満杯のu.children[i]を2つに分割し、中央のキーをuに上げる
+    fn split_child(&mut self, u: &mut Node, i: usize) -> io::Result<()> {
+        let b = self.b;
+        let mut child = self.read(u.children[i])?;
+        let keys = child.keys.split_off(b);
+        let vals = child.vals.split_off(b);
+        let children = if child.is_leaf() {
+            Vec::new()
+        } else {
+            child.children.split_off(b)
+        };
+        let right = self.place(keys, vals, children)?;
+        u.keys.insert(i, child.keys.pop().unwrap());
+        u.vals.insert(i, child.vals.pop().unwrap());
+        u.children.insert(i + 1, right.id);
+        self.write(&child);
+        self.write(u);
+        Ok(())
+    }
+
+    fn read(&mut self, id: usize) -> io::Result<Node> {
+        Node::decode(id, &self.store.read_block(id)?)
+    }
+
+    fn write(&mut self, u: &Node) {
+        self.store.write_block(u.id, &u.encode());
+    }
+
+    /// 新しいブロックにノードを作る
+    fn place(&mut self, keys: Vec<u64>, vals: Vec<u64>, children: Vec<usize>) -> io::Result<Node> {
+        let mut u = Node {
+            id: 0,
+            keys,
+            vals,
+            children,
+        };
+        u.id = self.store.place_block(&u.encode())?;
+        Ok(u)
+    }
+
+    /// b、根、キーの数をメタデータのブロックに書き込む
+    fn write_meta(&mut self) {
+        let mut meta = Vec::new();
+        for field in [MAGIC, self.b as u64, self.root as u64, self.n as u64] {
+            meta.extend_from_slice(&field.to_le_bytes());
+        }
+        self.store.write_block(META_BLOCK, &meta);
+    }
+
+    /// B木の条件を満たしているか検査し、すべてのキーを昇順に返す
+    #[cfg(test)]
+    fn check(&mut self) -> Result<Vec<u64>, String> {
+        let mut keys = Vec::new();
+        let mut leaf_depth = None;
+        let root = self.read(self.root).map_err(|e| e.to_string())?;
+        self.check_node(root, 0, true, &mut leaf_depth, &mut keys)?;
+        if !keys.windows(2).all(|w| w[0] < w[1]) {
+            return Err(format!("キーが昇順ではない: {keys:?}"));
+        }
+        if keys.len() != self.n {
+            return Err(format!("キーの数{}がn = {}と異なる", keys.len(), self.n));
+        }
+        Ok(keys)
+    }
+
+    #[cfg(test)]
+    fn check_node(
+        &mut self,
+        u: Node,
+        depth: usize,
+        is_root: bool,
+        leaf_depth: &mut Option<usize>,
+        keys: &mut Vec<u64>,
+    ) -> Result<(), String> {
+        let n = u.keys.len();
+        if n > 2 * self.b - 1 || (!is_root && n < self.b - 1) {
+            return Err(format!("ノード{}のキーの数{n}が範囲外", u.id));
+        }
+        if u.is_leaf() {
+            if *leaf_depth.get_or_insert(depth) != depth {
+                return Err(format!("葉{}の深さ{depth}が他の葉と異なる", u.id));
+            }
+            keys.extend(u.keys.iter());
+            return Ok(());
+        }
+        for (i, c) in u.children.iter().enumerate() {
+            let child = self.read(*c).map_err(|e| e.to_string())?;
+            self.check_node(child, depth + 1, false, leaf_depth, keys)?;
+            if i < n {
+                keys.push(u.keys[i]);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::{temp_path, Rng};
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    #[test]
+    fn test_btree() {
+        let path = temp_path("btree");
+        let mut tree = BTree::open(&path, 2).unwrap();
+        for k in [5, 1, 9, 3, 7, 2, 8, 4, 6] {
+            assert_eq!(tree.put(k, k * 10).unwrap(), None);
+        }
+        assert_eq!(tree.put(3, 33).unwrap(), Some(30));
+        assert_eq!(tree.check(), Ok((1..=9).collect()));
+        assert_eq!(tree.get(3).unwrap(), Some(33));
+        assert_eq!(tree.get(10).unwrap(), None);
+
+        assert_eq!(tree.remove(5).unwrap(), Some(50));
+        assert_eq!(tree.remove(5).unwrap(), None);
+        assert_eq!(tree.size(), 8);
+        tree.flush().unwrap();
+        drop(tree);
+
+        // 開き直してもflushした内容が残っている
+        let mut tree = BTree::open(&path, 4).unwrap();
+        assert_eq!(tree.b, 2);
+        assert_eq!(tree.check(), Ok(vec![1, 2, 3, 4, 6, 7, 8, 9]));
+        assert_eq!(tree.get(9).unwrap(), Some(90));
+
+        // flushしていない変更は失われる
+        tree.put(100, 1).unwrap();
+        drop(tree);
+        let mut tree = BTree::open(&path, 2).unwrap();
+        assert_eq!(tree.get(100).unwrap(), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_btree_random() {
+        // std::collections::BTreeMapをモデルとし、ときどきflushして開き直す
+        let path = temp_path("btree_random");
+        let mut tree = BTree::open(&path, 2).unwrap();
+        let mut model = BTreeMap::new();
+        let mut rng = Rng::new(1);
+        for n in 0..3000 {
+            let key = rng.below(200) as u64;
+            match rng.below(10) {
+                0..=4 => {
+                    let val = rng.next_u64();
+                    assert_eq!(tree.put(key, val).unwrap(), model.insert(key, val), "{n}");
+                }
+                5..=7 => assert_eq!(tree.remove(key).unwrap(), model.remove(&key), "{n}"),
+                8 => assert_eq!(tree.get(key).unwrap(), model.get(&key).copied(), "{n}"),
+                _ => {
+                    tree.flush().unwrap();
+                    tree = BTree::open(&path, 2).unwrap();
+                }
+            }
+            assert_eq!(tree.check(), Ok(model.keys().copied().collect()), "{n}");
+        }
+
+        // 削除したノードのブロックは再利用されるため、ファイルは要素数に比例する大きさにとどまる
+        for key in 0..200 {
+            assert_eq!(tree.remove(key).unwrap(), model.remove(&key));
+        }
+        assert_eq!(tree.check(), Ok(Vec::new()));
+        let mut blocks = Vec::new();
+        for _ in 0..2 {
+            for key in 0..200 {
+                tree.put(key, key).unwrap();
+            }
+            for key in 0..200 {
+                tree.remove(key).unwrap();
+            }
+            blocks.push(tree.store.num_blocks());
+        }
+        assert_eq!(blocks[0], blocks[1]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_btree_crash() {
+        let path = temp_path("btree_crash");
+        let mut tree = BTree::open(&path, 3).unwrap();
+        for k in 0..50 {
+            tree.put(k, k).unwrap();
+        }
+        tree.flush().unwrap();
+
+        // WALを書き出した後にクラッシュした場合は、開き直すとWALから復元される
+        for k in 0..25 {
+            tree.remove(k * 2).unwrap();
+        }
+        tree.put(100, 100).unwrap();
+        tree.store.crash_after_wal().unwrap();
+        let mut tree = BTree::open(&path, 3).unwrap();
+        let mut expected: Vec<u64> = (0..25).map(|k| k * 2 + 1).collect();
+        expected.push(100);
+        assert_eq!(tree.check(), Ok(expected));
+
+        // WALの書き出し中にクラッシュした場合は、直前のflushの状態に戻る
+        tree.put(200, 200).unwrap();
+        let mut wal = path.as_os_str().to_owned();
+        wal.push(".wal");
+        tree.store.crash_after_wal().unwrap();
+        let data = fs::read(&wal).unwrap();
+        fs::write(&wal, &data[..data.len() / 2]).unwrap();
+        let mut tree = BTree::open(&path, 3).unwrap();
+        assert_eq!(tree.get(200).unwrap(), None);
+        assert_eq!(tree.get(100).unwrap(), Some(100));
+        assert_eq!(tree.size(), 26);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// ファイルの先頭に書き込む識別子
+const MAGIC: u64 = u64::from_le_bytes(*b"odsblk01");
+
+/// WALの末尾に書き込む、書き込みが完了したことを表す印
+const WAL_COMMIT: u64 = u64::from_le_bytes(*b"odswal01");
+
+/// ヘッダのバイト数。ブロックの大きさはこれ以上でなければならない
+pub const HEADER_SIZE: usize = 32;
+
+/// 外部記憶を固定長のブロックの配列として扱うBlockStore
+///
+/// ブロック0はヘッダとし、識別子、ブロックの大きさ、ブロック数、解放されたブロックのリストの先頭を保存する。
+/// 解放されたブロックは先頭8バイトに次に解放されたブロックの番号を書き込み、連結リストにする。
+///
+/// 書き込んだブロックはflushまでメモリ上に保持する。
+/// flushでは、変更したブロックを先行書き込みログ(WAL)に書き出して同期してから本体のファイルに反映し、
+/// 最後にWALを削除する。本体への反映中にクラッシュしても、次のopenでWALから反映し直せる。
+/// WALの書き出し中にクラッシュした場合は、WALの末尾に完了の印がないため破棄し、直前のflushの状態に戻る
+pub struct BlockStore {
+    file: File,
+    wal_path: PathBuf,
+    block_size: usize,
+    num_blocks: usize,               // ヘッダを含むブロック数
+    free_head: usize,                // 解放されたブロックのリストの先頭。0なら空
+    dirty: BTreeMap<usize, Vec<u8>>, // flushしていないブロック
+}
+
+impl BlockStore {
+    /// pathのファイルを開く。存在しない場合は、大きさblock_sizeのブロックを持つファイルを作る
+    ///
+    /// 既存のファイルの場合、ブロックの大きさはファイルに保存されたものを使う。
+    /// 反映されていないWALが残っていれば、先に反映する
+    pub fn open(path: impl AsRef<Path>, block_size: usize) -> io::Result<Self> {
+        assert!(
+            block_size >= HEADER_SIZE,
+            "ブロックの大きさ{block_size}がヘッダの大きさ{HEADER_SIZE}より小さい"
+        );
+        let path = path.as_ref();
+        let mut wal_path = path.as_os_str().to_owned();
+        wal_path.push(".wal");
+        let wal_path = PathBuf::from(wal_path);
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        recover(&mut file, &wal_path)?;
+
+        if file.metadata()?.len() == 0 {
+            let mut store = Self {
+                file,
+                wal_path,
+                block_size,
+                num_blocks: 1,
+                free_head: 0,
+                dirty: BTreeMap::new(),
+            };
+            store.flush()?;
+            return Ok(store);
+        }
+
+        let mut header = [0; HEADER_SIZE];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+        let field = |i: usize| u64::from_le_bytes(header[i * 8..(i + 1) * 8].try_into().unwrap());
+        if field(0) != MAGIC {
+            return Err(invalid_data("BlockStoreのファイルではない"));
+        }
+        Ok(Self {
+            file,
+            wal_path,
+            block_size: field(1) as usize,
+            num_blocks: field(2) as usize,
+            free_head: field(3) as usize,
+            dirty: BTreeMap::new(),
+        })
+    }
+
+    /// ブロックの大きさを返す
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// ヘッダを含むブロック数を返す
+    pub fn num_blocks(&self) -> usize {
+        self.num_blocks
+    }
+
+    /// i番目のブロックを読み込む
+    pub fn read_block(&mut self, i: usize) -> io::Result<Vec<u8>> {
+        assert!(
+            0 < i && i < self.num_blocks,
+            "ブロック{i}は存在しない (ブロック数{})",
+            self.num_blocks
+        );
+        if let Some(data) = self.dirty.get(&i) {
+            return Ok(data.clone());
+        }
+        let mut data = vec![0; self.block_size];
+        self.file
+            .seek(SeekFrom::Start((i * self.block_size) as u64))?;
+        self.file.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// i番目のブロックにdataを書き込む。ブロックの大きさに満たない部分は0で埋める
+    ///
+    /// flushを呼び出すまでファイルには書き込まない
+    pub fn write_block(&mut self, i: usize, data: &[u8]) {
+        assert!(
+            0 < i && i < self.num_blocks,
+            "ブロック{i}は存在しない (ブロック数{})",
+            self.num_blocks
+        );
+        assert!(
+            data.len() <= self.block_size,
+            "{}バイトのデータはブロックに入らない",
+            data.len()
+        );
+        let mut block = data.to_vec();
+        block.resize(self.block_size, 0);
+        self.dirty.insert(i, block);
+    }
+
+    /// 新しいブロックにdataを書き込み、その番号を返す
+    ///
+    /// 解放されたブロックがあればそれを再利用し、なければ末尾に追加する
+    pub fn place_block(&mut self, data: &[u8]) -> io::Result<usize> {
+        let i = if self.free_head != 0 {
+            let i = self.free_head;
+            let block = self.read_block(i)?;
+            self.free_head = u64::from_le_bytes(block[..8].try_into().unwrap()) as usize;
+            i
+        } else {
+            self.num_blocks += 1;
+            self.num_blocks - 1
+        };
+        self.write_block(i, data);
+        Ok(i)
+    }
+
+    /// i番目のブロックを解放し、place_blockで再利用できるようにする
+    pub fn free_block(&mut self, i: usize) {
+        let next = (self.free_head as u64).to_le_bytes();
+        self.write_block(i, &next);
+        self.free_head = i;
+    }
+
+    /// 書き込んだブロックとヘッダを、WALを経由してファイルに反映する
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_wal()?;
+        for (i, data) in self.dirty.iter() {
+            self.file
+                .seek(SeekFrom::Start((i * self.block_size) as u64))?;
+            self.file.write_all(data)?;
+        }
+        self.file.sync_all()?;
+        self.dirty.clear();
+        fs::remove_file(&self.wal_path)
+    }
+
+    /// ヘッダを書き込んだブロックに加え、それらをWALに書き出して同期する
+    ///
+    /// WALは、ブロックの大きさ、(ブロック番号, ブロックの内容)の列、完了の印、ブロックの数からなる
+    fn write_wal(&mut self) -> io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        for field in [
+            MAGIC,
+            self.block_size as u64,
+            self.num_blocks as u64,
+            self.free_head as u64,
+        ] {
+            header.extend_from_slice(&field.to_le_bytes());
+        }
+        header.resize(self.block_size, 0);
+        self.dirty.insert(0, header);
+
+        let mut wal = Vec::new();
+        wal.extend_from_slice(&(self.block_size as u64).to_le_bytes());
+        for (i, data) in self.dirty.iter() {
+            wal.extend_from_slice(&(*i as u64).to_le_bytes());
+            wal.extend_from_slice(data);
+        }
+        wal.extend_from_slice(&WAL_COMMIT.to_le_bytes());
+        wal.extend_from_slice(&(self.dirty.len() as u64).to_le_bytes());
+
+        let mut file = File::create(&self.wal_path)?;
+        file.write_all(&wal)?;
+        file.sync_all()
+    }
+
+    /// WALを書き出した直後にクラッシュした状態を再現する
+    #[cfg(test)]
+    pub(crate) fn crash_after_wal(mut self) -> io::Result<()> {
+        self.write_wal()
+    }
+}
+
+/// wal_pathのWALが完全に書き出されていれば、その内容をfileに反映してから削除する
+///
+/// 完了の印がないWALは、書き出し中にクラッシュしたものとして削除する
+fn recover(file: &mut File, wal_path: &Path) -> io::Result<()> {
+    let wal = match fs::read(wal_path) {
+        Ok(wal) => wal,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let word = |pos: usize| u64::from_le_bytes(wal[pos..pos + 8].try_into().unwrap());
+
+    let complete = wal.len() >= 24 && word(wal.len() - 16) == WAL_COMMIT && {
+        let block_size = word(0) as usize;
+        let count = word(wal.len() - 8) as usize;
+        (block_size + 8)
+            .checked_mul(count)
+            .is_some_and(|len| len + 24 == wal.len())
+    };
+    if complete {
+        let block_size = word(0) as usize;
+        let mut pos = 8;
+        while pos < wal.len() - 16 {
+            let i = word(pos) as usize;
+            file.seek(SeekFrom::Start((i * block_size) as u64))?;
+            file.write_all(&wal[pos + 8..pos + 8 + block_size])?;
+            pos += 8 + block_size;
+        }
+        file.sync_all()?;
+    }
+    fs::remove_file(wal_path)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::temp_path;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_place_free() {
+        let path = temp_path("block_store_place");
+        let mut store = BlockStore::open(&path, 64).unwrap();
+        let a = store.place_block(b"a").unwrap();
+        let b = store.place_block(b"b").unwrap();
+        assert_eq!((a, b), (1, 2));
+        assert_eq!(&store.read_block(b).unwrap()[..2], b"b\0");
+
+        // 解放したブロックは後に解放したものから再利用する
+        store.free_block(a);
+        store.free_block(b);
+        assert_eq!(store.place_block(b"c").unwrap(), 2);
+        assert_eq!(store.place_block(b"d").unwrap(), 1);
+        assert_eq!(store.place_block(b"e").unwrap(), 3);
+        store.flush().unwrap();
+        drop(store);
+
+        let mut store = BlockStore::open(&path, 128).unwrap();
+        assert_eq!(store.block_size(), 64);
+        assert_eq!(store.num_blocks(), 4);
+        assert_eq!(&store.read_block(1).unwrap()[..1], b"d");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_recover() {
+        let path = temp_path("block_store_recover");
+        let mut store = BlockStore::open(&path, 32).unwrap();
+        let i = store.place_block(b"old").unwrap();
+        store.flush().unwrap();
+
+        // WALを書き出した後、本体に反映する前にクラッシュしても、openで反映される
+        store.write_block(i, b"new");
+        store.crash_after_wal().unwrap();
+        let mut store = BlockStore::open(&path, 32).unwrap();
+        assert_eq!(&store.read_block(i).unwrap()[..3], b"new");
+
+        // 書き出し途中のWALは破棄する
+        store.write_block(i, b"lost");
+        let j = store.place_block(b"lost").unwrap();
+        let wal_path = store.wal_path.clone();
+        store.crash_after_wal().unwrap();
+        let wal = fs::read(&wal_path).unwrap();
+        fs::write(&wal_path, &wal[..wal.len() - 4]).unwrap();
+
+        let mut store = BlockStore::open(&path, 32).unwrap();
+        assert_eq!(&store.read_block(i).unwrap()[..3], b"new");
+        assert_eq!(store.num_blocks(), j);
+        assert!(!wal_path.exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -2,13 +2,16 @@ use std::borrow::{Borrow, BorrowMut};
 use std::cell::RefCell;
 use std::rc::{Rc, Weak};
 
+use crate::alloc_stats::NodeToken;
 use crate::interface::clone_list::CloneList;
+use crate::interface::heap_size::{rc_alloc_size, HeapSize};
 
 #[derive(Debug)]
 pub struct Node<T> {
     x: T,
     next: Option<Rc<RefCell<Node<T>>>>,
     prev: Option<Weak<RefCell<Node<T>>>>,
+    _token: NodeToken,
 }
 
 impl<T: Default> Node<T> {
@@ -17,6 +20,7 @@ impl<T: Default> Node<T> {
             x: T::default(),
             prev: None,
             next: None,
+            _token: NodeToken::new("DLList"),
         }
     }
 }
@@ -86,6 +90,86 @@ impl<T: Default + Clone> DLList<T> {
         next.map(|p| p.as_ref().borrow_mut().prev = prev);
         self.n -= 1;
     }
+
+    /// i番目以降の要素を切り離し、それらを要素とするリストを返す
+    ///
+    /// # 計算量
+    /// i番目のノードを探すのにO(1 + min(i, n - i))、ノードのつなぎ替えはO(1)の時間がかかる
+    ///
+    /// # Panics
+    /// iが要素数より大きい場合
+    pub fn split_off(&mut self, i: usize) -> Self {
+        assert!(
+            i <= self.n,
+            "split_offの位置{i}が要素数{}を超えています",
+            self.n
+        );
+        let mut other = Self::new();
+        if i == self.n {
+            return other;
+        }
+
+        // uからlastまでをotherに移す
+        let u = self.get_node(i).unwrap();
+        let last = self.last_node();
+        let prev = u
+            .as_ref()
+            .borrow()
+            .prev
+            .as_ref()
+            .and_then(|w| w.upgrade())
+            .unwrap();
+
+        prev.as_ref().borrow_mut().next = Some(Rc::clone(&self.dummy));
+        self.dummy.as_ref().borrow_mut().prev = Some(Rc::downgrade(&prev));
+
+        u.as_ref().borrow_mut().prev = Some(Rc::downgrade(&other.dummy));
+        last.as_ref().borrow_mut().next = Some(Rc::clone(&other.dummy));
+        other.dummy.as_ref().borrow_mut().next = Some(u);
+        other.dummy.as_ref().borrow_mut().prev = Some(Rc::downgrade(&last));
+
+        other.n = self.n - i;
+        self.n = i;
+        other
+    }
+
+    /// otherのすべての要素を末尾に移し、otherを空にする
+    ///
+    /// # 計算量
+    /// ノードのつなぎ替えのみなので、O(1)の時間がかかる
+    pub fn append(&mut self, other: &mut Self) {
+        if other.n == 0 {
+            return;
+        }
+
+        // otherの先頭から末尾までを、selfの末尾とダミーノードの間に挿入する
+        let first = other.dummy.as_ref().borrow().next.clone().unwrap();
+        let last = other.last_node();
+        let tail = self.last_node();
+
+        tail.as_ref().borrow_mut().next = Some(Rc::clone(&first));
+        first.as_ref().borrow_mut().prev = Some(Rc::downgrade(&tail));
+        last.as_ref().borrow_mut().next = Some(Rc::clone(&self.dummy));
+        self.dummy.as_ref().borrow_mut().prev = Some(Rc::downgrade(&last));
+
+        // otherはダミーノードのみの空のリストに戻す
+        other.dummy.as_ref().borrow_mut().next = Some(Rc::clone(&other.dummy));
+        other.dummy.as_ref().borrow_mut().prev = Some(Rc::downgrade(&other.dummy));
+
+        self.n += other.n;
+        other.n = 0;
+    }
+
+    /// 末尾のノードを返す。空の場合はダミーノード
+    fn last_node(&self) -> Rc<RefCell<Node<T>>> {
+        self.dummy
+            .as_ref()
+            .borrow()
+            .prev
+            .as_ref()
+            .and_then(|w| w.upgrade())
+            .unwrap()
+    }
 }
 
 impl<T: Default + Clone> CloneList<T> for DLList<T> {
@@ -115,12 +199,44 @@ impl<T: Default + Clone> CloneList<T> for DLList<T> {
     }
 }
 
+/// ダミーノードと末尾のノードは互いをRcで指しているため、そのままでは循環参照で解放されない
+///
+/// 先頭から順にnextを外して循環を断つ。再帰的に解放しないので、長いリストでもスタックを使い切らない
+impl<T> Drop for DLList<T> {
+    fn drop(&mut self) {
+        let mut next = self.dummy.as_ref().borrow_mut().next.take();
+        while let Some(node) = next {
+            next = node.as_ref().borrow_mut().next.take();
+        }
+    }
+}
+
+/// n個のノードとダミーノードの大きさの合計
+impl<T> HeapSize for DLList<T> {
+    fn heap_size(&self) -> usize {
+        (self.n + 1) * rc_alloc_size(std::mem::size_of::<RefCell<Node<T>>>())
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::testing::Rng;
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn test_heap_size() {
+        // 空のリストでもダミーノードを持つ
+        let mut list: DLList<u64> = DLList::new();
+        let node = rc_alloc_size(std::mem::size_of::<RefCell<Node<u64>>>());
+        assert_eq!(list.heap_size(), node);
+        for i in 0..3 {
+            list.add(i, i as u64);
+        }
+        assert_eq!(list.heap_size(), 4 * node);
+    }
+
     #[test]
     fn test_clone_list() {
         let mut list = DLList::new();
@@ -143,4 +259,124 @@ mod tests {
         assert_eq!(list.get(2).unwrap(), 'c');
         assert_eq!(list.get(3).unwrap(), 'e');
     }
-}
\ No newline at end of file
+
+    fn to_vec(list: &DLList<i32>) -> Vec<i32> {
+        (0..list.size()).map(|i| list.get(i).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_split_off_append() {
+        let mut list = DLList::new();
+        for i in 0..6 {
+            list.add(i, i as i32);
+        }
+
+        let mut tail = list.split_off(4);
+        assert_eq!(to_vec(&list), vec![0, 1, 2, 3]);
+        assert_eq!(to_vec(&tail), vec![4, 5]);
+
+        // 先頭と末尾での分割
+        let mut all = list.split_off(0);
+        assert_eq!(list.size(), 0);
+        assert_eq!(to_vec(&all), vec![0, 1, 2, 3]);
+        let empty = all.split_off(4);
+        assert_eq!(empty.size(), 0);
+
+        // 空のリストへの連結と、空のリストの連結
+        list.append(&mut tail);
+        assert_eq!(tail.size(), 0);
+        list.append(&mut all);
+        list.append(&mut tail);
+        assert_eq!(to_vec(&list), vec![4, 5, 0, 1, 2, 3]);
+
+        // 連結後も追加と削除ができる
+        list.add(6, 6);
+        list.add(0, -1);
+        assert_eq!(list.remove(3), 0);
+        assert_eq!(to_vec(&list), vec![-1, 4, 5, 1, 2, 3, 6]);
+        all.add(0, 7);
+        assert_eq!(to_vec(&all), vec![7]);
+    }
+
+    #[test]
+    #[should_panic(expected = "要素数")]
+    fn test_split_off_out_of_range() {
+        let mut list: DLList<i32> = DLList::new();
+        list.split_off(1);
+    }
+
+    #[test]
+    fn test_split_off_append_random() {
+        // 分割と連結を繰り返し、各位置の要素をVecと比較する
+        // get_nodeはiがn/2以上なら後ろからたどるため、prevのつなぎ替えも検査できる
+        let mut rng = Rng::new(0);
+        let mut lists = [DLList::new(), DLList::new()];
+        let mut models = [Vec::new(), Vec::new()];
+        for k in 0..1000 {
+            let j = rng.below(2);
+            match rng.below(3) {
+                0 => {
+                    let i = rng.below(models[j].len() + 1);
+                    lists[j].add(i, k);
+                    models[j].insert(i, k);
+                }
+                1 => {
+                    let i = rng.below(models[j].len() + 1);
+                    let mut tail = lists[j].split_off(i);
+                    let mut model_tail = models[j].split_off(i);
+                    tail.append(&mut lists[1 - j]);
+                    model_tail.append(&mut models[1 - j]);
+                    lists[1 - j] = tail;
+                    models[1 - j] = model_tail;
+                }
+                _ => {
+                    let (a, b) = lists.split_at_mut(1);
+                    let (ma, mb) = models.split_at_mut(1);
+                    if j == 0 {
+                        a[0].append(&mut b[0]);
+                        ma[0].append(&mut mb[0]);
+                    } else {
+                        b[0].append(&mut a[0]);
+                        mb[0].append(&mut ma[0]);
+                    }
+                }
+            }
+            for j in 0..2 {
+                assert_eq!(lists[j].size(), models[j].len());
+                assert_eq!(to_vec(&lists[j]), models[j]);
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc-stats")]
+    #[test]
+    fn test_alloc_stats() {
+        use crate::alloc_stats;
+
+        // 追加、削除、分割、連結を繰り返しても、生きているノードは要素とダミーノードのみ
+        alloc_stats::reset();
+        let mut rng = Rng::new(1);
+        {
+            let mut lists = [DLList::new(), DLList::new()];
+            for k in 0..500 {
+                let j = rng.below(2);
+                match rng.below(4) {
+                    0 | 1 => lists[j].add(rng.below(lists[j].size() + 1), k),
+                    2 if lists[j].size() > 0 => {
+                        lists[j].remove(rng.below(lists[j].size()));
+                    }
+                    _ => {
+                        let mut tail = lists[j].split_off(rng.below(lists[j].size() + 1));
+                        tail.append(&mut lists[1 - j]);
+                        lists[1 - j] = tail;
+                    }
+                }
+                let live = lists[0].size() + lists[1].size() + 2;
+                assert_eq!(alloc_stats::stats("DLList").live(), live);
+            }
+        }
+        let stats = alloc_stats::stats("DLList");
+        assert_eq!(stats.frees, stats.allocs);
+        assert!(stats.peak >= 2);
+    }
+}
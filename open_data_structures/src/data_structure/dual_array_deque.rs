@@ -1,3 +1,4 @@
+use crate::interface::heap_size::HeapSize;
 use crate::{data_structure::array_stack::ArrayStack, interface::list::List};
 
 #[derive(Debug)]
@@ -35,9 +36,9 @@ where
             for i in 0..nb {
                 ab[i] = self.get(nf + i).unwrap().clone(); // TODO: fix
             }
-            self.front.a = af.into_boxed_slice();
+            self.front.a = af.into();
             self.front.n = nf;
-            self.back.a = ab.into_boxed_slice();
+            self.back.a = ab.into();
             self.back.n = nb;
         }
     }
@@ -87,12 +88,22 @@ where
     }
 }
 
+/// frontとbackの2つの配列の大きさの合計
+impl<T> HeapSize for DualArrayDeque<T> {
+    fn heap_size(&self) -> usize {
+        self.front.heap_size() + self.back.heap_size()
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::testing::{check_list, gen_list_op, quickcheck};
     use pretty_assertions::assert_eq;
 
+    crate::testing::conformance::test_list_impl!(DualArrayDeque::new(1));
+
     #[test]
     fn test_list() {
         let mut array = DualArrayDeque::new(0);
@@ -100,40 +111,38 @@ mod tests {
         array.add(1, "b");
         array.add(2, "c");
         array.add(3, "d");
-        assert_eq!(array.front.a, vec!["a", ""].into_boxed_slice());
+        assert_eq!(*array.front.a, ["a", ""]);
         assert_eq!(array.front.n, 1);
-        assert_eq!(array.back.a, vec!["b", "c", "d", ""].into_boxed_slice());
+        assert_eq!(*array.back.a, ["b", "c", "d", ""]);
         assert_eq!(array.back.n, 3);
         assert_eq!(array.size(), 4);
 
         array.add(3, "x");
-        assert_eq!(array.front.a, vec!["b", "a", "", ""].into_boxed_slice());
+        assert_eq!(*array.front.a, ["b", "a", "", ""]);
         assert_eq!(array.front.n, 2);
-        assert_eq!(
-            array.back.a,
-            vec!["c", "x", "d", "", "", ""].into_boxed_slice()
-        );
+        assert_eq!(*array.back.a, ["c", "x", "d", "", "", ""]);
         assert_eq!(array.back.n, 3);
         assert_eq!(array.size(), 5);
 
         array.add(4, "y");
-        assert_eq!(array.front.a, vec!["b", "a", "", ""].into_boxed_slice());
+        assert_eq!(*array.front.a, ["b", "a", "", ""]);
         assert_eq!(array.front.n, 2);
-        assert_eq!(
-            array.back.a,
-            vec!["c", "x", "y", "d", "", ""].into_boxed_slice()
-        );
+        assert_eq!(*array.back.a, ["c", "x", "y", "d", "", ""]);
         assert_eq!(array.back.n, 4);
         assert_eq!(array.size(), 6);
 
         array.remove(0);
-        assert_eq!(array.front.a, vec!["c", "b", "", ""].into_boxed_slice());
+        assert_eq!(*array.front.a, ["c", "b", "", ""]);
         assert_eq!(array.front.n, 2);
-        assert_eq!(
-            array.back.a,
-            vec!["x", "y", "d", "", "", ""].into_boxed_slice()
-        );
+        assert_eq!(*array.back.a, ["x", "y", "d", "", "", ""]);
         assert_eq!(array.back.n, 3);
         assert_eq!(array.size(), 5);
     }
+
+    #[test]
+    fn test_list_random() {
+        quickcheck(1000, gen_list_op, |ops| {
+            check_list(DualArrayDeque::new(1), ops)
+        });
+    }
 }
@@ -0,0 +1,222 @@
+use crate::interface::heap_size::HeapSize;
+use core::fmt;
+
+/// 容量を超えて要素を追加しようとしたときのエラー
+///
+/// 追加できなかった値を保持しているため、呼び出し側で取り戻せる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityError<T>(pub T);
+
+impl<T> fmt::Display for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "容量を超えて要素を追加しようとした")
+    }
+}
+
+/// 容量がNに固定された、配列を使ったスタック
+///
+/// ArrayStackと異なりresizeを行わず、配列は構造体に直接埋め込むため、生成後にメモリを割り当てることはない。
+/// そのため、ヒープのない環境(no_std)でも利用できる。
+/// 容量を超えて追加しようとした場合は、CapacityErrorを返す
+#[derive(Debug)]
+pub struct FixedArrayStack<T, const N: usize> {
+    a: [Option<T>; N], // 要素はa[0]..a[n-1]に入っていて、それ以外はNone
+    n: usize,          // リストの要素数
+}
+
+impl<T, const N: usize> Default for FixedArrayStack<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> FixedArrayStack<T, N> {
+    pub fn new() -> Self {
+        Self {
+            a: core::array::from_fn(|_| None),
+            n: 0,
+        }
+    }
+
+    /// 追加できる要素数の上限Nを返す
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// リストの長さnを返す
+    pub fn size(&self) -> usize {
+        self.n
+    }
+
+    /// 要素数が容量に達していればtrueを返す
+    pub fn is_full(&self) -> bool {
+        self.n == N
+    }
+
+    /// x(i)の値を返す
+    ///
+    /// # 計算量
+    /// O(1)
+    pub fn get(&self, i: usize) -> Option<&T> {
+        self.a[..self.n].get(i)?.as_ref()
+    }
+
+    /// x(i)の値をxにし、元の値を返す
+    ///
+    /// # 計算量
+    /// O(1)
+    pub fn set(&mut self, i: usize, x: T) -> T {
+        assert!(i < self.n, "添字{i}が要素数{}以上", self.n);
+        self.a[i].replace(x).unwrap()
+    }
+
+    /// xをi番目として追加し、x(i)..x(n-1)を後ろにずらす
+    ///
+    /// 要素数が容量に達している場合は、追加せずにxをCapacityErrorとして返す
+    ///
+    /// # 計算量
+    /// O(1+n-i)
+    pub fn add(&mut self, i: usize, x: T) -> Result<(), CapacityError<T>> {
+        assert!(i <= self.n, "添字{i}が要素数{}より大きい", self.n);
+        if self.is_full() {
+            return Err(CapacityError(x));
+        }
+        // 末尾に追加してからa[i]..a[n]を右に1つ回転させる
+        self.a[self.n] = Some(x);
+        self.a[i..=self.n].rotate_right(1);
+        self.n += 1;
+        Ok(())
+    }
+
+    /// x(i)を削除し、x(i+1)..x(n-1)を前にずらす
+    ///
+    /// # 計算量
+    /// O(1+n-i)
+    pub fn remove(&mut self, i: usize) -> T {
+        assert!(i < self.n, "添字{i}が要素数{}以上", self.n);
+        let x = self.a[i].take().unwrap();
+        self.a[i..self.n].rotate_left(1);
+        self.n -= 1;
+        x
+    }
+
+    /// 値xを末尾に追加する
+    pub fn push(&mut self, x: T) -> Result<(), CapacityError<T>> {
+        self.add(self.n, x)
+    }
+
+    /// 最後に追加された値を削除し、それを返す
+    pub fn pop(&mut self) -> Option<T> {
+        if self.n == 0 {
+            return None;
+        }
+        Some(self.remove(self.n - 1))
+    }
+}
+
+/// 配列は構造体に直接埋め込まれているため、ヒープ上には何も確保しない
+impl<T, const N: usize> HeapSize for FixedArrayStack<T, N> {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::{gen_list_op, quickcheck, ListOp};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_stack() {
+        let mut array: FixedArrayStack<i32, 2> = FixedArrayStack::new();
+        assert_eq!(array.capacity(), 2);
+        assert_eq!(array.push(1), Ok(()));
+        assert_eq!(array.push(2), Ok(()));
+        assert!(array.is_full());
+
+        // 容量を超えた場合は値を返す
+        assert_eq!(array.push(3), Err(CapacityError(3)));
+        assert_eq!(array.size(), 2);
+
+        assert_eq!(array.pop(), Some(2));
+        assert_eq!(array.pop(), Some(1));
+        assert_eq!(array.pop(), None);
+
+        // 容量0のスタックには追加できない
+        let mut empty: FixedArrayStack<i32, 0> = FixedArrayStack::new();
+        assert_eq!(empty.push(1), Err(CapacityError(1)));
+        assert_eq!(empty.pop(), None);
+    }
+
+    #[test]
+    fn test_list() {
+        let mut array: FixedArrayStack<&str, 4> = FixedArrayStack::new();
+        array.add(0, "b").unwrap();
+        array.add(1, "d").unwrap();
+        array.add(1, "r").unwrap();
+        array.add(2, "e").unwrap();
+        assert_eq!(array.a, [Some("b"), Some("r"), Some("e"), Some("d")]);
+        assert_eq!(array.add(0, "x"), Err(CapacityError("x")));
+
+        assert_eq!(array.remove(1), "r");
+        assert_eq!(array.a, [Some("b"), Some("e"), Some("d"), None]);
+        assert_eq!(array.set(2, "x"), "d");
+        assert_eq!(array.get(2), Some(&"x"));
+        assert_eq!(array.get(3), None);
+    }
+
+    #[test]
+    fn test_list_random() {
+        // 容量を超える追加はエラーとなり、要素は変わらないことを確認する
+        quickcheck(1000, gen_list_op, |ops| {
+            let mut array: FixedArrayStack<i32, 8> = FixedArrayStack::new();
+            let mut model: Vec<i32> = Vec::new();
+            for (n, op) in ops.iter().enumerate() {
+                match *op {
+                    ListOp::Add(i, x) => {
+                        let i = i % (model.len() + 1);
+                        let expected = if model.len() < 8 {
+                            model.insert(i, x);
+                            Ok(())
+                        } else {
+                            Err(CapacityError(x))
+                        };
+                        let actual = array.add(i, x);
+                        if actual != expected {
+                            return Err(format!(
+                                "{n}番目の操作 add({i}, {x}) の返り値: {actual:?}, 期待値: {expected:?}"
+                            ));
+                        }
+                    }
+                    ListOp::Remove(i) if !model.is_empty() => {
+                        let i = i % model.len();
+                        let expected = model.remove(i);
+                        let actual = array.remove(i);
+                        if actual != expected {
+                            return Err(format!(
+                                "{n}番目の操作 remove({i}) の返り値: {actual:?}, 期待値: {expected:?}"
+                            ));
+                        }
+                    }
+                    ListOp::Set(i, x) if !model.is_empty() => {
+                        let i = i % model.len();
+                        model[i] = x;
+                        array.set(i, x);
+                    }
+                    _ => (),
+                }
+
+                let actual: Vec<Option<&i32>> = (0..=model.len()).map(|i| array.get(i)).collect();
+                let expected: Vec<Option<&i32>> = model.iter().map(Some).chain([None]).collect();
+                if actual != expected {
+                    return Err(format!(
+                        "{n}番目の操作 {op:?} の後の要素: {actual:?}, 期待値: {expected:?}"
+                    ));
+                }
+            }
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,209 @@
+use super::fixed_array_stack::CapacityError;
+use crate::interface::heap_size::HeapSize;
+
+/// 容量がNに固定された、循環配列を使った双方向キュー
+///
+/// ArrayDequeと異なりresizeを行わず、配列は構造体に直接埋め込むため、生成後にメモリを割り当てることはない。
+/// そのため、ヒープのない環境(no_std)でも利用できる。
+/// 容量を超えて追加しようとした場合は、CapacityErrorを返す
+#[derive(Debug)]
+pub struct FixedDeque<T, const N: usize> {
+    a: [Option<T>; N], // 要素はa[j], a[(j+1)%N], ..., a[(j+n-1)%N]に入っていて、それ以外はNone
+    j: usize,          // 先頭の要素の位置
+    n: usize,          // キューの要素数
+}
+
+impl<T, const N: usize> Default for FixedDeque<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> FixedDeque<T, N> {
+    pub fn new() -> Self {
+        Self {
+            a: core::array::from_fn(|_| None),
+            j: 0,
+            n: 0,
+        }
+    }
+
+    /// 追加できる要素数の上限Nを返す
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// キューの要素数nを返す
+    pub fn size(&self) -> usize {
+        self.n
+    }
+
+    /// 要素数が容量に達していればtrueを返す
+    pub fn is_full(&self) -> bool {
+        self.n == N
+    }
+
+    /// 先頭からi番目の要素を返す
+    ///
+    /// # 計算量
+    /// O(1)
+    pub fn get(&self, i: usize) -> Option<&T> {
+        if i >= self.n {
+            return None;
+        }
+        self.a[(self.j + i) % N].as_ref()
+    }
+
+    /// 先頭にxを追加する。容量に達している場合は、追加せずにxをCapacityErrorとして返す
+    ///
+    /// # 計算量
+    /// O(1)
+    pub fn add_first(&mut self, x: T) -> Result<(), CapacityError<T>> {
+        if self.is_full() {
+            return Err(CapacityError(x));
+        }
+        self.j = (self.j + N - 1) % N;
+        self.a[self.j] = Some(x);
+        self.n += 1;
+        Ok(())
+    }
+
+    /// 末尾にxを追加する。容量に達している場合は、追加せずにxをCapacityErrorとして返す
+    ///
+    /// # 計算量
+    /// O(1)
+    pub fn add_last(&mut self, x: T) -> Result<(), CapacityError<T>> {
+        if self.is_full() {
+            return Err(CapacityError(x));
+        }
+        self.a[(self.j + self.n) % N] = Some(x);
+        self.n += 1;
+        Ok(())
+    }
+
+    /// 先頭の要素を削除し、それを返す
+    ///
+    /// # 計算量
+    /// O(1)
+    pub fn remove_first(&mut self) -> Option<T> {
+        if self.n == 0 {
+            return None;
+        }
+        let x = self.a[self.j].take();
+        self.j = (self.j + 1) % N;
+        self.n -= 1;
+        x
+    }
+
+    /// 末尾の要素を削除し、それを返す
+    ///
+    /// # 計算量
+    /// O(1)
+    pub fn remove_last(&mut self) -> Option<T> {
+        if self.n == 0 {
+            return None;
+        }
+        self.n -= 1;
+        self.a[(self.j + self.n) % N].take()
+    }
+}
+
+/// 配列は構造体に直接埋め込まれているため、ヒープ上には何も確保しない
+impl<T, const N: usize> HeapSize for FixedDeque<T, N> {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::{gen_list_op, quickcheck, ListOp};
+    use pretty_assertions::assert_eq;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn test_deque() {
+        let mut deque: FixedDeque<&str, 3> = FixedDeque::new();
+        deque.add_last("b").unwrap();
+        deque.add_first("a").unwrap();
+        deque.add_last("c").unwrap();
+        assert_eq!(deque.a, [Some("b"), Some("c"), Some("a")]);
+        assert_eq!(deque.j, 2);
+        assert_eq!(deque.add_first("x"), Err(CapacityError("x")));
+        assert_eq!(deque.add_last("y"), Err(CapacityError("y")));
+
+        assert_eq!(deque.get(0), Some(&"a"));
+        assert_eq!(deque.get(2), Some(&"c"));
+        assert_eq!(deque.get(3), None);
+
+        assert_eq!(deque.remove_first(), Some("a"));
+        assert_eq!(deque.remove_last(), Some("c"));
+        assert_eq!(deque.remove_last(), Some("b"));
+        assert_eq!(deque.remove_first(), None);
+        assert_eq!(deque.size(), 0);
+
+        // 容量0のキューには追加できない
+        let mut empty: FixedDeque<i32, 0> = FixedDeque::new();
+        assert_eq!(empty.add_first(1), Err(CapacityError(1)));
+        assert_eq!(empty.remove_last(), None);
+        assert_eq!(empty.get(0), None);
+    }
+
+    #[test]
+    fn test_deque_random() {
+        // Addは添字の偶奇で先頭か末尾に追加し、Removeも同様に削除する。VecDequeをモデルとする
+        quickcheck(1000, gen_list_op, |ops| {
+            let mut deque: FixedDeque<i32, 8> = FixedDeque::new();
+            let mut model: VecDeque<i32> = VecDeque::new();
+            for (n, op) in ops.iter().enumerate() {
+                match *op {
+                    ListOp::Add(i, x) => {
+                        let expected = if model.len() == 8 {
+                            Err(CapacityError(x))
+                        } else if i % 2 == 0 {
+                            model.push_front(x);
+                            Ok(())
+                        } else {
+                            model.push_back(x);
+                            Ok(())
+                        };
+                        let actual = if i % 2 == 0 {
+                            deque.add_first(x)
+                        } else {
+                            deque.add_last(x)
+                        };
+                        if actual != expected {
+                            return Err(format!(
+                                "{n}番目の操作 {op:?} の返り値: {actual:?}, 期待値: {expected:?}"
+                            ));
+                        }
+                    }
+                    ListOp::Remove(i) => {
+                        let (actual, expected) = if i % 2 == 0 {
+                            (deque.remove_first(), model.pop_front())
+                        } else {
+                            (deque.remove_last(), model.pop_back())
+                        };
+                        if actual != expected {
+                            return Err(format!(
+                                "{n}番目の操作 {op:?} の返り値: {actual:?}, 期待値: {expected:?}"
+                            ));
+                        }
+                    }
+                    _ => (),
+                }
+
+                let actual: Vec<Option<&i32>> = (0..=model.len()).map(|i| deque.get(i)).collect();
+                let expected: Vec<Option<&i32>> = model.iter().map(Some).chain([None]).collect();
+                if actual != expected {
+                    return Err(format!(
+                        "{n}番目の操作 {op:?} の後の要素: {actual:?}, 期待値: {expected:?}"
+                    ));
+                }
+            }
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,304 @@
+use crate::interface::heap_size::HeapSize;
+use crate::interface::queue::Queue;
+
+/// ハンドルでキーを変更できる二分ヒープ(優先度付きキュー)
+///
+/// 要素を追加するとハンドルが返され、そのハンドルでキーを小さく(decrease_key)または
+/// 大きく(increase_key)できる。ダイクストラ法やA*のように、キューに入っている
+/// 頂点の距離を更新する必要がある場合に使う。
+///
+/// ヒープの配列とは別に、ハンドルからヒープ内の位置への対応表を持ち、
+/// 要素を入れ替えるたびに更新することで、ハンドルから要素をO(1)で見つける
+#[derive(Debug)]
+pub struct IndexedBinaryHeap<K> {
+    heap: Vec<usize>,        // ヒープ順に並べたハンドル。heap[0]のキーが最小
+    pos: Vec<Option<usize>>, // ハンドルに対応する要素のheap内の位置。削除済みならNone
+    keys: Vec<Option<K>>,    // ハンドルに対応する要素のキー。削除済みならNone
+}
+
+impl<K: Ord> IndexedBinaryHeap<K> {
+    pub fn new() -> Self {
+        Self {
+            heap: Vec::new(),
+            pos: Vec::new(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// ヒープの要素数を返す
+    pub fn size(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// キーxの要素を追加し、その要素のハンドルを返す
+    ///
+    /// # 計算量
+    /// O(log n)の時間がかかる
+    pub fn push(&mut self, x: K) -> usize {
+        let h = self.keys.len();
+        self.keys.push(Some(x));
+        self.pos.push(Some(self.heap.len()));
+        self.heap.push(h);
+        self.bubble_up(self.heap.len() - 1);
+        h
+    }
+
+    /// キーが最小の要素のハンドルとキーを返す
+    pub fn peek(&self) -> Option<(usize, &K)> {
+        let h = *self.heap.first()?;
+        Some((h, self.keys[h].as_ref().unwrap()))
+    }
+
+    /// キーが最小の要素を削除し、そのハンドルとキーを返す
+    ///
+    /// # 計算量
+    /// O(log n)の時間がかかる
+    pub fn pop(&mut self) -> Option<(usize, K)> {
+        if self.heap.is_empty() {
+            return None;
+        }
+        // 末尾の要素を根に移してから下に移動させる
+        let last = self.heap.len() - 1;
+        self.swap(0, last);
+        let h = self.heap.pop().unwrap();
+        self.pos[h] = None;
+        self.trickle_down(0);
+        Some((h, self.keys[h].take().unwrap()))
+    }
+
+    /// ハンドルhの要素がヒープに入っていれば真
+    pub fn contains(&self, h: usize) -> bool {
+        matches!(self.pos.get(h), Some(Some(_)))
+    }
+
+    /// ハンドルhの要素のキーを返す。削除済みならNone
+    pub fn key(&self, h: usize) -> Option<&K> {
+        self.keys.get(h)?.as_ref()
+    }
+
+    /// ハンドルhの要素のキーを、より小さいキーxにする
+    ///
+    /// # 計算量
+    /// O(log n)の時間がかかる
+    ///
+    /// # Panics
+    /// hの要素がヒープに入っていない場合と、xが現在のキーより大きい場合
+    pub fn decrease_key(&mut self, h: usize, x: K) {
+        let i = self.position(h);
+        assert!(
+            x <= *self.keys[h].as_ref().unwrap(),
+            "decrease_keyで大きいキーが指定されました"
+        );
+        self.keys[h] = Some(x);
+        self.bubble_up(i);
+    }
+
+    /// ハンドルhの要素のキーを、より大きいキーxにする
+    ///
+    /// # 計算量
+    /// O(log n)の時間がかかる
+    ///
+    /// # Panics
+    /// hの要素がヒープに入っていない場合と、xが現在のキーより小さい場合
+    pub fn increase_key(&mut self, h: usize, x: K) {
+        let i = self.position(h);
+        assert!(
+            x >= *self.keys[h].as_ref().unwrap(),
+            "increase_keyで小さいキーが指定されました"
+        );
+        self.keys[h] = Some(x);
+        self.trickle_down(i);
+    }
+
+    /// ハンドルhの要素のheap内の位置を返す
+    fn position(&self, h: usize) -> usize {
+        match self.pos.get(h) {
+            Some(Some(i)) => *i,
+            _ => panic!("ハンドル{h}の要素はヒープに入っていません"),
+        }
+    }
+
+    fn left(i: usize) -> usize {
+        2 * i + 1
+    }
+
+    fn right(i: usize) -> usize {
+        2 * i + 2
+    }
+
+    fn parent(i: usize) -> usize {
+        (i - 1) / 2
+    }
+
+    /// heapの位置iの要素のキー
+    fn key_at(&self, i: usize) -> &K {
+        self.keys[self.heap[i]].as_ref().unwrap()
+    }
+
+    /// heapの位置iとjの要素を入れ替え、位置の対応表も更新する
+    fn swap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+        self.pos[self.heap[i]] = Some(i);
+        self.pos[self.heap[j]] = Some(j);
+    }
+
+    /// 位置iの要素を、親より小さい間は親と入れ替えて上に移動させる
+    fn bubble_up(&mut self, mut i: usize) {
+        while i > 0 && self.key_at(i) < self.key_at(Self::parent(i)) {
+            let p = Self::parent(i);
+            self.swap(i, p);
+            i = p;
+        }
+    }
+
+    /// 位置iの要素を、子のうち小さい方より大きい間はその子と入れ替えて下に移動させる
+    fn trickle_down(&mut self, mut i: usize) {
+        loop {
+            let mut j = i;
+            for c in [Self::left(i), Self::right(i)] {
+                if c < self.heap.len() && self.key_at(c) < self.key_at(j) {
+                    j = c;
+                }
+            }
+            if j == i {
+                return;
+            }
+            self.swap(i, j);
+            i = j;
+        }
+    }
+}
+
+impl<K: Ord> Default for IndexedBinaryHeap<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ハンドルを使わず、優先度付きキューとして使う場合のQueueインタフェースの実装
+///
+/// remove()はキーが最小の要素を削除する
+impl<K: Ord> Queue<K> for IndexedBinaryHeap<K> {
+    fn add(&mut self, x: K) {
+        self.push(x);
+    }
+
+    fn remove(&mut self) -> Option<K> {
+        self.pop().map(|(_, x)| x)
+    }
+}
+
+/// 3つのVecが確保している領域の合計
+///
+/// 削除した要素のハンドルもposとkeysに残るため、要素数ではなくこれまでに追加した要素の数に比例する
+impl<K> HeapSize for IndexedBinaryHeap<K> {
+    fn heap_size(&self) -> usize {
+        use std::mem::size_of;
+        self.heap.capacity() * size_of::<usize>()
+            + self.pos.capacity() * size_of::<Option<usize>>()
+            + self.keys.capacity() * size_of::<Option<K>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::Rng;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_queue() {
+        let mut heap = IndexedBinaryHeap::new();
+        for x in [5, 3, 8, 1, 9, 2] {
+            heap.add(x);
+        }
+        assert_eq!(heap.size(), 6);
+
+        let mut sorted = Vec::new();
+        while let Some(x) = heap.remove() {
+            sorted.push(x);
+        }
+        assert_eq!(sorted, vec![1, 2, 3, 5, 8, 9]);
+        assert_eq!(heap.remove(), None);
+    }
+
+    #[test]
+    fn test_update_key() {
+        let mut heap = IndexedBinaryHeap::new();
+        let a = heap.push(10);
+        let b = heap.push(20);
+        let c = heap.push(30);
+        assert_eq!(heap.peek(), Some((a, &10)));
+
+        // 最大の要素を最小にする
+        heap.decrease_key(c, 5);
+        assert_eq!(heap.peek(), Some((c, &5)));
+
+        // 最小の要素を最大にする
+        heap.increase_key(c, 40);
+        assert_eq!(heap.pop(), Some((a, 10)));
+        assert!(!heap.contains(a));
+        assert_eq!(heap.key(a), None);
+
+        // 削除と交互にキーを更新する
+        let d = heap.push(25);
+        heap.decrease_key(b, 15);
+        heap.decrease_key(c, 1);
+        assert_eq!(heap.pop(), Some((c, 1)));
+        heap.increase_key(b, 35);
+        assert_eq!(heap.key(b), Some(&35));
+        assert_eq!(heap.pop(), Some((d, 25)));
+        assert_eq!(heap.pop(), Some((b, 35)));
+        assert_eq!(heap.pop(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ヒープに入っていません")]
+    fn test_update_removed() {
+        let mut heap = IndexedBinaryHeap::new();
+        let a = heap.push(1);
+        heap.pop();
+        heap.decrease_key(a, 0);
+    }
+
+    #[test]
+    fn test_random() {
+        // ヒープに入っている(ハンドル, キー)の組をモデルとして、ランダムな操作の結果を比較する
+        let mut rng = Rng::new(0);
+        let mut heap = IndexedBinaryHeap::new();
+        let mut model: Vec<(usize, i32)> = Vec::new();
+        for _ in 0..10000 {
+            match rng.below(4) {
+                0 => {
+                    let x = rng.below(1000) as i32;
+                    model.push((heap.push(x), x));
+                }
+                1 => {
+                    let popped = heap.pop();
+                    let min = model.iter().map(|(_, x)| *x).min();
+                    assert_eq!(popped.map(|(_, x)| x), min);
+                    if let Some((h, _)) = popped {
+                        model.retain(|(g, _)| *g != h);
+                    }
+                }
+                _ if !model.is_empty() => {
+                    let k = rng.below(model.len());
+                    let (h, x) = &mut model[k];
+                    let y = rng.below(1000) as i32;
+                    if y <= *x {
+                        heap.decrease_key(*h, y);
+                    } else {
+                        heap.increase_key(*h, y);
+                    }
+                    *x = y;
+                }
+                _ => (),
+            }
+            assert_eq!(heap.size(), model.len());
+            for (h, x) in &model {
+                assert_eq!(heap.key(*h), Some(x));
+            }
+        }
+    }
+}
@@ -1,17 +1,24 @@
 use std::fmt::{self, Debug};
 use std::{cell::RefCell, rc::Rc};
 
+use crate::alloc_stats::NodeToken;
+use crate::interface::heap_size::{rc_alloc_size, HeapSize};
 use crate::interface::queue::Queue;
 use crate::interface::stack::Stack;
 
 pub struct Node<T> {
     x: T,
     next: Option<Rc<RefCell<Node<T>>>>,
+    _token: NodeToken,
 }
 
 impl<T> Node<T> {
     fn new(x: T) -> Self {
-        Self { x, next: None }
+        Self {
+            x,
+            next: None,
+            _token: NodeToken::new("SLList"),
+        }
     }
 }
 
@@ -108,12 +115,35 @@ impl<T> Queue<T> for SLList<T> {
     }
 }
 
+/// n個のノードの大きさの合計
+impl<T> HeapSize for SLList<T> {
+    fn heap_size(&self) -> usize {
+        self.n * rc_alloc_size(std::mem::size_of::<RefCell<Node<T>>>())
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
     use pretty_assertions::assert_eq;
 
+    crate::testing::conformance::test_stack_impl!(SLList::new());
+    crate::testing::conformance::test_queue_impl!(SLList::new());
+
+    #[test]
+    fn test_heap_size() {
+        let mut list: SLList<u64> = SLList::new();
+        assert_eq!(list.heap_size(), 0);
+        for x in 0..3 {
+            list.push(x);
+        }
+        let node = rc_alloc_size(std::mem::size_of::<RefCell<Node<u64>>>());
+        assert_eq!(list.heap_size(), 3 * node);
+        list.pop();
+        assert_eq!(list.heap_size(), 2 * node);
+    }
+
     #[test]
     fn test_stack() {
         let mut list = SLList::new();
@@ -145,4 +175,27 @@ mod tests {
         assert_eq!(list.n, 5);
         println!("{:?}", list);
     }
+
+    #[cfg(feature = "alloc-stats")]
+    #[test]
+    fn test_alloc_stats() {
+        use crate::alloc_stats;
+
+        alloc_stats::reset();
+        {
+            let mut list = SLList::new();
+            for x in 0..10 {
+                list.push(x);
+                list.add(x);
+            }
+            for _ in 0..5 {
+                list.pop();
+            }
+            assert_eq!(alloc_stats::stats("SLList").live(), 15);
+        }
+        let stats = alloc_stats::stats("SLList");
+        assert_eq!(stats.allocs, 20);
+        assert_eq!(stats.frees, 20);
+        assert_eq!(stats.peak, 20);
+    }
 }
@@ -0,0 +1,171 @@
+use crate::data_structure::array_deque::ArrayDeque;
+use crate::interface::dequeue::Deque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// ワークスティーリング用の双方向キューの所有者側
+///
+/// スレッドごとに1つ持ち、自分のタスクは末尾に追加して末尾から取り出す(LIFO)。
+/// タスクがなくなったスレッドは、他のスレッドのStealerを使って先頭から盗む(FIFO)。
+/// 所有者と盗む側が反対の端を使うため、直前に追加したタスク(キャッシュに載っている)は所有者が、
+/// 古いタスク(分割前の大きなタスクであることが多い)は盗む側が処理することになる。
+///
+/// Chase–Lev dequeは所有者側の操作をロックなしで行うが、
+/// ここでは循環配列のArrayDequeをMutexで保護した単純な実装とする
+#[derive(Debug)]
+pub struct Worker<T> {
+    deque: Arc<Mutex<ArrayDeque<T>>>,
+}
+
+/// ワークスティーリング用の双方向キューから盗む側
+///
+/// cloneして複数のスレッドに渡せる
+#[derive(Debug)]
+pub struct Stealer<T> {
+    deque: Arc<Mutex<ArrayDeque<T>>>,
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            deque: Arc::clone(&self.deque),
+        }
+    }
+}
+
+/// ロックを取得する
+///
+/// キューの操作中にパニックすることはないため、他のスレッドがパニックしていても中身はそのまま使える
+fn lock<T>(deque: &Mutex<ArrayDeque<T>>) -> MutexGuard<'_, ArrayDeque<T>> {
+    deque.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl<T> Default for Worker<T>
+where
+    T: Default + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Worker<T>
+where
+    T: Default + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            deque: Arc::new(Mutex::new(ArrayDeque::new(1))),
+        }
+    }
+
+    /// このキューから盗むためのStealerを返す
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            deque: Arc::clone(&self.deque),
+        }
+    }
+
+    /// 末尾にタスクを追加する
+    ///
+    /// ## 計算量
+    /// * O(1)(resizeを除く)
+    pub fn push(&self, x: T) {
+        lock(&self.deque).add_last(x);
+    }
+
+    /// 末尾のタスクを取り出す。空の場合はNone
+    ///
+    /// ## 計算量
+    /// * O(1)(resizeを除く)
+    pub fn pop(&self) -> Option<T> {
+        lock(&self.deque).remove_last()
+    }
+}
+
+impl<T> Stealer<T>
+where
+    T: Default + Clone,
+{
+    /// 先頭のタスクを盗む。空の場合はNone
+    ///
+    /// ## 計算量
+    /// * O(1)(resizeを除く)
+    pub fn steal(&self) -> Option<T> {
+        lock(&self.deque).remove_first()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_worker() {
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        worker.push(1);
+        worker.push(2);
+        worker.push(3);
+        // 所有者は末尾から、盗む側は先頭から取り出す
+        assert_eq!(worker.pop(), Some(3));
+        assert_eq!(stealer.steal(), Some(1));
+        assert_eq!(stealer.clone().steal(), Some(2));
+        assert_eq!(stealer.steal(), None);
+        assert_eq!(worker.pop(), None);
+    }
+
+    #[test]
+    fn test_steal() {
+        // 0..Nの和を、範囲を半分に分割するタスクとして4スレッドで計算する
+        // 最初のタスクはスレッド0のキューにしかないため、他のスレッドは盗まないと処理できない
+        const N: u64 = 100_000;
+        const THREADS: usize = 4;
+        const CUTOFF: u64 = 64; // これより短い範囲は分割せずに計算する
+
+        let workers: Vec<Worker<(u64, u64)>> = (0..THREADS).map(|_| Worker::new()).collect();
+        let stealers: Vec<Stealer<(u64, u64)>> = workers.iter().map(|w| w.stealer()).collect();
+        workers[0].push((0, N));
+
+        let done: Vec<AtomicBool> = (0..N).map(|_| AtomicBool::new(false)).collect();
+        let remaining = AtomicUsize::new(N as usize); // まだ計算していない数の個数
+        let sum = AtomicU64::new(0);
+
+        thread::scope(|s| {
+            for (i, worker) in workers.iter().enumerate() {
+                let (stealers, done, remaining, sum) = (&stealers, &done, &remaining, &sum);
+                s.spawn(move || {
+                    while remaining.load(Ordering::Acquire) > 0 {
+                        // 自分のキューが空なら、隣のスレッドから順に盗む
+                        let task = worker.pop().or_else(|| {
+                            (1..THREADS).find_map(|k| stealers[(i + k) % THREADS].steal())
+                        });
+                        let Some((lo, hi)) = task else {
+                            thread::yield_now();
+                            continue;
+                        };
+                        if hi - lo > CUTOFF {
+                            let mid = (lo + hi) / 2;
+                            worker.push((lo, mid));
+                            worker.push((mid, hi));
+                            continue;
+                        }
+                        for x in lo..hi {
+                            // 同じ数を2回計算していないことを確認する
+                            assert!(!done[x as usize].swap(true, Ordering::Relaxed));
+                            sum.fetch_add(x, Ordering::Relaxed);
+                        }
+                        remaining.fetch_sub((hi - lo) as usize, Ordering::Release);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(sum.load(Ordering::Relaxed), N * (N - 1) / 2);
+        assert!(done.iter().all(|d| d.load(Ordering::Relaxed)));
+        assert!(workers.iter().all(|w| w.pop().is_none()));
+    }
+}
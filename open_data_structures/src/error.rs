@@ -0,0 +1,130 @@
+use core::fmt;
+
+use crate::data_structure::fixed_array_stack::CapacityError;
+
+/// データ構造に対する操作が失敗した理由
+///
+/// 各インタフェースのtry_から始まるメソッドが返す。
+/// 範囲外の添字などでパニックする代わりにこのエラーを返すため、呼び出し側でmatchして処理できる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OdsError {
+    /// 添字indexが要素数len以上(追加の場合はlenより大きい)
+    IndexOutOfBounds { index: usize, len: usize },
+    /// 空のデータ構造から要素を取り出そうとした
+    Empty,
+    /// 容量を超えて要素を追加しようとした
+    CapacityExceeded,
+    /// 指定した要素が集合に入っていない
+    KeyNotFound,
+}
+
+impl fmt::Display for OdsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OdsError::IndexOutOfBounds { index, len } => {
+                write!(f, "添字{index}が範囲外(要素数{len})")
+            }
+            OdsError::Empty => write!(f, "要素が空"),
+            OdsError::CapacityExceeded => write!(f, "容量を超えて要素を追加しようとした"),
+            OdsError::KeyNotFound => write!(f, "要素が見つからない"),
+        }
+    }
+}
+
+impl std::error::Error for OdsError {}
+
+/// 追加できなかった値は捨てて、CapacityExceededにする
+impl<T> From<CapacityError<T>> for OdsError {
+    fn from(_: CapacityError<T>) -> Self {
+        OdsError::CapacityExceeded
+    }
+}
+
+/// Result<(), String>を返すテストなどで、?でそのまま失敗として扱えるようにする
+impl From<OdsError> for String {
+    fn from(e: OdsError) -> Self {
+        e.to_string()
+    }
+}
+
+/// 添字iが要素数n未満であることを確認する
+pub(crate) fn check_index(i: usize, n: usize) -> Result<(), OdsError> {
+    if i < n {
+        Ok(())
+    } else {
+        Err(OdsError::IndexOutOfBounds { index: i, len: n })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::data_structure::array_stack::ArrayStack;
+    use crate::data_structure::dl_list::DLList;
+    use crate::data_structure::fixed_array_stack::FixedArrayStack;
+    use crate::data_structure::sl_list::SLList;
+    use crate::interface::clone_list::CloneList;
+    use crate::interface::list::List;
+    use crate::interface::queue::Queue;
+    use crate::interface::stack::Stack;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_list() {
+        let mut array: ArrayStack<i32> = ArrayStack::new(1);
+        assert_eq!(array.try_add(0, 1), Ok(()));
+        assert_eq!(array.try_add(1, 3), Ok(()));
+        assert_eq!(
+            array.try_add(3, 4),
+            Err(OdsError::IndexOutOfBounds { index: 3, len: 2 })
+        );
+        assert_eq!(array.try_get(1), Ok(&3));
+        assert_eq!(array.try_set(1, 2), Ok(3));
+        assert_eq!(
+            array.try_set(2, 0),
+            Err(OdsError::IndexOutOfBounds { index: 2, len: 2 })
+        );
+        assert_eq!(array.try_remove(0), Ok(1));
+        assert_eq!(
+            array.try_get(1),
+            Err(OdsError::IndexOutOfBounds { index: 1, len: 1 })
+        );
+        assert_eq!(array.try_pop(), Ok(2));
+        assert_eq!(array.try_pop(), Err(OdsError::Empty));
+
+        let mut list: DLList<char> = DLList::new();
+        assert_eq!(list.try_add(0, 'a'), Ok(()));
+        assert_eq!(list.try_get(0), Ok('a'));
+        assert_eq!(
+            list.try_remove(1),
+            Err(OdsError::IndexOutOfBounds { index: 1, len: 1 })
+        );
+    }
+
+    #[test]
+    fn test_queue() {
+        let mut queue: SLList<i32> = SLList::new();
+        queue.add(1);
+        assert_eq!(queue.try_remove(), Ok(1));
+        assert_eq!(queue.try_remove(), Err(OdsError::Empty));
+    }
+
+    #[test]
+    fn test_from() {
+        // CapacityErrorとOdsErrorを?でまとめて扱える
+        let fill = || -> Result<(), OdsError> {
+            let mut array: FixedArrayStack<i32, 1> = FixedArrayStack::new();
+            array.push(1)?;
+            array.push(2)?;
+            Ok(())
+        };
+        assert_eq!(fill(), Err(OdsError::CapacityExceeded));
+
+        let get = || -> Result<i32, String> {
+            let array: ArrayStack<i32> = ArrayStack::new(1);
+            Ok(*array.try_get(0)?)
+        };
+        assert_eq!(get(), Err("添字0が範囲外(要素数0)".to_string()));
+    }
+}
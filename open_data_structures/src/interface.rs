@@ -1,5 +1,6 @@
 pub mod clone_list;
 pub mod dequeue;
+pub mod heap_size;
 pub mod list;
 pub mod queue;
 pub mod set;
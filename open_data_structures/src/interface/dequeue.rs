@@ -1,3 +1,5 @@
+use crate::error::OdsError;
+
 /// 双方向キュー
 /// 先頭と末尾を持った要素の列を表す
 /// 先頭または末尾に要素を追加できる
@@ -6,4 +8,14 @@ pub trait Deque<T> {
     fn remove_first(&mut self) -> Option<T>;
     fn add_last(&mut self, x: T);
     fn remove_last(&mut self) -> Option<T>;
+
+    /// 先頭の要素を削除して返す。空の場合はEmptyを返す
+    fn try_remove_first(&mut self) -> Result<T, OdsError> {
+        self.remove_first().ok_or(OdsError::Empty)
+    }
+
+    /// 末尾の要素を削除して返す。空の場合はEmptyを返す
+    fn try_remove_last(&mut self) -> Result<T, OdsError> {
+        self.remove_last().ok_or(OdsError::Empty)
+    }
 }
@@ -0,0 +1,19 @@
+use std::mem::size_of;
+
+/// ヒープ上に確保したメモリ量の見積もり
+///
+/// 配列を使ったデータ構造と連結リストの空間計算量の違いを、実際のバイト数で比較するために使う
+pub trait HeapSize {
+    /// データ構造がヒープ上に確保しているバイト数の見積もりを返す
+    ///
+    /// 配列は使われていない部分も含めた長さ、連結リストはノードの数とノードあたりの大きさから求める。
+    /// 要素自身が確保しているヒープ領域(Stringの文字列など)は含まない
+    fn heap_size(&self) -> usize;
+}
+
+/// Rcで大きさsizeの値を割り当てたときの大きさ
+///
+/// 値に加えて、強参照と弱参照の参照カウントを持つ
+pub fn rc_alloc_size(size: usize) -> usize {
+    size + 2 * size_of::<usize>()
+}
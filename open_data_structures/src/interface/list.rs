@@ -1,3 +1,5 @@
+use crate::error::{check_index, OdsError};
+
 /// 値の列x(0)..x(n-1)とその列に対する操作からなる
 pub trait List<T> {
     /// リストの長さnを返す
@@ -14,4 +16,34 @@ pub trait List<T> {
 
     /// x(i)を削除し、x(i+1)..x(n-1)を前にずらす
     fn remove(&mut self, i: usize) -> T;
+
+    /// x(i)の値を返す。iが要素数以上の場合はIndexOutOfBoundsを返す
+    fn try_get(&self, i: usize) -> Result<&T, OdsError> {
+        let len = self.size();
+        check_index(i, len)?;
+        self.get(i)
+            .ok_or(OdsError::IndexOutOfBounds { index: i, len })
+    }
+
+    /// x(i)の値をxにし、元の値を返す。iが要素数以上の場合はIndexOutOfBoundsを返す
+    fn try_set(&mut self, i: usize, x: T) -> Result<T, OdsError> {
+        check_index(i, self.size())?;
+        Ok(self.set(i, x))
+    }
+
+    /// xをi番目として追加する。iが要素数より大きい場合はIndexOutOfBoundsを返す
+    fn try_add(&mut self, i: usize, x: T) -> Result<(), OdsError> {
+        let len = self.size();
+        if i > len {
+            return Err(OdsError::IndexOutOfBounds { index: i, len });
+        }
+        self.add(i, x);
+        Ok(())
+    }
+
+    /// x(i)を削除して返す。iが要素数以上の場合はIndexOutOfBoundsを返す
+    fn try_remove(&mut self, i: usize) -> Result<T, OdsError> {
+        check_index(i, self.size())?;
+        Ok(self.remove(i))
+    }
 }
@@ -1,7 +1,14 @@
+use crate::error::OdsError;
+
 pub trait Queue<T> {
     /// 値xをQueueに追加する
     fn add(&mut self, x: T);
 
     /// 以前に追加された「次の値」yをQueueから削除し、yを返す
     fn remove(&mut self) -> Option<T>;
+
+    /// removeと同様に「次の値」を削除して返す。Queueが空の場合はEmptyを返す
+    fn try_remove(&mut self) -> Result<T, OdsError> {
+        self.remove().ok_or(OdsError::Empty)
+    }
 }
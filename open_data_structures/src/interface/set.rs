@@ -1,3 +1,5 @@
+use crate::error::OdsError;
+
 /// 重複がなく順序つけられていない要素の集まりを表現する
 /// 数学における集合のようなもの
 /// n個の互いに相異なる要素が含まれる。つまり、
@@ -17,6 +19,11 @@ pub trait USet<'a, T> {
     /// そのような要素が見つかればyを見つかれなければnullを返す
     fn remove(&mut self, x: T) -> Option<T>;
 
+    /// removeと同様にxと等しい要素を取り除いて返す。見つからなければKeyNotFoundを返す
+    fn try_remove(&mut self, x: T) -> Result<T, OdsError> {
+        self.remove(x).ok_or(OdsError::KeyNotFound)
+    }
+
     /// 集合にxが入っていればそれを見つける
     /// x = yを満たす集合の要素yを見つける。
     /// そのような要素が見つかればyを、見つからなければnullを返す
@@ -40,6 +47,11 @@ pub trait SSet<'a, T> {
     /// そのような要素が見つかればyを見つかれなければnullを返す
     fn remove(&mut self, x: T) -> Option<T>;
 
+    /// removeと同様にxと等しい要素を取り除いて返す。見つからなければKeyNotFoundを返す
+    fn try_remove(&mut self, x: T) -> Result<T, OdsError> {
+        self.remove(x).ok_or(OdsError::KeyNotFound)
+    }
+
     /// 順序づけられた集合からxの位置を特定する
     /// すなわちy>=xを満たす最小の要素yを見つける
     /// もしそのようなyが存在すればそれを返し、存在しないならnullを返す
@@ -1,3 +1,5 @@
+use crate::error::OdsError;
+
 /// スタックインタフェース
 /// LIFO(last-in-first-out 後入れ先だし)キューとも
 pub trait Stack<T> {
@@ -6,4 +8,9 @@ pub trait Stack<T> {
 
     /// 最後に追加された値yをStackから削除し、yを返す
     fn pop(&mut self) -> Option<T>;
+
+    /// popと同様に最後に追加された値を削除して返す。Stackが空の場合はEmptyを返す
+    fn try_pop(&mut self) -> Result<T, OdsError> {
+        self.pop().ok_or(OdsError::Empty)
+    }
 }
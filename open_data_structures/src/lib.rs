@@ -1,2 +1,8 @@
+pub mod algorithm;
+pub mod alloc_stats;
 pub mod data_structure;
+pub mod error;
 pub mod interface;
+
+#[cfg(test)]
+mod testing;
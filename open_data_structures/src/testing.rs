@@ -0,0 +1,364 @@
+//! ランダムな操作列によるテスト
+//!
+//! データ構造に対してランダムな操作列を実行し、単純で正しいことが明らかな
+//! 実装(モデル)と結果を比較する。結果が異なった場合は、失敗を再現する
+//! できるだけ短く単純な操作列を探してから報告する(縮小、shrinking)。
+//! ランダムに生成された操作列は長く、そのままでは原因の特定が難しいため。
+
+use std::fmt::Debug;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::{env, fs, process};
+
+use crate::interface::list::List;
+
+pub mod conformance;
+
+/// 1つの操作列で実行する操作の最大数
+const MAX_OPS: usize = 64;
+
+/// 擬似乱数生成器(xorshift64)
+///
+/// 外部クレートに依存せず、シードから同じ乱数列を再現できれば十分なので自前で実装する
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // 状態が0だと0しか生成されないため、0以外の値にする
+        Self {
+            state: seed ^ 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+
+    /// 0..nの範囲の乱数を返す
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// 値を縮小した候補を返す
+///
+/// 候補は元の値より単純なものに限り、単純なものから順に並べる。
+/// 候補がない場合は空のVecを返す
+pub trait Shrink: Sized {
+    fn shrink(&self) -> Vec<Self>;
+}
+
+impl Shrink for usize {
+    fn shrink(&self) -> Vec<Self> {
+        // 0、半分、1つ小さい値の順に試す
+        let mut v = vec![0, self / 2, self.saturating_sub(1)];
+        v.dedup();
+        v.retain(|x| x < self);
+        v
+    }
+}
+
+impl Shrink for i32 {
+    fn shrink(&self) -> Vec<Self> {
+        let mut v = vec![0, self / 2, self - self.signum()];
+        v.dedup();
+        v.retain(|x| x.abs() < self.abs());
+        v
+    }
+}
+
+/// Listに対する操作
+///
+/// 添字はリストの長さに関係なく生成し、実行時にリストの長さで剰余をとる。
+/// こうすることで、縮小した操作列も必ず実行可能になる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListOp<T> {
+    Add(usize, T),
+    Remove(usize),
+    Set(usize, T),
+    Get(usize),
+}
+
+impl<T: Shrink + Clone> Shrink for ListOp<T> {
+    fn shrink(&self) -> Vec<Self> {
+        let mut v = Vec::new();
+        match self {
+            ListOp::Add(i, x) => {
+                v.extend(i.shrink().into_iter().map(|i| ListOp::Add(i, x.clone())));
+                v.extend(x.shrink().into_iter().map(|x| ListOp::Add(*i, x)));
+            }
+            ListOp::Set(i, x) => {
+                v.extend(i.shrink().into_iter().map(|i| ListOp::Set(i, x.clone())));
+                v.extend(x.shrink().into_iter().map(|x| ListOp::Set(*i, x)));
+            }
+            ListOp::Remove(i) => v.extend(i.shrink().into_iter().map(ListOp::Remove)),
+            ListOp::Get(i) => v.extend(i.shrink().into_iter().map(ListOp::Get)),
+        }
+        v
+    }
+}
+
+/// ランダムなListの操作を生成
+///
+/// 要素数が増えるように、Addを他の操作より多く生成する
+pub fn gen_list_op(rng: &mut Rng) -> ListOp<i32> {
+    let i = rng.below(MAX_OPS);
+    let x = rng.below(100) as i32;
+    match rng.below(5) {
+        0 | 1 => ListOp::Add(i, x),
+        2 => ListOp::Remove(i),
+        3 => ListOp::Set(i, x),
+        _ => ListOp::Get(i),
+    }
+}
+
+/// Listの実装に操作列を実行し、Vecをモデルとして結果を比較する
+///
+/// 各操作の返り値と、各操作後のsize()とget()で得られる全要素を比較する
+pub fn check_list<T, L>(mut list: L, ops: &[ListOp<T>]) -> Result<(), String>
+where
+    T: Clone + PartialEq + Debug,
+    L: List<T>,
+{
+    let mut model: Vec<T> = Vec::new();
+    for (n, op) in ops.iter().enumerate() {
+        match op {
+            ListOp::Add(i, x) => {
+                let i = i % (model.len() + 1);
+                model.insert(i, x.clone());
+                list.add(i, x.clone());
+            }
+            ListOp::Remove(i) if !model.is_empty() => {
+                let i = i % model.len();
+                let expected = model.remove(i);
+                let actual = list.remove(i);
+                if actual != expected {
+                    return Err(format!(
+                        "{n}番目の操作 remove({i}) の返り値: {actual:?}, 期待値: {expected:?}"
+                    ));
+                }
+            }
+            ListOp::Set(i, x) if !model.is_empty() => {
+                let i = i % model.len();
+                let expected = std::mem::replace(&mut model[i], x.clone());
+                let actual = list.set(i, x.clone());
+                if actual != expected {
+                    return Err(format!(
+                        "{n}番目の操作 set({i}, {x:?}) の返り値: {actual:?}, 期待値: {expected:?}"
+                    ));
+                }
+            }
+            ListOp::Get(i) if !model.is_empty() => {
+                let i = i % model.len();
+                let actual = list.get(i);
+                if actual != model.get(i) {
+                    return Err(format!(
+                        "{n}番目の操作 get({i}) の返り値: {actual:?}, 期待値: {:?}",
+                        model.get(i)
+                    ));
+                }
+            }
+            _ => (), // 空のリストに対するremove、set、getは何もしない
+        }
+
+        if list.size() != model.len() {
+            return Err(format!(
+                "{n}番目の操作 {op:?} の後のsize(): {}, 期待値: {}",
+                list.size(),
+                model.len()
+            ));
+        }
+        let actual: Vec<Option<&T>> = (0..model.len()).map(|i| list.get(i)).collect();
+        let expected: Vec<Option<&T>> = model.iter().map(Some).collect();
+        if actual != expected {
+            return Err(format!(
+                "{n}番目の操作 {op:?} の後の要素: {actual:?}, 期待値: {expected:?}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 失敗する操作列を縮小する
+///
+/// 1. 操作列から連続する操作をまとめて取り除く(大きな塊から順に、1個ずつまで)
+/// 2. 各操作をShrinkで単純な操作に置き換える
+///
+/// のどちらかで失敗が再現する限り縮小を繰り返し、縮小できなくなった操作列と、
+/// その操作列での失敗メッセージを返す
+pub fn shrink<Op, F>(mut ops: Vec<Op>, mut msg: String, run: F) -> (Vec<Op>, String)
+where
+    Op: Shrink + Clone,
+    F: Fn(&[Op]) -> Result<(), String>,
+{
+    loop {
+        let mut progress = false;
+
+        // 操作を取り除く
+        let mut chunk = ops.len() / 2;
+        while chunk > 0 {
+            let mut start = 0;
+            while start + chunk <= ops.len() {
+                let mut candidate = ops.clone();
+                candidate.drain(start..start + chunk);
+                if let Err(m) = run(&candidate) {
+                    ops = candidate;
+                    msg = m;
+                    progress = true;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+
+        // 操作を単純にする
+        for i in 0..ops.len() {
+            for op in ops[i].shrink() {
+                let mut candidate = ops.clone();
+                candidate[i] = op;
+                if let Err(m) = run(&candidate) {
+                    ops = candidate;
+                    msg = m;
+                    progress = true;
+                    break;
+                }
+            }
+        }
+
+        if !progress {
+            return (ops, msg);
+        }
+    }
+}
+
+/// ランダムな操作列でrunを繰り返し実行し、失敗した場合は縮小した操作列を報告してパニックする
+///
+/// ## Arguments
+/// * `cases` - 試行回数。i回目の試行ではシードiの乱数で操作列を生成する
+/// * `gen`   - 操作を1つ生成する関数
+/// * `run`   - 操作列を実行し、失敗した場合はErrを返す関数
+pub fn quickcheck<Op, G, F>(cases: u64, gen: G, run: F)
+where
+    Op: Shrink + Clone + Debug,
+    G: Fn(&mut Rng) -> Op,
+    F: Fn(&[Op]) -> Result<(), String>,
+{
+    for seed in 0..cases {
+        let mut rng = Rng::new(seed);
+        let len = rng.below(MAX_OPS + 1);
+        let ops: Vec<Op> = (0..len).map(|_| gen(&mut rng)).collect();
+
+        // 実装がパニックした場合も失敗として縮小する
+        let run = |ops: &[Op]| {
+            panic::catch_unwind(AssertUnwindSafe(|| run(ops)))
+                .unwrap_or_else(|e| Err(panic_message(e)))
+        };
+
+        if let Err(msg) = run(&ops) {
+            let (ops, msg) = shrink(ops, msg, run);
+            panic!(
+                "シード{seed}の操作列で失敗しました\n縮小した操作列({}個): {ops:?}\n{msg}",
+                ops.len()
+            );
+        }
+    }
+}
+
+/// パニックのメッセージを取り出す
+fn panic_message(e: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = e.downcast_ref::<&str>() {
+        format!("パニック: {s}")
+    } else if let Some(s) = e.downcast_ref::<String>() {
+        format!("パニック: {s}")
+    } else {
+        "パニック".to_string()
+    }
+}
+
+/// テストで使う一時ファイルのパスを返す
+///
+/// テストごとにnameを変え、並列に実行しても衝突しないようにする。
+/// 前回のテストで残ったファイルとWALは削除しておく
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("ods_{}_{name}", process::id()));
+    let mut wal = path.as_os_str().to_owned();
+    wal.push(".wal");
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(wal);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// 要素数が3を超えると先頭への追加を末尾に追加してしまう、誤ったList
+    struct BuggyList(Vec<i32>);
+
+    impl List<i32> for BuggyList {
+        fn size(&self) -> usize {
+            self.0.len()
+        }
+
+        fn get(&self, i: usize) -> Option<&i32> {
+            self.0.get(i)
+        }
+
+        fn set(&mut self, i: usize, x: i32) -> i32 {
+            std::mem::replace(&mut self.0[i], x)
+        }
+
+        fn add(&mut self, i: usize, x: i32) {
+            if i == 0 && self.0.len() >= 3 {
+                self.0.push(x);
+            } else {
+                self.0.insert(i, x);
+            }
+        }
+
+        fn remove(&mut self, i: usize) -> i32 {
+            self.0.remove(i)
+        }
+    }
+
+    #[test]
+    fn test_shrink() {
+        let run = |ops: &[ListOp<i32>]| check_list(BuggyList(Vec::new()), ops);
+        let ops: Vec<ListOp<i32>> = {
+            let mut rng = Rng::new(1);
+            (0..MAX_OPS).map(|_| gen_list_op(&mut rng)).collect()
+        };
+        let msg = run(&ops).unwrap_err();
+
+        // 3個の要素を追加した後に、先頭に値が異なる要素を追加する操作列まで縮小される
+        let (ops, _) = shrink(ops, msg, run);
+        assert_eq!(
+            ops,
+            vec![
+                ListOp::Add(0, 0),
+                ListOp::Add(0, 0),
+                ListOp::Add(0, 0),
+                ListOp::Add(0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "縮小した操作列(4個)")]
+    fn test_quickcheck() {
+        quickcheck(100, gen_list_op, |ops| {
+            check_list(BuggyList(Vec::new()), ops)
+        });
+    }
+}
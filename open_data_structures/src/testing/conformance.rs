@@ -0,0 +1,478 @@
+//! インタフェースの適合性テスト
+//!
+//! List、Stack、Queue、Deque、SSetの各インタフェースについて、どの実装でも成り立つべき性質を
+//! 確かめる一連のテストを用意する。新しいデータ構造を追加したら、そのテストモジュールに
+//!
+//! ```ignore
+//! test_list_impl!(ArrayStack::new(1));
+//! ```
+//!
+//! のように1行加えるだけで、インタフェースのテストを一通り実行できる。
+//! 引数は空のデータ構造を生成する式で、テストごとに評価する。要素の型はi32とする。
+//! マクロはlist_implなどのモジュールを生成するため、同じテストモジュールで異なるインタフェースのマクロを併用できる。
+//!
+//! 範囲外の添字や空のデータ構造に対する操作は、パニックするかどうかを実装に任せているため、
+//! try_から始まるメソッドがエラーを返すことのみを検査する。
+
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt::Debug;
+
+use pretty_assertions::assert_eq;
+
+use super::{check_list, gen_list_op, quickcheck, Rng};
+use crate::error::OdsError;
+use crate::interface::{dequeue::Deque, list::List, queue::Queue, set::SSet, stack::Stack};
+
+/// 1つのテストで追加する要素の数。配列の拡大と縮小が何度か起きる程度にする
+const N: i32 = 100;
+
+/// ランダムな操作列の長さ
+const RANDOM_OPS: usize = 1000;
+
+/// listの全要素がmodelと等しいことを確かめる
+fn assert_list_eq<L: List<i32>>(list: &L, model: &[i32]) {
+    assert_eq!(list.size(), model.len());
+    let actual: Vec<Option<&i32>> = (0..model.len()).map(|i| list.get(i)).collect();
+    let expected: Vec<Option<&i32>> = model.iter().map(Some).collect();
+    assert_eq!(actual, expected);
+}
+
+/// 先頭、中央、末尾への追加、set、removeの結果がVecと一致する
+pub fn check_list_basic<L: List<i32>>(new: impl Fn() -> L) {
+    let mut list = new();
+    let mut model = Vec::new();
+    assert_list_eq(&list, &model);
+
+    for x in 0..N {
+        let i = match x % 3 {
+            0 => 0,
+            1 => model.len() / 2,
+            _ => model.len(),
+        };
+        list.add(i, x);
+        model.insert(i, x);
+    }
+    assert_list_eq(&list, &model);
+
+    // setは元の値を返す
+    for (i, y) in model.iter_mut().enumerate() {
+        assert_eq!(list.set(i, -(i as i32)), *y);
+        *y = -(i as i32);
+    }
+    assert_list_eq(&list, &model);
+
+    // removeは削除した値を返し、後ろの要素を前にずらす
+    while !model.is_empty() {
+        let i = model.len() / 3;
+        assert_eq!(list.remove(i), model.remove(i));
+        assert_list_eq(&list, &model);
+    }
+}
+
+/// try_から始まるメソッドは、範囲外の添字にIndexOutOfBoundsを返し、リストを変更しない
+pub fn check_list_errors<L: List<i32>>(new: impl Fn() -> L) {
+    fn oob<T>(index: usize, len: usize) -> Result<T, OdsError> {
+        Err(OdsError::IndexOutOfBounds { index, len })
+    }
+
+    let mut list = new();
+    assert_eq!(list.try_get(0), oob(0, 0));
+    assert_eq!(list.try_set(0, 1), oob(0, 0));
+    assert_eq!(list.try_remove(0), oob(0, 0));
+    assert_eq!(list.try_add(1, 1), oob(1, 0));
+
+    assert_eq!(list.try_add(0, 1), Ok(()));
+    assert_eq!(list.try_add(1, 3), Ok(()));
+    assert_eq!(list.try_add(1, 2), Ok(()));
+    assert_eq!(list.try_get(2), Ok(&3));
+    assert_eq!(list.try_get(3), oob(3, 3));
+    assert_eq!(list.try_set(0, 0), Ok(1));
+    assert_eq!(list.try_set(3, 0), oob(3, 3));
+    assert_eq!(list.try_add(4, 0), oob(4, 3));
+    assert_eq!(list.try_remove(3), oob(3, 3));
+    assert_eq!(list.try_remove(1), Ok(2));
+    assert_list_eq(&list, &[0, 3]);
+}
+
+/// ランダムな操作列の結果がVecと一致する
+pub fn check_list_random<L: List<i32>>(new: impl Fn() -> L) {
+    quickcheck(300, gen_list_op, |ops| check_list(new(), ops));
+}
+
+/// 追加した順と逆順に取り出され、空ならNoneとEmptyを返す
+pub fn check_stack_basic<S: Stack<i32>>(new: impl Fn() -> S) {
+    let mut stack = new();
+    assert_eq!(stack.pop(), None);
+    assert_eq!(stack.try_pop(), Err(OdsError::Empty));
+
+    for x in 0..N {
+        stack.push(x);
+    }
+    for x in (0..N).rev() {
+        assert_eq!(stack.pop(), Some(x));
+    }
+    assert_eq!(stack.pop(), None);
+
+    // 空になった後も使える
+    stack.push(1);
+    assert_eq!(stack.try_pop(), Ok(1));
+    assert_eq!(stack.try_pop(), Err(OdsError::Empty));
+}
+
+/// pushとpopをランダムに繰り返した結果がVecと一致する
+pub fn check_stack_random<S: Stack<i32>>(new: impl Fn() -> S) {
+    let mut stack = new();
+    let mut model = Vec::new();
+    let mut rng = Rng::new(0);
+    for n in 0..RANDOM_OPS {
+        // 要素数が増減するように、pushを少し多くする
+        if rng.below(5) < 3 {
+            let x = rng.below(100) as i32;
+            stack.push(x);
+            model.push(x);
+        } else {
+            assert_eq!(stack.pop(), model.pop(), "{n}番目の操作 pop");
+        }
+    }
+    drain(|| stack.pop(), model.into_iter().rev());
+}
+
+/// 追加した順に取り出され、空ならNoneとEmptyを返す(FIFOのQueue)
+pub fn check_queue_basic<Q: Queue<i32>>(new: impl Fn() -> Q) {
+    let mut queue = new();
+    assert_eq!(queue.remove(), None);
+    assert_eq!(queue.try_remove(), Err(OdsError::Empty));
+
+    for x in 0..N {
+        queue.add(x);
+    }
+    for x in 0..N {
+        assert_eq!(queue.remove(), Some(x));
+    }
+    assert_eq!(queue.remove(), None);
+
+    queue.add(1);
+    assert_eq!(queue.try_remove(), Ok(1));
+    assert_eq!(queue.try_remove(), Err(OdsError::Empty));
+}
+
+/// addとremoveをランダムに繰り返した結果がVecDequeと一致する(FIFOのQueue)
+pub fn check_queue_random<Q: Queue<i32>>(new: impl Fn() -> Q) {
+    let mut queue = new();
+    let mut model = VecDeque::new();
+    let mut rng = Rng::new(0);
+    for n in 0..RANDOM_OPS {
+        if rng.below(5) < 3 {
+            let x = rng.below(100) as i32;
+            queue.add(x);
+            model.push_back(x);
+        } else {
+            assert_eq!(queue.remove(), model.pop_front(), "{n}番目の操作 remove");
+        }
+    }
+    drain(|| queue.remove(), model);
+}
+
+/// 両端への追加と両端からの削除がVecDequeと一致し、空ならNoneとEmptyを返す
+pub fn check_deque_basic<D: Deque<i32>>(new: impl Fn() -> D) {
+    let mut deque = new();
+    assert_eq!(deque.remove_first(), None);
+    assert_eq!(deque.remove_last(), None);
+    assert_eq!(deque.try_remove_first(), Err(OdsError::Empty));
+    assert_eq!(deque.try_remove_last(), Err(OdsError::Empty));
+
+    // 先頭には逆順に、末尾には順に並ぶ
+    for x in 0..N {
+        deque.add_first(-x);
+        deque.add_last(x);
+    }
+    for x in (0..N).rev() {
+        assert_eq!(deque.remove_first(), Some(-x));
+    }
+    for x in (0..N).rev() {
+        assert_eq!(deque.remove_last(), Some(x));
+    }
+    assert_eq!(deque.remove_first(), None);
+
+    deque.add_last(1);
+    assert_eq!(deque.try_remove_first(), Ok(1));
+    deque.add_first(2);
+    assert_eq!(deque.try_remove_last(), Ok(2));
+    assert_eq!(deque.try_remove_last(), Err(OdsError::Empty));
+}
+
+/// 両端の操作をランダムに繰り返した結果がVecDequeと一致する
+pub fn check_deque_random<D: Deque<i32>>(new: impl Fn() -> D) {
+    let mut deque = new();
+    let mut model = VecDeque::new();
+    let mut rng = Rng::new(0);
+    for n in 0..RANDOM_OPS {
+        let x = rng.below(100) as i32;
+        match rng.below(5) {
+            0 => {
+                deque.add_first(x);
+                model.push_front(x);
+            }
+            1 | 2 => {
+                deque.add_last(x);
+                model.push_back(x);
+            }
+            3 => assert_eq!(deque.remove_first(), model.pop_front(), "{n}番目の操作"),
+            _ => assert_eq!(deque.remove_last(), model.pop_back(), "{n}番目の操作"),
+        }
+    }
+    drain(|| deque.remove_first(), model);
+}
+
+/// 残りの要素をremoveで取り出し、expectedと一致して最後にNoneとなることを確かめる
+fn drain<T: PartialEq + Debug>(
+    mut remove: impl FnMut() -> Option<T>,
+    expected: impl IntoIterator<Item = T>,
+) {
+    for (n, x) in expected.into_iter().enumerate() {
+        assert_eq!(remove(), Some(x), "残りの{n}番目の要素");
+    }
+    assert_eq!(remove(), None);
+}
+
+/// 追加、削除、検索(x以上の最小の要素)の結果が期待どおりになる
+///
+/// SSetのsizeとfindはselfを消費するため、可変参照に対して実装したもの(`impl SSet for &mut X`)を対象とし、
+/// 呼び出すたびに再借用する
+pub fn check_sset_basic<S>(new: impl Fn() -> S)
+where
+    for<'a> &'a mut S: SSet<'a, i32>,
+{
+    let mut set = new();
+    assert_eq!((&mut set).size(), 0);
+    assert_eq!((&mut set).find(0), None);
+    assert_eq!((&mut set).try_remove(0), Err(OdsError::KeyNotFound));
+
+    for x in [5, 1, 9, 3, 7] {
+        assert!((&mut set).add(x));
+    }
+    // 重複する要素は追加しない
+    assert!(!(&mut set).add(5));
+    assert_eq!((&mut set).size(), 5);
+
+    assert_eq!((&mut set).find(0), Some(&1));
+    assert_eq!((&mut set).find(5), Some(&5));
+    assert_eq!((&mut set).find(6), Some(&7));
+    assert_eq!((&mut set).find(10), None);
+
+    assert_eq!((&mut set).remove(5), Some(5));
+    assert_eq!((&mut set).remove(5), None);
+    assert_eq!((&mut set).try_remove(5), Err(OdsError::KeyNotFound));
+    assert_eq!((&mut set).find(5), Some(&7));
+    assert_eq!((&mut set).size(), 4);
+}
+
+/// 追加、削除、検索をランダムに繰り返した結果がBTreeSetと一致する
+pub fn check_sset_random<S>(new: impl Fn() -> S)
+where
+    for<'a> &'a mut S: SSet<'a, i32>,
+{
+    let mut set = new();
+    let mut model = BTreeSet::new();
+    let mut rng = Rng::new(0);
+    for n in 0..RANDOM_OPS {
+        let x = rng.below(N as usize) as i32;
+        match rng.below(3) {
+            0 => assert_eq!((&mut set).add(x), model.insert(x), "{n}番目の操作 add({x})"),
+            1 => assert_eq!(
+                (&mut set).remove(x),
+                model.take(&x),
+                "{n}番目の操作 remove({x})"
+            ),
+            _ => assert_eq!(
+                (&mut set).find(x),
+                model.range(x..).next(),
+                "{n}番目の操作 find({x})"
+            ),
+        }
+        assert_eq!((&mut set).size(), model.len(), "{n}番目の操作の後のsize()");
+    }
+}
+
+/// Listの適合性テストを生成する。引数は空のリストを生成する式
+macro_rules! test_list_impl {
+    ($new:expr) => {
+        mod list_impl {
+            use super::*;
+
+            #[test]
+            fn test_list_basic() {
+                $crate::testing::conformance::check_list_basic(|| $new);
+            }
+
+            #[test]
+            fn test_list_errors() {
+                $crate::testing::conformance::check_list_errors(|| $new);
+            }
+
+            #[test]
+            fn test_list_random() {
+                $crate::testing::conformance::check_list_random(|| $new);
+            }
+        }
+    };
+}
+pub(crate) use test_list_impl;
+
+/// Stackの適合性テストを生成する。引数は空のスタックを生成する式
+macro_rules! test_stack_impl {
+    ($new:expr) => {
+        mod stack_impl {
+            use super::*;
+
+            #[test]
+            fn test_stack_basic() {
+                $crate::testing::conformance::check_stack_basic(|| $new);
+            }
+
+            #[test]
+            fn test_stack_random() {
+                $crate::testing::conformance::check_stack_random(|| $new);
+            }
+        }
+    };
+}
+pub(crate) use test_stack_impl;
+
+/// FIFOのQueueの適合性テストを生成する。引数は空のキューを生成する式
+///
+/// 優先度付きキューのように、追加した順に取り出さない実装には使えない
+macro_rules! test_queue_impl {
+    ($new:expr) => {
+        mod queue_impl {
+            use super::*;
+
+            #[test]
+            fn test_queue_basic() {
+                $crate::testing::conformance::check_queue_basic(|| $new);
+            }
+
+            #[test]
+            fn test_queue_random() {
+                $crate::testing::conformance::check_queue_random(|| $new);
+            }
+        }
+    };
+}
+pub(crate) use test_queue_impl;
+
+/// Dequeの適合性テストを生成する。引数は空の双方向キューを生成する式
+macro_rules! test_deque_impl {
+    ($new:expr) => {
+        mod deque_impl {
+            use super::*;
+
+            #[test]
+            fn test_deque_basic() {
+                $crate::testing::conformance::check_deque_basic(|| $new);
+            }
+
+            #[test]
+            fn test_deque_random() {
+                $crate::testing::conformance::check_deque_random(|| $new);
+            }
+        }
+    };
+}
+pub(crate) use test_deque_impl;
+
+/// SSetの適合性テストを生成する。引数は空の集合を生成する式で、その可変参照がSSetを実装している必要がある
+macro_rules! test_sset_impl {
+    ($new:expr) => {
+        mod sset_impl {
+            use super::*;
+
+            #[test]
+            fn test_sset_basic() {
+                $crate::testing::conformance::check_sset_basic(|| $new);
+            }
+
+            #[test]
+            fn test_sset_random() {
+                $crate::testing::conformance::check_sset_random(|| $new);
+            }
+        }
+    };
+}
+pub(crate) use test_sset_impl;
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// ソート済みのVecによるSSet。適合性テスト自体を確かめるための単純な実装
+    #[derive(Debug, Default)]
+    struct SortedVec(Vec<i32>);
+
+    impl<'a> SSet<'a, i32> for &'a mut SortedVec {
+        fn size(self) -> usize {
+            self.0.len()
+        }
+
+        fn add(&mut self, x: i32) -> bool {
+            match self.0.binary_search(&x) {
+                Ok(_) => false,
+                Err(i) => {
+                    self.0.insert(i, x);
+                    true
+                }
+            }
+        }
+
+        fn remove(&mut self, x: i32) -> Option<i32> {
+            let i = self.0.binary_search(&x).ok()?;
+            Some(self.0.remove(i))
+        }
+
+        fn find(self, x: i32) -> Option<&'a i32> {
+            let i = self.0.partition_point(|y| *y < x);
+            self.0.get(i)
+        }
+    }
+
+    crate::testing::conformance::test_sset_impl!(SortedVec::default());
+
+    // 標準ライブラリのVecDequeは、すべての適合性テストに合格する
+    struct StdDeque(VecDeque<i32>);
+
+    impl Deque<i32> for StdDeque {
+        fn add_first(&mut self, x: i32) {
+            self.0.push_front(x)
+        }
+        fn remove_first(&mut self) -> Option<i32> {
+            self.0.pop_front()
+        }
+        fn add_last(&mut self, x: i32) {
+            self.0.push_back(x)
+        }
+        fn remove_last(&mut self) -> Option<i32> {
+            self.0.pop_back()
+        }
+    }
+
+    crate::testing::conformance::test_deque_impl!(StdDeque(VecDeque::new()));
+
+    /// 末尾から取り出してしまう、誤ったQueue
+    struct LifoQueue(Vec<i32>);
+
+    impl Queue<i32> for LifoQueue {
+        fn add(&mut self, x: i32) {
+            self.0.push(x)
+        }
+        fn remove(&mut self) -> Option<i32> {
+            self.0.pop()
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_queue_rejects_lifo() {
+        check_queue_basic(|| LifoQueue(Vec::new()));
+    }
+}
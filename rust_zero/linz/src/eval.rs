@@ -0,0 +1,379 @@
+//! 型付けに成功した式の評価
+//!
+//! 値呼びで評価し、関数は定義時の環境を捕捉したクロージャとする。
+//! 型付けによってlin型の値は一度だけ利用されることが保証されているため、評価時には線形性を検査しない。
+//! free文は変数の束縛を取り除き、以降の参照をエラーとする。
+//! モジュールは評価の前に名前解決によってlet式に変換されている必要がある。
+//!
+//! 評価の過程は、簡約ごとに1行のJSONとして記録できる(--trace-eval json)。
+
+use crate::parser::{Expr, FnExpr, Pattern, Qual, ValExpr};
+use std::{fmt, ptr, rc::Rc};
+
+/// 評価の結果。エラー時にはメッセージを返す
+pub type EResult<'a> = Result<Rc<Value<'a>>, String>;
+
+/// 変数名と値の束縛のスタック。後ろほど内側のスコープ
+type Env<'a> = Vec<(String, Rc<Value<'a>>)>;
+
+/// 評価結果の値
+#[derive(Debug)]
+pub enum Value<'a> {
+    Bool(Qual, bool),                         // 真偽値
+    Pair(Qual, Rc<Value<'a>>, Rc<Value<'a>>), // ペア
+    Fun(Qual, &'a FnExpr, Env<'a>),           // 関数と、定義時の環境
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let qual = |q: &Qual| if *q == Qual::Lin { "lin" } else { "un" };
+        match self {
+            Value::Bool(q, b) => write!(f, "{} {b}", qual(q)),
+            Value::Pair(q, v1, v2) => write!(f, "{} <{v1}, {v2}>", qual(q)),
+            // 関数の本体は表示せず、引数とその型のみを表示する
+            Value::Fun(q, e, _) => write!(f, "{} fn {} : {} {{ ... }}", qual(q), e.var, e.ty),
+        }
+    }
+}
+
+impl Value<'_> {
+    /// 値の修飾子
+    fn qual(&self) -> Qual {
+        match self {
+            Value::Bool(q, _) | Value::Pair(q, ..) | Value::Fun(q, ..) => *q,
+        }
+    }
+}
+
+/// 式exprを評価する
+pub fn eval(expr: &Expr) -> EResult<'_> {
+    eval_expr(expr, &mut Vec::new(), &mut Trace::default())
+}
+
+/// 式exprを評価し、評価の過程を記録したJSONの各行を返す
+///
+/// 最後の行は評価の結果で、kindはresultかerrorとなる
+pub fn trace(expr: &Expr) -> Vec<String> {
+    let mut tr = Trace {
+        enabled: true,
+        ..Default::default()
+    };
+    let last = match eval_expr(expr, &mut Vec::new(), &mut tr) {
+        Ok(val) => format!(
+            "\"kind\":\"result\",\"value\":{}",
+            json_str(&val.to_string())
+        ),
+        Err(msg) => format!("\"kind\":\"error\",\"message\":{}", json_str(&msg)),
+    };
+    let alloc = tr.allocated();
+    tr.lines.push(format!(
+        "{{\"step\":{},{last},\"alloc\":{alloc}}}",
+        tr.lines.len() + 1
+    ));
+    tr.lines
+}
+
+/// 評価の過程の記録
+///
+/// 簡約(let、if、split、free、関数適用)ごとに、簡約した式の位置、その時点で束縛されている変数、
+/// 直前の簡約から確保したlin型の値と、その簡約で消費したlin型の値を1行のJSONとして記録する。
+/// 式の位置はプログラム全体の式から子の式のフィールド名を辿ったパス(/expr2/then_exprなど)で表し、
+/// 関数本体の式は関数を定義した位置からのパスとなる。
+/// lin型の値には確保した順に1から番号を付けるため、同じプログラムからは常に同じ記録が得られる
+#[derive(Default)]
+struct Trace<'a> {
+    enabled: bool,                                  // 偽なら何も記録しない
+    lines: Vec<String>,                             // 記録したJSONの各行
+    path: Vec<&'static str>,                        // 評価中の式の位置
+    fn_paths: Vec<(&'a FnExpr, Vec<&'static str>)>, // 評価した関数と、定義した位置
+    lin: Vec<Rc<Value<'a>>>,                        // 確保したlin型の値。番号-1の位置に格納する
+    allocated: Vec<usize>,                          // 直前の簡約から確保した値の番号
+}
+
+impl<'a> Trace<'a> {
+    /// 子の式labelに移動してfを評価する
+    fn sub<T>(&mut self, label: &'static str, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.path.push(label);
+        let result = f(self);
+        self.path.pop();
+        result
+    }
+
+    /// 値valを確保した。lin型なら番号を付ける
+    fn alloc(&mut self, val: &Rc<Value<'a>>) {
+        if self.enabled && val.qual() == Qual::Lin {
+            self.lin.push(val.clone());
+            self.allocated.push(self.lin.len());
+        }
+    }
+
+    /// 関数fを評価した位置を記録する
+    fn define_fn(&mut self, f: &'a FnExpr) {
+        if self.enabled && !self.fn_paths.iter().any(|(g, _)| ptr::eq(*g, f)) {
+            self.fn_paths.push((f, self.path.clone()));
+        }
+    }
+
+    /// 関数fの本体の位置に移動し、元の位置を返す
+    fn enter_fn(&mut self, f: &FnExpr) -> Vec<&'static str> {
+        let mut path = match self.fn_paths.iter().find(|(g, _)| ptr::eq(*g, f)) {
+            Some((_, path)) => path.clone(),
+            None => Vec::new(),
+        };
+        path.push("expr");
+        std::mem::replace(&mut self.path, path)
+    }
+
+    /// 番号がidsの値の一覧をJSONの配列で表す
+    fn values(&self, ids: &[usize]) -> String {
+        let vals: Vec<String> = ids
+            .iter()
+            .map(|id| {
+                let val = json_str(&self.lin[id - 1].to_string());
+                format!("{{\"id\":{id},\"value\":{val}}}")
+            })
+            .collect();
+        format!("[{}]", vals.join(","))
+    }
+
+    /// 直前の簡約から確保した値の一覧をJSONの配列で表し、一覧を空にする
+    fn allocated(&mut self) -> String {
+        let ids = std::mem::take(&mut self.allocated);
+        self.values(&ids)
+    }
+
+    /// 簡約kindを記録する。freedは簡約で消費した値
+    fn step(&mut self, kind: &str, env: &Env<'a>, freed: &[&Rc<Value<'a>>]) {
+        if !self.enabled {
+            return;
+        }
+        let at = format!("/{}", self.path.join("/"));
+        let env: Vec<String> = env.iter().map(|(var, _)| json_str(var)).collect();
+        let freed: Vec<usize> = freed
+            .iter()
+            .filter_map(|val| Some(self.lin.iter().position(|v| Rc::ptr_eq(v, val))? + 1))
+            .collect();
+        let line = format!(
+            "{{\"step\":{},\"kind\":\"{kind}\",\"at\":{},\"env\":[{}],\"alloc\":{},\"free\":{}}}",
+            self.lines.len() + 1,
+            json_str(&at),
+            env.join(","),
+            self.allocated(),
+            self.values(&freed),
+        );
+        self.lines.push(line);
+    }
+}
+
+/// 文字列sをJSONの文字列リテラルで表す
+fn json_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn eval_expr<'a>(expr: &'a Expr, env: &mut Env<'a>, tr: &mut Trace<'a>) -> EResult<'a> {
+    match expr {
+        Expr::QVal(e) => {
+            let val = match &e.val {
+                ValExpr::Bool(b) => Rc::new(Value::Bool(e.qual, *b)),
+                ValExpr::Pair(e1, e2) => {
+                    let v1 = tr.sub("0", |tr| eval_expr(e1, env, tr))?;
+                    let v2 = tr.sub("1", |tr| eval_expr(e2, env, tr))?;
+                    Rc::new(Value::Pair(e.qual, v1, v2))
+                }
+                ValExpr::Fun(f) => {
+                    tr.define_fn(f);
+                    Rc::new(Value::Fun(e.qual, f, env.clone()))
+                }
+            };
+            tr.alloc(&val);
+            Ok(val)
+        }
+        Expr::Var(var) => match env.iter().rev().find(|(v, _)| v == var) {
+            Some((_, val)) => Ok(val.clone()),
+            None => Err(format!("変数\"{var}\"は束縛されていない")),
+        },
+        Expr::Let(e) => {
+            let val = tr.sub("expr1", |tr| eval_expr(&e.expr1, env, tr))?;
+            tr.step("let", env, &[]);
+            tr.sub("expr2", |tr| bind(env, &[(&e.var, val)], &e.expr2, tr))
+        }
+        Expr::If(e) => {
+            let cond = tr.sub("cond_expr", |tr| eval_expr(&e.cond_expr, env, tr))?;
+            tr.step("if", env, &[&cond]);
+            match &*cond {
+                Value::Bool(_, true) => tr.sub("then_expr", |tr| eval_expr(&e.then_expr, env, tr)),
+                Value::Bool(_, false) => tr.sub("else_expr", |tr| eval_expr(&e.else_expr, env, tr)),
+                v => Err(format!("ifの条件式が真偽値ではない : {v}")),
+            }
+        }
+        Expr::Split(e) => {
+            let target = tr.sub("expr", |tr| eval_expr(&e.expr, env, tr))?;
+            tr.step("split", env, &[&target]);
+            match &*target {
+                Value::Pair(_, v1, v2) => {
+                    let mut vals = Vec::new();
+                    destructure(&e.left, v1, &mut vals)?;
+                    destructure(&e.right, v2, &mut vals)?;
+                    tr.sub("body", |tr| bind(env, &vals, &e.body, tr))
+                }
+                v => Err(format!("splitの対象がペアではない : {v}")),
+            }
+        }
+        Expr::Free(e) => {
+            let Some(i) = env.iter().rposition(|(v, _)| *v == e.var) else {
+                return Err(format!("変数\"{}\"は束縛されていない", e.var));
+            };
+            let freed = env.remove(i);
+            tr.step("free", env, &[&freed.1]);
+            let result = tr.sub("expr", |tr| eval_expr(&e.expr, env, tr));
+            env.insert(i, freed); // 外側のスコープでは束縛を戻す
+            result
+        }
+        Expr::App(e) => {
+            let fun = tr.sub("expr1", |tr| eval_expr(&e.expr1, env, tr))?;
+            let arg = tr.sub("expr2", |tr| eval_expr(&e.expr2, env, tr))?;
+            tr.step("app", env, &[&fun]);
+            match &*fun {
+                Value::Fun(_, f, captured) => {
+                    let mut env = captured.clone();
+                    let saved = tr.enter_fn(f);
+                    let result = bind(&mut env, &[(&f.var, arg)], &f.expr, tr);
+                    tr.path = saved;
+                    result
+                }
+                v => Err(format!("関数ではない値を適用している : {v}")),
+            }
+        }
+        Expr::Module(..) => Err("モジュールの名前解決を行っていない".to_string()),
+        Expr::Hole => Err("穴(_?)は評価できない".to_string()),
+        Expr::Error(_) => Err("パースエラーのため評価できない".to_string()),
+    }
+}
+
+/// 値valをパターンpatで分解し、束縛する変数と値をvalsに追加する
+fn destructure<'a, 'b>(
+    pat: &'b Pattern,
+    val: &Rc<Value<'a>>,
+    vals: &mut Vec<(&'b String, Rc<Value<'a>>)>,
+) -> Result<(), String> {
+    match (pat, &**val) {
+        (Pattern::Var(var), _) => vals.push((var, val.clone())),
+        (Pattern::Pair(p1, p2), Value::Pair(_, v1, v2)) => {
+            destructure(p1, v1, vals)?;
+            destructure(p2, v2, vals)?;
+        }
+        (_, v) => {
+            return Err(format!(
+                "splitのパターン{pat}でペアではない値を分解している : {v}"
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// 変数valsを束縛してbodyを評価する
+fn bind<'a>(
+    env: &mut Env<'a>,
+    vals: &[(&String, Rc<Value<'a>>)],
+    body: &'a Expr,
+    tr: &mut Trace<'a>,
+) -> EResult<'a> {
+    let len = env.len();
+    for (var, val) in vals {
+        env.push((var.to_string(), val.clone()));
+    }
+    let result = eval_expr(body, env, tr);
+    env.truncate(len);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{module::Modules, parser::parse_program};
+
+    fn eval_str(input: &str) -> Result<String, String> {
+        let (_, expr) = parse_program(input).unwrap();
+        let expr = Modules::new().resolve(expr)?;
+        eval(&expr).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(
+            eval_str("let x : un bool = un true; if x { un false } else { un true }"),
+            Ok("un false".to_string())
+        );
+
+        // 関数は定義時の環境を捕捉する
+        let input = "let x : un bool = un true;
+            let f : un (un bool -> un (un bool * un bool)) = un fn y : un bool { un <x, y> };
+            let x : un bool = un false;
+            (f x)";
+        assert_eq!(eval_str(input), Ok("un <un true, un false>".to_string()));
+
+        assert_eq!(
+            eval_str("split lin <lin true, un false> as a, b { lin <b, a> }"),
+            Ok("lin <un false, lin true>".to_string())
+        );
+        // 入れ子のパターンで、内側のペアも分解する
+        assert_eq!(
+            eval_str(
+                "split lin <un true, lin <lin false, un true>> as (a, (b, c)) { lin <lin <c, b>, a> }"
+            ),
+            Ok("lin <lin <un true, lin false>, un true>".to_string())
+        );
+        assert_eq!(
+            eval_str("lin fn x : lin bool { free x; lin false }"),
+            Ok("lin fn x : lin bool { ... }".to_string())
+        );
+
+        // モジュールの定義はlet式として評価する
+        let input = "module B = export not;
+            let not : un (lin bool -> lin bool) = un fn x : lin bool {
+                if x { lin false } else { lin true }
+            };
+        end
+        (B.not lin false)";
+        assert_eq!(eval_str(input), Ok("lin true".to_string()));
+
+        // 型付けされていない式では、解放した変数の参照はエラー
+        assert!(eval_str("let x : un bool = un true; free x; x").is_err());
+    }
+
+    #[test]
+    fn test_trace() {
+        let input = "let f : un (lin bool -> un bool) = un fn x : lin bool { if x { un true } else { un false } };
+            (f lin true)";
+        let (_, expr) = parse_program(input).unwrap();
+        // 関数本体の位置は定義した位置からのパスで表し、lin型の値は確保した順に番号を付ける
+        assert_eq!(
+            trace(&expr),
+            vec![
+                r#"{"step":1,"kind":"let","at":"/","env":[],"alloc":[],"free":[]}"#,
+                r#"{"step":2,"kind":"app","at":"/expr2","env":["f"],"alloc":[{"id":1,"value":"lin true"}],"free":[]}"#,
+                r#"{"step":3,"kind":"if","at":"/expr1/expr","env":["x"],"alloc":[],"free":[{"id":1,"value":"lin true"}]}"#,
+                r#"{"step":4,"kind":"result","value":"un true","alloc":[]}"#,
+            ]
+        );
+
+        // 評価に失敗した場合は、最後の行にエラーを記録する
+        let (_, expr) = parse_program("let x : un bool = un true; free x; x").unwrap();
+        let lines = trace(&expr);
+        assert_eq!(
+            lines.last().unwrap(),
+            r#"{"step":3,"kind":"error","message":"変数\"x\"は束縛されていない","alloc":[]}"#
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! 型付けに成功した式の定数畳み込み
+//!
+//! un型の定数(真偽値と、定数から成るペア)を束縛したlet式とsplit式を取り除いて変数の参照を定数に置き換え、
+//! 条件が定数のif式を実行される節のみに置き換える。
+//! 線形性は型付けで検査済みのため、lin型の値を含む式は変数の参照を含め、そのまま残す。
+//! 畳み込んだ式は評価に利用し、ifの条件が定数となった箇所はリントの警告に利用する。
+//! モジュールは名前解決によってlet式に変換されている必要がある。
+
+use crate::parser::{
+    AppExpr, Expr, FnExpr, FreeExpr, IfExpr, LetExpr, Pattern, QValExpr, Qual, SplitExpr, ValExpr,
+};
+use std::collections::HashMap;
+
+/// 定数畳み込みの結果
+#[derive(Debug)]
+pub struct Folded {
+    pub expr: Expr,                      // 畳み込んだ式
+    conds: HashMap<*const IfExpr, bool>, // 条件が定数となった元の式のif式(アドレスで識別)と、その値
+}
+
+impl Folded {
+    /// 元の式のif式eの条件が定数となった場合は、その値を返す
+    pub fn cond(&self, e: &IfExpr) -> Option<bool> {
+        self.conds.get(&(e as *const IfExpr)).copied()
+    }
+}
+
+/// 式exprを定数畳み込みする
+pub fn fold(expr: &Expr) -> Folded {
+    let mut folder = Folder::default();
+    let expr = folder.expr(expr);
+    Folded {
+        expr,
+        conds: folder.conds,
+    }
+}
+
+/// exprがun型の定数なら真
+fn is_const(expr: &Expr) -> bool {
+    match expr {
+        Expr::QVal(q) if q.qual == Qual::Un => match &q.val {
+            ValExpr::Bool(_) => true,
+            ValExpr::Pair(e1, e2) => is_const(e1) && is_const(e2),
+            ValExpr::Fun(_) => false,
+        },
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct Folder {
+    scope: Vec<(String, Option<Expr>)>, // 束縛された変数と、定数を束縛した場合はその値。後ろほど内側のスコープ
+    conds: HashMap<*const IfExpr, bool>, // これまでに条件が定数となったif式
+}
+
+impl Folder {
+    fn expr(&mut self, expr: &Expr) -> Expr {
+        match expr {
+            Expr::Let(e) => {
+                let expr1 = self.expr(&e.expr1);
+                if is_const(&expr1) {
+                    // 定数はすべての参照に埋め込み、let式は取り除く
+                    return self.bind(vec![(e.var.clone(), Some(expr1))], &e.expr2);
+                }
+                Expr::Let(LetExpr {
+                    var: e.var.clone(),
+                    ty: e.ty.clone(),
+                    expr1: Box::new(expr1),
+                    expr2: Box::new(self.bind(vec![(e.var.clone(), None)], &e.expr2)),
+                })
+            }
+            Expr::If(e) => {
+                let cond_expr = self.expr(&e.cond_expr);
+                // 実行されない節も、その中のif式を記録するために畳み込む
+                let then_expr = self.expr(&e.then_expr);
+                let else_expr = self.expr(&e.else_expr);
+                // 条件は消費されるだけなので、lin型の真偽値リテラルでもよい
+                if let Expr::QVal(q) = &cond_expr {
+                    if let ValExpr::Bool(b) = q.val {
+                        self.conds.insert(e as *const IfExpr, b);
+                        return if b { then_expr } else { else_expr };
+                    }
+                }
+                Expr::If(IfExpr {
+                    cond_expr: Box::new(cond_expr),
+                    then_expr: Box::new(then_expr),
+                    else_expr: Box::new(else_expr),
+                })
+            }
+            Expr::Split(e) => {
+                let target = self.expr(&e.expr);
+                if is_const(&target) {
+                    if let Expr::QVal(q) = &target {
+                        if let ValExpr::Pair(v1, v2) = &q.val {
+                            let mut vals = Vec::new();
+                            destructure(&e.left, v1, &mut vals);
+                            destructure(&e.right, v2, &mut vals);
+                            return self.bind(vals, &e.body);
+                        }
+                    }
+                }
+                let vals = e.vars().into_iter().map(|v| (v.clone(), None)).collect();
+                Expr::Split(SplitExpr {
+                    expr: Box::new(target),
+                    left: e.left.clone(),
+                    right: e.right.clone(),
+                    body: Box::new(self.bind(vals, &e.body)),
+                })
+            }
+            Expr::Free(e) => {
+                // 定数を埋め込んだ変数の束縛は取り除いているため、解放も取り除く
+                if self.lookup(&e.var).is_some() {
+                    return self.expr(&e.expr);
+                }
+                Expr::Free(FreeExpr {
+                    var: e.var.clone(),
+                    expr: Box::new(self.expr(&e.expr)),
+                })
+            }
+            Expr::App(e) => Expr::App(AppExpr {
+                expr1: Box::new(self.expr(&e.expr1)),
+                expr2: Box::new(self.expr(&e.expr2)),
+            }),
+            Expr::Var(var) => match self.lookup(var) {
+                Some(val) => val.clone(),
+                None => Expr::Var(var.clone()),
+            },
+            Expr::QVal(e) => {
+                let val = match &e.val {
+                    ValExpr::Bool(b) => ValExpr::Bool(*b),
+                    ValExpr::Pair(e1, e2) => {
+                        ValExpr::Pair(Box::new(self.expr(e1)), Box::new(self.expr(e2)))
+                    }
+                    ValExpr::Fun(f) => ValExpr::Fun(FnExpr {
+                        var: f.var.clone(),
+                        ty: f.ty.clone(),
+                        expr: Box::new(self.bind(vec![(f.var.clone(), None)], &f.expr)),
+                    }),
+                };
+                Expr::QVal(QValExpr { qual: e.qual, val })
+            }
+            // 名前解決前のモジュールは、定義とその名前を定数として扱わずに畳み込む
+            Expr::Module(m, body) => {
+                let len = self.scope.len();
+                let mut m = m.clone();
+                for (var, _, e) in m.defs.iter_mut() {
+                    *e = self.expr(e);
+                    self.scope.push((var.clone(), None));
+                    self.scope.push((format!("{}.{var}", m.name), None));
+                }
+                let body = self.expr(body);
+                self.scope.truncate(len);
+                Expr::Module(m, Box::new(body))
+            }
+            Expr::Hole | Expr::Error(_) => expr.clone(),
+        }
+    }
+
+    /// 変数valsを束縛してbodyを畳み込む
+    fn bind(&mut self, vals: Vec<(String, Option<Expr>)>, body: &Expr) -> Expr {
+        let len = self.scope.len();
+        self.scope.extend(vals);
+        let result = self.expr(body);
+        self.scope.truncate(len);
+        result
+    }
+
+    /// 変数varに定数が束縛されていれば、その値を返す
+    fn lookup(&self, var: &str) -> Option<&Expr> {
+        let (_, val) = self.scope.iter().rev().find(|(v, _)| v == var)?;
+        val.as_ref()
+    }
+}
+
+/// 定数valをパターンpatで分解し、束縛する変数と値をvalsに追加する
+fn destructure(pat: &Pattern, val: &Expr, vals: &mut Vec<(String, Option<Expr>)>) {
+    match (pat, val) {
+        (Pattern::Var(var), _) => vals.push((var.clone(), Some(val.clone()))),
+        (Pattern::Pair(p1, p2), Expr::QVal(q)) if matches!(q.val, ValExpr::Pair(..)) => {
+            if let ValExpr::Pair(v1, v2) = &q.val {
+                destructure(p1, v1, vals);
+                destructure(p2, v2, vals);
+            }
+        }
+        // 型付けに成功していれば、ペアでない値をパターンで分解することはない
+        _ => vals.extend(pat.vars().into_iter().map(|v| (v.clone(), None))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::eval, parser::parse_program};
+
+    fn fold_str(input: &str) -> Folded {
+        let (_, expr) = parse_program(input).unwrap();
+        fold(&expr)
+    }
+
+    #[test]
+    fn test_fold() {
+        // 定数を束縛したletとsplitを取り除き、条件が定数のifを畳み込む
+        let folded = fold_str(
+            "let x : un bool = un true;
+            split un <un false, un <x, un true>> as a, (b, c) {
+                if b { un <a, c> } else { un false }
+            }",
+        );
+        assert!(matches!(
+            &folded.expr,
+            Expr::QVal(q) if matches!(q.val, ValExpr::Pair(..))
+        ));
+        assert_eq!(
+            eval(&folded.expr).map(|v| v.to_string()),
+            Ok("un <un false, un true>".to_string())
+        );
+
+        // lin型の値や関数の引数は定数として扱わない
+        let folded =
+            fold_str("let x : lin bool = lin true; un fn y : un bool { if y { x } else { x } }");
+        assert!(matches!(&folded.expr, Expr::Let(_)));
+        assert!(folded.conds.is_empty());
+
+        // 定数を束縛した変数の解放は取り除く
+        let folded = fold_str("let x : un bool = un false; free x; un true");
+        assert_eq!(
+            eval(&folded.expr).map(|v| v.to_string()),
+            Ok("un true".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cond() {
+        let (_, expr) = parse_program(
+            "let x : un bool = un false;
+            if x { if un true { un true } else { un false } } else { un false }",
+        )
+        .unwrap();
+        let folded = fold(&expr);
+        let Expr::Let(e) = &expr else { unreachable!() };
+        let Expr::If(outer) = e.expr2.as_ref() else {
+            unreachable!()
+        };
+        let Expr::If(inner) = outer.then_expr.as_ref() else {
+            unreachable!()
+        };
+        assert_eq!(folded.cond(outer), Some(false));
+        // 実行されない節のif式も記録する
+        assert_eq!(folded.cond(inner), Some(true));
+    }
+}
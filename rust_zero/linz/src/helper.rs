@@ -21,4 +21,20 @@ where
     }
 }
 
+/// safe_addに加えて、加算した結果がmaxを超える場合もfの返すエラーとする
+///
+/// 型の入れ子の深さなど、オーバーフローより手前で打ち切りたい値の上限の検査に使う
+pub fn bounded_add<T, F, E>(dst: &mut T, src: &T, max: &T, f: F) -> Result<(), E>
+where
+    T: SafeAdd + PartialOrd,
+    F: Fn() -> E,
+{
+    safe_add(dst, src, &f)?;
+    if *dst > *max {
+        Err(f())
+    } else {
+        Ok(())
+    }
+}
+
 pub type DynError = Box<dyn std::error::Error + Send + Sync + 'static>;
@@ -0,0 +1,222 @@
+//! 型付けに成功した式に対するリント
+//!
+//! 型エラーではないが、誤りの可能性が高い箇所を警告として報告する。
+//! 警告はエラーと異なり型付けの結果に影響しないが、--deny-warningsを指定するとエラーとして扱う。
+//! モジュールの定義(M.x)は、モジュール外から参照されうるため未使用の警告を行わない。
+//! ifの条件が定数かどうかは、定数畳み込みの結果を利用して判定する。
+
+use crate::{
+    fold::Folded,
+    parser::{Expr, ValExpr},
+};
+use std::fmt;
+
+/// リントの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lint {
+    UnusedVariable,    // 使用されないun型の変数
+    UnreachableBranch, // 条件が定数のため到達しないifの節
+}
+
+impl Lint {
+    /// 警告に表示するリントコード
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused_variable",
+            Lint::UnreachableBranch => "unreachable_branch",
+        }
+    }
+}
+
+/// リントによる警告
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "警告[{}]: {}", self.lint.code(), self.message)
+    }
+}
+
+/// 型付けに成功した式exprを検査し、警告を出現順に返す。foldedはexprを定数畳み込みした結果
+///
+/// 型付けに成功した式では、lin型の変数はすべて消費されているため、
+/// 一度も参照されない変数はun型の変数である
+pub fn lint(expr: &Expr, folded: &Folded) -> Vec<Warning> {
+    let mut linter = Linter {
+        folded,
+        scope: Vec::new(),
+        warnings: Vec::new(),
+    };
+    linter.expr(expr);
+    linter.warnings
+}
+
+/// 束縛された変数
+struct Binding {
+    name: String,
+    used: bool, // 一度でも参照されたら真
+    pos: usize, // 束縛した時点のwarningsの長さ。未使用の警告を挿入する位置
+}
+
+struct Linter<'a> {
+    folded: &'a Folded,     // 定数畳み込みの結果
+    scope: Vec<Binding>,    // 束縛された変数のスタック。後ろほど内側のスコープ
+    warnings: Vec<Warning>, // これまでの警告
+}
+
+impl Linter<'_> {
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Let(e) => {
+                self.expr(&e.expr1);
+                self.bind(&[&e.var], &e.expr2);
+            }
+            Expr::If(e) => {
+                self.expr(&e.cond_expr);
+                if let Some(b) = self.folded.cond(e) {
+                    let (taken, skipped) = if b {
+                        ("true", "else")
+                    } else {
+                        ("false", "then")
+                    };
+                    self.warn(
+                        Lint::UnreachableBranch,
+                        format!("条件が{taken}のため、ifの{skipped}節は実行されない"),
+                    );
+                }
+                self.expr(&e.then_expr);
+                self.expr(&e.else_expr);
+            }
+            Expr::Split(e) => {
+                self.expr(&e.expr);
+                self.bind(&e.vars(), &e.body);
+            }
+            Expr::Free(e) => {
+                self.use_var(&e.var);
+                self.expr(&e.expr);
+            }
+            Expr::App(e) => {
+                self.expr(&e.expr1);
+                self.expr(&e.expr2);
+            }
+            Expr::Var(var) => self.use_var(var),
+            Expr::QVal(e) => match &e.val {
+                ValExpr::Bool(_) => (),
+                ValExpr::Pair(e1, e2) => {
+                    self.expr(e1);
+                    self.expr(e2);
+                }
+                ValExpr::Fun(f) => self.bind(&[&f.var], &f.expr),
+            },
+            Expr::Module(m, body) => {
+                for (_, _, e) in m.defs.iter() {
+                    self.expr(e);
+                }
+                self.expr(body);
+            }
+            Expr::Hole | Expr::Error(_) => (),
+        }
+    }
+
+    /// 変数varsを束縛してbodyを検査し、参照されなかった変数を警告する
+    fn bind(&mut self, vars: &[&String], body: &Expr) {
+        for var in vars {
+            self.scope.push(Binding {
+                name: var.to_string(),
+                used: false,
+                pos: self.warnings.len(),
+            });
+        }
+
+        self.expr(body);
+
+        // 束縛した順に警告が並ぶように、後ろから同じ位置に挿入する
+        for _ in vars {
+            let b = self.scope.pop().unwrap();
+            if !b.used && !b.name.contains('.') {
+                let w = Warning {
+                    lint: Lint::UnusedVariable,
+                    message: format!("変数\"{}\"は使用されていない", b.name),
+                };
+                self.warnings.insert(b.pos, w);
+            }
+        }
+    }
+
+    /// 変数varを参照する。シャドーイングを考慮し、最も内側の束縛を使用済みにする
+    fn use_var(&mut self, var: &str) {
+        if let Some(b) = self.scope.iter_mut().rev().find(|b| b.name == var) {
+            b.used = true;
+        }
+    }
+
+    fn warn(&mut self, lint: Lint, message: String) {
+        self.warnings.push(Warning { lint, message });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fold::fold, parser::parse_program};
+
+    fn lint_str(input: &str) -> Vec<String> {
+        let (_, expr) = parse_program(input).unwrap();
+        lint(&expr, &fold(&expr))
+            .iter()
+            .map(|w| w.to_string())
+            .collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn test_unused_variable() {
+        assert_eq!(
+            lint_str("let x : un bool = un true;\nun fn y : un bool { x }"),
+            vec!["警告[unused_variable]: 変数\"y\"は使用されていない"]
+        );
+
+        // 内側の束縛のみ参照した場合は、外側の変数が未使用
+        assert_eq!(
+            lint_str("un fn x : un bool { un fn x : un bool { x } }"),
+            vec!["警告[unused_variable]: 変数\"x\"は使用されていない"]
+        );
+
+        // 束縛した順に報告する
+        assert_eq!(
+            lint_str(
+                "split un <un true, un false> as a, b { let c : un bool = un true; un false }"
+            ),
+            vec![
+                "警告[unused_variable]: 変数\"a\"は使用されていない",
+                "警告[unused_variable]: 変数\"b\"は使用されていない",
+                "警告[unused_variable]: 変数\"c\"は使用されていない",
+            ]
+        );
+
+        // lin型の変数はfreeで解放すると使用済みになる
+        assert!(lint_str("lin fn x : lin bool { free x; un true }").is_empty());
+    }
+
+    #[test]
+    fn test_unreachable_branch() {
+        assert_eq!(
+            lint_str("if un true { un true } else { un false }"),
+            vec!["警告[unreachable_branch]: 条件がtrueのため、ifのelse節は実行されない"]
+        );
+        assert_eq!(
+            lint_str("if lin false { un true } else { un false }"),
+            vec!["警告[unreachable_branch]: 条件がfalseのため、ifのthen節は実行されない"]
+        );
+        assert!(lint_str("un fn x : un bool { if x { x } else { x } }").is_empty());
+
+        // 定数を束縛した変数も、畳み込んだ結果が定数なら警告する
+        assert_eq!(
+            lint_str("let x : un bool = un false; if x { un true } else { x }"),
+            vec!["警告[unreachable_branch]: 条件がfalseのため、ifのthen節は実行されない"]
+        );
+    }
+}
@@ -1,5 +1,11 @@
+mod eval;
+mod fold;
 mod helper;
+mod lint;
+mod module;
 mod parser;
+mod repl;
+mod tour;
 mod typing;
 
 use nom::error::convert_error;
@@ -7,28 +13,128 @@ use std::{env, error::Error, fs};
 
 fn main() -> Result<(), Box<dyn Error>> {
     // コマンドライン引数の検査
-    let args: Vec<String> = env::args().collect();
+    // --deny-warningsを指定した場合は、リントの警告もエラーとする
+    // --no-shadowを指定した場合は、消費されていないlin型の変数のシャドーイングをエラーとする
+    // --dump-foldedを指定した場合は、定数畳み込みした後の式を表示する
+    // --trace-eval jsonを指定した場合は、型付けに成功した式を評価し、簡約ごとの記録をJSON Linesで出力する
+    // --max-type-depth N、--max-type-size Nを指定した場合は、型の入れ子の深さと大きさの上限を変更する
+    // ファイル名が指定されていない場合はREPLを起動
+    // tourを指定した場合は、例題を順に実行するツアーを起動
+    let mut args: Vec<String> = env::args().collect();
+    let deny_warnings = args.iter().any(|a| a == "--deny-warnings");
+    let no_shadow = args.iter().any(|a| a == "--no-shadow");
+    let dump_folded = args.iter().any(|a| a == "--dump-folded");
+    args.retain(|a| a != "--deny-warnings" && a != "--no-shadow" && a != "--dump-folded");
+    let trace_eval = match args.iter().position(|a| a == "--trace-eval") {
+        Some(i) => {
+            let format = args.drain(i..(i + 2).min(args.len())).nth(1);
+            if format.as_deref() != Some("json") {
+                return Err("--trace-evalの形式はjsonのみ指定できます".into());
+            }
+            true
+        }
+        None => false,
+    };
+    let mut limits = typing::TypeLimits::default();
+    for (opt, limit) in [
+        ("--max-type-depth", &mut limits.max_depth),
+        ("--max-type-size", &mut limits.max_size),
+    ] {
+        if let Some(i) = args.iter().position(|a| a == opt) {
+            let n = args.drain(i..(i + 2).min(args.len())).nth(1);
+            *limit = n
+                .as_deref()
+                .and_then(|n| n.parse().ok())
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("{opt}には正の整数を指定してください"))?;
+        }
+    }
     if args.len() < 2 {
-        eprintln!("以下のようにファイル名を指定して実行してください\ncargo run codes/ex1.lin");
-        return Err("引数が不足".into());
+        eprintln!("ファイルを検査する場合は、以下のようにファイル名を指定して実行してください\ncargo run codes/ex1.lin\ncargo run -- --deny-warnings codes/ex1.lin\ncargo run -- --no-shadow codes/shadow1.lin\ncargo run -- --dump-folded codes/fold1.lin\ncargo run -- --trace-eval json codes/ex6.lin\ncargo run -- --max-type-depth 8 codes/ex1.lin");
+        eprintln!("例題のツアーは、cargo run tour [例題の番号] で起動します");
+        eprintln!(":helpでREPLのヘルプを表示します");
+        repl::Repl::new().run()?;
+        return Ok(());
+    }
+
+    if args[1] == "tour" {
+        let only = match args.get(2) {
+            Some(n) => Some(
+                n.parse()
+                    .map_err(|_| format!("例題の番号が不正です : {n}"))?,
+            ),
+            None => None,
+        };
+        tour::run(only)?;
+        return Ok(());
     }
 
     // ファイル読み込み
     let content = fs::read_to_string(&args[1])?;
 
-    let ast = parser::parse_expr(&content); // パース
+    let ast = parser::parse_program(&content); // パース
     println!("AST:\n{:#?}\n", ast);
 
     match ast {
         Ok((_, expr)) => {
+            // パースエラーから回復した箇所があればすべて報告し、
+            // 正しくパースできた部分について型付けを続ける
+            let errors = expr.errors();
+            for e in errors.iter() {
+                eprintln!("パースエラー:\n{}", e.message(&content));
+            }
+            let num_errors = errors.len();
+
+            // モジュールの名前解決
+            let expr = match module::Modules::new().resolve(expr) {
+                Ok(expr) => expr,
+                Err(e) => {
+                    eprintln!("名前解決エラー:\n{e}");
+                    return Err(e.into());
+                }
+            };
+
             let mut ctx = typing::TypeEnv::new();
+            ctx.set_no_shadow(no_shadow);
+            ctx.set_limits(limits);
             println!("式:\n{content}");
 
             // 型付け
-            let a = typing::typing(&expr, &mut ctx, 0)?;
+            // 穴(_?)の報告は複数行になるため、エラーをそのまま表示してから終了する
+            let a = match typing::typing(&expr, &mut ctx, 0) {
+                Ok(a) => a,
+                Err(e) => {
+                    eprintln!("型エラー:\n{e}");
+                    return Err(e.into());
+                }
+            };
             println!("の型は\n{a}\nです。");
+
+            if num_errors > 0 {
+                return Err(format!("{num_errors}個のパースエラー").into());
+            }
+
+            // 型付けに成功した場合のみ定数畳み込みとリントを行う
+            let folded = fold::fold(&expr);
+            if dump_folded {
+                println!("定数畳み込み後のAST:\n{:#?}\n", folded.expr);
+            }
+            let warnings = lint::lint(&expr, &folded);
+            for w in warnings.iter() {
+                eprintln!("{w}");
+            }
+            if deny_warnings && !warnings.is_empty() {
+                return Err(format!("{}個の警告 (--deny-warnings)", warnings.len()).into());
+            }
+
+            // 畳み込む前の式を評価し、プログラムのとおりの簡約を記録する
+            if trace_eval {
+                for line in eval::trace(&expr) {
+                    println!("{line}");
+                }
+            }
         }
-        Err(nom::Err::Error(e)) => {
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
             let msg = convert_error(content.as_str(), e);
             eprintln!("パースエラー:\n{msg}");
             return Err(msg.into());
@@ -0,0 +1,259 @@
+//! モジュールの名前解決
+//!
+//! `module M = export x; let x : T = e; end`でまとめた定義は、モジュール内では名前のみで、
+//! モジュール外からは公開した名前のみをM.xとして参照する。
+//!
+//! 型付けの前にモジュール定義を、M.xという名前の変数を束縛するlet式に変換し、
+//! モジュール内での名前のみの参照もM.xに置き換える。
+//! 変数名はアルファベットのみから成るため、M.xが利用者の変数と衝突することはない。
+
+use crate::parser::{Expr, LetExpr, ModuleDef, TypeExpr, ValExpr};
+use std::{collections::BTreeMap, mem};
+
+/// 名前解決の結果。エラー時にはメッセージを返す
+pub type RResult<T> = Result<T, String>;
+
+/// 参照できるモジュールと、名前解決中のスコープ
+#[derive(Debug, Clone, Default)]
+pub struct Modules {
+    exports: BTreeMap<String, Vec<String>>, // モジュール名から公開する名前へのマップ
+    scope: Vec<(String, String)>,           // 束縛された(名前, 解決後の名前)のスタック
+}
+
+impl Modules {
+    pub fn new() -> Self {
+        Modules::default()
+    }
+
+    /// 式exprに含まれるモジュール定義をlet式に変換し、変数の参照を解決する
+    pub fn resolve(&mut self, mut expr: Expr) -> RResult<Expr> {
+        self.expr(&mut expr)?;
+        Ok(expr)
+    }
+
+    /// モジュール定義mの各定義を、(M.x, 型, 式)として返す
+    ///
+    /// 以降のresolveでは、このモジュールの公開した名前を参照できる。
+    /// REPLのトップレベルでモジュールを定義するために利用する
+    pub fn define(&mut self, m: ModuleDef) -> RResult<Vec<(String, TypeExpr, Expr)>> {
+        let name = m.name.clone();
+        let exports = m.exports.clone();
+        let defs = self.module(m)?;
+        self.exports.insert(name, exports);
+        Ok(defs)
+    }
+
+    /// モジュールの定義を順に解決し、(M.x, 型, 式)として返す
+    ///
+    /// 各定義の式では、それより前の定義を名前のみで参照できる
+    fn module(&mut self, m: ModuleDef) -> RResult<Vec<(String, TypeExpr, Expr)>> {
+        let ModuleDef {
+            name,
+            exports,
+            defs,
+        } = m;
+        for (i, (var, _, _)) in defs.iter().enumerate() {
+            if defs[..i].iter().any(|(v, _, _)| v == var) {
+                return Err(format!(
+                    "モジュール\"{name}\"で\"{var}\"が複数回定義されている"
+                ));
+            }
+        }
+        if let Some(var) = exports
+            .iter()
+            .find(|x| !defs.iter().any(|(v, _, _)| v == *x))
+        {
+            return Err(format!(
+                "モジュール\"{name}\"で公開する\"{var}\"が定義されていない"
+            ));
+        }
+
+        let len = self.scope.len();
+        let mut result = Vec::new();
+        for (var, ty, mut expr) in defs {
+            if let Err(e) = self.expr(&mut expr) {
+                self.scope.truncate(len);
+                return Err(e);
+            }
+            let qualified = format!("{name}.{var}");
+            self.scope.push((var, qualified.clone()));
+            result.push((qualified, ty, expr));
+        }
+        self.scope.truncate(len);
+        Ok(result)
+    }
+
+    fn expr(&mut self, expr: &mut Expr) -> RResult<()> {
+        match expr {
+            Expr::Let(e) => {
+                self.expr(&mut e.expr1)?;
+                self.bind(&[&e.var], &mut e.expr2)
+            }
+            Expr::If(e) => {
+                self.expr(&mut e.cond_expr)?;
+                self.expr(&mut e.then_expr)?;
+                self.expr(&mut e.else_expr)
+            }
+            Expr::Split(e) => {
+                self.expr(&mut e.expr)?;
+                let vars: Vec<String> = e.vars().into_iter().cloned().collect();
+                self.bind(&vars.iter().collect::<Vec<_>>(), &mut e.body)
+            }
+            Expr::Free(e) => {
+                e.var = self.lookup(&e.var)?;
+                self.expr(&mut e.expr)
+            }
+            Expr::App(e) => {
+                self.expr(&mut e.expr1)?;
+                self.expr(&mut e.expr2)
+            }
+            Expr::Var(var) => {
+                *var = self.lookup(var)?;
+                Ok(())
+            }
+            Expr::QVal(e) => match &mut e.val {
+                ValExpr::Bool(_) => Ok(()),
+                ValExpr::Pair(e1, e2) => {
+                    self.expr(e1)?;
+                    self.expr(e2)
+                }
+                ValExpr::Fun(f) => self.bind(&[&f.var], &mut f.expr),
+            },
+            Expr::Module(..) => {
+                let Expr::Module(m, body) = mem::replace(expr, Expr::Hole) else {
+                    unreachable!()
+                };
+                *expr = self.module_expr(m, *body)?;
+                Ok(())
+            }
+            Expr::Hole | Expr::Error(_) => Ok(()),
+        }
+    }
+
+    /// モジュール定義mを、M.xを束縛するlet式を連ねて本体bodyを評価する式に変換する
+    ///
+    /// モジュールはbodyの中でのみ参照できる
+    fn module_expr(&mut self, m: ModuleDef, mut body: Expr) -> RResult<Expr> {
+        let name = m.name.clone();
+        let prev = self.exports.insert(name.clone(), m.exports.clone());
+        let result = self.module(m).and_then(|defs| {
+            self.expr(&mut body)?;
+            Ok(defs)
+        });
+        match prev {
+            Some(prev) => self.exports.insert(name, prev),
+            None => self.exports.remove(&name),
+        };
+
+        let mut expr = body;
+        for (var, ty, expr1) in result?.into_iter().rev() {
+            expr = Expr::Let(LetExpr {
+                var,
+                ty,
+                expr1: Box::new(expr1),
+                expr2: Box::new(expr),
+            });
+        }
+        Ok(expr)
+    }
+
+    /// 変数varsを束縛してbodyを解決する
+    fn bind(&mut self, vars: &[&String], body: &mut Expr) -> RResult<()> {
+        let len = self.scope.len();
+        for var in vars {
+            self.scope.push((var.to_string(), var.to_string()));
+        }
+        let result = self.expr(body);
+        self.scope.truncate(len);
+        result
+    }
+
+    /// 変数の参照varを解決した名前を返す
+    ///
+    /// M.xはモジュールMが公開する名前でなければエラーとする。
+    /// 名前のみの場合は最も内側の束縛を参照し、それがモジュール内の定義ならM.xとする
+    fn lookup(&self, var: &str) -> RResult<String> {
+        if let Some((module, name)) = var.split_once('.') {
+            let Some(exports) = self.exports.get(module) else {
+                return Err(format!("モジュール\"{module}\"は定義されていない"));
+            };
+            if !exports.iter().any(|x| x == name) {
+                return Err(format!(
+                    "\"{var}\"はモジュール\"{module}\"から公開されていない"
+                ));
+            }
+            return Ok(var.to_string());
+        }
+
+        match self.scope.iter().rev().find(|(v, _)| v == var) {
+            Some((_, resolved)) => Ok(resolved.clone()),
+            None => Ok(var.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::parse_program,
+        typing::{typing, TypeEnv},
+    };
+
+    fn typing_str(input: &str) -> Result<String, String> {
+        let (_, expr) = parse_program(input).unwrap();
+        let expr = Modules::new().resolve(expr)?;
+        typing(&expr, &mut TypeEnv::new(), 0)
+            .map(|t| t.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_module() {
+        // モジュール内では名前のみで参照し、外からはM.xとして参照する
+        // モジュール外の同名の変数とは衝突しない
+        let input = "module B = export not;
+            let id : un (lin bool -> lin bool) = un fn x : lin bool { x };
+            let not : un (lin bool -> lin bool) = un fn x : lin bool {
+                if (id x) { lin false } else { lin true }
+            };
+        end
+        let not : un bool = un true;
+        lin <(B.not lin true), not>";
+        assert_eq!(
+            typing_str(input),
+            Ok("lin (lin bool * un bool)".to_string())
+        );
+
+        // 公開されていない名前は参照できない
+        let input =
+            "module B = export t; let f : un bool = un false; let t : un bool = un true; end B.f";
+        assert_eq!(
+            typing_str(input),
+            Err("\"B.f\"はモジュール\"B\"から公開されていない".to_string())
+        );
+        assert_eq!(
+            typing_str("un <B.t, un true>"),
+            Err("モジュール\"B\"は定義されていない".to_string())
+        );
+
+        // モジュール内の定義はシャドーイングできる
+        let input = "module B = export t; let t : un bool = un true; end
+            un fn t : un bool { un <t, B.t> }";
+        assert_eq!(
+            typing_str(input),
+            Ok("un (un bool -> un (un bool * un bool))".to_string())
+        );
+
+        // 公開する名前は定義されている必要がある
+        assert_eq!(
+            typing_str("module B = export f; let t : un bool = un true; end B.t"),
+            Err("モジュール\"B\"で公開する\"f\"が定義されていない".to_string())
+        );
+
+        // 消費されないlin型の定義はエラー
+        assert!(
+            typing_str("module B = export t; let t : lin bool = lin true; end un true").is_err()
+        );
+    }
+}
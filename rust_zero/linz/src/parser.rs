@@ -2,34 +2,84 @@ use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{alpha1, char, multispace0, multispace1},
-    error::VerboseError,
+    combinator::{eof, map, opt},
+    error::{convert_error, VerboseError, VerboseErrorKind},
     multi,
-    sequence::delimited,
+    sequence::{delimited, preceded, terminated},
     IResult,
 };
-use std::fmt;
+use std::{cell::Cell, fmt};
 
 /// 抽象構文木
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Expr {
-    Let(LetExpr),     // let式
-    If(IfExpr),       // if式
-    Split(SplitExpr), // split式
-    Free(FreeExpr),   // free文
-    App(AppExpr),     // 関数適用
-    Var(String),      // 変数
-    QVal(QValExpr),   // 値
+    Let(LetExpr),                 // let式
+    If(IfExpr),                   // if式
+    Split(SplitExpr),             // split式
+    Free(FreeExpr),               // free文
+    Module(ModuleDef, Box<Expr>), // モジュール定義と、そのモジュールを参照できる式
+    App(AppExpr),                 // 関数適用
+    Var(String),                  // 変数。M.xはモジュールMの変数x
+    QVal(QValExpr),               // 値
+    Hole,                         // 穴(_?)。型付け時に期待される型と利用可能な変数を報告する
+    Error(ParseError),            // パースエラーから回復するために読み飛ばした式
 }
 
-/// 関数適用
+/// パースエラー
+///
+/// エラー箇所は入力の末尾からのバイト数として保持し、
+/// 元の入力と合わせてメッセージに変換する。
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    errors: Vec<(usize, VerboseErrorKind)>,
+}
+
+impl ParseError {
+    fn new(e: VerboseError<&str>) -> Self {
+        ParseError {
+            errors: e.errors.into_iter().map(|(s, k)| (s.len(), k)).collect(),
+        }
+    }
+
+    /// パースした入力inputを元に、エラー箇所を示すメッセージを生成
+    pub fn message(&self, input: &str) -> String {
+        let errors = self
+            .errors
+            .iter()
+            .map(|(len, kind)| (&input[input.len() - len..], kind.clone()))
+            .collect();
+        convert_error(input, VerboseError { errors })
+    }
+}
+
+/// REPLのトップレベルでの入力
 #[derive(Debug)]
+pub enum TopLevel {
+    Def(String, TypeExpr, Expr), // 変数定義。let x : T = e;
+    Module(ModuleDef),           // モジュール定義。module M = export x; let x : T = e; end
+    Expr(Expr),                  // 式
+}
+
+/// モジュール定義
+///
+/// `module M = export x, y; let x : T = e; let y : T = e; end`のように、公開する名前と変数定義をまとめる。
+/// モジュール内の定義は名前のみで、モジュール外からは公開した名前のみをM.xとして参照する
+#[derive(Debug, Clone)]
+pub struct ModuleDef {
+    pub name: String,
+    pub exports: Vec<String>,
+    pub defs: Vec<(String, TypeExpr, Expr)>,
+}
+
+/// 関数適用
+#[derive(Debug, Clone)]
 pub struct AppExpr {
     pub expr1: Box<Expr>,
     pub expr2: Box<Expr>,
 }
 
 /// if式
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IfExpr {
     pub cond_expr: Box<Expr>,
     pub then_expr: Box<Expr>,
@@ -37,16 +87,43 @@ pub struct IfExpr {
 }
 
 /// split式
-#[derive(Debug)]
+///
+/// `split e as a, (b, c) { ... }`のように、ペアの要素をそれぞれパターンで束縛する。
+/// `split e as (a, (b, c)) { ... }`のように、全体を括弧で囲んでもよい
+#[derive(Debug, Clone)]
 pub struct SplitExpr {
     pub expr: Box<Expr>,
-    pub left: String,
-    pub right: String,
+    pub left: Pattern,
+    pub right: Pattern,
     pub body: Box<Expr>,
 }
 
+/// splitで分解した要素を束縛するパターン
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Var(String),                      // 変数に束縛
+    Pair(Box<Pattern>, Box<Pattern>), // ペアをさらに分解して、要素をそれぞれのパターンで束縛
+}
+
+impl Pattern {
+    /// パターンが束縛する変数を、左から順に返す
+    pub fn vars(&self) -> Vec<&String> {
+        match self {
+            Pattern::Var(v) => vec![v],
+            Pattern::Pair(p1, p2) => [p1.vars(), p2.vars()].concat(),
+        }
+    }
+}
+
+impl SplitExpr {
+    /// splitが束縛する変数を、左から順に返す
+    pub fn vars(&self) -> Vec<&String> {
+        [self.left.vars(), self.right.vars()].concat()
+    }
+}
+
 /// let式
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LetExpr {
     pub var: String,
     pub ty: TypeExpr,
@@ -55,7 +132,7 @@ pub struct LetExpr {
 }
 
 /// 値。真偽値、関数、ペア値などになる
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ValExpr {
     Bool(bool),                 // 真偽値リテラル
     Pair(Box<Expr>, Box<Expr>), // ペア
@@ -70,14 +147,14 @@ pub enum Qual {
 }
 
 /// 修飾子付き値
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct QValExpr {
     pub qual: Qual,
     pub val: ValExpr,
 }
 
 /// 関数
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FnExpr {
     pub var: String,
     pub ty: TypeExpr,
@@ -85,7 +162,7 @@ pub struct FnExpr {
 }
 
 /// free文
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FreeExpr {
     pub var: String,
     pub expr: Box<Expr>,
@@ -106,6 +183,83 @@ pub enum PrimType {
     Arrow(Box<TypeExpr>, Box<TypeExpr>), // 関数型
 }
 
+impl Expr {
+    /// 式に含まれるパースエラーを、出現順に返す
+    pub fn errors(&self) -> Vec<&ParseError> {
+        let mut errors = Vec::new();
+        self.collect_errors(&mut errors);
+        errors
+    }
+
+    fn collect_errors<'a>(&'a self, errors: &mut Vec<&'a ParseError>) {
+        match self {
+            Expr::Let(e) => {
+                e.expr1.collect_errors(errors);
+                e.expr2.collect_errors(errors);
+            }
+            Expr::If(e) => {
+                e.cond_expr.collect_errors(errors);
+                e.then_expr.collect_errors(errors);
+                e.else_expr.collect_errors(errors);
+            }
+            Expr::Split(e) => {
+                e.expr.collect_errors(errors);
+                e.body.collect_errors(errors);
+            }
+            Expr::Free(e) => e.expr.collect_errors(errors),
+            Expr::Module(m, body) => {
+                m.collect_errors(errors);
+                body.collect_errors(errors);
+            }
+            Expr::App(e) => {
+                e.expr1.collect_errors(errors);
+                e.expr2.collect_errors(errors);
+            }
+            Expr::QVal(e) => match &e.val {
+                ValExpr::Pair(e1, e2) => {
+                    e1.collect_errors(errors);
+                    e2.collect_errors(errors);
+                }
+                ValExpr::Fun(f) => f.expr.collect_errors(errors),
+                ValExpr::Bool(_) => (),
+            },
+            Expr::Var(_) | Expr::Hole => (),
+            Expr::Error(e) => errors.push(e),
+        }
+    }
+}
+
+impl ModuleDef {
+    fn collect_errors<'a>(&'a self, errors: &mut Vec<&'a ParseError>) {
+        for (_, _, e) in self.defs.iter() {
+            e.collect_errors(errors);
+        }
+    }
+}
+
+impl TopLevel {
+    /// 入力に含まれるパースエラーを、出現順に返す
+    pub fn errors(&self) -> Vec<&ParseError> {
+        match self {
+            TopLevel::Def(_, _, e) | TopLevel::Expr(e) => e.errors(),
+            TopLevel::Module(m) => {
+                let mut errors = Vec::new();
+                m.collect_errors(&mut errors);
+                errors
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Var(v) => write!(f, "{v}"),
+            Pattern::Pair(p1, p2) => write!(f, "({p1}, {p2})"),
+        }
+    }
+}
+
 impl fmt::Display for TypeExpr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.qual == Qual::Lin {
@@ -126,48 +280,154 @@ impl fmt::Display for PrimType {
     }
 }
 
+/// パース中の式、型、パターンの入れ子の深さの上限
+///
+/// letやfreeに続く式は入れ子として数えない。
+/// パーサは再帰下降で、型付けなども構文木を再帰的にたどるため、深すぎる入れ子はスタックを使い切る。
+/// 上限を超えた時点でパースエラーとし、それ以上は再帰しない。
+/// 既定の型の深さの上限(256)を少し超えるペアなどは、型付けで報告できるよう少し大きくしている
+pub const MAX_NESTING: usize = 300;
+
+thread_local! {
+    /// パース中の式、型、パターンの入れ子の深さ
+    static NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// 式、型、パターンのパースに入る際に入れ子の深さを1増やし、dropで元に戻す
+struct Nest;
+
+impl Nest {
+    /// 深さがMAX_NESTINGを超える場合は、入力iの位置を示すエラーを返す
+    fn enter(i: &str) -> Result<Nest, nom::Err<VerboseError<&str>>> {
+        let depth = NESTING.with(|n| {
+            n.set(n.get() + 1);
+            n.get()
+        });
+        let nest = Nest; // エラーの場合もdropで深さを戻す
+        if depth > MAX_NESTING {
+            return Err(nom::Err::Failure(VerboseError {
+                errors: vec![(i, VerboseErrorKind::Context("式か型の入れ子が深すぎる"))],
+            }));
+        }
+        Ok(nest)
+    }
+}
+
+impl Drop for Nest {
+    fn drop(&mut self) {
+        NESTING.with(|n| n.set(n.get() - 1));
+    }
+}
+
 pub fn parse_expr(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
+    let nest = Nest::enter(i)?;
     let (i, _) = multispace0(i)?;
-    let (i, val) = alt((alpha1, tag("(")))(i)?;
+    let (i, val) = alt((alpha1, tag("("), tag("_?")))(i)?;
 
     match val {
-        "let" => parse_let(i),
+        "let" => parse_let(i, nest),
         "if" => parse_if(i),
         "split" => parse_split(i),
-        "free" => parse_free(i),
+        "free" => parse_free(i, nest),
+        "module" => parse_module(i, nest),
         "lin" => parse_qval(Qual::Lin, i),
         "un" => parse_qval(Qual::Un, i),
         "(" => parse_app(i),
-        _ => Ok((i, Expr::Var(val.to_string()))),
+        "_?" => Ok((i, Expr::Hole)),
+        _ => map(|i| parse_qualified(val, i), Expr::Var)(i),
     }
 }
 
-fn parse_let(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
-    let (i, _) = multispace1(i)?;
-    let (i, var) = alpha1(i)?;
+/// ファイル全体をパース
+///
+/// 式の後に入力が残っている場合はエラーとなる。
+/// 回復可能なパースエラーは式中のExpr::Errorとなるため、Expr::errorsで取得する。
+pub fn parse_program(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
+    let (i, expr) = parse_expr(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, _) = eof(i)?;
+    Ok((i, expr))
+}
 
+/// REPLの入力をパース
+///
+/// 後続の式を持たない`let x : T = e;`は変数定義として、それ以外は式としてパースする。
+/// どちらの場合も、入力をすべて消費できなければエラーとなる。
+pub fn parse_toplevel(i: &str) -> IResult<&str, TopLevel, VerboseError<&str>> {
     let (i, _) = multispace0(i)?;
-    let (i, _) = char(':')(i)?;
+    let (i, top) = alt((parse_def, parse_module_def, map(parse_expr, TopLevel::Expr)))(i)?;
     let (i, _) = multispace0(i)?;
+    let (i, _) = eof(i)?;
+    Ok((i, top))
+}
 
-    let (i, ty) = parse_type(i)?; // 引数の型
+/// 変数定義をパース
+fn parse_def(i: &str) -> IResult<&str, TopLevel, VerboseError<&str>> {
+    let (i, _) = tag("let")(i)?;
+    let (i, (var, ty, expr)) = parse_let_head(i)?;
+    let (i, _) = eof(i)?; // 後続の式がある場合はlet式
+    Ok((i, TopLevel::Def(var, ty, expr)))
+}
+
+/// モジュール定義をパース
+fn parse_module_def(i: &str) -> IResult<&str, TopLevel, VerboseError<&str>> {
+    let (i, _) = tag("module")(i)?;
+    let (i, m) = parse_module_head(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, _) = eof(i)?; // 後続の式がある場合はモジュール式
+    Ok((i, TopLevel::Module(m)))
+}
 
+/// モジュール定義と、続く式をパース
+///
+/// 続く式は入れ子ではないため、nestで増やした深さを戻してからパースする
+fn parse_module(i: &str, nest: Nest) -> IResult<&str, Expr, VerboseError<&str>> {
+    let (i, m) = parse_module_head(i)?;
+    drop(nest);
+    let (i, body) = parse_expr(i)?;
+    Ok((i, Expr::Module(m, Box::new(body))))
+}
+
+/// モジュール定義の`M = export x, y; let ... end`までをパース
+fn parse_module_head(i: &str) -> IResult<&str, ModuleDef, VerboseError<&str>> {
     let (i, _) = multispace1(i)?;
-    let (i, _) = char('=')(i)?;
+    let (i, name) = alpha1(i)?;
+    let (i, _) = delimited(multispace0, char('='), multispace0)(i)?;
+
+    // 公開する名前
+    let (i, _) = tag("export")(i)?;
     let (i, _) = multispace1(i)?;
+    let (i, exports) =
+        multi::separated_list1(delimited(multispace0, char(','), multispace0), parse_var)(i)?;
+    let (i, _) = delimited(multispace0, char(';'), multispace0)(i)?;
 
-    let (i, expr1) = parse_expr(i)?;
+    // 変数定義
+    let (i, defs) = multi::many0(preceded(tag("let"), parse_let_head))(i)?;
+    let (i, _) = tag("end")(i)?;
 
-    let (i, _) = multispace0(i)?;
-    let (i, _) = char(';')(i)?;
-    let (i, _) = multispace0(i)?;
+    Ok((
+        i,
+        ModuleDef {
+            name: name.to_string(),
+            exports,
+            defs,
+        },
+    ))
+}
 
+/// let式をパース
+///
+/// 続く式は入れ子ではないため、nestで増やした深さを戻してからパースする。
+/// これにより、letを並べたプログラムはMAX_NESTINGに制限されない
+fn parse_let(i: &str, nest: Nest) -> IResult<&str, Expr, VerboseError<&str>> {
+    let (i, (var, ty, expr1)) = parse_let_head(i)?;
+    drop(nest);
     let (i, expr2) = parse_expr(i)?;
 
     Ok((
         i,
         Expr::Let(LetExpr {
-            var: var.to_string(),
+            var,
             ty,
             expr1: Box::new(expr1),
             expr2: Box::new(expr2),
@@ -175,26 +435,39 @@ fn parse_let(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
     ))
 }
 
+/// let式の`x : T = e;`までをパース
+fn parse_let_head(i: &str) -> IResult<&str, (String, TypeExpr, Expr), VerboseError<&str>> {
+    let (i, _) = multispace1(i)?;
+    let (i, var) = alpha1(i)?;
+
+    let (i, _) = multispace0(i)?;
+    let (i, _) = char(':')(i)?;
+    let (i, _) = multispace0(i)?;
+
+    let (i, ty) = parse_type(i)?; // 引数の型
+
+    let (i, _) = multispace1(i)?;
+    let (i, _) = char('=')(i)?;
+    let (i, _) = multispace1(i)?;
+
+    let (i, expr1) = recover(';', terminated(parse_expr, multispace0))(i)?;
+    let (i, _) = multispace0(i)?;
+
+    Ok((i, (var.to_string(), ty, expr1)))
+}
+
 fn parse_if(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
     let (i, _) = multispace1(i)?;
     let (i, cond_expr) = parse_expr(i)?;
     let (i, _) = multispace0(i)?;
 
-    let (i, then_expr) = delimited(
-        char('{'),
-        delimited(multispace0, parse_expr, multispace0),
-        char('}'),
-    )(i)?;
+    let (i, then_expr) = parse_block(i)?;
 
     let (i, _) = multispace0(i)?;
     let (i, _) = tag("else")(i)?;
     let (i, _) = multispace0(i)?;
 
-    let (i, else_expr) = delimited(
-        char('{'),
-        delimited(multispace0, parse_expr, multispace0),
-        char('}'),
-    )(i)?;
+    let (i, else_expr) = parse_block(i)?;
 
     Ok((
         i,
@@ -212,58 +485,142 @@ fn parse_split(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
     let (i, _) = multispace1(i)?;
     let (i, _) = tag("as")(i)?;
     let (i, _) = multispace1(i)?;
-    let (i, left) = alpha1(i)?;
-    let (i, _) = multispace0(i)?;
-    let (i, _) = char(',')(i)?;
-    let (i, _) = multispace0(i)?;
-    let (i, right) = alpha1(i)?;
+    let (i, (left, right)) = alt((
+        parse_pattern_pair,
+        delimited(
+            terminated(char('('), multispace0),
+            parse_pattern_pair,
+            preceded(multispace0, char(')')),
+        ),
+    ))(i)?;
     let (i, _) = multispace0(i)?;
 
-    let (i, body) = delimited(
-        char('{'),
-        delimited(multispace0, parse_expr, multispace0),
-        char('}'),
-    )(i)?;
+    let (i, body) = parse_block(i)?;
 
     Ok((
         i,
         Expr::Split(SplitExpr {
             expr: Box::new(expr),
-            left: left.to_string(),
-            right: right.to_string(),
+            left,
+            right,
             body: Box::new(body),
         }),
     ))
 }
 
-fn parse_free(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
+/// splitのパターンをパース。パターンは変数か、括弧で囲んだパターンの組
+fn parse_pattern(i: &str) -> IResult<&str, Pattern, VerboseError<&str>> {
+    let _nest = Nest::enter(i)?;
+    alt((
+        map(parse_var, Pattern::Var),
+        map(
+            delimited(
+                terminated(char('('), multispace0),
+                parse_pattern_pair,
+                preceded(multispace0, char(')')),
+            ),
+            |(p1, p2)| Pattern::Pair(Box::new(p1), Box::new(p2)),
+        ),
+    ))(i)
+}
+
+/// `p1, p2`のように、カンマで区切った2つのパターンをパース
+fn parse_pattern_pair(i: &str) -> IResult<&str, (Pattern, Pattern), VerboseError<&str>> {
+    let (i, left) = parse_pattern(i)?;
+    let (i, _) = delimited(multispace0, char(','), multispace0)(i)?;
+    let (i, right) = parse_pattern(i)?;
+    Ok((i, (left, right)))
+}
+
+/// free文と、続く式をパース
+///
+/// 続く式は入れ子ではないため、nestで増やした深さを戻してからパースする
+fn parse_free(i: &str, nest: Nest) -> IResult<&str, Expr, VerboseError<&str>> {
     let (i, _) = multispace1(i)?;
     let (i, var) = alpha1(i)?;
+    let (i, var) = parse_qualified(var, i)?;
     let (i, _) = multispace0(i)?;
     let (i, _) = char(';')(i)?;
+    drop(nest);
     let (i, expr) = parse_expr(i)?;
     Ok((
         i,
         Expr::Free(FreeExpr {
-            var: var.to_string(),
+            var,
             expr: Box::new(expr),
         }),
     ))
 }
 
+/// 関数適用を、閉じ括弧までパース
 fn parse_app(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
-    let (i, _) = multispace0(i)?;
-    let (i, expr1) = parse_expr(i)?;
-    let (i, _) = multispace1(i)?;
-    let (i, expr2) = parse_expr(i)?;
+    recover(')', |i| {
+        let (i, _) = multispace0(i)?;
+        let (i, expr1) = parse_expr(i)?;
+        let (i, _) = multispace1(i)?;
+        let (i, expr2) = parse_expr(i)?;
+        let (i, _) = multispace0(i)?;
 
-    Ok((
-        i,
-        Expr::App(AppExpr {
-            expr1: Box::new(expr1),
-            expr2: Box::new(expr2),
-        }),
-    ))
+        Ok((
+            i,
+            Expr::App(AppExpr {
+                expr1: Box::new(expr1),
+                expr2: Box::new(expr2),
+            }),
+        ))
+    })(i)
+}
+
+/// { <E> } というように、波括弧で囲まれた式をパース
+fn parse_block(i: &str) -> IResult<&str, Expr, VerboseError<&str>> {
+    let (i, _) = char('{')(i)?;
+    recover('}', delimited(multispace0, parse_expr, multispace0))(i)
+}
+
+/// 式をパースし、続く同期トークンsyncを消費する
+///
+/// 式のパースに失敗した場合や、式の直後がsyncでない場合は、エラーをExpr::Errorとして記録し、
+/// syncまで読み飛ばしてパースを続ける。
+/// これにより、1つのファイル中の複数のパースエラーを報告できる。
+/// syncが見つからない場合は回復できないため、エラーをそのまま返す。
+fn recover<'a, F>(
+    sync: char,
+    mut f: F,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Expr, VerboseError<&'a str>>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, Expr, VerboseError<&'a str>>,
+{
+    move |i| {
+        let result = f(i).and_then(|(i, expr)| {
+            let (i, _) = char(sync)(i)?;
+            Ok((i, expr))
+        });
+        match result {
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => match skip_to(i, sync) {
+                Some(rest) => Ok((rest, Expr::Error(ParseError::new(e)))),
+                None => Err(nom::Err::Error(e)),
+            },
+            result => result,
+        }
+    }
+}
+
+/// 括弧の対応を考慮しながら同期トークンsyncまで読み飛ばし、syncの直後からの入力を返す
+///
+/// syncより先に、外側の括弧を閉じる')'か'}'が現れた場合はNoneを返す。
+fn skip_to(i: &str, sync: char) -> Option<&str> {
+    let mut depth = 0usize;
+    for (pos, c) in i.char_indices() {
+        if depth == 0 && c == sync {
+            return Some(&i[pos + c.len_utf8()..]);
+        }
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth = depth.checked_sub(1)?,
+            _ => (),
+        }
+    }
+    None
 }
 
 /// 修飾子付き値をパース
@@ -297,12 +654,7 @@ fn parse_fn(i: &str) -> IResult<&str, ValExpr, VerboseError<&str>> {
     let (i, ty) = parse_type(i)?; // 引数の型
     let (i, _) = multispace0(i)?;
 
-    // { <E> } というように、波括弧で囲まれた式をパース
-    let (i, expr) = delimited(
-        char('{'),
-        delimited(multispace0, parse_expr, multispace0),
-        char('}'),
-    )(i)?;
+    let (i, expr) = parse_block(i)?;
 
     Ok((
         i,
@@ -332,7 +684,19 @@ fn parse_var(i: &str) -> IResult<&str, String, VerboseError<&str>> {
     Ok((i, v.to_string()))
 }
 
+/// 変数名nameに続く`.x`をパースし、モジュールMの変数xを表すM.xという名前を返す
+///
+/// `.x`が続かない場合は、nameをそのまま返す
+fn parse_qualified<'a>(name: &str, i: &'a str) -> IResult<&'a str, String, VerboseError<&'a str>> {
+    let (i, field) = opt(preceded(char('.'), alpha1))(i)?;
+    match field {
+        Some(field) => Ok((i, format!("{name}.{field}"))),
+        None => Ok((i, name.to_string())),
+    }
+}
+
 fn parse_type(i: &str) -> IResult<&str, TypeExpr, VerboseError<&str>> {
+    let _nest = Nest::enter(i)?;
     let (i, q) = parse_qual(i)?; // 修飾子
     let (i, _) = multispace1(i)?;
     let (i, val) = alt((tag("bool"), tag("(")))(i)?;
@@ -383,3 +747,108 @@ fn parse_qual(i: &str) -> IResult<&str, Qual, VerboseError<&str>> {
         Ok((i, Qual::Un))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recover() {
+        // 定義の式のエラーは;で、ブロック内のエラーは}で回復する
+        let input = "let x : lin bool = lin tru;\nlet y : un bool = (un fals x);\nlin fn z : lin bool { iff z { z } else { z } }";
+        let (_, expr) = parse_program(input).unwrap();
+        let errors = expr.errors();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].message(input).contains("line 1"));
+        assert!(errors[1].message(input).contains("line 2"));
+        assert!(errors[2].message(input).contains("line 3"));
+
+        // 同期トークンが見つからない場合は回復できない
+        assert!(parse_program("let x : lin bool = lin tru").is_err());
+        assert!(parse_program("(lin fn x : lin bool { x } lin true").is_err());
+
+        // エラーがない場合
+        let (_, expr) = parse_program("(lin fn x : lin bool { x } lin true)").unwrap();
+        assert!(expr.errors().is_empty());
+    }
+
+    #[test]
+    fn test_nesting() {
+        let pair = |depth| {
+            format!(
+                "{}un true{}",
+                "un <".repeat(depth),
+                ", un true>".repeat(depth)
+            )
+        };
+        let ty = |depth| {
+            format!(
+                "{}un bool{}",
+                "un (".repeat(depth),
+                " * un bool)".repeat(depth)
+            )
+        };
+
+        // 上限以下の入れ子はパースできる
+        assert!(parse_program(&pair(MAX_NESTING - 1)).is_ok());
+        assert!(parse_type(&ty(MAX_NESTING - 1)).is_ok());
+
+        // 上限を大きく超える入れ子も、スタックを使い切らずにパースエラーとなる
+        let input = pair(10_000);
+        match parse_program(&input) {
+            Err(nom::Err::Failure(e)) => {
+                assert!(convert_error(input.as_str(), e).contains("式か型の入れ子が深すぎる"))
+            }
+            r => panic!("{r:?}"),
+        }
+        assert!(parse_type(&ty(10_000)).is_err());
+
+        // 関数適用の括弧は回復して、エラーをExpr::Errorとして記録する
+        let input = format!("{}x{}", "(x ".repeat(10_000), ")".repeat(10_000));
+        let (_, expr) = parse_program(&input).unwrap();
+        let errors = expr.errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .message(&input)
+            .contains("式か型の入れ子が深すぎる"));
+
+        // splitのパターンの入れ子も制限する
+        let input = format!(
+            "split p as {}a{} {{ a }}",
+            "(".repeat(200_000),
+            ", c)".repeat(200_000)
+        );
+        assert!(parse_program(&input).is_err());
+        let input = format!(
+            "split p as {}a{} {{ a }}",
+            "(".repeat(100),
+            ", c)".repeat(100)
+        );
+        assert!(parse_program(&input).is_ok());
+
+        // letやfreeに続く式は入れ子として数えない
+        let input = format!(
+            "{}free x; un true",
+            "let x : un bool = un true;\n".repeat(MAX_NESTING + 20)
+        );
+        assert!(parse_program(&input).is_ok());
+    }
+
+    #[test]
+    fn test_split_pattern() {
+        let pattern = |input: &str| match parse_program(input).unwrap().1 {
+            Expr::Split(e) => format!("{}, {}", e.left, e.right),
+            e => panic!("{e:?}"),
+        };
+
+        // 全体を括弧で囲んでも囲まなくてもよい
+        assert_eq!(pattern("split p as a, b { a }"), "a, b");
+        assert_eq!(pattern("split p as (a, b) { a }"), "a, b");
+        assert_eq!(pattern("split p as (a, (b, c)) { a }"), "a, (b, c)");
+        assert_eq!(pattern("split p as ( (a,b) , c ) { a }"), "(a, b), c");
+
+        // 要素が1つだけのパターンは書けない
+        assert!(parse_program("split p as (a) { a }").is_err());
+        assert!(parse_program("split p as a, (b) { a }").is_err());
+    }
+}
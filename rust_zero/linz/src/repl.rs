@@ -0,0 +1,294 @@
+use crate::{
+    fold, lint,
+    module::Modules,
+    parser::{self, Expr, TopLevel, TypeExpr},
+    typing::{self, Expected, TypeEnv},
+};
+use nom::error::convert_error;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = r#"式を入力すると型を表示します。
+let x : T = e; と入力すると、変数xを定義します。
+module M = export x; let x : T = e; end と入力すると、モジュールMを定義し、M.xとして参照できます。
+式の一部を _? とすると、その位置に期待される型と利用可能な変数を表示します。
+
+:type 式 : 式の型を表示 (変数は消費しない) (:t)
+:env     : 定義済みの変数を表示
+:reset   : 定義済みの変数をすべて削除
+:help    : このヘルプを表示 (:h)
+:quit    : 終了 (:q)"#;
+
+/// REPLのセッション
+///
+/// 入力をまたいで型環境と定義済みのモジュールを保持する。
+/// 入力の型付けは型環境のコピーに対して行い、成功した場合のみ反映するため、
+/// 型エラーとなった入力で変数が消費されることはない。
+pub struct Repl {
+    env: TypeEnv,     // 定義済みの変数の型環境
+    modules: Modules, // 定義済みのモジュール
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl {
+            env: TypeEnv::new(),
+            modules: Modules::new(),
+        }
+    }
+
+    /// REPLを実行
+    pub fn run(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("linz> ");
+            io::stdout().flush()?;
+
+            let Some(line) = lines.next() else {
+                println!();
+                return Ok(()); // EOF
+            };
+            let line = line?;
+            let line = line.trim();
+
+            match line {
+                "" => continue,
+                ":quit" | ":q" => return Ok(()),
+                _ => match self.eval(line) {
+                    Ok(msg) => println!("{msg}"),
+                    Err(msg) => eprintln!("{msg}"),
+                },
+            }
+        }
+    }
+
+    /// 1行の入力を処理し、表示する文字列を返す
+    fn eval(&mut self, line: &str) -> Result<String, String> {
+        let (cmd, arg) = match line.split_once(char::is_whitespace) {
+            Some((cmd, arg)) => (cmd, arg.trim()),
+            None => (line, ""),
+        };
+
+        match cmd {
+            ":type" | ":t" => self.do_type(arg),
+            ":env" => Ok(self.do_env()),
+            ":reset" => {
+                self.env = TypeEnv::new();
+                self.modules = Modules::new();
+                Ok("型環境を初期化しました".to_string())
+            }
+            ":help" | ":h" => Ok(HELP.to_string()),
+            _ if cmd.starts_with(':') => Err(format!("不明なコマンド : {cmd}")),
+            _ => self.do_input(line),
+        }
+    }
+
+    /// 変数定義、モジュール定義か式を型付けし、型環境に反映する
+    ///
+    /// リントの警告がある場合は、型の後に続けて返す
+    fn do_input(&mut self, line: &str) -> Result<String, String> {
+        let mut env = self.env.clone();
+        let mut modules = self.modules.clone();
+        let (msg, exprs) = match parse(line)? {
+            TopLevel::Def(var, ty, expr) => {
+                let expr = modules.resolve(expr)?;
+                (define(&mut env, var, ty, &expr)?, vec![expr])
+            }
+            TopLevel::Module(m) => {
+                let name = m.name.clone();
+                let exports = m.exports.clone();
+                let mut lines = vec![format!("module {name}")];
+                let mut privates = Vec::new();
+                let mut exprs = Vec::new();
+                for (var, ty, expr) in modules.define(m)? {
+                    let msg = define(&mut env, var.clone(), ty, &expr)?;
+                    if exports.iter().any(|x| var == format!("{name}.{x}")) {
+                        lines.push(msg);
+                    } else {
+                        privates.push(var);
+                    }
+                    exprs.push(expr);
+                }
+                // 公開しない定義は以降の入力から参照できないため、型環境から削除する
+                for var in privates {
+                    env.undefine(&var).map_err(|e| e.to_string())?;
+                }
+                (lines.join("\n"), exprs)
+            }
+            TopLevel::Expr(expr) => {
+                let expr = modules.resolve(expr)?;
+                let t = typing::typing(&expr, &mut env, 0).map_err(|e| e.to_string())?;
+                (t.to_string(), vec![expr])
+            }
+        };
+        self.env = env;
+        self.modules = modules;
+
+        let mut lines = vec![msg];
+        for expr in exprs.iter() {
+            lines.extend(
+                lint::lint(expr, &fold::fold(expr))
+                    .iter()
+                    .map(|w| w.to_string()),
+            );
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// :typeコマンド
+    /// 型環境のコピーに対して型付けを行い、変数を消費しない
+    fn do_type(&self, arg: &str) -> Result<String, String> {
+        let TopLevel::Expr(expr) = parse(arg)? else {
+            return Err("usage: :type 式".to_string());
+        };
+        let expr = self.modules.clone().resolve(expr)?;
+        let mut env = self.env.clone();
+        let t = typing::typing(&expr, &mut env, 0).map_err(|e| e.to_string())?;
+        Ok(t.to_string())
+    }
+
+    /// :envコマンド
+    fn do_env(&self) -> String {
+        let bindings = self.env.bindings();
+        if bindings.is_empty() {
+            return "定義済みの変数はありません".to_string();
+        }
+
+        let mut lines = Vec::new();
+        for (var, qual, ty) in bindings {
+            match ty {
+                Some(ty) => lines.push(format!("{var} : {ty}")),
+                None => {
+                    let q = if qual == parser::Qual::Lin {
+                        "lin"
+                    } else {
+                        "un"
+                    };
+                    lines.push(format!("{var} : {q} (消費済み)"));
+                }
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// 変数varを型tyとしてexprの型で型環境envに定義し、表示する文字列を返す
+fn define(env: &mut TypeEnv, var: String, ty: TypeExpr, expr: &Expr) -> Result<String, String> {
+    env.check_type(&ty).map_err(|e| e.to_string())?;
+    let t =
+        typing::typing_expected(expr, env, 0, Expected::Type(&ty)).map_err(|e| e.to_string())?;
+    if ty != t {
+        return Err("変数の型が一致しない".to_string());
+    }
+    let msg = format!("{var} : {ty}");
+    env.define(var, ty).map_err(|e| e.to_string())?;
+    Ok(msg)
+}
+
+/// 入力をパースし、エラーの場合はエラーメッセージを返す
+///
+/// パースエラーから回復した箇所がある場合は、すべてのエラーをまとめて返す
+fn parse(line: &str) -> Result<TopLevel, String> {
+    match parser::parse_toplevel(line) {
+        Ok((_, top)) => {
+            let errors = top.errors();
+            if errors.is_empty() {
+                Ok(top)
+            } else {
+                let msgs: Vec<String> = errors.iter().map(|e| e.message(line)).collect();
+                Err(format!("パースエラー:\n{}", msgs.join("\n")))
+            }
+        }
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            Err(format!("パースエラー:\n{}", convert_error(line, e)))
+        }
+        Err(nom::Err::Incomplete(_)) => Err("パースエラー: 入力が不完全".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repl() {
+        let mut repl = Repl::new();
+        assert_eq!(
+            repl.eval("let x : lin bool = lin true;"),
+            Ok("x : lin bool".to_string())
+        );
+        assert_eq!(
+            repl.eval("let y : un bool = un false;"),
+            Ok("y : un bool".to_string())
+        );
+
+        // :typeでは変数を消費しない
+        assert_eq!(repl.eval(":type x"), Ok("lin bool".to_string()));
+        assert_eq!(
+            repl.eval(":env"),
+            Ok("x : lin bool\ny : un bool".to_string())
+        );
+
+        // 未消費のlin型の変数は再定義できない
+        assert!(repl.eval("let x : lin bool = lin false;").is_err());
+
+        // 式を入力すると変数が消費される
+        assert_eq!(
+            repl.eval("lin <x, y>"),
+            Ok("lin (lin bool * un bool)".to_string())
+        );
+        assert_eq!(
+            repl.eval(":env"),
+            Ok("x : lin (消費済み)\ny : un bool".to_string())
+        );
+        assert!(repl.eval("x").is_err());
+
+        // 型エラーとなった入力では変数は消費されない
+        assert!(repl.eval("let z : lin bool = lin true;").is_ok());
+        assert!(repl.eval("un <z, y>").is_err());
+        assert_eq!(repl.eval(":type z"), Ok("lin bool".to_string()));
+
+        assert!(repl.eval(":reset").is_ok());
+        assert_eq!(
+            repl.eval(":env"),
+            Ok("定義済みの変数はありません".to_string())
+        );
+        assert!(repl.eval(":type z").is_err());
+
+        // 警告は型の後に表示する
+        assert_eq!(
+            repl.eval("if un true { un true } else { un false }"),
+            Ok(
+                "un bool\n警告[unreachable_branch]: 条件がtrueのため、ifのelse節は実行されない"
+                    .to_string()
+            )
+        );
+
+        // 穴には期待される型と利用可能な変数を表示する
+        assert!(repl.eval("let v : lin bool = lin true;").is_ok());
+        assert_eq!(
+            repl.eval("let u : un bool = _?;"),
+            Err("穴_?に期待される型 : un bool\n消費可能なlin型の変数 : v : lin bool\n利用可能なun型の変数 : なし".to_string())
+        );
+
+        // パースエラーとなった定義は反映されない
+        assert!(repl.eval("let w : lin bool = lin tru;").is_err());
+        assert!(repl.eval(":type w").is_err());
+
+        // モジュールは公開した定義のみ参照できる
+        assert_eq!(
+            repl.eval("module B = export t; let f : un bool = un false; let t : un bool = f; end"),
+            Ok("module B\nB.t : un bool".to_string())
+        );
+        assert_eq!(repl.eval(":type B.t"), Ok("un bool".to_string()));
+        assert!(repl.eval(":type B.f").is_err());
+
+        // 消費されない公開しないlin型の定義はエラー
+        assert!(repl
+            .eval(
+                "module C = export t; let x : lin bool = lin true; let t : un bool = un true; end"
+            )
+            .is_err());
+        assert!(repl.eval(":type C.t").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! 例題を順に実行して線形型を紹介するツアー
+//!
+//! `linz tour`で起動し、codes/tour以下の例題ごとに、説明、ソース、型付けの結果、評価結果を表示する。
+//! 型エラーとなることを示す例題もあり、その場合は評価を行わない。
+//! 標準入力が端末の場合は、例題ごとにEnterの入力を待つ。
+
+use crate::{eval, fold, module::Modules, parser, typing};
+use nom::error::convert_error;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// ツアーの例題
+struct Example {
+    title: &'static str,       // 題名
+    description: &'static str, // 例題の説明
+    source: &'static str,      // linzのソース
+}
+
+const EXAMPLES: &[Example] = &[
+    Example {
+        title: "線形な値の交換",
+        description:
+            "splitでペアを分解すると、lin型のペアは消費され、要素aとbを一度ずつ使う必要がある。
+要素を入れ替えたペアを返すことで、どちらも一度だけ使っている。",
+        source: include_str!("../codes/tour/1_swap.lin"),
+    },
+    Example {
+        title: "リソースのハンドル",
+        description: "openが返すlin型のペアの第1要素をハンドルとみなす。
+ハンドルは必ず一度だけ解放する必要があり、closeではsplitで取り出してfreeで解放している。
+freeを忘れたり二度解放したりすると型エラーになる。",
+        source: include_str!("../codes/tour/2_handle.lin"),
+    },
+    Example {
+        title: "unとlinのクロージャ",
+        description: "un型の値のみをキャプチャする関数twiceはun型にでき、何度でも呼び出せる。
+lin型の変数xをキャプチャする関数onceはlin型となり、一度しか呼び出せない。",
+        source: include_str!("../codes/tour/3_closure.lin"),
+    },
+    Example {
+        title: "linのクロージャを二度呼び出す",
+        description:
+            "前の例のonceを二度呼び出すと、二度目の呼び出しではonceが消費済みのため型エラーとなる。
+これにより、キャプチャしたxが二度使われることを防いでいる。",
+        source: include_str!("../codes/tour/4_closure_err.lin"),
+    },
+];
+
+/// 例題を実行した結果
+struct Outcome {
+    typed: Result<String, String>,         // 型、またはエラーメッセージ
+    value: Option<Result<String, String>>, // 評価結果。型付けに失敗した場合はNone
+}
+
+/// 例題のソースsourceをパース、名前解決、型付けし、型付けに成功した場合は定数畳み込みしてから評価する
+fn run_example(source: &str) -> Outcome {
+    let fail = |msg: String| Outcome {
+        typed: Err(msg),
+        value: None,
+    };
+
+    let expr = match parser::parse_program(source) {
+        Ok((_, expr)) => expr,
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            return fail(format!("パースエラー:\n{}", convert_error(source, e)));
+        }
+        Err(e) => return fail(format!("パースエラー:\n{e}")),
+    };
+    if let Some(e) = expr.errors().first() {
+        return fail(format!("パースエラー:\n{}", e.message(source)));
+    }
+    let expr = match Modules::new().resolve(expr) {
+        Ok(expr) => expr,
+        Err(e) => return fail(format!("名前解決エラー:\n{e}")),
+    };
+    match typing::typing(&expr, &mut typing::TypeEnv::new(), 0) {
+        Ok(t) => Outcome {
+            typed: Ok(t.to_string()),
+            value: Some(eval::eval(&fold::fold(&expr).expr).map(|v| v.to_string())),
+        },
+        Err(e) => fail(format!("型エラー:\n{e}")),
+    }
+}
+
+/// ツアーを実行する。onlyを指定した場合は、その番号(1から始まる)の例題のみを表示する
+pub fn run(only: Option<usize>) -> Result<(), String> {
+    let range = match only {
+        Some(n) if (1..=EXAMPLES.len()).contains(&n) => n - 1..n,
+        Some(n) => return Err(format!("例題{n}はありません (1から{})", EXAMPLES.len())),
+        None => 0..EXAMPLES.len(),
+    };
+
+    let interactive = io::stdin().is_terminal();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    for i in range.clone() {
+        let ex = &EXAMPLES[i];
+        println!("=== [{}/{}] {} ===", i + 1, EXAMPLES.len(), ex.title);
+        println!("{}\n", ex.description);
+        println!("ソース:\n{}", ex.source.trim_end());
+
+        let outcome = run_example(ex.source);
+        match &outcome.typed {
+            Ok(t) => println!("\n型:\n{t}"),
+            Err(e) => println!("\n{e}"),
+        }
+        match &outcome.value {
+            Some(Ok(v)) => println!("\n評価結果:\n{v}"),
+            Some(Err(e)) => println!("\n評価エラー:\n{e}"),
+            None => (),
+        }
+        println!();
+
+        // 最後の例題の後は待たない
+        if interactive && i + 1 < range.end {
+            print!("Enterで次の例題へ (qで終了) ");
+            io::stdout().flush().map_err(|e| e.to_string())?;
+            match lines.next() {
+                Some(Ok(line)) if line.trim() != "q" => (),
+                _ => break,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_examples() {
+        let results: Vec<Outcome> = EXAMPLES.iter().map(|ex| run_example(ex.source)).collect();
+
+        assert_eq!(results[0].typed, Ok("lin (un bool * lin bool)".to_string()));
+        assert_eq!(
+            results[0].value,
+            Some(Ok("lin <un false, lin true>".to_string()))
+        );
+        assert_eq!(results[1].typed, Ok("un bool".to_string()));
+        assert_eq!(results[1].value, Some(Ok("un true".to_string())));
+        assert_eq!(
+            results[2].value,
+            Some(Ok(
+                "lin <lin <lin true, un false>, lin <un <un true, un true>, un <un true, un false>>>"
+                    .to_string()
+            ))
+        );
+
+        // 型エラーを示す例題は評価しない
+        assert!(results[3]
+            .typed
+            .as_ref()
+            .is_err_and(|e| e.starts_with("型エラー")));
+        assert_eq!(results[3].value, None);
+    }
+}
@@ -1,8 +1,8 @@
 use crate::{
-    helper::safe_add,
-    parser::{self, PrimType, TypeExpr},
+    helper::{bounded_add, safe_add},
+    parser::{self, Pattern, PrimType, Qual, TypeExpr},
 };
-use std::{borrow::Cow, cmp::Ordering, collections::BTreeMap, mem};
+use std::{borrow::Cow, cmp::Ordering, collections::BTreeMap, fmt, mem};
 
 /// 変数名から型へのマップ
 /// Optionにしているのはlin型の変数を消費したことを表現するため
@@ -56,14 +56,53 @@ impl TypeEnvStack {
         }
         None
     }
+
+    /// get_mutと同様に、最初に発見したデータとその深さを取得する
+    fn get(&self, key: &str) -> Option<(usize, &Option<parser::TypeExpr>)> {
+        self.vars
+            .iter()
+            .rev()
+            .find_map(|(depth, elm)| Some((*depth, elm.get(key)?)))
+    }
+}
+
+/// 変数を束縛した構文。--no-shadowのエラーで、隠される束縛を示すために使う
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Binder {
+    Let,   // let式
+    Fn,    // 関数の引数
+    Split, // splitで分解した要素
+}
+
+/// 型付けで扱う型の大きさの上限
+///
+/// 生成されたプログラムなどでペア型や関数型が病的に入れ子になると、
+/// 型の比較や表示、解放の再帰でスタックを使い切ってしまう。
+/// 宣言された型や、型付けで組み立てた型が上限を超えた時点で型エラーとする
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeLimits {
+    pub max_depth: usize, // 型の入れ子の深さ。boolの深さは1
+    pub max_size: usize,  // 型に含まれるboolと、ペア型・関数型の型構成子の数の合計
+}
+
+impl Default for TypeLimits {
+    fn default() -> Self {
+        TypeLimits {
+            max_depth: 256,
+            max_size: 65536,
+        }
+    }
 }
 
 /// 実際の型環境
 /// lin用とun用で別々のTypeEnvStackを用意する
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TypeEnv {
-    env_lin: TypeEnvStack, // lin用
-    env_un: TypeEnvStack,  // un用
+    env_lin: TypeEnvStack,                              // lin用
+    env_un: TypeEnvStack,                               // un用
+    no_shadow: bool, // 真なら、消費されていないlin型の変数と同名の変数の束縛をエラーとする
+    binders: BTreeMap<usize, BTreeMap<String, Binder>>, // 深さごとの、変数を束縛した構文
+    limits: TypeLimits, // 型の大きさの上限
 }
 
 impl TypeEnv {
@@ -71,14 +110,58 @@ impl TypeEnv {
         TypeEnv {
             env_lin: TypeEnvStack::new(),
             env_un: TypeEnvStack::new(),
+            no_shadow: false,
+            binders: BTreeMap::new(),
+            limits: TypeLimits::default(),
         }
     }
 
+    /// 型の入れ子の深さと大きさの上限(--max-type-depth、--max-type-size)を設定する
+    pub fn set_limits(&mut self, limits: TypeLimits) {
+        self.limits = limits;
+    }
+
+    /// 型tyの入れ子の深さと大きさが上限以下か検査する
+    ///
+    /// 上限を超える型をたどる再帰でスタックを使い切らないよう、明示的なスタックを用いて、
+    /// 上限を超えた時点で打ち切る
+    pub fn check_type<'a>(&self, ty: &TypeExpr) -> Result<(), Cow<'a, str>> {
+        let TypeLimits {
+            max_depth,
+            max_size,
+        } = self.limits;
+        let mut size = 0;
+        let mut stack = vec![(ty, 0)];
+        while let Some((t, mut depth)) = stack.pop() {
+            bounded_add(&mut depth, &1, &max_depth, || {
+                format!("型の入れ子の深さが上限{max_depth}を超えている")
+            })?;
+            bounded_add(&mut size, &1, &max_size, || {
+                format!("型の大きさが上限{max_size}を超えている")
+            })?;
+            if let PrimType::Pair(t1, t2) | PrimType::Arrow(t1, t2) = &t.prim {
+                stack.push((t2, depth));
+                stack.push((t1, depth));
+            }
+        }
+        Ok(())
+    }
+
+    /// 消費されていないlin型の変数のシャドーイングを禁止するか(--no-shadow)を設定する
+    ///
+    /// シャドーイングされたlin型の変数には到達できなくなり、
+    /// 消費していないというエラーがスコープの終わりで報告されるため、原因が分かりにくい。
+    /// 禁止した場合は、束縛し直した時点でエラーとする
+    pub fn set_no_shadow(&mut self, no_shadow: bool) {
+        self.no_shadow = no_shadow;
+    }
+
     /// 型環境をpush
     /// 両方の型環境にpushする
     fn push(&mut self, depth: usize) {
         self.env_lin.push(depth);
         self.env_un.push(depth);
+        self.binders.insert(depth, BTreeMap::new());
     }
 
     /// 型環境をpop
@@ -86,9 +169,41 @@ impl TypeEnv {
     fn pop(&mut self, depth: usize) -> (Option<VarToType>, Option<VarToType>) {
         let t1 = self.env_lin.pop(depth);
         let t2 = self.env_un.pop(depth);
+        self.binders.remove(&depth);
         (t1, t2)
     }
 
+    /// 構文binderで束縛した変数を型環境へ追加
+    ///
+    /// no_shadowが真で、参照可能な消費されていないlin型の変数と同名の場合はエラーとし、
+    /// 隠される束縛を報告する
+    fn bind<'a>(
+        &mut self,
+        key: &str,
+        value: &parser::TypeExpr,
+        binder: Binder,
+    ) -> Result<(), Cow<'a, str>> {
+        if self.no_shadow {
+            if let Some((depth, Some(t))) = self.env_lin.get(key) {
+                let site = match self.binders.get(&depth).and_then(|b| b.get(key)) {
+                    Some(Binder::Let) => format!("let {key} : {t}"),
+                    Some(Binder::Fn) => format!("関数の引数 {key} : {t}"),
+                    Some(Binder::Split) => format!("splitの要素 {key} : {t}"),
+                    None => format!("{key} : {t}"),
+                };
+                return Err(format!(
+                    "--no-shadow: 変数\"{key}\"を束縛し直すと、消費されていないlin型の変数({site})に到達できなくなる"
+                )
+                .into());
+            }
+        }
+        if let Some(last) = self.binders.values_mut().next_back() {
+            last.insert(key.to_string(), binder);
+        }
+        self.insert(key.to_string(), value.clone());
+        Ok(())
+    }
+
     /// 型環境へ変数と型を追加
     /// スタックの最も上にあるマップに対して追加するが、
     /// linかunかを判別して適切な型環境に追加する
@@ -100,6 +215,57 @@ impl TypeEnv {
         }
     }
 
+    /// トップレベル(depth = 0)に変数を定義
+    ///
+    /// REPLで入力をまたいで変数を保持するために利用する。
+    /// 同名の変数がすでに定義されている場合は置き換えるが、
+    /// 消費されていないlin型の変数は置き換えられない。
+    pub fn define<'a>(&mut self, key: String, value: TypeExpr) -> Result<(), Cow<'a, str>> {
+        self.undefine(&key)?;
+        self.insert(key, value);
+        Ok(())
+    }
+
+    /// トップレベル(depth = 0)の変数を削除
+    ///
+    /// 消費されていないlin型の変数は削除できない。
+    /// REPLで定義したモジュールの、公開しない変数を削除するために利用する。
+    pub fn undefine<'a>(&mut self, key: &str) -> Result<(), Cow<'a, str>> {
+        let lin = self.env_lin.vars.entry(0).or_default();
+        if let Some(Some(_)) = lin.get(key) {
+            return Err(format!("lin型の変数\"{key}\"を消費していない").into());
+        }
+        lin.remove(key);
+        self.env_un.vars.entry(0).or_default().remove(key);
+        Ok(())
+    }
+
+    /// 型環境に登録されている変数の一覧を、(変数名, 修飾子, 型)として返す
+    /// 型がNoneの変数は消費済み
+    /// 同じ変数名が複数のスコープにある場合は、最も内側のものだけを返す
+    pub fn bindings(&self) -> Vec<(&str, Qual, Option<&TypeExpr>)> {
+        let mut result = BTreeMap::new();
+        for (qual, stack) in [(Qual::Lin, &self.env_lin), (Qual::Un, &self.env_un)] {
+            for (depth, vars) in stack.vars.iter() {
+                for (k, v) in vars.iter() {
+                    // より深いスコープの変数を優先
+                    let is_inner = match result.get(k.as_str()) {
+                        Some((d, _, _)) => *d < *depth,
+                        None => true,
+                    };
+                    if is_inner {
+                        result.insert(k.as_str(), (*depth, qual, v.as_ref()));
+                    }
+                }
+            }
+        }
+
+        result
+            .into_iter()
+            .map(|(k, (_, qual, v))| (k, qual, v))
+            .collect()
+    }
+
     /// linとunの型環境からget_mutを呼び出し、depthが大きい方を返す
     fn get_mut(&mut self, key: &str) -> Option<&mut Option<parser::TypeExpr>> {
         match (self.env_lin.get_mut(key), self.env_un.get_mut(key)) {
@@ -138,11 +304,77 @@ pub fn typing<'a>(expr: &parser::Expr, env: &mut TypeEnv, depth: usize) -> TResu
         parser::Expr::Split(e) => typing_split(e, env, depth),
         parser::Expr::Var(e) => typing_var(e, env),
         parser::Expr::Let(e) => typing_let(e, env, depth),
+        parser::Expr::Hole => Err(hole_report(env, &Expected::Unknown).into()),
+        parser::Expr::Error(_) => Err("パースエラーのため型付けできない".into()),
+        parser::Expr::Module(..) => Err("モジュールの名前解決を行っていない".into()),
+    }
+}
+
+/// 穴(_?)に期待される型
+pub enum Expected<'b> {
+    Unknown,            // 文脈から型が定まらない
+    Type(&'b TypeExpr), // 型が定まっている
+    Bool,               // 修飾子を問わないbool型。ifの条件式
+}
+
+impl fmt::Display for Expected<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Unknown => write!(f, "不明"),
+            Expected::Type(t) => write!(f, "{t}"),
+            Expected::Bool => write!(f, "lin boolかun bool"),
+        }
     }
 }
+
+/// 型がexpectedであるべき位置にある式を型付け
+///
+/// 式が穴の場合は、期待される型と、その位置で利用可能な変数を報告するエラーとなる。
+/// 穴でない場合はtypingと同じで、型がexpectedと一致するかは呼び出し側で検査する
+pub fn typing_expected<'a>(
+    expr: &parser::Expr,
+    env: &mut TypeEnv,
+    depth: usize,
+    expected: Expected,
+) -> TResult<'a> {
+    match expr {
+        parser::Expr::Hole => Err(hole_report(env, &expected).into()),
+        _ => typing(expr, env, depth),
+    }
+}
+
+/// 穴に期待される型と、型環境envで消費可能なlin型の変数と利用可能なun型の変数を報告する
+fn hole_report(env: &TypeEnv, expected: &Expected) -> String {
+    let mut lin = Vec::new();
+    let mut un = Vec::new();
+    for (var, qual, ty) in env.bindings() {
+        let Some(ty) = ty else {
+            continue; // 消費済み
+        };
+        let v = if qual == Qual::Lin { &mut lin } else { &mut un };
+        v.push(format!("{var} : {ty}"));
+    }
+
+    let list = |v: Vec<String>| {
+        if v.is_empty() {
+            "なし".to_string()
+        } else {
+            v.join(", ")
+        }
+    };
+    format!(
+        "穴_?に期待される型 : {expected}\n消費可能なlin型の変数 : {}\n利用可能なun型の変数 : {}",
+        list(lin),
+        list(un)
+    )
+}
 fn typing_app<'a>(expr: &parser::AppExpr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
     let func_t = typing(&expr.expr1, env, depth)?;
-    let param_t = typing(&expr.expr2, env, depth)?;
+    let expected = match &func_t.prim {
+        PrimType::Arrow(e1, _) => Expected::Type(e1),
+        _ => Expected::Unknown,
+    };
+    let param_t = typing_expected(&expr.expr2, env, depth, expected)?;
 
     match func_t.prim {
         PrimType::Arrow(e1, e2) => {
@@ -176,18 +408,20 @@ fn typing_free<'a>(expr: &parser::FreeExpr, env: &mut TypeEnv, depth: usize) ->
 }
 
 fn typing_split<'a>(expr: &parser::SplitExpr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
-    if &expr.left == &expr.right {
+    let vars = expr.vars();
+    if vars.iter().enumerate().any(|(i, v)| vars[..i].contains(v)) {
         return Err("同じ変数名は使用できません。".into());
     }
 
+    // splitの型は、ペアの要素を束縛した本体の式の型となる
     let param_type = typing(&expr.expr, env, depth)?;
-    let (q, p) = match param_type.prim.clone() {
+    match param_type.prim {
         PrimType::Pair(t1, t2) => {
             let mut depth = depth;
             safe_add(&mut depth, &1, || "変数スコープのネストが深すぎる")?;
             env.push(depth);
-            env.insert(expr.left.clone(), *t1);
-            env.insert(expr.right.clone(), *t2);
+            bind_pattern(&expr.left, &t1, env)?;
+            bind_pattern(&expr.right, &t2, env)?;
 
             // 関数中の式を型付け
             let t = typing(&expr.body, env, depth)?;
@@ -199,29 +433,42 @@ fn typing_split<'a>(expr: &parser::SplitExpr, env: &mut TypeEnv, depth: usize) -
                 }
             }
 
-            (
-                t.qual,
-                parser::PrimType::Arrow(Box::new(param_type), Box::new(t)),
-            )
+            Ok(t)
         }
-        _ => {
-            return Err("splitでペア型以外を使用している".into());
-        }
-    };
+        _ => Err("splitでペア型以外を使用している".into()),
+    }
+}
 
-    Ok(parser::TypeExpr { qual: q, prim: p })
+/// splitのパターンpatで型tyの値を分解し、束縛する変数を型環境へ追加する
+///
+/// 入れ子のパターンで分解するペアは、分解した時点で消費したものとみなす。
+/// ペア型でない値をペアのパターンで分解しようとした場合はエラー
+fn bind_pattern<'a>(pat: &Pattern, ty: &TypeExpr, env: &mut TypeEnv) -> Result<(), Cow<'a, str>> {
+    match (pat, &ty.prim) {
+        (Pattern::Var(var), _) => env.bind(var, ty, Binder::Split),
+        (Pattern::Pair(p1, p2), PrimType::Pair(t1, t2)) => {
+            bind_pattern(p1, t1, env)?;
+            bind_pattern(p2, t2, env)
+        }
+        _ => Err(format!("splitのパターン{pat}で、ペア型でない{ty}型の値を分解している").into()),
+    }
 }
 
 fn typing_let<'a>(expr: &parser::LetExpr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
-    let t1 = typing(&expr.expr1, env, depth)?;
-    if expr.ty != t1 {
-        return Err("変数の型が一致しない".into());
+    env.check_type(&expr.ty)?;
+
+    // 束縛する式がパースエラーの場合も、宣言された型を用いて後続の式の型付けを続ける
+    if !matches!(*expr.expr1, parser::Expr::Error(_)) {
+        let t1 = typing_expected(&expr.expr1, env, depth, Expected::Type(&expr.ty))?;
+        if expr.ty != t1 {
+            return Err("変数の型が一致しない".into());
+        }
     }
 
     let mut depth = depth;
     safe_add(&mut depth, &1, || "変数スコープのネストが深すぎる")?;
     env.push(depth);
-    env.insert(expr.var.clone(), expr.ty.clone());
+    env.bind(&expr.var, &expr.ty, Binder::Let)?;
 
     let t2 = typing(&expr.expr2, env, depth)?;
 
@@ -272,10 +519,11 @@ fn typing_qval<'a>(expr: &parser::QValExpr, env: &mut TypeEnv, depth: usize) ->
 
             // 型環境のスタックをインクリメントする
             // スタックのプッシュにはdepthが必要なため、インクリメントを忘れずに行う
+            env.check_type(&e.ty)?;
             let mut depth = depth;
             safe_add(&mut depth, &1, || "変数スコープのネストが深すぎる")?;
             env.push(depth);
-            env.insert(e.var.clone(), e.ty.clone()); // 変数の型を挿入
+            env.bind(&e.var, &e.ty, Binder::Fn)?; // 変数の型を挿入
 
             // 関数中の式を型付け
             let t = typing(&e.expr, env, depth)?;
@@ -302,10 +550,15 @@ fn typing_qval<'a>(expr: &parser::QValExpr, env: &mut TypeEnv, depth: usize) ->
     };
 
     // 修飾子付き型を返す
-    Ok(parser::TypeExpr {
+    // ペア型と関数型は要素の型より大きくなるため、上限を超えていないか検査する
+    let t = parser::TypeExpr {
         qual: expr.qual,
         prim: p,
-    })
+    };
+    if !matches!(t.prim, PrimType::Bool) {
+        env.check_type(&t)?;
+    }
+    Ok(t)
 }
 
 /// 変数の型付け
@@ -332,15 +585,21 @@ fn typing_var<'a>(expr: &str, env: &mut TypeEnv) -> TResult<'a> {
 /// if式の型付け
 fn typing_if<'a>(expr: &parser::IfExpr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
     // 条件の式の型つけを行い、その型がboolであるかを検査
-    let t1 = typing(&expr.cond_expr, env, depth)?;
+    let t1 = typing_expected(&expr.cond_expr, env, depth, Expected::Bool)?;
     if t1.prim != parser::PrimType::Bool {
         return Err("ifの条件式がboolでない".into());
     }
 
     // thenとelseで別々の式を同じ型環境で検査するため、型環境をcloneしてから、それぞれの式の型付けを行う
+    // 一方の節が穴の場合は、もう一方の節の型を期待される型とする
     let mut e = env.clone();
-    let t2 = typing(&expr.then_expr, &mut e, depth)?;
-    let t3 = typing(&expr.else_expr, &mut e, depth)?;
+    let t2 = if let parser::Expr::Hole = *expr.then_expr {
+        let t3 = typing(&expr.else_expr, &mut e.clone(), depth)?;
+        typing_expected(&expr.then_expr, &mut e, depth, Expected::Type(&t3))?
+    } else {
+        typing(&expr.then_expr, &mut e, depth)?
+    };
+    let t3 = typing_expected(&expr.else_expr, &mut e, depth, Expected::Type(&t2))?;
 
     // thenとelse部の型は同じで、
     // thenとelse部の評価後の型環境は同じかチェック
@@ -349,3 +608,176 @@ fn typing_if<'a>(expr: &parser::IfExpr, env: &mut TypeEnv, depth: usize) -> TRes
     }
     Ok(t2)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    fn typing_str(input: &str) -> Result<String, String> {
+        let (_, expr) = parse_program(input).unwrap();
+        typing(&expr, &mut TypeEnv::new(), 0)
+            .map(|t| t.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_hole() {
+        // letで束縛する式の穴は、宣言された型が期待される
+        assert_eq!(
+            typing_str("lin fn x : lin bool { let y : un bool = _?; free x; y }"),
+            Err("穴_?に期待される型 : un bool\n消費可能なlin型の変数 : x : lin bool\n利用可能なun型の変数 : なし".to_string())
+        );
+
+        // 関数適用の引数の穴は、関数の引数型が期待される。消費済みのlin型の変数は含まない
+        assert_eq!(
+            typing_str(
+                "lin fn x : lin bool { lin fn f : lin (lin bool -> un bool) { (f _?) } }"
+            ),
+            Err("穴_?に期待される型 : lin bool\n消費可能なlin型の変数 : x : lin bool\n利用可能なun型の変数 : なし".to_string())
+        );
+
+        // ifの条件式の穴は、修飾子を問わないbool型が期待される
+        let err = typing_str("if _? { un true } else { un false }").unwrap_err();
+        assert!(
+            err.starts_with("穴_?に期待される型 : lin boolかun bool\n"),
+            "{err}"
+        );
+
+        // ifの節の穴は、もう一方の節の型が期待される
+        let err = typing_str("if un true { _? } else { lin false }").unwrap_err();
+        assert!(err.starts_with("穴_?に期待される型 : lin bool\n"), "{err}");
+
+        // それ以外の位置では、期待される型は不明
+        let err = typing_str("un fn x : un bool { _? }").unwrap_err();
+        assert_eq!(
+            err,
+            "穴_?に期待される型 : 不明\n消費可能なlin型の変数 : なし\n利用可能なun型の変数 : x : un bool"
+        );
+    }
+
+    #[test]
+    fn test_split_pattern() {
+        // 入れ子のパターンの要素は、それぞれの型で束縛される
+        assert_eq!(
+            typing_str(
+                "split lin <un true, lin <lin false, un true>> as (a, (b, c)) { lin <b, un <a, c>> }"
+            ),
+            Ok("lin (lin bool * un (un bool * un bool))".to_string())
+        );
+
+        // 内側のパターンで束縛したlin型の変数も、消費しなければエラー
+        assert_eq!(
+            typing_str("split lin <un true, lin <lin false, un true>> as a, (b, c) { a }"),
+            Err("関数定義内でlin型の変数\"b\"を消費していない".to_string())
+        );
+
+        // パターンの形が型と合わない場合や、変数名が重複する場合はエラー
+        assert_eq!(
+            typing_str("split lin <un true, lin false> as a, (b, c) { a }"),
+            Err("splitのパターン(b, c)で、ペア型でないlin bool型の値を分解している".to_string())
+        );
+        assert_eq!(
+            typing_str("split un <un true, un <un false, un true>> as a, (b, a) { a }"),
+            Err("同じ変数名は使用できません。".to_string())
+        );
+    }
+
+    #[test]
+    fn test_no_shadow() {
+        let typing_no_shadow = |input: &str| -> Result<String, String> {
+            let (_, expr) = parse_program(input).unwrap();
+            let mut env = TypeEnv::new();
+            env.set_no_shadow(true);
+            typing(&expr, &mut env, 0)
+                .map(|t| t.to_string())
+                .map_err(|e| e.to_string())
+        };
+
+        // 通常は、隠されたxを消費していないことがスコープの終わりで報告される
+        let input = "let x : lin bool = lin true; let x : lin bool = lin false; x";
+        assert_eq!(
+            typing_str(input),
+            Err("関数定義内でlin型の変数\"x\"を消費していない".to_string())
+        );
+        assert_eq!(
+            typing_no_shadow(input),
+            Err("--no-shadow: 変数\"x\"を束縛し直すと、消費されていないlin型の変数(let x : lin bool)に到達できなくなる".to_string())
+        );
+        assert_eq!(
+            typing_no_shadow(
+                "lin fn x : lin bool { split lin <lin true, un false> as x, y { x } }"
+            ),
+            Err("--no-shadow: 変数\"x\"を束縛し直すと、消費されていないlin型の変数(関数の引数 x : lin bool)に到達できなくなる".to_string())
+        );
+
+        // 消費済みのlin型の変数や、un型の変数は束縛し直せる
+        assert_eq!(
+            typing_no_shadow("let x : lin bool = lin true; let x : lin bool = x; x"),
+            Ok("lin bool".to_string())
+        );
+        assert_eq!(
+            typing_no_shadow("let x : un bool = un true; let x : lin bool = lin false; x"),
+            Ok("lin bool".to_string())
+        );
+        // un型の関数の中からは外側のlin型の変数を参照できないため、隠すことにはならない
+        assert_eq!(
+            typing_no_shadow(
+                "lin fn x : lin bool { let f : un (un bool -> un bool) = un fn x : un bool { x }; x }"
+            ),
+            Ok("lin (lin bool -> lin bool)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_type_limits() {
+        let typing_limits = |input: &str, max_depth, max_size| -> Result<String, String> {
+            let (_, expr) = parse_program(input).unwrap();
+            let mut env = TypeEnv::new();
+            env.set_limits(TypeLimits {
+                max_depth,
+                max_size,
+            });
+            typing(&expr, &mut env, 0)
+                .map(|t| t.to_string())
+                .map_err(|e| e.to_string())
+        };
+
+        // ペアを組み立てた結果の型が上限を超える
+        let input = "un <un <un true, un true>, un true>";
+        assert_eq!(
+            typing_limits(input, 3, 100),
+            Ok("un (un (un bool * un bool) * un bool)".to_string())
+        );
+        assert_eq!(
+            typing_limits(input, 2, 100),
+            Err("型の入れ子の深さが上限2を超えている".to_string())
+        );
+        assert_eq!(
+            typing_limits(input, 3, 4),
+            Err("型の大きさが上限4を超えている".to_string())
+        );
+
+        // 宣言された型も検査する
+        assert_eq!(
+            typing_limits(
+                "let f : un (un (un bool * un bool) -> un bool) = un fn x : un (un bool * un bool) { un true }; un true",
+                2,
+                100
+            ),
+            Err("型の入れ子の深さが上限2を超えている".to_string())
+        );
+
+        // 既定の上限を超える入れ子のペアも、スタックを使い切らずにエラーとなる
+        let depth = TypeLimits::default().max_depth;
+        let input = format!(
+            "{}un true{}",
+            "un <".repeat(depth),
+            ", un true>".repeat(depth)
+        );
+        assert_eq!(
+            typing_str(&input),
+            Err(format!("型の入れ子の深さが上限{depth}を超えている"))
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! アーキテクチャに依存するステップ実行の方法
+//!
+//! 1命令のステップ実行には、PTRACE_SINGLESTEPによるハードウェアのステップ実行と、
+//! 命令を解釈して次に実行されうるアドレスを求め、そこに一時的なブレークポイントを設定して再開する
+//! ソフトウェアのステップ実行がある。PTRACE_SINGLESTEPを持たないアーキテクチャや、
+//! ハードウェアのステップ実行では結果が変わってしまう命令では後者を用いる。
+//! アーキテクチャごとの違いはArchトレイトにまとめ、デバッガ本体は`set step`で選んだ方法で呼び出す。
+
+use crate::{disas, regs::reg_mut};
+use nix::libc::user_regs_struct;
+use std::fmt;
+
+/// ステップ実行の方法を決めるアーキテクチャ依存の処理
+pub trait Arch {
+    /// PTRACE_SINGLESTEPを利用できるなら真
+    fn has_hw_step(&self) -> bool;
+
+    /// codeの先頭の命令を、ハードウェアでステップ実行すべきでないなら真
+    fn needs_sw_step(&self, code: &[u8]) -> bool;
+
+    /// PCがregs.ripで、codeから始まる命令を実行した直後に到達しうるアドレスを返す
+    ///
+    /// readは子プロセスのメモリから8バイトを読む関数。分岐先を求められない場合はErrを返す
+    fn next_pcs(
+        &self,
+        code: &[u8],
+        regs: &user_regs_struct,
+        read: &dyn Fn(u64) -> Result<u64, String>,
+    ) -> Result<Vec<u64>, String>;
+
+    /// ブレークポイント命令で停止した直後のPCから、ブレークポイントのアドレスを求める
+    fn break_addr(&self, pc: u64) -> u64;
+}
+
+/// x86-64
+pub struct X86_64;
+
+impl Arch for X86_64 {
+    fn has_hw_step(&self) -> bool {
+        true
+    }
+
+    /// pushfはTFが立ったeflagsをスタックに積んでしまうため、ソフトウェアでステップ実行する
+    fn needs_sw_step(&self, code: &[u8]) -> bool {
+        pushf_len(code).is_some()
+    }
+
+    fn next_pcs(
+        &self,
+        code: &[u8],
+        regs: &user_regs_struct,
+        read: &dyn Fn(u64) -> Result<u64, String>,
+    ) -> Result<Vec<u64>, String> {
+        let rip = regs.rip;
+        if let Some(len) = pushf_len(code) {
+            return Ok(vec![rip + len as u64]);
+        }
+        let inst = disas::decode(code, rip).map_err(|_| "命令を解釈できません".to_string())?;
+        let next = rip + inst.len as u64;
+        let (name, operand) = inst.text.split_once(' ').unwrap_or((&inst.text, ""));
+        match (name, inst.target) {
+            ("jmp" | "call", Some(target)) => Ok(vec![target]),
+            // 条件分岐は、分岐する場合としない場合のどちらにも到達しうる
+            (_, Some(target)) => Ok(vec![next, target]),
+            ("ret", None) => Ok(vec![read(regs.rsp)?]),
+            ("jmp" | "call", None) => {
+                let target = match inst.rip_ref {
+                    Some(addr) => read(addr)?,
+                    None => indirect_target(operand, regs, read)?,
+                };
+                Ok(vec![target])
+            }
+            ("int3" | "int" | "hlt" | "(bad)", _) => Err(format!("{}は実行できません", inst.text)),
+            _ => Ok(vec![next]),
+        }
+    }
+
+    /// int 3は1バイトで、実行後のPCは次のアドレスとなる
+    fn break_addr(&self, pc: u64) -> u64 {
+        pc - 1
+    }
+}
+
+/// codeの先頭がpushfならその命令長を返す
+fn pushf_len(code: &[u8]) -> Option<usize> {
+    let prefixes = code.iter().take_while(|b| **b == 0x66).count();
+    (code.get(prefixes) == Some(&0x9c)).then_some(prefixes + 1)
+}
+
+/// 間接分岐のオペランド(raxやqword ptr [rax+rbx*8+0x10])から分岐先を求める
+fn indirect_target(
+    operand: &str,
+    regs: &user_regs_struct,
+    read: &dyn Fn(u64) -> Result<u64, String>,
+) -> Result<u64, String> {
+    let mut regs = *regs;
+    let Some(mem) = operand.strip_prefix("qword ptr ") else {
+        return reg_mut(&mut regs, operand)
+            .map(|r| *r)
+            .ok_or_else(|| format!("分岐先を求められません : {operand}"));
+    };
+    let expr = mem
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .ok_or_else(|| format!("分岐先を求められません : {operand}"))?;
+
+    // base+index*scale±dispの各項を足し合わせる
+    let mut addr = 0u64;
+    let mut rest = expr;
+    let mut sign = 1i64;
+    loop {
+        let end = rest.find(['+', '-']).unwrap_or(rest.len());
+        let term = &rest[..end];
+        let val = match term.split_once('*') {
+            Some((reg, scale)) => {
+                let scale: u64 = scale
+                    .parse()
+                    .map_err(|_| format!("分岐先を求められません : {operand}"))?;
+                reg_value(&mut regs, reg, operand)?.wrapping_mul(scale)
+            }
+            None => match term.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16)
+                    .map_err(|_| format!("分岐先を求められません : {operand}"))?,
+                None => reg_value(&mut regs, term, operand)?,
+            },
+        };
+        addr = addr.wrapping_add((val as i64).wrapping_mul(sign) as u64);
+        let Some(op) = rest[end..].chars().next() else {
+            break;
+        };
+        sign = if op == '-' { -1 } else { 1 };
+        rest = &rest[end + 1..];
+    }
+    read(addr)
+}
+
+/// 名前がnameのレジスタの値
+fn reg_value(regs: &mut user_regs_struct, name: &str, operand: &str) -> Result<u64, String> {
+    reg_mut(regs, name)
+        .map(|r| *r)
+        .ok_or_else(|| format!("分岐先を求められません : {operand}"))
+}
+
+/// ステップ実行の方法(set step)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepMode {
+    Auto,     // 通常はハードウェア、必要な命令とPTRACE_SINGLESTEPがない場合はソフトウェア
+    Hardware, // 常にPTRACE_SINGLESTEP
+    Software, // 常に一時的なブレークポイント
+}
+
+impl StepMode {
+    /// set stepで指定する名前を解析する
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(StepMode::Auto),
+            "hw" => Some(StepMode::Hardware),
+            "sw" => Some(StepMode::Software),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for StepMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepMode::Auto => write!(f, "auto"),
+            StepMode::Hardware => write!(f, "hw"),
+            StepMode::Software => write!(f, "sw"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regs() -> user_regs_struct {
+        let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+        regs.rip = 0x401000;
+        regs.rsp = 0x7ff000;
+        regs.rax = 0x401234;
+        regs.rbx = 0x600000;
+        regs.rcx = 2;
+        regs
+    }
+
+    /// 0x7ff000にリターンアドレス、0x600000から8バイトごとに分岐先の表があるメモリ
+    fn read(addr: u64) -> Result<u64, String> {
+        match addr {
+            0x7ff000 => Ok(0x401100),
+            0x600000..=0x600018 => Ok(0x402000 + (addr - 0x600000)),
+            0x403000 => Ok(0x7ffff7e00000),
+            _ => Err(format!("{addr:#x}を読み込めません")),
+        }
+    }
+
+    fn next_pcs(code: &[u8]) -> Result<Vec<u64>, String> {
+        X86_64.next_pcs(code, &regs(), &read)
+    }
+
+    #[test]
+    fn test_next_pcs() {
+        // 分岐しない命令は次の命令
+        assert_eq!(next_pcs(&[0x48, 0x89, 0xe5]), Ok(vec![0x401003]));
+        assert_eq!(next_pcs(&[0x0f, 0x05]), Ok(vec![0x401002]));
+        // 直接分岐は分岐先、条件分岐は次の命令と分岐先
+        assert_eq!(next_pcs(&[0xe8, 0xfb, 0x00, 0, 0]), Ok(vec![0x401100]));
+        assert_eq!(next_pcs(&[0xeb, 0x10]), Ok(vec![0x401012]));
+        assert_eq!(next_pcs(&[0x75, 0x02]), Ok(vec![0x401002, 0x401004]));
+        // retはスタックのリターンアドレス
+        assert_eq!(next_pcs(&[0xc3]), Ok(vec![0x401100]));
+        // 間接分岐はレジスタかメモリの値
+        assert_eq!(next_pcs(&[0xff, 0xd0]), Ok(vec![0x401234])); // call rax
+        assert_eq!(
+            next_pcs(&[0xff, 0x24, 0xcb]), // jmp qword ptr [rbx+rcx*8]
+            Ok(vec![0x402010])
+        );
+        assert_eq!(
+            next_pcs(&[0xff, 0x63, 0x08]), // jmp qword ptr [rbx+0x8]
+            Ok(vec![0x402008])
+        );
+        assert_eq!(
+            next_pcs(&[0xff, 0x15, 0xfa, 0x1f, 0, 0]), // call qword ptr [rip+0x1ffa]
+            Ok(vec![0x7ffff7e00000])
+        );
+        // pushfは解釈できない命令だが、次の命令に進む
+        assert_eq!(next_pcs(&[0x9c]), Ok(vec![0x401001]));
+        assert!(next_pcs(&[0x06]).is_err());
+        assert!(next_pcs(&[0xff, 0x20]).is_err()); // jmp qword ptr [rax]は読めない
+    }
+
+    #[test]
+    fn test_needs_sw_step() {
+        assert!(X86_64.needs_sw_step(&[0x9c]));
+        assert!(X86_64.needs_sw_step(&[0x66, 0x9c]));
+        assert!(!X86_64.needs_sw_step(&[0x55]));
+        assert_eq!(X86_64.break_addr(0x401001), 0x401000);
+    }
+
+    #[test]
+    fn test_step_mode() {
+        assert_eq!(StepMode::parse("sw"), Some(StepMode::Software));
+        assert_eq!(StepMode::parse("software"), None);
+        assert_eq!(StepMode::Hardware.to_string(), "hw");
+    }
+}
@@ -0,0 +1,284 @@
+//! 呼び出し元を辿るバックトレース(bt)
+//!
+//! 各フレームでは、CFI(.eh_frameと.debug_frame)の規則で呼び出し元のレジスタを復元する。
+//! CFIがない関数(CFIを持たない共有ライブラリや、手書きのアセンブリなど)では、
+//! push rbp; mov rbp, rsp で始まるとみなしてフレームポインタを辿る。
+//! このときrbpが指す位置に呼び出し元のrbpが、その8バイト後ろにリターンアドレスが保存されている。
+
+use crate::cfi::{FrameRule, RegRule, Regs, RA, RBP, RSP};
+use crate::disas::Inst;
+
+/// 辿るフレーム数の上限。rbpが壊れている場合に際限なく辿らないようにする
+pub const MAX_FRAMES: usize = 64;
+
+/// 停止した位置での、関数のプロローグの実行状況
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prologue {
+    Entry,  // push rbpの前、またはret直前。リターンアドレスは[rsp]
+    Pushed, // push rbpの後、mov rbp, rspの前。リターンアドレスは[rsp+8]
+    Done,   // rbpを設定済み。リターンアドレスは[rbp+8]
+}
+
+impl Prologue {
+    /// CFIがない場合に使う、フレームポインタによる巻き戻しの規則
+    pub fn rule(self) -> FrameRule {
+        let (cfa_reg, cfa_offset) = match self {
+            Prologue::Entry => (RSP, 8),
+            Prologue::Pushed => (RSP, 16),
+            Prologue::Done => (RBP, 16),
+        };
+        let mut regs = vec![(RA, RegRule::Offset(-8))];
+        if self != Prologue::Entry {
+            regs.push((RBP, RegRule::Offset(-16)));
+        }
+        FrameRule {
+            cfa_reg,
+            cfa_offset,
+            regs,
+        }
+    }
+}
+
+/// 関数の先頭から停止位置の直前までに実行した命令executedと、停止位置の命令currentから、
+/// プロローグの実行状況を判定する
+pub fn prologue(executed: &[Inst], current: Option<&Inst>) -> Prologue {
+    if current.is_some_and(|inst| inst.text == "ret") {
+        return Prologue::Entry;
+    }
+    let done = |text: &str| executed.iter().any(|inst| inst.text == text);
+    if done("mov rbp, rsp") {
+        Prologue::Done
+    } else if done("push rbp") {
+        Prologue::Pushed
+    } else {
+        Prologue::Entry
+    }
+}
+
+/// バックトレースの1フレーム
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub pc: u64,              // フレームのPC
+    pub sp: u64,              // フレームのrsp。停止位置以外では、呼び出し先から戻った直後の値
+    pub ra_addr: Option<u64>, // pcを読み込んだ、リターンアドレスが保存されているアドレス。停止位置ではNone
+}
+
+/// 停止時のレジスタregsから呼び出し元を辿り、各フレームを返す。先頭は停止位置
+///
+/// findは実行時のアドレスからCFIの規則を引く関数、readはアドレスから8バイトを読み込む関数。
+/// CFIがない場合は、停止位置のフレームではprologueに応じて、それ以外ではrbpを設定済みとして辿る。
+/// リターンアドレスが0か読み込めない場合、または呼び出し元のrspがスタックの奥(大きいアドレス)に
+/// 進んでいない場合に辿るのをやめる
+pub fn walk_frames<'a>(
+    mut regs: Regs,
+    prologue: Prologue,
+    find: impl Fn(u64) -> Option<&'a FrameRule>,
+    read: impl Fn(u64) -> Option<u64>,
+) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut ra_addr = None;
+    while let Some(pc) = regs.get(RA).filter(|pc| *pc != 0) {
+        frames.push(Frame {
+            pc,
+            sp: regs.get(RSP).unwrap_or(0),
+            ra_addr,
+        });
+        if frames.len() >= MAX_FRAMES {
+            break;
+        }
+
+        // 呼び出し元のPCはcall命令の直後を指す。callが関数の末尾にある場合は
+        // 次の関数を指してしまうため、1バイト前のcall命令の位置で規則を探す
+        let (lookup, fallback) = match frames.len() {
+            1 => (pc, prologue),
+            _ => (pc - 1, Prologue::Done),
+        };
+        let fallback_rule;
+        let rule = match find(lookup) {
+            Some(rule) => rule,
+            None => {
+                fallback_rule = fallback.rule();
+                &fallback_rule
+            }
+        };
+        let Some(caller) = rule.unwind(&regs, &read) else {
+            break;
+        };
+        if caller.get(RSP) <= regs.get(RSP) {
+            break;
+        }
+        ra_addr = rule.ra_addr(&regs);
+        regs = caller;
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disas::disassemble;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_prologue() {
+        // endbr64; push rbp; mov rbp, rsp; pop rbp; ret
+        let code = [0xf3, 0x0f, 0x1e, 0xfa, 0x55, 0x48, 0x89, 0xe5, 0x5d, 0xc3];
+        let insts = disassemble(&code, 0x401000, code.len());
+        let at = |i: usize| prologue(&insts[..i], insts.get(i));
+        assert_eq!(at(0), Prologue::Entry);
+        assert_eq!(at(1), Prologue::Entry);
+        assert_eq!(at(2), Prologue::Pushed);
+        assert_eq!(at(3), Prologue::Done);
+        assert_eq!(at(4), Prologue::Entry);
+    }
+
+    /// 停止時のレジスタ
+    fn regs(pc: u64, sp: u64, fp: u64) -> Regs {
+        let mut regs = Regs::default();
+        regs.set(RA, Some(pc));
+        regs.set(RSP, Some(sp));
+        regs.set(RBP, Some(fp));
+        regs
+    }
+
+    /// 各フレームのPCのみを返すwalk_frames
+    fn walk<'a>(
+        regs: Regs,
+        prologue: Prologue,
+        find: impl Fn(u64) -> Option<&'a FrameRule>,
+        read: impl Fn(u64) -> Option<u64>,
+    ) -> Vec<u64> {
+        walk_frames(regs, prologue, find, read)
+            .iter()
+            .map(|f| f.pc)
+            .collect()
+    }
+
+    #[test]
+    fn test_walk() {
+        // main(0x7ff0) -> f(0x7fd0) -> g(rbp = 0x7fb0)
+        let mem: HashMap<u64, u64> = [
+            (0x7fb0, 0x7fd0),
+            (0x7fb8, 0x401200), // gからfへのリターンアドレス
+            (0x7fd0, 0x7ff0),
+            (0x7fd8, 0x401300), // fからmainへのリターンアドレス
+            (0x7ff0, 0),
+            (0x7ff8, 0x401400), // mainから__libc_start_call_mainへのリターンアドレス
+            (0x7fa0, 0x401250), // gの先頭で停止した場合の[rsp]
+        ]
+        .into_iter()
+        .collect();
+        let read = |addr| mem.get(&addr).copied();
+        let no_cfi = |_| None;
+
+        assert_eq!(
+            walk(regs(0x401100, 0x7fa0, 0x7fb0), Prologue::Done, no_cfi, read),
+            vec![0x401100, 0x401200, 0x401300, 0x401400]
+        );
+
+        // 関数の先頭ではrbpは呼び出し元のもの
+        assert_eq!(
+            walk(
+                regs(0x401240, 0x7fa0, 0x7fd0),
+                Prologue::Entry,
+                no_cfi,
+                read
+            ),
+            vec![0x401240, 0x401250, 0x401300, 0x401400]
+        );
+        assert_eq!(
+            walk(
+                regs(0x401244, 0x7fb0, 0x7fd0),
+                Prologue::Pushed,
+                no_cfi,
+                read
+            ),
+            vec![0x401244, 0x401200, 0x401300, 0x401400]
+        );
+
+        // rbpが読み込めない場合や、スタックの手前を指している場合はそこでやめる
+        assert_eq!(
+            walk(regs(0x401100, 0x7fa0, 0x9000), Prologue::Done, no_cfi, read),
+            vec![0x401100]
+        );
+        assert_eq!(
+            walk(regs(0x401100, 0x7fc0, 0x7fb0), Prologue::Done, no_cfi, read),
+            vec![0x401100]
+        );
+        let looped = |addr| if addr % 16 == 0 { Some(addr) } else { Some(1) };
+        assert_eq!(
+            walk(
+                regs(0x401100, 0x7fa0, 0x7fb0),
+                Prologue::Done,
+                no_cfi,
+                looped
+            ),
+            vec![0x401100, 1]
+        );
+    }
+
+    #[test]
+    fn test_walk_frames() {
+        let mem: HashMap<u64, u64> = [(0x7fb0, 0x7fd0), (0x7fb8, 0x401200), (0x7fd0, 0)]
+            .into_iter()
+            .collect();
+        let read = |addr| mem.get(&addr).copied();
+        assert_eq!(
+            walk_frames(
+                regs(0x401100, 0x7fa0, 0x7fb0),
+                Prologue::Done,
+                |_| None,
+                read
+            ),
+            vec![
+                Frame {
+                    pc: 0x401100,
+                    sp: 0x7fa0,
+                    ra_addr: None
+                },
+                Frame {
+                    pc: 0x401200,
+                    sp: 0x7fc0,
+                    ra_addr: Some(0x7fb8)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walk_cfi() {
+        // rbpを使わないh(CFA = rsp+0x18)から、フレームポインタを使うfに戻る。rbpはfのもの
+        let rule = FrameRule {
+            cfa_reg: RSP,
+            cfa_offset: 0x18,
+            regs: vec![(RA, RegRule::Offset(-8))],
+        };
+        let find = |pc| (0x401500..0x401580).contains(&pc).then_some(&rule);
+        let mem: HashMap<u64, u64> = [
+            (0x7f90, 0x401200), // hからfへのリターンアドレス
+            (0x7fd0, 0x7ff0),
+            (0x7fd8, 0x401300),
+            (0x7ff0, 0),
+            (0x7ff8, 0x401400),
+        ]
+        .into_iter()
+        .collect();
+        let read = |addr| mem.get(&addr).copied();
+
+        // CFIがあればプロローグの判定は使わない
+        assert_eq!(
+            walk(regs(0x401540, 0x7f80, 0x7fd0), Prologue::Entry, find, read),
+            vec![0x401540, 0x401200, 0x401300, 0x401400]
+        );
+
+        // 呼び出し元のフレームでは、リターンアドレスの1バイト前で規則を探す
+        let mem: HashMap<u64, u64> = [(0x7f90, 0x401580), (0x7fa8, 0x401400)]
+            .into_iter()
+            .collect();
+        let read = |addr| mem.get(&addr).copied();
+        assert_eq!(
+            walk(regs(0x401100, 0x7f90, 0x7fa0), Prologue::Entry, find, read),
+            vec![0x401100, 0x401580, 0x401400]
+        );
+    }
+}
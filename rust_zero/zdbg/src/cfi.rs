@@ -0,0 +1,284 @@
+//! 呼び出しフレーム情報(CFI)によるスタックの巻き戻し
+//!
+//! .eh_frameと.debug_frameには、命令のアドレスごとに「呼び出し元のrspに相当するアドレス(CFA)を
+//! どのレジスタから求めるか」と「各レジスタがCFAからどこに保存されているか」が記録されている。
+//! これを使うと、rbpをフレームポインタとして使わない関数でも呼び出し元のレジスタを復元できる。
+//! 記録の解釈(CIEとFDEの命令の実行)はgimliクレートに任せ、ここでは結果をアドレス順に並べて保持する。
+
+use crate::helper::DynError;
+use gimli::{
+    BaseAddresses, CfaRule, DebugFrame, EhFrame, EndianSlice, RegisterRule, RunTimeEndian,
+    UnwindContext, UnwindSection,
+};
+use nix::libc::user_regs_struct;
+use object::{Object, ObjectSection};
+use std::{borrow::Cow, fs};
+
+/// DWARFのレジスタ番号(x86-64)
+pub const RBP: u16 = 6;
+pub const RSP: u16 = 7;
+pub const RA: u16 = 16; // リターンアドレス。現在のフレームではrip
+
+/// 扱うレジスタの個数。rax〜r15とリターンアドレス
+const NUM_REGS: usize = 17;
+
+/// findで重なりを探す範囲の最大数
+const MAX_OVERLAP: usize = 4;
+
+/// 1フレーム分のレジスタの値。DWARFのレジスタ番号で引き、値が分からない場合はNone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Regs([Option<u64>; NUM_REGS]);
+
+impl Regs {
+    pub fn get(&self, reg: u16) -> Option<u64> {
+        *self.0.get(reg as usize)?
+    }
+
+    pub fn set(&mut self, reg: u16, val: Option<u64>) {
+        if let Some(r) = self.0.get_mut(reg as usize) {
+            *r = val;
+        }
+    }
+}
+
+impl From<&user_regs_struct> for Regs {
+    fn from(regs: &user_regs_struct) -> Self {
+        Regs(
+            [
+                regs.rax, regs.rdx, regs.rcx, regs.rbx, regs.rsi, regs.rdi, regs.rbp, regs.rsp,
+                regs.r8, regs.r9, regs.r10, regs.r11, regs.r12, regs.r13, regs.r14, regs.r15,
+                regs.rip,
+            ]
+            .map(Some),
+        )
+    }
+}
+
+/// 呼び出し元でのレジスタの値の求め方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegRule {
+    Undefined,      // 復元できない
+    Offset(i64),    // CFA+Nのアドレスに保存されている
+    ValOffset(i64), // CFA+Nそのもの
+    Register(u16),  // 別のレジスタに保存されている
+}
+
+/// ある命令の位置で、呼び出し元のレジスタを復元する規則
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameRule {
+    pub cfa_reg: u16,              // CFAの基準となるレジスタ
+    pub cfa_offset: i64,           // 基準のレジスタからCFAへのオフセット
+    pub regs: Vec<(u16, RegRule)>, // 規則のあるレジスタ。ないレジスタは値が変わらない
+}
+
+impl FrameRule {
+    /// regsからCFAを求める。基準のレジスタが分からない場合はNone
+    pub fn cfa(&self, regs: &Regs) -> Option<u64> {
+        Some(regs.get(self.cfa_reg)?.wrapping_add(self.cfa_offset as u64))
+    }
+
+    /// regsから、リターンアドレスが保存されているスタック上のアドレスを求める
+    ///
+    /// リターンアドレスがCFA+Nに保存されていない場合はNone
+    pub fn ra_addr(&self, regs: &Regs) -> Option<u64> {
+        match self.regs.iter().find(|(reg, _)| *reg == RA)? {
+            (_, RegRule::Offset(n)) => Some(self.cfa(regs)?.wrapping_add(*n as u64)),
+            _ => None,
+        }
+    }
+
+    /// regsから呼び出し元のレジスタを復元する。CFAを求められない場合はNone
+    ///
+    /// 呼び出し元のrspはCFAとなる。readはアドレスから8バイトを読み込む関数
+    pub fn unwind(&self, regs: &Regs, read: impl Fn(u64) -> Option<u64>) -> Option<Regs> {
+        let cfa = self.cfa(regs)?;
+        let mut caller = *regs;
+        caller.set(RSP, Some(cfa));
+        for (reg, rule) in self.regs.iter() {
+            let val = match *rule {
+                RegRule::Undefined => None,
+                RegRule::Offset(n) => read(cfa.wrapping_add(n as u64)),
+                RegRule::ValOffset(n) => Some(cfa.wrapping_add(n as u64)),
+                RegRule::Register(r) => regs.get(r),
+            };
+            caller.set(*reg, val);
+        }
+        Some(caller)
+    }
+}
+
+/// アドレスの範囲とその範囲で使う規則
+#[derive(Debug)]
+struct CfiRow {
+    start: u64, // 範囲の先頭(実行ファイル上のアドレス)
+    end: u64,   // 範囲の終端。この値は含まない
+    rule: FrameRule,
+}
+
+/// .eh_frameと.debug_frameから生成した、アドレスから巻き戻しの規則を引くテーブル
+#[derive(Debug)]
+pub struct CfiTable {
+    rows: Vec<CfiRow>, // 先頭のアドレス順にソートされた範囲
+}
+
+impl CfiTable {
+    /// 実行ファイルを読み込み、CFIのテーブルを生成
+    ///
+    /// 解釈できないFDEや、DWARFの式でCFAを求める範囲(PLTなど)は含めない
+    pub fn load(filename: &str) -> Result<Self, DynError> {
+        let data = fs::read(filename)?;
+        let obj = object::File::parse(&*data)?;
+        let endian = if obj.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        let section_data = |name: &str| {
+            obj.section_by_name(name)
+                .and_then(|s| s.uncompressed_data().ok())
+                .unwrap_or(Cow::Borrowed(&[]))
+        };
+        let section_addr = |name: &str| obj.section_by_name(name).map_or(0, |s| s.address());
+
+        let mut rows = Vec::new();
+
+        // .eh_frameはポインタをセクションや.textからの相対アドレスで表す場合がある
+        let eh_frame_data = section_data(".eh_frame");
+        let mut eh_frame = EhFrame::new(&eh_frame_data, endian);
+        eh_frame.set_address_size(8);
+        let bases = BaseAddresses::default()
+            .set_eh_frame(section_addr(".eh_frame"))
+            .set_text(section_addr(".text"))
+            .set_got(section_addr(".got"));
+        collect_rows(&eh_frame, &bases, &mut rows);
+
+        let debug_frame_data = section_data(".debug_frame");
+        let mut debug_frame = DebugFrame::new(&debug_frame_data, endian);
+        debug_frame.set_address_size(8);
+        collect_rows(&debug_frame, &BaseAddresses::default(), &mut rows);
+
+        rows.sort_by_key(|row| row.start);
+        Ok(CfiTable { rows })
+    }
+
+    /// 実行ファイル上のアドレスaddrで使う規則を返す
+    pub fn find(&self, addr: u64) -> Option<&FrameRule> {
+        let idx = self.rows.partition_point(|row| row.start <= addr);
+        // 範囲が重なる場合(.eh_frameと.debug_frameの両方にある場合など)は、先頭が近いものから探す
+        self.rows[..idx]
+            .iter()
+            .rev()
+            .take(MAX_OVERLAP)
+            .find(|row| addr < row.end)
+            .map(|row| &row.rule)
+    }
+}
+
+/// sectionのすべてのFDEの命令を実行し、範囲ごとの規則をrowsに追加する
+fn collect_rows<'a, S>(section: &S, bases: &BaseAddresses, rows: &mut Vec<CfiRow>)
+where
+    S: UnwindSection<EndianSlice<'a, RunTimeEndian>>,
+{
+    let mut ctx = UnwindContext::new();
+    let mut entries = section.entries(bases);
+    // 壊れたエントリ以降は読み飛ばせないため、そこで打ち切る
+    while let Ok(Some(entry)) = entries.next() {
+        let gimli::CieOrFde::Fde(partial) = entry else {
+            continue;
+        };
+        let Ok(fde) = partial.parse(|s, b, o| s.cie_from_offset(b, o)) else {
+            continue;
+        };
+        let Ok(mut table) = fde.rows(section, bases, &mut ctx) else {
+            continue;
+        };
+        while let Ok(Some(row)) = table.next_row() {
+            let CfaRule::RegisterAndOffset { register, offset } = row.cfa() else {
+                continue;
+            };
+            let mut regs: Vec<(u16, RegRule)> = row
+                .registers()
+                .filter(|(reg, _)| (reg.0 as usize) < NUM_REGS)
+                .filter_map(|(reg, rule)| {
+                    let rule = match rule {
+                        RegisterRule::SameValue => return None,
+                        RegisterRule::Offset(n) => RegRule::Offset(*n),
+                        RegisterRule::ValOffset(n) => RegRule::ValOffset(*n),
+                        RegisterRule::Register(r) => RegRule::Register(r.0),
+                        // DWARFの式などには対応しない
+                        _ => RegRule::Undefined,
+                    };
+                    Some((reg.0, rule))
+                })
+                .collect();
+            // gimliは未定義の規則を省くが、リターンアドレスがない場合は最も外側のフレームを表す
+            if regs.iter().all(|(reg, _)| *reg != RA) {
+                regs.push((RA, RegRule::Undefined));
+            }
+            rows.push(CfiRow {
+                start: row.start_address(),
+                end: row.end_address(),
+                rule: FrameRule {
+                    cfa_reg: register.0,
+                    cfa_offset: *offset,
+                    regs,
+                },
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_unwind() {
+        // push rbx; sub rsp, 0x8の後: CFA = rsp+0x18、リターンアドレスはCFA-8、rbxはCFA-16
+        let rule = FrameRule {
+            cfa_reg: RSP,
+            cfa_offset: 0x18,
+            regs: vec![(RA, RegRule::Offset(-8)), (3, RegRule::Offset(-16))],
+        };
+        let mem: HashMap<u64, u64> = [(0x7f10, 0x401234), (0x7f08, 42)].into_iter().collect();
+        let mut regs = Regs::default();
+        regs.set(RSP, Some(0x7f00));
+        regs.set(RBP, Some(0x7fd0));
+        regs.set(RA, Some(0x401100));
+
+        let caller = rule.unwind(&regs, |addr| mem.get(&addr).copied()).unwrap();
+        assert_eq!(caller.get(RSP), Some(0x7f18));
+        assert_eq!(caller.get(RA), Some(0x401234));
+        assert_eq!(caller.get(3), Some(42));
+        assert_eq!(caller.get(RBP), Some(0x7fd0)); // 規則のないレジスタは変わらない
+
+        // 基準のレジスタが分からない場合は巻き戻せない
+        regs.set(RSP, None);
+        assert_eq!(rule.unwind(&regs, |_| None), None);
+    }
+
+    #[test]
+    fn test_load() {
+        // テストの実行ファイル自身の.eh_frameを読み込む
+        let exe = std::env::current_exe().unwrap();
+        let table = CfiTable::load(exe.to_str().unwrap()).unwrap();
+        assert!(!table.rows.is_empty());
+        assert!(table.rows.windows(2).all(|w| w[0].start <= w[1].start));
+
+        // 関数の先頭ではCFA = rsp+8で、リターンアドレスはCFA-8にある
+        let row = table
+            .rows
+            .iter()
+            .find(|row| row.rule.regs.contains(&(RA, RegRule::Offset(-8))))
+            .unwrap();
+        assert_eq!((row.rule.cfa_reg, row.rule.cfa_offset), (RSP, 8));
+        assert_eq!(table.find(row.start), Some(&row.rule));
+
+        // _startではリターンアドレスが未定義で、それ以上辿れない
+        assert!(table
+            .rows
+            .iter()
+            .any(|row| row.rule.regs.contains(&(RA, RegRule::Undefined))));
+    }
+}
@@ -0,0 +1,342 @@
+//! 条件付きブレークポイントの条件式(break ... if 条件)
+//!
+//! `rdi == 5`や`rax != 0 && *(rsp + 8) == 0x401000`のように、レジスタ、整数、
+//! メモリの値(*アドレスで8バイトを読み込む)を演算子で組み合わせた式を解釈する。
+//! 値はすべて64ビットで、比較は符号付き整数として行い、0以外を真とする。
+//! 演算子の優先順位はRustと同じで、高い順に単項の!・-・*、+と-、&、比較、&&、||となる。
+//! 条件は設定時に構文解析しておき、ブレークポイントで停止するたびに評価する。
+
+use crate::regs::{parse_value, reg_mut};
+use nix::libc::user_regs_struct;
+use std::fmt;
+
+/// 二項演算子
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+/// 単項演算子
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnOp {
+    Not,   // 論理否定
+    Neg,   // 符号反転
+    Deref, // メモリの8バイトを読み込む
+}
+
+/// 条件式の構文木
+#[derive(Debug)]
+enum Node {
+    Num(u64),
+    Reg(String),
+    Unary(UnOp, Box<Node>),
+    Binary(BinOp, Box<Node>, Box<Node>),
+}
+
+/// 条件式
+#[derive(Debug)]
+pub struct Cond {
+    src: String, // 入力された式。info breakpointsで表示する
+    node: Node,
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.src)
+    }
+}
+
+impl Cond {
+    /// 条件式srcを構文解析する
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let tokens = tokenize(src)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let node = parser.or()?;
+        if let Some(t) = parser.tokens.get(parser.pos) {
+            return Err(format!("余分な字句があります : {t}"));
+        }
+        Ok(Cond {
+            src: src.to_string(),
+            node,
+        })
+    }
+
+    /// レジスタregsの値で条件を評価する。readはメモリの8バイトを読み込む関数
+    pub fn eval(
+        &self,
+        regs: &user_regs_struct,
+        read: &dyn Fn(u64) -> Result<u64, String>,
+    ) -> Result<bool, String> {
+        Ok(eval(&self.node, regs, read)? != 0)
+    }
+}
+
+fn eval(
+    node: &Node,
+    regs: &user_regs_struct,
+    read: &dyn Fn(u64) -> Result<u64, String>,
+) -> Result<u64, String> {
+    let val = match node {
+        Node::Num(n) => *n,
+        Node::Reg(name) => {
+            let mut regs = *regs;
+            *reg_mut(&mut regs, name).ok_or_else(|| format!("不明なレジスタです : {name}"))?
+        }
+        Node::Unary(op, e) => {
+            let v = eval(e, regs, read)?;
+            match op {
+                UnOp::Not => (v == 0) as u64,
+                UnOp::Neg => v.wrapping_neg(),
+                UnOp::Deref => read(v)?,
+            }
+        }
+        // 論理演算子は短絡評価し、右辺のメモリを読み込まないようにする
+        Node::Binary(BinOp::Or, l, r) => {
+            (eval(l, regs, read)? != 0 || eval(r, regs, read)? != 0) as u64
+        }
+        Node::Binary(BinOp::And, l, r) => {
+            (eval(l, regs, read)? != 0 && eval(r, regs, read)? != 0) as u64
+        }
+        Node::Binary(op, l, r) => {
+            let (l, r) = (eval(l, regs, read)?, eval(r, regs, read)?);
+            let (sl, sr) = (l as i64, r as i64);
+            match op {
+                BinOp::Eq => (l == r) as u64,
+                BinOp::Ne => (l != r) as u64,
+                BinOp::Lt => (sl < sr) as u64,
+                BinOp::Le => (sl <= sr) as u64,
+                BinOp::Gt => (sl > sr) as u64,
+                BinOp::Ge => (sl >= sr) as u64,
+                BinOp::BitAnd => l & r,
+                BinOp::Add => l.wrapping_add(r),
+                BinOp::Sub => l.wrapping_sub(r),
+                BinOp::Or | BinOp::And => unreachable!(),
+            }
+        }
+    };
+    Ok(val)
+}
+
+/// 字句
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Num(u64),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "{n:#x}"),
+            Token::Ident(s) => write!(f, "{s}"),
+            Token::Op(op) => write!(f, "{op}"),
+        }
+    }
+}
+
+/// 演算子と括弧。2文字のものを先に照合する
+const OPS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "&", "+", "-", "!", "*", "(", ")",
+];
+
+/// 条件式を字句に分割する
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = src.trim_start();
+    while !rest.is_empty() {
+        if let Some(op) = OPS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else if rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                tokens.push(Token::Num(parse_value(word)?));
+            } else {
+                tokens.push(Token::Ident(word.to_string()));
+            }
+            rest = &rest[len..];
+        } else {
+            let c = rest.chars().next().unwrap();
+            return Err(format!("不正な文字です : {c}"));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// 再帰下降による構文解析器
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    /// 次の字句が演算子opsのいずれかなら読み進めて返す
+    fn eat(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    /// 左結合の二項演算子opsとその下位の式nextから成る式
+    fn binary(
+        &mut self,
+        ops: &[(&'static str, BinOp)],
+        next: fn(&mut Self) -> Result<Node, String>,
+    ) -> Result<Node, String> {
+        let names: Vec<&'static str> = ops.iter().map(|(name, _)| *name).collect();
+        let mut lhs = next(self)?;
+        while let Some(name) = self.eat(&names) {
+            let op = ops.iter().find(|(n, _)| *n == name).unwrap().1;
+            let rhs = next(self)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        self.binary(&[("||", BinOp::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        self.binary(&[("&&", BinOp::And)], Self::cmp)
+    }
+
+    /// 比較は結合しないため、a < b < cはエラーとする
+    fn cmp(&mut self) -> Result<Node, String> {
+        const CMP: &[(&str, BinOp)] = &[
+            ("==", BinOp::Eq),
+            ("!=", BinOp::Ne),
+            ("<=", BinOp::Le),
+            (">=", BinOp::Ge),
+            ("<", BinOp::Lt),
+            (">", BinOp::Gt),
+        ];
+        let names: Vec<&'static str> = CMP.iter().map(|(name, _)| *name).collect();
+        let lhs = self.bitand()?;
+        let Some(name) = self.eat(&names) else {
+            return Ok(lhs);
+        };
+        let op = CMP.iter().find(|(n, _)| *n == name).unwrap().1;
+        let rhs = self.bitand()?;
+        if self.eat(&names).is_some() {
+            return Err("比較演算子は連続して使えません。&&で区切ってください".to_string());
+        }
+        Ok(Node::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn bitand(&mut self) -> Result<Node, String> {
+        self.binary(&[("&", BinOp::BitAnd)], Self::sum)
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        self.binary(&[("+", BinOp::Add), ("-", BinOp::Sub)], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        let op = match self.eat(&["!", "-", "*"]) {
+            Some("!") => UnOp::Not,
+            Some("-") => UnOp::Neg,
+            Some(_) => UnOp::Deref,
+            None => return self.primary(),
+        };
+        Ok(Node::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        let Some(token) = self.tokens.get(self.pos).cloned() else {
+            return Err("式が途中で終わっています".to_string());
+        };
+        self.pos += 1;
+        match token {
+            Token::Num(n) => Ok(Node::Num(n)),
+            Token::Ident(name) => {
+                // 評価時に失敗しないように、レジスタ名はここで検査する
+                let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+                if reg_mut(&mut regs, &name).is_none() {
+                    return Err(format!("不明なレジスタです : {name}"));
+                }
+                Ok(Node::Reg(name))
+            }
+            Token::Op("(") => {
+                let node = self.or()?;
+                if self.eat(&[")"]).is_none() {
+                    return Err("閉じ括弧がありません".to_string());
+                }
+                Ok(node)
+            }
+            Token::Op(op) => Err(format!("値が必要な位置に{op}があります")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_str(src: &str) -> Result<bool, String> {
+        let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+        regs.rdi = 5;
+        regs.rax = -1i64 as u64;
+        regs.rsp = 0x7ffe0000;
+        regs.eflags = 0x246;
+        let read = |addr: u64| match addr {
+            0x7ffe0008 => Ok(0x401000),
+            _ => Err(format!("{addr:#x}を読み込めません")),
+        };
+        Cond::parse(src)?.eval(&regs, &read)
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval_str("rdi == 5"), Ok(true));
+        assert_eq!(eval_str("RDI==0x5"), Ok(true));
+        assert_eq!(eval_str("rdi != 5"), Ok(false));
+        // 比較は符号付き
+        assert_eq!(eval_str("rax < 0"), Ok(true));
+        assert_eq!(eval_str("rax == -1"), Ok(true));
+        // 優先順位は高い順に単項、+と-、&、比較、&&、||
+        assert_eq!(eval_str("rdi + 1 - 2 == 4"), Ok(true));
+        assert_eq!(eval_str("eflags & 0x40 != 0"), Ok(true));
+        assert_eq!(eval_str("rdi == 1 || rdi == 5 && !(rax == 0)"), Ok(true));
+        assert_eq!(eval_str("(rdi == 1 || rdi == 5) && rax == 0"), Ok(false));
+        // メモリの値
+        assert_eq!(eval_str("*(rsp + 8) == 0x401000"), Ok(true));
+        assert!(eval_str("*rsp == 0").is_err());
+        // 短絡評価するため、右辺のメモリは読み込まない
+        assert_eq!(eval_str("rdi == 0 && *rsp == 0"), Ok(false));
+        // 整数のみの条件
+        assert_eq!(eval_str("1"), Ok(true));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(Cond::parse("").is_err());
+        assert!(Cond::parse("rdi ==").is_err());
+        assert!(Cond::parse("xmm0 == 1").is_err());
+        assert!(Cond::parse("(rdi == 1").is_err());
+        assert!(Cond::parse("rdi == 1)").is_err());
+        assert!(Cond::parse("1 < rdi < 3").is_err());
+        assert!(Cond::parse("rdi = 1").is_err());
+        assert!(Cond::parse("0xzz").is_err());
+        assert_eq!(Cond::parse("rdi == 5").unwrap().to_string(), "rdi == 5");
+    }
+}
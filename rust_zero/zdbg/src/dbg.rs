@@ -1,25 +1,109 @@
-use crate::helper::DynError;
+use crate::{
+    arch::{Arch, StepMode, X86_64},
+    backtrace::{self, Frame, Prologue},
+    cfi::{CfiTable, Regs},
+    cond::Cond,
+    deref::deref_chain,
+    disas::{self, Inst, MAX_INST_LEN},
+    dwarf::{LineTable, Location},
+    examine::{examine, Format},
+    heap::{self, HeapFn, HeapTracker},
+    help,
+    helper::DynError,
+    hwwatch::{self, HwWatch, WatchKind, NUM_SLOTS},
+    maps,
+    redirect::Redirects,
+    regs::{parse_value, reg_mut, REG_NAMES},
+    render::{Radix, Render},
+    session::Stop,
+    signals::{self, SignalTable},
+    source::{self, LIST_LINES},
+    stackcheck::StackChecker,
+    symbol::{Symbol, SymbolTable},
+    syscall::{self, Catches},
+    trace,
+    vars::{self, BaseKind, Place, Type, VarTable},
+    watch::{self, Watch, MAX_WATCH_LEN},
+};
 use nix::{
     libc::{ptrace, user_regs_struct},
     sys::{
         personality::{self, Persona},
         ptrace,
-        wait::{waitpid, WaitStatus},
+        signal::Signal,
+        wait::WaitStatus,
     },
     unistd::{execvp, fork, ForkResult, Pid},
 };
+use regex::Regex;
 use std::{
     ffi::{c_void, CString},
+    fs,
     ops::Not,
     rc::Rc,
+    time::Instant,
 };
 
+/// ブレークポイントの指定方法
+///
+/// SymbolとLineは実行ファイル上のアドレスを持ち、実行するたびにbiasを加えて実行時のアドレスを求める。
+/// Heapは共有ライブラリの関数であり、子プロセスの終了とともに削除する
+enum BreakSite {
+    Addr,                // アドレスで指定
+    Symbol(Symbol),      // 関数名かrbreakで指定した関数
+    Line(Location, u64), // ソースコード上の位置と、対応する実行ファイル上のアドレス
+    Heap(HeapFn),        // heap onで追跡するメモリの確保と解放の関数
+}
+
+impl BreakSite {
+    /// 実行ファイル上のアドレス。アドレスで指定した場合はNone
+    fn file_addr(&self) -> Option<u64> {
+        match self {
+            BreakSite::Addr | BreakSite::Heap(_) => None,
+            BreakSite::Symbol(sym) => Some(sym.addr),
+            BreakSite::Line(_, addr) => Some(*addr),
+        }
+    }
+}
+
+/// ブレークポイント
+struct Breakpoint {
+    id: usize,          // info breakpointsで表示し、deleteなどで指定する番号
+    addr: u64,          // 実行時のアドレス
+    enabled: bool,      // disableで無効にした場合は偽。int 3を書き込まない
+    site: BreakSite,    // 設定時の指定方法
+    orig: Option<u8>,   // int 3を書き込む前の1バイト。書き込んでいなければNone
+    cond: Option<Cond>, // break ... ifで指定した条件。偽の場合は停止せずに実行を続ける
+    temporary: bool,    // tbreakで設定した場合は真。最初に停止した時点で削除する
+}
+
 /// デバッガ内の情報
 pub struct DbgInfo {
     pid: Pid,
-    brk_addr: Option<*mut c_void>, // ブレークポイントのアドレス
-    brk_val: i64,                  // ブレークポイントを設定したメモリの元の値
-    filename: String,              // 実行ファイル
+    breaks: Vec<Breakpoint>,                  // ブレークポイントの一覧
+    next_break_id: usize,                     // 次に設定するブレークポイントの番号
+    filename: String,                         // 実行ファイル
+    lines: Option<LineTable>,                 // 行番号テーブル。デバッグ情報がない場合はNone
+    symbols: Option<SymbolTable>,             // 関数のシンボル。読み込めなかった場合はNone
+    cfi: Option<CfiTable>, // スタックの巻き戻しの規則。読み込めなかった場合はNone
+    vars: Option<VarTable>, // 引数とローカル変数。デバッグ情報がない場合はNone
+    bias: u64,             // 実行ファイル上のアドレスと実行時のアドレスの差
+    deref_depth: usize,    // レジスタやスタックの値の参照先を辿る段数
+    last_stop: Option<Stop>, // 直前のコマンドで発生した停止イベント
+    watches: Vec<Watch>,   // watchmemで監視中のメモリ領域
+    hw_watches: [Option<HwWatch>; NUM_SLOTS], // watchで設定したハードウェアウォッチポイント
+    sw_watches: Vec<Watch>, // スロットが足りない場合に設定したソフトウェアウォッチポイント
+    disas_count: usize,    // 停止するたびに逆アセンブルして表示する命令の個数。0の場合は表示しない
+    heap: HeapTracker,     // heap onで記録したメモリの確保と解放
+    arch: &'static dyn Arch, // ステップ実行の方法を決めるアーキテクチャ依存の処理
+    step_mode: StepMode,   // set stepで選んだステップ実行の方法
+    stack_check: StackChecker, // checkstackで記録したカナリアの位置と、自動で検査するか
+    aslr: bool,            // set aslr onの場合は真。runの際にASLRを無効にしない
+    args: Vec<String>,     // set argsかrunで指定した、子プロセスに渡す引数
+    signals: SignalTable,  // handleで設定したシグナルの扱い
+    pending_sig: Option<Signal>, // 停止の原因となったシグナル。passなら次の再開時に子プロセスに渡す
+    catches: Catches,      // catch syscallで捕捉するシステムコール
+    in_syscall: bool, // システムコールの入口で停止してPTRACE_SYSCALLで再開し、次の停止が出口となる場合は真
 }
 
 /// デバッガ
@@ -41,43 +125,555 @@ pub enum State {
     Exit,
 }
 
+impl State {
+    /// 直前のコマンドで発生した停止イベントを取り出す
+    pub fn take_stop(&mut self) -> Option<Stop> {
+        match self {
+            State::Running(r) => r.info.last_stop.take(),
+            State::NotRunning(n) => n.info.last_stop.take(),
+            State::Exit => None,
+        }
+    }
+
+    /// 子プロセスが停止した場合は、watchmemで監視中の領域の変更を検査し、次に実行する命令を表示
+    ///
+    /// checkstack onの場合は、スタックの破壊も検査する
+    fn after_stop(mut self) -> Self {
+        if let State::Running(r) = &mut self {
+            if r.info.last_stop.is_some() {
+                r.remove_temp_break();
+                r.check_watches();
+                if r.info.stack_check.auto {
+                    if let Err(e) = r.check_stack(false) {
+                        eprintln!("<<スタックの検査に失敗 : {e}>>");
+                    }
+                }
+                r.print_next_insts();
+            }
+        }
+        self
+    }
+}
+
 /// RunningとNotRunningで共通の実装
 impl<T> ZDbg<T> {
     /// ブレークポイントのアドレスを設定する関数。子プロセスのメモリ上には反映しない。
-    /// アドレス設定に成功した場合はtrueを返す。
-    fn set_break_addr(&mut self, cmd: &[&str]) -> bool {
-        if self.info.brk_addr.is_some() {
+    /// アドレス設定に成功した場合はそのアドレスを返す。
+    ///
+    /// 0xから始まらない場合は、ファイル名:行番号ならソースコード上の位置、それ以外は関数名とみなし、
+    /// 行番号テーブルかシンボルテーブルからアドレスを求める。
+    /// 位置に続けて`if 条件`を指定すると、条件付きブレークポイントとする。
+    /// tbreakの場合は、最初に停止した時点で削除する一時ブレークポイントとする
+    fn set_break_addr(&mut self, cmd: &[&str]) -> Option<u64> {
+        let cond = match cmd.get(2..) {
+            None | Some([]) => None,
+            Some(["if", expr @ ..]) if !expr.is_empty() => match Cond::parse(&expr.join(" ")) {
+                Ok(cond) => Some(cond),
+                Err(e) => {
+                    eprintln!("<<条件式が不正です : {e}>>");
+                    return None;
+                }
+            },
+            _ => {
+                eprintln!("<<条件はifに続けて指定してください\n例: break 0x401234 if rdi == 5>>");
+                return None;
+            }
+        };
+        let (site, desc) = match cmd.get(1) {
+            Some(arg) if !arg.starts_with("0x") => match parse_file_line(arg) {
+                Some((file, line)) => {
+                    let (loc, addr) = self.find_line(file, line)?;
+                    let desc = format!("{loc} ");
+                    (BreakSite::Line(loc, addr), desc)
+                }
+                None => {
+                    let sym = self.find_symbol(arg)?;
+                    let desc = format!("{} ", sym.name);
+                    (BreakSite::Symbol(sym), desc)
+                }
+            },
+            _ => (BreakSite::Addr, String::new()),
+        };
+        let addr = match site.file_addr() {
+            Some(addr) => addr + self.info.bias,
+            None => get_break_addr(cmd)? as u64,
+        };
+        if self.is_break(addr) {
+            eprintln!("<<ブレークポイントは設定済みです: Addr = {addr:#x}>>");
+            return None;
+        }
+        // ブレークポイントのアドレスを保存
+        let id = self.push_break(addr, site);
+        let temporary = cmd[0] == "tbreak";
+        let kind = if temporary {
+            "一時ブレークポイント"
+        } else {
+            "ブレークポイント"
+        };
+        match &cond {
+            Some(cond) => {
+                println!("<<{kind}{id}を設定しました : {desc}Addr = {addr:#x} if {cond}>>")
+            }
+            None => println!("<<{kind}{id}を設定しました : {desc}Addr = {addr:#x}>>"),
+        }
+        if let Some(b) = self.info.breaks.last_mut() {
+            b.cond = cond;
+            b.temporary = temporary;
+        }
+        Some(addr)
+    }
+
+    /// ソースファイルfileのline行目に対応する位置と、実行ファイル上のアドレスを返す
+    fn find_line(&self, file: &str, line: u64) -> Option<(Location, u64)> {
+        let Some(lines) = &self.info.lines else {
+            eprintln!("<<行番号情報がないため、ソースコード上の位置を検索できません>>");
+            return None;
+        };
+        match lines.find_addr(file, line) {
+            Some((addr, loc)) => {
+                if loc.line != line {
+                    println!(
+                        "<<{file}:{line}に対応する命令がないため、{}行目に設定します>>",
+                        loc.line
+                    );
+                }
+                Some((loc.clone(), addr))
+            }
+            None => {
+                eprintln!("<<{file}:{line}に対応する命令が見つかりません>>");
+                None
+            }
+        }
+    }
+
+    /// 名前がnameの関数のシンボルを返す
+    fn find_symbol(&self, name: &str) -> Option<Symbol> {
+        let Some(symbols) = &self.info.symbols else {
+            eprintln!("<<シンボル情報がないため、関数を検索できません>>");
+            return None;
+        };
+        let sym = symbols.find(name).cloned();
+        if sym.is_none() {
+            eprintln!("<<関数{name}が見つかりません>>");
+        }
+        sym
+    }
+
+    /// 実行時のアドレスaddrから始まる関数の名前を返す
+    fn symbol_name(&self, addr: u64) -> Option<&str> {
+        // rbreakなどで設定したブレークポイントは、別名ではなく設定時の名前を返す
+        let brk = self.info.breaks.iter().find(|b| b.addr == addr);
+        if let Some(BreakSite::Symbol(sym)) = brk.map(|b| &b.site) {
+            return Some(&sym.name);
+        }
+        self.info
+            .symbols
+            .as_ref()?
+            .name_at(addr.checked_sub(self.info.bias)?)
+    }
+
+    /// 実行時のアドレスaddrを、それを含む関数の名前と先頭からのオフセットで表す
+    fn symbol_offset(&self, addr: u64) -> Option<String> {
+        let sym = self
+            .info
+            .symbols
+            .as_ref()?
+            .containing(addr.checked_sub(self.info.bias)?)?;
+        match addr - self.info.bias - sym.addr {
+            0 => Some(sym.name.clone()),
+            off => Some(format!("{}+{off}", sym.name)),
+        }
+    }
+
+    /// ブレークポイントを一覧に追加し、その番号を返す
+    fn push_break(&mut self, addr: u64, site: BreakSite) -> usize {
+        let id = self.info.next_break_id;
+        self.info.next_break_id += 1;
+        self.info.breaks.push(Breakpoint {
+            id,
+            addr,
+            enabled: true,
+            site,
+            orig: None,
+            cond: None,
+            temporary: false,
+        });
+        id
+    }
+
+    /// delete、disable、enableコマンドで指定された番号のブレークポイントのアドレスを返す
+    fn find_break_by_id(&self, cmd: &[&str]) -> Option<u64> {
+        let [_, id] = cmd else {
             eprintln!(
-                "<<ブレークポイントは設定済みです: Addr = {:p}>>",
-                self.info.brk_addr.unwrap()
+                "<<ブレークポイントの番号を指定してください\n例: {} 1>>",
+                cmd[0]
             );
-            false
-        } else if let Some(addr) = get_break_addr(cmd) {
-            self.info.brk_addr = Some(addr); // ブレークポイントのアドレスを保存
-            true
-        } else {
-            false
+            return None;
+        };
+        let Ok(id) = id.parse::<usize>() else {
+            eprintln!("<<ブレークポイントの番号が不正です : {id}>>");
+            return None;
+        };
+        match self.info.breaks.iter().find(|b| b.id == id) {
+            Some(b) => Some(b.addr),
+            None => {
+                eprintln!("<<ブレークポイント{id}はありません>>");
+                None
+            }
+        }
+    }
+
+    /// addrのブレークポイントを有効(enabled = true)か無効にする。子プロセスのメモリ上には反映しない
+    fn set_break_enabled(&mut self, addr: u64, enabled: bool) {
+        if let Some(b) = self.info.breaks.iter_mut().find(|b| b.addr == addr) {
+            b.enabled = enabled;
+            let state = if enabled { "有効" } else { "無効" };
+            println!("<<ブレークポイント{}を{state}にしました>>", b.id);
+        }
+    }
+
+    /// addrのブレークポイントを一覧から削除する。子プロセスのメモリ上には反映しない
+    fn remove_break(&mut self, addr: u64) {
+        if let Some(i) = self.info.breaks.iter().position(|b| b.addr == addr) {
+            let b = self.info.breaks.remove(i);
+            println!("<<ブレークポイント{}を削除しました>>", b.id);
+        }
+    }
+
+    /// info breakpointsコマンドを実行し、ブレークポイントの一覧を表示
+    fn do_info_breaks(&self) {
+        if self.info.breaks.is_empty() {
+            println!("<<ブレークポイントは設定されていません>>");
+            return;
+        }
+        println!("番号 状態 アドレス           位置");
+        for b in self.info.breaks.iter() {
+            let state = if b.enabled { "有効" } else { "無効" };
+            let addr = format!("{:#x}", b.addr);
+            let mut line = match &b.site {
+                BreakSite::Addr => format!("{:<4} {state} {addr}", b.id),
+                BreakSite::Symbol(sym) => format!("{:<4} {state} {addr:<18} {}", b.id, sym.name),
+                BreakSite::Line(loc, _) => format!("{:<4} {state} {addr:<18} {loc}", b.id),
+                BreakSite::Heap(f) => format!("{:<4} {state} {addr:<18} {} (heap)", b.id, f.name()),
+            };
+            if b.temporary {
+                line.push_str(" (一時)");
+            }
+            if let Some(cond) = &b.cond {
+                line.push_str(&format!(" if {cond}"));
+            }
+            println!("{line}");
+        }
+    }
+
+    /// rbreakコマンドの正規表現に名前がマッチするすべての関数の先頭に、ブレークポイントを設定する。
+    /// 子プロセスのメモリ上には反映しない。設定したアドレスを返す
+    ///
+    /// 設定済みのアドレスは数えない。
+    /// 実行していない場合、PIEの関数のアドレスは実行ファイル上のアドレスを表示し、実行時に補正する
+    fn set_regex_break_addrs(&mut self, cmd: &[&str]) -> Vec<u64> {
+        let [_, pattern] = cmd else {
+            eprintln!("<<正規表現を指定してください\n例: rbreak ^parse_>>");
+            return Vec::new();
+        };
+        let re = match Regex::new(pattern) {
+            Ok(re) => re,
+            Err(e) => {
+                eprintln!("<<正規表現が不正です : {e}>>");
+                return Vec::new();
+            }
+        };
+        let Some(symbols) = &self.info.symbols else {
+            eprintln!("<<シンボル情報がないため、関数を検索できません>>");
+            return Vec::new();
+        };
+
+        let matched: Vec<Symbol> = symbols.matching(&re).into_iter().cloned().collect();
+        let mut addrs = Vec::new();
+        for sym in matched {
+            let addr = sym.addr + self.info.bias;
+            if self.is_break(addr) {
+                continue;
+            }
+            println!("  {addr:#x} {}", sym.name);
+            self.push_break(addr, BreakSite::Symbol(sym));
+            addrs.push(addr);
+        }
+        println!(
+            "<<{}個の関数にブレークポイントを設定しました>>",
+            addrs.len()
+        );
+        addrs
+    }
+
+    /// addrにブレークポイントが設定されていれば真
+    fn is_break(&self, addr: u64) -> bool {
+        self.info.breaks.iter().any(|b| b.addr == addr)
+    }
+
+    /// addrがheap onで追跡中の関数のブレークポイントなら、その関数を返す
+    fn heap_fn(&self, addr: u64) -> Option<HeapFn> {
+        self.info.breaks.iter().find_map(|b| match b.site {
+            BreakSite::Heap(f) if b.addr == addr => Some(f),
+            _ => None,
+        })
+    }
+
+    /// 引数なしのheapコマンドを実行し、解放されていない領域の一覧と合計を表示する
+    ///
+    /// 子プロセスの終了後も、最後に追跡した記録を表示できる
+    fn do_heap_list(&self) {
+        let heap = &self.info.heap;
+        if heap.is_empty() {
+            println!("<<記録はありません。heap onで追跡を開始してください>>");
+            return;
+        }
+        let (count, _) = heap.live_total();
+        if count > 0 {
+            println!("アドレス           バイト数 確保した位置");
+        }
+        for a in heap.live().take(MAX_HEAP_LIST) {
+            match self.symbol_offset(a.site) {
+                Some(name) => println!(
+                    "{:<18} {:>8} {:#x} ({name})",
+                    format!("{:#x}", a.addr),
+                    a.size,
+                    a.site
+                ),
+                None => println!(
+                    "{:<18} {:>8} {:#x}",
+                    format!("{:#x}", a.addr),
+                    a.size,
+                    a.site
+                ),
+            }
         }
+        if count > MAX_HEAP_LIST {
+            println!("... 他{}個", count - MAX_HEAP_LIST);
+        }
+        println!("<<{}>>", heap.summary());
     }
 
     /// 共通のコマンドを実行
-    fn do_cmd_common(&self, cmd: &[&str]) {
+    fn do_cmd_common(&mut self, cmd: &[&str]) {
         match cmd[0] {
-            "help" | "h" => do_help(),
+            "help" | "h" => help::do_help(cmd),
+            "set" => self.do_set(cmd),
+            "heap" => self.do_heap_list(),
+            "handle" => self.do_handle(cmd),
+            "catch" => self.do_catch(cmd),
+            "info" | "i" => match cmd.get(1) {
+                Some(&("breakpoints" | "break" | "b")) => self.do_info_breaks(),
+                Some(&("signals" | "signal")) => self.do_info_signals(cmd),
+                _ => eprintln!(
+                    "<<usage: info breakpoints | info signals [シグナル] | info proc maps>>"
+                ),
+            },
             _ => (),
         }
     }
+
+    /// catch syscallを実行し、指定したシステムコールの入口で停止するようにする
+    ///
+    /// システムコールを省略した場合は捕捉中のものを一覧表示し、clearですべて解除する
+    fn do_catch(&mut self, cmd: &[&str]) {
+        match cmd.get(1..) {
+            Some(["syscall"]) => {
+                if self.info.catches.is_empty() {
+                    println!("<<捕捉中のシステムコールはありません>>");
+                }
+                for nr in self.info.catches.iter() {
+                    println!("{nr:<4} {}", syscall::name(nr));
+                }
+            }
+            Some(["syscall", "clear"]) => {
+                self.info.catches.clear();
+                println!("<<すべてのシステムコールの捕捉を解除しました>>");
+            }
+            Some(["syscall", names @ ..]) => {
+                let nrs = match names
+                    .iter()
+                    .map(|s| syscall::parse(s))
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(nrs) => nrs,
+                    Err(e) => {
+                        eprintln!("<<{e}>>");
+                        return;
+                    }
+                };
+                for nr in nrs {
+                    if self.info.catches.add(nr) {
+                        println!("<<システムコール{}({nr})を捕捉します>>", syscall::name(nr));
+                    }
+                }
+            }
+            _ => eprintln!("<<usage: catch syscall [名前 | 番号]* | catch syscall clear>>"),
+        }
+    }
+
+    /// handleコマンドを実行し、シグナルを受信した際に停止するか、表示するか、子プロセスに渡すかを設定する
+    fn do_handle(&mut self, cmd: &[&str]) {
+        match self.info.signals.handle(&cmd[1..]) {
+            Ok(row) => print!("{}", signals::format_policies(&[row])),
+            Err(e) => {
+                eprintln!("<<{e}>>");
+                eprintln!("<<usage: handle シグナル [stop|nostop|print|noprint|pass|nopass]*>>");
+            }
+        }
+    }
+
+    /// info signalsを実行し、シグナルの扱いの一覧を表示する。シグナルを指定した場合はそのシグナルのみ表示する
+    fn do_info_signals(&self, cmd: &[&str]) {
+        let rows: Vec<_> = match cmd.get(2) {
+            Some(name) => match signals::parse_signal(name) {
+                Ok(sig) => vec![(sig, self.info.signals.get(sig))],
+                Err(e) => {
+                    eprintln!("<<{e}>>");
+                    return;
+                }
+            },
+            None => Signal::iterator()
+                .map(|sig| (sig, self.info.signals.get(sig)))
+                .collect(),
+        };
+        print!("{}", signals::format_policies(&rows));
+    }
+
+    /// checkstack on|offを実行し、停止するたびにスタックの破壊を検査するかを設定する
+    ///
+    /// 引数がonかoffでない場合は何もせずに偽を返す
+    fn set_check_stack_auto(&mut self, cmd: &[&str]) -> bool {
+        let auto = match cmd.get(1..) {
+            Some(["on"]) => true,
+            Some(["off"]) => false,
+            _ => return false,
+        };
+        self.info.stack_check.auto = auto;
+        println!(
+            "<<停止するたびにスタックを検査{}>>",
+            if auto { "します" } else { "しません" }
+        );
+        true
+    }
+
+    /// setコマンドを実行し、デバッガの設定を変更する
+    ///
+    /// - set deref-depth N : レジスタやスタックの値の参照先を辿る段数。0の場合は辿らない
+    /// - set disas-count N : 停止するたびに表示する命令の個数。0の場合は表示しない
+    /// - set debug ptrace on|off : ptraceとwaitpidの呼び出しを表示するか
+    /// - set aslr on|off : runの際にASLRを有効のままにするか。次のrunから反映する
+    /// - set args 引数* : 引数なしのrunで子プロセスに渡す引数。省略した場合は引数を消去する
+    ///
+    /// set regは実行中のみ有効で、ZDbg<Running>::do_set_regで処理する
+    fn do_set(&mut self, cmd: &[&str]) {
+        match cmd.get(1..) {
+            Some(["deref-depth", n]) => match n.parse::<usize>() {
+                Ok(n) if n <= MAX_DEREF_DEPTH => self.info.deref_depth = n,
+                _ => eprintln!("<<deref-depthは0から{MAX_DEREF_DEPTH}の整数で指定してください>>"),
+            },
+            Some(["deref-depth"]) => println!("deref-depth = {}", self.info.deref_depth),
+            Some(["disas-count", n]) => match n.parse::<usize>() {
+                Ok(n) if n <= MAX_DISAS_COUNT => self.info.disas_count = n,
+                _ => eprintln!("<<disas-countは0から{MAX_DISAS_COUNT}の整数で指定してください>>"),
+            },
+            Some(["disas-count"]) => println!("disas-count = {}", self.info.disas_count),
+            Some(["debug", "ptrace", "on"]) => trace::set_debug(true),
+            Some(["debug", "ptrace", "off"]) => trace::set_debug(false),
+            Some(["debug", "ptrace"]) => {
+                println!(
+                    "debug ptrace = {}",
+                    if trace::debug() { "on" } else { "off" }
+                )
+            }
+            Some(["step", mode]) => match StepMode::parse(mode) {
+                Some(mode) if mode == StepMode::Hardware && !self.info.arch.has_hw_step() => {
+                    eprintln!(
+                        "<<このアーキテクチャではハードウェアのステップ実行を利用できません>>"
+                    )
+                }
+                Some(mode) => self.info.step_mode = mode,
+                None => eprintln!("<<stepはauto、hw、swのいずれかで指定してください>>"),
+            },
+            Some(["step"]) => println!("step = {}", self.info.step_mode),
+            Some(["aslr", "on"]) => self.info.aslr = true,
+            Some(["aslr", "off"]) => self.info.aslr = false,
+            Some(["aslr"]) => println!("aslr = {}", if self.info.aslr { "on" } else { "off" }),
+            Some(["args", args @ ..]) => {
+                self.info.args = args.iter().map(|s| s.to_string()).collect();
+                if args.is_empty() {
+                    println!("<<引数を消去しました>>");
+                } else {
+                    println!("<<次のrunから引数 {} を渡します>>", args.join(" "));
+                }
+            }
+            Some(["reg", ..]) => eprintln!("<<レジスタは実行中のみ変更できます>>"),
+            _ => {
+                eprintln!("<<usage: set deref-depth N | set disas-count N | set step auto|hw|sw | set aslr on|off | set args 引数* | set debug ptrace on|off | set reg レジスタ 値>>")
+            }
+        }
+    }
 }
 
 /// NotRunning時に呼び出し可能なメソッド
 impl ZDbg<NotRunning> {
     pub fn new(filename: String) -> Self {
+        let lines = match LineTable::load(&filename) {
+            Ok(lines) => Some(lines),
+            Err(e) => {
+                eprintln!("<<行番号情報の読み込みに失敗 : {e}>>");
+                None
+            }
+        };
+        let symbols = match SymbolTable::load(&filename) {
+            Ok(symbols) => Some(symbols),
+            Err(e) => {
+                eprintln!("<<シンボル情報の読み込みに失敗 : {e}>>");
+                None
+            }
+        };
+        let cfi = match CfiTable::load(&filename) {
+            Ok(cfi) => Some(cfi),
+            Err(e) => {
+                eprintln!("<<CFIの読み込みに失敗 : {e}>>");
+                None
+            }
+        };
+        let vars = match VarTable::load(&filename) {
+            Ok(vars) => Some(vars),
+            Err(e) => {
+                eprintln!("<<変数情報の読み込みに失敗 : {e}>>");
+                None
+            }
+        };
+
         ZDbg {
             info: Box::new(DbgInfo {
                 pid: Pid::from_raw(0),
-                brk_addr: None,
-                brk_val: 0,
+                breaks: Vec::new(),
+                next_break_id: 1,
                 filename,
+                lines,
+                symbols,
+                cfi,
+                vars,
+                bias: 0,
+                deref_depth: DEFAULT_DEREF_DEPTH,
+                last_stop: None,
+                watches: Vec::new(),
+                hw_watches: Default::default(),
+                sw_watches: Vec::new(),
+                disas_count: DEFAULT_DISAS_COUNT,
+                heap: HeapTracker::default(),
+                arch: &X86_64,
+                step_mode: StepMode::Auto,
+                stack_check: StackChecker::default(),
+                aslr: false,
+                args: Vec::new(),
+                signals: SignalTable::default(),
+                pending_sig: None,
+                catches: Catches::default(),
+                in_syscall: false,
             }),
             _state: NotRunning,
         }
@@ -89,12 +685,39 @@ impl ZDbg<NotRunning> {
         }
 
         match cmd[0] {
-            "run" | "r" => return self.do_run(cmd),
-            "break" | "b" => {
+            "run" | "r" => return self.do_run(cmd).map(State::after_stop),
+            "break" | "b" | "tbreak" => {
                 self.do_break(cmd);
             }
+            "rbreak" => {
+                self.set_regex_break_addrs(cmd);
+            }
+            "delete" | "d" => {
+                if let Some(addr) = self.find_break_by_id(cmd) {
+                    self.remove_break(addr);
+                }
+            }
+            "disable" | "enable" => {
+                if let Some(addr) = self.find_break_by_id(cmd) {
+                    self.set_break_enabled(addr, cmd[0] == "enable");
+                }
+            }
             "exit" => return Ok(State::Exit),
-            "continue" | "c" | "stepi" | "s" | "registers" | "regs" => {
+            "continue" | "c" | "stepi" | "s" | "nexti" | "ni" | "finish" | "step" | "next"
+            | "n" | "registers" | "regs" | "tls" | "watchmem" | "watch" | "disas" | "backtrace"
+            | "bt" | "list" | "l" | "print" | "p" => {
+                eprintln!("<<ターゲットを実行していません。runで実行してください>>")
+            }
+            "heap" if cmd.len() > 1 => {
+                eprintln!("<<ターゲットを実行していません。runで実行してください>>")
+            }
+            "info" | "i" if cmd.get(1) == Some(&"proc") => {
+                eprintln!("<<ターゲットを実行していません。runで実行してください>>")
+            }
+            "checkstack" if !self.set_check_stack_auto(cmd) => {
+                eprintln!("<<ターゲットを実行していません。runで実行してください>>")
+            }
+            x if is_examine(x) || is_stack(x) => {
                 eprintln!("<<ターゲットを実行していません。runで実行してください>>")
             }
             _ => self.do_cmd_common(cmd),
@@ -105,14 +728,68 @@ impl ZDbg<NotRunning> {
 
     /// ブレークポイントを設定
     fn do_break(&mut self, cmd: &[&str]) -> bool {
-        self.set_break_addr(cmd)
+        self.set_break_addr(cmd).is_some()
+    }
+
+    /// 前回の実行時にアドレスで指定したブレークポイントのうち、実行ファイルの関数内のものを
+    /// 今回のロードアドレスに合わせて補正する
+    ///
+    /// ASLRが有効な場合はPIEのロードアドレスが実行するたびに変わるため、prev_biasとの差だけずらす。
+    /// 共有ライブラリなど、実行ファイルの外のアドレスは補正できないため、そのままとする
+    fn rebase_addr_breaks(&mut self, prev_bias: u64) {
+        let bias = self.info.bias;
+        if prev_bias == 0 || prev_bias == bias {
+            return;
+        }
+        let Some(symbols) = &self.info.symbols else {
+            return;
+        };
+        for b in self.info.breaks.iter_mut() {
+            if !matches!(b.site, BreakSite::Addr) {
+                continue;
+            }
+            let Some(file_addr) = b
+                .addr
+                .checked_sub(prev_bias)
+                .filter(|addr| symbols.containing(*addr).is_some())
+            else {
+                continue;
+            };
+            let addr = file_addr + bias;
+            println!(
+                "<<ブレークポイント{}のアドレスを補正しました : {:#x} -> {addr:#x}>>",
+                b.id, b.addr
+            );
+            b.addr = addr;
+        }
     }
 
     /// 子プロセスを生成し、成功した場合はRunning状態に遷移
+    ///
+    /// runに引数を指定した場合はそれを記録し、省略した場合は前回のrunかset argsの引数を使う。
+    /// 引数に含めた`< ファイル`や`> ファイル`は、子プロセスの標準入出力のリダイレクトとなる
     fn do_run(mut self, cmd: &[&str]) -> Result<State, DynError> {
-        // 子プロセスに渡すコマンドライン引数
+        if cmd.len() > 1 {
+            self.info.args = cmd[1..].iter().map(|s| s.to_string()).collect();
+        } else if !self.info.args.is_empty() {
+            println!("<<引数 : {}>>", self.info.args.join(" "));
+        }
+        // リダイレクト先はforkの前に開き、開けない場合は実行しない
+        let (args, redirects) = match Redirects::parse(&self.info.args)
+            .and_then(|(args, redirects)| Ok((args, redirects.open()?)))
+        {
+            Ok(opened) => opened,
+            Err(e) => {
+                eprintln!("<<リダイレクトに失敗 : {e}>>");
+                return Ok(State::NotRunning(self));
+            }
+        };
+        // 子プロセスに渡すコマンドライン引数。argv[0]は実行ファイル名とする
         // execvpへはCStringの文字列を渡す必要があるため、ここで変換している
-        let args: Vec<CString> = cmd.iter().map(|s| CString::new(*s).unwrap()).collect();
+        let args: Vec<CString> = std::iter::once(&self.info.filename)
+            .chain(args.iter())
+            .map(|s| CString::new(s.as_str()).unwrap())
+            .collect();
 
         match unsafe { fork()? } {
             ForkResult::Child => {
@@ -123,10 +800,19 @@ impl ZDbg<NotRunning> {
                 // デバッグ時には不便なため、ここでオフにする。
                 // Linuxではセキュリティ上の理由から、可能な場合はASLRを適用している
                 // ASLRは、Return-to-libc攻撃といった攻撃手法による被害を軽減させる目的で導入された。
+                // ただし、配置に依存する不具合を再現するため、set aslr onの場合は有効にする。
+                // personalityは子プロセスに継承されるため、デバッガ自身が無効で起動された場合も明示的に戻す
                 let p = personality::get().unwrap();
                 println!("before personality {:?}", p);
-                personality::set(p | Persona::ADDR_NO_RANDOMIZE).unwrap();
+                if self.info.aslr {
+                    personality::set(p - Persona::ADDR_NO_RANDOMIZE).unwrap();
+                } else {
+                    personality::set(p | Persona::ADDR_NO_RANDOMIZE).unwrap();
+                }
                 println!("after personality {:?}", p);
+                // 標準入出力をリダイレクト先のファイルに置き換える
+                // デバッガの端末を子プロセスの入出力で使わないようにするため
+                redirects.apply().unwrap();
                 // 自身がデバッガによるトレース対象であることを指定する
                 // tracemeを指定したあとは、execすると即座にプロセスが停止するようになる
                 // nix::sys::ptraceにはシステムコールのptrace関数のラッパが多く定義されている
@@ -137,10 +823,25 @@ impl ZDbg<NotRunning> {
             }
             // 親プロセスは、waitpidで子プロセスが停止するのを待つ。
             // 子プロセスでtracemeを呼び出しているため、子プロセスは停止、もしくは終了するはずである。
-            ForkResult::Parent { child, .. } => match waitpid(child, None)? {
+            ForkResult::Parent { child, .. } => match trace::waitpid(child, None)? {
                 WaitStatus::Stopped(..) => {
                     println!("<<子プロセスの実行に成功しました : PID = {child}>>");
                     self.info.pid = child;
+                    // システムコールによる停止を、SIGTRAPによる停止と区別できるようにする
+                    trace::setoptions(child, ptrace::Options::PTRACE_O_TRACESYSGOOD)?;
+                    let prev_bias = self.info.bias;
+                    self.info.bias = load_bias(child, &self.info.filename);
+                    self.info.stack_check.reset();
+                    if self.info.bias != 0 {
+                        println!("<<PIEのロードアドレス : {:#x}>>", self.info.bias);
+                    }
+                    // 関数名やソースコード上の位置で設定したアドレスを、実行時のアドレスに補正
+                    for b in self.info.breaks.iter_mut() {
+                        if let Some(addr) = b.site.file_addr() {
+                            b.addr = addr + self.info.bias;
+                        }
+                    }
+                    self.rebase_addr_breaks(prev_bias);
                     // ZDbg<Running>の値を生成して状態遷移を実現
                     let mut dbg = ZDbg::<Running> {
                         info: self.info,
@@ -149,7 +850,7 @@ impl ZDbg<NotRunning> {
                     // ブレークポイントを子プロセスのメモリ上に実際に設定
                     // ブレークポイントはプロセスの実行中にしか行えないため、
                     // この時点でブレークポイントを設定している
-                    dbg.set_break()?;
+                    dbg.set_breaks()?;
                     // 子プロセスの実行を再開
                     dbg.do_continue()
                 }
@@ -170,16 +871,41 @@ impl ZDbg<Running> {
         }
 
         match cmd[0] {
-            "break" | "b" => self.do_break(cmd)?,
-            "continue" | "c" => return self.do_continue(),
+            "break" | "b" | "tbreak" => self.do_break(cmd)?,
+            "rbreak" => self.do_rbreak(cmd)?,
+            "delete" | "d" => self.do_delete(cmd)?,
+            "disable" => self.do_enable(cmd, false)?,
+            "enable" => self.do_enable(cmd, true)?,
+            "continue" | "c" => return self.do_continue().map(State::after_stop),
             "registers" | "regs" => {
                 // レジスタ情報の取得
                 // Cのptrace(PTRACE_GETREGS, pid, 0, &struct)に相当
                 // &structはレジスタ情報おw保存する構造体へのポインタであり、結果がこれに格納される
-                let regs = ptrace::getregs(self.info.pid)?;
+                let regs = trace::getregs(self.info.pid)?;
                 print_regs(&regs); // 取得した情報を表示する
+                self.print_regs_deref(&regs);
             }
-            "stepi" | "s" => return self.do_stepi(),
+            "set" if cmd.get(1) == Some(&"reg") => self.do_set_reg(cmd)?,
+            "tls" => self.do_tls(cmd)?,
+            x if is_stack(x) => self.do_stack(cmd)?,
+            "stepi" | "s" => return self.do_stepi().map(State::after_stop),
+            "nexti" | "ni" => return self.do_nexti().map(State::after_stop),
+            "finish" => return self.do_finish().map(State::after_stop),
+            "step" => return self.do_step_line(false).map(State::after_stop),
+            "next" | "n" => return self.do_step_line(true).map(State::after_stop),
+            "watchmem" => self.do_watchmem(cmd)?,
+            "watch" => self.do_watch(cmd)?,
+            "disas" => self.do_disas(cmd)?,
+            "backtrace" | "bt" => self.do_backtrace()?,
+            "list" | "l" => self.do_list()?,
+            "print" | "p" => self.do_print(cmd)?,
+            "heap" => self.do_heap(cmd)?,
+            "info" | "i" if cmd.get(1) == Some(&"proc") => self.do_info_proc(cmd)?,
+            "checkstack" if !self.set_check_stack_auto(cmd) => match cmd.len() {
+                1 => self.check_stack(true)?,
+                _ => eprintln!("<<usage: checkstack [on | off]>>"),
+            },
+            x if is_examine(x) => self.do_examine(cmd)?,
             "run" | "r" => eprintln!("<<すでに実行中です>>"),
             "exit" => {
                 self.do_exit()?; // 子プロセスを終了させる
@@ -195,8 +921,8 @@ impl ZDbg<Running> {
     fn do_exit(self) -> Result<(), DynError> {
         loop {
             // SIGKILLシグナルを子プロセスに送信する
-            ptrace::kill(self.info.pid)?;
-            match waitpid(self.info.pid, None)? {
+            trace::kill(self.info.pid)?;
+            match trace::waitpid(self.info.pid, None)? {
                 WaitStatus::Exited(..) | WaitStatus::Signaled(..) => return Ok(()),
                 _ => (),
             }
@@ -205,24 +931,89 @@ impl ZDbg<Running> {
 
     /// breakを実行
     fn do_break(&mut self, cmd: &[&str]) -> Result<(), DynError> {
-        if self.set_break_addr(cmd) {
-            self.set_break()?;
+        if let Some(addr) = self.set_break_addr(cmd) {
+            self.set_break(addr, true)?;
+        }
+        Ok(())
+    }
+
+    /// rbreakを実行
+    fn do_rbreak(&mut self, cmd: &[&str]) -> Result<(), DynError> {
+        for addr in self.set_regex_break_addrs(cmd) {
+            self.set_break(addr, false)?;
+        }
+        Ok(())
+    }
+
+    /// deleteを実行
+    ///
+    /// int 3を書き込んでいる場合は、元の値に戻してから削除する
+    fn do_delete(&mut self, cmd: &[&str]) -> Result<(), DynError> {
+        if let Some(addr) = self.find_break_by_id(cmd) {
+            self.write_break(addr, false)?;
+            self.remove_break(addr);
+        }
+        Ok(())
+    }
+
+    /// enable(enabled = true)かdisableを実行
+    ///
+    /// 無効にする場合は元の値に戻し、有効にする場合はint 3を書き込む
+    fn do_enable(&mut self, cmd: &[&str], enabled: bool) -> Result<(), DynError> {
+        if let Some(addr) = self.find_break_by_id(cmd) {
+            if !enabled {
+                self.write_break(addr, false)?;
+            }
+            self.set_break_enabled(addr, enabled);
+            if enabled {
+                self.write_break(addr, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 一覧の有効なブレークポイントを実際に設定
+    ///
+    /// rbreakで設定した関数のブレークポイントは数が多くなるため、書き換えるメモリの値は表示しない
+    fn set_breaks(&mut self) -> Result<(), DynError> {
+        let addrs: Vec<(u64, bool)> = self
+            .info
+            .breaks
+            .iter()
+            .filter(|b| b.enabled)
+            .map(|b| {
+                (
+                    b.addr,
+                    matches!(b.site, BreakSite::Addr | BreakSite::Line(..)),
+                )
+            })
+            .collect();
+        for (addr, verbose) in addrs {
+            self.set_break(addr, verbose)?;
         }
         Ok(())
     }
 
     /// ブレークポイントを実際に設定
     /// つまり、該当アドレスのメモリを"int 3" = 0xccに設定
-    fn set_break(&mut self) -> Result<(), DynError> {
-        let addr = if let Some(addr) = self.info.brk_addr {
-            addr
-        } else {
+    ///
+    /// verboseが真なら、書き換える前と後のメモリの値を表示する
+    fn set_break(&mut self, brk_addr: u64, verbose: bool) -> Result<(), DynError> {
+        let addr = brk_addr as *mut c_void;
+
+        // int 3を書き込む前に、実行可能な領域のアドレスかを検査
+        // データ領域に書き込むと値を壊してしまうため、その場合はブレークポイントを解除する
+        if let Err(msg) = maps::read_maps(self.info.pid)
+            .and_then(|regions| maps::check_break_addr(&regions, brk_addr))
+        {
+            eprintln!("<<ブレークポイントを設定できません : {msg}>>");
+            self.info.breaks.retain(|b| b.addr != brk_addr);
             return Ok(());
-        };
+        }
 
         // ブレークするアドレスにあるメモリ上の値を取得
         // メモリの値はi64型で返される。つまり、8バイト単位で取得できる。
-        let val = match ptrace::read(self.info.pid, addr) {
+        let val = match trace::read(self.info.pid, addr) {
             Ok(val) => val,
             Err(e) => {
                 eprintln!("<<ptrace::readに失敗 : {e}, addr = {:p}>>", addr);
@@ -238,10 +1029,12 @@ impl ZDbg<Running> {
             }
         }
 
-        println!("<<以下のようにメモリを書き換えます>>");
-        print!("<<before : "); // もとの値を表示
-        print_val(addr as usize, val);
-        println!(">>");
+        if verbose {
+            println!("<<以下のようにメモリを書き換えます>>");
+            print!("<<before : "); // もとの値を表示
+            print_val(addr as usize, val);
+            println!(">>");
+        }
 
         // "int 3"に設定
         // "int 3"はソフトウェア割り込みを発生させるx86_64の命令
@@ -254,16 +1047,21 @@ impl ZDbg<Running> {
         // その後、0xccとビット和を取ると、下位8ビットが0xccとなる
         // x86_64はリトルエンディアンを用いるため、下位ビットを書き換えている
         let val_int3 = (val & !0xff) | 0xcc;
-        print!("<<after : "); // 変更後の値を表示
-        print_val(addr as usize, val_int3);
-        println!(">>");
+        if verbose {
+            print!("<<after : "); // 変更後の値を表示
+            print_val(addr as usize, val_int3);
+            println!(">>");
+        }
 
         // "int 3"をメモリに書き込み
         // as *mut c_voidと型変換しているのは、ptrace::write、つまり、Cのptraceが引数にポインタを取るためである
-        match unsafe { ptrace::write(self.info.pid, addr, val_int3 as *mut c_void) } {
+        match unsafe { trace::write(self.info.pid, addr, val_int3 as *mut c_void) } {
             Ok(_) => {
-                self.info.brk_addr = Some(addr);
-                self.info.brk_val = val; // 元の値を保持
+                // 元の値を保持
+                // 同じ8バイトに複数のブレークポイントがあっても壊さないように、書き換えた1バイトのみ保持する
+                if let Some(b) = self.info.breaks.iter_mut().find(|b| b.addr == brk_addr) {
+                    b.orig = Some(val as u8);
+                }
             }
             Err(e) => {
                 eprintln!("<<ptrace::writeに失敗 : {e}, addr = {:p}>>", addr);
@@ -281,22 +1079,25 @@ impl ZDbg<Running> {
     /// プロセスを正常に再開させるためには、int 3に書き換えた箇所の復元と、プログラムカウンタを-1にする必要がある
     ///
     /// 1. continueコマンドが実行される
-    /// 2. 機械語レベルで1ステップ実行　<step_and_brek関数> (前回のwait_childでbrk_addrのメモリの値はもとの値に復元済みなので普通に実行できる)
-    /// 3. ブレークポイントを再設定 <step_and_brek関数> (brk_addrの値をint 3に再設定)
+    /// 2. 機械語レベルで1ステップ実行　<step_and_brek関数> (前回のwait_childでブレークポイントのメモリの値はもとの値に復元済みなので普通に実行できる)
+    /// 3. ブレークポイントを再設定 <step_and_brek関数> (ブレークポイントの値をint 3に再設定)
     /// 4. ptrace::contを呼び出し、子プロセスを再開 <step_and_brek関数>
     /// 5. waitpidで子プロセス停止を待ち、ブレークポイントで停止する <wait_child関数>
     /// 6. ブレークポイントを設定した番地のメモリの値を、元の値に復元 <wait_child関数> (次のstep_and_brek実行時に元の命令が実行されるようにする)
-    /// 7. プログラムカウンタを-1する。 <wait_child関数> (プログラムカウンタがbrk_addrの+1を指しているので)
+    /// 7. プログラムカウンタを-1する。 <wait_child関数> (プログラムカウンタがブレークポイントの+1を指しているので)
     /// 8. 1に戻る
     fn do_continue(self) -> Result<State, DynError> {
         // ブレークポイントで停止していた場合は1ステップ実行後再設定
         match self.step_and_break()? {
-            State::Running(r) => {
+            State::Running(r) if !r.info.sw_watches.is_empty() => r.cont_by_step(),
+            State::Running(mut r) => {
                 // 実行再開
                 // ptrace::contで子プロセスを再開させる
                 // ptrace::contの第２引数には、再開時に送信するシグナルを指定可能
                 // Noneを指定した場合はシグナルは送信されない
-                ptrace::cont(r.info.pid, None)?;
+                hwwatch::clear_hits(r.info.pid)?;
+                let sig = r.resume_sig();
+                r.resume(sig)?;
                 r.wait_child()
             }
             n => Ok(n),
@@ -307,119 +1108,1866 @@ impl ZDbg<Running> {
     /// 1ステップ実行しブレークポイントを再設定
     /// これは、ブレークポインが揮発してしまうのを防ぐための操作
     /// ブレークポイントを再設定しないと、ループなどで再び同じコードが時刻された場合に停止しなくなってしまう
+    ///
+    /// 実行のウォッチポイントで停止していた場合も、命令の実行前に停止し続けないように、
+    /// そのスロットを無効にして1ステップ実行してから有効に戻す
     fn step_and_break(mut self) -> Result<State, DynError> {
-        let regs = ptrace::getregs(self.info.pid)?; // レジスタ取得
-        if Some((regs.rip) as *mut c_void) == self.info.brk_addr {
-            // プログラムカウンタを意味するripがブレークポイントのアドレスかチェック
-            ptrace::step(self.info.pid, None)?; // 機械語レベルで1ステップ実行
-            match waitpid(self.info.pid, None)? {
-                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
-                    println!("<<子プロセスが終了しました>>");
-                    return Ok(State::NotRunning(ZDbg::<NotRunning> {
-                        info: self.info,
-                        _state: NotRunning,
-                    }));
-                }
-                _ => (),
+        let regs = trace::getregs(self.info.pid)?; // レジスタ取得
+                                                   // プログラムカウンタを意味するripがブレークポイントのアドレスかチェック
+        let on_break = self.is_break(regs.rip);
+        let on_exec = self.mask_exec_watch(regs.rip)?;
+        if on_break || on_exec {
+            self.write_break(regs.rip, false)?;
+            let status = self.single_step()?; // 機械語レベルで1ステップ実行
+            if let Some(stop) = Stop::from_exit(&status) {
+                self.info.last_stop = Some(stop);
+                return Ok(self.into_not_running());
+            }
+            self.write_break(regs.rip, true)?; // 再度ブレークポイントを設定
+            if on_exec {
+                self.apply_hw_watches()?;
             }
-            self.set_break()?; // 再度ブレークポイントを設定
         }
         Ok(State::Running(self))
     }
 
-    /// 子プロセスをwait. 子プロセスが終了した場合はNotRunning状態に遷移
-    fn wait_child(self) -> Result<State, DynError> {
-        match waitpid(self.info.pid, None)? {
-            WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
-                println!("<<子プロセスが終了しました>>");
-                let not_run = ZDbg::<NotRunning> {
-                    info: self.info,
-                    _state: NotRunning,
-                };
-                Ok(State::NotRunning(not_run))
-            }
-            WaitStatus::Stopped(..) => {
-                // 子プロセスが停止した場合
-                let mut regs = ptrace::getregs(self.info.pid)?;
-                if Some((regs.rip - 1) as *mut c_void) == self.info.brk_addr {
-                    // ブレークポイントで停止した場合
-                    // 書き換えたメモリをもとの値に戻す
-                    unsafe {
-                        ptrace::write(
-                            self.info.pid,
-                            self.info.brk_addr.unwrap(),
-                            self.info.brk_val as *mut c_void,
-                        )?
-                    };
-
-                    // ブレークポイントで停止したアドレスから１つ戻す
-                    regs.rip -= 1;
-                    ptrace::setregs(self.info.pid, regs)?;
-                }
-                println!("<<子プロセスが停止しました : PC = {:#x}>>", regs.rip);
-                Ok(State::Running(self))
-            }
-            _ => Err("waitpidの返り値が不正です".into()),
+    /// 子プロセスの実行を再開する
+    ///
+    /// catch syscallで捕捉するシステムコールがある場合は、システムコールの入口と出口で停止するよう
+    /// PTRACE_SYSCALLで再開し、wait_childで捕捉するものかを判定する。
+    /// PTRACE_CONTで再開した場合は出口で停止しないため、システムコールの途中ではなくなる
+    fn resume(&mut self, sig: Option<Signal>) -> nix::Result<()> {
+        if self.info.catches.is_empty() {
+            self.info.in_syscall = false;
+            trace::cont(self.info.pid, sig)
+        } else {
+            trace::syscall(self.info.pid, sig)
         }
     }
 
-    /// stepiコマンドを実行する
-    /// 機械語レベルで1ステップ実行を行うメソッド
-    fn do_stepi(mut self) -> Result<State, DynError> {
-        let regs = ptrace::getregs(self.info.pid)?;
-        if Some((regs.rip) as *mut c_void) == self.info.brk_addr {
-            // ブレークポイントで停止した場合は、そのメモリの値が0xccとなっている
-            // 可能性があるため、もとの値に復元する
-            unsafe {
-                ptrace::write(
-                    self.info.pid,
-                    self.info.brk_addr.unwrap(),
-                    self.info.brk_val as *mut c_void,
-                )?
+    /// 停止の原因となったシグナルのうち、再開時に子プロセスに渡すものを取り出す
+    ///
+    /// handleで停止後に扱いを変更した場合も反映されるよう、再開する時点のpassで判定する
+    fn resume_sig(&mut self) -> Option<Signal> {
+        let sig = self.info.pending_sig.take()?;
+        self.info.signals.get(sig).pass.then_some(sig)
+    }
+
+    /// シグナルsigを受信して停止した際に、handleの設定に従って表示する
+    ///
+    /// 停止しない設定なら、受信を記録して真を返す。呼び出し側は停止せずに再開する
+    fn on_signal(&mut self, sig: Signal, rip: u64) -> bool {
+        let policy = self.info.signals.get(sig);
+        self.info.pending_sig = Some(sig);
+        if policy.print {
+            let action = if policy.pass {
+                "渡します"
+            } else {
+                "渡しません"
             };
-            // regs.rip -= 1;
-            // ptrace::setregs(self.info.pid, regs)?;
-            ptrace::step(self.info.pid, None)?; // 機械語レベルで1ステップ実行
-            match waitpid(self.info.pid, None)? {
-                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
-                    println!("<<子プロセスが終了しました>>");
-                    return Ok(State::NotRunning(ZDbg::<NotRunning> {
-                        info: self.info,
-                        _state: NotRunning,
-                    }));
+            println!("<<シグナル{sig}を受信しました : PC = {rip:#x}。子プロセスに{action}>>");
+        }
+        !policy.stop
+    }
+
+    /// 子プロセスをwait. 子プロセスが終了した場合はNotRunning状態に遷移
+    ///
+    /// heap onで追跡中の関数のブレークポイントでは停止せず、記録して実行を再開する。
+    /// handleで停止しないとしたシグナルでは、必要なら子プロセスに渡して実行を再開する
+    fn wait_child(mut self) -> Result<State, DynError> {
+        loop {
+            match trace::waitpid(self.info.pid, None)? {
+                status @ (WaitStatus::Exited(..) | WaitStatus::Signaled(..)) => {
+                    self.info.last_stop = Stop::from_exit(&status);
+                    return Ok(self.into_not_running());
                 }
-                _ => (),
-            }
-            self.set_break()?;
-        } else {
-            ptrace::step(self.info.pid, None)?; // 機械語レベルで1ステップ実行
-            match waitpid(self.info.pid, None)? {
-                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
-                    println!("<<子プロセスが終了しました>>");
-                    return Ok(State::NotRunning(ZDbg::<NotRunning> {
-                        info: self.info,
-                        _state: NotRunning,
-                    }));
+                WaitStatus::Stopped(_, sig) => {
+                    // 子プロセスが停止した場合
+                    // システムコール以外の理由で停止したため、次のシステムコールの停止は入口となる
+                    self.info.in_syscall = false;
+                    self.check_breaks();
+                    let mut regs = trace::getregs(self.info.pid)?;
+                    if self.is_inserted_break(regs.rip - 1) {
+                        // ブレークポイントで停止した場合
+                        // 書き換えたメモリをもとの値に戻す
+                        self.write_break(regs.rip - 1, false)?;
+
+                        // ブレークポイントで停止したアドレスから１つ戻す
+                        regs.rip -= 1;
+                        trace::setregs(self.info.pid, regs)?;
+                        if let Some(f) = self.heap_fn(regs.rip) {
+                            match self.record_heap(f, &regs)? {
+                                HeapHit::Recorded => {
+                                    hwwatch::clear_hits(self.info.pid)?;
+                                    self.resume(None)?;
+                                    continue;
+                                }
+                                HeapHit::Stopped => regs = trace::getregs(self.info.pid)?,
+                                HeapHit::Exited => return Ok(self.into_not_running()),
+                            }
+                        }
+                        if self.skip_break(regs.rip)? {
+                            // 条件が偽なら、元の命令を実行してブレークポイントを再設定し、停止せずに再開する
+                            if !self.step_inst()? {
+                                return Ok(self.into_not_running());
+                            }
+                            hwwatch::clear_hits(self.info.pid)?;
+                            self.resume(None)?;
+                            continue;
+                        }
+                        self.info.last_stop = Some(Stop::Break(regs.rip));
+                    } else {
+                        let watch = match sig {
+                            Signal::SIGTRAP => self.report_hw_watches()?,
+                            _ => None,
+                        };
+                        if watch.is_none() && self.on_signal(sig, regs.rip) {
+                            let sig = self.resume_sig();
+                            self.resume(sig)?;
+                            continue;
+                        }
+                        self.info.last_stop = Some(match watch {
+                            Some(addr) => Stop::Watch(addr, regs.rip),
+                            None => Stop::Signal(sig, regs.rip),
+                        });
+                    }
+                    self.print_stopped(regs.rip)?;
+                    return Ok(State::Running(self));
                 }
-                _ => (),
+                WaitStatus::PtraceSyscall(_) => {
+                    // 捕捉するシステムコールの入口でのみ停止し、出口やそれ以外では再開する
+                    // 入口と出口の停止は区別できないため、停止するたびに入れ替えて追跡する。
+                    // 入口のraxは-ENOSYSだが、-ENOSYSを返したシステムコールの出口も同じ値になる
+                    let entry = !self.info.in_syscall;
+                    self.info.in_syscall = entry;
+                    let regs = trace::getregs(self.info.pid)?;
+                    if !entry || !self.info.catches.contains(regs.orig_rax) {
+                        self.resume(None)?;
+                        continue;
+                    }
+                    let pid = self.info.pid;
+                    let read = |addr: u64| {
+                        trace::read(pid, addr as *mut c_void)
+                            .ok()
+                            .map(|val| val as u64)
+                    };
+                    let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+                    println!(
+                        "<<システムコールを呼び出します : {}>>",
+                        syscall::format_call(regs.orig_rax, &args, read)
+                    );
+                    self.info.last_stop = Some(Stop::Syscall(regs.orig_rax, regs.rip));
+                    self.print_stopped(regs.rip)?;
+                    return Ok(State::Running(self));
+                }
+                _ => return Err("waitpidの返り値が不正です".into()),
             }
         }
-        Ok(State::Running(self))
+    }
+
+    /// heap onで追跡中の関数fの先頭で停止した際に、引数と戻り値を記録する
+    ///
+    /// regsは停止時のレジスタ。freeは引数のみを記録して先頭の命令を実行し、
+    /// 確保関数は呼び出し元に戻るまで実行してraxから確保した領域のアドレスを得る。
+    /// Recordedを返した場合は、ptrace::contで実行を再開できる状態になっている
+    fn record_heap(&mut self, f: HeapFn, regs: &user_regs_struct) -> Result<HeapHit, DynError> {
+        let pid = self.info.pid;
+        // 関数の先頭ではrspがリターンアドレスを指す
+        let site = trace::read(pid, regs.rsp as *mut c_void)? as u64;
+        if !self.step_inst()? {
+            return Ok(HeapHit::Exited);
+        }
+        if f == HeapFn::Free {
+            self.info.heap.free(regs.rdi);
+            return Ok(HeapHit::Recorded);
+        }
+
+        if !self.run_until_return(regs.rsp)? {
+            return Ok(HeapHit::Exited);
+        }
+        let ret = trace::getregs(pid)?;
+        if ret.rsp <= regs.rsp {
+            // 戻る前に通常のブレークポイントに到達した
+            return Ok(HeapHit::Stopped);
+        }
+        match f {
+            HeapFn::Malloc => self.info.heap.alloc(ret.rax, regs.rdi, site),
+            HeapFn::Calloc => {
+                let size = regs.rdi.saturating_mul(regs.rsi);
+                self.info.heap.alloc(ret.rax, size, site)
+            }
+            HeapFn::Realloc => self.info.heap.realloc(regs.rdi, ret.rax, regs.rsi, site),
+            HeapFn::Free => unreachable!(),
+        }
+        Ok(HeapHit::Recorded)
+    }
+
+    /// heapコマンドを実行する
+    ///
+    /// - heap on  : malloc、calloc、realloc、freeにブレークポイントを設定して追跡を開始
+    /// - heap off : ブレークポイントを削除して追跡を終了。記録はheapで表示できる
+    /// - heap     : 解放されていない領域の一覧と合計を表示
+    ///
+    /// 動的リンクされたプログラムでは、libcがマップされた後(mainなど)で停止してから開始する必要がある
+    fn do_heap(&mut self, cmd: &[&str]) -> Result<(), DynError> {
+        let tracking = self
+            .info
+            .breaks
+            .iter()
+            .any(|b| matches!(b.site, BreakSite::Heap(_)));
+        match cmd.get(1..) {
+            Some([]) => self.do_heap_list(),
+            Some(["on"]) if tracking => eprintln!("<<すでに追跡中です>>"),
+            Some(["on"]) => {
+                let fns = match maps::read_maps(self.info.pid).and_then(|r| heap::resolve(&r)) {
+                    Ok(fns) => fns,
+                    Err(msg) => {
+                        eprintln!("<<追跡を開始できません : {msg}。mainなどで停止してから実行してください>>");
+                        return Ok(());
+                    }
+                };
+                self.info.heap = HeapTracker::default();
+                let mut names = Vec::new();
+                for (f, addr) in fns {
+                    // 利用者のブレークポイントでは停止する必要があるため、追跡しない
+                    if self.is_break(addr) {
+                        eprintln!(
+                            "<<{}にはブレークポイントが設定されているため追跡しません>>",
+                            f.name()
+                        );
+                        continue;
+                    }
+                    self.push_break(addr, BreakSite::Heap(f));
+                    self.set_break(addr, false)?;
+                    names.push(f.name());
+                }
+                println!("<<{}の追跡を開始しました>>", names.join(", "));
+            }
+            Some(["off"]) if !tracking => eprintln!("<<追跡していません>>"),
+            Some(["off"]) => {
+                self.remove_heap_breaks()?;
+                println!("<<追跡を終了しました : {}>>", self.info.heap.summary());
+            }
+            _ => eprintln!("<<usage: heap [on|off]>>"),
+        }
+        Ok(())
+    }
+
+    /// heap onで設定したブレークポイントを元の値に戻して削除する
+    fn remove_heap_breaks(&mut self) -> Result<(), DynError> {
+        let addrs: Vec<u64> = self
+            .info
+            .breaks
+            .iter()
+            .filter(|b| matches!(b.site, BreakSite::Heap(_)))
+            .map(|b| b.addr)
+            .collect();
+        for addr in addrs {
+            self.write_break(addr, false)?;
+        }
+        self.info
+            .breaks
+            .retain(|b| !matches!(b.site, BreakSite::Heap(_)));
+        Ok(())
+    }
+
+    /// ソフトウェアウォッチポイントがある場合のcontinue
+    ///
+    /// 1命令ずつステップ実行し、実行するたびに監視中の領域を読み込んで比較する。
+    /// 領域の変更、ブレークポイント、ハードウェアウォッチポイント、シグナルのいずれかで停止する。
+    /// ステップ実行した命令数と経過時間を表示し、通常のcontinueより遅いことを利用者に示す
+    fn cont_by_step(mut self) -> Result<State, DynError> {
+        let pid = self.info.pid;
+        let start = Instant::now();
+        let mut steps = 0u64;
+        let report = |steps: u64| {
+            println!(
+                "<<ソフトウェアウォッチポイントのため{steps}命令をステップ実行しました ({:.3}秒)>>",
+                start.elapsed().as_secs_f64()
+            )
+        };
+
+        let stop = loop {
+            // step_and_breakで実行した命令による変更も検出するため、ステップ実行の前に比較する
+            let rip = trace::getregs(pid)?.rip;
+            if let Some(addr) = self.check_sw_watches() {
+                break Stop::Watch(addr, rip);
+            }
+            if self.is_inserted_break(rip) {
+                if self.skip_break(rip)? {
+                    // 条件が偽なら、元の命令を実行して続ける
+                    steps += 1;
+                    if !self.step_inst()? {
+                        report(steps);
+                        return Ok(self.into_not_running());
+                    }
+                    continue;
+                }
+                // int 3を実行する前に、ブレークポイントで停止した状態にする
+                self.write_break(rip, false)?;
+                break Stop::Break(rip);
+            }
+
+            hwwatch::clear_hits(pid)?;
+            let status = self.single_step()?;
+            steps += 1;
+            match status {
+                status @ (WaitStatus::Exited(..) | WaitStatus::Signaled(..)) => {
+                    report(steps);
+                    self.info.last_stop = Stop::from_exit(&status);
+                    return Ok(self.into_not_running());
+                }
+                WaitStatus::Stopped(_, Signal::SIGTRAP) => (),
+                WaitStatus::Stopped(_, sig) => {
+                    // 停止しないシグナルは、次のステップ実行で子プロセスに渡す
+                    let rip = trace::getregs(pid)?.rip;
+                    if !self.on_signal(sig, rip) {
+                        break Stop::Signal(sig, rip);
+                    }
+                    continue;
+                }
+                _ => return Err("waitpidの返り値が不正です".into()),
+            }
+            if let Some(addr) = self.report_hw_watches()? {
+                break Stop::Watch(addr, trace::getregs(pid)?.rip);
+            }
+        };
+
+        report(steps);
+        self.check_breaks();
+        self.print_stopped(trace::getregs(pid)?.rip)?;
+        self.info.last_stop = Some(stop);
+        Ok(State::Running(self))
+    }
+
+    /// 停止した位置のソースコード上の位置とPCを表示する
+    fn print_stopped(&self, rip: u64) -> Result<(), DynError> {
+        if let Some(loc) = self.current_location()? {
+            println!("<<{loc}>>");
+        }
+        match self.symbol_name(rip) {
+            Some(name) => println!("<<子プロセスが停止しました : PC = {rip:#x} ({name})>>"),
+            None => println!("<<子プロセスが停止しました : PC = {rip:#x}>>"),
+        }
+        Ok(())
+    }
+
+    /// stepiコマンドを実行する
+    /// 機械語レベルで1ステップ実行を行うメソッド
+    fn do_stepi(mut self) -> Result<State, DynError> {
+        let regs = trace::getregs(self.info.pid)?;
+        let on_exec = self.mask_exec_watch(regs.rip)?;
+        hwwatch::clear_hits(self.info.pid)?;
+        if self.is_break(regs.rip) {
+            // ブレークポイントで停止した場合は、そのメモリの値が0xccとなっている
+            // 可能性があるため、もとの値に復元する
+            self.write_break(regs.rip, false)?;
+            // regs.rip -= 1;
+            // trace::setregs(self.info.pid, regs)?;
+            let status = self.single_step()?; // 機械語レベルで1ステップ実行
+            if let Some(stop) = Stop::from_exit(&status) {
+                self.info.last_stop = Some(stop);
+                return Ok(self.into_not_running());
+            }
+            self.write_break(regs.rip, true)?;
+        } else {
+            let status = self.single_step()?; // 機械語レベルで1ステップ実行
+            if let Some(stop) = Stop::from_exit(&status) {
+                self.info.last_stop = Some(stop);
+                return Ok(self.into_not_running());
+            }
+        }
+        if on_exec {
+            self.apply_hw_watches()?;
+        }
+        self.report_hw_watches()?;
+        self.info.last_stop = Some(Stop::Step(trace::getregs(self.info.pid)?.rip));
+        Ok(State::Running(self))
+    }
+
+    /// nextiコマンドを実行する
+    /// 機械語レベルで1ステップ実行し、call命令を実行した場合は呼び出した関数から戻るまで実行する
+    ///
+    /// 関数から戻るまでは、リターンアドレスに一時的なブレークポイントを設定して実行を再開するため、
+    /// ライブラリ関数の呼び出しなども1回の停止で済む
+    fn do_nexti(mut self) -> Result<State, DynError> {
+        hwwatch::clear_hits(self.info.pid)?;
+        let regs = trace::getregs(self.info.pid)?;
+        if !self.step_inst()? {
+            return Ok(self.into_not_running());
+        }
+        let called = self.called(&regs)?;
+        if called {
+            let sp = trace::getregs(self.info.pid)?.rsp;
+            if !self.run_until_return(sp)? {
+                return Ok(self.into_not_running());
+            }
+        }
+        self.report_hw_watches()?;
+
+        let regs = trace::getregs(self.info.pid)?;
+        if called {
+            println!("<<子プロセスが停止しました : PC = {:#x}>>", regs.rip);
+        }
+        self.info.last_stop = Some(if self.at_break()? {
+            Stop::Break(regs.rip)
+        } else {
+            Stop::Step(regs.rip)
+        });
+        Ok(State::Running(self))
+    }
+
+    /// finishコマンドを実行し、現在の関数から戻るまで実行して戻り値を表示する
+    ///
+    /// リターンアドレスはCFAの直前にあり、そこに一時的なブレークポイントを設定して実行を再開する。
+    /// 戻り値の型はデバッグ情報から求め、System V ABIに従ってraxとrdx、
+    /// または大きな構造体の場合はraxが指すメモリから読み取る。デバッグ情報がない場合はraxをそのまま表示する
+    fn do_finish(mut self) -> Result<State, DynError> {
+        let regs = trace::getregs(self.info.pid)?;
+        let Some(sp) = self.cfa(&regs).map(|cfa| cfa - 8) else {
+            eprintln!("<<現在の関数のリターンアドレスを求められません>>");
+            return Ok(State::Running(self));
+        };
+        let pc = regs.rip.wrapping_sub(self.info.bias);
+        let func = self
+            .info
+            .symbols
+            .as_ref()
+            .and_then(|symbols| symbols.containing(pc))
+            .map_or("??".to_string(), |sym| sym.name.clone());
+
+        hwwatch::clear_hits(self.info.pid)?;
+        // ブレークポイントで停止している場合は、元の命令を実行してからブレークポイントを再設定する
+        if self.is_break(regs.rip) && !self.step_inst()? {
+            return Ok(self.into_not_running());
+        }
+        if trace::getregs(self.info.pid)?.rsp <= sp && !self.run_until_return(sp)? {
+            return Ok(self.into_not_running());
+        }
+        self.report_hw_watches()?;
+
+        let regs = trace::getregs(self.info.pid)?;
+        println!("<<子プロセスが停止しました : PC = {:#x}>>", regs.rip);
+        // 戻る前にブレークポイントに到達した場合は、戻り値を表示しない
+        if regs.rsp > sp {
+            println!("<<{func}から戻りました>>");
+            self.print_return_value(pc, &regs);
+        }
+        self.info.last_stop = Some(if self.at_break()? {
+            Stop::Break(regs.rip)
+        } else {
+            Stop::Step(regs.rip)
+        });
+        Ok(State::Running(self))
+    }
+
+    /// 実行ファイル上のアドレスpcを含む関数から戻った直後のレジスタregsから、戻り値を表示する
+    fn print_return_value(&self, pc: u64, regs: &user_regs_struct) {
+        let ty = match self.info.vars.as_ref().and_then(|vars| vars.ret_type(pc)) {
+            Some(Some(ty)) => ty,
+            Some(None) => return, // void
+            None => {
+                println!("戻り値 : rax = {:#x} ({})", regs.rax, regs.rax as i64);
+                return;
+            }
+        };
+        if matches!(ty, Type::Base(BaseKind::Float, _)) {
+            eprintln!("<<浮動小数点数の戻り値(xmm0)は表示できません>>");
+            return;
+        }
+        let bytes = match ty.size() {
+            0..=8 => regs.rax.to_le_bytes().to_vec(),
+            9..=16 => [regs.rax, regs.rdx].map(u64::to_le_bytes).concat(),
+            size => match watch::read_mem(self.info.pid, regs.rax, size.min(MAX_PRINT_LEN)) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    eprintln!("<<戻り値({:#x})を読み込めません : {e}>>", regs.rax);
+                    return;
+                }
+            },
+        };
+        println!("戻り値 = {}", vars::format(ty, &bytes));
+    }
+
+    /// 1命令実行する前のレジスタregsと現在のレジスタから、実行した命令がcall命令だったかを判定する
+    ///
+    /// call命令はリターンアドレスをスタックにpushしてジャンプするため、
+    /// rspが8減っており、スタックトップに直前の命令の直後のアドレスが積まれている
+    fn called(&self, regs: &user_regs_struct) -> Result<bool, DynError> {
+        let new_regs = trace::getregs(self.info.pid)?;
+        if new_regs.rsp != regs.rsp.wrapping_sub(8) {
+            return Ok(false);
+        }
+        let ret = trace::read(self.info.pid, new_regs.rsp as *mut c_void)? as u64;
+        Ok(ret > regs.rip && ret <= regs.rip + 15) // x86_64の命令長は最大15バイト
+    }
+
+    /// stepとnextコマンドを実行する
+    /// ソースコードの行が変わるまで機械語レベルで1ステップ実行を繰り返す
+    ///
+    /// overが真の場合はnextコマンドとして動作し、関数呼び出しは1ステップとみなして実行する。
+    /// 偽の場合はstepコマンドとして動作し、呼び出された関数の中に入る。
+    /// ただし、行番号情報のない関数(ライブラリ関数など)に入った場合は、その関数から戻るまで実行する。
+    fn do_step_line(mut self, over: bool) -> Result<State, DynError> {
+        let Some(start) = self.current_location()? else {
+            eprintln!(
+                "<<現在のアドレスに対応する行番号情報がありません。stepiを利用してください>>"
+            );
+            return Ok(State::Running(self));
+        };
+
+        loop {
+            let regs = trace::getregs(self.info.pid)?;
+            if !self.step_inst()? {
+                return Ok(self.into_not_running());
+            }
+
+            let new_regs = trace::getregs(self.info.pid)?;
+            let called = self.called(&regs)?;
+
+            if called && !over && !self.skip_prologue(new_regs.rip)? {
+                return Ok(self.into_not_running());
+            }
+
+            let loc = self.current_location()?;
+            if called && (over || loc.is_none()) {
+                // 関数から戻るまで実行
+                if !self.run_until_return(new_regs.rsp)? {
+                    return Ok(self.into_not_running());
+                }
+                if self.at_break()? {
+                    // 関数内でブレークポイントに到達した
+                    break;
+                }
+                continue;
+            }
+
+            match loc {
+                Some(loc) if loc != start => {
+                    println!("<<{loc}>>");
+                    break;
+                }
+                _ => (),
+            }
+        }
+
+        let regs = trace::getregs(self.info.pid)?;
+        println!("<<子プロセスが停止しました : PC = {:#x}>>", regs.rip);
+        self.info.last_stop = Some(if self.at_break()? {
+            Stop::Break(regs.rip)
+        } else {
+            Stop::Step(regs.rip)
+        });
+        Ok(State::Running(self))
+    }
+
+    /// 関数の先頭entryから、行番号情報が示すプロローグの直後まで1命令ずつ実行する
+    ///
+    /// 関数の先頭では引数やローカル変数がまだスタックに用意されていないため、stepで関数に入った場合に用いる。
+    /// 行番号情報がない場合や、ブレークポイントに到達した場合はそこで止める。子プロセスが終了した場合はfalseを返す
+    fn skip_prologue(&mut self, entry: u64) -> Result<bool, DynError> {
+        let end = self
+            .info
+            .lines
+            .as_ref()
+            .zip(entry.checked_sub(self.info.bias))
+            .and_then(|(lines, addr)| lines.prologue_end(addr));
+        let Some(end) = end.map(|addr| addr + self.info.bias) else {
+            return Ok(true);
+        };
+        loop {
+            let rip = trace::getregs(self.info.pid)?.rip;
+            if !(entry..end).contains(&rip) || self.is_break(rip) {
+                return Ok(true);
+            }
+            if !self.step_inst()? {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// 機械語レベルで1ステップ実行する。子プロセスが終了した場合はfalseを返す
+    ///
+    /// ブレークポイントのアドレスから実行する場合は、一時的に元の命令に戻して実行し、
+    /// 実行後にブレークポイントを再設定する
+    fn step_inst(&mut self) -> Result<bool, DynError> {
+        let regs = trace::getregs(self.info.pid)?;
+        let on_break = self.is_break(regs.rip);
+        if on_break {
+            self.write_break(regs.rip, false)?;
+        }
+        let on_exec = self.mask_exec_watch(regs.rip)?;
+
+        let status = self.single_step()?;
+        if let Some(stop) = Stop::from_exit(&status) {
+            self.info.last_stop = Some(stop);
+            return Ok(false);
+        }
+
+        if on_break {
+            self.write_break(regs.rip, true)?;
+        }
+        if on_exec {
+            self.apply_hw_watches()?;
+        }
+        Ok(true)
+    }
+
+    /// ripの命令を1つ実行し、waitpidの結果を返す
+    ///
+    /// ripにブレークポイントがある場合は、呼び出し側で元の値に戻しておく。
+    /// set stepの設定と命令に応じて、PTRACE_SINGLESTEPかソフトウェアのステップ実行を選ぶ。
+    /// ソフトウェアのステップ実行で次の命令のアドレスを求められない場合は、可能ならPTRACE_SINGLESTEPで実行する
+    fn single_step(&mut self) -> Result<WaitStatus, DynError> {
+        let pid = self.info.pid;
+        let arch = self.info.arch;
+        // PTRACE_SINGLESTEPやPTRACE_CONTで再開すると、システムコールの出口では停止しない
+        self.info.in_syscall = false;
+        let regs = trace::getregs(pid)?;
+        let code = self.read_code(regs.rip, MAX_INST_LEN);
+        let software = match self.info.step_mode {
+            StepMode::Hardware => false,
+            StepMode::Software => true,
+            StepMode::Auto => !arch.has_hw_step() || arch.needs_sw_step(&code),
+        };
+        if software {
+            let read = |addr: u64| {
+                trace::read(pid, addr as *mut c_void)
+                    .map(|val| val as u64)
+                    .map_err(|e| format!("{addr:#x}を読み込めません : {e}"))
+            };
+            match arch.next_pcs(&code, &regs, &read) {
+                Ok(pcs) => return self.sw_step(&pcs),
+                Err(e) if arch.has_hw_step() => {
+                    eprintln!(
+                        "<<{:#x}はソフトウェアでステップ実行できません : {e}>>",
+                        regs.rip
+                    )
+                }
+                Err(e) => return Err(format!("次の命令のアドレスを求められません : {e}").into()),
+            }
+        }
+        let sig = self.resume_sig();
+        trace::step(pid, sig)?;
+        Ok(trace::waitpid(pid, None)?)
+    }
+
+    /// ソフトウェアのステップ実行
+    ///
+    /// pcsの各アドレスに一時的なint 3を書き込んで実行を再開し、停止したら元の値に戻す。
+    /// いずれかで停止した場合は、ripをそのアドレスに戻してステップ実行が完了した状態にする。
+    /// すでにブレークポイントが書き込まれているアドレスはそのまま利用する
+    fn sw_step(&mut self, pcs: &[u64]) -> Result<WaitStatus, DynError> {
+        let pid = self.info.pid;
+        let mut saved: Vec<(u64, u8)> = Vec::new();
+        for &pc in pcs {
+            if self.is_inserted_break(pc) || saved.iter().any(|(addr, _)| *addr == pc) {
+                continue;
+            }
+            let ptr = pc as *mut c_void;
+            let val = trace::read(pid, ptr)?;
+            unsafe { trace::write(pid, ptr, ((val & !0xff) | 0xcc) as *mut c_void)? };
+            saved.push((pc, val as u8));
+        }
+
+        trace::cont(pid, None)?;
+        let status = trace::waitpid(pid, None)?;
+        if Stop::from_exit(&status).is_some() {
+            return Ok(status);
+        }
+        // 同じ8バイトに複数書き込んだ場合に備え、逆順に下位1バイトのみを戻す
+        for (pc, orig) in saved.iter().rev() {
+            let ptr = *pc as *mut c_void;
+            let val = trace::read(pid, ptr)?;
+            unsafe { trace::write(pid, ptr, ((val & !0xff) | *orig as i64) as *mut c_void)? };
+        }
+        if let WaitStatus::Stopped(_, Signal::SIGTRAP) = status {
+            let mut regs = trace::getregs(pid)?;
+            let addr = self.info.arch.break_addr(regs.rip);
+            if pcs.contains(&addr) {
+                regs.rip = addr;
+                trace::setregs(pid, regs)?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// addrのブレークポイントにint 3(enable = true)か、元の値(enable = false)を書き込む
+    ///
+    /// 同じ8バイトにある他のブレークポイントを壊さないように、現在の値の下位1バイトのみを書き換える。
+    /// すでに書き込まれている場合や、ブレークポイントでない場合、無効にしたブレークポイントにint 3を書き込む場合は何もしない
+    fn write_break(&mut self, addr: u64, enable: bool) -> Result<(), DynError> {
+        let pid = self.info.pid;
+        let Some(b) = self.info.breaks.iter_mut().find(|b| b.addr == addr) else {
+            return Ok(());
+        };
+        let ptr = addr as *mut c_void;
+        match (enable, b.orig) {
+            (true, None) if b.enabled => {
+                let val = trace::read(pid, ptr)?;
+                unsafe { trace::write(pid, ptr, ((val & !0xff) | 0xcc) as *mut c_void)? };
+                b.orig = Some(val as u8);
+            }
+            (false, Some(orig)) => {
+                let val = trace::read(pid, ptr)?;
+                unsafe { trace::write(pid, ptr, ((val & !0xff) | orig as i64) as *mut c_void)? };
+                b.orig = None;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// 書き込んだint 3が、子プロセス自身によって書き換えられていないか検査する
+    ///
+    /// 自己書き換えコードや動的ローダの再配置によって、ブレークポイントの位置の命令が書き換えられた場合は警告し、
+    /// 書き換え後の値を元の値として保持してからint 3を書き込み直す。
+    /// 古い値のままだと、ブレークポイントを外したときに新しい命令を壊してしまう
+    fn check_breaks(&mut self) {
+        let pid = self.info.pid;
+        for b in self.info.breaks.iter_mut() {
+            let Some(orig) = b.orig else {
+                continue;
+            };
+            let ptr = b.addr as *mut c_void;
+            let Ok(val) = trace::read(pid, ptr) else {
+                continue; // アンマップされた場合などは検査しない
+            };
+            let byte = val as u8;
+            if byte == 0xcc {
+                continue;
+            }
+
+            println!(
+                "<<{:#x}のブレークポイントが上書きされました : {orig:#04x} -> {byte:#04x}>>",
+                b.addr
+            );
+            b.orig = Some(byte);
+            if let Err(e) = unsafe { trace::write(pid, ptr, ((val & !0xff) | 0xcc) as *mut c_void) }
+            {
+                eprintln!("<<ptrace::writeに失敗 : {e}, addr = {:p}>>", ptr);
+                b.orig = None;
+            }
+        }
+    }
+
+    /// addrのブレークポイントにint 3が書き込まれていれば真
+    fn is_inserted_break(&self, addr: u64) -> bool {
+        self.info
+            .breaks
+            .iter()
+            .any(|b| b.addr == addr && b.orig.is_some())
+    }
+
+    /// call命令の直後に呼び出し、呼び出された関数から戻るまで実行する
+    /// 子プロセスが終了した場合はfalseを返す
+    ///
+    /// spはcall命令実行後のrspで、リターンアドレスが格納されている。
+    /// リターンアドレスに一時的なブレークポイントを設定して実行を再開し、
+    /// rspがspより大きくなった状態で停止した時点で、関数から戻ったとみなす。
+    /// 再帰呼び出しの場合は同じリターンアドレスで複数回停止するため、rspも検査する必要がある。
+    /// 途中で通常のブレークポイントに到達した場合は、そこで停止する。
+    fn run_until_return(&mut self, sp: u64) -> Result<bool, DynError> {
+        self.info.in_syscall = false; // PTRACE_CONTで再開するため、出口では停止しない
+        let ret_addr = trace::read(self.info.pid, sp as *mut c_void)? as u64;
+        let ret_ptr = ret_addr as *mut c_void;
+        let orig = trace::read(self.info.pid, ret_ptr)?;
+
+        loop {
+            unsafe {
+                trace::write(
+                    self.info.pid,
+                    ret_ptr,
+                    ((orig & !0xff) | 0xcc) as *mut c_void,
+                )?
+            };
+            trace::cont(self.info.pid, None)?;
+            let status = trace::waitpid(self.info.pid, None)?;
+            if let Some(stop) = Stop::from_exit(&status) {
+                self.info.last_stop = Some(stop);
+                return Ok(false);
+            }
+            unsafe { trace::write(self.info.pid, ret_ptr, orig as *mut c_void)? };
+
+            let mut regs = trace::getregs(self.info.pid)?;
+            if regs.rip - 1 == ret_addr {
+                // 一時的なブレークポイントで停止
+                regs.rip -= 1;
+                trace::setregs(self.info.pid, regs)?;
+                if regs.rsp > sp {
+                    return Ok(true);
+                }
+                // 再帰呼び出しの内側から戻っただけなので、1ステップ進めて再開
+                if !self.step_inst()? {
+                    return Ok(false);
+                }
+                continue;
+            }
+
+            if self.is_inserted_break(regs.rip - 1) {
+                // 通常のブレークポイントで停止
+                self.write_break(regs.rip - 1, false)?;
+                regs.rip -= 1;
+                trace::setregs(self.info.pid, regs)?;
+                // heap onで追跡中の関数なら、記録して再開する
+                if let Some(f) = self.heap_fn(regs.rip) {
+                    match self.record_heap(f, &regs)? {
+                        HeapHit::Recorded => continue,
+                        HeapHit::Stopped => (),
+                        HeapHit::Exited => return Ok(false),
+                    }
+                }
+                // 条件が偽なら、1ステップ進めて再開
+                if self.skip_break(regs.rip)? {
+                    if !self.step_inst()? {
+                        return Ok(false);
+                    }
+                    continue;
+                }
+            }
+            return Ok(true);
+        }
+    }
+
+    /// 一時ブレークポイント(tbreak)で停止した場合は、書き込んだint 3を元の値に戻して削除する
+    fn remove_temp_break(&mut self) {
+        let Some(Stop::Break(rip)) = self.info.last_stop else {
+            return;
+        };
+        if !self
+            .info
+            .breaks
+            .iter()
+            .any(|b| b.addr == rip && b.temporary)
+        {
+            return;
+        }
+        if let Err(e) = self.write_break(rip, false) {
+            eprintln!("<<一時ブレークポイントを元の値に戻せません : {e}>>");
+            return;
+        }
+        self.remove_break(rip);
+    }
+
+    /// ripのブレークポイントに条件が設定されていて、現在のレジスタとメモリでは偽なら真
+    ///
+    /// 条件を評価できない場合は、停止して利用者に知らせるため偽を返す
+    fn skip_break(&self, rip: u64) -> Result<bool, DynError> {
+        let Some(b) = self.info.breaks.iter().find(|b| b.addr == rip) else {
+            return Ok(false);
+        };
+        let Some(cond) = &b.cond else {
+            return Ok(false);
+        };
+        let pid = self.info.pid;
+        let regs = trace::getregs(pid)?;
+        let read = |addr: u64| {
+            trace::read(pid, addr as *mut c_void)
+                .map(|val| val as u64)
+                .map_err(|e| format!("{addr:#x}を読み込めません : {e}"))
+        };
+        match cond.eval(&regs, &read) {
+            Ok(hit) => Ok(!hit),
+            Err(e) => {
+                eprintln!("<<ブレークポイント{}の条件を評価できません : {e}>>", b.id);
+                Ok(false)
+            }
+        }
+    }
+
+    /// ブレークポイントで停止中なら真
+    fn at_break(&self) -> Result<bool, DynError> {
+        let regs = trace::getregs(self.info.pid)?;
+        Ok(self.is_break(regs.rip))
+    }
+
+    /// 現在のプログラムカウンタに対応するソースコード上の位置を返す
+    fn current_location(&self) -> Result<Option<Location>, DynError> {
+        let Some(lines) = &self.info.lines else {
+            return Ok(None);
+        };
+        let regs = trace::getregs(self.info.pid)?;
+        Ok(regs
+            .rip
+            .checked_sub(self.info.bias)
+            .and_then(|addr| lines.find(addr))
+            .cloned())
+    }
+
+    /// info proc mapsを実行し、/proc/PID/mapsのメモリ領域の一覧を表示する
+    ///
+    /// ripとrspを含む領域には印を付ける
+    fn do_info_proc(&self, cmd: &[&str]) -> Result<(), DynError> {
+        if cmd.get(2..) != Some(&["maps"]) {
+            eprintln!("<<usage: info proc maps>>");
+            return Ok(());
+        }
+        let regions = match maps::read_maps(self.info.pid) {
+            Ok(regions) => regions,
+            Err(msg) => {
+                eprintln!("<<{msg}>>");
+                return Ok(());
+            }
+        };
+        let regs = trace::getregs(self.info.pid)?;
+        println!("process {}", self.info.pid);
+        for line in maps::format_regions(&regions, &[("rip", regs.rip), ("rsp", regs.rsp)]) {
+            println!("{line}");
+        }
+        Ok(())
+    }
+
+    /// tlsコマンドを実行する
+    ///
+    /// 引数がない場合は、fs_base、gs_baseとスタックカナリア(fs:0x28)の値を表示する。
+    /// 引数がある場合は、fs:0x10のようなセグメント相対のアドレスも含め、
+    /// 指定されたアドレスから8バイト読み込んで表示する。
+    fn do_tls(&self, cmd: &[&str]) -> Result<(), DynError> {
+        let regs = trace::getregs(self.info.pid)?;
+        let Some(arg) = cmd.get(1) else {
+            println!("fs_base: {:#018x}", regs.fs_base);
+            println!("gs_base: {:#018x}", regs.gs_base);
+            // x86_64のglibcでは、スタックカナリアはTCBのfs:0x28に格納されている
+            let canary_addr = regs.fs_base + STACK_CANARY_OFFSET;
+            match trace::read(self.info.pid, canary_addr as *mut c_void) {
+                Ok(val) => println!("canary : {:#018x} (fs:{STACK_CANARY_OFFSET:#x})", val),
+                Err(e) => eprintln!("<<カナリアの読み込みに失敗 : {e}>>"),
+            }
+            return Ok(());
+        };
+
+        let addr = match resolve_addr(arg, &regs) {
+            Ok(addr) => addr,
+            Err(e) => {
+                eprintln!("<<{e}>>");
+                return Ok(());
+            }
+        };
+        match trace::read(self.info.pid, addr as *mut c_void) {
+            Ok(val) => println!("{addr:#018x}: {:#018x}", val),
+            Err(e) => eprintln!("<<ptrace::readに失敗 : {e}, addr = {addr:#x}>>"),
+        }
+        Ok(())
+    }
+
+    /// ポインタのように見えるレジスタについて、参照先の連鎖を表示する
+    fn print_regs_deref(&self, regs: &user_regs_struct) {
+        if self.info.deref_depth == 0 {
+            return;
+        }
+        let named = [
+            ("RIP", regs.rip),
+            ("RSP", regs.rsp),
+            ("RBP", regs.rbp),
+            ("RAX", regs.rax),
+            ("RBX", regs.rbx),
+            ("RCX", regs.rcx),
+            ("RDX", regs.rdx),
+            ("RSI", regs.rsi),
+            ("RDI", regs.rdi),
+            ("R8", regs.r8),
+            ("R9", regs.r9),
+            ("R10", regs.r10),
+            ("R11", regs.r11),
+            ("R12", regs.r12),
+            ("R13", regs.r13),
+            ("R14", regs.r14),
+            ("R15", regs.r15),
+        ];
+        for (name, val) in named {
+            let chain = deref_chain(self.info.pid, val, self.info.deref_depth);
+            // 参照先を辿れなかったレジスタは表示しない
+            if chain.contains(" -> ") {
+                println!("{name:>3}: {chain}");
+            }
+        }
+    }
+
+    /// stackコマンドを実行する
+    ///
+    /// スタックポインタから指定された個数(省略時は8個)の値を、参照先の連鎖とともに表示する。
+    /// stack/dwのように形式を指定した場合は、参照先の代わりに各8バイトをその形式で表示する
+    fn do_stack(&self, cmd: &[&str]) -> Result<(), DynError> {
+        let render = match cmd[0].strip_prefix("stack/") {
+            None => None,
+            Some(spec) => {
+                let mut render = Render::new(Radix::Hex, 8);
+                if let Some(c) = spec.chars().find(|c| !render.set_flag(*c)) {
+                    eprintln!("<<不明な形式です : {c}>>");
+                    return Ok(());
+                }
+                Some(render)
+            }
+        };
+        let n = match cmd.get(1).map(|n| n.parse::<u64>()) {
+            None => DEFAULT_STACK_SLOTS,
+            Some(Ok(n)) => n,
+            Some(Err(e)) => {
+                eprintln!("<<個数の変換エラー : {e}>>");
+                return Ok(());
+            }
+        };
+
+        let regs = trace::getregs(self.info.pid)?;
+        for i in 0..n {
+            let addr = regs.rsp + i * 8;
+            let val = match trace::read(self.info.pid, addr as *mut c_void) {
+                Ok(val) => val as u64,
+                Err(e) => {
+                    eprintln!("<<ptrace::readに失敗 : {e}, addr = {addr:#x}>>");
+                    break;
+                }
+            };
+            let shown = match &render {
+                Some(render) => render.units(&val.to_le_bytes()).join(" "),
+                None => deref_chain(self.info.pid, val, self.info.deref_depth),
+            };
+            println!("{addr:#018x}|+{:#06x}: {shown}", i * 8);
+        }
+        Ok(())
+    }
+
+    /// set regコマンドを実行し、レジスタの値を変更する
+    ///
+    /// ブレークポイントで停止中にripを変更した場合、停止したアドレスのブレークポイントは
+    /// 再開時に再設定されなくなるため、ここで書き込み直す
+    fn do_set_reg(&mut self, cmd: &[&str]) -> Result<(), DynError> {
+        let [_, _, name, val] = cmd else {
+            eprintln!("<<usage: set reg レジスタ 値\n例: set reg rip 0x401000>>");
+            return Ok(());
+        };
+        let val = match parse_value(val) {
+            Ok(val) => val,
+            Err(msg) => {
+                eprintln!("<<{msg}>>");
+                return Ok(());
+            }
+        };
+
+        let mut regs = trace::getregs(self.info.pid)?;
+        let old_rip = regs.rip;
+        let Some(reg) = reg_mut(&mut regs, name) else {
+            eprintln!(
+                "<<{name}というレジスタはありません\n変更できるレジスタ : {}>>",
+                REG_NAMES.join(", ")
+            );
+            return Ok(());
+        };
+        *reg = val;
+        trace::setregs(self.info.pid, regs)?;
+        if regs.rip != old_rip {
+            self.write_break(old_rip, true)?;
+        }
+        println!("<<{} = {val:#x}>>", name.to_ascii_lowercase());
+        Ok(())
+    }
+
+    /// xコマンドを実行し、x/[個数][形式][単位] アドレス の形式でメモリの内容を表示する
+    fn do_examine(&self, cmd: &[&str]) -> Result<(), DynError> {
+        let spec = cmd[0][1..].strip_prefix('/').unwrap_or("");
+        let fmt = match Format::parse(spec) {
+            Ok(fmt) => fmt,
+            Err(msg) => {
+                eprintln!("<<{msg}>>");
+                return Ok(());
+            }
+        };
+        let [_, addr] = cmd else {
+            eprintln!("<<usage: x/[個数][形式][単位] アドレス\n例: x/16xb 0x404010>>");
+            return Ok(());
+        };
+
+        let regs = trace::getregs(self.info.pid)?;
+        let addr = match resolve_addr(addr, &regs) {
+            Ok(addr) => addr,
+            Err(msg) => {
+                eprintln!("<<{msg}>>");
+                return Ok(());
+            }
+        };
+        match examine(self.info.pid, addr, &fmt) {
+            Ok(lines) => lines.iter().for_each(|line| println!("{line}")),
+            Err(msg) => eprintln!("<<{msg}>>"),
+        }
+        Ok(())
+    }
+
+    /// disasコマンドを実行する
+    ///
+    /// - disas                  : PCを含む関数を先頭から解釈し、PCの前後の命令を表示
+    /// - disas 0x401126 [個数]  : 指定したアドレスから指定個数(省略時は8個)の命令を表示
+    /// - disas main [個数]      : 関数の先頭から指定個数の命令を表示
+    fn do_disas(&self, cmd: &[&str]) -> Result<(), DynError> {
+        let regs = trace::getregs(self.info.pid)?;
+        let (addr, count) = match cmd.get(1..) {
+            Some([]) => {
+                self.print_insts(&self.disas_around(regs.rip), regs.rip);
+                return Ok(());
+            }
+            Some([addr]) => (*addr, DEFAULT_DISAS_INSTS),
+            Some([addr, n]) => match n.parse::<usize>() {
+                Ok(n) if (1..=MAX_DISAS_INSTS).contains(&n) => (*addr, n),
+                _ => {
+                    eprintln!("<<個数は1から{MAX_DISAS_INSTS}の整数で指定してください>>");
+                    return Ok(());
+                }
+            },
+            _ => {
+                eprintln!("<<usage: disas [アドレス | 関数名] [個数]>>");
+                return Ok(());
+            }
+        };
+
+        let addr = if addr.starts_with("0x") || addr.contains(':') {
+            match resolve_addr(addr, &regs) {
+                Ok(addr) => addr,
+                Err(msg) => {
+                    eprintln!("<<{msg}>>");
+                    return Ok(());
+                }
+            }
+        } else {
+            match self.find_symbol(addr) {
+                Some(sym) => sym.addr + self.info.bias,
+                None => return Ok(()),
+            }
+        };
+        let code = self.read_code(addr, count * MAX_INST_LEN);
+        if code.is_empty() {
+            eprintln!("<<{addr:#x}を読み込めません>>");
+            return Ok(());
+        }
+        self.print_insts(&disas::disassemble(&code, addr, count), regs.rip);
+        Ok(())
+    }
+
+    /// 停止した位置から実行する命令を、set disas-countで指定した個数だけ表示
+    fn print_next_insts(&self) {
+        if self.info.disas_count == 0 {
+            return;
+        }
+        let Ok(regs) = trace::getregs(self.info.pid) else {
+            return;
+        };
+        let code = self.read_code(regs.rip, self.info.disas_count * MAX_INST_LEN);
+        let insts = disas::disassemble(&code, regs.rip, self.info.disas_count);
+        self.print_insts(&insts, regs.rip);
+    }
+
+    /// ripの前後の命令を逆アセンブルする
+    ///
+    /// x86-64の命令は可変長のため、ripより前の命令は関数の先頭から順に解釈して求める。
+    /// 関数が分からない場合や、解釈した命令の境界がripと一致しない場合はripから解釈する
+    fn disas_around(&self, rip: u64) -> Vec<Inst> {
+        let after = DEFAULT_DISAS_INSTS - DISAS_INSTS_BEFORE;
+        let start = self
+            .info
+            .symbols
+            .as_ref()
+            .zip(rip.checked_sub(self.info.bias))
+            .and_then(|(symbols, addr)| symbols.containing(addr))
+            .map(|sym| sym.addr + self.info.bias)
+            .filter(|start| rip - start <= MAX_DISAS_PRECEDING);
+
+        if let Some(start) = start {
+            let len = (rip - start) as usize + after * MAX_INST_LEN;
+            let code = self.read_code(start, len);
+            let insts = disas::disassemble(&code, start, usize::MAX);
+            if let Some(idx) = insts.iter().position(|inst| inst.addr == rip) {
+                let from = idx.saturating_sub(DISAS_INSTS_BEFORE);
+                return insts
+                    .into_iter()
+                    .skip(from)
+                    .take(idx - from + after)
+                    .collect();
+            }
+        }
+        let code = self.read_code(rip, after * MAX_INST_LEN);
+        disas::disassemble(&code, rip, after)
+    }
+
+    /// addrから最大lenバイトの機械語を読み込む
+    ///
+    /// 読み込めなくなった時点で打ち切る。ブレークポイントのint 3は元の1バイトに戻して返す
+    fn read_code(&self, addr: u64, len: usize) -> Vec<u8> {
+        let mut code = Vec::with_capacity(len + 8);
+        while code.len() < len {
+            let word_addr = addr + code.len() as u64;
+            match trace::read(self.info.pid, word_addr as *mut c_void) {
+                Ok(word) => code.extend_from_slice(&word.to_le_bytes()),
+                Err(_) => break,
+            }
+        }
+        code.truncate(len);
+
+        for b in self.info.breaks.iter() {
+            if let (Some(orig), Some(off)) = (b.orig, b.addr.checked_sub(addr)) {
+                if let Some(byte) = code.get_mut(off as usize) {
+                    *byte = orig;
+                }
+            }
+        }
+        code
+    }
+
+    /// 逆アセンブルした命令を表示する。ripの命令には=>を付ける
+    fn print_insts(&self, insts: &[Inst], rip: u64) {
+        for inst in insts {
+            let marker = if inst.addr == rip { "=>" } else { "  " };
+            let loc = match self.symbol_offset(inst.addr) {
+                Some(loc) => format!(" <{loc}>"),
+                None => String::new(),
+            };
+            let bytes = self
+                .read_code(inst.addr, inst.len)
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let note = match (inst.target, inst.rip_ref) {
+                (Some(target), _) => self
+                    .symbol_offset(target)
+                    .map(|loc| format!(" <{loc}>"))
+                    .unwrap_or_default(),
+                (None, Some(addr)) => format!("  # {addr:#x}"),
+                (None, None) => String::new(),
+            };
+            println!(
+                "{marker} {:#x}{loc}: {bytes:<21} {}{note}",
+                inst.addr, inst.text
+            );
+        }
+    }
+
+    /// backtraceコマンドを実行し、呼び出し元の関数を1フレーム1行で表示する
+    ///
+    /// CFIがあればそれを使い、なければrbpを辿る。
+    /// #0は停止している位置、#1以降はリターンアドレスを表示する
+    fn do_backtrace(&self) -> Result<(), DynError> {
+        for (i, frame) in self.frames()?.iter().enumerate() {
+            let pc = frame.pc;
+            match self.symbol_offset(pc) {
+                Some(loc) => println!("#{i:<2} {pc:#018x} in {loc}"),
+                None => println!("#{i:<2} {pc:#018x} in ??"),
+            }
+        }
+        Ok(())
+    }
+
+    /// 停止位置から呼び出し元を辿り、各フレームを返す
+    fn frames(&self) -> Result<Vec<Frame>, DynError> {
+        let regs = trace::getregs(self.info.pid)?;
+        let pid = self.info.pid;
+        let read = |addr: u64| {
+            trace::read(pid, addr as *mut c_void)
+                .ok()
+                .map(|val| val as u64)
+        };
+        let find = |pc: u64| {
+            self.info
+                .cfi
+                .as_ref()?
+                .find(pc.checked_sub(self.info.bias)?)
+        };
+        Ok(backtrace::walk_frames(
+            Regs::from(&regs),
+            self.prologue(regs.rip),
+            find,
+            read,
+        ))
+    }
+
+    /// スタックの破壊を検査し、見つかった問題を警告する
+    ///
+    /// 各フレームのリターンアドレスが実行可能な領域を指しているかと、前回の検査でカナリアを
+    /// 保持していたスロットが書き換えられていないかを検査する。
+    /// verboseが真なら、問題がない場合も検査したフレームとカナリアの数を表示する
+    fn check_stack(&mut self, verbose: bool) -> Result<(), DynError> {
+        let frames = self.frames()?;
+        let regions = maps::read_maps(self.info.pid)?;
+        let pid = self.info.pid;
+        let read = |addr: u64| {
+            trace::read(pid, addr as *mut c_void)
+                .ok()
+                .map(|val| val as u64)
+        };
+        let regs = trace::getregs(pid)?;
+        let canary = regs
+            .fs_base
+            .checked_add(STACK_CANARY_OFFSET)
+            .filter(|_| regs.fs_base != 0)
+            .and_then(read);
+
+        let warnings = self.info.stack_check.check(&frames, &regions, canary, read);
+        for w in warnings.iter() {
+            println!("<<警告: {w}>>");
+        }
+        if verbose && warnings.is_empty() {
+            println!(
+                "<<スタックの破壊は見つかりませんでした (フレーム{}個、カナリア{}個)>>",
+                frames.len(),
+                self.info.stack_check.num_canaries()
+            );
+        }
+        Ok(())
+    }
+
+    /// listコマンドを実行し、停止した行を中心にソースコードを表示する
+    fn do_list(&self) -> Result<(), DynError> {
+        if self.info.lines.is_none() {
+            eprintln!("<<行番号情報がないため、ソースコードを表示できません>>");
+            return Ok(());
+        }
+        let Some(loc) = self.current_location()? else {
+            let regs = trace::getregs(self.info.pid)?;
+            eprintln!(
+                "<<{:#x}に対応するソースコード上の位置がありません>>",
+                regs.rip
+            );
+            return Ok(());
+        };
+        let src = match fs::read(&loc.file) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("<<{}を読み込めません : {e}>>", loc.file.display());
+                return Ok(());
+            }
+        };
+        println!("<<{loc}>>");
+        for line in source::render(&String::from_utf8_lossy(&src), loc.line, LIST_LINES) {
+            println!("{line}");
+        }
+        Ok(())
+    }
+
+    /// printコマンドを実行し、停止位置で参照できる引数かローカル変数の値を表示する
+    ///
+    /// 変数の位置はDW_AT_locationから求め、フレームベースにCFAを用いる場合は
+    /// CFIの規則(なければプロローグの実行状況)から停止位置のCFAを求める
+    fn do_print(&self, cmd: &[&str]) -> Result<(), DynError> {
+        if cmd.len() != 2 {
+            eprintln!("<<usage: print 変数名>>");
+            return Ok(());
+        }
+        let Some(table) = self.info.vars.as_ref() else {
+            eprintln!("<<デバッグ情報がないため、変数を表示できません>>");
+            return Ok(());
+        };
+        let name = cmd[1];
+        let regs = trace::getregs(self.info.pid)?;
+        let pc = regs.rip.wrapping_sub(self.info.bias);
+        let Some((var, frame_base)) = table.find(pc, name) else {
+            eprintln!("<<変数{name}は現在の位置から参照できません>>");
+            return Ok(());
+        };
+
+        let cur = Regs::from(&regs);
+        let cfa = self.cfa(&regs);
+        let place = var
+            .loc
+            .and_then(|loc| vars::resolve(loc, frame_base, &cur, cfa, self.info.bias));
+        let bytes = match place {
+            Some(Place::Mem(addr)) => {
+                let len = var.ty.size().min(MAX_PRINT_LEN);
+                match watch::read_mem(self.info.pid, addr, len) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        eprintln!("<<{name}の値({addr:#x})を読み込めません : {e}>>");
+                        return Ok(());
+                    }
+                }
+            }
+            Some(Place::Reg(r)) if cur.get(r).is_some() => {
+                cur.get(r).unwrap_or_default().to_le_bytes().to_vec()
+            }
+            _ => {
+                eprintln!("<<{name}の位置を求められません(最適化されている可能性があります)>>");
+                return Ok(());
+            }
+        };
+        println!("{name} = {}", vars::format(&var.ty, &bytes));
+        Ok(())
+    }
+
+    /// 停止位置での現在のフレームのCFA(呼び出し元のrsp)を求める
+    ///
+    /// CFIの規則があればそれを使い、なければプロローグの実行状況から求める
+    fn cfa(&self, regs: &user_regs_struct) -> Option<u64> {
+        let cur = Regs::from(regs);
+        let rule = self
+            .info
+            .cfi
+            .as_ref()
+            .zip(regs.rip.checked_sub(self.info.bias))
+            .and_then(|(cfi, pc)| cfi.find(pc));
+        match rule {
+            Some(rule) => rule.cfa(&cur),
+            None => self.prologue(regs.rip).rule().cfa(&cur),
+        }
+    }
+
+    /// ripを含む関数を先頭から逆アセンブルし、プロローグの実行状況を判定する
+    ///
+    /// 関数が分からない場合は、rbpを設定済みとみなす
+    fn prologue(&self, rip: u64) -> Prologue {
+        let start = self
+            .info
+            .symbols
+            .as_ref()
+            .zip(rip.checked_sub(self.info.bias))
+            .and_then(|(symbols, addr)| symbols.containing(addr))
+            .map(|sym| sym.addr + self.info.bias)
+            .filter(|start| rip - start <= MAX_DISAS_PRECEDING);
+        let Some(start) = start else {
+            return Prologue::Done;
+        };
+        let code = self.read_code(start, (rip - start) as usize + MAX_INST_LEN);
+        let insts = disas::disassemble(&code, start, usize::MAX);
+        match insts.iter().position(|inst| inst.addr == rip) {
+            Some(idx) => backtrace::prologue(&insts[..idx], insts.get(idx)),
+            None => Prologue::Done,
+        }
+    }
+
+    /// watchmemコマンドを実行する
+    ///
+    /// - watchmem 0x404010 16 : 0x404010から16バイトの監視を開始 (fs:/gs:相対アドレスも可)
+    /// - watchmem             : 監視中の領域を一覧表示
+    /// - watchmem clear       : すべての監視を解除
+    fn do_watchmem(&mut self, cmd: &[&str]) -> Result<(), DynError> {
+        match cmd.get(1..) {
+            Some([]) => {
+                if self.info.watches.is_empty() {
+                    println!("<<監視中の領域はありません>>");
+                }
+                for (i, w) in self.info.watches.iter().enumerate() {
+                    println!("{i}: {:#x} ({}バイト)", w.addr, w.len());
+                }
+            }
+            Some(["clear"]) => self.info.watches.clear(),
+            Some([addr, len]) => {
+                let regs = trace::getregs(self.info.pid)?;
+                let addr = match resolve_addr(addr, &regs) {
+                    Ok(addr) => addr,
+                    Err(msg) => {
+                        eprintln!("<<{msg}>>");
+                        return Ok(());
+                    }
+                };
+                let len = match len.parse::<usize>() {
+                    Ok(len) if (1..=MAX_WATCH_LEN).contains(&len) => len,
+                    _ => {
+                        eprintln!("<<バイト数は1から{MAX_WATCH_LEN}の整数で指定してください>>");
+                        return Ok(());
+                    }
+                };
+                match Watch::new(self.info.pid, addr, len) {
+                    Ok(w) => {
+                        println!("<<{addr:#x}から{len}バイトの監視を開始しました>>");
+                        self.info.watches.push(w);
+                    }
+                    Err(msg) => eprintln!("<<監視を開始できません : {msg}>>"),
+                }
+            }
+            _ => eprintln!("<<usage: watchmem [アドレス バイト数 | clear]>>"),
+        }
+        Ok(())
+    }
+
+    /// 監視中の領域を読み込み、前回の停止時から変更されていれば差分を表示
+    ///
+    /// stepiなどで変更された場合に備え、ソフトウェアウォッチポイントも検査する
+    fn check_watches(&mut self) {
+        self.check_sw_watches();
+        let pid = self.info.pid;
+        for w in self.info.watches.iter_mut() {
+            match w.check(pid) {
+                Ok(Some(diff)) => {
+                    println!("<<{:#x} ({}バイト)が変更されました>>", w.addr, w.len());
+                    for line in diff {
+                        println!("{line}");
+                    }
+                }
+                Ok(None) => (),
+                Err(msg) => eprintln!("<<watchmemの検査に失敗 : {msg}>>"),
+            }
+        }
+    }
+
+    /// watchコマンドを実行する
+    ///
+    /// - watch [w|rw|x] 0x404020 [バイト数] : 空いているスロットにウォッチポイントを設定。種類の省略時はw
+    /// - watch                               : 設定中のウォッチポイントを一覧表示
+    /// - watch delete N                      : ウォッチポイントNを解除
+    /// - watch clear                         : すべてのウォッチポイントを解除
+    ///
+    /// スロットが埋まっている場合、書き込みのウォッチポイントはソフトウェアウォッチポイントとして設定する。
+    /// ソフトウェアウォッチポイントの番号は、スロットの番号の後に続ける
+    fn do_watch(&mut self, cmd: &[&str]) -> Result<(), DynError> {
+        let usage = "<<usage: watch [[w|rw|x] アドレス [バイト数] | delete 番号 | clear]>>";
+        let args = match cmd.get(1..) {
+            Some([]) => {
+                if self.info.hw_watches.iter().all(Option::is_none)
+                    && self.info.sw_watches.is_empty()
+                {
+                    println!("<<ウォッチポイントはありません>>");
+                }
+                for (i, w) in self.info.hw_watches.iter().enumerate() {
+                    if let Some(w) = w {
+                        println!("{i}: {:#x} ({}バイト, {})", w.addr, w.len, w.kind);
+                    }
+                }
+                for (i, w) in self.info.sw_watches.iter().enumerate() {
+                    println!(
+                        "{}: {:#x} ({}バイト, {}, ソフトウェア)",
+                        NUM_SLOTS + i,
+                        w.addr,
+                        w.len(),
+                        WatchKind::Write
+                    );
+                }
+                return Ok(());
+            }
+            Some(["clear"]) => {
+                self.info.hw_watches = Default::default();
+                self.info.sw_watches.clear();
+                self.apply_hw_watches()?;
+                return Ok(());
+            }
+            Some(["delete", n]) => {
+                match n.parse::<usize>() {
+                    Ok(n) if n < NUM_SLOTS && self.info.hw_watches[n].is_some() => {
+                        self.info.hw_watches[n] = None;
+                        self.apply_hw_watches()?;
+                    }
+                    Ok(n) if (NUM_SLOTS..NUM_SLOTS + self.info.sw_watches.len()).contains(&n) => {
+                        self.info.sw_watches.remove(n - NUM_SLOTS);
+                    }
+                    _ => eprintln!("<<ウォッチポイント{n}は設定されていません>>"),
+                }
+                return Ok(());
+            }
+            Some(args) => args,
+            None => {
+                eprintln!("{usage}");
+                return Ok(());
+            }
+        };
+
+        let (kind, args) = match args
+            .split_first()
+            .and_then(|(k, rest)| Some((WatchKind::parse(k)?, rest)))
+        {
+            Some((kind, rest)) => (kind, rest),
+            None => (WatchKind::Write, args),
+        };
+        let (addr, len) = match args {
+            [addr] if kind == WatchKind::Exec => (addr, 1),
+            [addr] => (addr, 8),
+            [addr, len] => match len.parse::<usize>() {
+                Ok(len) => (addr, len),
+                Err(_) => {
+                    eprintln!("<<バイト数は10進数で指定してください>>");
+                    return Ok(());
+                }
+            },
+            _ => {
+                eprintln!("{usage}");
+                return Ok(());
+            }
+        };
+
+        let regs = trace::getregs(self.info.pid)?;
+        let addr = match resolve_addr(addr, &regs) {
+            Ok(addr) => addr,
+            Err(msg) => {
+                eprintln!("<<{msg}>>");
+                return Ok(());
+            }
+        };
+        let Some(slot) = self.info.hw_watches.iter().position(Option::is_none) else {
+            return self.add_sw_watch(kind, addr, len);
+        };
+        let mut w = match HwWatch::new(kind, addr, len) {
+            Ok(w) => w,
+            Err(msg) => {
+                eprintln!("<<{msg}>>");
+                return Ok(());
+            }
+        };
+        if kind != WatchKind::Exec {
+            match w.read_value(self.info.pid) {
+                Ok(val) => w.old = val,
+                Err(msg) => {
+                    eprintln!("<<{msg}>>");
+                    return Ok(());
+                }
+            }
+        }
+
+        self.info.hw_watches[slot] = Some(w);
+        if let Err(e) = self.apply_hw_watches() {
+            self.info.hw_watches[slot] = None;
+            self.apply_hw_watches()?;
+            eprintln!("<<デバッグレジスタの設定に失敗 : {e}>>");
+            return Ok(());
+        }
+        println!("<<ウォッチポイント{slot}を設定しました : {addr:#x} ({len}バイト, {kind})>>");
+        Ok(())
+    }
+
+    /// スロットが埋まっている場合に、ソフトウェアウォッチポイントを設定する
+    ///
+    /// 値の変化でしか検出できないため、書き込みのみ設定できる
+    fn add_sw_watch(&mut self, kind: WatchKind, addr: u64, len: usize) -> Result<(), DynError> {
+        if kind != WatchKind::Write {
+            eprintln!(
+                "<<ウォッチポイントは{NUM_SLOTS}個までしか設定できません\n\
+                 ソフトウェアウォッチポイントは書き込み(w)のみ設定できます>>"
+            );
+            return Ok(());
+        }
+        if !(1..=MAX_WATCH_LEN).contains(&len) {
+            eprintln!("<<バイト数は1から{MAX_WATCH_LEN}の範囲で指定してください>>");
+            return Ok(());
+        }
+        match Watch::new(self.info.pid, addr, len) {
+            Ok(w) => self.info.sw_watches.push(w),
+            Err(msg) => {
+                eprintln!("<<{msg}>>");
+                return Ok(());
+            }
+        }
+        println!(
+            "<<スロットが埋まっているため、ソフトウェアウォッチポイント{}を設定しました : {addr:#x} ({len}バイト, {kind})>>",
+            NUM_SLOTS + self.info.sw_watches.len() - 1
+        );
+        println!(
+            "<<注意 : continueは1命令ごとに停止して値を比較するため、通常より数千倍遅くなります>>"
+        );
+        Ok(())
+    }
+
+    /// ソフトウェアウォッチポイントの領域を読み込み、変更されていれば差分を表示する
+    ///
+    /// 変更されたウォッチポイントがあれば、その最初のアドレスを返す
+    fn check_sw_watches(&mut self) -> Option<u64> {
+        let pid = self.info.pid;
+        let mut first = None;
+        for (i, w) in self.info.sw_watches.iter_mut().enumerate() {
+            match w.check(pid) {
+                Ok(Some(diff)) => {
+                    first.get_or_insert(w.addr);
+                    println!(
+                        "<<ウォッチポイント{} : {:#x}が変更されました (ソフトウェア)>>",
+                        NUM_SLOTS + i,
+                        w.addr
+                    );
+                    for line in diff {
+                        println!("{line}");
+                    }
+                }
+                Ok(None) => (),
+                Err(msg) => eprintln!(
+                    "<<ウォッチポイント{}の読み込みに失敗 : {msg}>>",
+                    NUM_SLOTS + i
+                ),
+            }
+        }
+        first
+    }
+
+    /// ウォッチポイントの一覧を子プロセスのデバッグレジスタに設定する
+    fn apply_hw_watches(&self) -> Result<(), DynError> {
+        hwwatch::apply(self.info.pid, &self.info.hw_watches)?;
+        Ok(())
+    }
+
+    /// ripに実行のウォッチポイントがある場合は、そのスロットを一時的に無効にして真を返す
+    ///
+    /// 無効にした場合は、1ステップ実行した後にapply_hw_watchesで有効に戻す
+    fn mask_exec_watch(&self, rip: u64) -> Result<bool, DynError> {
+        let mut slots = self.info.hw_watches.clone();
+        let mut masked = false;
+        for slot in slots.iter_mut() {
+            if matches!(slot, Some(w) if w.kind == WatchKind::Exec && w.addr == rip) {
+                *slot = None;
+                masked = true;
+            }
+        }
+        if masked {
+            hwwatch::write_dr7(self.info.pid, hwwatch::dr7(&slots))?;
+        }
+        Ok(masked)
+    }
+
+    /// DR6を調べ、ウォッチポイントが検出したアクセスを表示する
+    ///
+    /// 書き込みと読み書きの場合は前回からの値の変化も表示する。
+    /// 検出したウォッチポイントがあれば、その最初のアドレスを返す
+    fn report_hw_watches(&mut self) -> Result<Option<u64>, DynError> {
+        let pid = self.info.pid;
+        let mut first = None;
+        for i in hwwatch::take_hits(pid)? {
+            let Some(w) = self.info.hw_watches[i].as_mut() else {
+                continue;
+            };
+            first.get_or_insert(w.addr);
+            if w.kind == WatchKind::Exec {
+                println!("<<ウォッチポイント{i} : {:#x}を実行します>>", w.addr);
+                continue;
+            }
+            match w.read_value(pid) {
+                Ok(val) if val != w.old => {
+                    println!(
+                        "<<ウォッチポイント{i} : {:#x}が変更されました ({}) : {:#x} -> {val:#x}>>",
+                        w.addr, w.kind, w.old
+                    );
+                    w.old = val;
+                }
+                Ok(val) => println!(
+                    "<<ウォッチポイント{i} : {:#x}にアクセスしました ({}) : 値 = {val:#x}>>",
+                    w.addr, w.kind
+                ),
+                Err(msg) => eprintln!("<<ウォッチポイント{i}の読み込みに失敗 : {msg}>>"),
+            }
+        }
+        Ok(first)
+    }
+
+    /// 子プロセスが終了したのでNotRunning状態に遷移
+    ///
+    /// 監視中の領域とウォッチポイントは子プロセスとともに無効になるため解除する
+    fn into_not_running(mut self) -> State {
+        println!("<<子プロセスが終了しました>>");
+        // heap onで設定したブレークポイントは共有ライブラリのアドレスのため、次の実行には引き継がない
+        let len = self.info.breaks.len();
+        self.info
+            .breaks
+            .retain(|b| !matches!(b.site, BreakSite::Heap(_)));
+        if self.info.breaks.len() < len {
+            let (count, bytes) = self.info.heap.live_total();
+            println!(
+                "<<解放されていない領域 : {count}個 ({bytes} bytes)。heapで一覧を表示します>>"
+            );
+        }
+        self.info.watches.clear();
+        self.info.hw_watches = Default::default();
+        self.info.sw_watches.clear();
+        self.info.pending_sig = None;
+        self.info.in_syscall = false;
+        // 子プロセスのメモリは失われたため、ブレークポイントは書き込まれていない状態に戻す
+        for b in self.info.breaks.iter_mut() {
+            b.orig = None;
+        }
+        State::NotRunning(ZDbg::<NotRunning> {
+            info: self.info,
+            _state: NotRunning,
+        })
     }
 }
 
-/// ヘルプを表示
-fn do_help() {
-    println!(
-        r#"コマンド一覧(括弧内は省略記法)
-        break 0x8000 : ブレークポイントを0x8000番地に設定 (b 0x8000)
-        run          : プログラムを実行 (r)
-        continue     : プログラムを再開 (c)
-        stepi        : 機械語レベルで1ステップ実行 (s)
-        registers    : レジスタを表示 (regs)
-        exit         : 終了
-        help         : このヘルプを表示 (h) "#
-    );
+/// 参照先を辿る段数のデフォルト値
+const DEFAULT_DEREF_DEPTH: usize = 2;
+
+/// 参照先を辿る段数の上限
+const MAX_DEREF_DEPTH: usize = 8;
+
+/// stackコマンドで表示するスタックの値の個数のデフォルト値
+const DEFAULT_STACK_SLOTS: u64 = 8;
+
+/// 停止するたびに表示する命令の個数のデフォルト値
+const DEFAULT_DISAS_COUNT: usize = 3;
+
+/// 停止するたびに表示する命令の個数の上限
+const MAX_DISAS_COUNT: usize = 32;
+
+/// disasコマンドで表示する命令の個数のデフォルト値
+const DEFAULT_DISAS_INSTS: usize = 8;
+
+/// disasコマンドで表示する命令の個数の上限
+const MAX_DISAS_INSTS: usize = 256;
+
+/// 引数なしのdisasコマンドで、PCより前に表示する命令の個数
+const DISAS_INSTS_BEFORE: usize = 3;
+
+/// 引数なしのdisasコマンドで、関数の先頭から解釈するPCまでのバイト数の上限
+const MAX_DISAS_PRECEDING: u64 = 0x10000;
+
+/// x86_64のglibcにおける、fsセグメント先頭からスタックカナリアへのオフセット
+const STACK_CANARY_OFFSET: u64 = 0x28;
+
+/// printで読み込む値の最大バイト数。大きな配列などはこれを超えた部分を?と表示する
+const MAX_PRINT_LEN: usize = 4096;
+
+/// heapコマンドで一覧表示する領域の個数の上限
+const MAX_HEAP_LIST: usize = 32;
+
+/// heap onで追跡中の関数のブレークポイントに到達した後の状態
+enum HeapHit {
+    Recorded, // 記録した
+    Stopped,  // 呼び出し元に戻る前に通常のブレークポイントで停止した
+    Exited,   // 子プロセスが終了した
+}
+
+/// アドレスを表す文字列を解決する
+///
+/// - 0x401000 : 16進数の絶対アドレス
+/// - fs:0x28  : fsセグメントのベースアドレス(fs_base)からの相対アドレス
+/// - gs:0x10  : gsセグメントのベースアドレス(gs_base)からの相対アドレス
+///
+/// x86_64のLinuxでは、fsはスレッドローカルストレージ(TLS)を指すために利用されている。
+/// fs_baseとgs_baseはPTRACE_GETREGSで取得できるuser_regs_structに含まれている。
+fn resolve_addr(s: &str, regs: &user_regs_struct) -> Result<u64, String> {
+    let (base, offset) = if let Some(off) = s.strip_prefix("fs:") {
+        (regs.fs_base, off)
+    } else if let Some(off) = s.strip_prefix("gs:") {
+        (regs.gs_base, off)
+    } else {
+        (0, s)
+    };
+
+    let Some(hex) = offset.strip_prefix("0x") else {
+        return Err(format!("アドレスは16進数でのみ指定可能です : {s}"));
+    };
+    let offset = u64::from_str_radix(hex, 16).map_err(|e| format!("アドレス変換エラー : {e}"))?;
+    base.checked_add(offset)
+        .ok_or_else(|| format!("アドレスが大きすぎます : {s}"))
+}
+
+/// 実行ファイルがロードされたアドレスと、実行ファイル上のアドレスの差を計算する
+///
+/// 位置独立実行形式(PIE)の場合、実行ファイル上のアドレスは0からの相対アドレスで、
+/// ASLRを無効にしていても0x555555554000などにロードされる。
+/// そこで、補助ベクタのAT_ENTRY(エントリポイントの実行時のアドレス)とELFヘッダのe_entryの差を求める。
+/// 補助ベクタを読み込めない場合は、/proc/PID/mapsから実行ファイルが最初にマップされたアドレスを取得する。
+/// PIEでない場合は実行ファイル上のアドレスがそのまま実行時のアドレスとなるので0を返す。
+fn load_bias(pid: Pid, filename: &str) -> u64 {
+    let Ok(data) = fs::read(filename) else {
+        return 0;
+    };
+    // ELFヘッダのe_type(オフセット16の2バイト)がET_DYN(3)ならPIE
+    if data.len() < 32 || u16::from_le_bytes([data[16], data[17]]) != 3 {
+        return 0;
+    }
+
+    // e_entryはオフセット24の8バイト
+    let entry = u64::from_le_bytes(data[24..32].try_into().unwrap());
+    if let Some(bias) = maps::read_auxv(pid, maps::AT_ENTRY)
+        .ok()
+        .and_then(|at_entry| at_entry.checked_sub(entry))
+    {
+        return bias;
+    }
+
+    let Ok(exe) = fs::read_link(format!("/proc/{pid}/exe")) else {
+        return 0;
+    };
+    let exe = exe.to_string_lossy();
+    maps::read_maps(pid)
+        .ok()
+        .and_then(|regions| regions.into_iter().find(|r| r.path == exe))
+        .map_or(0, |r| r.start)
 }
 
 /// レジスタを表示
@@ -430,7 +2978,8 @@ RAX: {:#016x}, RBX:{:#016x}, RCX: {:#016x}
 RDX: {:#016x}, RSI:{:#016x}, RDI: {:#016x}
  R8: {:#016x},  R9:{:#016x}, R10: {:#016x}
 R11: {:#016x}, R12:{:#016x}, R13: {:#016x} 
-R14: {:#016x}, R15:{:#016x}"#,
+R14: {:#016x}, R15:{:#016x}
+ FS: {:#016x},  GS:{:#016x}"#,
         regs.rip,
         regs.rsp,
         regs.rbp,
@@ -448,9 +2997,30 @@ R14: {:#016x}, R15:{:#016x}"#,
         regs.r13,
         regs.r14,
         regs.r15,
+        regs.fs_base,
+        regs.gs_base,
     );
 }
 
+/// xコマンド(x、またはx/16xbのように形式を付けたもの)なら真
+fn is_examine(cmd: &str) -> bool {
+    cmd == "x" || cmd.starts_with("x/")
+}
+
+/// stackコマンド(stack、またはstack/dwのように形式を付けたもの)なら真
+fn is_stack(cmd: &str) -> bool {
+    cmd == "stack" || cmd.starts_with("stack/")
+}
+
+/// breakコマンドの引数がファイル名:行番号の形式なら、ファイル名と行番号を返す
+fn parse_file_line(arg: &str) -> Option<(&str, u64)> {
+    let (file, line) = arg.rsplit_once(':')?;
+    if file.is_empty() {
+        return None;
+    }
+    Some((file, line.parse().ok()?))
+}
+
 /// コマンドからブレークポイントを計算
 fn get_break_addr(cmd: &[&str]) -> Option<*mut c_void> {
     if cmd.len() < 2 {
@@ -0,0 +1,79 @@
+//! ポインタの参照先のプレビュー
+//!
+//! レジスタやスタック上の値がポインタのように見える場合、参照先を辿って
+//! 0x7ffe...f00 -> 0x401136 -> "hello" のような参照の連鎖を表示する。
+//! 参照先の読み込みはptraceで行うため、不正なアドレスでも読み込みに失敗するだけで安全である。
+
+use crate::trace;
+use nix::unistd::Pid;
+use std::ffi::c_void;
+
+/// これ未満のアドレスはポインタとみなさない(NULLページ付近の小さな整数を除外する)
+const MIN_ADDR: u64 = 0x1000;
+
+/// 文字列としてプレビューする最大バイト数
+const MAX_STR_LEN: usize = 32;
+
+/// 文字列とみなす最小の長さ
+const MIN_STR_LEN: usize = 4;
+
+/// valから最大depth段まで参照先を辿り、参照の連鎖を表す文字列を返す
+///
+/// 参照先が表示可能な文字列の場合は文字列を表示して辿るのをやめる。
+/// 参照先が読み込めない場合もその時点で辿るのをやめる。
+pub fn deref_chain(pid: Pid, val: u64, depth: usize) -> String {
+    let mut chain = format!("{val:#x}");
+    let mut addr = val;
+    for _ in 0..depth {
+        if addr < MIN_ADDR {
+            break;
+        }
+        let Some(next) = read_u64(pid, addr) else {
+            break;
+        };
+        if let Some(s) = read_str(pid, addr) {
+            chain.push_str(&format!(" -> {s:?}"));
+            break;
+        }
+        chain.push_str(&format!(" -> {next:#x}"));
+        addr = next;
+    }
+    chain
+}
+
+/// addrから8バイト読み込む。読み込めない場合はNone
+fn read_u64(pid: Pid, addr: u64) -> Option<u64> {
+    trace::read(pid, addr as *mut c_void)
+        .ok()
+        .map(|val| val as u64)
+}
+
+/// addrから表示可能なASCII文字列を読み込む
+///
+/// MIN_STR_LEN文字以上の表示可能文字が続く場合のみ文字列とみなす。
+/// MAX_STR_LENバイトを超える場合は切り詰め、末尾に...を付ける。
+fn read_str(pid: Pid, addr: u64) -> Option<String> {
+    let mut bytes = Vec::new();
+    for off in (0..MAX_STR_LEN as u64).step_by(8) {
+        let Some(word) = read_u64(pid, addr + off) else {
+            break;
+        };
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+
+    let len = bytes
+        .iter()
+        .position(|b| !(b.is_ascii_graphic() || *b == b' '))
+        .unwrap_or(bytes.len());
+    if len < MIN_STR_LEN {
+        return None;
+    }
+    // 文字列の途中で読み込みを打ち切った場合
+    let truncated = len == bytes.len() || bytes[len] != 0;
+
+    let mut s = String::from_utf8_lossy(&bytes[..len]).into_owned();
+    if truncated {
+        s.push_str("...");
+    }
+    Some(s)
+}
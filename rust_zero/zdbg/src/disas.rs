@@ -0,0 +1,692 @@
+//! x86-64の逆アセンブラ(disas)
+//!
+//! コンパイラが生成する典型的な命令(整数演算、データ転送、分岐、スタック操作、文字列命令と、
+//! 浮動小数点数やmemcpyなどで使われる一部のSSE命令)を解釈し、Intel記法の文字列にする。
+//! 対応していない命令は(bad)として1バイトだけ読み飛ばすため、それ以降の表示は正しくない場合がある。
+
+/// 1命令の最大バイト数
+pub const MAX_INST_LEN: usize = 15;
+
+/// 逆アセンブルした命令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inst {
+    pub addr: u64,            // 命令のアドレス
+    pub len: usize,           // 命令のバイト数
+    pub text: String,         // Intel記法の命令
+    pub target: Option<u64>,  // 分岐命令の分岐先
+    pub rip_ref: Option<u64>, // rip相対で参照するメモリのアドレス
+}
+
+/// 条件分岐などの条件の名前
+const CONDS: [&str; 16] = [
+    "o", "no", "b", "ae", "e", "ne", "be", "a", "s", "ns", "p", "np", "l", "ge", "le", "g",
+];
+
+/// 0x00〜0x3fと0x80〜0x83の算術演算の名前
+const ALU: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+
+/// 0xc0、0xc1、0xd0〜0xd3のシフトと回転の名前
+const SHIFT: [&str; 8] = ["rol", "ror", "rcl", "rcr", "shl", "shr", "sal", "sar"];
+
+const REG64: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+const REG32: [&str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d",
+    "r13d", "r14d", "r15d",
+];
+const REG16: [&str; 16] = [
+    "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "r8w", "r9w", "r10w", "r11w", "r12w", "r13w",
+    "r14w", "r15w",
+];
+const REG8: [&str; 16] = [
+    "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b", "r12b",
+    "r13b", "r14b", "r15b",
+];
+/// REXプレフィックスがない場合の、4〜7番の8ビットレジスタ
+const REG8_LEGACY: [&str; 4] = ["ah", "ch", "dh", "bh"];
+
+/// codeの先頭の命令を解釈する。addrは命令のアドレス
+///
+/// 対応していない命令の場合はErr(false)、codeが途中で終わっている場合はErr(true)を返す
+pub fn decode(code: &[u8], addr: u64) -> Result<Inst, bool> {
+    let mut d = Decoder {
+        code: &code[..code.len().min(MAX_INST_LEN)],
+        addr,
+        ..Default::default()
+    };
+    let text = d.inst().ok_or(d.truncated)?;
+    let end = addr + d.pos as u64;
+    Ok(Inst {
+        addr,
+        len: d.pos,
+        text,
+        target: d.rel.map(|rel| end.wrapping_add(rel as u64)),
+        rip_ref: d.rip_disp.map(|disp| end.wrapping_add(disp as u64)),
+    })
+}
+
+/// codeをaddrから最大count個の命令に逆アセンブルする
+///
+/// 解釈できない命令は1バイトの(bad)とし、codeの末尾で途中で終わっている命令は含めない
+pub fn disassemble(code: &[u8], addr: u64, count: usize) -> Vec<Inst> {
+    let mut insts = Vec::new();
+    let mut pos = 0;
+    while insts.len() < count && pos < code.len() {
+        let inst_addr = addr + pos as u64;
+        let inst = match decode(&code[pos..], inst_addr) {
+            Ok(inst) => inst,
+            Err(true) => break,
+            Err(false) => Inst {
+                addr: inst_addr,
+                len: 1,
+                text: "(bad)".to_string(),
+                target: None,
+                rip_ref: None,
+            },
+        };
+        pos += inst.len;
+        insts.push(inst);
+    }
+    insts
+}
+
+/// ModR/Mバイトを分解したもの
+struct ModRm {
+    md: u8,  // mod。3ならr/mはレジスタ
+    reg: u8, // regフィールド。REX.Rを含む
+    op: u8,  // regフィールドの下位3ビット。/digitで命令を選ぶ場合に使う
+    rm: u8,  // r/mフィールド。REX.Bは含まない
+}
+
+/// 1命令を解釈する状態
+#[derive(Default)]
+struct Decoder<'a> {
+    code: &'a [u8],            // 命令のバイト列
+    addr: u64,                 // 命令のアドレス
+    pos: usize,                // 次に読むバイトの位置
+    rex: u8,                   // REXプレフィックス。ない場合は0
+    opsize16: bool,            // オペランドサイズプレフィックス(0x66)があるか
+    rep: Option<u8>,           // 0xf2か0xf3のプレフィックス
+    lock: bool,                // lockプレフィックス(0xf0)があるか
+    seg: Option<&'static str>, // fsかgsのセグメントプレフィックス
+    rel: Option<i64>,          // 分岐先の、次の命令からの相対アドレス
+    rip_disp: Option<i64>,     // rip相対アドレスの、次の命令からの変位
+    truncated: bool,           // 命令の途中でcodeが終わったか
+}
+
+impl Decoder<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        let b = self.code.get(self.pos).copied();
+        self.truncated = b.is_none();
+        b
+    }
+
+    /// sizeバイトのリトルエンディアンの即値を読み、符号拡張して返す
+    fn imm(&mut self, size: usize) -> Option<i64> {
+        let Some(bytes) = self.code.get(self.pos..self.pos + size) else {
+            self.truncated = true;
+            return None;
+        };
+        self.pos += size;
+        let mut buf = [0; 8];
+        buf[..size].copy_from_slice(bytes);
+        let shift = 64 - size * 8;
+        Some((i64::from_le_bytes(buf) << shift) >> shift)
+    }
+
+    /// 分岐先の相対アドレスをsizeバイト読み、分岐先を表す文字列を返す
+    ///
+    /// 分岐先の絶対アドレスは命令の末尾が決まってから計算するため、ここでは計算済みの値を返す
+    fn rel(&mut self, size: usize) -> Option<String> {
+        let rel = self.imm(size)?;
+        self.rel = Some(rel);
+        let end = self.addr + self.pos as u64;
+        Some(format!("{:#x}", end.wrapping_add(rel as u64)))
+    }
+
+    fn rex_w(&self) -> bool {
+        self.rex & 8 != 0
+    }
+
+    /// プレフィックスから決まるオペランドのバイト数
+    fn osize(&self) -> usize {
+        if self.rex_w() {
+            8
+        } else if self.opsize16 {
+            2
+        } else {
+            4
+        }
+    }
+
+    /// sizeバイトの汎用レジスタnの名前
+    fn reg(&self, n: u8, size: usize) -> String {
+        let n = n as usize;
+        match size {
+            8 => REG64[n],
+            4 => REG32[n],
+            2 => REG16[n],
+            _ if self.rex == 0 && (4..8).contains(&n) => REG8_LEGACY[n - 4],
+            _ => REG8[n],
+        }
+        .to_string()
+    }
+
+    fn modrm(&mut self) -> Option<ModRm> {
+        let b = self.byte()?;
+        let r = (self.rex & 4) << 1;
+        Some(ModRm {
+            md: b >> 6,
+            reg: (b >> 3 & 7) | r,
+            op: b >> 3 & 7,
+            rm: b & 7,
+        })
+    }
+
+    /// r/mが指すsizeバイトの汎用レジスタかメモリを表す文字列
+    ///
+    /// sizeが0の場合は、lea命令のようにメモリのサイズを表示しない
+    fn rm(&mut self, m: &ModRm, size: usize) -> Option<String> {
+        if m.md == 3 {
+            return Some(self.reg(m.rm | (self.rex & 1) << 3, size));
+        }
+        self.mem(m, size)
+    }
+
+    /// r/mが指すxmmレジスタかメモリを表す文字列
+    fn rm_xmm(&mut self, m: &ModRm, size: usize) -> Option<String> {
+        if m.md == 3 {
+            return Some(format!("xmm{}", m.rm | (self.rex & 1) << 3));
+        }
+        self.mem(m, size)
+    }
+
+    /// r/mが指すメモリを表す文字列。[base+index*scale+disp]の形式にする
+    fn mem(&mut self, m: &ModRm, size: usize) -> Option<String> {
+        let ptr = match size {
+            0 => "",
+            1 => "byte ptr ",
+            2 => "word ptr ",
+            4 => "dword ptr ",
+            8 => "qword ptr ",
+            _ => "xmmword ptr ",
+        };
+        let seg = self.seg.map(|s| format!("{s}:")).unwrap_or_default();
+        let b = (self.rex & 1) << 3;
+
+        if m.md == 0 && m.rm == 5 {
+            // rip相対アドレス
+            let disp = self.imm(4)?;
+            self.rip_disp = Some(disp);
+            return Some(format!("{ptr}{seg}[rip{}]", signed_disp(disp)));
+        }
+
+        let (base, index) = if m.rm == 4 {
+            let sib = self.byte()?;
+            let scale = 1 << (sib >> 6);
+            let idx = (sib >> 3 & 7) | (self.rex & 2) << 2;
+            let base = (sib & 7) | b;
+            let index = (idx != 4).then(|| format!("{}*{scale}", REG64[idx as usize]));
+            // baseが5でmodが0の場合は、ベースレジスタなしで32ビットの変位が続く
+            let base = (sib & 7 != 5 || m.md != 0).then_some(base);
+            (base, index)
+        } else {
+            (Some(m.rm | b), None)
+        };
+        let disp = match m.md {
+            0 if base.is_none() => self.imm(4)?,
+            0 => 0,
+            1 => self.imm(1)?,
+            _ => self.imm(4)?,
+        };
+
+        let mut s = String::new();
+        if let Some(base) = base {
+            s.push_str(REG64[base as usize]);
+        }
+        if let Some(index) = index {
+            if !s.is_empty() {
+                s.push('+');
+            }
+            s.push_str(&index);
+        }
+        if s.is_empty() {
+            s = format!("{disp:#x}");
+        } else if disp != 0 {
+            s.push_str(&signed_disp(disp));
+        }
+        Some(format!("{ptr}{seg}[{s}]"))
+    }
+
+    /// プレフィックスを読み飛ばして1命令を解釈する
+    fn inst(&mut self) -> Option<String> {
+        loop {
+            match self.peek()? {
+                0x66 => self.opsize16 = true,
+                b @ (0xf2 | 0xf3) => self.rep = Some(b),
+                0xf0 => self.lock = true,
+                0x64 => self.seg = Some("fs"),
+                0x65 => self.seg = Some("gs"),
+                0x26 | 0x2e | 0x36 | 0x3e => (), // 64ビットモードでは無視される
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        if let Some(b @ 0x40..=0x4f) = self.peek() {
+            self.rex = b;
+            self.pos += 1;
+        }
+
+        let op = self.byte()?;
+        let text = if op == 0x0f {
+            self.two_byte()?
+        } else {
+            self.one_byte(op)?
+        };
+        Some(if self.lock {
+            format!("lock {text}")
+        } else {
+            text
+        })
+    }
+
+    /// op E, G の形式の命令
+    fn op_e_g(&mut self, name: &str, size: usize) -> Option<String> {
+        let m = self.modrm()?;
+        let e = self.rm(&m, size)?;
+        Some(format!("{name} {e}, {}", self.reg(m.reg, size)))
+    }
+
+    /// op G, E の形式の命令
+    fn op_g_e(&mut self, name: &str, size: usize) -> Option<String> {
+        let m = self.modrm()?;
+        let e = self.rm(&m, size)?;
+        Some(format!("{name} {}, {e}", self.reg(m.reg, size)))
+    }
+
+    /// movzxやmovsxのように、G(オペランドサイズ)とsrcバイトのEを取る命令
+    fn op_g_e_ext(&mut self, name: &str, src: usize) -> Option<String> {
+        let m = self.modrm()?;
+        let e = self.rm(&m, src)?;
+        Some(format!("{name} {}, {e}", self.reg(m.reg, self.osize())))
+    }
+
+    /// 1バイトのオペコードの命令
+    fn one_byte(&mut self, op: u8) -> Option<String> {
+        let osize = self.osize();
+        let b = (self.rex & 1) << 3;
+        let text = match op {
+            0x00..=0x3f if op & 7 < 6 => {
+                let name = ALU[(op >> 3) as usize];
+                match op & 7 {
+                    0 => self.op_e_g(name, 1)?,
+                    1 => self.op_e_g(name, osize)?,
+                    2 => self.op_g_e(name, 1)?,
+                    3 => self.op_g_e(name, osize)?,
+                    4 => format!("{name} al, {}", hex(self.imm(1)?)),
+                    _ => format!(
+                        "{name} {}, {}",
+                        self.reg(0, osize),
+                        hex(self.imm(osize.min(4))?)
+                    ),
+                }
+            }
+            0x50..=0x57 => format!("push {}", REG64[((op & 7) | b) as usize]),
+            0x58..=0x5f => format!("pop {}", REG64[((op & 7) | b) as usize]),
+            0x63 => self.op_g_e_ext("movsxd", 4)?,
+            0x68 => format!("push {}", hex(self.imm(4)?)),
+            0x6a => format!("push {}", hex(self.imm(1)?)),
+            0x69 | 0x6b => {
+                let m = self.modrm()?;
+                let e = self.rm(&m, osize)?;
+                let imm = self.imm(if op == 0x69 { osize.min(4) } else { 1 })?;
+                format!("imul {}, {e}, {}", self.reg(m.reg, osize), hex(imm))
+            }
+            0x70..=0x7f => format!("j{} {}", CONDS[(op & 0xf) as usize], self.rel(1)?),
+            0x80..=0x83 => {
+                let size = if op == 0x80 { 1 } else { osize };
+                let m = self.modrm()?;
+                let e = self.rm(&m, size)?;
+                let imm = self.imm(if op == 0x81 { size.min(4) } else { 1 })?;
+                format!("{} {e}, {}", ALU[m.op as usize], hex(imm))
+            }
+            0x84 => self.op_e_g("test", 1)?,
+            0x85 => self.op_e_g("test", osize)?,
+            0x86 => self.op_e_g("xchg", 1)?,
+            0x87 => self.op_e_g("xchg", osize)?,
+            0x88 => self.op_e_g("mov", 1)?,
+            0x89 => self.op_e_g("mov", osize)?,
+            0x8a => self.op_g_e("mov", 1)?,
+            0x8b => self.op_g_e("mov", osize)?,
+            0x8d => {
+                let m = self.modrm()?;
+                if m.md == 3 {
+                    return None;
+                }
+                let e = self.mem(&m, 0)?;
+                format!("lea {}, {e}", self.reg(m.reg, osize))
+            }
+            0x8f => {
+                let m = self.modrm()?;
+                if m.op != 0 {
+                    return None;
+                }
+                format!("pop {}", self.rm(&m, 8)?)
+            }
+            0x90 if self.rep == Some(0xf3) => "pause".to_string(),
+            0x90 if b == 0 => "nop".to_string(),
+            0x90..=0x97 => format!(
+                "xchg {}, {}",
+                self.reg((op & 7) | b, osize),
+                self.reg(0, osize)
+            ),
+            0x98 => match osize {
+                8 => "cdqe",
+                2 => "cbw",
+                _ => "cwde",
+            }
+            .to_string(),
+            0x99 => match osize {
+                8 => "cqo",
+                2 => "cwd",
+                _ => "cdq",
+            }
+            .to_string(),
+            0xa4..=0xa7 | 0xaa..=0xaf => {
+                let name = match op {
+                    0xa4 | 0xa5 => "movs",
+                    0xa6 | 0xa7 => "cmps",
+                    0xaa | 0xab => "stos",
+                    0xac | 0xad => "lods",
+                    _ => "scas",
+                };
+                let suffix = match if op & 1 == 0 { 1 } else { osize } {
+                    1 => 'b',
+                    2 => 'w',
+                    4 => 'd',
+                    _ => 'q',
+                };
+                // cmpsとscasは条件付きの繰り返しになる
+                let rep = match (self.rep, name) {
+                    (Some(0xf3), "cmps" | "scas") => "repe ",
+                    (Some(0xf2), "cmps" | "scas") => "repne ",
+                    (Some(_), _) => "rep ",
+                    (None, _) => "",
+                };
+                format!("{rep}{name}{suffix}")
+            }
+            0xa8 => format!("test al, {}", hex(self.imm(1)?)),
+            0xa9 => format!(
+                "test {}, {}",
+                self.reg(0, osize),
+                hex(self.imm(osize.min(4))?)
+            ),
+            0xb0..=0xb7 => format!("mov {}, {}", self.reg((op & 7) | b, 1), hex(self.imm(1)?)),
+            0xb8..=0xbf if osize == 8 => format!(
+                "movabs {}, {}",
+                self.reg((op & 7) | b, 8),
+                hex(self.imm(8)?)
+            ),
+            0xb8..=0xbf => format!(
+                "mov {}, {}",
+                self.reg((op & 7) | b, osize),
+                hex(self.imm(osize)?)
+            ),
+            0xc0 | 0xc1 | 0xd0..=0xd3 => {
+                let size = if op & 1 == 0 { 1 } else { osize };
+                let m = self.modrm()?;
+                let e = self.rm(&m, size)?;
+                let count = match op {
+                    0xc0 | 0xc1 => hex(self.imm(1)?),
+                    0xd0 | 0xd1 => "1".to_string(),
+                    _ => "cl".to_string(),
+                };
+                format!("{} {e}, {count}", SHIFT[m.op as usize])
+            }
+            0xc2 => format!("ret {:#x}", self.imm(2)? as u16),
+            0xc3 => "ret".to_string(),
+            0xc6 | 0xc7 => {
+                let size = if op == 0xc6 { 1 } else { osize };
+                let m = self.modrm()?;
+                if m.op != 0 {
+                    return None;
+                }
+                let e = self.rm(&m, size)?;
+                format!("mov {e}, {}", hex(self.imm(size.min(4))?))
+            }
+            0xc9 => "leave".to_string(),
+            0xcc => "int3".to_string(),
+            0xcd => format!("int {:#x}", self.imm(1)? as u8),
+            0xe8 => format!("call {}", self.rel(4)?),
+            0xe9 => format!("jmp {}", self.rel(4)?),
+            0xeb => format!("jmp {}", self.rel(1)?),
+            0xf4 => "hlt".to_string(),
+            0xf5 => "cmc".to_string(),
+            0xf8 => "clc".to_string(),
+            0xf9 => "stc".to_string(),
+            0xfc => "cld".to_string(),
+            0xfd => "std".to_string(),
+            0xf6 | 0xf7 => {
+                let size = if op == 0xf6 { 1 } else { osize };
+                let m = self.modrm()?;
+                let e = self.rm(&m, size)?;
+                match m.op {
+                    0 | 1 => format!("test {e}, {}", hex(self.imm(size.min(4))?)),
+                    n => {
+                        let name = ["", "", "not", "neg", "mul", "imul", "div", "idiv"][n as usize];
+                        format!("{name} {e}")
+                    }
+                }
+            }
+            0xfe | 0xff => {
+                let m = self.modrm()?;
+                let (name, size) = match (op, m.op) {
+                    (0xfe, 0) => ("inc", 1),
+                    (0xfe, 1) => ("dec", 1),
+                    (0xff, 0) => ("inc", osize),
+                    (0xff, 1) => ("dec", osize),
+                    // 64ビットモードでは、間接分岐とpushのオペランドは8バイト
+                    (0xff, 2) => ("call", 8),
+                    (0xff, 4) => ("jmp", 8),
+                    (0xff, 6) => ("push", 8),
+                    _ => return None,
+                };
+                format!("{name} {}", self.rm(&m, size)?)
+            }
+            _ => return None,
+        };
+        Some(text)
+    }
+
+    /// 0x0fから始まる2バイトのオペコードの命令
+    fn two_byte(&mut self) -> Option<String> {
+        let op = self.byte()?;
+        let text = match op {
+            0x05 => "syscall".to_string(),
+            0x0b => "ud2".to_string(),
+            0x31 => "rdtsc".to_string(),
+            0xa2 => "cpuid".to_string(),
+            0x1e if self.rep == Some(0xf3) && self.peek() == Some(0xfa) => {
+                self.pos += 1;
+                "endbr64".to_string()
+            }
+            0x1f => {
+                let m = self.modrm()?;
+                format!("nop {}", self.rm(&m, self.osize())?)
+            }
+            0x10 | 0x11 => {
+                let (name, size) = match (self.rep, self.opsize16) {
+                    (Some(0xf3), _) => ("movss", 4),
+                    (Some(_), _) => ("movsd", 8),
+                    (None, true) => ("movupd", 16),
+                    (None, false) => ("movups", 16),
+                };
+                self.sse_move(name, size, op == 0x11)?
+            }
+            0x28 | 0x29 if self.rep.is_none() => {
+                let name = if self.opsize16 { "movapd" } else { "movaps" };
+                self.sse_move(name, 16, op == 0x29)?
+            }
+            0x6f | 0x7f if self.opsize16 || self.rep == Some(0xf3) => {
+                let name = if self.opsize16 { "movdqa" } else { "movdqu" };
+                self.sse_move(name, 16, op == 0x7f)?
+            }
+            0x57 if self.rep.is_none() => {
+                let name = if self.opsize16 { "xorpd" } else { "xorps" };
+                self.sse_move(name, 16, false)?
+            }
+            0xef if self.opsize16 => self.sse_move("pxor", 16, false)?,
+            0xd6 if self.opsize16 => self.sse_move("movq", 8, true)?,
+            0x7e if self.rep == Some(0xf3) => self.sse_move("movq", 8, false)?,
+            0x6e | 0x7e if self.opsize16 => {
+                // 汎用レジスタかメモリとxmmレジスタの間の転送
+                let (name, size) = if self.rex_w() {
+                    ("movq", 8)
+                } else {
+                    ("movd", 4)
+                };
+                let m = self.modrm()?;
+                let e = self.rm(&m, size)?;
+                let x = format!("xmm{}", m.reg);
+                if op == 0x6e {
+                    format!("{name} {x}, {e}")
+                } else {
+                    format!("{name} {e}, {x}")
+                }
+            }
+            0x40..=0x4f => {
+                let name = format!("cmov{}", CONDS[(op & 0xf) as usize]);
+                self.op_g_e(&name, self.osize())?
+            }
+            0x80..=0x8f => format!("j{} {}", CONDS[(op & 0xf) as usize], self.rel(4)?),
+            0x90..=0x9f => {
+                let m = self.modrm()?;
+                format!("set{} {}", CONDS[(op & 0xf) as usize], self.rm(&m, 1)?)
+            }
+            0xaf => self.op_g_e("imul", self.osize())?,
+            0xb6 => self.op_g_e_ext("movzx", 1)?,
+            0xb7 => self.op_g_e_ext("movzx", 2)?,
+            0xbe => self.op_g_e_ext("movsx", 1)?,
+            0xbf => self.op_g_e_ext("movsx", 2)?,
+            _ => return None,
+        };
+        Some(text)
+    }
+
+    /// xmmレジスタとxmmレジスタかsizeバイトのメモリの間の命令
+    ///
+    /// storeが真の場合は、r/m側を転送先とする
+    fn sse_move(&mut self, name: &str, size: usize, store: bool) -> Option<String> {
+        let m = self.modrm()?;
+        let e = self.rm_xmm(&m, size)?;
+        let x = format!("xmm{}", m.reg);
+        Some(if store {
+            format!("{name} {e}, {x}")
+        } else {
+            format!("{name} {x}, {e}")
+        })
+    }
+}
+
+/// 即値を16進数で表す。負の値は-を付ける
+fn hex(v: i64) -> String {
+    if v < 0 {
+        format!("-{:#x}", v.unsigned_abs())
+    } else {
+        format!("{v:#x}")
+    }
+}
+
+/// アドレスの変位を、+0x10や-0x8の形式で表す
+fn signed_disp(disp: i64) -> String {
+    if disp < 0 {
+        format!("-{:#x}", disp.unsigned_abs())
+    } else {
+        format!("+{disp:#x}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(code: &[u8]) -> String {
+        decode(code, 0x401000).map(|i| i.text).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(text(&[0x55]), "push rbp");
+        assert_eq!(text(&[0x41, 0x57]), "push r15");
+        assert_eq!(text(&[0x48, 0x89, 0xe5]), "mov rbp, rsp");
+        assert_eq!(text(&[0x48, 0x83, 0xec, 0x10]), "sub rsp, 0x10");
+        assert_eq!(
+            text(&[0xc7, 0x45, 0xfc, 0, 0, 0, 0]),
+            "mov dword ptr [rbp-0x4], 0x0"
+        );
+        assert_eq!(
+            text(&[0x64, 0x48, 0x8b, 0x04, 0x25, 0x28, 0, 0, 0]),
+            "mov rax, qword ptr fs:[0x28]"
+        );
+        assert_eq!(
+            text(&[0x48, 0x8d, 0x44, 0x8b, 0x08]),
+            "lea rax, [rbx+rcx*4+0x8]"
+        );
+        assert_eq!(
+            text(&[0x0f, 0xb6, 0x45, 0xff]),
+            "movzx eax, byte ptr [rbp-0x1]"
+        );
+        assert_eq!(text(&[0x40, 0x88, 0xf0]), "mov al, sil");
+        assert_eq!(text(&[0x88, 0xf0]), "mov al, dh");
+        assert_eq!(text(&[0xd1, 0xe0]), "shl eax, 1");
+        assert_eq!(text(&[0xf3, 0x0f, 0x1e, 0xfa]), "endbr64");
+        assert_eq!(text(&[0x66, 0x0f, 0xef, 0xc0]), "pxor xmm0, xmm0");
+        assert_eq!(text(&[0xf3, 0x48, 0xab]), "rep stosq");
+        assert_eq!(text(&[0x0f, 0x05]), "syscall");
+        assert_eq!(text(&[0xc3]), "ret");
+
+        // 64ビットモードで無効な命令と、途中で終わっている命令
+        assert_eq!(decode(&[0x06], 0x401000), Err(false));
+        assert_eq!(decode(&[0x48, 0x89], 0x401000), Err(true));
+    }
+
+    #[test]
+    fn test_target() {
+        // 分岐先は次の命令からの相対アドレス
+        let call = decode(&[0xe8, 0xde, 0xff, 0xff, 0xff], 0x401150).unwrap();
+        assert_eq!((call.text.as_str(), call.len), ("call 0x401133", 5));
+        assert_eq!(call.target, Some(0x401133));
+        let jne = decode(&[0x75, 0x02], 0x401000).unwrap();
+        assert_eq!(
+            (jne.text.as_str(), jne.target),
+            ("jne 0x401004", Some(0x401004))
+        );
+
+        // rip相対アドレスは、即値を含めた命令の末尾からの変位
+        let add = decode(&[0x83, 0x05, 0xe6, 0x2e, 0, 0, 0x0a], 0x401126).unwrap();
+        assert_eq!(add.text, "add dword ptr [rip+0x2ee6], 0xa");
+        assert_eq!(add.rip_ref, Some(0x404013));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let code = [0x55, 0x48, 0x89, 0xe5, 0x06, 0x5d, 0xc3, 0x48];
+        let insts = disassemble(&code, 0x401000, 10);
+        let texts: Vec<&str> = insts.iter().map(|i| i.text.as_str()).collect();
+        // 解釈できないバイトは(bad)とし、末尾の途中で終わっている命令は含めない
+        assert_eq!(
+            texts,
+            vec!["push rbp", "mov rbp, rsp", "(bad)", "pop rbp", "ret"]
+        );
+
+        assert_eq!(disassemble(&code, 0x401000, 2).len(), 2);
+    }
+}
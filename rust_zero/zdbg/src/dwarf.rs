@@ -0,0 +1,213 @@
+use crate::helper::DynError;
+use gimli::{EndianSlice, RunTimeEndian};
+use object::{Object, ObjectSection};
+use std::{
+    borrow::Cow,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// ソースコード上の位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: PathBuf, // ソースファイル
+    pub line: u64,     // 行番号
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
+/// 行番号テーブルの1行
+#[derive(Debug)]
+struct LineRow {
+    addr: u64,          // 命令のアドレス(実行ファイル上のアドレス)
+    loc: Location,      // 対応するソースコード上の位置
+    end_sequence: bool, // 命令列の終端を表す行なら真
+}
+
+/// DWARFの.debug_lineから生成した、アドレスからソースコード上の位置へのテーブル
+///
+/// .debug_lineには、コンパイル単位ごとに行番号プログラムと呼ばれる
+/// 小さなバイトコードが格納されており、これを実行するとアドレスと行番号の対応表が得られる。
+/// 行番号プログラムの実行はgimliクレートに任せ、ここでは結果をアドレス順に並べて保持する。
+#[derive(Debug)]
+pub struct LineTable {
+    rows: Vec<LineRow>, // アドレス順にソートされた行
+}
+
+impl LineTable {
+    /// 実行ファイルを読み込み、行番号テーブルを生成
+    pub fn load(filename: &str) -> Result<Self, DynError> {
+        let data = fs::read(filename)?;
+        let obj = object::File::parse(&*data)?;
+        let endian = if obj.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+
+        // DWARFの各セクションを読み込む。存在しないセクションは空として扱う
+        let load_section = |id: gimli::SectionId| -> Result<Cow<[u8]>, gimli::Error> {
+            Ok(obj
+                .section_by_name(id.name())
+                .and_then(|s| s.uncompressed_data().ok())
+                .unwrap_or(Cow::Borrowed(&[])))
+        };
+        let dwarf_cow = gimli::Dwarf::load(load_section)?;
+        let dwarf = dwarf_cow.borrow(|section| EndianSlice::new(section, endian));
+
+        let mut rows = Vec::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+
+            // 行番号プログラムを実行
+            let mut lines = program.rows();
+            while let Some((header, row)) = lines.next_row()? {
+                if row.end_sequence() {
+                    rows.push(LineRow {
+                        addr: row.address(),
+                        loc: Location {
+                            file: PathBuf::new(),
+                            line: 0,
+                        },
+                        end_sequence: true,
+                    });
+                    continue;
+                }
+
+                // 行番号0はソースコード上の位置と対応しない命令
+                let line = match row.line() {
+                    Some(line) => line.get(),
+                    None => continue,
+                };
+
+                let mut file = PathBuf::new();
+                if let Some(entry) = row.file(header) {
+                    // ディレクトリ + ファイル名でパスを構成する
+                    // ディレクトリが相対パスの場合はコンパイル時のディレクトリからの相対パス
+                    if let Some(comp_dir) = &unit.comp_dir {
+                        file.push(comp_dir.to_string_lossy().as_ref());
+                    }
+                    if let Some(dir) = entry.directory(header) {
+                        let dir = dwarf.attr_string(&unit, dir)?;
+                        file.push(dir.to_string_lossy().as_ref());
+                    }
+                    let name = dwarf.attr_string(&unit, entry.path_name())?;
+                    file.push(name.to_string_lossy().as_ref());
+                }
+
+                rows.push(LineRow {
+                    addr: row.address(),
+                    loc: Location { file, line },
+                    end_sequence: false,
+                });
+            }
+        }
+
+        // 同じアドレスでは命令列の終端が先になるように並べ、
+        // 次の命令列の先頭が優先して検索されるようにする
+        rows.sort_by_key(|r| (r.addr, !r.end_sequence));
+        Ok(LineTable { rows })
+    }
+
+    /// 実行ファイル上のアドレスに対応するソースコード上の位置を返す
+    ///
+    /// addr以下で最大のアドレスを持つ行を返す。
+    /// それが命令列の終端だった場合は、対応する位置は存在しない。
+    pub fn find(&self, addr: u64) -> Option<&Location> {
+        let idx = self.rows.partition_point(|r| r.addr <= addr);
+        let row = self.rows.get(idx.checked_sub(1)?)?;
+        if row.end_sequence {
+            None
+        } else {
+            Some(&row.loc)
+        }
+    }
+
+    /// 関数の先頭アドレスaddrから、プロローグの直後のアドレスを返す
+    ///
+    /// コンパイラは関数の先頭と、プロローグを終えた最初の文の位置にそれぞれ行を出力するため、
+    /// 先頭の次の行のアドレスを用いる。addrが行の先頭でない場合や、次の行がない場合はNone
+    pub fn prologue_end(&self, addr: u64) -> Option<u64> {
+        let idx = self.rows.partition_point(|r| r.addr < addr);
+        self.rows
+            .get(idx)
+            .filter(|r| r.addr == addr && !r.end_sequence)?;
+        self.rows[idx..]
+            .iter()
+            .find(|r| r.addr > addr)
+            .filter(|r| !r.end_sequence)
+            .map(|r| r.addr)
+    }
+
+    /// ソースファイルfileのline行目に対応する実行ファイル上のアドレスと、その位置を返す
+    ///
+    /// fileはパスの末尾と比較するため、ディレクトリを省略してファイル名のみでも指定できる。
+    /// line行目に命令がない場合は、それ以降で命令がある最初の行を用いる。
+    /// 1行に複数の命令列が対応する場合は、最小のアドレスを返す
+    pub fn find_addr(&self, file: &str, line: u64) -> Option<(u64, &Location)> {
+        let file = Path::new(file);
+        self.rows
+            .iter()
+            .filter(|r| !r.end_sequence && r.loc.line >= line && r.loc.file.ends_with(file))
+            .min_by_key(|r| (r.loc.line, r.addr))
+            .map(|r| (r.addr, &r.loc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(addr: u64, file: &str, line: u64) -> LineRow {
+        LineRow {
+            addr,
+            loc: Location {
+                file: PathBuf::from(file),
+                line,
+            },
+            end_sequence: false,
+        }
+    }
+
+    #[test]
+    fn test_find_addr() {
+        let mut end = row(0x1180, "", 0);
+        end.end_sequence = true;
+        let table = LineTable {
+            rows: vec![
+                row(0x1130, "/src/util.c", 3),
+                row(0x1139, "/src/main.c", 5),
+                row(0x1140, "/src/main.c", 7),
+                row(0x1150, "/src/main.c", 5),
+                row(0x1160, "/src/util.c", 9),
+                end,
+            ],
+        };
+
+        let addr = |file, line| table.find_addr(file, line).map(|(a, l)| (a, l.line));
+        assert_eq!(addr("main.c", 5), Some((0x1139, 5)));
+        assert_eq!(addr("/src/main.c", 7), Some((0x1140, 7)));
+        // 命令のない行はその次の行に設定する
+        assert_eq!(addr("main.c", 6), Some((0x1140, 7)));
+        assert_eq!(addr("util.c", 4), Some((0x1160, 9)));
+        assert_eq!(addr("main.c", 8), None);
+        // パスの途中の一部分とは一致しない
+        assert_eq!(addr("ain.c", 5), None);
+
+        assert_eq!(table.find(0x1145).map(|l| l.line), Some(7));
+        assert_eq!(table.find(0x1180), None);
+
+        // プロローグの直後は関数の先頭の次の行
+        assert_eq!(table.prologue_end(0x1139), Some(0x1140));
+        assert_eq!(table.prologue_end(0x113a), None);
+        assert_eq!(table.prologue_end(0x1160), None);
+    }
+}
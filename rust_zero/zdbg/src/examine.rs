@@ -0,0 +1,202 @@
+//! メモリの内容の表示(x)
+//!
+//! gdbのxコマンドと同様に、`x/16xb 0x404010`のように個数、表示形式、単位を指定してメモリを表示する。
+//! 形式、単位、バイト順は順不同で、省略した場合は1個、16進数、4バイト単位、リトルエンディアンとする。
+//! 文字列(s)の場合は単位を使わず、NUL文字までを1個の文字列として指定個数表示する。
+
+use crate::{
+    render::{Radix, Render},
+    trace,
+    watch::read_mem,
+};
+use nix::unistd::Pid;
+use std::ffi::c_void;
+
+/// 一度に表示できる最大の個数
+pub const MAX_EXAMINE_COUNT: usize = 1024;
+
+/// 文字列として読み込む最大バイト数。これを超える場合は切り詰める
+const MAX_STR_LEN: usize = 256;
+
+/// x/の後に指定する、個数、表示形式、単位、バイト順
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    count: usize,   // 表示する個数
+    string: bool,   // NUL終端文字列として表示するか (s)
+    render: Render, // 文字列以外の場合の表示形式
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format {
+            count: 1,
+            string: false,
+            render: Render::new(Radix::Hex, 4),
+        }
+    }
+}
+
+impl Format {
+    /// x/16xbの16xbの部分を解析する
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut fmt = Format::default();
+        let digits = spec
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(spec.len());
+        if digits > 0 {
+            fmt.count = match spec[..digits].parse() {
+                Ok(n) if (1..=MAX_EXAMINE_COUNT).contains(&n) => n,
+                _ => {
+                    return Err(format!(
+                        "個数は1から{MAX_EXAMINE_COUNT}の整数で指定してください"
+                    ))
+                }
+            };
+        }
+        for c in spec[digits..].chars() {
+            if c == 's' {
+                fmt.string = true;
+            } else if fmt.render.set_flag(c) {
+                // 後に指定した形式を優先する
+                if Radix::parse(c).is_some() {
+                    fmt.string = false;
+                }
+            } else {
+                return Err(format!("不明な形式です : {c}"));
+            }
+        }
+        Ok(fmt)
+    }
+}
+
+/// 子プロセスのaddrから、形式fmtでメモリの内容を表示する行を返す
+pub fn examine(pid: Pid, addr: u64, fmt: &Format) -> Result<Vec<String>, String> {
+    if fmt.string {
+        let mut lines = Vec::new();
+        let mut addr = addr;
+        for _ in 0..fmt.count {
+            let (s, len) = read_cstr(pid, addr)?;
+            lines.push(format!("{addr:#x}: {s}"));
+            addr += len as u64;
+        }
+        return Ok(lines);
+    }
+    let data = read_mem(pid, addr, fmt.count * fmt.render.size)?;
+    Ok(format_units(addr, &data, fmt))
+}
+
+/// 1行に表示する個数。1行が16バイト程度になるようにする
+fn per_line(size: usize) -> usize {
+    match size {
+        1 | 2 => 8,
+        4 => 4,
+        _ => 2,
+    }
+}
+
+/// addrから読み込んだdataを、単位ごとに区切って表示する行を返す
+fn format_units(addr: u64, data: &[u8], fmt: &Format) -> Vec<String> {
+    let size = fmt.render.size;
+    let n = per_line(size);
+    data.chunks(size * n)
+        .enumerate()
+        .map(|(i, row)| {
+            let row_addr = addr + (i * size * n) as u64;
+            format!("{row_addr:#x}: {}", fmt.render.units(row).join(" "))
+        })
+        .collect()
+}
+
+/// addrからNUL文字までを読み込み、エスケープした文字列とNUL文字を含めたバイト数を返す
+///
+/// MAX_STR_LENバイトまでにNUL文字がない場合は切り詰め、末尾に...を付ける
+fn read_cstr(pid: Pid, addr: u64) -> Result<(String, usize), String> {
+    let mut bytes = Vec::new();
+    while bytes.len() < MAX_STR_LEN {
+        let read_addr = addr + bytes.len() as u64;
+        let word = trace::read(pid, read_addr as *mut c_void)
+            .map_err(|e| format!("{read_addr:#x}の読み込みに失敗 : {e}"))?;
+        bytes.extend_from_slice(&word.to_le_bytes());
+        if let Some(len) = bytes.iter().position(|b| *b == 0) {
+            return Ok((escape(&bytes[..len]), len + 1));
+        }
+    }
+    let s = format!("{}...", escape(&bytes[..MAX_STR_LEN]));
+    Ok((s, MAX_STR_LEN))
+}
+
+/// バイト列を、表示できない文字をエスケープした"..."の形式にする
+fn escape(bytes: &[u8]) -> String {
+    let s: String = bytes
+        .iter()
+        .flat_map(|b| std::ascii::escape_default(*b))
+        .map(char::from)
+        .collect();
+    format!("\"{s}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Format::parse(""), Ok(Format::default()));
+        assert_eq!(
+            Format::parse("16xb"),
+            Ok(Format {
+                count: 16,
+                string: false,
+                render: Render::new(Radix::Hex, 1)
+            })
+        );
+        // 表示形式、単位、バイト順は順不同
+        assert_eq!(Format::parse("2gd"), Format::parse("2dg"));
+        assert_eq!(Format::parse("2>ut"), Format::parse("2t>"));
+        assert_eq!(Format::parse("3s").map(|f| f.string), Ok(true));
+        assert_eq!(Format::parse("sx").map(|f| f.string), Ok(false));
+        assert!(Format::parse("0x").is_err());
+        assert!(Format::parse("4q").is_err());
+    }
+
+    #[test]
+    fn test_format_units() {
+        let data: Vec<u8> = vec![0x41, 0x42, 0xff, 0xff, 1, 0, 0, 0, 0x10, 0, 0, 0];
+        let fmt = |spec| Format::parse(spec).unwrap();
+
+        assert_eq!(
+            format_units(0x404010, &data, &fmt("12xb")),
+            vec![
+                "0x404010: 0x41 0x42 0xff 0xff 0x01 0x00 0x00 0x00",
+                "0x404018: 0x10 0x00 0x00 0x00",
+            ]
+        );
+        assert_eq!(
+            format_units(0x404010, &data, &fmt("3xw")),
+            vec!["0x404010: 0xffff4241 0x00000001 0x00000010"]
+        );
+        // 符号付きの10進数で表示する
+        assert_eq!(
+            format_units(0x404010, &data[..4], &fmt("2dh")),
+            vec!["0x404010: 16961 -1"]
+        );
+        assert_eq!(
+            format_units(0x404010, &data[..8], &fmt("dg")),
+            vec!["0x404010: 8589886017"]
+        );
+        // ビッグエンディアンで、符号なしの10進数と2進数で表示する
+        assert_eq!(
+            format_units(0x404010, &data[..4], &fmt("2uh>")),
+            vec!["0x404010: 16706 65535"]
+        );
+        assert_eq!(
+            format_units(0x404014, &data[4..6], &fmt("2tb")),
+            vec!["0x404014: 0b00000001 0b00000000"]
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(b"hello\n\x01"), "\"hello\\n\\x01\"");
+    }
+}
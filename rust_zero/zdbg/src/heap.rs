@@ -0,0 +1,226 @@
+//! malloc/freeのブレークポイントによるヒープの追跡(heap)
+//!
+//! 共有ライブラリを含め、子プロセスにマップされたファイルのシンボルからmallocなどのアドレスを求め、
+//! そこに通常のブレークポイントを設定する。確保関数では引数の大きさと呼び出し元のアドレスを、
+//! 呼び出し元に戻った時点のraxから確保した領域のアドレスを記録し、freeでは引数の領域を記録から取り除く。
+//! 終了時に残っている領域が、解放されなかった(リークした)領域となる。
+//! 追跡を始める前に確保された領域のfreeは、追跡していない領域の解放として数えるのみとする。
+
+use crate::{maps::Region, symbol::SymbolTable};
+use std::collections::BTreeMap;
+
+/// 追跡する関数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapFn {
+    Malloc,  // malloc(size)
+    Calloc,  // calloc(nmemb, size)
+    Realloc, // realloc(ptr, size)
+    Free,    // free(ptr)
+}
+
+impl HeapFn {
+    pub const ALL: [HeapFn; 4] = [
+        HeapFn::Malloc,
+        HeapFn::Calloc,
+        HeapFn::Realloc,
+        HeapFn::Free,
+    ];
+
+    /// 関数名
+    pub fn name(self) -> &'static str {
+        match self {
+            HeapFn::Malloc => "malloc",
+            HeapFn::Calloc => "calloc",
+            HeapFn::Realloc => "realloc",
+            HeapFn::Free => "free",
+        }
+    }
+}
+
+/// 子プロセスにマップされたファイルからmallocを定義するものを探し、追跡する関数の実行時のアドレスを返す
+///
+/// regionsは/proc/PID/mapsの内容。ファイルは最初にマップされた順に探すため、
+/// 静的リンクされた実行ファイルでは実行ファイル自身の、そうでなければlibcの関数となる
+pub fn resolve(regions: &[Region]) -> Result<Vec<(HeapFn, u64)>, String> {
+    let mut paths: Vec<&str> = Vec::new();
+    for r in regions
+        .iter()
+        .filter(|r| r.is_exec() && r.path.starts_with('/'))
+    {
+        if !paths.contains(&r.path.as_str()) {
+            paths.push(&r.path);
+        }
+    }
+    for path in paths {
+        let Ok(symbols) = SymbolTable::load(path) else {
+            continue;
+        };
+        if symbols.find(HeapFn::Malloc.name()).is_none() {
+            continue;
+        }
+        let bias = file_bias(regions, path);
+        return Ok(HeapFn::ALL
+            .into_iter()
+            .filter_map(|f| Some((f, symbols.find(f.name())?.addr + bias)))
+            .collect());
+    }
+    Err("mallocを定義するファイルがマップされていません".to_string())
+}
+
+/// pathのファイル上のアドレスと実行時のアドレスの差
+///
+/// 位置独立(ET_DYN)のファイルは、最初にマップされた領域の先頭を0番地とみなす
+fn file_bias(regions: &[Region], path: &str) -> u64 {
+    let is_dyn = std::fs::read(path)
+        .map(|data| data.len() > 17 && u16::from_le_bytes([data[16], data[17]]) == 3)
+        .unwrap_or(false);
+    if !is_dyn {
+        return 0;
+    }
+    regions
+        .iter()
+        .filter(|r| r.path == path)
+        .map(|r| r.start)
+        .min()
+        .unwrap_or(0)
+}
+
+/// 確保された領域
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alloc {
+    pub addr: u64, // 領域の先頭
+    pub size: u64, // 要求されたバイト数
+    pub site: u64, // 確保関数の呼び出し元(リターンアドレス)
+}
+
+/// 確保と解放の記録
+#[derive(Debug, Default)]
+pub struct HeapTracker {
+    live: BTreeMap<u64, Alloc>, // 解放されていない領域。先頭のアドレス順
+    allocs: u64,                // 確保した回数
+    frees: u64,                 // 解放した回数
+    alloc_bytes: u64,           // 確保したバイト数の合計
+    unknown_frees: u64,         // 追跡していない領域を解放した回数
+}
+
+impl HeapTracker {
+    /// siteから呼び出した確保関数が、sizeバイトの領域をaddrに確保した。addrが0なら確保に失敗した
+    pub fn alloc(&mut self, addr: u64, size: u64, site: u64) {
+        if addr == 0 {
+            return;
+        }
+        self.allocs += 1;
+        self.alloc_bytes += size;
+        self.live.insert(addr, Alloc { addr, size, site });
+    }
+
+    /// addrの領域を解放した。free(NULL)は何もしない
+    pub fn free(&mut self, addr: u64) {
+        if addr == 0 {
+            return;
+        }
+        self.frees += 1;
+        if self.live.remove(&addr).is_none() {
+            self.unknown_frees += 1;
+        }
+    }
+
+    /// realloc(old, size)がnewを返した
+    ///
+    /// 失敗した場合(newが0)は元の領域がそのまま残る。ただしsizeが0の場合はfreeと同じ
+    pub fn realloc(&mut self, old: u64, new: u64, size: u64, site: u64) {
+        if new == 0 {
+            if size == 0 {
+                self.free(old);
+            }
+            return;
+        }
+        self.free(old);
+        self.alloc(new, size, site);
+    }
+
+    /// 確保も解放も記録していなければ真
+    pub fn is_empty(&self) -> bool {
+        self.allocs == 0 && self.frees == 0
+    }
+
+    /// 解放されていない領域を先頭のアドレス順に返す
+    pub fn live(&self) -> impl Iterator<Item = &Alloc> {
+        self.live.values()
+    }
+
+    /// 解放されていない領域の個数とバイト数の合計
+    pub fn live_total(&self) -> (usize, u64) {
+        (self.live.len(), self.live.values().map(|a| a.size).sum())
+    }
+
+    /// 確保と解放の回数などをまとめた1行
+    pub fn summary(&self) -> String {
+        let (count, bytes) = self.live_total();
+        let mut s = format!(
+            "確保 {}回 ({} bytes), 解放 {}回, 未解放 {count}個 ({bytes} bytes)",
+            self.allocs, self.alloc_bytes, self.frees
+        );
+        if self.unknown_frees > 0 {
+            s.push_str(&format!(", 追跡前の領域の解放 {}回", self.unknown_frees));
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maps::read_maps;
+    use nix::unistd::Pid;
+
+    #[test]
+    fn test_tracker() {
+        let mut heap = HeapTracker::default();
+        heap.alloc(0x1000, 16, 0x401100);
+        heap.alloc(0x2000, 32, 0x401200);
+        heap.alloc(0, 1 << 40, 0x401300); // 確保に失敗
+        heap.free(0x1000);
+        heap.free(0); // free(NULL)
+        heap.free(0x9000); // 追跡前に確保された領域
+        assert_eq!(heap.live_total(), (1, 32));
+
+        // reallocは元の領域を解放して新たに確保する
+        heap.realloc(0x2000, 0x3000, 64, 0x401400);
+        assert_eq!(
+            heap.live().collect::<Vec<_>>(),
+            vec![&Alloc {
+                addr: 0x3000,
+                size: 64,
+                site: 0x401400
+            }]
+        );
+        // 失敗した場合は元の領域が残る
+        heap.realloc(0x3000, 0, 1 << 40, 0x401500);
+        assert_eq!(heap.live_total(), (1, 64));
+        // 大きさが0ならfreeと同じ
+        heap.realloc(0x3000, 0, 0, 0x401500);
+        assert_eq!(heap.live_total(), (0, 0));
+
+        assert_eq!(
+            heap.summary(),
+            "確保 3回 (112 bytes), 解放 4回, 未解放 0個 (0 bytes), 追跡前の領域の解放 1回"
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        // テストの実行ファイル自身にリンクされたlibcのmallocとfreeを探す
+        let regions = read_maps(Pid::this()).unwrap();
+        let fns = resolve(&regions).unwrap();
+        let addr = |f| fns.iter().find(|(g, _)| *g == f).map(|(_, addr)| *addr);
+        assert_eq!(
+            addr(HeapFn::Malloc),
+            Some(nix::libc::malloc as *const () as u64)
+        );
+        assert_eq!(
+            addr(HeapFn::Free),
+            Some(nix::libc::free as *const () as u64)
+        );
+    }
+}
@@ -0,0 +1,507 @@
+//! コマンドのヘルプ
+//!
+//! 各コマンドの書式、説明、例をCOMMANDSに登録しておき、
+//! `help`では一覧を、`help break`のようにコマンド名を指定した場合はそのコマンドの詳しい説明を表示する。
+
+/// 1つのコマンドのヘルプ
+pub struct CmdHelp {
+    pub name: &'static str,                                // コマンド名
+    pub aliases: &'static [&'static str],                  // 省略記法
+    pub usage: &'static str,                               // 書式
+    pub summary: &'static str,                             // 一覧に表示する1行の説明
+    pub detail: &'static str,                              // help <コマンド>で表示する説明
+    pub examples: &'static [(&'static str, &'static str)], // 例とその説明
+}
+
+/// コマンドのヘルプの一覧。helpではこの順に表示する
+pub const COMMANDS: &[CmdHelp] = &[
+    CmdHelp {
+        name: "break",
+        aliases: &["b"],
+        usage: "break (アドレス | 関数名 | ファイル名:行番号) [if 条件]",
+        summary: "ブレークポイントを設定",
+        detail: "\
+指定した位置にブレークポイントを設定する。実行前に設定した場合は、runの実行時に書き込む。
+- 0xから始まる場合は、実行時のアドレスとみなす
+- ファイル名:行番号の場合は、行番号情報(.debug_line)からアドレスを求める。
+  ファイル名はパスの末尾と比較するため、ディレクトリは省略できる。
+  指定した行に命令がない場合は、それ以降で命令がある最初の行に設定する
+- それ以外は関数名とみなし、シンボルテーブルから関数の先頭のアドレスを求める
+関数名と行番号で指定した場合、PIEでは実行するたびにロードされたアドレスに補正する。
+if 条件を続けると、到達するたびに条件を評価し、偽の場合は停止せずに実行を続ける。
+条件にはレジスタ、整数、*アドレス(8バイトの読み込み)、==、!=、<、<=、>、>=(符号付き)、
++、-、&、!、&&、||と括弧を使える",
+        examples: &[
+            ("break 0x401136", "0x401136番地に設定"),
+            ("break main", "関数mainの先頭に設定"),
+            ("break main.c:42", "main.cの42行目に設定"),
+            ("break fib if rdi == 5", "第1引数が5のときのみfibで停止"),
+        ],
+    },
+    CmdHelp {
+        name: "tbreak",
+        aliases: &[],
+        usage: "tbreak (アドレス | 関数名 | ファイル名:行番号) [if 条件]",
+        summary: "一度停止したら削除される一時ブレークポイントを設定",
+        detail: "\
+位置と条件の指定方法はbreakと同じ。最初に停止した時点でブレークポイントの一覧から削除する。
+条件が偽で停止しなかった場合は削除しない。info breakpointsでは(一時)と表示する",
+        examples: &[("tbreak main", "mainで一度だけ停止")],
+    },
+    CmdHelp {
+        name: "rbreak",
+        aliases: &[],
+        usage: "rbreak 正規表現",
+        summary: "名前が正規表現にマッチするすべての関数の先頭にブレークポイントを設定",
+        detail: "\
+シンボルテーブル中の関数のうち、名前が正規表現にマッチするものすべてにブレークポイントを設定する。
+設定済みのアドレスと、別名で同じアドレスを持つ関数は重複して設定しない",
+        examples: &[("rbreak ^parse_", "parse_から始まる関数に設定")],
+    },
+    CmdHelp {
+        name: "info",
+        aliases: &["i"],
+        usage: "info breakpoints | info signals [シグナル] | info proc maps",
+        summary: "ブレークポイントの一覧や、シグナルの扱い、メモリ領域の一覧を表示",
+        detail: "\
+info breakpointsは、ブレークポイントの番号、有効か無効か、アドレス、設定した関数名またはソースコード上の位置を表示する。
+番号はdelete、disable、enableで指定する。
+info signalsは、各シグナルを受信した際に停止するか、表示するか、子プロセスに渡すかを表示する。
+info proc mapsは、実行中のプロセスの/proc/PID/mapsを読み込み、各領域のアドレスの範囲、大きさ、権限、
+マップされたファイルか[stack]や[heap]などの名前を表示する。ripとrspを含む領域には印を付ける",
+        examples: &[
+            ("i b", "info breakpointsの省略記法"),
+            ("info proc maps", "スタックやヒープ、ライブラリのアドレスを確認"),
+            ("info signals SIGSEGV", "SIGSEGVの扱いを表示"),
+        ],
+    },
+    CmdHelp {
+        name: "delete",
+        aliases: &["d"],
+        usage: "delete 番号",
+        summary: "指定した番号のブレークポイントを削除",
+        detail: "実行中の場合は、書き込んだint 3を元の値に戻してから削除する",
+        examples: &[("delete 1", "ブレークポイント1を削除")],
+    },
+    CmdHelp {
+        name: "disable",
+        aliases: &[],
+        usage: "disable 番号",
+        summary: "指定した番号のブレークポイントを無効にする",
+        detail: "一覧には残したまま、int 3を書き込まないようにする。enableで再び有効にできる",
+        examples: &[("disable 1", "ブレークポイント1を無効にする")],
+    },
+    CmdHelp {
+        name: "enable",
+        aliases: &[],
+        usage: "enable 番号",
+        summary: "指定した番号のブレークポイントを有効にする",
+        detail: "disableで無効にしたブレークポイントを有効に戻す",
+        examples: &[("enable 1", "ブレークポイント1を有効にする")],
+    },
+    CmdHelp {
+        name: "run",
+        aliases: &["r"],
+        usage: "run [引数*] [< ファイル] [> ファイル]",
+        summary: "プログラムを実行",
+        detail: "\
+ASLRを無効にして子プロセスを生成し、ブレークポイントを書き込んでから実行を開始する。
+引数はそのままプログラムに渡し、次回以降の引数なしのrunでも使う(set argsでも設定できる)。
+`< ファイル`、`> ファイル`、`>> ファイル`、`2> ファイル`で子プロセスの標準入出力をリダイレクトする。
+子プロセスが終了した後は、ブレークポイントを残したままrunで再実行できる",
+        examples: &[
+            ("run -v input.txt", "引数-vとinput.txtを渡して実行"),
+            ("run < in.txt > out.log", "標準入力をin.txt、標準出力をout.logにして実行"),
+            ("run", "前回と同じ引数で実行"),
+        ],
+    },
+    CmdHelp {
+        name: "continue",
+        aliases: &["c"],
+        usage: "continue",
+        summary: "プログラムを再開",
+        detail: "次のブレークポイントに到達するか、シグナルを受信するか、終了するまで実行する",
+        examples: &[],
+    },
+    CmdHelp {
+        name: "stepi",
+        aliases: &["s"],
+        usage: "stepi",
+        summary: "機械語レベルで1ステップ実行",
+        detail: "1命令だけ実行して停止する。ブレークポイントで停止している場合は、元の命令を実行する",
+        examples: &[],
+    },
+    CmdHelp {
+        name: "nexti",
+        aliases: &["ni"],
+        usage: "nexti",
+        summary: "機械語レベルで1ステップ実行。関数呼び出しは1命令とみなす",
+        detail: "\
+stepiと同様だが、call命令の場合はリターンアドレスに一時的なブレークポイントを設定し、
+呼び出した関数から戻るまで実行する。呼び出した関数の中でブレークポイントに到達した場合は、そこで停止する",
+        examples: &[("ni", "nextiの省略記法")],
+    },
+    CmdHelp {
+        name: "finish",
+        aliases: &[],
+        usage: "finish",
+        summary: "現在の関数から戻るまで実行し、戻り値を表示",
+        detail: "\
+CFIから現在のフレームのリターンアドレスを求め、そこに一時的なブレークポイントを設定して実行する。
+戻った後は、デバッグ情報の型に従ってraxなどから戻り値を表示する。デバッグ情報がない場合はraxの値を表示する。
+戻る前にブレークポイントに到達した場合は、そこで停止する",
+        examples: &[],
+    },
+    CmdHelp {
+        name: "step",
+        aliases: &[],
+        usage: "step",
+        summary: "ソースコードレベルで1行実行。関数呼び出しの中に入る",
+        detail: "\
+ソースコード上の位置が変わるまで1命令ずつ実行する。
+関数の中に入った場合は、引数を参照できるようにプロローグの直後まで実行して止まる。
+行番号情報のない関数を呼び出した場合は、その関数から戻るまで実行する",
+        examples: &[],
+    },
+    CmdHelp {
+        name: "next",
+        aliases: &["n"],
+        usage: "next",
+        summary: "ソースコードレベルで1行実行。関数呼び出しは1行とみなす",
+        detail: "\
+stepと同様だが、関数を呼び出した場合はその関数から戻るまで実行する。
+呼び出した関数の中でブレークポイントに到達した場合は、そこで停止する",
+        examples: &[],
+    },
+    CmdHelp {
+        name: "registers",
+        aliases: &["regs"],
+        usage: "registers",
+        summary: "レジスタを表示。ポインタの場合は参照先も表示",
+        detail: "汎用レジスタを表示し、ポインタとみなせる値はset deref-depthで指定した段数まで参照先を辿って表示する",
+        examples: &[],
+    },
+    CmdHelp {
+        name: "stack",
+        aliases: &[],
+        usage: "stack[/[形式][単位][バイト順]] [個数]",
+        summary: "スタックの値を参照先とともに指定個数表示",
+        detail: "\
+rspから8バイトずつ、指定した個数(省略時は8個)の値を参照先とともに表示する。
+形式を指定した場合は参照先の代わりに、8バイトを単位ごとに区切って指定した形式で表示する。
+形式、単位、バイト順はxコマンドと同じである",
+        examples: &[
+            ("stack 16", "スタックトップから16個表示"),
+            ("stack/dw 4", "4個の値を4バイトずつ符号付き10進数で表示"),
+        ],
+    },
+    CmdHelp {
+        name: "backtrace",
+        aliases: &["bt"],
+        usage: "backtrace",
+        summary: "呼び出し元の関数の一覧を表示",
+        detail: "\
+呼び出し元を辿り、1フレームごとにアドレスと関数名+オフセットを表示する。
+#0は停止している位置で、#1以降は呼び出し元へのリターンアドレスである。
+実行ファイルのCFI(.eh_frameと.debug_frame)があればそれで巻き戻すため、
+-fomit-frame-pointerでコンパイルされた関数も辿れる。
+CFIがない関数では、rbpに保存されたフレームポインタを辿る",
+        examples: &[("bt", "呼び出し元の一覧を表示")],
+    },
+    CmdHelp {
+        name: "print",
+        aliases: &["p"],
+        usage: "print 変数名",
+        summary: "引数やローカル変数の値を表示",
+        detail: "\
+デバッグ情報(.debug_info)から停止位置で参照できる変数を探し、型に応じて値を表示する。
+整数、文字、浮動小数点数、ポインタ、構造体、配列に対応する。
+同じ名前の変数がある場合は最も内側のブロックのものを、関数内にない場合はグローバル変数を表示する。
+最適化によって位置がロケーションリストで表される変数は表示できない",
+        examples: &[("p argc", "引数argcの値を表示")],
+    },
+    CmdHelp {
+        name: "tls",
+        aliases: &[],
+        usage: "tls [アドレス]",
+        summary: "fs_base、gs_base、スタックカナリアを表示",
+        detail: "\
+引数を省略した場合は、fs_base、gs_baseと、fs:0x28に格納されたスタックカナリアを表示する。
+アドレスを指定した場合はその8バイトの値を表示する。fs:やgs:を付けると、それぞれからの相対アドレスとなる",
+        examples: &[
+            ("tls", "fs_base、gs_base、カナリアを表示"),
+            ("tls fs:0x10", "fs_base+0x10の値を表示"),
+        ],
+    },
+    CmdHelp {
+        name: "x",
+        aliases: &[],
+        usage: "x/[個数][形式][単位][バイト順] アドレス",
+        summary: "メモリの内容を指定した形式で表示",
+        detail: "\
+アドレスから指定した個数の値を表示する。アドレスにはfs:やgs:からの相対アドレスも指定できる。
+形式、単位、バイト順は順不同で、省略した場合は1個、x、w、<とする。
+- 形式     : x = 16進数、d = 符号付き10進数、u = 符号なし10進数、t = 2進数、s = NUL終端文字列
+- 単位     : b = 1バイト、h = 2バイト、w = 4バイト、g = 8バイト
+- バイト順 : < = リトルエンディアン、> = ビッグエンディアン
+sの場合は単位を使わず、連続する文字列を指定個数表示する",
+        examples: &[
+            ("x/16xb 0x404010", "0x404010から16バイトを16進数で表示"),
+            ("x/4dg fs:0x0", "fs_baseから8バイトずつ4個を10進数で表示"),
+            ("x/2s 0x402004", "0x402004から文字列を2個表示"),
+            ("x/4xw> 0x404010", "4バイトずつビッグエンディアンとして4個表示"),
+        ],
+    },
+    CmdHelp {
+        name: "disas",
+        aliases: &[],
+        usage: "disas [アドレス | 関数名] [個数]",
+        summary: "機械語を逆アセンブルして表示",
+        detail: "\
+引数を省略した場合は、PCの前の3命令とPCからの5命令を表示する。PCの命令には=>を付ける。
+アドレスか関数名を指定した場合は、そこから指定した個数(省略時は8個)の命令を表示する。
+分岐先やrip相対で参照するアドレスは、関数名+オフセットやアドレスを命令の後に表示する。
+停止するたびに、PCから3命令を表示する。個数はset disas-countで変更できる。
+対応していない命令は(bad)と表示し、1バイトだけ読み飛ばす",
+        examples: &[
+            ("disas", "PCの前後の命令を表示"),
+            ("disas main 20", "関数mainの先頭から20命令を表示"),
+            ("disas 0x401126", "0x401126から8命令を表示"),
+        ],
+    },
+    CmdHelp {
+        name: "list",
+        aliases: &["l"],
+        usage: "list",
+        summary: "停止した行の前後のソースコードを表示",
+        detail: "\
+行番号情報(.debug_line)からPCに対応するソースファイルと行番号を求め、
+その行を中心に10行を行番号付きで表示する。停止した行には=>を付ける。
+ソースファイルはコンパイル時のパスから読み込むため、移動や削除した場合は表示できない",
+        examples: &[("l", "listの省略記法")],
+    },
+    CmdHelp {
+        name: "watch",
+        aliases: &[],
+        usage: "watch [[w | rw | x] アドレス [バイト数] | delete 番号 | clear]",
+        summary: "デバッグレジスタを使ったハードウェアウォッチポイントを設定",
+        detail: "\
+DR0〜DR3を使い、最大4個のウォッチポイントを設定する。CPUがアクセスを検出した時点で停止する。
+- w  : 書き込みで停止し、変更前と変更後の値を表示する (省略時)
+- rw : 読み込みか書き込みで停止し、値を表示する
+- x  : その番地の命令を実行する前に停止する
+バイト数は1、2、4、8のいずれかで(省略時は8、xは1のみ)、アドレスはバイト数の倍数でなければならない。
+スロットが埋まっている場合、wは4番以降のソフトウェアウォッチポイントとして設定する。
+ソフトウェアウォッチポイントは最大4096バイトを監視できるが、continueが1命令ずつのステップ実行になるため非常に遅い。
+引数なしで設定中のウォッチポイントを一覧表示し、delete 番号で解除、clearですべて解除する",
+        examples: &[
+            ("watch 0x404020", "0x404020からの8バイトへの書き込みで停止"),
+            ("watch rw 0x404030 4", "0x404030からの4バイトへのアクセスで停止"),
+            ("watch x 0x401136", "0x401136の命令の実行前に停止"),
+        ],
+    },
+    CmdHelp {
+        name: "watchmem",
+        aliases: &[],
+        usage: "watchmem [アドレス バイト数 | clear]",
+        summary: "メモリを監視し、停止時に変更されていれば差分を表示",
+        detail: "\
+指定アドレスから指定バイト数(最大4096バイト)を監視し、子プロセスが停止するたびに変更を検査して差分を表示する。
+アドレスにはtlsと同様にfs:やgs:からの相対アドレスも指定できる。
+引数なしで監視中の領域を一覧表示し、clearですべて解除する",
+        examples: &[
+            ("watchmem 0x404010 16", "0x404010から16バイトを監視"),
+            ("watchmem clear", "すべての監視を解除"),
+        ],
+    },
+    CmdHelp {
+        name: "checkstack",
+        aliases: &[],
+        usage: "checkstack [on|off]",
+        summary: "リターンアドレスとスタックカナリアの破壊を検査",
+        detail: "\
+バックトレースの各フレームのリターンアドレスが実行可能な領域を指しているかを検査する。
+また、各フレームの末尾付近でfs:0x28のカナリアと同じ値を保持するスロットを記録し、
+次回の検査でそのフレームが残っていれば、値が書き換えられていないかを検査する。
+checkstack onで停止するたびに検査し、問題がある場合のみ警告を表示する",
+        examples: &[
+            ("checkstack", "今すぐ検査"),
+            ("checkstack on", "停止するたびに検査"),
+        ],
+    },
+    CmdHelp {
+        name: "catch",
+        aliases: &[],
+        usage: "catch syscall [名前 | 番号]* | catch syscall clear",
+        summary: "指定したシステムコールの呼び出しで停止",
+        detail: "\
+指定したシステムコールの入口で停止し、引数を表示する。openatなどのファイル名を引数に取るものは、
+子プロセスのメモリから文字列を読み込んで表示する。名前はx86-64の主なシステムコールに対応し、
+それ以外は番号で指定する。捕捉するシステムコールがある間は、continueでPTRACE_SYSCALLを使い、
+すべてのシステムコールで一度停止して判定するため、通常より遅くなる。
+名前を省略した場合は捕捉中のものを一覧表示し、clearですべて解除する",
+        examples: &[
+            ("catch syscall openat", "ファイルを開く際に停止"),
+            ("catch syscall write 59", "writeとexecve(59)で停止"),
+        ],
+    },
+    CmdHelp {
+        name: "handle",
+        aliases: &[],
+        usage: "handle シグナル [stop | nostop | print | noprint | pass | nopass]*",
+        summary: "シグナルを受信した際に停止するか、表示するか、子プロセスに渡すかを設定",
+        detail: "\
+シグナルはSIGSEGV、SEGV、11のいずれかの形式で指定する。stopは受信したら停止し、nostopは停止せずに実行を続ける。
+printは受信したことを表示し、passは再開時にシグナルを子プロセスに渡す。nopassの場合、シグナルは捨てられる。
+stopはprintを、noprintはnostopを伴う。キーワードを省略した場合は現在の扱いを表示する。
+既定では、SIGTRAPとSIGINTは停止して渡さず、SIGCHLDやSIGALRMなどは停止も表示もせずに渡し、その他は停止して渡す",
+        examples: &[
+            ("handle SIGUSR1 nostop", "SIGUSR1では停止せずに表示して渡す"),
+            ("handle SIGSEGV nopass", "continueでSIGSEGVを渡さずに再開する"),
+        ],
+    },
+    CmdHelp {
+        name: "heap",
+        aliases: &[],
+        usage: "heap [on|off]",
+        summary: "mallocとfreeを追跡し、解放されていない領域を表示",
+        detail: "\
+heap onでmalloc、calloc、realloc、freeにブレークポイントを設定し、確保した領域の大きさと呼び出し元を記録する。
+これらのブレークポイントでは停止せずに実行を続ける。動的リンクの場合は、mainなどで停止してから実行する。
+引数なしのheapは解放されていない領域の一覧と合計を表示し、子プロセスの終了後はリークした領域の確認に使える",
+        examples: &[
+            ("heap on", "追跡を開始"),
+            ("heap", "解放されていない領域を表示"),
+        ],
+    },
+    CmdHelp {
+        name: "set",
+        aliases: &[],
+        usage: "set (deref-depth [段数] | disas-count [個数] | step [auto | hw | sw] | aslr [on | off] | args [引数*] | debug ptrace [on | off] | reg レジスタ 値)",
+        summary: "参照先を辿る段数などの設定、またはレジスタの値を変更",
+        detail: "\
+- deref-depth : レジスタやスタックの値の参照先を辿る段数を0から8で指定する。
+  0の場合は参照先を辿らない。段数を省略した場合は現在の値を表示する
+- disas-count : 停止するたびに表示する命令の個数を0から32で指定する。
+  0の場合は表示しない。個数を省略した場合は現在の値を表示する
+- step : stepiなどで1命令を実行する方法を指定する。hwはPTRACE_SINGLESTEP、
+  swは次の命令のアドレスを求めて一時的なブレークポイントを設定する。
+  autoは通常hwとし、pushfのようにhwでは結果が変わる命令のみswとする。
+  swで次の命令のアドレスを求められない場合はhwで実行する
+- aslr : onの場合、runで子プロセスのASLRを無効にせず、実行するたびにアドレスが変わる。
+  配置に依存する不具合の再現に使う。関数名や行で指定したブレークポイントと、
+  実行ファイル内のアドレスで指定したブレークポイントはロードアドレスに合わせて補正する。
+  既定はoffで、次のrunから反映する
+- args : 引数を省略したrunで子プロセスに渡す引数を設定する。省略した場合は引数を消去する
+- debug ptrace : onの場合、ptraceとwaitpidの呼び出しごとに引数と結果を表示する。
+  デバッガ自身の動作を調べるためのもので、省略した場合は現在の値を表示する
+- reg : 実行中に、指定したレジスタの値を変更する。値は16進数(0x...)か10進数で指定する。
+  ripを変更すると、命令を飛ばしたり同じ命令を再実行したりできる",
+        examples: &[
+            ("set deref-depth 2", "参照先を2段まで辿る"),
+            ("set disas-count 0", "停止時の逆アセンブルを表示しない"),
+            ("set step sw", "ソフトウェアでステップ実行する"),
+            ("set aslr on", "次のrunからASLRを有効にする"),
+            ("set args -v input.txt", "引数なしのrunで-vとinput.txtを渡す"),
+            ("set debug ptrace on", "ptraceの呼び出しを表示する"),
+            ("set reg rip 0x401000", "0x401000から実行を再開する"),
+            ("set reg rdi 42", "第1引数を42にする"),
+        ],
+    },
+    CmdHelp {
+        name: "replay",
+        aliases: &[],
+        usage: "replay",
+        summary: "--replayで再生中に、記録と異なる停止により一時停止した再生を再開",
+        detail: "\
+--replayでセッションを再生中に、記録と異なる停止イベントが発生すると再生を一時停止する。
+一時停止中は通常どおりコマンドを入力でき、replayで残りの再生を再開する",
+        examples: &[],
+    },
+    CmdHelp {
+        name: "exit",
+        aliases: &[],
+        usage: "exit",
+        summary: "終了",
+        detail: "実行中の子プロセスがある場合は、killしてから終了する",
+        examples: &[],
+    },
+    CmdHelp {
+        name: "help",
+        aliases: &["h"],
+        usage: "help [コマンド名]",
+        summary: "このヘルプを表示",
+        detail: "コマンド名を指定した場合は、そのコマンドの書式、説明、例を表示する。省略記法でも指定できる",
+        examples: &[("help break", "breakの詳しい説明を表示")],
+    },
+];
+
+/// コマンド名か省略記法nameのヘルプを返す
+pub fn find(name: &str) -> Option<&'static CmdHelp> {
+    COMMANDS
+        .iter()
+        .find(|c| c.name == name || c.aliases.contains(&name))
+}
+
+/// helpコマンドを実行し、コマンドの一覧か、指定したコマンドの詳しい説明を表示
+pub fn do_help(cmd: &[&str]) {
+    match cmd.get(1) {
+        None => print_list(),
+        Some(name) => match find(name) {
+            Some(c) => print_detail(c),
+            None => eprintln!("<<{name}というコマンドはありません>>"),
+        },
+    }
+}
+
+/// コマンドの一覧を表示
+fn print_list() {
+    println!("コマンド一覧(括弧内は省略記法)");
+    for c in COMMANDS {
+        let aliases = if c.aliases.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", c.aliases.join(", "))
+        };
+        println!("    {:<12} : {}{aliases}", c.name, c.summary);
+    }
+    println!("help コマンド名 で、そのコマンドの詳しい説明を表示");
+    println!("空行を入力すると、run以外の直前のコマンドを繰り返す");
+}
+
+/// コマンドcの書式、説明、例を表示
+fn print_detail(c: &CmdHelp) {
+    println!("書式: {}", c.usage);
+    if !c.aliases.is_empty() {
+        println!("省略記法: {}", c.aliases.join(", "));
+    }
+    println!("\n{}", c.detail);
+    if !c.examples.is_empty() {
+        println!("\n例:");
+        for (example, desc) in c.examples {
+            println!("    {example:<24} : {desc}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("b").map(|c| c.name), Some("break"));
+        assert_eq!(find("watchmem").map(|c| c.name), Some("watchmem"));
+        assert!(find("nosuch").is_none());
+
+        // コマンド名と省略記法は重複しない
+        let mut names: Vec<&str> = COMMANDS
+            .iter()
+            .flat_map(|c| [c.name].into_iter().chain(c.aliases.iter().copied()))
+            .collect();
+        let len = names.len();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), len);
+    }
+}
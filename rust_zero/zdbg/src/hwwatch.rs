@@ -0,0 +1,217 @@
+//! ハードウェアウォッチポイント(watch)
+//!
+//! x86_64のデバッグレジスタを使い、指定したアドレスへのアクセスをCPUに検出させて子プロセスを停止させる。
+//! DR0〜DR3に監視するアドレスを、DR7に各スロットの有効/無効、種類(書き込み、読み書き、実行)、長さを設定する。
+//! 停止した原因は、DR6の下位4ビットでどのスロットが検出したかを調べる。
+//!
+//! デバッグレジスタは、PTRACE_POKEUSERでstruct userのu_debugregに書き込んで設定する。
+//! 書き込みと読み書きの場合はアクセスした命令の実行後に、実行の場合は命令の実行前に停止する。
+
+use crate::{trace, watch::read_mem};
+use nix::{libc::user, unistd::Pid};
+use std::{ffi::c_void, fmt, mem::offset_of};
+
+/// ウォッチポイントのスロット数(DR0〜DR3)
+pub const NUM_SLOTS: usize = 4;
+
+/// 状態を表すデバッグレジスタDR6とDR7の番号
+const DR6: usize = 6;
+const DR7: usize = 7;
+
+/// 検出するアクセスの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Write,     // 書き込み (w)
+    ReadWrite, // 読み込みか書き込み (rw)
+    Exec,      // 命令の実行 (x)
+}
+
+impl WatchKind {
+    /// watchコマンドで指定する種類を解析する
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "w" => Some(WatchKind::Write),
+            "rw" => Some(WatchKind::ReadWrite),
+            "x" => Some(WatchKind::Exec),
+            _ => None,
+        }
+    }
+
+    /// DR7のR/Wフィールドの値
+    fn rw_bits(self) -> u64 {
+        match self {
+            WatchKind::Exec => 0b00,
+            WatchKind::Write => 0b01,
+            WatchKind::ReadWrite => 0b11,
+        }
+    }
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchKind::Write => write!(f, "書き込み"),
+            WatchKind::ReadWrite => write!(f, "読み書き"),
+            WatchKind::Exec => write!(f, "実行"),
+        }
+    }
+}
+
+/// ハードウェアウォッチポイント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HwWatch {
+    pub addr: u64,       // 監視するアドレス
+    pub len: usize,      // 監視するバイト数。1、2、4、8のいずれか
+    pub kind: WatchKind, // 検出するアクセスの種類
+    pub old: u64,        // 前回停止したときの値。実行の場合は使わない
+}
+
+impl HwWatch {
+    /// addrからlenバイトを監視するウォッチポイントを作る
+    ///
+    /// CPUの制約により、lenは1、2、4、8のいずれかで、addrはlenの倍数でなければならない。
+    /// 実行の場合はlenは1とする
+    pub fn new(kind: WatchKind, addr: u64, len: usize) -> Result<Self, String> {
+        if kind == WatchKind::Exec && len != 1 {
+            return Err("実行のウォッチポイントのバイト数は1のみ指定可能です".to_string());
+        }
+        if ![1, 2, 4, 8].contains(&len) {
+            return Err(format!(
+                "バイト数は1、2、4、8のいずれかで指定してください : {len}"
+            ));
+        }
+        if !addr.is_multiple_of(len as u64) {
+            return Err(format!(
+                "アドレス{addr:#x}が{len}バイト境界に揃っていません"
+            ));
+        }
+        Ok(HwWatch {
+            addr,
+            len,
+            kind,
+            old: 0,
+        })
+    }
+
+    /// 監視している領域の現在の値を読み込む
+    pub fn read_value(&self, pid: Pid) -> Result<u64, String> {
+        let data = read_mem(pid, self.addr, self.len)?;
+        let mut bytes = [0; 8];
+        bytes[..data.len()].copy_from_slice(&data);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// DR7のLEN フィールドの値
+    fn len_bits(&self) -> u64 {
+        match self.len {
+            1 => 0b00,
+            2 => 0b01,
+            8 => 0b10,
+            _ => 0b11, // 4バイト
+        }
+    }
+}
+
+/// スロットの一覧からDR7の値を計算する
+///
+/// スロットiについて、ビット2iがローカル有効ビット(L0〜L3)、
+/// ビット16+4iから2ビットがR/W、ビット18+4iから2ビットがLENである
+pub fn dr7(slots: &[Option<HwWatch>]) -> u64 {
+    slots
+        .iter()
+        .enumerate()
+        .filter_map(|(i, w)| w.as_ref().map(|w| (i, w)))
+        .fold(0, |dr7, (i, w)| {
+            dr7 | 1 << (2 * i) | (w.kind.rw_bits() | w.len_bits() << 2) << (16 + 4 * i)
+        })
+}
+
+/// DR6の値から、検出したスロットの番号を返す
+pub fn hit_slots(dr6: u64) -> Vec<usize> {
+    (0..NUM_SLOTS).filter(|i| dr6 & (1 << i) != 0).collect()
+}
+
+/// i番目のデバッグレジスタに書き込む
+fn write_debugreg(pid: Pid, i: usize, val: u64) -> nix::Result<()> {
+    let offset = offset_of!(user, u_debugreg) + i * 8;
+    unsafe { trace::write_user(pid, offset as *mut c_void, val as *mut c_void) }
+}
+
+/// i番目のデバッグレジスタを読み込む
+fn read_debugreg(pid: Pid, i: usize) -> nix::Result<u64> {
+    let offset = offset_of!(user, u_debugreg) + i * 8;
+    trace::read_user(pid, offset as *mut c_void).map(|v| v as u64)
+}
+
+/// スロットの一覧を子プロセスのデバッグレジスタに設定する
+///
+/// DR7でアドレスを有効にする前に、DR0〜DR3にアドレスを書き込んでおく必要がある
+pub fn apply(pid: Pid, slots: &[Option<HwWatch>]) -> nix::Result<()> {
+    for (i, w) in slots.iter().enumerate() {
+        if let Some(w) = w {
+            write_debugreg(pid, i, w.addr)?;
+        }
+    }
+    write_debugreg(pid, DR7, dr7(slots))
+}
+
+/// DR7のみを書き換え、ウォッチポイントを一時的に無効にする
+pub fn write_dr7(pid: Pid, val: u64) -> nix::Result<()> {
+    write_debugreg(pid, DR7, val)
+}
+
+/// DR6を読み込んでから0にし、検出したスロットの番号を返す
+pub fn take_hits(pid: Pid) -> nix::Result<Vec<usize>> {
+    let dr6 = read_debugreg(pid, DR6)?;
+    if dr6 != 0 {
+        clear_hits(pid)?;
+    }
+    Ok(hit_slots(dr6))
+}
+
+/// DR6を0にする
+///
+/// int 3による停止などではDR6は更新されないため、以前に検出したビットが残らないように再開前に呼び出す
+pub fn clear_hits(pid: Pid) -> nix::Result<()> {
+    write_debugreg(pid, DR6, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        assert!(HwWatch::new(WatchKind::Write, 0x404020, 8).is_ok());
+        assert!(HwWatch::new(WatchKind::Write, 0x404024, 8).is_err());
+        assert!(HwWatch::new(WatchKind::ReadWrite, 0x404020, 3).is_err());
+        assert!(HwWatch::new(WatchKind::Exec, 0x401137, 1).is_ok());
+        assert!(HwWatch::new(WatchKind::Exec, 0x401138, 4).is_err());
+    }
+
+    #[test]
+    fn test_dr7() {
+        let w = |kind, addr, len| Some(HwWatch::new(kind, addr, len).unwrap());
+        assert_eq!(dr7(&[None, None, None, None]), 0);
+
+        // スロット0 : 8バイトの書き込み (L0 = 1, R/W = 01, LEN = 10)
+        assert_eq!(dr7(&[w(WatchKind::Write, 0x404020, 8)]), 0x9_0001);
+
+        // スロット1 : 4バイトの読み書き (L1 = 1, R/W = 11, LEN = 11)
+        // スロット3 : 実行 (L3 = 1, R/W = 00, LEN = 00)
+        let slots = [
+            None,
+            w(WatchKind::ReadWrite, 0x404030, 4),
+            None,
+            w(WatchKind::Exec, 0x401136, 1),
+        ];
+        assert_eq!(dr7(&slots), 0x00f0_0044);
+    }
+
+    #[test]
+    fn test_hit_slots() {
+        // ビット14(BS)はシングルステップによる停止で、スロットとは無関係
+        assert_eq!(hit_slots(0x4005), vec![0, 2]);
+        assert_eq!(hit_slots(0), Vec::<usize>::new());
+    }
+}
@@ -1,40 +1,178 @@
+mod arch;
+mod backtrace;
+mod cfi;
+mod cond;
 mod dbg;
+mod deref;
+mod disas;
+mod dwarf;
+mod examine;
+mod heap;
+mod help;
 mod helper;
+mod hwwatch;
+mod maps;
+mod redirect;
+mod regs;
+mod render;
+mod session;
+mod signals;
+mod source;
+mod stackcheck;
+mod symbol;
+mod syscall;
+mod trace;
+mod vars;
+mod watch;
 
 use dbg::{State, ZDbg};
 use helper::DynError;
-use rustyline::{error::ReadlineError, Editor};
-use std::env;
+use rustyline::{error::ReadlineError, Config, Editor};
+use session::{Entry, Recorder, Replay};
+use std::{env, io::ErrorKind, path::PathBuf};
+
+/// コマンドのヒストリを保存するファイル。ホームディレクトリに置く
+const HISTORY_FILE: &str = ".zdbg_history";
+
+/// ヒストリに保存する最大の行数
+const HISTORY_SIZE: usize = 1000;
+
+/// 空行で繰り返さないコマンド。子プロセスを再生成したり終了したりするため
+const NO_REPEAT: [&str; 3] = ["run", "r", "exit"];
 
 fn main() -> Result<(), DynError> {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        let msg = format!("引数が必要です\n例 : {} 実行ファイル [引数*]", args[0]);
-        return Err(msg.into());
-    }
+    let usage = format!(
+        "引数が必要です\n例 : {} [--record セッション] [--replay セッション] 実行ファイル [引数*]",
+        args[0]
+    );
+
+    // オプションを解析
+    let mut record = None;
+    let mut replay = None;
+    let mut rest = args[1..].iter();
+    let filename = loop {
+        match rest.next().map(|s| s.as_str()) {
+            Some("--record") => record = Some(rest.next().ok_or(usage.as_str())?),
+            Some("--replay") => replay = Some(rest.next().ok_or(usage.as_str())?),
+            Some(filename) => break filename,
+            None => return Err(usage.into()),
+        }
+    };
+
+    let recorder = match record {
+        Some(path) => Some(Recorder::create(path, filename)?),
+        None => None,
+    };
+    let replay = match replay {
+        Some(path) => Some(Replay::load(path, filename)?),
+        None => None,
+    };
 
-    run_dbg(&args[1])?;
+    run_dbg(filename, recorder, replay)?;
     Ok(())
 }
 
-fn run_dbg(filename: &str) -> Result<(), DynError> {
+fn run_dbg(
+    filename: &str,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+) -> Result<(), DynError> {
     let debugger = ZDbg::new(filename.to_string());
-    let mut state = State::NotRunning(debugger);
-    let mut rl = Editor::<()>::new()?;
+    let state = State::NotRunning(debugger);
+
+    // zeroshと同様に、rustylineのEditorで矢印キーによる編集とヒストリを提供する
+    // ヒストリは終了時にこのセッションで入力した行のみをファイルに追記する
+    let config = Config::builder()
+        .max_history_size(HISTORY_SIZE)
+        .history_ignore_dups(true)
+        .build();
+    let mut rl = Editor::<()>::with_config(config)?;
+    let history = history_path();
+    match rl.load_history(&history) {
+        Err(ReadlineError::Io(e)) if e.kind() == ErrorKind::NotFound => (),
+        Err(e) => eprintln!("<<ヒストリの読み込みに失敗 : {e}>>"),
+        Ok(()) => (),
+    }
+    let result = read_loop(&mut rl, state, recorder, replay);
+    if let Err(e) = rl.append_history(&history) {
+        eprintln!("<<ヒストリの保存に失敗 : {e}>>");
+    }
+    result
+}
+
+/// ヒストリファイルのパス。ホームディレクトリが分からない場合はカレントディレクトリ
+fn history_path() -> PathBuf {
+    dirs::home_dir().unwrap_or_default().join(HISTORY_FILE)
+}
+
+/// コマンドを読み込んで実行するループ
+///
+/// 空行を入力した場合は、gdbと同様に直前のコマンドを繰り返す(stepiを続けて実行するなど)
+fn read_loop(
+    rl: &mut Editor<()>,
+    mut state: State,
+    mut recorder: Option<Recorder>,
+    mut replay: Option<Replay>,
+) -> Result<(), DynError> {
+    let mut last_cmd: Option<String> = None;
 
     loop {
-        match rl.readline("zdbg > ") {
+        // 再生中は記録されたコマンドを、そうでなければ入力されたコマンドを実行
+        let replayed = replay.as_mut().and_then(|r| r.next());
+        let input = match &replayed {
+            Some(Entry { cmd, .. }) => {
+                println!("zdbg(replay) > {cmd}");
+                Ok(cmd.clone())
+            }
+            None if replay.as_ref().is_some_and(|r| r.is_paused()) => {
+                rl.readline("zdbg(paused) > ")
+            }
+            None => rl.readline("zdbg > "),
+        };
+
+        match input {
             Ok(line) => {
-                let trimed = line.trim(); // 行頭と行末の空白文字を削除
+                let mut trimed = line.trim(); // 行頭と行末の空白文字を削除
+                if trimed.is_empty() && replayed.is_none() {
+                    match &last_cmd {
+                        Some(cmd) => trimed = cmd,
+                        None => continue,
+                    }
+                }
                 let cmd: Vec<&str> = trimed.split(' ').filter(|c| !c.is_empty()).collect(); // 文字列を削除
+
+                // 一時停止中の再生を再開
+                if let (["replay"], Some(r)) = (cmd.as_slice(), replay.as_mut()) {
+                    r.resume();
+                    continue;
+                }
+
+                if let Some(rec) = recorder.as_mut() {
+                    rec.record_cmd(trimed)?;
+                }
                 state = match state {
                     State::Running(r) => r.do_cmd(&cmd)?,
                     State::NotRunning(n) => n.do_cmd(&cmd)?,
                     _ => break,
                 };
+
+                // 停止イベントを記録し、再生中なら記録と比較
+                let stop = state.take_stop();
+                if let (Some(rec), Some(stop)) = (recorder.as_mut(), &stop) {
+                    rec.record_stop(stop)?;
+                }
+                if let (Some(r), Some(entry)) = (replay.as_mut(), &replayed) {
+                    r.check(entry, stop.as_ref());
+                }
+
                 if let State::Exit = state {
                     break;
                 }
+                last_cmd = cmd
+                    .first()
+                    .filter(|name| !NO_REPEAT.contains(name))
+                    .map(|_| trimed.to_string());
                 rl.add_history_entry(line);
             }
             Err(ReadlineError::Interrupted) => eprintln!("<<終了はCtrl+d>>"),
@@ -0,0 +1,241 @@
+//! /proc/PID/mapsによるメモリ領域の検査
+//!
+//! ブレークポイントはint 3(0xcc)をメモリに書き込んで設定するため、
+//! マップされていないアドレスでは書き込みに失敗し、データ領域では値を壊してしまう。
+//! そこで、書き込む前に/proc/PID/mapsを読み込み、実行可能な領域のアドレスかを検査する。
+//! また、info proc mapsでスタックやヒープ、ライブラリがマップされたアドレスを表示する。
+//!
+//! PIEのロードアドレスを求めるため、/proc/PID/auxvの補助ベクタも読み込む。
+
+use nix::unistd::Pid;
+use std::fs;
+
+/// 付近の実行可能な領域として表示する最大数
+const MAX_NEARBY: usize = 3;
+
+/// 補助ベクタのエントリポイントのアドレスの種類
+pub const AT_ENTRY: u64 = 9;
+
+/// /proc/PID/mapsの1行が表すメモリ領域
+#[derive(Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: u64,    // 開始アドレス
+    pub end: u64,      // 終了アドレス。この値は領域に含まない
+    pub perms: String, // 権限。rwxpの形式
+    pub path: String,  // マップされたファイルのパスか[stack]などの名前。無名の領域は空
+}
+
+impl Region {
+    /// 実行可能な領域なら真
+    pub fn is_exec(&self) -> bool {
+        self.perms.as_bytes().get(2) == Some(&b'x')
+    }
+
+    /// addrが領域に含まれるなら真
+    fn contains(&self, addr: u64) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    /// addrとの距離。領域に含まれる場合は0
+    fn distance(&self, addr: u64) -> u64 {
+        if addr < self.start {
+            self.start - addr
+        } else {
+            addr.saturating_sub(self.end - 1)
+        }
+    }
+}
+
+/// 子プロセスのメモリ領域の一覧を取得
+pub fn read_maps(pid: Pid) -> Result<Vec<Region>, String> {
+    let text = fs::read_to_string(format!("/proc/{pid}/maps"))
+        .map_err(|e| format!("/proc/{pid}/mapsの読み込みに失敗 : {e}"))?;
+    Ok(parse(&text))
+}
+
+/// 子プロセスの補助ベクタ(/proc/PID/auxv)から、種類がkeyの値を取得
+///
+/// 補助ベクタはカーネルがexec時にスタックに積む情報で、AT_ENTRYは実行ファイルのエントリポイントの
+/// 実行時のアドレスを表す。動的リンカが動き出す前の、exec直後の停止時点でも読み込める
+pub fn read_auxv(pid: Pid, key: u64) -> Result<u64, String> {
+    let data = fs::read(format!("/proc/{pid}/auxv"))
+        .map_err(|e| format!("/proc/{pid}/auxvの読み込みに失敗 : {e}"))?;
+    auxv_value(&data, key).ok_or_else(|| format!("補助ベクタに{key}がありません"))
+}
+
+/// 補助ベクタから種類がkeyの値を探す
+///
+/// 補助ベクタは(種類, 値)の8バイトずつの組の並びで、種類が0(AT_NULL)の組で終わる
+fn auxv_value(data: &[u8], key: u64) -> Option<u64> {
+    data.chunks_exact(16)
+        .map(|c| {
+            let word = |i: usize| u64::from_le_bytes(c[i..i + 8].try_into().unwrap());
+            (word(0), word(8))
+        })
+        .take_while(|(k, _)| *k != 0)
+        .find_map(|(k, v)| (k == key).then_some(v))
+}
+
+/// addrを含む領域を探す
+pub fn find(regions: &[Region], addr: u64) -> Option<&Region> {
+    regions.iter().find(|r| r.contains(addr))
+}
+
+/// ブレークポイントをaddrに設定できるかを検査し、できない場合は理由を返す
+///
+/// 理由には、addrに近い実行可能な領域の一覧を含める
+pub fn check_break_addr(regions: &[Region], addr: u64) -> Result<(), String> {
+    let reason = match find(regions, addr) {
+        Some(r) if r.is_exec() => return Ok(()),
+        Some(r) => format!(
+            "{addr:#x}は実行可能な領域ではありません ({:#x}-{:#x} {} {})",
+            r.start, r.end, r.perms, r.path
+        ),
+        None => format!("{addr:#x}はマップされていません"),
+    };
+
+    let mut nearby: Vec<&Region> = regions.iter().filter(|r| r.is_exec()).collect();
+    nearby.sort_by_key(|r| r.distance(addr));
+    nearby.truncate(MAX_NEARBY);
+    nearby.sort_by_key(|r| r.start);
+
+    let mut msg = reason;
+    if !nearby.is_empty() {
+        msg.push_str("\n付近の実行可能な領域 :");
+        for r in nearby {
+            msg.push_str(&format!(
+                "\n  {:#x}-{:#x} {} {}",
+                r.start, r.end, r.perms, r.path
+            ));
+        }
+    }
+    Err(msg)
+}
+
+/// info proc mapsで表示する行を返す
+///
+/// marksは(名前, アドレス)の組で、アドレスを含む領域の行末に "<- 名前" を付ける。
+/// ripやrspを渡し、実行中のコードとスタックがどの領域にあるかを示すために使う
+pub fn format_regions(regions: &[Region], marks: &[(&str, u64)]) -> Vec<String> {
+    let mut lines =
+        vec!["開始               終了                   大きさ 権限 ファイル".to_string()];
+    for r in regions {
+        let mut line = format!(
+            "{:#018x} {:#018x} {:>#10x} {:4} {}",
+            r.start,
+            r.end,
+            r.end - r.start,
+            r.perms,
+            r.path
+        );
+        for (name, addr) in marks {
+            if r.contains(*addr) {
+                line.push_str(&format!(" <- {name}"));
+            }
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+/// /proc/PID/mapsの内容をパースする
+///
+/// 各行は "開始-終了 権限 オフセット デバイス inode パス" の形式。パースできない行は無視する
+fn parse(text: &str) -> Vec<Region> {
+    text.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let (start, end) = words.next()?.split_once('-')?;
+            let perms = words.next()?.to_string();
+            let path = words.nth(3).unwrap_or("").to_string();
+            Some(Region {
+                start: u64::from_str_radix(start, 16).ok()?,
+                end: u64::from_str_radix(end, 16).ok()?,
+                perms,
+                path,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPS: &str = "\
+00400000-00401000 r--p 00000000 08:01 1234 /tmp/a.out
+00401000-00402000 r-xp 00001000 08:01 1234 /tmp/a.out
+00402000-00403000 r--p 00002000 08:01 1234 /tmp/a.out
+00404000-00405000 rw-p 00003000 08:01 1234 /tmp/a.out
+7ffff7dd0000-7ffff7df8000 r-xp 00000000 08:01 5678 /usr/lib/ld-linux-x86-64.so.2
+7ffffffde000-7ffffffff000 rw-p 00000000 00:00 0 [stack]
+";
+
+    #[test]
+    fn test_parse() {
+        let regions = parse(MAPS);
+        assert_eq!(regions.len(), 6);
+        assert_eq!(
+            regions[1],
+            Region {
+                start: 0x401000,
+                end: 0x402000,
+                perms: "r-xp".to_string(),
+                path: "/tmp/a.out".to_string(),
+            }
+        );
+        assert!(regions[1].is_exec());
+        assert!(!regions[3].is_exec());
+        assert_eq!(regions[5].path, "[stack]");
+    }
+
+    #[test]
+    fn test_check_break_addr() {
+        let regions = parse(MAPS);
+        assert!(check_break_addr(&regions, 0x401136).is_ok());
+        assert!(check_break_addr(&regions, 0x7ffff7dd0000).is_ok());
+
+        // データ領域
+        let msg = check_break_addr(&regions, 0x404010).unwrap_err();
+        assert!(
+            msg.starts_with("0x404010は実行可能な領域ではありません"),
+            "{msg}"
+        );
+        assert!(msg.contains("0x401000-0x402000 r-xp /tmp/a.out"), "{msg}");
+
+        // マップされていない
+        let msg = check_break_addr(&regions, 0x8000).unwrap_err();
+        assert!(msg.starts_with("0x8000はマップされていません"), "{msg}");
+
+        // 領域の終了アドレスは領域に含まない
+        assert!(check_break_addr(&regions, 0x402000).is_err());
+    }
+
+    #[test]
+    fn test_format_regions() {
+        let regions = parse(MAPS);
+        let lines = format_regions(&regions[3..], &[("rip", 0x404010), ("rsp", 0x7fffffffe000)]);
+        assert_eq!(
+            lines,
+            [
+                "開始               終了                   大きさ 権限 ファイル",
+                "0x0000000000404000 0x0000000000405000     0x1000 rw-p /tmp/a.out <- rip",
+                "0x00007ffff7dd0000 0x00007ffff7df8000    0x28000 r-xp /usr/lib/ld-linux-x86-64.so.2",
+                "0x00007ffffffde000 0x00007ffffffff000    0x21000 rw-p [stack] <- rsp",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auxv_value() {
+        let auxv: Vec<u8> = [6, 0x1000, AT_ENTRY, 0x555555569dd0, 0, 0, AT_ENTRY, 1]
+            .iter()
+            .flat_map(|w: &u64| w.to_le_bytes())
+            .collect();
+        assert_eq!(auxv_value(&auxv, AT_ENTRY), Some(0x555555569dd0));
+        assert_eq!(auxv_value(&auxv, 6), Some(0x1000));
+        // AT_NULLより後ろは読まない
+        assert_eq!(auxv_value(&auxv, 1), None);
+        assert_eq!(auxv_value(&auxv[..24], AT_ENTRY), None);
+    }
+}
@@ -0,0 +1,149 @@
+//! 子プロセスの標準入出力のリダイレクト
+//!
+//! `run < input.txt > out.log`のように、runやset argsの引数に含めたリダイレクトを解析する。
+//! デバッガと子プロセスは同じ端末を共有するため、子プロセスが標準入力を読むとデバッガのコマンドと
+//! 入力が混ざってしまう。リダイレクトしたファイルはforkの前に開いておき、
+//! 子プロセスでexecvpの前にdup2で標準入出力に複製する。
+
+use nix::unistd::dup2;
+use std::{
+    fs::{File, OpenOptions},
+    os::unix::io::AsRawFd,
+};
+
+/// 出力のリダイレクト先
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub path: String,
+    pub append: bool, // >>の場合は真。ファイルの末尾に追記する
+}
+
+/// 標準入力、標準出力、標準エラー出力のリダイレクト。指定しなかったものはNone
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Redirects {
+    pub stdin: Option<String>,
+    pub stdout: Option<Output>,
+    pub stderr: Option<Output>,
+}
+
+/// 開いたリダイレクト先のファイル
+pub struct Opened {
+    files: [Option<File>; 3], // 標準入力、標準出力、標準エラー出力の順
+}
+
+impl Redirects {
+    /// 引数argsからリダイレクトを取り除き、残りの引数とリダイレクトを返す
+    ///
+    /// `< ファイル`、`> ファイル`、`>> ファイル`、`2> ファイル`、`2>> ファイル`に対応し、
+    /// 記号とファイル名の間の空白は省略できる。同じものを複数回指定した場合は最後のものを使う
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<(Vec<String>, Self), String> {
+        let mut rest = Vec::new();
+        let mut redirects = Redirects::default();
+        let mut it = args.iter().map(|s| s.as_ref());
+        while let Some(arg) = it.next() {
+            let Some((op, path)) = ["2>>", "2>", ">>", ">", "<"]
+                .iter()
+                .find_map(|op| arg.strip_prefix(op).map(|path| (*op, path)))
+            else {
+                rest.push(arg.to_string());
+                continue;
+            };
+            let path = match path {
+                "" => it
+                    .next()
+                    .ok_or_else(|| format!("{op}の後にファイル名を指定してください"))?,
+                path => path,
+            };
+            let output = |append| {
+                Some(Output {
+                    path: path.to_string(),
+                    append,
+                })
+            };
+            match op {
+                "<" => redirects.stdin = Some(path.to_string()),
+                ">" => redirects.stdout = output(false),
+                ">>" => redirects.stdout = output(true),
+                "2>" => redirects.stderr = output(false),
+                _ => redirects.stderr = output(true),
+            }
+        }
+        Ok((rest, redirects))
+    }
+
+    /// リダイレクト先のファイルを開く。forkの前に呼び出し、開けない場合は子プロセスを生成しない
+    pub fn open(&self) -> Result<Opened, String> {
+        let open_output = |out: &Option<Output>| {
+            out.as_ref()
+                .map(|out| {
+                    OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .append(out.append)
+                        .truncate(!out.append)
+                        .open(&out.path)
+                        .map_err(|e| format!("{}を開けません : {e}", out.path))
+                })
+                .transpose()
+        };
+        let stdin = self
+            .stdin
+            .as_ref()
+            .map(|path| File::open(path).map_err(|e| format!("{path}を開けません : {e}")))
+            .transpose()?;
+        Ok(Opened {
+            files: [
+                stdin,
+                open_output(&self.stdout)?,
+                open_output(&self.stderr)?,
+            ],
+        })
+    }
+}
+
+impl Opened {
+    /// 開いたファイルを標準入出力に複製する。子プロセスでexecvpの前に呼び出す
+    pub fn apply(&self) -> nix::Result<()> {
+        for (fd, file) in self.files.iter().enumerate() {
+            if let Some(file) = file {
+                dup2(file.as_raw_fd(), fd as i32)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<(Vec<String>, Redirects), String> {
+        Redirects::parse(args)
+    }
+
+    #[test]
+    fn test_parse() {
+        let (args, r) = parse(&["-v", "<", "in.txt", ">out.log", "x"]).unwrap();
+        assert_eq!(args, ["-v", "x"]);
+        assert_eq!(r.stdin.as_deref(), Some("in.txt"));
+        assert_eq!(
+            r.stdout,
+            Some(Output {
+                path: "out.log".to_string(),
+                append: false
+            })
+        );
+        assert_eq!(r.stderr, None);
+
+        let (args, r) = parse(&["2>>", "err.log", ">>", "out.log"]).unwrap();
+        assert!(args.is_empty());
+        assert!(r.stdout.unwrap().append);
+        assert_eq!(r.stderr.unwrap().path, "err.log");
+
+        let (args, r) = parse(&["a", "b"]).unwrap();
+        assert_eq!(args, ["a", "b"]);
+        assert_eq!(r, Redirects::default());
+
+        assert!(parse(&["a", ">"]).is_err());
+    }
+}
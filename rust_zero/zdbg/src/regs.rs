@@ -0,0 +1,79 @@
+//! レジスタの値の変更(set reg)
+//!
+//! `set reg rip 0x401000`や`set reg rdi 42`のように、レジスタ名と値を指定して変更する。
+//! 値は0xから始まる16進数か10進数で指定し、負の10進数は2の補数として設定する。
+
+use nix::libc::user_regs_struct;
+
+/// 変更できるレジスタの名前の一覧
+pub const REG_NAMES: &[&str] = &[
+    "rip", "rsp", "rbp", "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12",
+    "r13", "r14", "r15", "eflags", "fs_base", "gs_base",
+];
+
+/// 名前がnameのレジスタへの参照を返す。大文字と小文字は区別しない
+pub fn reg_mut<'a>(regs: &'a mut user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    let reg = match name.to_ascii_lowercase().as_str() {
+        "rip" => &mut regs.rip,
+        "rsp" => &mut regs.rsp,
+        "rbp" => &mut regs.rbp,
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "eflags" => &mut regs.eflags,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        _ => return None,
+    };
+    Some(reg)
+}
+
+/// レジスタに設定する値を解析する
+pub fn parse_value(s: &str) -> Result<u64, String> {
+    let val = if let Some(hex) = s.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if s.starts_with('-') {
+        s.parse::<i64>().ok().map(|v| v as u64)
+    } else {
+        s.parse::<u64>().ok()
+    };
+    val.ok_or_else(|| format!("値は16進数(0x...)か10進数で指定してください : {s}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reg_mut() {
+        let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+        *reg_mut(&mut regs, "RDI").unwrap() = 42;
+        assert_eq!(regs.rdi, 42);
+        assert!(reg_mut(&mut regs, "xmm0").is_none());
+
+        // 一覧のレジスタはすべて変更できる
+        for name in REG_NAMES {
+            assert!(reg_mut(&mut regs, name).is_some(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("0x401000"), Ok(0x401000));
+        assert_eq!(parse_value("42"), Ok(42));
+        assert_eq!(parse_value("-1"), Ok(u64::MAX));
+        assert!(parse_value("0xg").is_err());
+        assert!(parse_value("abc").is_err());
+    }
+}
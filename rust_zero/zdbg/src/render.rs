@@ -0,0 +1,142 @@
+//! メモリの値の表示形式(x、stackで共通)
+//!
+//! 読み込んだバイト列を単位ごとに区切り、指定された基数とバイト順で文字列にする。
+//! 形式、単位、バイト順はそれぞれ1文字で指定し、コマンドの/の後に順不同で並べる。
+//!
+//! - 形式     : x = 16進数、d = 符号付き10進数、u = 符号なし10進数、t = 2進数
+//! - 単位     : b = 1バイト、h = 2バイト、w = 4バイト、g = 8バイト
+//! - バイト順 : < = リトルエンディアン、> = ビッグエンディアン
+
+/// 表示する基数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Hex,      // 16進数 (x)
+    Signed,   // 符号付き10進数 (d)
+    Unsigned, // 符号なし10進数 (u)
+    Bin,      // 2進数 (t)
+}
+
+impl Radix {
+    /// 形式を表す文字を解析する
+    pub fn parse(c: char) -> Option<Self> {
+        match c {
+            'x' => Some(Radix::Hex),
+            'd' => Some(Radix::Signed),
+            'u' => Some(Radix::Unsigned),
+            't' => Some(Radix::Bin),
+            _ => None,
+        }
+    }
+}
+
+/// 単位の中のバイト順
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little, // 先頭のバイトが最下位 (<)
+    Big,    // 先頭のバイトが最上位 (>)
+}
+
+/// 値の表示形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Render {
+    pub radix: Radix,   // 基数
+    pub size: usize,    // 単位のバイト数。1、2、4、8のいずれか
+    pub endian: Endian, // バイト順
+}
+
+impl Render {
+    /// 基数、単位の大きさ、リトルエンディアンの表示形式を作る
+    pub fn new(radix: Radix, size: usize) -> Self {
+        Render {
+            radix,
+            size,
+            endian: Endian::Little,
+        }
+    }
+
+    /// 形式、単位、バイト順を表す1文字を解析して反映する。いずれでもない場合はfalseを返す
+    pub fn set_flag(&mut self, c: char) -> bool {
+        if let Some(radix) = Radix::parse(c) {
+            self.radix = radix;
+            return true;
+        }
+        match c {
+            'b' => self.size = 1,
+            'h' => self.size = 2,
+            'w' => self.size = 4,
+            'g' => self.size = 8,
+            '<' => self.endian = Endian::Little,
+            '>' => self.endian = Endian::Big,
+            _ => return false,
+        }
+        true
+    }
+
+    /// 1単位分のバイト列を文字列にする
+    pub fn unit(&self, unit: &[u8]) -> String {
+        let mut bytes = [0; 8];
+        match self.endian {
+            Endian::Little => bytes[..unit.len()].copy_from_slice(unit),
+            Endian::Big => bytes[..unit.len()]
+                .iter_mut()
+                .zip(unit.iter().rev())
+                .for_each(|(dst, src)| *dst = *src),
+        }
+        let val = u64::from_le_bytes(bytes);
+        match self.radix {
+            Radix::Hex => format!("{val:#0w$x}", w = self.size * 2 + 2),
+            Radix::Bin => format!("{val:#0w$b}", w = self.size * 8 + 2),
+            Radix::Unsigned => val.to_string(),
+            Radix::Signed => {
+                // 単位のバイト数で符号拡張する
+                let shift = 64 - self.size * 8;
+                (((val << shift) as i64) >> shift).to_string()
+            }
+        }
+    }
+
+    /// バイト列を単位ごとに区切って文字列にする。端数のバイトも1単位として扱う
+    pub fn units(&self, data: &[u8]) -> Vec<String> {
+        data.chunks(self.size).map(|unit| self.unit(unit)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_flag() {
+        let mut r = Render::new(Radix::Hex, 4);
+        assert!(r.set_flag('t'));
+        assert!(r.set_flag('h'));
+        assert!(r.set_flag('>'));
+        assert_eq!(
+            r,
+            Render {
+                radix: Radix::Bin,
+                size: 2,
+                endian: Endian::Big
+            }
+        );
+        assert!(!r.set_flag('s'));
+        assert!(!r.set_flag('q'));
+    }
+
+    #[test]
+    fn test_unit() {
+        let data = [0x12, 0x34, 0xff, 0xfe];
+        let r = |spec: &str| {
+            let mut r = Render::new(Radix::Hex, 4);
+            spec.chars().for_each(|c| assert!(r.set_flag(c)));
+            r
+        };
+        assert_eq!(r("xw").unit(&data), "0xfeff3412");
+        assert_eq!(r("xw>").unit(&data), "0x1234fffe");
+        assert_eq!(r("dh").units(&data), vec!["13330", "-257"]);
+        assert_eq!(r("uh").units(&data), vec!["13330", "65279"]);
+        assert_eq!(r("dh>").units(&data), vec!["4660", "-2"]);
+        assert_eq!(r("tb").unit(&data[..1]), "0b00010010");
+        assert_eq!(r("ug").unit(&[0xff; 8]), u64::MAX.to_string());
+    }
+}
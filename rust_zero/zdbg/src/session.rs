@@ -0,0 +1,289 @@
+//! デバッグセッションの記録と再生
+//!
+//! 入力されたコマンドと、それによって発生した停止イベントをセッションファイルに記録する。
+//! セッションファイルは次のような行指向のテキストである。
+//!
+//! ```text
+//! # zdbg session: ./target/debug/dbg_target
+//! cmd break 0x401136
+//! cmd run
+//! stop break 0x401136
+//! cmd stepi
+//! stop step 0x40113a
+//! ```
+//!
+//! 再生時は記録されたコマンドを順に実行し、停止イベントが記録と異なる場合はそこで再生を一時停止する。
+//! ASLRを無効にして実行するため、同じ実行ファイルであれば停止するアドレスも再現される。
+
+use crate::helper::DynError;
+use nix::sys::{signal::Signal, wait::WaitStatus};
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    str::FromStr,
+};
+
+/// 子プロセスの停止イベント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    Break(u64),          // ブレークポイントで停止。値は停止したアドレス
+    Step(u64),           // ステップ実行により停止
+    Signal(Signal, u64), // シグナルを受信して停止
+    Watch(u64, u64), // ハードウェアウォッチポイントで停止。値は監視するアドレスと停止したアドレス
+    Syscall(u64, u64), // catch syscallで捕捉したシステムコールの入口で停止。値はシステムコール番号と停止したアドレス
+    Exited(i32),       // 終了。値は終了コード
+    Signaled(Signal),  // シグナルにより終了
+}
+
+impl Stop {
+    /// waitpidの結果が子プロセスの終了を表す場合、対応する停止イベントを返す
+    pub fn from_exit(status: &WaitStatus) -> Option<Self> {
+        match status {
+            WaitStatus::Exited(_, code) => Some(Stop::Exited(*code)),
+            WaitStatus::Signaled(_, sig, _) => Some(Stop::Signaled(*sig)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Break(pc) => write!(f, "break {pc:#x}"),
+            Stop::Step(pc) => write!(f, "step {pc:#x}"),
+            Stop::Signal(sig, pc) => write!(f, "signal {sig} {pc:#x}"),
+            Stop::Watch(addr, pc) => write!(f, "watch {addr:#x} {pc:#x}"),
+            Stop::Syscall(nr, pc) => write!(f, "syscall {nr} {pc:#x}"),
+            Stop::Exited(code) => write!(f, "exited {code}"),
+            Stop::Signaled(sig) => write!(f, "signaled {sig}"),
+        }
+    }
+}
+
+impl FromStr for Stop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn addr(s: &str) -> Result<u64, String> {
+            let hex = s
+                .strip_prefix("0x")
+                .ok_or_else(|| format!("アドレスは16進数でのみ指定可能です : {s}"))?;
+            u64::from_str_radix(hex, 16).map_err(|e| format!("アドレス変換エラー : {e}"))
+        }
+        fn signal(s: &str) -> Result<Signal, String> {
+            Signal::from_str(s).map_err(|_| format!("不明なシグナル : {s}"))
+        }
+
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.as_slice() {
+            ["break", pc] => Ok(Stop::Break(addr(pc)?)),
+            ["step", pc] => Ok(Stop::Step(addr(pc)?)),
+            ["signal", sig, pc] => Ok(Stop::Signal(signal(sig)?, addr(pc)?)),
+            ["watch", watch, pc] => Ok(Stop::Watch(addr(watch)?, addr(pc)?)),
+            ["syscall", nr, pc] => nr
+                .parse()
+                .map_err(|e| format!("システムコール番号の変換エラー : {e}"))
+                .and_then(|nr| Ok(Stop::Syscall(nr, addr(pc)?))),
+            ["exited", code] => code
+                .parse()
+                .map(Stop::Exited)
+                .map_err(|e| format!("終了コードの変換エラー : {e}")),
+            ["signaled", sig] => Ok(Stop::Signaled(signal(sig)?)),
+            _ => Err(format!("不正な停止イベント : {s}")),
+        }
+    }
+}
+
+/// セッションファイルへの記録
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    /// セッションファイルを作成し、ヘッダとしてデバッグ対象の実行ファイル名を書き込む
+    pub fn create(path: &str, target: &str) -> Result<Self, DynError> {
+        let mut rec = Recorder {
+            out: BufWriter::new(File::create(path)?),
+        };
+        rec.write_line(&format!("{HEADER}{target}"))?;
+        Ok(rec)
+    }
+
+    /// 入力されたコマンドを記録
+    pub fn record_cmd(&mut self, line: &str) -> Result<(), DynError> {
+        self.write_line(&format!("cmd {line}"))
+    }
+
+    /// 停止イベントを記録
+    pub fn record_stop(&mut self, stop: &Stop) -> Result<(), DynError> {
+        self.write_line(&format!("stop {stop}"))
+    }
+
+    /// 1行書き込む
+    ///
+    /// zdbgやデバッグ対象が異常終了した場合でも、そこまでのセッションが残るように毎回フラッシュする
+    fn write_line(&mut self, line: &str) -> Result<(), DynError> {
+        writeln!(self.out, "{line}")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// 記録された1コマンド分のエントリ
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub cmd: String,        // 入力されたコマンド
+    pub stop: Option<Stop>, // コマンドにより発生した停止イベント
+}
+
+/// 記録されたセッションの再生
+pub struct Replay {
+    entries: VecDeque<Entry>, // 未実行のエントリ
+    paused: bool,             // 記録と異なる停止イベントが発生し、一時停止中なら真
+}
+
+impl Replay {
+    /// セッションファイルを読み込む
+    ///
+    /// 記録時と実行ファイルが異なる場合は警告を表示する
+    pub fn load(path: &str, target: &str) -> Result<Self, DynError> {
+        let text = fs::read_to_string(path)?;
+        if let Some(recorded) = text.lines().next().and_then(|l| l.strip_prefix(HEADER)) {
+            if recorded != target {
+                eprintln!(
+                    "<<記録時の実行ファイルと異なります : 記録 = {recorded}, 実行 = {target}>>"
+                );
+            }
+        }
+        let entries = parse(&text).map_err(|e| format!("{path}: {e}"))?;
+        Ok(Replay {
+            entries: entries.into(),
+            paused: false,
+        })
+    }
+
+    /// 次に再生するエントリを取り出す。一時停止中か、すべて再生済みの場合はNone
+    pub fn next(&mut self) -> Option<Entry> {
+        if self.paused {
+            None
+        } else {
+            self.entries.pop_front()
+        }
+    }
+
+    /// 実行結果の停止イベントを記録と比較し、異なる場合は一時停止する
+    pub fn check(&mut self, entry: &Entry, actual: Option<&Stop>) {
+        if entry.stop.as_ref() == actual {
+            return;
+        }
+        fn show(stop: Option<&Stop>) -> String {
+            stop.map_or("なし".to_string(), |s| s.to_string())
+        }
+        eprintln!(
+            "<<記録と異なる停止イベントです : コマンド = {}, 記録 = {}, 実際 = {}>>",
+            entry.cmd,
+            show(entry.stop.as_ref()),
+            show(actual)
+        );
+        eprintln!("<<再生を一時停止しました。replayで再開します>>");
+        self.paused = true;
+    }
+
+    /// 一時停止した再生を再開する
+    pub fn resume(&mut self) {
+        if self.entries.is_empty() {
+            eprintln!("<<再生するコマンドはありません>>");
+        }
+        self.paused = false;
+    }
+
+    /// 一時停止中なら真
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
+
+/// セッションファイルのヘッダ
+const HEADER: &str = "# zdbg session: ";
+
+/// セッションファイルをパースする
+///
+/// stop行は直前のcmd行に対応付ける。空行と#で始まる行は無視する。
+fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let err = |msg: &str| format!("{}行目: {msg}", i + 1);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(cmd) = line.strip_prefix("cmd ") {
+            entries.push(Entry {
+                cmd: cmd.to_string(),
+                stop: None,
+            });
+        } else if let Some(stop) = line.strip_prefix("stop ") {
+            let Some(entry) = entries.last_mut() else {
+                return Err(err("コマンドより前に停止イベントがあります"));
+            };
+            if entry.stop.is_some() {
+                return Err(err("1つのコマンドに複数の停止イベントがあります"));
+            }
+            entry.stop = Some(stop.parse().map_err(|e: String| err(&e))?);
+        } else {
+            return Err(err(&format!("不正な行 : {line}")));
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_round_trip() {
+        let stops = [
+            Stop::Break(0x401136),
+            Stop::Step(0x40113a),
+            Stop::Signal(Signal::SIGSEGV, 0x401000),
+            Stop::Watch(0x404020, 0x401150),
+            Stop::Syscall(257, 0x7ffff7e9a0b2),
+            Stop::Exited(3),
+            Stop::Signaled(Signal::SIGKILL),
+        ];
+        for stop in stops {
+            assert_eq!(stop.to_string().parse::<Stop>(), Ok(stop));
+        }
+        assert!("break 401136".parse::<Stop>().is_err());
+        assert!("signal SIGFOO 0x0".parse::<Stop>().is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let text =
+            "# zdbg session: a.out\ncmd break 0x401136\ncmd run\nstop break 0x401136\n\ncmd regs\n";
+        assert_eq!(
+            parse(text),
+            Ok(vec![
+                Entry {
+                    cmd: "break 0x401136".to_string(),
+                    stop: None
+                },
+                Entry {
+                    cmd: "run".to_string(),
+                    stop: Some(Stop::Break(0x401136))
+                },
+                Entry {
+                    cmd: "regs".to_string(),
+                    stop: None
+                },
+            ])
+        );
+
+        assert!(parse("stop exited 0\n").is_err());
+        assert!(parse("cmd c\nstop exited 0\nstop exited 1\n").is_err());
+        assert!(parse("run\n").is_err());
+    }
+}
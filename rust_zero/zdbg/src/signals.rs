@@ -0,0 +1,175 @@
+//! 子プロセスが受信したシグナルの扱い(handle)
+//!
+//! ptraceでトレース中の子プロセスにシグナルが届くと、子プロセスはシグナルを処理する前に停止し、
+//! デバッガに通知される。再開時にptrace::contの引数にシグナルを指定しないと、そのシグナルは
+//! 子プロセスに届かずに捨てられる。
+//! そこで、シグナルごとに停止するか(stop)、受信したことを表示するか(print)、
+//! 再開時に子プロセスに渡すか(pass)を決めておき、`handle SIGSEGV stop print pass`のように変更できるようにする。
+
+use nix::sys::signal::Signal;
+use std::{collections::HashMap, str::FromStr};
+
+/// 1つのシグナルの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    pub stop: bool,  // 受信したら停止する
+    pub print: bool, // 受信したことを表示する
+    pub pass: bool,  // 再開時に子プロセスに渡す
+}
+
+impl Policy {
+    /// シグナルsigの既定の扱い
+    ///
+    /// SIGTRAPとSIGINTはデバッガが使うため渡さない。SIGCHLDのように頻繁に届き、
+    /// 通常の動作の一部であるシグナルは停止も表示もせずに渡す。その他は停止して渡す
+    pub fn default_for(sig: Signal) -> Self {
+        match sig {
+            Signal::SIGTRAP | Signal::SIGINT => Policy {
+                stop: true,
+                print: true,
+                pass: false,
+            },
+            Signal::SIGALRM
+            | Signal::SIGURG
+            | Signal::SIGCHLD
+            | Signal::SIGWINCH
+            | Signal::SIGIO
+            | Signal::SIGVTALRM
+            | Signal::SIGPROF => Policy {
+                stop: false,
+                print: false,
+                pass: true,
+            },
+            _ => Policy {
+                stop: true,
+                print: true,
+                pass: true,
+            },
+        }
+    }
+
+    /// handleのキーワードを適用する
+    ///
+    /// stopはprintを、noprintはnostopを伴う。停止するのに表示しないことはできないため
+    fn apply(&mut self, keyword: &str) -> Result<(), String> {
+        match keyword {
+            "stop" => (self.stop, self.print) = (true, true),
+            "nostop" => self.stop = false,
+            "print" => self.print = true,
+            "noprint" => (self.stop, self.print) = (false, false),
+            "pass" => self.pass = true,
+            "nopass" => self.pass = false,
+            _ => return Err(format!("不明なキーワード : {keyword}")),
+        }
+        Ok(())
+    }
+}
+
+/// シグナルごとの扱い。変更していないシグナルは既定の扱いとなる
+#[derive(Debug, Default)]
+pub struct SignalTable {
+    policies: HashMap<Signal, Policy>,
+}
+
+impl SignalTable {
+    /// シグナルsigの扱い
+    pub fn get(&self, sig: Signal) -> Policy {
+        self.policies
+            .get(&sig)
+            .copied()
+            .unwrap_or_else(|| Policy::default_for(sig))
+    }
+
+    /// handleコマンドの引数(シグナル名とキーワード)を解析して適用し、変更後の扱いを返す
+    ///
+    /// キーワードを省略した場合は変更せずに現在の扱いを返す。
+    /// いずれかのキーワードが不正な場合は何も変更しない
+    pub fn handle(&mut self, args: &[&str]) -> Result<(Signal, Policy), String> {
+        let (name, keywords) = args
+            .split_first()
+            .ok_or_else(|| "シグナル名を指定してください".to_string())?;
+        let sig = parse_signal(name)?;
+        let mut policy = self.get(sig);
+        for keyword in keywords {
+            policy.apply(keyword)?;
+        }
+        self.policies.insert(sig, policy);
+        Ok((sig, policy))
+    }
+}
+
+/// SIGSEGV、SEGV、segv、11のいずれかの形式でシグナルを指定する
+pub fn parse_signal(name: &str) -> Result<Signal, String> {
+    if let Ok(n) = name.parse::<i32>() {
+        return Signal::try_from(n).map_err(|_| format!("不明なシグナル : {name}"));
+    }
+    let upper = name.to_ascii_uppercase();
+    let full = match upper.starts_with("SIG") {
+        true => upper,
+        false => format!("SIG{upper}"),
+    };
+    Signal::from_str(&full).map_err(|_| format!("不明なシグナル : {name}"))
+}
+
+/// info signalsやhandleで表示する、シグナルの扱いの一覧
+pub fn format_policies(rows: &[(Signal, Policy)]) -> String {
+    let yes_no = |b: bool| if b { "Yes" } else { "No" };
+    // 全角文字は幅を数えられないため、見出しは空白を含めてそのまま書く
+    let mut s = "シグナル   停止 表示  渡す\n".to_string();
+    for (sig, p) in rows {
+        s += &format!(
+            "{:<10} {:<4} {:<5} {}\n",
+            sig.as_str(),
+            yes_no(p.stop),
+            yes_no(p.print),
+            yes_no(p.pass)
+        );
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("SIGSEGV"), Ok(Signal::SIGSEGV));
+        assert_eq!(parse_signal("segv"), Ok(Signal::SIGSEGV));
+        assert_eq!(parse_signal("10"), Ok(Signal::SIGUSR1));
+        assert!(parse_signal("SIGFOO").is_err());
+        assert!(parse_signal("0").is_err());
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut table = SignalTable::default();
+        assert!(!table.get(Signal::SIGCHLD).stop);
+        assert!(!table.get(Signal::SIGTRAP).pass);
+
+        let (sig, p) = table.handle(&["SIGUSR1", "nostop", "noprint"]).unwrap();
+        assert_eq!(sig, Signal::SIGUSR1);
+        assert_eq!(
+            p,
+            Policy {
+                stop: false,
+                print: false,
+                pass: true
+            }
+        );
+        // stopはprintを伴う
+        let (_, p) = table.handle(&["usr1", "stop", "nopass"]).unwrap();
+        assert!(p.stop && p.print && !p.pass);
+        assert_eq!(table.get(Signal::SIGUSR1), p);
+
+        // 不正なキーワードがあれば変更しない
+        assert!(table.handle(&["SIGUSR1", "pass", "bogus"]).is_err());
+        assert!(!table.get(Signal::SIGUSR1).pass);
+        assert!(table.handle(&[]).is_err());
+
+        assert_eq!(
+            format_policies(&[(Signal::SIGUSR1, p)]),
+            "シグナル   停止 表示  渡す\nSIGUSR1    Yes  Yes   No\n"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! 停止位置の前後のソースコードの表示(list)
+//!
+//! 行番号情報(.debug_line)から求めたソースファイルをディスクから読み込み、
+//! 停止した行を中心とした範囲を行番号付きで表示する。
+
+use std::ops::Range;
+
+/// listで表示する行数
+pub const LIST_LINES: usize = 10;
+
+/// 全体でlen行のファイルから、line行目(1始まり)を中心にcount行を表示する範囲を返す
+///
+/// 範囲は0始まりの添字で表す。ファイルの先頭や末尾に近い場合は、はみ出さないようにずらす
+pub fn window(line: u64, count: usize, len: usize) -> Range<usize> {
+    let idx = (line.saturating_sub(1) as usize).min(len.saturating_sub(1));
+    let start = idx.saturating_sub(count / 2).min(len.saturating_sub(count));
+    start..(start + count).min(len)
+}
+
+/// ソースコードsrcのline行目を中心にcount行を、行番号付きで整形する。line行目には=>を付ける
+pub fn render(src: &str, line: u64, count: usize) -> Vec<String> {
+    let lines: Vec<&str> = src.lines().collect();
+    let range = window(line, count, lines.len());
+    let width = range.end.to_string().len();
+    range
+        .map(|i| {
+            let marker = if i as u64 + 1 == line { "=>" } else { "  " };
+            format!("{marker} {:>width$}  {}", i + 1, lines[i])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        assert_eq!(window(20, 10, 100), 14..24);
+        // 先頭と末尾ではみ出さないようにずらす
+        assert_eq!(window(2, 10, 100), 0..10);
+        assert_eq!(window(99, 10, 100), 90..100);
+        // ファイルが短い場合は全体
+        assert_eq!(window(3, 10, 5), 0..5);
+        // 行番号がファイルの行数を超える場合は末尾
+        assert_eq!(window(120, 10, 100), 90..100);
+        assert_eq!(window(1, 10, 0), 0..0);
+    }
+
+    #[test]
+    fn test_render() {
+        let src = (1..=12)
+            .map(|i| format!("line{i}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let lines = render(&src, 9, 4);
+        assert_eq!(
+            lines,
+            vec![
+                "    7  line7",
+                "    8  line8",
+                "=>  9  line9",
+                "   10  line10",
+            ]
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! スタックの破壊の検査(checkstack)
+//!
+//! バッファオーバーフローなどでスタック上のリターンアドレスやスタックカナリアが書き換えられても、
+//! 関数から戻るまでは何も起きず、戻った時点で不正なアドレスに飛ぶか__stack_chk_failで異常終了するため、
+//! どこで書き換えられたのかが分からない。
+//! そこで、停止した時点のバックトレースの各フレームについて、リターンアドレスが実行可能な領域を指しているかと、
+//! 前回の検査でカナリアの値を保持していたスロットが今も同じ値を保持しているかを検査する。
+//! checkstack onとすると、停止するたびに検査して、問題がある場合のみ警告する。
+
+use crate::{
+    backtrace::Frame,
+    maps::{self, Region},
+};
+use std::{collections::BTreeMap, fmt};
+
+/// カナリアを探す、各フレームの末尾(CFA)からの範囲のバイト数
+///
+/// カナリアは保存したrbpやリターンアドレスの直下に置かれるため、フレーム全体は探さない
+const CANARY_SCAN: u64 = 0x40;
+
+/// 検査で見つかった問題
+#[derive(Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// frame番目のフレームのリターンアドレスvalue(slotに保存)が実行可能な領域を指していない
+    BadReturn {
+        frame: usize,
+        slot: Option<u64>,
+        value: u64,
+        region: Option<String>, // valueを含む領域の権限とパス。マップされていない場合はNone
+    },
+    /// frame番目のフレームでカナリアを保持していたslotの値がvalueに書き換えられた
+    Canary { frame: usize, slot: u64, value: u64 },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::BadReturn {
+                frame,
+                slot,
+                value,
+                region,
+            } => {
+                write!(f, "フレーム#{frame}のリターンアドレス{value:#x}")?;
+                if let Some(slot) = slot {
+                    write!(f, " ({slot:#x}に保存)")?;
+                }
+                match region {
+                    Some(region) => write!(f, "は実行可能な領域を指していません ({region})"),
+                    None => write!(f, "はマップされていない領域を指しています"),
+                }
+            }
+            Warning::Canary { frame, slot, value } => write!(
+                f,
+                "フレーム#{frame}のスタックカナリア ({slot:#x}) が{value:#x}に書き換えられました"
+            ),
+        }
+    }
+}
+
+/// カナリアを保持していたスロットを記録し、スタックの破壊を検査する
+#[derive(Debug, Default)]
+pub struct StackChecker {
+    pub auto: bool, // 真なら停止するたびに検査する(checkstack on)
+    // カナリアを保持していたスロットのアドレスから、そのフレームの(CFA, リターンアドレス)へのマップ
+    canaries: BTreeMap<u64, (u64, u64)>,
+}
+
+impl StackChecker {
+    /// 記録したスロットを消去する。プロセスを実行し直すとカナリアの値が変わるため、runの際に呼び出す
+    pub fn reset(&mut self) {
+        self.canaries.clear();
+    }
+
+    /// 記録しているカナリアのスロットの数
+    pub fn num_canaries(&self) -> usize {
+        self.canaries.len()
+    }
+
+    /// バックトレースframesを検査し、見つかった問題を返す
+    ///
+    /// regionsは/proc/PID/maps、canaryはTLSに保存されたカナリアの値(読み込めない場合はNone)、
+    /// readはアドレスから8バイトを読み込む関数。
+    /// 前回の検査でカナリアを保持していたスロットは、CFAとリターンアドレスが同じフレームが
+    /// まだ存在する場合のみ検査する。同じ位置で別の関数が呼び出された場合の誤検知を避けるため
+    pub fn check(
+        &mut self,
+        frames: &[Frame],
+        regions: &[Region],
+        canary: Option<u64>,
+        read: impl Fn(u64) -> Option<u64>,
+    ) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        for (i, f) in frames.iter().enumerate().skip(1) {
+            match maps::find(regions, f.pc) {
+                Some(r) if r.is_exec() => (),
+                r => warnings.push(Warning::BadReturn {
+                    frame: i,
+                    slot: f.ra_addr,
+                    value: f.pc,
+                    region: r.map(|r| format!("{} {}", r.perms, r.path).trim_end().to_string()),
+                }),
+            }
+        }
+
+        let Some(canary) = canary.filter(|c| *c != 0) else {
+            self.canaries.clear();
+            return warnings;
+        };
+
+        // 各フレームの(CFA, リターンアドレス)。フレームiのCFAはフレームi+1のrsp
+        let ends: Vec<(u64, u64)> = frames.windows(2).map(|w| (w[1].sp, w[1].pc)).collect();
+
+        // 前回カナリアを保持していたスロットのうち、フレームが残っているものを検査する
+        for (slot, end) in std::mem::take(&mut self.canaries) {
+            let Some(frame) = ends.iter().position(|e| *e == end) else {
+                continue; // 関数から戻った
+            };
+            match read(slot) {
+                Some(value) if value == canary => (),
+                Some(value) => warnings.push(Warning::Canary { frame, slot, value }),
+                None => (),
+            }
+        }
+
+        // 各フレームの末尾付近でカナリアの値を保持しているスロットを記録する
+        for (i, end) in ends.iter().enumerate() {
+            let start = frames[i].sp.max(end.0.saturating_sub(CANARY_SCAN)) & !7;
+            for slot in (start..end.0).step_by(8) {
+                if read(slot) == Some(canary) {
+                    self.canaries.insert(slot, *end);
+                }
+            }
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const CANARY: u64 = 0x1234_5678_9abc_de00;
+
+    fn region(start: u64, end: u64, perms: &str, path: &str) -> Region {
+        Region {
+            start,
+            end,
+            perms: perms.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    fn frame(pc: u64, sp: u64, ra_addr: Option<u64>) -> Frame {
+        Frame { pc, sp, ra_addr }
+    }
+
+    #[test]
+    fn test_check() {
+        let regions = [
+            region(0x401000, 0x402000, "r-xp", "/tmp/a.out"),
+            region(0x404000, 0x405000, "rw-p", "/tmp/a.out"),
+            region(0x7000, 0x8000, "rw-p", "[stack]"),
+        ];
+        // main -> f -> g(CFA 0x7fc0、カナリアは0x7fb0)で停止
+        let frames = [
+            frame(0x401100, 0x7f80, None),
+            frame(0x401200, 0x7fc0, Some(0x7fb8)),
+            frame(0x401300, 0x7ff0, Some(0x7fe8)),
+        ];
+        let mut mem: HashMap<u64, u64> = [(0x7fb0, CANARY)].into_iter().collect();
+        let mut checker = StackChecker::default();
+        let warnings = checker.check(&frames, &regions, Some(CANARY), |a| {
+            Some(mem.get(&a).copied().unwrap_or(0))
+        });
+        assert!(warnings.is_empty());
+        assert_eq!(checker.num_canaries(), 1);
+
+        // gのカナリアと、fとmainのリターンアドレスが書き換えられた
+        mem.insert(0x7fb0, 0x4141414141414141);
+        let frames = [
+            frame(0x401150, 0x7f80, None),
+            frame(0x401200, 0x7fc0, Some(0x7fb8)),
+            frame(0x404010, 0x7ff0, Some(0x7fe8)),
+            frame(0x4141414141414141, 0x8000, Some(0x7ff8)),
+        ];
+        let warnings = checker.check(&frames, &regions, Some(CANARY), |a| {
+            Some(mem.get(&a).copied().unwrap_or(0))
+        });
+        assert_eq!(
+            warnings,
+            [
+                Warning::BadReturn {
+                    frame: 2,
+                    slot: Some(0x7fe8),
+                    value: 0x404010,
+                    region: Some("rw-p /tmp/a.out".to_string()),
+                },
+                Warning::BadReturn {
+                    frame: 3,
+                    slot: Some(0x7ff8),
+                    value: 0x4141414141414141,
+                    region: None,
+                },
+                Warning::Canary {
+                    frame: 0,
+                    slot: 0x7fb0,
+                    value: 0x4141414141414141
+                },
+            ]
+        );
+        assert_eq!(
+            warnings[1].to_string(),
+            "フレーム#3のリターンアドレス0x4141414141414141 (0x7ff8に保存)はマップされていない領域を指しています"
+        );
+
+        // gから戻った後に、fの別の位置から呼び出された関数のカナリアとしては検査しない
+        checker.reset();
+        mem.insert(0x7fb0, CANARY);
+        let frames = [
+            frame(0x401100, 0x7f80, None),
+            frame(0x401200, 0x7fc0, Some(0x7fb8)),
+        ];
+        checker.check(&frames, &regions, Some(CANARY), |a| mem.get(&a).copied());
+        mem.insert(0x7fb0, 0);
+        let frames = [
+            frame(0x401100, 0x7f80, None),
+            frame(0x401280, 0x7fc0, Some(0x7fb8)),
+        ];
+        assert!(checker
+            .check(&frames, &regions, Some(CANARY), |a| mem.get(&a).copied())
+            .is_empty());
+    }
+}
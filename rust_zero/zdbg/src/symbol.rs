@@ -0,0 +1,182 @@
+//! ELFのシンボルテーブルによる関数名とアドレスの対応
+//!
+//! .symtab(ストリップされている場合は.dynsym)から関数を表すシンボルを読み込み、
+//! 名前からアドレス、アドレスから名前を引けるようにする。アドレスは実行ファイル上のアドレスであり、
+//! PIEの場合は実行時にロードされたアドレスとの差(bias)を加える必要がある。
+
+use crate::helper::DynError;
+use object::{Object, ObjectSymbol, SymbolKind};
+use regex::Regex;
+use std::fs;
+
+/// 関数のシンボル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String, // シンボル名
+    pub addr: u64,    // 実行ファイル上のアドレス
+    pub size: u64,    // 関数のバイト数。不明な場合は0
+}
+
+/// 実行ファイル中の関数のシンボルの一覧
+#[derive(Debug)]
+pub struct SymbolTable {
+    funcs: Vec<Symbol>, // 名前順に並べた関数
+}
+
+impl SymbolTable {
+    /// 実行ファイルを読み込み、定義されている関数のシンボルの一覧を生成
+    pub fn load(filename: &str) -> Result<Self, DynError> {
+        let data = fs::read(filename)?;
+        let obj = object::File::parse(&*data)?;
+        let symbols = if obj.symbols().next().is_some() {
+            obj.symbols()
+        } else {
+            obj.dynamic_symbols()
+        };
+
+        let funcs = symbols
+            .filter(|s| s.kind() == SymbolKind::Text && s.is_definition() && s.address() != 0)
+            .filter_map(|s| {
+                let name = s.name().ok().filter(|name| !name.is_empty())?;
+                Some(Symbol {
+                    name: name.to_string(),
+                    addr: s.address(),
+                    size: s.size(),
+                })
+            })
+            .collect();
+        Ok(Self::new(funcs))
+    }
+
+    /// 関数のシンボルfuncsから一覧を生成
+    fn new(mut funcs: Vec<Symbol>) -> Self {
+        funcs.sort_by(|a, b| a.name.cmp(&b.name).then(a.addr.cmp(&b.addr)));
+        funcs.dedup();
+        SymbolTable { funcs }
+    }
+
+    /// 名前が正規表現reにマッチする関数を名前順に返す
+    ///
+    /// 別名などで同じアドレスを持つシンボルが複数マッチした場合は、最初のもののみを返す
+    pub fn matching(&self, re: &Regex) -> Vec<&Symbol> {
+        let mut result: Vec<&Symbol> = Vec::new();
+        for sym in self.funcs.iter().filter(|s| re.is_match(&s.name)) {
+            if result.iter().all(|s| s.addr != sym.addr) {
+                result.push(sym);
+            }
+        }
+        result
+    }
+
+    /// 名前がnameの関数を返す。同名の関数が複数ある場合は、アドレスが最小のものを返す
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        let idx = self.funcs.partition_point(|s| s.name.as_str() < name);
+        self.funcs.get(idx).filter(|s| s.name == name)
+    }
+
+    /// 実行ファイル上のアドレスaddrから始まる関数の名前を返す
+    pub fn name_at(&self, addr: u64) -> Option<&str> {
+        self.funcs
+            .iter()
+            .find(|s| s.addr == addr)
+            .map(|s| s.name.as_str())
+    }
+
+    /// 実行ファイル上のアドレスaddrを含む関数を返す
+    ///
+    /// バイト数が不明な関数は、先頭のアドレスのみを含むとみなす。
+    /// 該当する関数が複数ある場合は、先頭のアドレスが最も大きいものを返す
+    pub fn containing(&self, addr: u64) -> Option<&Symbol> {
+        self.funcs
+            .iter()
+            .filter(|s| s.addr == addr || (s.addr..s.addr + s.size).contains(&addr))
+            .max_by_key(|s| s.addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sym(name: &str, addr: u64) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            addr,
+            size: 0x80,
+        }
+    }
+
+    #[test]
+    fn test_matching() {
+        let table = SymbolTable::new(vec![
+            sym("parse_expr", 0x1200),
+            sym("main", 0x1100),
+            sym("parse_term", 0x1300),
+            sym("parse_expr", 0x1200),
+            sym("_parse_alias", 0x1300),
+        ]);
+
+        let names = |re: &str| -> Vec<String> {
+            let re = Regex::new(re).unwrap();
+            table
+                .matching(&re)
+                .iter()
+                .map(|s| format!("{}@{:#x}", s.name, s.addr))
+                .collect()
+        };
+        assert_eq!(
+            names("^parse_"),
+            vec!["parse_expr@0x1200", "parse_term@0x1300"]
+        );
+        assert_eq!(names("^main$"), vec!["main@0x1100"]);
+
+        // 同じアドレスのシンボルは1つにまとめる
+        assert_eq!(
+            names("parse"),
+            vec!["_parse_alias@0x1300", "parse_expr@0x1200"]
+        );
+        assert!(names("^nosuch").is_empty());
+    }
+
+    #[test]
+    fn test_find() {
+        let table = SymbolTable::new(vec![
+            sym("main", 0x1100),
+            sym("helper", 0x1400),
+            sym("helper", 0x1380),
+            sym("_alias", 0x1100),
+        ]);
+
+        assert_eq!(table.find("main"), Some(&sym("main", 0x1100)));
+        assert_eq!(table.find("helper"), Some(&sym("helper", 0x1380)));
+        assert_eq!(table.find("mai"), None);
+        assert_eq!(table.find("nosuch"), None);
+
+        assert_eq!(table.name_at(0x1400), Some("helper"));
+        assert!(table.name_at(0x1100).is_some());
+        assert_eq!(table.name_at(0x1104), None);
+    }
+
+    #[test]
+    fn test_containing() {
+        let table = SymbolTable::new(vec![
+            sym("main", 0x1100),
+            sym("helper", 0x1180),
+            Symbol {
+                name: "_start".to_string(),
+                addr: 0x1000,
+                size: 0,
+            },
+        ]);
+        let name = |addr| table.containing(addr).map(|s| s.name.as_str());
+
+        assert_eq!(name(0x1100), Some("main"));
+        assert_eq!(name(0x117f), Some("main"));
+        assert_eq!(name(0x1180), Some("helper"));
+        assert_eq!(name(0x1200), None);
+
+        // バイト数が不明な関数は先頭のアドレスのみ
+        assert_eq!(name(0x1000), Some("_start"));
+        assert_eq!(name(0x1004), None);
+    }
+}
@@ -0,0 +1,221 @@
+//! システムコールの捕捉(catch syscall)
+//!
+//! PTRACE_SYSCALLで再開すると、子プロセスはシステムコールの入口と出口で停止する。
+//! PTRACE_O_TRACESYSGOODを設定しておけば、この停止は通常のSIGTRAPと区別できる。
+//! x86-64では、停止した時点のorig_raxがシステムコール番号となる。入口と出口の停止は区別できないため、
+//! 停止するたびに入口か出口かを入れ替えて追跡する。
+//! `catch syscall openat`のように指定したシステムコールの入口でのみ停止し、
+//! 引数を表示する。ファイル名を引数に取るシステムコールは、子プロセスのメモリから文字列を読み込んで表示する。
+
+use std::collections::BTreeSet;
+
+/// 表示するファイル名の最大バイト数
+const MAX_PATH_LEN: usize = 256;
+
+/// x86-64のシステムコール
+pub struct Syscall {
+    pub name: &'static str,
+    pub nr: u64,
+    pub nargs: usize,        // 表示する引数の個数
+    pub path: Option<usize>, // ファイル名を指す引数の位置
+}
+
+/// SYSCALLSの要素を短く書くための関数
+const fn sc(name: &'static str, nr: u64, nargs: usize, path: Option<usize>) -> Syscall {
+    Syscall {
+        name,
+        nr,
+        nargs,
+        path,
+    }
+}
+
+/// 名前で指定できるシステムコールの一覧
+pub const SYSCALLS: &[Syscall] = &[
+    sc("read", 0, 3, None),
+    sc("write", 1, 3, None),
+    sc("open", 2, 3, Some(0)),
+    sc("close", 3, 1, None),
+    sc("stat", 4, 2, Some(0)),
+    sc("fstat", 5, 2, None),
+    sc("lstat", 6, 2, Some(0)),
+    sc("lseek", 8, 3, None),
+    sc("mmap", 9, 6, None),
+    sc("mprotect", 10, 3, None),
+    sc("munmap", 11, 2, None),
+    sc("brk", 12, 1, None),
+    sc("ioctl", 16, 3, None),
+    sc("access", 21, 2, Some(0)),
+    sc("pipe", 22, 1, None),
+    sc("dup", 32, 1, None),
+    sc("dup2", 33, 2, None),
+    sc("nanosleep", 35, 2, None),
+    sc("getpid", 39, 0, None),
+    sc("socket", 41, 3, None),
+    sc("connect", 42, 3, None),
+    sc("clone", 56, 5, None),
+    sc("fork", 57, 0, None),
+    sc("vfork", 58, 0, None),
+    sc("execve", 59, 3, Some(0)),
+    sc("exit", 60, 1, None),
+    sc("kill", 62, 2, None),
+    sc("fcntl", 72, 3, None),
+    sc("getcwd", 79, 2, None),
+    sc("chdir", 80, 1, Some(0)),
+    sc("rename", 82, 2, Some(0)),
+    sc("mkdir", 83, 2, Some(0)),
+    sc("rmdir", 84, 1, Some(0)),
+    sc("creat", 85, 2, Some(0)),
+    sc("unlink", 87, 1, Some(0)),
+    sc("readlink", 89, 3, Some(0)),
+    sc("chmod", 90, 2, Some(0)),
+    sc("exit_group", 231, 1, None),
+    sc("openat", 257, 4, Some(1)),
+    sc("mkdirat", 258, 3, Some(1)),
+    sc("newfstatat", 262, 4, Some(1)),
+    sc("unlinkat", 263, 3, Some(1)),
+    sc("readlinkat", 267, 4, Some(1)),
+    sc("faccessat", 269, 3, Some(1)),
+    sc("pipe2", 293, 2, None),
+    sc("getrandom", 318, 3, None),
+    sc("execveat", 322, 5, Some(1)),
+    sc("statx", 332, 5, Some(1)),
+    sc("faccessat2", 439, 4, Some(1)),
+];
+
+/// 番号がnrのシステムコール
+pub fn by_nr(nr: u64) -> Option<&'static Syscall> {
+    SYSCALLS.iter().find(|s| s.nr == nr)
+}
+
+/// 名前か番号で指定したシステムコールの番号を返す
+pub fn parse(s: &str) -> Result<u64, String> {
+    if let Ok(nr) = s.parse() {
+        return Ok(nr);
+    }
+    SYSCALLS
+        .iter()
+        .find(|sc| sc.name == s)
+        .map(|sc| sc.nr)
+        .ok_or_else(|| format!("不明なシステムコール : {s}"))
+}
+
+/// システムコール番号nrの表示名。一覧にない場合は番号
+pub fn name(nr: u64) -> String {
+    by_nr(nr).map_or_else(|| format!("syscall {nr}"), |s| s.name.to_string())
+}
+
+/// 捕捉するシステムコールの番号の集合
+#[derive(Debug, Default)]
+pub struct Catches {
+    nrs: BTreeSet<u64>,
+}
+
+impl Catches {
+    /// nrを捕捉する。すでに捕捉していれば偽を返す
+    pub fn add(&mut self, nr: u64) -> bool {
+        self.nrs.insert(nr)
+    }
+
+    /// すべての捕捉を解除する
+    pub fn clear(&mut self) {
+        self.nrs.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nrs.is_empty()
+    }
+
+    pub fn contains(&self, nr: u64) -> bool {
+        self.nrs.contains(&nr)
+    }
+
+    /// 捕捉中のシステムコールの番号
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.nrs.iter().copied()
+    }
+}
+
+/// 番号nr、引数argsのシステムコールの呼び出しを`openat(0xffffff9c, "/etc/passwd", 0x0, 0x0)`の形式にする
+///
+/// readはアドレスから8バイトを読み込む関数で、ファイル名の引数の文字列を読み込むのに使う。
+/// 一覧にないシステムコールは6個の引数をすべて表示する
+pub fn format_call(nr: u64, args: &[u64; 6], read: impl Fn(u64) -> Option<u64>) -> String {
+    let (nargs, path) = by_nr(nr).map_or((args.len(), None), |s| (s.nargs, s.path));
+    let args: Vec<String> = args[..nargs]
+        .iter()
+        .enumerate()
+        .map(
+            |(i, arg)| match (path == Some(i)).then(|| read_cstr(&read, *arg)).flatten() {
+                Some(s) => format!("{s:?}"),
+                None => format!("{arg:#x}"),
+            },
+        )
+        .collect();
+    format!("{}({})", name(nr), args.join(", "))
+}
+
+/// addrから終端の0までを文字列として読み込む。MAX_PATH_LENを超える場合は省略して...を付ける
+fn read_cstr(read: impl Fn(u64) -> Option<u64>, addr: u64) -> Option<String> {
+    if addr == 0 {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    for offset in (0..MAX_PATH_LEN as u64).step_by(8) {
+        let word = read(addr.checked_add(offset)?)?.to_le_bytes();
+        match word.iter().position(|b| *b == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&word[..end]);
+                return Some(String::from_utf8_lossy(&bytes).into_owned());
+            }
+            None => bytes.extend_from_slice(&word),
+        }
+    }
+    Some(format!("{}...", String::from_utf8_lossy(&bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("openat"), Ok(257));
+        assert_eq!(parse("59"), Ok(59));
+        assert!(parse("opennat").is_err());
+        assert_eq!(name(257), "openat");
+        assert_eq!(name(1000), "syscall 1000");
+    }
+
+    #[test]
+    fn test_format_call() {
+        // 0x1000に"/etc/passwd"
+        let mem: HashMap<u64, u64> = [
+            (0x1000, u64::from_le_bytes(*b"/etc/pas")),
+            (0x1008, u64::from_le_bytes(*b"swd\0\0\0\0\0")),
+        ]
+        .into_iter()
+        .collect();
+        let read = |addr| mem.get(&addr).copied();
+        assert_eq!(
+            format_call(257, &[0xffffff9c, 0x1000, 0, 0, 7, 7], read),
+            "openat(0xffffff9c, \"/etc/passwd\", 0x0, 0x0)"
+        );
+        // ファイル名を読み込めない場合はアドレスを表示
+        assert_eq!(
+            format_call(2, &[0x2000, 0, 0, 0, 0, 0], read),
+            "open(0x2000, 0x0, 0x0)"
+        );
+        assert_eq!(
+            format_call(1000, &[1, 2, 3, 4, 5, 6], read),
+            "syscall 1000(0x1, 0x2, 0x3, 0x4, 0x5, 0x6)"
+        );
+
+        // 長いファイル名は省略する
+        let long = |_| Some(u64::from_le_bytes(*b"aaaaaaaa"));
+        let s = read_cstr(long, 0x1000).unwrap();
+        assert_eq!(s.len(), MAX_PATH_LEN + 3);
+        assert!(s.ends_with("..."));
+    }
+}
@@ -0,0 +1,229 @@
+//! ptraceとwaitpidの呼び出し
+//!
+//! nixの関数と同じ引数で呼び出せるラッパを提供し、デバッガからの呼び出しはすべてここを通す。
+//! シグナルによる中断(EINTR)は即座に再試行する。また、停止の通知を受け取った直後などに
+//! 子プロセスがまだptraceで操作できる状態になっていない場合はESRCHが返るため、
+//! 間隔を空けて上限回数まで再試行する。
+//! `set debug ptrace on`の場合は、各呼び出しの引数と結果を表示する。
+
+use nix::{
+    errno::Errno,
+    libc::{c_long, user_regs_struct},
+    sys::{
+        ptrace::{self, AddressType},
+        signal::Signal,
+        wait::{self, WaitPidFlag, WaitStatus},
+    },
+    unistd::Pid,
+};
+use std::{
+    ffi::c_void,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// ESRCHを再試行する最大回数
+const MAX_RETRIES: usize = 5;
+
+/// ESRCHを再試行する間隔
+const RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+/// 各呼び出しを表示するなら真
+static DEBUG: AtomicBool = AtomicBool::new(false);
+
+/// 各呼び出しを表示するかを設定
+pub fn set_debug(on: bool) {
+    DEBUG.store(on, Ordering::Relaxed);
+}
+
+/// 各呼び出しを表示するなら真
+pub fn debug() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+/// fを呼び出し、EINTRとESRCHの場合は再試行する
+///
+/// descは呼び出しの説明、showは成功時の値の表示方法で、デバッグ表示が有効な場合のみ使う
+fn retry<T>(
+    desc: impl Fn() -> String,
+    show: impl Fn(&T) -> String,
+    mut f: impl FnMut() -> nix::Result<T>,
+) -> nix::Result<T> {
+    let mut retries = 0;
+    let result = loop {
+        match f() {
+            Err(Errno::EINTR) => (),
+            Err(Errno::ESRCH) if retries < MAX_RETRIES => {
+                retries += 1;
+                thread::sleep(RETRY_INTERVAL);
+            }
+            result => break result,
+        }
+    };
+    if debug() {
+        let res = match &result {
+            Ok(val) => show(val),
+            Err(e) => format!("{e}"),
+        };
+        eprintln!("<<[ptrace] {} -> {res}>>", desc());
+    }
+    result
+}
+
+/// 値を返さない呼び出しの表示
+fn ok(_: &()) -> String {
+    "ok".to_string()
+}
+
+/// ptrace::getregs
+pub fn getregs(pid: Pid) -> nix::Result<user_regs_struct> {
+    retry(
+        || format!("getregs({pid})"),
+        |regs: &user_regs_struct| format!("rip = {:#x}", regs.rip),
+        || ptrace::getregs(pid),
+    )
+}
+
+/// ptrace::setregs
+pub fn setregs(pid: Pid, regs: user_regs_struct) -> nix::Result<()> {
+    retry(
+        || format!("setregs({pid}, rip = {:#x})", regs.rip),
+        ok,
+        || ptrace::setregs(pid, regs),
+    )
+}
+
+/// ptrace::read
+pub fn read(pid: Pid, addr: AddressType) -> nix::Result<c_long> {
+    retry(
+        || format!("read({pid}, {addr:p})"),
+        |val: &c_long| format!("{val:#x}"),
+        || ptrace::read(pid, addr),
+    )
+}
+
+/// ptrace::write
+///
+/// # Safety
+///
+/// ptrace::writeと同じ
+pub unsafe fn write(pid: Pid, addr: AddressType, data: *mut c_void) -> nix::Result<()> {
+    retry(
+        || format!("write({pid}, {addr:p}, {:#x})", data as u64),
+        ok,
+        || ptrace::write(pid, addr, data),
+    )
+}
+
+/// ptrace::read_user
+pub fn read_user(pid: Pid, offset: AddressType) -> nix::Result<c_long> {
+    retry(
+        || format!("read_user({pid}, {offset:p})"),
+        |val: &c_long| format!("{val:#x}"),
+        || ptrace::read_user(pid, offset),
+    )
+}
+
+/// ptrace::write_user
+///
+/// # Safety
+///
+/// ptrace::write_userと同じ
+pub unsafe fn write_user(pid: Pid, offset: AddressType, data: *mut c_void) -> nix::Result<()> {
+    retry(
+        || format!("write_user({pid}, {offset:p}, {:#x})", data as u64),
+        ok,
+        || ptrace::write_user(pid, offset, data),
+    )
+}
+
+/// ptrace::cont
+pub fn cont(pid: Pid, sig: Option<Signal>) -> nix::Result<()> {
+    retry(
+        || format!("cont({pid}, {sig:?})"),
+        ok,
+        || ptrace::cont(pid, sig),
+    )
+}
+
+/// ptrace::syscall
+pub fn syscall(pid: Pid, sig: Option<Signal>) -> nix::Result<()> {
+    retry(
+        || format!("syscall({pid}, {sig:?})"),
+        ok,
+        || ptrace::syscall(pid, sig),
+    )
+}
+
+/// ptrace::setoptions
+pub fn setoptions(pid: Pid, options: ptrace::Options) -> nix::Result<()> {
+    retry(
+        || format!("setoptions({pid}, {options:?})"),
+        ok,
+        || ptrace::setoptions(pid, options),
+    )
+}
+
+/// ptrace::step
+pub fn step(pid: Pid, sig: Option<Signal>) -> nix::Result<()> {
+    retry(
+        || format!("step({pid}, {sig:?})"),
+        ok,
+        || ptrace::step(pid, sig),
+    )
+}
+
+/// ptrace::kill
+pub fn kill(pid: Pid) -> nix::Result<()> {
+    retry(|| format!("kill({pid})"), ok, || ptrace::kill(pid))
+}
+
+/// waitpid
+pub fn waitpid(pid: Pid, options: Option<WaitPidFlag>) -> nix::Result<WaitStatus> {
+    retry(
+        || format!("waitpid({pid})"),
+        |status: &WaitStatus| format!("{status:?}"),
+        || wait::waitpid(pid, options),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_retry() {
+        // EINTRは成功するまで再試行する
+        let calls = Cell::new(0);
+        let result = retry(String::new, ok, || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 10 {
+                Err(Errno::EINTR)
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(calls.get(), 10);
+
+        // ESRCHは上限回数まで再試行する
+        calls.set(0);
+        let result = retry(String::new, ok, || {
+            calls.set(calls.get() + 1);
+            Err(Errno::ESRCH)
+        });
+        assert_eq!(result, Err(Errno::ESRCH));
+        assert_eq!(calls.get(), MAX_RETRIES + 1);
+
+        // それ以外のエラーは再試行しない
+        calls.set(0);
+        let result = retry(String::new, ok, || {
+            calls.set(calls.get() + 1);
+            Err(Errno::EIO)
+        });
+        assert_eq!(result, Err(Errno::EIO));
+        assert_eq!(calls.get(), 1);
+    }
+}
@@ -0,0 +1,652 @@
+//! DWARFの.debug_infoによる引数とローカル変数の位置と型(print)
+//!
+//! 関数(DW_TAG_subprogram)ごとに引数(DW_TAG_formal_parameter)とローカル変数(DW_TAG_variable)を読み込み、
+//! DW_AT_locationの式から変数の位置を、DW_AT_typeから値の表示方法を求める。
+//! 位置の式は、レジスタ、レジスタ+オフセット、フレームベース+オフセット、固定のアドレスのいずれか
+//! 1つの命令から成るもののみに対応する。最適化によってロケーションリストで表される変数は位置を求められない。
+
+use crate::{cfi::Regs, helper::DynError};
+use gimli::{
+    constants, AttributeValue, DebuggingInformationEntry, EndianSlice, Expression, Operation,
+    RunTimeEndian, UnitOffset,
+};
+use object::{Object, ObjectSection};
+use std::{borrow::Cow, fs, ops::Range};
+
+type Reader<'a> = EndianSlice<'a, RunTimeEndian>;
+type Dwarf<'a> = gimli::Dwarf<Reader<'a>>;
+type Unit<'a> = gimli::Unit<Reader<'a>>;
+
+/// 型を辿る深さの上限。自身へのポインタを持つ構造体などで際限なく辿らないようにする
+const MAX_TYPE_DEPTH: usize = 8;
+
+/// 配列で表示する要素数の上限
+const MAX_ARRAY_ELEMS: usize = 16;
+
+/// DW_AT_locationとDW_AT_frame_baseの式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocExpr {
+    Addr(u64),           // 固定のアドレス(実行ファイル上のアドレス)。DW_OP_addr
+    Reg(u16),            // レジスタ。DW_OP_reg
+    RegOffset(u16, i64), // レジスタの値+オフセットのアドレス。DW_OP_breg
+    FrameOffset(i64),    // フレームベース+オフセットのアドレス。DW_OP_fbreg
+    Cfa,                 // CFA。フレームベースに用いる。DW_OP_call_frame_cfa
+}
+
+/// 変数の値がある場所
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Place {
+    Reg(u16), // レジスタ(DWARFのレジスタ番号)
+    Mem(u64), // メモリ(実行時のアドレス)
+}
+
+/// 基本型の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaseKind {
+    Signed,
+    Unsigned,
+    Bool,
+    SignedChar,
+    UnsignedChar,
+    Float,
+}
+
+/// 値の表示に用いる型
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Base(BaseKind, usize),      // 基本型とバイト数
+    Pointer(String),            // ポインタ。参照先を含めた型の名前
+    Struct(usize, Vec<Member>), // 構造体とバイト数
+    Array(Box<Type>, usize),    // 配列の要素の型と要素数
+    Unknown(usize),             // 対応していない型とバイト数
+}
+
+/// 構造体のメンバ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub offset: usize, // 構造体の先頭からのバイト数
+    pub ty: Type,
+}
+
+impl Type {
+    /// 値のバイト数
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Base(_, size) | Type::Struct(size, _) | Type::Unknown(size) => *size,
+            Type::Pointer(_) => 8,
+            Type::Array(elem, count) => elem.size() * count,
+        }
+    }
+}
+
+/// 引数かローカル変数
+#[derive(Debug)]
+pub struct Var {
+    pub name: String,
+    pub ty: Type,
+    pub loc: Option<LocExpr>, // 位置。ロケーションリストなどで求められない場合はNone
+    scope: Vec<Range<u64>>,   // 宣言されたブロックの範囲。関数全体の場合は空
+}
+
+/// 関数と、その引数とローカル変数
+#[derive(Debug)]
+struct Func {
+    ranges: Vec<Range<u64>>,     // 関数の命令の範囲(実行ファイル上のアドレス)
+    frame_base: Option<LocExpr>, // フレームベース
+    vars: Vec<Var>,              // 引数とローカル変数。宣言順
+    ret: Option<Type>,           // 戻り値の型。voidの場合はNone
+}
+
+/// .debug_infoから生成した、関数ごとの変数の一覧
+#[derive(Debug)]
+pub struct VarTable {
+    funcs: Vec<Func>,
+    globals: Vec<Var>, // コンパイル単位の直下で宣言された変数
+}
+
+impl VarTable {
+    /// 実行ファイルを読み込み、変数の一覧を生成
+    pub fn load(filename: &str) -> Result<Self, DynError> {
+        let data = fs::read(filename)?;
+        let obj = object::File::parse(&*data)?;
+        let endian = if obj.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        let load_section = |id: gimli::SectionId| -> Result<Cow<[u8]>, gimli::Error> {
+            Ok(obj
+                .section_by_name(id.name())
+                .and_then(|s| s.uncompressed_data().ok())
+                .unwrap_or(Cow::Borrowed(&[])))
+        };
+        let dwarf_cow = gimli::Dwarf::load(load_section)?;
+        let dwarf = dwarf_cow.borrow(|section| EndianSlice::new(section, endian));
+
+        let mut table = VarTable {
+            funcs: Vec::new(),
+            globals: Vec::new(),
+        };
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let mut tree = unit.entries_tree(None)?;
+            let mut children = tree.root()?.children();
+            while let Some(node) = children.next()? {
+                match node.entry().tag() {
+                    constants::DW_TAG_subprogram => {
+                        let entry = node.entry();
+                        let ranges = die_ranges(&dwarf, &unit, entry)?;
+                        if ranges.is_empty() {
+                            continue; // 宣言のみ
+                        }
+                        let frame_base = entry
+                            .attr_value(constants::DW_AT_frame_base)?
+                            .and_then(|v| v.exprloc_value())
+                            .and_then(|e| parse_loc(&dwarf, &unit, e));
+                        let ret = type_ref(entry).map(|t| parse_type(&dwarf, &unit, t, 0));
+                        let mut vars = Vec::new();
+                        collect_vars(&dwarf, &unit, node, &[], &mut vars)?;
+                        table.funcs.push(Func {
+                            ranges,
+                            frame_base,
+                            vars,
+                            ret,
+                        });
+                    }
+                    constants::DW_TAG_variable => {
+                        if let Some(var) = parse_var(&dwarf, &unit, node.entry(), &[])? {
+                            table.globals.push(var);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(table)
+    }
+
+    /// 実行ファイル上のアドレスpcで参照できる、名前がnameの変数と、それを宣言した関数のフレームベースを返す
+    ///
+    /// 関数内では、最も内側のブロックで宣言されたものを優先する。関数内にない場合はグローバル変数から探す
+    pub fn find(&self, pc: u64, name: &str) -> Option<(&Var, Option<LocExpr>)> {
+        if let Some(func) = self.func(pc) {
+            let var = func
+                .vars
+                .iter()
+                .rev()
+                .filter(|v| v.name == name)
+                .filter(|v| v.scope.is_empty() || v.scope.iter().any(|r| r.contains(&pc)))
+                .min_by_key(|v| match v.scope.is_empty() {
+                    true => u64::MAX,
+                    false => v.scope.iter().map(|r| r.end - r.start).sum(),
+                });
+            if let Some(var) = var {
+                return Some((var, func.frame_base));
+            }
+        }
+        self.globals
+            .iter()
+            .find(|v| v.name == name)
+            .map(|var| (var, None))
+    }
+
+    /// 実行ファイル上のアドレスpcを含む関数の、戻り値の型を返す
+    ///
+    /// 関数が見つからない場合はNone、戻り値がない(void)場合はSome(None)を返す
+    pub fn ret_type(&self, pc: u64) -> Option<Option<&Type>> {
+        self.func(pc).map(|f| f.ret.as_ref())
+    }
+
+    /// 実行ファイル上のアドレスpcを含む関数
+    fn func(&self, pc: u64) -> Option<&Func> {
+        self.funcs
+            .iter()
+            .find(|f| f.ranges.iter().any(|r| r.contains(&pc)))
+    }
+}
+
+/// 変数の位置locから、値がある場所を求める
+///
+/// frame_baseは関数のフレームベース、regsは停止時のレジスタ、cfaは停止位置でのCFA、
+/// biasは実行ファイル上のアドレスと実行時のアドレスの差
+pub fn resolve(
+    loc: LocExpr,
+    frame_base: Option<LocExpr>,
+    regs: &Regs,
+    cfa: Option<u64>,
+    bias: u64,
+) -> Option<Place> {
+    let addr = |e: LocExpr| match e {
+        LocExpr::Addr(addr) => Some(addr + bias),
+        LocExpr::Reg(r) => regs.get(r),
+        LocExpr::RegOffset(r, off) => Some(regs.get(r)?.wrapping_add(off as u64)),
+        LocExpr::Cfa => cfa,
+        LocExpr::FrameOffset(_) => None,
+    };
+    match loc {
+        LocExpr::Reg(r) => Some(Place::Reg(r)),
+        LocExpr::FrameOffset(off) => Some(Place::Mem(addr(frame_base?)?.wrapping_add(off as u64))),
+        loc => addr(loc).map(Place::Mem),
+    }
+}
+
+/// 型tyの値をリトルエンディアンのバイト列bytesから読み取り、文字列にする
+///
+/// バイト列が足りない部分は?と表示する
+pub fn format(ty: &Type, bytes: &[u8]) -> String {
+    // 構造体と配列はメンバや要素ごとにバイト列が足りるか調べる
+    let bytes = match ty {
+        Type::Struct(..) | Type::Array(..) => bytes,
+        _ => match bytes.get(..ty.size()) {
+            Some(bytes) => bytes,
+            None => return "?".to_string(),
+        },
+    };
+    match ty {
+        Type::Base(kind, size) if *size <= 8 => {
+            let mut buf = [0; 8];
+            buf[..*size].copy_from_slice(bytes);
+            let raw = u64::from_le_bytes(buf);
+            let shift = 64 - 8 * *size as u32;
+            let signed = if *size == 0 {
+                0
+            } else {
+                ((raw << shift) as i64) >> shift
+            };
+            match kind {
+                BaseKind::Signed => signed.to_string(),
+                BaseKind::Unsigned => raw.to_string(),
+                BaseKind::Bool => (raw != 0).to_string(),
+                BaseKind::SignedChar => format!("{signed} '{}'", (raw as u8).escape_ascii()),
+                BaseKind::UnsignedChar => format!("{raw} '{}'", (raw as u8).escape_ascii()),
+                BaseKind::Float => match size {
+                    4 => f32::from_bits(raw as u32).to_string(),
+                    8 => f64::from_bits(raw).to_string(),
+                    _ => format!("{raw:#x}"),
+                },
+            }
+        }
+        Type::Pointer(name) => {
+            let addr = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+            format!("({name}) {addr:#x}")
+        }
+        Type::Struct(_, members) => {
+            let members: Vec<String> = members
+                .iter()
+                .map(|m| {
+                    let val = format(&m.ty, bytes.get(m.offset..).unwrap_or_default());
+                    format!("{} = {val}", m.name)
+                })
+                .collect();
+            format!("{{{}}}", members.join(", "))
+        }
+        Type::Array(elem, count) => {
+            let size = elem.size();
+            let mut elems: Vec<String> = (0..*count.min(&MAX_ARRAY_ELEMS))
+                .map(|i| format(elem, bytes.get(i * size..).unwrap_or_default()))
+                .collect();
+            if *count > MAX_ARRAY_ELEMS {
+                elems.push("...".to_string());
+            }
+            format!("{{{}}}", elems.join(", "))
+        }
+        Type::Base(..) | Type::Unknown(_) => bytes
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// DIEの命令の範囲を返す
+fn die_ranges(
+    dwarf: &Dwarf,
+    unit: &Unit,
+    entry: &DebuggingInformationEntry<Reader>,
+) -> Result<Vec<Range<u64>>, gimli::Error> {
+    let mut ranges = Vec::new();
+    let mut iter = dwarf.die_ranges(unit, entry)?;
+    while let Some(r) = iter.next()? {
+        ranges.push(r.begin..r.end);
+    }
+    Ok(ranges)
+}
+
+/// nodeの子のうち引数とローカル変数をvarsに追加する。scopeはnodeが表すブロックの範囲
+///
+/// 入れ子のブロック(DW_TAG_lexical_block)も辿るが、インライン展開された関数は辿らない
+fn collect_vars(
+    dwarf: &Dwarf,
+    unit: &Unit,
+    node: gimli::EntriesTreeNode<Reader>,
+    scope: &[Range<u64>],
+    vars: &mut Vec<Var>,
+) -> Result<(), gimli::Error> {
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        match child.entry().tag() {
+            constants::DW_TAG_formal_parameter | constants::DW_TAG_variable => {
+                if let Some(var) = parse_var(dwarf, unit, child.entry(), scope)? {
+                    vars.push(var);
+                }
+            }
+            constants::DW_TAG_lexical_block => {
+                let ranges = die_ranges(dwarf, unit, child.entry())?;
+                let scope = if ranges.is_empty() {
+                    scope.to_vec()
+                } else {
+                    ranges
+                };
+                collect_vars(dwarf, unit, child, &scope, vars)?;
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// 変数のDIEから変数を生成する。名前がない場合はNone
+fn parse_var(
+    dwarf: &Dwarf,
+    unit: &Unit,
+    entry: &DebuggingInformationEntry<Reader>,
+    scope: &[Range<u64>],
+) -> Result<Option<Var>, gimli::Error> {
+    let Some(name) = attr_name(dwarf, unit, entry) else {
+        return Ok(None);
+    };
+    let ty = match type_ref(entry) {
+        Some(offset) => parse_type(dwarf, unit, offset, 0),
+        None => Type::Unknown(0),
+    };
+    let loc = entry
+        .attr_value(constants::DW_AT_location)?
+        .and_then(|v| v.exprloc_value())
+        .and_then(|e| parse_loc(dwarf, unit, e));
+    Ok(Some(Var {
+        name,
+        ty,
+        loc,
+        scope: scope.to_vec(),
+    }))
+}
+
+/// 1つの命令から成る位置の式を解釈する。対応していない式の場合はNone
+fn parse_loc(dwarf: &Dwarf, unit: &Unit, expr: Expression<Reader>) -> Option<LocExpr> {
+    let mut ops = expr.operations(unit.encoding());
+    let op = ops.next().ok()??;
+    if !matches!(ops.next(), Ok(None)) {
+        return None;
+    }
+    match op {
+        Operation::Address { address } => Some(LocExpr::Addr(address)),
+        Operation::AddressIndex { index } => dwarf.address(unit, index).ok().map(LocExpr::Addr),
+        Operation::Register { register } => Some(LocExpr::Reg(register.0)),
+        Operation::RegisterOffset {
+            register, offset, ..
+        } => Some(LocExpr::RegOffset(register.0, offset)),
+        Operation::FrameOffset { offset } => Some(LocExpr::FrameOffset(offset)),
+        Operation::CallFrameCFA => Some(LocExpr::Cfa),
+        _ => None,
+    }
+}
+
+/// DW_AT_nameの文字列
+fn attr_name(
+    dwarf: &Dwarf,
+    unit: &Unit,
+    entry: &DebuggingInformationEntry<Reader>,
+) -> Option<String> {
+    let name = entry.attr_value(constants::DW_AT_name).ok()??;
+    let name = dwarf.attr_string(unit, name).ok()?;
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// DW_AT_typeが参照する型のDIE
+fn type_ref(entry: &DebuggingInformationEntry<Reader>) -> Option<UnitOffset> {
+    match entry.attr_value(constants::DW_AT_type).ok()?? {
+        AttributeValue::UnitRef(offset) => Some(offset),
+        _ => None,
+    }
+}
+
+/// DW_AT_byte_sizeの値。ない場合は0
+fn byte_size(entry: &DebuggingInformationEntry<Reader>) -> usize {
+    entry
+        .attr_value(constants::DW_AT_byte_size)
+        .ok()
+        .flatten()
+        .and_then(|v| v.udata_value())
+        .unwrap_or(0) as usize
+}
+
+/// offsetにある型のDIEから、値の表示に用いる型を生成する
+///
+/// typedefとconstなどの修飾は取り除き、元の型を返す
+fn parse_type(dwarf: &Dwarf, unit: &Unit, offset: UnitOffset, depth: usize) -> Type {
+    let Ok(entry) = unit.entry(offset) else {
+        return Type::Unknown(0);
+    };
+    if depth > MAX_TYPE_DEPTH {
+        return Type::Unknown(byte_size(&entry));
+    }
+    let size = byte_size(&entry);
+    match entry.tag() {
+        constants::DW_TAG_base_type => {
+            let kind = match entry.attr_value(constants::DW_AT_encoding) {
+                Ok(Some(AttributeValue::Encoding(enc))) => match enc {
+                    constants::DW_ATE_signed => BaseKind::Signed,
+                    constants::DW_ATE_unsigned => BaseKind::Unsigned,
+                    constants::DW_ATE_boolean => BaseKind::Bool,
+                    constants::DW_ATE_signed_char => BaseKind::SignedChar,
+                    constants::DW_ATE_unsigned_char | constants::DW_ATE_UTF => {
+                        BaseKind::UnsignedChar
+                    }
+                    constants::DW_ATE_float => BaseKind::Float,
+                    _ => return Type::Unknown(size),
+                },
+                _ => return Type::Unknown(size),
+            };
+            Type::Base(kind, size)
+        }
+        constants::DW_TAG_enumeration_type => Type::Base(BaseKind::Signed, size),
+        constants::DW_TAG_pointer_type => Type::Pointer(format!(
+            "{} *",
+            type_name(dwarf, unit, type_ref(&entry), depth)
+        )),
+        constants::DW_TAG_typedef
+        | constants::DW_TAG_const_type
+        | constants::DW_TAG_volatile_type => match type_ref(&entry) {
+            Some(offset) => parse_type(dwarf, unit, offset, depth + 1),
+            None => Type::Unknown(0),
+        },
+        constants::DW_TAG_structure_type | constants::DW_TAG_union_type => {
+            let mut members = Vec::new();
+            if let Ok(mut tree) = unit.entries_tree(Some(offset)) {
+                if let Ok(root) = tree.root() {
+                    let mut children = root.children();
+                    while let Ok(Some(child)) = children.next() {
+                        let member = child.entry();
+                        if member.tag() != constants::DW_TAG_member {
+                            continue;
+                        }
+                        let offset = member
+                            .attr_value(constants::DW_AT_data_member_location)
+                            .ok()
+                            .flatten()
+                            .and_then(|v| v.udata_value())
+                            .unwrap_or(0) as usize;
+                        let ty = match type_ref(member) {
+                            Some(t) => parse_type(dwarf, unit, t, depth + 1),
+                            None => Type::Unknown(0),
+                        };
+                        members.push(Member {
+                            name: attr_name(dwarf, unit, member).unwrap_or_default(),
+                            offset,
+                            ty,
+                        });
+                    }
+                }
+            }
+            Type::Struct(size, members)
+        }
+        constants::DW_TAG_array_type => {
+            let elem = match type_ref(&entry) {
+                Some(t) => parse_type(dwarf, unit, t, depth + 1),
+                None => return Type::Unknown(size),
+            };
+            // 多次元配列は、次元ごとに要素数を持つDW_TAG_subrange_typeが並ぶ
+            let mut counts = Vec::new();
+            if let Ok(mut tree) = unit.entries_tree(Some(offset)) {
+                if let Ok(root) = tree.root() {
+                    let mut children = root.children();
+                    while let Ok(Some(child)) = children.next() {
+                        let sub = child.entry();
+                        if sub.tag() != constants::DW_TAG_subrange_type {
+                            continue;
+                        }
+                        let attr = |at| {
+                            sub.attr_value(at)
+                                .ok()
+                                .flatten()
+                                .and_then(|v| v.udata_value())
+                        };
+                        let count = attr(constants::DW_AT_count)
+                            .or_else(|| attr(constants::DW_AT_upper_bound).map(|n| n + 1))
+                            .unwrap_or(0);
+                        counts.push(count as usize);
+                    }
+                }
+            }
+            counts
+                .into_iter()
+                .rev()
+                .fold(elem, |ty, count| Type::Array(Box::new(ty), count))
+        }
+        _ => Type::Unknown(size),
+    }
+}
+
+/// offsetにある型の、Cでの名前。ポインタの参照先の表示に用いる
+fn type_name(dwarf: &Dwarf, unit: &Unit, offset: Option<UnitOffset>, depth: usize) -> String {
+    let Some(entry) = offset.and_then(|offset| unit.entry(offset).ok()) else {
+        return "void".to_string();
+    };
+    if depth > MAX_TYPE_DEPTH {
+        return "...".to_string();
+    }
+    let name = attr_name(dwarf, unit, &entry).unwrap_or_default();
+    let inner = || type_name(dwarf, unit, type_ref(&entry), depth + 1);
+    match entry.tag() {
+        constants::DW_TAG_structure_type => format!("struct {name}"),
+        constants::DW_TAG_union_type => format!("union {name}"),
+        constants::DW_TAG_enumeration_type => format!("enum {name}"),
+        constants::DW_TAG_pointer_type => format!("{} *", inner()),
+        constants::DW_TAG_const_type => format!("const {}", inner()),
+        constants::DW_TAG_volatile_type => format!("volatile {}", inner()),
+        constants::DW_TAG_array_type => format!("{} []", inner()),
+        constants::DW_TAG_subroutine_type => "関数".to_string(),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfi::{RBP, RSP};
+
+    #[test]
+    fn test_resolve() {
+        let mut regs = Regs::default();
+        regs.set(RBP, Some(0x7fd0));
+        regs.set(RSP, Some(0x7fb0));
+        let bias = 0x555555554000;
+
+        // gccはフレームベースをCFAとし、変数をそこからのオフセットで表す
+        let at = |loc, base| resolve(loc, base, &regs, Some(0x7fe0), bias);
+        assert_eq!(
+            at(LocExpr::FrameOffset(-20), Some(LocExpr::Cfa)),
+            Some(Place::Mem(0x7fcc))
+        );
+        assert_eq!(
+            at(LocExpr::FrameOffset(-8), Some(LocExpr::Reg(RBP))),
+            Some(Place::Mem(0x7fc8))
+        );
+        assert_eq!(at(LocExpr::FrameOffset(-8), None), None);
+        assert_eq!(
+            at(LocExpr::RegOffset(RSP, 8), None),
+            Some(Place::Mem(0x7fb8))
+        );
+        assert_eq!(at(LocExpr::Reg(5), None), Some(Place::Reg(5)));
+        assert_eq!(
+            at(LocExpr::Addr(0x4010), None),
+            Some(Place::Mem(0x555555558010))
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let int = Type::Base(BaseKind::Signed, 4);
+        assert_eq!(format(&int, &(-42i32).to_le_bytes()), "-42");
+        assert_eq!(
+            format(&Type::Base(BaseKind::Unsigned, 4), &(-1i32).to_le_bytes()),
+            "4294967295"
+        );
+        assert_eq!(format(&Type::Base(BaseKind::SignedChar, 1), b"A"), "65 'A'");
+        assert_eq!(format(&Type::Base(BaseKind::Bool, 1), &[1]), "true");
+        assert_eq!(
+            format(&Type::Base(BaseKind::Float, 8), &1.5f64.to_le_bytes()),
+            "1.5"
+        );
+        assert_eq!(
+            format(
+                &Type::Pointer("char *".to_string()),
+                &0x402004u64.to_le_bytes()
+            ),
+            "(char *) 0x402004"
+        );
+
+        // struct { int x; struct point *next; int v[2]; }
+        let ty = Type::Struct(
+            24,
+            vec![
+                Member {
+                    name: "x".to_string(),
+                    offset: 0,
+                    ty: int.clone(),
+                },
+                Member {
+                    name: "next".to_string(),
+                    offset: 8,
+                    ty: Type::Pointer("struct point *".to_string()),
+                },
+                Member {
+                    name: "v".to_string(),
+                    offset: 16,
+                    ty: Type::Array(Box::new(int.clone()), 2),
+                },
+            ],
+        );
+        let mut bytes = vec![0; 24];
+        bytes[0] = 3;
+        bytes[8] = 0x10;
+        bytes[16] = 1;
+        bytes[20] = 2;
+        assert_eq!(
+            format(&ty, &bytes),
+            "{x = 3, next = (struct point *) 0x10, v = {1, 2}}"
+        );
+        // バイト列が足りない部分
+        assert_eq!(format(&ty, &bytes[..8]), "{x = 3, next = ?, v = {?, ?}}");
+        assert_eq!(format(&int, &[1, 2]), "?");
+
+        // 要素数が多い配列は省略する
+        let ty = Type::Array(Box::new(Type::Base(BaseKind::Unsigned, 1)), 20);
+        assert_eq!(
+            format(&ty, &[7; 20]),
+            "{7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, ...}"
+        );
+    }
+}
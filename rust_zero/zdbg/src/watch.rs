@@ -0,0 +1,139 @@
+//! メモリ領域の変更検出(watchmem)
+//!
+//! ハードウェアウォッチポイントの代わりに、子プロセスが停止するたびに監視対象の領域を読み込み、
+//! 前回の停止時からのハッシュ値の変化で書き換えを検出する。
+//! 停止した時点でしか検査しないため、どの命令で書き換えられたかはstepiなどで絞り込む必要がある。
+
+use crate::trace;
+use nix::unistd::Pid;
+use std::{
+    collections::hash_map::DefaultHasher,
+    ffi::c_void,
+    hash::{Hash, Hasher},
+};
+
+/// 監視できる領域の最大バイト数
+pub const MAX_WATCH_LEN: usize = 4096;
+
+/// 差分の1行に表示するバイト数
+const DIFF_WIDTH: usize = 16;
+
+/// 監視中のメモリ領域
+pub struct Watch {
+    pub addr: u64, // 先頭アドレス
+    data: Vec<u8>, // 前回の停止時の内容
+    hash: u64,     // dataのハッシュ値
+}
+
+impl Watch {
+    /// addrからlenバイトの領域の監視を開始する。現在の内容を初期値とする
+    pub fn new(pid: Pid, addr: u64, len: usize) -> Result<Self, String> {
+        let data = read_mem(pid, addr, len)?;
+        Ok(Watch {
+            addr,
+            hash: hash(&data),
+            data,
+        })
+    }
+
+    /// 領域のバイト数
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// 領域を読み込み直し、前回から変更されていれば差分を返す
+    ///
+    /// 変更されていた場合は、読み込んだ内容を次回の比較対象とする
+    pub fn check(&mut self, pid: Pid) -> Result<Option<Vec<String>>, String> {
+        let data = read_mem(pid, self.addr, self.data.len())?;
+        let h = hash(&data);
+        if h == self.hash {
+            return Ok(None);
+        }
+        let diff = hex_diff(self.addr, &self.data, &data);
+        self.data = data;
+        self.hash = h;
+        Ok(Some(diff))
+    }
+}
+
+fn hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 子プロセスのaddrからlenバイト読み込む
+///
+/// ptraceは8バイト単位でしか読み込めないため、末尾が8バイトに満たない場合は
+/// 領域の外を読まないように、末尾から8バイト前を読み込んで後半を使う
+pub fn read_mem(pid: Pid, addr: u64, len: usize) -> Result<Vec<u8>, String> {
+    let mut data = Vec::with_capacity(len);
+    while data.len() < len {
+        let off = data.len();
+        let rest = len - off;
+        let (read_addr, skip) = if rest < 8 && len >= 8 {
+            (addr + (len - 8) as u64, 8 - rest)
+        } else {
+            (addr + off as u64, 0)
+        };
+        let word = trace::read(pid, read_addr as *mut c_void)
+            .map_err(|e| format!("{read_addr:#x}の読み込みに失敗 : {e}"))?;
+        let bytes = word.to_le_bytes();
+        data.extend_from_slice(&bytes[skip..(skip + rest).min(8)]);
+    }
+    Ok(data)
+}
+
+/// 変更前の内容oldと変更後の内容newの差分を16進数で表す
+///
+/// 変更のあった16バイトごとの行について、変更前を"-"、変更後を"+"で始まる行で表示する
+fn hex_diff(addr: u64, old: &[u8], new: &[u8]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (i, (o, n)) in old
+        .chunks(DIFF_WIDTH)
+        .zip(new.chunks(DIFF_WIDTH))
+        .enumerate()
+    {
+        if o == n {
+            continue;
+        }
+        let row = addr + (i * DIFF_WIDTH) as u64;
+        lines.push(format!("- {row:#x}: {}", hex(o)));
+        lines.push(format!("+ {row:#x}: {}", hex(n)));
+    }
+    lines
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_diff() {
+        let old: Vec<u8> = (0..40).collect();
+        assert!(hex_diff(0x404010, &old, &old).is_empty());
+
+        // 変更のあった行のみ表示する
+        let mut new = old.clone();
+        new[17] = 0xff;
+        new[39] = 0x2a;
+        assert_eq!(
+            hex_diff(0x404010, &old, &new),
+            vec![
+                "- 0x404020: 10 11 12 13 14 15 16 17 18 19 1a 1b 1c 1d 1e 1f",
+                "+ 0x404020: 10 ff 12 13 14 15 16 17 18 19 1a 1b 1c 1d 1e 1f",
+                "- 0x404030: 20 21 22 23 24 25 26 27",
+                "+ 0x404030: 20 21 22 23 24 25 26 2a",
+            ]
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! 算術式展開 $(( 式 ))
+//!
+//! 式は64ビット符号付き整数で計算し、以下の演算子を優先度の高い順にサポートする。
+//!
+//! - 単項演算子 : `+` `-` `!`
+//! - 乗除算     : `*` `/` `%`
+//! - 加減算     : `+` `-`
+//! - 大小比較   : `<` `<=` `>` `>=`
+//! - 等値比較   : `==` `!=`
+//! - 論理積     : `&&`
+//! - 論理和     : `||`
+//!
+//! 比較演算子と論理演算子は、真なら1、偽なら0となる。
+//! `&&`と`||`は、左辺で結果が決まる場合は右辺を計算しない。右辺の構文は検査するが、0による除算などのエラーにはならない。
+//! 変数は`x`または`$x`と書き、環境変数の値を整数として参照する。未定義の変数は0とする。
+//! `$?`は直前のコマンドの終了コードを参照する
+
+use crate::msg::msg;
+
+/// 変数の値を取得する関数の型。未定義ならNone
+type Lookup<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// line中の$(( 式 ))を、式を計算した結果に置き換える
+///
+/// 変数の値はlookupで取得する
+pub fn expand(line: &str, lookup: Lookup) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("$((") {
+        result.push_str(&rest[..start]);
+        let expr_start = start + 3;
+
+        // 括弧の対応を数えて、閉じ括弧の))を探す
+        let mut depth = 0;
+        let mut end = None;
+        for (i, c) in rest[expr_start..].char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ')' if rest[expr_start + i..].starts_with("))") => {
+                    end = Some(expr_start + i);
+                    break;
+                }
+                ')' => return Err(msg!(ArithUnbalanced)),
+                _ => (),
+            }
+        }
+        let end = end.ok_or_else(|| msg!(ArithNoClose))?;
+
+        let value = eval(&rest[expr_start..end], lookup)?;
+        result.push_str(&value.to_string());
+        rest = &rest[end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// 式exprを計算する
+pub fn eval(expr: &str, lookup: Lookup) -> Result<i64, String> {
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0); // $(())は0
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        lookup,
+        skip: false,
+    };
+    let value = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(value),
+        Some(t) => Err(msg!(ArithBadToken, format!("{t:?}"))),
+    }
+}
+
+/// 字句
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Num(i64),         // 整数
+    Var(String),      // 変数名
+    Op(&'static str), // 演算子
+    LParen,           // (
+    RParen,           // )
+}
+
+/// 2文字の演算子を先に検査するため、長い順に並べる
+const OPS: &[&str] = &[
+    "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!",
+];
+
+/// 式を字句に分割
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let num = rest[..len]
+                .parse()
+                .map_err(|_| msg!(ArithBadNumber, &rest[..len]))?;
+            tokens.push(Token::Num(num));
+            len
+        } else if rest.starts_with("$?") {
+            tokens.push(Token::Var("?".to_string()));
+            2
+        } else if c == '$' || c == '_' || c.is_ascii_alphabetic() {
+            let name = rest.strip_prefix('$').unwrap_or(rest);
+            let len = name
+                .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                .unwrap_or(name.len());
+            if len == 0 {
+                return Err(msg!(ArithNoVarName));
+            }
+            tokens.push(Token::Var(name[..len].to_string()));
+            len + rest.len() - name.len()
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            1
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            1
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            return Err(msg!(ArithBadChar, c));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// 再帰下降構文解析により、構文解析しながら計算する
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    lookup: Lookup<'a>,
+    skip: bool, // 真なら、&&と||の計算しない右辺を解析中。計算のエラーは無視する
+}
+
+impl Parser<'_> {
+    /// 次の字句が演算子opsのいずれかなら読み進めて返す
+    fn eat_op(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    /// 左結合の二項演算子opsを解析し、nextで被演算子を解析する
+    fn binary(
+        &mut self,
+        ops: &[&'static str],
+        next: fn(&mut Self) -> Result<i64, String>,
+    ) -> Result<i64, String> {
+        let mut lhs = next(self)?;
+        while let Some(op) = self.eat_op(ops) {
+            let rhs = next(self)?;
+            lhs = self.value(apply(op, lhs, rhs))?;
+        }
+        Ok(lhs)
+    }
+
+    /// 左結合の論理演算子op(&&か||)を解析し、nextで被演算子を解析する
+    ///
+    /// 左辺で結果が決まる場合は、右辺を解析のみ行い計算しない
+    fn logical(
+        &mut self,
+        op: &'static str,
+        next: fn(&mut Self) -> Result<i64, String>,
+    ) -> Result<i64, String> {
+        let mut lhs = next(self)?;
+        while self.eat_op(&[op]).is_some() {
+            // &&は左辺が偽、||は左辺が真なら結果が決まる
+            let decided = (op == "&&") == (lhs == 0);
+            let skip = self.skip;
+            self.skip = skip || decided;
+            let rhs = next(self);
+            self.skip = skip;
+            let rhs = rhs?;
+            lhs = (if decided { lhs } else { rhs } != 0) as i64;
+        }
+        Ok(lhs)
+    }
+
+    /// 計算結果resultを返す。計算しない右辺の解析中は、エラーを0とする
+    fn value(&self, result: Result<i64, String>) -> Result<i64, String> {
+        match result {
+            Err(_) if self.skip => Ok(0),
+            result => result,
+        }
+    }
+
+    fn or(&mut self) -> Result<i64, String> {
+        self.logical("||", Self::and)
+    }
+
+    fn and(&mut self) -> Result<i64, String> {
+        self.logical("&&", Self::equality)
+    }
+
+    fn equality(&mut self) -> Result<i64, String> {
+        self.binary(&["==", "!="], Self::relational)
+    }
+
+    fn relational(&mut self) -> Result<i64, String> {
+        self.binary(&["<", "<=", ">", ">="], Self::additive)
+    }
+
+    fn additive(&mut self) -> Result<i64, String> {
+        self.binary(&["+", "-"], Self::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<i64, String> {
+        self.binary(&["*", "/", "%"], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        match self.eat_op(&["+", "-", "!"]) {
+            Some("-") => {
+                let value = self.unary()?;
+                self.value(value.checked_neg().ok_or_else(overflow))
+            }
+            Some("!") => Ok((self.unary()? == 0) as i64),
+            Some(_) => self.unary(),
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Num(n)) => Ok(n),
+            Some(Token::Var(name)) => match (self.lookup)(&name) {
+                None => Ok(0),
+                Some(v) if v.trim().is_empty() => Ok(0),
+                Some(v) => self.value(v.trim().parse().map_err(|_| msg!(ArithNotInteger, name, v))),
+            },
+            Some(Token::LParen) => {
+                let value = self.or()?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::RParen) => {
+                        self.pos += 1;
+                        Ok(value)
+                    }
+                    _ => Err(msg!(ArithNoParen)),
+                }
+            }
+            Some(t) => Err(msg!(ArithBadToken, format!("{t:?}"))),
+            None => Err(msg!(ArithUnexpectedEnd)),
+        }
+    }
+}
+
+/// 二項演算子opを適用
+fn apply(op: &str, lhs: i64, rhs: i64) -> Result<i64, String> {
+    let value = match op {
+        "+" => lhs.checked_add(rhs),
+        "-" => lhs.checked_sub(rhs),
+        "*" => lhs.checked_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err(msg!(ArithDivZero)),
+        "/" => lhs.checked_div(rhs),
+        "%" => lhs.checked_rem(rhs),
+        "<" => Some((lhs < rhs) as i64),
+        "<=" => Some((lhs <= rhs) as i64),
+        ">" => Some((lhs > rhs) as i64),
+        ">=" => Some((lhs >= rhs) as i64),
+        "==" => Some((lhs == rhs) as i64),
+        "!=" => Some((lhs != rhs) as i64),
+        _ => unreachable!(),
+    };
+    value.ok_or_else(overflow)
+}
+
+fn overflow() -> String {
+    msg!(ArithOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calc(expr: &str) -> Result<i64, String> {
+        eval(expr, &|name| match name {
+            "i" => Some("41".to_string()),
+            "empty" => Some("".to_string()),
+            "word" => Some("abc".to_string()),
+            "?" => Some("3".to_string()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(calc("1 + 2 * 3"), Ok(7));
+        assert_eq!(calc("(1 + 2) * 3"), Ok(9));
+        assert_eq!(calc("10 - 4 - 3"), Ok(3));
+        assert_eq!(calc("7 / 2 + 7 % 2"), Ok(4));
+        assert_eq!(calc("-7 / 2"), Ok(-3));
+        assert_eq!(calc("- -3 + !0 + !5"), Ok(4));
+        assert_eq!(calc("1 < 2 == 2 >= 3"), Ok(0));
+        assert_eq!(calc("1 <= 1 && 2 != 3 || 0"), Ok(1));
+        assert_eq!(calc(""), Ok(0));
+
+        // &&と||は、左辺で結果が決まる場合に右辺を計算しない
+        assert_eq!(calc("0 && 1 / 0"), Ok(0));
+        assert_eq!(calc("1 || 1 / 0"), Ok(1));
+        assert_eq!(calc("0 && (word || -9223372036854775807 - 2)"), Ok(0));
+        assert_eq!(calc("1 || 0 && 5 % 0"), Ok(1));
+        assert_eq!(calc("2 && 3"), Ok(1));
+        assert_eq!(calc("0 || 4"), Ok(1));
+        assert!(calc("1 && 1 / 0").is_err());
+        assert!(calc("0 || 1 / 0").is_err());
+        // 計算しない右辺も構文は検査する
+        assert!(calc("0 && (1 +").is_err());
+
+        // 変数
+        assert_eq!(calc("i + 1"), Ok(42));
+        assert_eq!(calc("$i*2"), Ok(82));
+        assert_eq!(calc("undefined + empty"), Ok(0));
+        assert_eq!(calc("$? + 1"), Ok(4));
+        assert!(calc("?").is_err());
+        assert!(calc("word").is_err());
+
+        assert!(calc("1 / 0").is_err());
+        assert!(calc("5 % 0").is_err());
+        assert!(calc("9223372036854775807 + 1").is_err());
+        assert!(calc("(1 + 2").is_err());
+        assert!(calc("1 +").is_err());
+        assert!(calc("1 2").is_err());
+        assert!(calc("1 @ 2").is_err());
+        assert!(calc("12abc").is_err());
+    }
+
+    #[test]
+    fn test_expand() {
+        let lookup = |_: &str| None;
+        assert_eq!(
+            expand("echo $((1 + 2)) $(( (3) * (4 - 1) ))x", &lookup),
+            Ok("echo 3 9x".to_string())
+        );
+        assert_eq!(expand("echo $ (1)", &lookup), Ok("echo $ (1)".to_string()));
+        assert!(expand("echo $((1 + 2)", &lookup).is_err());
+        assert!(expand("echo $((1 + 2)))", &lookup).is_ok());
+        assert!(expand("echo $((1 ) + 2))", &lookup).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! rustylineによる行編集の設定
+//!
+//! 編集モード(emacs、vi)と補完の方式は、set -o viやshopt -s menucompleteなどのオプションで変更する。
+//! オプションはworkerスレッドで設定されるため、KeyBindingsを通してmainスレッドのEditorに反映する
+
+use crate::history;
+use rustyline::{
+    completion::{Completer, FilenameCompleter, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    CompletionType, Config, Context, EditMode, Editor, Helper,
+};
+
+/// ZeroShの行編集に用いるEditor
+pub type ShellEditor = Editor<ShellHelper>;
+
+/// Tabキーでファイル名を補完するヘルパ
+pub struct ShellHelper(FilenameCompleter);
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        self.0.complete(line, pos, ctx)
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// 行編集のオプションの値から、rustylineの編集モードと補完の方式を返す
+///
+/// viが真ならviモード、偽ならemacsモードとする。
+/// menu_completeが真ならTabキーを押すたびに候補を順に挿入し、偽なら共通部分まで補完して候補を一覧表示する
+pub fn modes(vi: bool, menu_complete: bool) -> (EditMode, CompletionType) {
+    let edit_mode = if vi { EditMode::Vi } else { EditMode::Emacs };
+    let completion_type = if menu_complete {
+        CompletionType::Circular
+    } else {
+        CompletionType::List
+    };
+    (edit_mode, completion_type)
+}
+
+/// オプションの初期値で設定したEditorを作成する
+pub fn new_editor() -> Result<ShellEditor, ReadlineError> {
+    let (edit_mode, completion_type) = modes(false, false);
+    let config = Config::builder()
+        .max_history_size(history::HISTORY_SIZE)
+        .edit_mode(edit_mode)
+        .completion_type(completion_type)
+        .build();
+    let mut rl = Editor::with_config(config)?;
+    rl.set_helper(Some(ShellHelper(FilenameCompleter::new())));
+    Ok(rl)
+}
@@ -0,0 +1,177 @@
+//! getoptsコマンドによるオプションの解析
+//!
+//! POSIXのgetoptsと同様に、呼び出すたびにオプションを1つ取り出し、
+//! 次に解析する引数の番号をOPTINDとする。
+//! -abのようにまとめて指定したオプションの、引数内の位置はOPTINDでは表せないため、Getoptsに保持する。
+//!
+//! 設定した変数は、`$opt`や`$OPTARG`のように引数の中で参照できる。
+//! ただし、ZeroShには変数への代入がなく、OPTIND=1として解析をやり直すことができない。
+//! そのため、前回と異なる引数が指定された場合は、OPTINDによらず先頭から解析する
+
+/// getoptsで取り出したオプション
+#[derive(Debug, PartialEq, Eq)]
+pub enum Opt {
+    Found(char, Option<String>), // オプション文字列に含まれるオプションと、その引数
+    Invalid(char),               // オプション文字列に含まれないオプション
+    MissingArg(char),            // 引数が必要だが、指定されていないオプション
+    End,                         // オプションの終わり
+}
+
+/// getoptsの呼び出しをまたいで保持する状態
+#[derive(Debug)]
+pub struct Getopts {
+    args: Vec<String>, // 前回の呼び出しで解析した引数
+    optind: usize,     // 前回の呼び出しで返したOPTIND
+    pos: usize,        // 解析中の引数内のバイト位置。0なら引数の先頭から解析する
+}
+
+impl Default for Getopts {
+    fn default() -> Self {
+        Getopts {
+            args: Vec::new(),
+            optind: 1,
+            pos: 0,
+        }
+    }
+}
+
+impl Getopts {
+    /// 引数argsのoptind番目(1から始まる)から次のオプションを取り出し、(オプション, 次のOPTIND)を返す
+    ///
+    /// optstringはPOSIXのgetoptsと同じ形式で、:が続く文字は引数を取るオプションとする。
+    /// 先頭の:はエラーを表示しないことを表すため、ここでは無視する。
+    /// optindが前回返したものと異なる場合は、OPTINDが変更されたとみなしてその引数の先頭から解析する。
+    /// argsが前回と異なる場合は、optindによらず最初の引数から解析する
+    pub fn next(&mut self, optstring: &str, args: &[&str], optind: usize) -> (Opt, usize) {
+        let mut optind = optind.max(1);
+        if self.args != args {
+            self.args = args.iter().map(|s| s.to_string()).collect();
+            optind = 1;
+            self.pos = 0;
+        } else if optind != self.optind {
+            self.pos = 0;
+        }
+        let opt = self.parse(optstring, args, &mut optind);
+        self.optind = optind;
+        (opt, optind)
+    }
+
+    fn parse(&mut self, optstring: &str, args: &[&str], optind: &mut usize) -> Opt {
+        let Some(arg) = args.get(*optind - 1) else {
+            return Opt::End;
+        };
+        if self.pos == 0 {
+            if *arg == "--" {
+                *optind += 1;
+                return Opt::End;
+            }
+            if !arg.starts_with('-') || *arg == "-" {
+                return Opt::End;
+            }
+            self.pos = 1;
+        }
+
+        let c = arg[self.pos..].chars().next().unwrap();
+        self.pos += c.len_utf8();
+        let rest = &arg[self.pos..];
+        if rest.is_empty() {
+            *optind += 1;
+            self.pos = 0;
+        }
+
+        let spec = optstring.strip_prefix(':').unwrap_or(optstring);
+        match spec.find(c).filter(|_| c != ':') {
+            None => Opt::Invalid(c),
+            Some(i) if spec[i + c.len_utf8()..].starts_with(':') => {
+                // -ofileのように続けて指定した場合は残りを、そうでなければ次の引数を取る
+                if !rest.is_empty() {
+                    *optind += 1;
+                    self.pos = 0;
+                    Opt::Found(c, Some(rest.to_string()))
+                } else if let Some(optarg) = args.get(*optind - 1) {
+                    *optind += 1;
+                    Opt::Found(c, Some(optarg.to_string()))
+                } else {
+                    Opt::MissingArg(c)
+                }
+            }
+            Some(_) => Opt::Found(c, None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// argsをすべて解析し、取り出したオプションと最後のOPTINDを返す
+    fn parse_all(optstring: &str, args: &[&str]) -> (Vec<Opt>, usize) {
+        let mut getopts = Getopts::default();
+        let mut optind = 1;
+        let mut opts = Vec::new();
+        loop {
+            let (opt, next) = getopts.next(optstring, args, optind);
+            optind = next;
+            if opt == Opt::End {
+                return (opts, optind);
+            }
+            opts.push(opt);
+        }
+    }
+
+    fn found(c: char, arg: Option<&str>) -> Opt {
+        Opt::Found(c, arg.map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_getopts() {
+        let (opts, optind) = parse_all("abo:", &["-ab", "-o", "out", "-ofile", "rest", "-a"]);
+        assert_eq!(
+            opts,
+            vec![
+                found('a', None),
+                found('b', None),
+                found('o', Some("out")),
+                found('o', Some("file")),
+            ]
+        );
+        assert_eq!(optind, 5);
+
+        // --はオプションの終わりを表し、OPTINDは次の引数を指す
+        let (opts, optind) = parse_all("a", &["-a", "--", "-a"]);
+        assert_eq!(opts, vec![found('a', None)]);
+        assert_eq!(optind, 3);
+
+        // -のみの引数はオプションではない
+        assert_eq!(parse_all("a", &["-", "-a"]), (vec![], 1));
+
+        let (opts, optind) = parse_all(":ao:", &["-xa", "-:", "-o"]);
+        assert_eq!(
+            opts,
+            vec![
+                Opt::Invalid('x'),
+                found('a', None),
+                Opt::Invalid(':'),
+                Opt::MissingArg('o'),
+            ]
+        );
+        assert_eq!(optind, 4);
+    }
+
+    #[test]
+    fn test_reset_optind() {
+        let mut getopts = Getopts::default();
+        let args = ["-ab", "-c"];
+        assert_eq!(getopts.next("abc", &args, 1), (found('a', None), 1));
+        assert_eq!(getopts.next("abc", &args, 1), (found('b', None), 2));
+
+        // OPTINDを戻した場合は、その引数の先頭から解析し直す
+        assert_eq!(getopts.next("abc", &args, 1), (found('a', None), 1));
+        assert_eq!(getopts.next("abc", &args, 1), (found('b', None), 2));
+        assert_eq!(getopts.next("abc", &args, 2), (found('c', None), 3));
+        assert_eq!(getopts.next("abc", &args, 3), (Opt::End, 3));
+
+        // 異なる引数は最初から解析する
+        assert_eq!(getopts.next("abc", &["-c"], 3), (found('c', None), 2));
+    }
+}
@@ -1 +1,45 @@
+use crate::msg::msg;
+use std::{ffi::NulError, fmt};
+
 pub type DynError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// workerスレッドの処理中に発生するエラー
+///
+/// エラーが発生してもシェルは終了させず、エラーを表示して入力を再開する
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShellError {
+    Syscall(&'static str, nix::Error), // システムコールの失敗。(関数名, エラー)
+    NoSuchJob(String),                 // 指定されたジョブが存在しない
+    AmbiguousJob(String),              // ジョブの指定に該当するジョブが複数ある
+    NulByte,                           // 引数にヌル文字が含まれる
+    Channel,                           // スレッド間の通信に失敗
+}
+
+impl ShellError {
+    /// システムコールのエラーをShellErrorに変換する関数を返す
+    ///
+    /// `tcsetpgrp(fd, pgid).map_err(ShellError::syscall("tcsetpgrp"))?`のように利用する
+    pub fn syscall(name: &'static str) -> impl FnOnce(nix::Error) -> Self {
+        move |e| ShellError::Syscall(name, e)
+    }
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShellError::Syscall(name, e) => write!(f, "{}", msg!(SyscallFailed, name, e)),
+            ShellError::NoSuchJob(spec) => write!(f, "{}", msg!(NoSuchJob, spec)),
+            ShellError::AmbiguousJob(spec) => write!(f, "{}", msg!(AmbiguousJob, spec)),
+            ShellError::NulByte => write!(f, "{}", msg!(NulByte)),
+            ShellError::Channel => write!(f, "{}", msg!(Channel)),
+        }
+    }
+}
+
+impl std::error::Error for ShellError {}
+
+impl From<NulError> for ShellError {
+    fn from(_: NulError) -> Self {
+        ShellError::NulByte
+    }
+}
@@ -0,0 +1,532 @@
+//! ヒストリファイルの読み書き
+//!
+//! 複数のZeroShを同時に実行すると、終了時にヒストリファイル全体を上書きしていたため、
+//! 先に終了したシェルのヒストリが失われていた。
+//! そこで、ヒストリファイルとは別のロックファイルでflockによる排他制御を行い、
+//! 保存時には最新のヒストリファイルを読み込んで、このセッションで入力した行を追加してから書き込む。
+//!
+//! rustylineも保存時にヒストリファイル自体をロックするが、
+//! ファイルを切り詰めてからロックするため、読み込みと保存が競合すると内容が失われる。
+//! ロックファイルを用いるのは、rustylineのロックとの競合を避けるため
+//!
+//! また、historyコマンドと!nによる参照のために、番号付きのヒストリをHistoryListとして保持する。
+//! !!:s/old/new/やfc -sで置換した行は、実行する前にpreviewで差分を表示して確認する
+
+use crate::{
+    helper::{DynError, ShellError},
+    msg::msg,
+};
+use nix::fcntl::{flock, FlockArg};
+use rustyline::{error::ReadlineError, history::History, Editor, Helper};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::ErrorKind,
+    os::unix::io::AsRawFd,
+};
+
+/// ヒストリファイルに保存する最大の行数
+pub const HISTORY_SIZE: usize = 1000;
+
+/// mainスレッドとworkerスレッドで共有する、番号付きのヒストリ
+///
+/// mainスレッドはrustylineのヒストリに追加した行を同じ順に追加し、!nなどの展開に用いる。
+/// workerスレッドはhistoryコマンドで表示や消去を行う。
+/// 番号は1から始まり、HISTORY_SIZEを超えて古い行が削除されても変わらない
+#[derive(Debug)]
+pub struct HistoryList {
+    base: usize,               // entriesの先頭の行の番号
+    entries: VecDeque<String>, // 古い順の行
+    cleared: bool,             // history -cで消去されたら真
+    confirm: bool,             // 真なら置換した行を実行する前に確認する(shopt histconfirm)
+}
+
+impl Default for HistoryList {
+    fn default() -> Self {
+        HistoryList {
+            base: 1,
+            entries: VecDeque::new(),
+            cleared: false,
+            confirm: true,
+        }
+    }
+}
+
+impl HistoryList {
+    /// 行linesを古い順に追加したヒストリを作成する
+    pub fn new<'a>(lines: impl Iterator<Item = &'a String>) -> Self {
+        let mut list = HistoryList::default();
+        for line in lines {
+            list.push(line.clone());
+        }
+        list
+    }
+
+    /// 行を追加する。HISTORY_SIZEを超えた場合は最も古い行を削除する
+    pub fn push(&mut self, line: String) {
+        if self.entries.len() == HISTORY_SIZE {
+            self.entries.pop_front();
+            self.base += 1;
+        }
+        self.entries.push_back(line);
+    }
+
+    /// すべての行を削除し、番号を1から振り直す
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.base = 1;
+        self.cleared = true;
+    }
+
+    /// clearが呼ばれていれば真を返し、その記録を消す
+    ///
+    /// mainスレッドはこれを検査して、rustylineのヒストリも消去する
+    pub fn take_cleared(&mut self) -> bool {
+        std::mem::take(&mut self.cleared)
+    }
+
+    /// 置換した行を実行する前に確認するかを設定する
+    pub fn set_confirm(&mut self, confirm: bool) {
+        self.confirm = confirm;
+    }
+
+    /// 置換した行を実行する前に確認するなら真
+    pub fn confirm(&self) -> bool {
+        self.confirm
+    }
+
+    /// 最後のn行を、(番号, 行)として古い順に返す
+    pub fn last(&self, n: usize) -> impl Iterator<Item = (usize, &str)> {
+        let skip = self.entries.len().saturating_sub(n);
+        self.entries
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .map(|(i, line)| (self.base + i, line.as_str()))
+    }
+
+    /// line中の!で始まるヒストリの参照を展開し、展開した場合はその行を返す
+    ///
+    /// - !!      : 直前の行
+    /// - !n      : 番号nの行
+    /// - !-n     : n行前の行
+    /// - !prefix : prefixで始まる最も新しい行
+    ///
+    /// 参照の後に:s/old/new/を続けると最初のoldを、:gs/old/new/ではすべてのoldをnewに置換する。
+    /// 区切りの/は他の文字でもよく、行末では最後の区切りを省略できる。
+    /// 行頭の^old^new^は!!:s^old^new^と同じ
+    ///
+    /// $((a != b))などと区別するため、!の後が空白、=、(、または行末の場合は展開しない
+    pub fn expand(&self, line: &str) -> Result<Option<Expansion>, String> {
+        let quick;
+        let line = match line.strip_prefix('^') {
+            Some(rest) => {
+                quick = format!("!!:s^{rest}");
+                &quick
+            }
+            None => line,
+        };
+
+        let mut result = String::new();
+        let mut plain = String::new(); // 置換しなかった場合の行
+        let mut rest = line;
+        let mut expanded = false;
+        let mut substituted = false;
+        while let Some(i) = rest.find('!') {
+            result.push_str(&rest[..i]);
+            plain.push_str(&rest[..i]);
+            let after = &rest[i + 1..];
+            let len = if after.starts_with('!') {
+                1
+            } else {
+                after
+                    .find(|c: char| c.is_whitespace() || "=()|&<>;:".contains(c))
+                    .unwrap_or(after.len())
+            };
+            if len == 0 {
+                result.push('!');
+                plain.push('!');
+                rest = after;
+                continue;
+            }
+
+            let word = &after[..len];
+            let Some(found) = self.find(word) else {
+                return Err(msg!(EventNotFound, word));
+            };
+            let mut text = found.clone();
+            rest = &after[len..];
+            while let Some((global, spec)) = rest
+                .strip_prefix(":s")
+                .map(|spec| (false, spec))
+                .or_else(|| rest.strip_prefix(":gs").map(|spec| (true, spec)))
+            {
+                let Some((old, new, remain)) = parse_subst(spec) else {
+                    return Err(msg!(BadSubst, &rest[1..]));
+                };
+                text = substitute(&text, old, new, global)
+                    .ok_or_else(|| msg!(SubstFailed, &rest[1..rest.len() - remain.len()]))?;
+                substituted = true;
+                rest = remain;
+            }
+            result.push_str(&text);
+            plain.push_str(found);
+            expanded = true;
+        }
+        result.push_str(rest);
+        plain.push_str(rest);
+        Ok(expanded.then(|| Expansion {
+            line: result,
+            before: substituted.then_some(plain),
+        }))
+    }
+
+    /// fc -s [old=new] [event] を実行し、再実行する行を返す
+    ///
+    /// eventは!の後と同じく番号、負の番号、接頭辞で指定し、省略した場合は直前の行とする。
+    /// old=newを指定した場合は、すべてのoldをnewに置換する
+    pub fn fc(&self, args: &[&str]) -> Result<Expansion, String> {
+        let ["-s", rest @ ..] = args else {
+            return Err(msg!(UsageFc));
+        };
+        let (subst, event) = match rest {
+            [s, event @ ..] if s.contains('=') => (s.split_once('='), event),
+            _ => (None, rest),
+        };
+        let found = match event {
+            [] => self.entries.back(),
+            [word] => self.find(word),
+            _ => return Err(msg!(UsageFc)),
+        };
+        let Some(found) = found else {
+            return Err(msg!(FcEventNotFound, event.first().unwrap_or(&"")));
+        };
+        match subst {
+            Some((old, new)) if !old.is_empty() && found.contains(old) => Ok(Expansion {
+                line: found.replace(old, new),
+                before: Some(found.clone()),
+            }),
+            _ => Ok(Expansion {
+                line: found.clone(),
+                before: None,
+            }),
+        }
+    }
+
+    /// 番号、負の番号、接頭辞、または!(直前の行)で指定した行を探す
+    fn find(&self, word: &str) -> Option<&String> {
+        match word {
+            "!" => self.entries.back(),
+            _ => match word.parse::<isize>() {
+                Ok(n) if n < 0 => self
+                    .entries
+                    .len()
+                    .checked_sub(n.unsigned_abs())
+                    .and_then(|i| self.entries.get(i)),
+                Ok(n) => (n as usize)
+                    .checked_sub(self.base)
+                    .and_then(|i| self.entries.get(i)),
+                Err(_) => self.entries.iter().rev().find(|e| e.starts_with(word)),
+            },
+        }
+    }
+}
+
+/// ヒストリの参照を展開した結果
+#[derive(Debug, PartialEq, Eq)]
+pub struct Expansion {
+    pub line: String,           // 展開した行
+    pub before: Option<String>, // 置換によって行が変わった場合の、置換する前の行
+}
+
+/// :sの後のd/old/new/d(dは区切りの文字)を(old, new, 残り)に分ける
+///
+/// 残りが行末の場合は、最後の区切りを省略できる
+fn parse_subst(spec: &str) -> Option<(&str, &str, &str)> {
+    let d = spec
+        .chars()
+        .next()
+        .filter(|c| !c.is_alphanumeric() && !c.is_whitespace())?;
+    let spec = &spec[d.len_utf8()..];
+    let (old, spec) = spec.split_once(d)?;
+    let (new, remain) = spec.split_once(d).unwrap_or((spec, ""));
+    (!old.is_empty()).then_some((old, new, remain))
+}
+
+/// textのoldをnewに置換する。globalが偽なら最初の1つのみを置換し、oldがなければNoneを返す
+fn substitute(text: &str, old: &str, new: &str, global: bool) -> Option<String> {
+    if !text.contains(old) {
+        return None;
+    }
+    Some(if global {
+        text.replace(old, new)
+    } else {
+        text.replacen(old, new, 1)
+    })
+}
+
+/// 置換する前の行beforeと後の行afterを、差分の形式で2行に整形する
+///
+/// 単語単位で比較し、colorが真なら削除した単語を赤、追加した単語を緑で強調する
+pub fn preview(before: &str, after: &str, color: bool) -> String {
+    let old: Vec<&str> = before.split_whitespace().collect();
+    let new: Vec<&str> = after.split_whitespace().collect();
+
+    // 最長共通部分列の長さの表。lcs[i][j]はold[i..]とnew[j..]の最長共通部分列の長さ
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    // 共通部分列に含まれない単語を強調する
+    let (mut old_kept, mut new_kept) = (vec![false; old.len()], vec![false; new.len()]);
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            old_kept[i] = true;
+            new_kept[j] = true;
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    let render = |sign: &str, words: &[&str], kept: &[bool], code: &str| {
+        let words: Vec<String> = words
+            .iter()
+            .zip(kept)
+            .map(|(w, k)| {
+                if color && !k {
+                    format!("\x1b[1;{code}m{w}\x1b[0m")
+                } else {
+                    w.to_string()
+                }
+            })
+            .collect();
+        if color {
+            format!("\x1b[{code}m{sign}\x1b[0m {}", words.join(" "))
+        } else {
+            format!("{sign} {}", words.join(" "))
+        }
+    };
+    format!(
+        "{}\n{}",
+        render("-", &old, &old_kept, "31"),
+        render("+", &new, &new_kept, "32")
+    )
+}
+
+/// ヒストリファイルpathをロックした状態でrlに読み込む
+///
+/// ヒストリファイルが存在しない場合は何もしない
+pub fn load<H: Helper>(rl: &mut Editor<H>, path: &str) -> Result<(), DynError> {
+    let _lock = lock(path, FlockArg::LockShared)?;
+    match rl.load_history(path) {
+        Err(ReadlineError::Io(e)) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// このセッションで入力した行entriesを、ヒストリファイルpathに追加して保存する
+///
+/// ロックした状態で最新のヒストリファイルを読み込み、mergeで追加してから書き込む
+pub fn save(path: &str, entries: &[String]) -> Result<(), DynError> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let _lock = lock(path, FlockArg::LockExclusive)?;
+
+    let mut current = History::new();
+    current.set_max_len(HISTORY_SIZE);
+    match current.load(path) {
+        Err(ReadlineError::Io(e)) if e.kind() == ErrorKind::NotFound => (),
+        result => result?,
+    }
+
+    let mut merged = History::new();
+    merged.set_max_len(HISTORY_SIZE);
+    for entry in merge(current.iter(), entries, HISTORY_SIZE) {
+        merged.add(entry);
+    }
+    merged.save(path)?;
+    Ok(())
+}
+
+/// ヒストリファイルpathのロックファイルをopenしてロックし、そのファイルを返す
+///
+/// 返したファイルをドロップするとクローズされ、ロックも解除される
+fn lock(path: &str, arg: FlockArg) -> Result<File, DynError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(format!("{path}.lock"))?;
+    flock(file.as_raw_fd(), arg).map_err(ShellError::syscall("flock"))?;
+    Ok(file)
+}
+
+/// ヒストリファイルの行oldの後に、このセッションで入力した行newを追加した行を返す
+///
+/// 同じ内容の行は最後のもののみを残す。
+/// 行数がmaxを超えた場合は、古いものから削除する
+fn merge<'a>(old: impl Iterator<Item = &'a String>, new: &'a [String], max: usize) -> Vec<&'a str> {
+    let all: Vec<&str> = old.chain(new).map(|s| s.as_str()).collect();
+    let mut result: Vec<&str> = all
+        .iter()
+        .enumerate()
+        .filter(|(i, s)| !all[i + 1..].contains(s))
+        .map(|(_, s)| *s)
+        .collect();
+    let excess = result.len().saturating_sub(max);
+    result.drain(..excess);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// 展開した行のみを返す
+    fn expand(list: &HistoryList, line: &str) -> Result<Option<String>, String> {
+        list.expand(line).map(|e| e.map(|e| e.line))
+    }
+
+    #[test]
+    fn test_history_list() {
+        let lines = strings(&["ls -l", "echo a", "cd /tmp"]);
+        let mut list = HistoryList::new(lines.iter());
+        assert_eq!(
+            list.last(2).collect::<Vec<_>>(),
+            vec![(2, "echo a"), (3, "cd /tmp")]
+        );
+
+        assert_eq!(expand(&list, "echo b"), Ok(None));
+        assert_eq!(expand(&list, "!!"), Ok(Some("cd /tmp".to_string())));
+        assert_eq!(expand(&list, "!1 | wc"), Ok(Some("ls -l | wc".to_string())));
+        assert_eq!(expand(&list, "!-2"), Ok(Some("echo a".to_string())));
+        assert_eq!(expand(&list, "!ec x"), Ok(Some("echo a x".to_string())));
+        assert_eq!(expand(&list, "echo $((1 != 2)) !"), Ok(None));
+        assert!(expand(&list, "!4").is_err());
+        assert!(expand(&list, "!0").is_err());
+        assert!(expand(&list, "!-4").is_err());
+        assert!(expand(&list, "!nosuch").is_err());
+
+        // 古い行が削除されても番号は変わらない
+        for i in 0..HISTORY_SIZE {
+            list.push(format!("echo {i}"));
+        }
+        assert_eq!(list.last(1).next(), Some((HISTORY_SIZE + 3, "echo 999")));
+        assert!(expand(&list, "!3").is_err());
+        assert_eq!(expand(&list, "!4"), Ok(Some("echo 0".to_string())));
+
+        list.clear();
+        assert!(list.take_cleared());
+        assert!(!list.take_cleared());
+        list.push("pwd".to_string());
+        assert_eq!(list.last(10).collect::<Vec<_>>(), vec![(1, "pwd")]);
+    }
+
+    #[test]
+    fn test_substitute() {
+        let lines = strings(&["rm -rf build", "echo foo foo"]);
+        let list = HistoryList::new(lines.iter());
+        let subst = |before: &str, line: &str| {
+            Ok(Some(Expansion {
+                line: line.to_string(),
+                before: Some(before.to_string()),
+            }))
+        };
+
+        assert_eq!(
+            list.expand("!!:s/foo/bar/"),
+            subst("echo foo foo", "echo bar foo")
+        );
+        assert_eq!(
+            list.expand("!!:gs/foo/bar/ | wc"),
+            subst("echo foo foo | wc", "echo bar bar | wc")
+        );
+        assert_eq!(
+            list.expand("!rm:s|build|dist"),
+            subst("rm -rf build", "rm -rf dist")
+        );
+        assert_eq!(
+            list.expand("^foo^x^ y"),
+            subst("echo foo foo y", "echo x foo y")
+        );
+        // 置換しない参照では、置換する前の行はない
+        assert_eq!(
+            list.expand("!-2"),
+            Ok(Some(Expansion {
+                line: "rm -rf build".to_string(),
+                before: None
+            }))
+        );
+        assert!(list.expand("!!:s/nosuch/x/").is_err());
+        assert!(list.expand("!!:s//x/").is_err());
+        assert!(list.expand("!!:s").is_err());
+
+        assert_eq!(
+            list.fc(&["-s"]),
+            Ok(Expansion {
+                line: "echo foo foo".to_string(),
+                before: None
+            })
+        );
+        assert_eq!(
+            list.fc(&["-s", "build=dist", "rm"]),
+            Ok(Expansion {
+                line: "rm -rf dist".to_string(),
+                before: Some("rm -rf build".to_string())
+            })
+        );
+        assert_eq!(
+            list.fc(&["-s", "foo=bar", "-1"]).map(|e| e.line),
+            Ok("echo bar bar".to_string())
+        );
+        assert!(list.fc(&["-s", "nosuch"]).is_err());
+        assert!(list.fc(&[]).is_err());
+    }
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(
+            preview("rm -rf build", "rm -rf dist", false),
+            "- rm -rf build\n+ rm -rf dist"
+        );
+        assert_eq!(
+            preview("rm -rf build", "rm -rf dist", true),
+            "\x1b[31m-\x1b[0m rm -rf \x1b[1;31mbuild\x1b[0m\n\x1b[32m+\x1b[0m rm -rf \x1b[1;32mdist\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let old = strings(&["ls", "cd /tmp", "echo a"]);
+        let new = strings(&["pwd", "ls"]);
+        assert_eq!(
+            merge(old.iter(), &new, 10),
+            vec!["cd /tmp", "echo a", "pwd", "ls"]
+        );
+
+        // 古いものから削除する
+        assert_eq!(merge(old.iter(), &new, 3), vec!["echo a", "pwd", "ls"]);
+
+        // このセッション内の重複も取り除く
+        let new = strings(&["pwd", "ls", "pwd"]);
+        assert_eq!(
+            merge(old.iter(), &new, 10),
+            vec!["cd /tmp", "echo a", "ls", "pwd"]
+        );
+    }
+}
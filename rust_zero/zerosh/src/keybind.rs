@@ -0,0 +1,309 @@
+//! bindコマンドによるキー割り当て
+//!
+//! 行の編集はmainスレッドのrustylineが行い、bindコマンドはworkerスレッドで実行されるため、
+//! workerスレッドは割り当ての変更をKeyBindingsに記録するだけにする。
+//! mainスレッドはコマンドの実行が終わるたびに記録された変更を取り出し、rustylineに反映する。
+//!
+//! キーはreadlineと同様に以下のように書く。複数のキーを続けて書くと、その順に押すキー列となる。
+//!
+//! - `\C-x` : Ctrl+x
+//! - `\M-x` : Alt+x(Escの後にx)
+//! - `\e`   : Esc
+//! - `\\`   : \
+//!
+//! ZeroShはクォートをサポートしていないため、空白を含むキー列は指定できない
+
+use crate::{
+    history::HistoryList,
+    msg::{msg, MsgId},
+};
+use rustyline::{
+    config::Configurer, At, Cmd, CompletionType, ConditionalEventHandler, EditMode, Event,
+    EventContext, EventHandler, KeyCode, KeyEvent, Modifiers, Movement, RepeatCount, Word,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// 割り当てられる機能の名前と説明
+pub const FUNCTIONS: &[(&str, MsgId)] = &[
+    ("insert-last-argument", MsgId::FnInsertLastArgument),
+    ("beginning-of-line", MsgId::FnBeginningOfLine),
+    ("end-of-line", MsgId::FnEndOfLine),
+    ("backward-word", MsgId::FnBackwardWord),
+    ("forward-word", MsgId::FnForwardWord),
+    ("kill-line", MsgId::FnKillLine),
+    ("unix-line-discard", MsgId::FnUnixLineDiscard),
+    ("kill-word", MsgId::FnKillWord),
+    ("backward-kill-word", MsgId::FnBackwardKillWord),
+    ("transpose-chars", MsgId::FnTransposeChars),
+    ("undo", MsgId::FnUndo),
+    ("clear-screen", MsgId::FnClearScreen),
+    ("reverse-search-history", MsgId::FnReverseSearchHistory),
+    ("history-search-backward", MsgId::FnHistorySearchBackward),
+    ("history-search-forward", MsgId::FnHistorySearchForward),
+    ("accept-line", MsgId::FnAcceptLine),
+];
+
+/// キーに割り当てる動作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Function(&'static str), // FUNCTIONSのいずれかの機能
+    Macro(String),          // 文字列を挿入
+}
+
+impl Action {
+    /// 機能の名前nameからActionを作成する。不明な名前ならNone
+    pub fn function(name: &str) -> Option<Self> {
+        FUNCTIONS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(n, _)| Action::Function(n))
+    }
+
+    /// rustylineに割り当てるハンドラを作成する
+    ///
+    /// historyはinsert-last-argumentで直前の行を参照するためのヒストリ
+    fn handler(&self, history: &Arc<Mutex<HistoryList>>) -> EventHandler {
+        let cmd = match self {
+            Action::Macro(text) => Cmd::Insert(1, text.clone()),
+            Action::Function("insert-last-argument") => {
+                return EventHandler::Conditional(Box::new(LastArgument(Arc::clone(history))))
+            }
+            Action::Function("beginning-of-line") => Cmd::Move(Movement::BeginningOfLine),
+            Action::Function("end-of-line") => Cmd::Move(Movement::EndOfLine),
+            Action::Function("backward-word") => Cmd::Move(Movement::BackwardWord(1, Word::Emacs)),
+            Action::Function("forward-word") => {
+                Cmd::Move(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs))
+            }
+            Action::Function("kill-line") => Cmd::Kill(Movement::EndOfLine),
+            Action::Function("unix-line-discard") => Cmd::Kill(Movement::BeginningOfLine),
+            Action::Function("kill-word") => {
+                Cmd::Kill(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs))
+            }
+            Action::Function("backward-kill-word") => {
+                Cmd::Kill(Movement::BackwardWord(1, Word::Emacs))
+            }
+            Action::Function("transpose-chars") => Cmd::TransposeChars,
+            Action::Function("undo") => Cmd::Undo(1),
+            Action::Function("clear-screen") => Cmd::ClearScreen,
+            Action::Function("reverse-search-history") => Cmd::ReverseSearchHistory,
+            Action::Function("history-search-backward") => Cmd::HistorySearchBackward,
+            Action::Function("history-search-forward") => Cmd::HistorySearchForward,
+            Action::Function("accept-line") => Cmd::AcceptLine,
+            Action::Function(name) => unreachable!("{name}"),
+        };
+        EventHandler::Simple(cmd)
+    }
+}
+
+/// キー列keysに動作actionを割り当てるbindコマンドの行を返す
+///
+/// bindコマンドの一覧の表示と、rcファイルへの書き込みに用いる
+pub fn bind_command(keys: &str, action: &Action) -> String {
+    match action {
+        Action::Function(name) => format!("bind {keys} {name}"),
+        Action::Macro(text) => format!("bind -s {keys} {text}"),
+    }
+}
+
+/// 直前の行の最後の引数を挿入するハンドラ
+struct LastArgument(Arc<Mutex<HistoryList>>);
+
+impl ConditionalEventHandler for LastArgument {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, _: &EventContext) -> Option<Cmd> {
+        let history = self.0.lock().unwrap();
+        let (_, line) = history.last(1).next()?;
+        let arg = line.split_whitespace().last()?;
+        Some(Cmd::Insert(1, arg.to_string()))
+    }
+}
+
+/// mainスレッドとworkerスレッドで共有する、bindコマンドによるキー割り当てと行編集のモード
+#[derive(Debug, Default)]
+pub struct KeyBindings {
+    bindings: BTreeMap<String, Action>,     // キー列と割り当てた動作
+    pending: Vec<(String, Option<Action>)>, // rustylineに未反映の変更。Noneなら割り当ての解除
+    modes: Option<(EditMode, CompletionType)>, // rustylineに未反映の編集モードと補完の方式
+}
+
+impl KeyBindings {
+    /// キー列keysに動作actionを割り当てる
+    ///
+    /// keysは正規化した表記を返す。keysが不正ならエラー
+    pub fn bind(&mut self, keys: &str, action: Action) -> Result<String, String> {
+        let keys = format_keys(&parse_keys(keys)?);
+        self.bindings.insert(keys.clone(), action.clone());
+        self.pending.push((keys.clone(), Some(action)));
+        Ok(keys)
+    }
+
+    /// キー列keysの割り当てを解除する
+    ///
+    /// keysは正規化した表記を返す。keysが不正か、割り当てられていなければエラー
+    pub fn unbind(&mut self, keys: &str) -> Result<String, String> {
+        let keys = format_keys(&parse_keys(keys)?);
+        if self.bindings.remove(&keys).is_none() {
+            return Err(msg!(KeyUnbound, keys));
+        }
+        self.pending.push((keys.clone(), None));
+        Ok(keys)
+    }
+
+    /// 割り当てをキー列の順に返す
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Action)> {
+        self.bindings.iter()
+    }
+
+    /// 編集モードと補完の方式を設定する
+    pub fn set_modes(&mut self, modes: (EditMode, CompletionType)) {
+        self.modes = Some(modes);
+    }
+
+    /// 未反映の変更をrustylineのEditorに反映する
+    ///
+    /// historyはinsert-last-argumentで参照するヒストリ
+    pub fn apply<H: rustyline::Helper>(
+        &mut self,
+        rl: &mut rustyline::Editor<H>,
+        history: &Arc<Mutex<HistoryList>>,
+    ) {
+        for (keys, action) in self.pending.drain(..) {
+            // bindやunbindで検査済みのため、解析に失敗することはない
+            let event = Event::KeySeq(parse_keys(&keys).unwrap());
+            match action {
+                Some(action) => rl.bind_sequence(event, action.handler(history)),
+                None => rl.unbind_sequence(event),
+            };
+        }
+        if let Some((edit_mode, completion_type)) = self.modes.take() {
+            rl.set_edit_mode(edit_mode);
+            rl.set_completion_type(completion_type);
+        }
+    }
+}
+
+/// キー列の表記sを解析する
+fn parse_keys(s: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut keys = Vec::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let mut mods = Modifiers::NONE;
+        loop {
+            if let Some(r) = rest.strip_prefix("\\C-") {
+                mods |= Modifiers::CTRL;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("\\M-") {
+                mods |= Modifiers::ALT;
+                rest = r;
+            } else {
+                break;
+            }
+        }
+
+        let (key, len) = if let Some(r) = rest.strip_prefix('\\') {
+            match r.chars().next() {
+                Some('e') if mods == Modifiers::NONE => (KeyEvent(KeyCode::Esc, mods), 2),
+                Some('\\') => (KeyEvent::new('\\', mods), 2),
+                _ => return Err(msg!(BadKeyNotation, s)),
+            }
+        } else {
+            match rest.chars().next() {
+                Some(c) if !c.is_control() => (KeyEvent::new(c, mods), c.len_utf8()),
+                _ => return Err(msg!(BadKeyNotation, s)),
+            }
+        };
+        keys.push(KeyEvent::normalize(key));
+        rest = &rest[len..];
+    }
+
+    if keys.is_empty() {
+        return Err(msg!(NoKey));
+    }
+    Ok(keys)
+}
+
+/// キー列keysをparse_keysで解析できる表記にする
+fn format_keys(keys: &[KeyEvent]) -> String {
+    let mut s = String::new();
+    for KeyEvent(code, mods) in keys {
+        if mods.contains(Modifiers::CTRL) {
+            s.push_str("\\C-");
+        }
+        if mods.contains(Modifiers::ALT) {
+            s.push_str("\\M-");
+        }
+        match code {
+            KeyCode::Esc => s.push_str("\\e"),
+            KeyCode::Char('\\') => s.push_str("\\\\"),
+            KeyCode::Char(c) if mods.contains(Modifiers::CTRL) => s.push(c.to_ascii_lowercase()),
+            KeyCode::Char(c) => s.push(*c),
+            _ => unreachable!("{code:?}"),
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys("\\C-t"), Ok(vec![KeyEvent::ctrl('T')]));
+        assert_eq!(parse_keys("\\M-."), Ok(vec![KeyEvent::alt('.')]));
+        assert_eq!(
+            parse_keys("\\C-xa"),
+            Ok(vec![KeyEvent::ctrl('X'), KeyEvent::from('a')])
+        );
+        assert_eq!(
+            parse_keys("\\e\\\\"),
+            Ok(vec![
+                KeyEvent(KeyCode::Esc, Modifiers::NONE),
+                KeyEvent::from('\\')
+            ])
+        );
+        assert!(parse_keys("").is_err());
+        assert!(parse_keys("\\C-").is_err());
+        assert!(parse_keys("\\x").is_err());
+
+        // 正規化した表記は解析し直しても同じになる
+        for s in ["\\C-t", "\\C-T", "\\M-.", "\\C-\\M-x", "\\C-xa", "\\e\\\\"] {
+            let keys = parse_keys(s).unwrap();
+            assert_eq!(parse_keys(&format_keys(&keys)), Ok(keys));
+        }
+        assert_eq!(format_keys(&parse_keys("\\C-T").unwrap()), "\\C-t");
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let mut bindings = KeyBindings::default();
+        let action = Action::function("insert-last-argument").unwrap();
+        assert_eq!(
+            bindings.bind("\\C-T", action.clone()),
+            Ok("\\C-t".to_string())
+        );
+        let text = Action::Macro("git status".to_string());
+        assert_eq!(
+            bindings.bind("\\M-g", text.clone()),
+            Ok("\\M-g".to_string())
+        );
+        assert!(Action::function("no-such-function").is_none());
+        assert!(bindings.bind("\\q", text.clone()).is_err());
+
+        let list: Vec<(&String, &Action)> = bindings.iter().collect();
+        assert_eq!(
+            list,
+            vec![
+                (&"\\C-t".to_string(), &action),
+                (&"\\M-g".to_string(), &text)
+            ]
+        );
+        assert_eq!(bind_command("\\M-g", &text), "bind -s \\M-g git status");
+
+        assert_eq!(bindings.unbind("\\C-t"), Ok("\\C-t".to_string()));
+        assert!(bindings.unbind("\\C-t").is_err());
+        assert_eq!(bindings.pending.len(), 3);
+    }
+}
@@ -1,5 +1,12 @@
+mod arith;
+mod editor;
+mod getopts;
 mod helper;
+mod history;
+mod keybind;
+mod msg;
 mod shell;
+mod usage;
 
 use helper::DynError;
 
@@ -13,8 +20,26 @@ fn main() -> Result<(), DynError> {
         logfile = h.to_str().unwrap_or(HISTORY_FILE);
     }
 
-    let sh = shell::Shell::new(logfile);
-    sh.run()?;
+    // argv[0]が-で始まる場合か、--loginが指定された場合はログインシェルとして起動
+    // loginやsshdは、ログインシェルを起動する際にargv[0]の先頭に-を付ける
+    let mut args: Vec<String> = std::env::args().collect();
+    let mut login = args.first().is_some_and(|arg0| arg0.starts_with('-'));
+    if args.get(1).is_some_and(|arg| arg == "--login") {
+        login = true;
+        args.remove(1);
+    }
+
+    // メッセージの言語はロケールの環境変数から決める
+    msg::set_lang(msg::Lang::from_env());
+
+    let sh = shell::Shell::new(logfile, login);
+
+    // 引数にスクリプトファイルが指定された場合は非対話的に実行
+    if let Some(script) = args.get(1) {
+        sh.run_script(script)?;
+    } else {
+        sh.run()?;
+    }
 
     Ok(())
 }
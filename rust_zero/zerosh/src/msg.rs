@@ -0,0 +1,340 @@
+//! 利用者に表示するメッセージのカタログ
+//!
+//! メッセージはMsgIdで識別し、言語ごとの書式をcatalog!で定義する。
+//! 書式中の{0}、{1}...は、msg!に渡した引数で順に置き換える。
+//! 表示する言語は起動時にLC_ALL、LC_MESSAGES、LANGから選び、`set lang`で変更できる。
+//!
+//! 言語はmainスレッドとworkerスレッド、fork後の子プロセスで共有するため、アトミック変数に保持する。
+//! 書式は&'static strで返すので、fork後の子プロセスでもメモリを確保せずに参照できる。
+
+use std::{
+    env, fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// メッセージの言語
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    Ja, // 日本語
+    En, // 英語
+}
+
+impl Lang {
+    /// set langで指定する言語名を解析する
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "ja" => Some(Lang::Ja),
+            "en" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    /// ロケールの環境変数から言語を決める
+    ///
+    /// LC_ALL、LC_MESSAGES、LANGの順に、最初に空でないものを使う。
+    /// enから始まる場合は英語、それ以外(未設定やCを含む)は日本語とする
+    pub fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|v| !v.is_empty());
+        match locale {
+            Some(l) if l.starts_with("en") => Lang::En,
+            _ => Lang::Ja,
+        }
+    }
+}
+
+impl fmt::Display for Lang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lang::Ja => write!(f, "ja"),
+            Lang::En => write!(f, "en"),
+        }
+    }
+}
+
+/// 現在の言語。Lang as u8で保持する
+static LANG: AtomicU8 = AtomicU8::new(Lang::Ja as u8);
+
+/// 現在の言語を返す
+pub fn lang() -> Lang {
+    match LANG.load(Ordering::Relaxed) {
+        x if x == Lang::En as u8 => Lang::En,
+        _ => Lang::Ja,
+    }
+}
+
+/// 表示する言語を変更する
+pub fn set_lang(lang: Lang) {
+    LANG.store(lang as u8, Ordering::Relaxed);
+}
+
+/// MsgIdと、言語ごとの書式を定義する
+///
+/// `Id => "日本語", "英語";`の形式で並べる。すべての言語の書式がなければコンパイルできない
+macro_rules! catalog {
+    ($($id:ident => $ja:literal, $en:literal;)*) => {
+        /// メッセージの識別子
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum MsgId {
+            $($id,)*
+        }
+
+        impl MsgId {
+            /// すべてのメッセージの識別子
+            #[cfg(test)]
+            const ALL: &'static [MsgId] = &[$(MsgId::$id,)*];
+
+            /// 言語langでの書式を返す
+            pub fn text_in(self, lang: Lang) -> &'static str {
+                match self {
+                    $(MsgId::$id => match lang {
+                        Lang::Ja => $ja,
+                        Lang::En => $en,
+                    },)*
+                }
+            }
+        }
+    };
+}
+
+catalog! {
+    // mainスレッド
+    HistoryLoadFailed => "ヒストリファイルの読み込みに失敗: {0}", "failed to read the history file: {0}";
+    HistorySaveFailed => "ヒストリファイルへの書き込みに失敗: {0}", "failed to write the history file: {0}";
+    ReadError => "読み込みエラー\n{0}", "read error\n{0}";
+    ExitWithCtrlD => "終了はCtrl+d", "use Ctrl+d to exit";
+
+    // ジョブと予約
+    JobStarted => "[s{0}] 開始\t{1}", "[s{0}] Started\t{1}";
+    JobTimedOut => "[{0}] タイムアウト\t{1}", "[{0}] Timed out\t{1}";
+    JobKilled => "[{0}] 強制終了\t{1}", "[{0}] Killed\t{1}";
+    JobContinues => "[{0}] 実行を継続します\t{1}", "[{0}] Continuing\t{1}";
+    JobHangup => "[{0}] SIGHUPを送信します\t{1}", "[{0}] Sending SIGHUP\t{1}";
+    JobTerminating => "[{0}] 終了させます\t{1}", "[{0}] Terminating\t{1}";
+    JobResumed => "{0} 再開\t{1}", "{0} Resumed\t{1}";
+    JobDone => "[{0}] 終了\t{1}", "[{0}] Done\t{1}";
+    JobStopped => "[{0}] 停止\t{1}", "[{0}] Stopped\t{1}";
+    JobDoneStatus => "[{0}] 終了 (status = {1})\t{2}", "[{0}] Done (status = {1})\t{2}";
+    JobDoneElapsed => "[{0}] 終了 (status = {1}, {2}秒)\t{3}", "[{0}] Done (status = {1}, {2}s)\t{3}";
+    JobStats => "経過 {0}秒, user {1}秒, sys {2}秒, 最大RSS {3}KB", "elapsed {0}s, user {1}s, sys {2}s, max RSS {3}KB";
+    StateRunning => "実行中", "Running";
+    StateStopped => "停止中", "Stopped";
+    Scheduled => "[s{0}] 予約\t{1}", "[s{0}] Scheduled\t{1}";
+    ScheduledIn => "[s{0}] 予約 (あと{1}秒)\t{2}", "[s{0}] Scheduled (in {1}s)\t{2}";
+    ScheduleCanceled => "[s{0}] 取り消し\t{1}", "[s{0}] Canceled\t{1}";
+    NoSuchSchedule => "{0}: そのような予約はありません", "{0}: no such scheduled command";
+    ScheduleBuiltin => "組み込みコマンドは予約できません", "builtin commands cannot be scheduled";
+    TooManyJobs => "管理可能なジョブの最大値に到達", "reached the maximum number of jobs";
+    StoppedJobs => "停止中のジョブがあります", "There are stopped jobs.";
+    RunningJobs => "実行中のジョブがあります", "There are running jobs.";
+    ExitAgain => "ジョブが実行中です。もう一度exitを実行すると終了します", "Jobs are still running. Run exit again to exit.";
+    EofAgain => "ジョブが実行中です。もう一度Ctrl+dを入力すると終了します", "Jobs are still running. Press Ctrl+d again to exit.";
+    ExitForce => "ジョブを終了させる場合はexit -fを実行してください", "Run exit -f to terminate the jobs.";
+    Detached => "[切り離し] {0}", "[detached] {0}";
+    PromptJob => "[ジョブ{0}個] ", "[{0} job] ";
+    PromptJobs => "[ジョブ{0}個] ", "[{0} jobs] ";
+    PromptJobStopped => "[ジョブ{0}個, 停止中{1}個] ", "[{0} job, {1} stopped] ";
+    PromptJobsStopped => "[ジョブ{0}個, 停止中{1}個] ", "[{0} jobs, {1} stopped] ";
+
+    // コマンドの実行
+    CommandNotFound => "コマンドが見つかりません: {0}", "command not found: {0}";
+    DidYouMean => "もしかして: {0}", "did you mean {0}?";
+    SpawnFailed => "プロセス生成エラー: {0}", "failed to create a process: {0}";
+    ExecFailed => "不明なコマンドを実行\n", "failed to execute the command\n";
+    WaitFailed => "waitが失敗: {0}", "wait failed: {0}";
+    ChildSignaled => "子プロセスがシグナルにより終了{0}: pid = {1}, signal = {2}", "child process terminated by a signal{0}: pid = {1}, signal = {2}";
+    CoreDumped => " (コアダンプ) ", " (core dumped) ";
+    SignalFailed => "{0}の送信に失敗: {1}", "failed to send {0}: {1}";
+    PipeTooLong => "3つ以上のコマンドによるパイプはサポートしていません", "pipelines of three or more commands are not supported";
+    PipeInProcSubst => "プロセス置換の中ではパイプを利用できません", "pipes cannot be used inside process substitution";
+    NotInPipeline => "{0}はパイプラインの中では実行できません", "{0} cannot be run in a pipeline";
+    ProcSubstBuiltin => "プロセス置換は組み込みコマンドには利用できません", "process substitution cannot be used with builtin commands";
+    HookFailed => "フックの実行に失敗: {0}", "failed to run the hook: {0}";
+    NotifyFailed => "通知フックの実行に失敗: {0}", "failed to run the notify hook: {0}";
+
+    // 入力の解析とリダイレクト
+    InputEof => "入力の途中でEOFになりました", "unexpected EOF in the middle of input";
+    IncompleteEof => "{0}: 完結していない行の途中でファイルが終了しました", "{0}: unexpected end of file in an incomplete line";
+    EmptyCommand => "空のコマンド", "empty command";
+    BadFd => "不正なファイルディスクリプタ: {0}", "bad file descriptor: {0}";
+    RedirectNoFile => "{0}の後にファイル名がありません", "missing file name after {0}";
+    RedirectDupTarget => "{0}の後は数字か-を指定してください: {1}", "{0} must be followed by a number or -: {1}";
+    RedirectFailed => "リダイレクトに失敗: {0}", "redirection failed: {0}";
+    RedirectRestoreFailed => "リダイレクトの復元に失敗: {0}: {1}", "failed to restore redirection: {0}: {1}";
+    BuiltinRedirectFd => "{0}: 組み込みコマンドでは0から2のみリダイレクトできます", "{0}: builtin commands can only redirect 0 to 2";
+    Noclobber => "{0}: 既存のファイルは上書きできません", "{0}: cannot overwrite existing file";
+    ProcSubstNoParen => "プロセス置換の)がありません", "missing ) in process substitution";
+    ProcSubstNested => "プロセス置換は入れ子にできません", "process substitution cannot be nested";
+    ProcSubstEmpty => "プロセス置換のコマンドが空です", "empty command in process substitution";
+
+    // 組み込みコマンド
+    InvalidArgument => "{0}は不正な引数です", "{0}: invalid argument";
+    CdNoDestination => "移動先のディレクトリが不明です", "no destination directory";
+    GetoptsIllegal => "不正なオプションです -- {0}", "illegal option -- {0}";
+    GetoptsNeedsArg => "オプションには引数が必要です -- {0}", "option requires an argument -- {0}";
+    HookKind => "{0}: preexecかprecmdを指定してください", "{0}: specify preexec or precmd";
+    UnknownOption => "{0}: 不明なオプション", "{0}: unknown option";
+    UnknownLang => "{0}: 不明な言語です (ja, en)", "{0}: unknown language (ja, en)";
+    NoHomeToSave => "ホームディレクトリが不明なため保存できません", "cannot save because the home directory is unknown";
+    HashEmpty => "ハッシュテーブルは空です", "hash table empty";
+    NotFound => "{0}: 見つかりません", "{0}: not found";
+    HistoryCount => "{0}: 行数を指定してください", "{0}: specify the number of lines";
+    BindUnknownFunction => "{0}: 不明な機能です。bind -lで一覧を表示します", "{0}: unknown function. Use bind -l to list them";
+    UnknownSignal => "{0}: 不明なシグナルです", "{0}: unknown signal";
+    TrapUncatchable => "{0}: 無視できないシグナルです", "{0}: cannot ignore this signal";
+    UmaskRange => "{0}: 8進数で000から777の範囲で指定してください", "{0}: specify an octal number from 000 to 777";
+    UsageFg => "usage: fg [数字 | %ジョブ指定]", "usage: fg [number | %jobspec]";
+    UsageJobs => "usage: jobs [-l | -s]", "usage: jobs [-l | -s]";
+    UsageDetach => "usage: detach cmd [args...]", "usage: detach cmd [args...]";
+    UsageSource => "usage: source ファイル名", "usage: source filename";
+    UsageGetopts => "usage: getopts オプション文字列 変数名 [引数...]", "usage: getopts optstring name [args...]";
+    UsageHook => "usage: hook [-r] preexec|precmd [コマンド...]", "usage: hook [-r] preexec|precmd [command...]";
+    UsageShopt => "usage: shopt [-s | -u] [--save] オプション名...", "usage: shopt [-s | -u] [--save] optname...";
+    UsageSet => "usage: set [-o | +o] [オプション名...] | set [-C | +C] | set lang [ja | en]", "usage: set [-o | +o] [optname...] | set [-C | +C] | set lang [ja | en]";
+    UsageBind => "usage: bind [-l] | bind [--save] [キー 機能 | -s キー 文字列... | -r キー]", "usage: bind [-l] | bind [--save] [keys function | -s keys string... | -r keys]";
+    UsageUmask => "usage: umask [-S | 8進数]", "usage: umask [-S | octal]";
+    UsageFc => "usage: fc -s [旧=新] [番号 | -n | 接頭辞]", "usage: fc -s [old=new] [number | -n | prefix]";
+    UsageTrap => "usage: trap ['' | -] シグナル...", "usage: trap ['' | -] signal...";
+    UsageTimeout => "usage: timeout [-k 時間] 時間 cmd [args...]", "usage: timeout [-k duration] duration cmd [args...]";
+    UsageSchedule => "usage: schedule 時間 cmd [args...]\n       schedule -c 予約ID", "usage: schedule duration cmd [args...]\n       schedule -c id";
+
+    // ShellError
+    SyscallFailed => "{0}に失敗: {1}", "{0} failed: {1}";
+    NoSuchJob => "{0}というジョブは見つかりませんでした。", "{0}: no such job";
+    AmbiguousJob => "{0}に該当するジョブが複数あります。", "{0}: ambiguous job spec";
+    NulByte => "引数にヌル文字が含まれています", "argument contains a null character";
+    Channel => "スレッド間の通信に失敗", "failed to communicate between threads";
+
+    // 算術式
+    ArithUnbalanced => "算術式の括弧が対応していません", "unbalanced parentheses in arithmetic expression";
+    ArithNoClose => "算術式の))がありません", "missing )) in arithmetic expression";
+    ArithBadToken => "算術式の構文エラー: 不正なトークン{0}", "arithmetic syntax error: unexpected token {0}";
+    ArithBadNumber => "算術式の不正な数値: {0}", "invalid number in arithmetic expression: {0}";
+    ArithNoVarName => "算術式の$の後に変数名がありません", "missing variable name after $ in arithmetic expression";
+    ArithBadChar => "算術式の不正な文字: {0}", "invalid character in arithmetic expression: {0}";
+    ArithNotInteger => "算術式の変数{0}の値が整数ではありません: {1}", "variable {0} in arithmetic expression is not an integer: {1}";
+    ArithNoParen => "算術式の)がありません", "missing ) in arithmetic expression";
+    ArithUnexpectedEnd => "算術式の構文エラー: 式が途中で終わっています", "arithmetic syntax error: unexpected end of expression";
+    ArithDivZero => "算術式で0による除算", "division by zero in arithmetic expression";
+    ArithOverflow => "算術式でオーバーフロー", "overflow in arithmetic expression";
+
+    // ヒストリ展開とキー割り当て
+    EventNotFound => "!{0}: イベントが見つかりません", "!{0}: event not found";
+    FcEventNotFound => "fc: {0}: イベントが見つかりません", "fc: {0}: event not found";
+    BadSubst => "{0}: 置換の指定が不正です", "{0}: bad substitution";
+    SubstFailed => "{0}: 置換する文字列が見つかりません", "{0}: substitution failed";
+    ConfirmSubst => "Enterで実行、他の入力かCtrl+Cで取り消し: ", "press Enter to run, anything else or Ctrl+C to cancel: ";
+    SubstCanceled => "置換した行の実行を取り消しました", "canceled running the substituted line";
+    KeyUnbound => "{0}: 割り当てられていません", "{0}: not bound";
+    BadKeyNotation => "{0}: 不正なキーの表記です", "{0}: invalid key notation";
+    NoKey => "キーを指定してください", "specify a key";
+    FnInsertLastArgument => "直前の行の最後の引数を挿入", "insert the last argument of the previous line";
+    FnBeginningOfLine => "行頭へ移動", "move to the beginning of the line";
+    FnEndOfLine => "行末へ移動", "move to the end of the line";
+    FnBackwardWord => "前の単語へ移動", "move to the previous word";
+    FnForwardWord => "次の単語へ移動", "move to the next word";
+    FnKillLine => "カーソルから行末までを削除", "delete from the cursor to the end of the line";
+    FnUnixLineDiscard => "行頭からカーソルまでを削除", "delete from the beginning of the line to the cursor";
+    FnKillWord => "カーソルから単語の末尾までを削除", "delete from the cursor to the end of the word";
+    FnBackwardKillWord => "カーソルの前の単語を削除", "delete the word before the cursor";
+    FnTransposeChars => "カーソルの前の2文字を入れ替え", "swap the two characters before the cursor";
+    FnUndo => "直前の編集を取り消し", "undo the last edit";
+    FnClearScreen => "画面を消去", "clear the screen";
+    FnReverseSearchHistory => "ヒストリを後方にインクリメンタル検索", "search the history backward incrementally";
+    FnHistorySearchBackward => "入力中の文字列で始まる前のヒストリ", "previous history entry starting with the input";
+    FnHistorySearchForward => "入力中の文字列で始まる次のヒストリ", "next history entry starting with the input";
+    FnAcceptLine => "行を確定", "accept the line";
+}
+
+/// 現在の言語でのidの書式を返す
+pub fn text(id: MsgId) -> &'static str {
+    id.text_in(lang())
+}
+
+/// 書式fmtの{0}、{1}...をargsで置き換える
+///
+/// 対応する引数がない場合は、そのまま残す
+pub fn format(fmt: &str, args: &[&dyn fmt::Display]) -> String {
+    let mut out = String::new();
+    let mut rest = fmt;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let arg = rest
+            .find('}')
+            .and_then(|end| Some((end, args.get(rest[1..end].parse::<usize>().ok()?)?)));
+        match arg {
+            Some((end, arg)) => {
+                out.push_str(&arg.to_string());
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 現在の言語でメッセージを組み立てる
+///
+/// `msg!(CommandNotFound, name)`のように、MsgIdの名前と書式の引数を指定する
+macro_rules! msg {
+    ($id:ident $(, $arg:expr)* $(,)?) => {
+        $crate::msg::format($crate::msg::text($crate::msg::MsgId::$id), &[$(&$arg),*])
+    };
+}
+
+pub(crate) use msg;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 書式に含まれる{0}、{1}...の番号
+    fn placeholders(fmt: &str) -> Vec<usize> {
+        let mut nums: Vec<usize> = fmt
+            .split('{')
+            .skip(1)
+            .filter_map(|s| s.split_once('}')?.0.parse().ok())
+            .collect();
+        nums.sort();
+        nums
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format("[{0}] 終了\t{1}", &[&1, &"sleep 1"]),
+            "[1] 終了\tsleep 1"
+        );
+        assert_eq!(format("{1}-{0}", &[&"a", &"b"]), "b-a");
+        assert_eq!(format("{2} {x} {", &[&0]), "{2} {x} {");
+        assert_eq!(
+            MsgId::CommandNotFound.text_in(Lang::En),
+            "command not found: {0}"
+        );
+    }
+
+    #[test]
+    fn test_catalog() {
+        // すべての言語で、書式の引数が一致する
+        for id in MsgId::ALL {
+            let ja = id.text_in(Lang::Ja);
+            let en = id.text_in(Lang::En);
+            assert_eq!(placeholders(ja), placeholders(en), "{id:?}");
+            assert!(!en.is_empty(), "{id:?}");
+        }
+    }
+
+    #[test]
+    fn test_lang() {
+        assert_eq!(Lang::parse("en"), Some(Lang::En));
+        assert_eq!(Lang::parse("fr"), None);
+        assert_eq!(Lang::En.to_string(), "en");
+    }
+}
@@ -1,24 +1,142 @@
-use crate::helper::DynError;
+use crate::{
+    arith,
+    editor::{self, ShellEditor},
+    getopts::{Getopts, Opt},
+    helper::{DynError, ShellError},
+    history::{self, HistoryList},
+    keybind::{bind_command, Action, KeyBindings, FUNCTIONS},
+    msg::{self, msg, Lang, MsgId},
+    usage::{JobStats, Usage},
+};
 use nix::{
+    fcntl::{fcntl, open, FcntlArg, FdFlag, OFlag},
     libc,
     sys::{
-        signal::{killpg, signal, SigHandler, Signal},
-        wait::{waitpid, WaitPidFlag, WaitStatus},
+        signal::{killpg, signal, SigHandler, SigSet, Signal},
+        stat::{umask, Mode},
+        wait::{WaitPidFlag, WaitStatus},
+    },
+    unistd::{
+        self, access, chdir, dup2, execv, fork, getpgrp, isatty, pipe2, setpgid, setsid, tcgetpgrp,
+        tcsetpgrp, AccessFlags, ForkResult, Pid,
     },
-    unistd::{self, dup2, execvp, fork, pipe, setpgid, tcgetpgrp, tcsetpgrp, ForkResult, Pid},
 };
-use rustyline::{error::ReadlineError, Editor};
+use rustyline::error::ReadlineError;
 use signal_hook::{consts::*, iterator::Signals};
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    env,
     ffi::CString,
-    mem::replace,
-    path::PathBuf,
-    process::exit,
-    sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
+    fmt, fs,
+    io::{self, Write},
+    mem::{self, replace},
+    ops::Range,
+    os::unix::{ffi::OsStrExt, io::RawFd, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{exit, Command},
+    sync::{
+        mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender},
+        Arc, Mutex,
+    },
     thread,
+    time::{Duration, Instant},
 };
 
+/// バックグラウンドジョブ終了時に実行する通知フックコマンドを指定する環境変数
+const NOTIFY_CMD_ENV: &str = "ZEROSH_NOTIFY_CMD";
+
+/// 通知フックを実行するジョブの実行時間の閾値(秒)を指定する環境変数
+const NOTIFY_SECS_ENV: &str = "ZEROSH_NOTIFY_SECS";
+
+/// 通知フックを実行するジョブの実行時間の閾値のデフォルト値(秒)
+const NOTIFY_SECS_DEFAULT: u64 = 10;
+
+/// シェルの終了時にジョブへSIGHUPを送信するかを指定する環境変数。空でなければ送信する
+///
+/// shopt -s huponexitと同じ
+const HUPONEXIT_ENV: &str = "ZEROSH_HUPONEXIT";
+
+/// 対話的に起動した場合に、最初に読み込んで実行するファイル。ホームディレクトリに置く
+const RC_FILE: &str = ".zeroshrc";
+
+/// ログインシェルとして起動した場合に、最初に読み込んで実行するシステム全体のファイル
+///
+/// /etc/profileはsh向けの構文で書かれていて実行できないため、ZeroSh専用のファイルとする
+const SYSTEM_PROFILE: &str = "/etc/zerosh_profile";
+
+/// ログインシェルとして起動した場合に、SYSTEM_PROFILEの次に読み込んで実行するファイル。
+/// ホームディレクトリに置く
+const PROFILE_FILE: &str = ".zerosh_profile";
+
+/// 入力が完結していない場合に、続きを読み込むために表示するプロンプト
+const CONTINUATION_PROMPT: &str = "> ";
+
+/// timeoutコマンドで、SIGTERMを送信してからSIGKILLを送信するまでの時間のデフォルト値(秒)
+const TIMEOUT_KILL_AFTER_DEFAULT: u64 = 2;
+
+/// timeoutコマンドで実行したジョブが制限時間を超えた場合の終了コード
+const TIMEOUT_STATUS: i32 = 124;
+
+/// 組み込みコマンドの名前。Worker::build_in_cmdで実行するコマンドと一致させる
+const BUILTINS: &[&str] = &[
+    "exit", "jobs", "fg", "cd", "hash", "umask", "exec", "detach", "timeout", "source", ".",
+    "shopt", "set", "history", "bind", "getopts", "hook", "jobstats", "schedule", "trap",
+];
+
+/// リダイレクトを子プロセスに適用する組み込みコマンドの名前
+///
+/// これ以外の組み込みコマンドのリダイレクトは、実行中のみシェル自身に適用する
+const REDIRECT_SELF_BUILTINS: &[&str] = &["exec", "detach", "timeout", "schedule"];
+
+/// 組み込みコマンドのリダイレクトで元のファイルディスクリプタを退避する先や、
+/// プロセス置換のパイプを置く先の最小値
+///
+/// 子プロセスでクローズされる3から6や、リダイレクトでよく使われる小さい番号を避ける
+const SAVED_FD_MIN: RawFd = 10;
+
+/// パイプラインの中で実行できる組み込みコマンドの名前
+///
+/// パイプラインの中の組み込みコマンドは出力をfork前に生成し、子プロセスはそれを書き込むだけとする。
+/// シェルの状態を変更するコマンドは、パイプラインの中では効果がないため実行できない
+const PIPE_BUILTINS: &[&str] = &["jobs", "jobstats", "history"];
+
+/// 子プロセスでデフォルトの処理に戻すシグナル
+///
+/// シェルはSIGINT、SIGTSTP、SIGCHLDを捕捉し、SIGTTOUを無視している。また、Rustの実行時ライブラリはSIGPIPEを無視する。
+/// 捕捉はexecでデフォルトに戻るが、無視はそのまま引き継がれるため、子プロセス自身のジョブ制御などが動作しなくなる
+const CHILD_DEFAULT_SIGNALS: &[Signal] = &[
+    Signal::SIGINT,
+    Signal::SIGTSTP,
+    Signal::SIGTTOU,
+    Signal::SIGCHLD,
+    Signal::SIGPIPE,
+];
+
+/// jobstatsコマンドで表示する、終了したジョブの数の上限
+const JOBSTATS_MAX: usize = 10;
+
+/// waitpid(-1, flag)と同様に任意の子プロセスの状態変化を検知する
+///
+/// wait4を利用し、プロセスが終了した場合はそのリソース使用量も返す。
+/// 終了以外の状態変化の場合、リソース使用量は0となる
+fn wait_any(flag: WaitPidFlag) -> Result<(WaitStatus, Usage), nix::Error> {
+    let mut status = 0;
+    // rusageはすべてのフィールドが整数のため、0で初期化できる
+    let mut ru: libc::rusage = unsafe { mem::zeroed() };
+    let pid = unsafe { libc::wait4(-1, &mut status, flag.bits(), &mut ru) };
+    let pid = nix::errno::Errno::result(pid)?;
+    if pid == 0 {
+        return Ok((WaitStatus::StillAlive, Usage::default()));
+    }
+    let status = WaitStatus::from_raw(Pid::from_raw(pid), status)?;
+    let usage = match status {
+        WaitStatus::Exited(..) | WaitStatus::Signaled(..) => Usage::from_rusage(&ru),
+        _ => Usage::default(),
+    };
+    Ok((status, usage))
+}
+
 /// システムコール呼び出しのラッパ。EINTRならリトライ
 ///
 /// EINTRはシステムコール中に割り込みが発生したことを示しており、
@@ -41,106 +159,346 @@ where
 
 /// workerスレッドが受信するメッセージ
 enum WorkerMsg {
-    Signal(i32), // シグナルを受信
-    Cmd(String), // コマンド入力
+    Signal(i32),       // シグナルを受信
+    Cmd(String, bool), // コマンド入力。boolはプロンプトで入力された行なら真で、その場合のみフックを実行する
+    Jobs,              // Ctrl+dによる終了の前に、ジョブの一覧を問い合わせる
 }
 
 /// mainスレッドが受信するメッセージ
 enum ShellMsg {
-    Continue(i32), // シェルの読み込みを再開。i32は最後の終了コード
-    Quit(i32),     // シェルを終了。i32はシェルの終了コード
+    Continue(CmdStatus, JobCount), // シェルの読み込みを再開。CmdStatusは最後のコマンドの終了状態、JobCountはジョブの数
+    Quit(i32),                     // シェルを終了。i32はシェルの終了コード
+    Jobs(JobCount, Vec<String>),   // WorkerMsg::Jobsへの返答。ジョブの数と、各ジョブの状態を表す行
+}
+
+/// プロンプトに表示するジョブの数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct JobCount {
+    total: usize,   // すべてのジョブの数
+    stopped: usize, // 停止中のジョブの数
+}
+
+impl fmt::Display for JobCount {
+    /// `[ジョブ2個, 停止中1個]`(英語では`[2 jobs, 1 stopped]`)のように表示する。ジョブがない場合は何も表示しない
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match (self.total, self.stopped) {
+            (0, _) => return Ok(()),
+            (1, 0) => msg!(PromptJob, self.total),
+            (_, 0) => msg!(PromptJobs, self.total),
+            (1, _) => msg!(PromptJobStopped, self.total, self.stopped),
+            (_, _) => msg!(PromptJobsStopped, self.total, self.stopped),
+        };
+        write!(f, "{text}")
+    }
+}
+
+/// フォアグラウンドで実行したコマンドの終了状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmdStatus {
+    Exited(i32),      // 終了コードを返して終了
+    Signaled(Signal), // シグナルにより終了
+    Stopped(Signal),  // シグナルにより停止
+}
+
+impl CmdStatus {
+    /// $?で参照される終了コードを返す
+    ///
+    /// POSIXと同様に、シグナルにより終了または停止した場合は128+シグナル番号とする。
+    /// 例えばCtrl+Cで終了した場合は130(SIGINT)、Ctrl+Zで停止した場合は148(SIGTSTP)となる。
+    /// 停止したジョブをfgで再開した場合は、そのジョブが終了した時点の終了状態となる
+    fn code(&self) -> i32 {
+        match self {
+            CmdStatus::Exited(n) => *n,
+            CmdStatus::Signaled(sig) | CmdStatus::Stopped(sig) => 128 + *sig as i32,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Shell {
-    logfile: String, // ログファイル
+    logfile: String,                   // ログファイル
+    login: bool,                       // ログインシェルなら真
+    history: Arc<Mutex<HistoryList>>,  // workerスレッドと共有する番号付きのヒストリ
+    bindings: Arc<Mutex<KeyBindings>>, // workerスレッドと共有するキー割り当て
 }
 
 impl Shell {
-    pub fn new(logfile: &str) -> Self {
+    /// loginが真の場合はログインシェルとして動作する
+    ///
+    /// ログインシェルは起動時にプロファイルを実行し、終了時にジョブへSIGHUPを送信する
+    pub fn new(logfile: &str, login: bool) -> Self {
         Shell {
             logfile: logfile.to_string(),
+            login,
+            history: Arc::default(),
+            bindings: Arc::default(),
         }
     }
 
-    /// mainスレッド
-    pub fn run(&self) -> Result<(), DynError> {
+    /// signal_handlerとworkerスレッドを生成し、
+    /// workerスレッドへの送信側とworkerスレッドからの受信側のチャネルを返す
+    fn start(&self) -> Result<(Sender<WorkerMsg>, Receiver<ShellMsg>), DynError> {
         // SIGTTOUを無視に設定しないと、SIGTSTPが配送される
         // デフォルトの挙動だと、標準出力への書き込み時にSIGTSTPが配送されて、シェルが停止してしまう
         // そこで、SIGTTOUシグナルを無視するために、SigIgnと設定する
         unsafe { signal(Signal::SIGTTOU, SigHandler::SigIgn).unwrap() };
 
-        // rustylineのEditorを利用すると、標準入力からの読み込みが容易に行え、
-        // 矢印キーを使った操作などをサポートできる。
-        let mut rl = Editor::<()>::new()?;
-        if let Err(e) = rl.load_history(&self.logfile) {
-            eprintln!("Zerosh: ヒストリファイルの読み込みに失敗: {e}");
-        };
-
         // チャネルを生成し、signal_handlerとworkerスレッドを生成
         let (worker_tx, worker_rx) = channel();
         let (shell_tx, shell_rx) = sync_channel(0);
         spawn_sig_handler(worker_tx.clone())?;
-        Worker::new().spawn(worker_rx, shell_tx);
+        Worker::new(
+            self.login,
+            Arc::clone(&self.history),
+            Arc::clone(&self.bindings),
+        )
+        .spawn(worker_rx, shell_tx);
+        Ok((worker_tx, shell_rx))
+    }
+
+    /// スクリプトファイルを非対話的に実行するmainスレッド
+    ///
+    /// スクリプトの各行を1行ずつworkerスレッドに送信し、完了を待ってから次の行を送信する。
+    /// 全ての行を実行し終えたら、最後の終了コードでシェルを終了する。
+    pub fn run_script(&self, path: &str) -> Result<(), DynError> {
+        let lines = read_script(path)?;
+        let (worker_tx, shell_rx) = self.start()?;
+
+        let mut exit_val = 0;
+        for line in lines {
+            worker_tx
+                .send(WorkerMsg::Cmd(line, false))
+                .map_err(|_| ShellError::Channel)?;
+            match shell_rx.recv().map_err(|_| ShellError::Channel)? {
+                ShellMsg::Continue(status, _) => exit_val = status.code(),
+                ShellMsg::Quit(n) => exit(n),
+                ShellMsg::Jobs(..) => (), // 問い合わせないため受信しない
+            }
+        }
+        exit(exit_val);
+    }
+
+    /// mainスレッド
+    pub fn run(&self) -> Result<(), DynError> {
+        // rustylineのEditorを利用すると、標準入力からの読み込みが容易に行え、
+        // 矢印キーを使った操作などをサポートできる。
+        // Editorは端末の大きさの変更時に入力行を再描画するためSIGWINCHのハンドラを設定する。
+        // signal_hookは登録時に既存のハンドラも呼び出すようにするため、Editorはworkerスレッドの生成前に作成する。
+        // 編集モードなどはオプションの初期値で設定し、オプションが変更されたらKeyBindingsを通して反映する
+        let mut rl = editor::new_editor()?;
+        if let Err(e) = history::load(&mut rl, &self.logfile) {
+            eprintln!("ZeroSh: {}", msg!(HistoryLoadFailed, e));
+        };
+        *self.history.lock().unwrap() = HistoryList::new(rl.history().iter());
+
+        // workerスレッドとの通信に失敗した場合も、ヒストリを保存してから終了する
+        let mut entries = Vec::new(); // このセッションで入力した行
+        let exit_val = match self.read_loop(&mut rl, &mut entries) {
+            Ok(n) => n,
+            Err(e) => {
+                eprintln!("ZeroSh: {e}");
+                1
+            }
+        };
+        self.shutdown(&entries, exit_val);
+    }
+
+    /// 1行ずつ読み込んでworkerスレッドに送信し、シェルの終了コードを返す
+    ///
+    /// 入力した行はヒストリに追加し、保存するためにentriesにも追加する
+    fn read_loop(&self, rl: &mut ShellEditor, entries: &mut Vec<String>) -> Result<i32, DynError> {
+        let (worker_tx, shell_rx) = self.start()?;
+
+        let mut prev = CmdStatus::Exited(0); // 直前のコマンドの終了状態
+        let mut jobs = JobCount::default(); // 直前のコマンド実行後のジョブの数
+        let mut eof_warned = false; // 直前のCtrl+dでジョブが残っていることを警告したか
+
+        // 最初にsourceで実行するファイル
+        // ログインシェルの場合はプロファイルを実行してから、rcファイルを実行する
+        let mut startup = Vec::new();
+        if self.login {
+            startup.push(PathBuf::from(SYSTEM_PROFILE));
+            startup.extend(dirs::home_dir().map(|home| home.join(PROFILE_FILE)));
+        }
+        startup.extend(rc_path());
+
+        for path in startup.iter().filter(|path| path.is_file()) {
+            worker_tx
+                .send(WorkerMsg::Cmd(format!("source {}", path.display()), false))
+                .map_err(|_| ShellError::Channel)?;
+            match shell_rx.recv().map_err(|_| ShellError::Channel)? {
+                ShellMsg::Continue(_, count) => jobs = count,
+                ShellMsg::Quit(n) => return Ok(n),
+                ShellMsg::Jobs(..) => (), // 問い合わせないため受信しない
+            }
+        }
 
-        let exit_val; // 終了コード
-        let mut prev = 0; // 直前の終了コード
+        // rcファイルなどのbindコマンドによるキー割り当てや、行編集のオプションを反映する
+        self.bindings.lock().unwrap().apply(rl, &self.history);
 
         loop {
             // 1行読み込んで、その行をworkerスレッドに送信
-            let face = if prev == 0 { '\u{1F642}' } else { '\u{1F480}' };
-            match rl.readline(&format!("ZeroSh {face} &> ")) {
+            // 直前のコマンドが成功した場合、停止した場合、失敗した場合で顔を変える
+            // 停止中のジョブを忘れないように、ジョブがある場合はその数も表示する
+            let face = match prev {
+                CmdStatus::Exited(0) => '\u{1F642}',
+                CmdStatus::Stopped(_) => '\u{1F634}',
+                _ => '\u{1F480}',
+            };
+            // プロンプトで入力された行ならtyped、Ctrl+dによるexitなら偽
+            let (line, typed) = match rl.readline(&format!("ZeroSh {face} {jobs}&> ")) {
                 Ok(line) => {
-                    let line_trimed = line.trim();
-                    if line_trimed.is_empty() {
+                    eof_warned = false;
+
+                    // 入力が完結していなければ、続きの行を読み込んで連結する
+                    let line = match read_continuation(rl, line) {
+                        Ok(Some(line)) => line,
+                        Ok(None) => continue, // 入力を取り消した
+                        Err(e) => {
+                            eprintln!("ZeroSh: {}", msg!(ReadError, e));
+                            return Ok(1);
+                        }
+                    };
+                    if line.trim().is_empty() {
                         continue; // 空のコマンドの場合は再読み込み
-                    } else {
-                        rl.add_history_entry(line_trimed); // ヒストリファイルに追加
                     }
 
-                    // workerスレッドに送信
-                    worker_tx.send(WorkerMsg::Cmd(line)).unwrap();
-
-                    //workerスレッドの処理が完了するまで待機
-                    match shell_rx.recv().unwrap() {
-                        ShellMsg::Continue(n) => prev = n, // 読み込み再開
-                        ShellMsg::Quit(n) => {
-                            // シェルを終了
-                            exit_val = n;
-                            break;
+                    // !nなどのヒストリの参照やfc -sを展開し、展開した行を表示する
+                    // 置換によって行が変わった場合は、差分を表示してEnterで確認してから実行する
+                    let expanded = {
+                        let history = self.history.lock().unwrap();
+                        let args: Vec<&str> = line.split_whitespace().collect();
+                        match args.split_first() {
+                            Some((&"fc", args)) => {
+                                history.fc(args).map(|e| Some((e, history.confirm())))
+                            }
+                            _ => history
+                                .expand(&line)
+                                .map(|e| e.map(|e| (e, history.confirm()))),
+                        }
+                    };
+                    let line = match expanded {
+                        Ok(Some((expanded, confirm))) => {
+                            match expanded.before {
+                                Some(before) if confirm => {
+                                    let color = isatty(libc::STDOUT_FILENO).unwrap_or(false);
+                                    println!(
+                                        "{}",
+                                        history::preview(&before, &expanded.line, color)
+                                    );
+                                    if !confirm_subst(rl)? {
+                                        eprintln!("ZeroSh: {}", msg!(SubstCanceled));
+                                        continue;
+                                    }
+                                }
+                                _ => println!("{}", expanded.line),
+                            }
+                            expanded.line
                         }
+                        Ok(None) => line,
+                        Err(e) => {
+                            eprintln!("ZeroSh: {e}");
+                            continue;
+                        }
+                    };
+
+                    // ヒストリに追加
+                    // rustylineが追加した場合のみ追加し、historyコマンドの番号と一致させる
+                    let line_trimed = line.trim();
+                    if rl.add_history_entry(line_trimed) {
+                        self.history.lock().unwrap().push(line_trimed.to_string());
                     }
+                    entries.push(line_trimed.to_string());
+                    (line, true)
                 }
                 // コマンド読み込み時に割り込みが発生した場合は、再実行する
                 // これは、主にCtrl+cが入力された場合に発生し、
                 // 誤ってシェルを終了させてしまうことを防ぐために、このようにしている
-                Err(ReadlineError::Interrupted) => eprintln!("ZeroSh: 終了はCtrl+d"),
+                Err(ReadlineError::Interrupted) => {
+                    eof_warned = false;
+                    eprintln!("ZeroSh: {}", msg!(ExitWithCtrlD));
+                    continue;
+                }
                 // Ctrl+dを入力すると、End of File(EOF)と呼ばれる入力終了を意味する特殊な文字を入力できる
                 // EOFが入力されるとexitコマンドをworkerスレッドに送信し、workerスレッドからの返答を受信後終了する
-                // exitコマンド実行後は必ず、Quitを受信するはずなので、それ以外を受信した場合にはパニックさせてプログラムを終了させる
+                // ジョブが存在する場合は、ジョブの一覧を表示して読み込みを再開し、
+                // 続けてもう一度Ctrl+dが入力された場合にのみexitコマンドを送信する
                 Err(ReadlineError::Eof) => {
-                    worker_tx.send(WorkerMsg::Cmd("exit".to_string())).unwrap();
-                    match shell_rx.recv().unwrap() {
-                        ShellMsg::Quit(n) => {
-                            // シェルを終了
-                            exit_val = n;
-                            break;
-                        }
-                        _ => {
-                            panic!("exitに失敗");
+                    if !eof_warned {
+                        worker_tx
+                            .send(WorkerMsg::Jobs)
+                            .map_err(|_| ShellError::Channel)?;
+                        match shell_rx.recv().map_err(|_| ShellError::Channel)? {
+                            ShellMsg::Jobs(count, list) if count.total > 0 => {
+                                if count.stopped > 0 {
+                                    eprintln!("{}", msg!(StoppedJobs));
+                                } else {
+                                    eprintln!("{}", msg!(RunningJobs));
+                                }
+                                for line in list {
+                                    eprintln!("{line}");
+                                }
+                                eprintln!("{}", msg!(EofAgain));
+                                eprintln!("{}", msg!(ExitForce));
+                                eof_warned = true;
+                                prev = CmdStatus::Exited(1);
+                                jobs = count;
+                                continue;
+                            }
+                            ShellMsg::Quit(n) => return Ok(n),
+                            _ => (),
                         }
                     }
+                    ("exit".to_string(), false)
                 }
                 Err(e) => {
-                    eprintln!("ZeroSh: 読み込みエラー\n{e}");
-                    exit_val = 1;
-                    break;
+                    eprintln!("ZeroSh: {}", msg!(ReadError, e));
+                    return Ok(1);
+                }
+            };
+
+            // workerスレッドに送信
+            worker_tx
+                .send(WorkerMsg::Cmd(line, typed))
+                .map_err(|_| ShellError::Channel)?;
+
+            //workerスレッドの処理が完了するまで待機
+            match shell_rx.recv().map_err(|_| ShellError::Channel)? {
+                // 読み込み再開
+                ShellMsg::Continue(status, count) => {
+                    prev = status;
+                    jobs = count;
                 }
+                ShellMsg::Quit(n) => return Ok(n), // シェルを終了
+                ShellMsg::Jobs(..) => (),          // 問い合わせの返答はEOFの入力時にのみ受信する
+            }
+
+            // history -cで消去された場合は、rustylineのヒストリと、保存する行も消去する
+            if self.history.lock().unwrap().take_cleared() {
+                rl.clear_history();
+                entries.clear();
+            }
+
+            // bindコマンドで変更されたキー割り当てや、set -o viなどで変更された行編集のオプションを反映する
+            self.bindings.lock().unwrap().apply(rl, &self.history);
+        }
+    }
+
+    /// シェルを終了する
+    ///
+    /// 端末のフォアグラウンドプロセスグループがジョブを指したまま終了しないように、
+    /// シェルのプロセスグループに戻してから、このセッションで入力した行entriesを
+    /// ヒストリファイルへ追加して終了する。
+    /// ジョブの後始末はexitコマンドの実行時にworkerスレッドで行われる。
+    fn shutdown(&self, entries: &[String], exit_val: i32) -> ! {
+        if isatty(libc::STDIN_FILENO).unwrap_or(false) {
+            if let Err(e) = tcsetpgrp(libc::STDIN_FILENO, getpgrp()) {
+                eprintln!("ZeroSh: {}", ShellError::Syscall("tcsetpgrp", e));
             }
         }
 
-        if let Err(e) = rl.save_history(&self.logfile) {
-            eprintln!("ZeroSh: ヒストリファイルへの書き込みに失敗: {e}");
+        if let Err(e) = history::save(&self.logfile, entries) {
+            eprintln!("ZeroSh: {}", msg!(HistorySaveFailed, e));
         }
         exit(exit_val);
     }
@@ -148,11 +506,15 @@ impl Shell {
 
 fn spawn_sig_handler(tx: Sender<WorkerMsg>) -> Result<(), DynError> {
     // SIGCHLD: 子プロセスの状態変化時に通知される
-    let mut signals = Signals::new(&[SIGINT, SIGTSTP, SIGCHLD])?;
+    // SIGWINCH: 端末の大きさの変更時に、端末のフォアグラウンドプロセスグループに通知される
+    let mut signals = Signals::new(&[SIGINT, SIGTSTP, SIGCHLD, SIGWINCH])?;
     thread::spawn(move || {
         for sig in signals.forever() {
             // シグナルを受信しworkerスレッドに転送
-            tx.send(WorkerMsg::Signal(sig)).unwrap();
+            // workerスレッドが終了している場合は転送先がないので終了
+            if tx.send(WorkerMsg::Signal(sig)).is_err() {
+                break;
+            }
         }
     });
     Ok(())
@@ -170,20 +532,196 @@ struct ProcInfo {
     pgid: Pid,        // プロセスグループID
 }
 
+/// ジョブの情報
+#[derive(Debug)]
+struct Job {
+    pgid: Pid,                // プロセスグループID
+    line: String,             // 実行コマンド
+    start: Instant,           // 実行開始時刻
+    last_pid: Pid,            // パイプラインの最後のプロセスのプロセスID
+    status: CmdStatus,        // パイプラインの最後のプロセスの終了状態
+    timeout: Option<Timeout>, // timeoutコマンドで指定された制限時間
+    usage: Usage,             // 回収済みのプロセスのリソース使用量
+    winch: bool,              // フォアグラウンドでない間に端末の大きさが変わったなら真
+}
+
+impl Job {
+    /// ジョブの終了状態を返す
+    ///
+    /// 制限時間を超えて終了させた場合は、シグナルによる終了ではなくTIMEOUT_STATUSとする
+    fn exit_status(&self) -> CmdStatus {
+        match &self.timeout {
+            Some(t) if t.stage != TimeoutStage::Running => CmdStatus::Exited(TIMEOUT_STATUS),
+            _ => self.status,
+        }
+    }
+
+    /// 開始からの経過時間と、回収済みのプロセスのリソース使用量を返す
+    fn stats(&self) -> JobStats {
+        JobStats {
+            elapsed: self.start.elapsed(),
+            usage: self.usage,
+        }
+    }
+}
+
+/// timeoutコマンドによる制限時間の経過の段階
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum TimeoutStage {
+    Running,     // 制限時間内
+    Terminating, // SIGTERMを送信済み
+    Killed,      // SIGKILLを送信済み
+}
+
+/// timeoutコマンドで実行したジョブの制限時間
+///
+/// 制限時間を超えるとSIGTERMを、その後kill_afterが経過しても終了しない場合はSIGKILLを送信する
+#[derive(Debug)]
+struct Timeout {
+    deadline: Instant,    // 次にシグナルを送信する時刻
+    kill_after: Duration, // SIGTERMを送信してからSIGKILLを送信するまでの時間
+    stage: TimeoutStage,  // 制限時間の経過の段階
+}
+
+/// scheduleコマンドで予約したコマンド
+#[derive(Debug)]
+struct Scheduled {
+    deadline: Instant, // 実行する時刻
+    line: String,      // 実行するコマンドの行
+}
+
+/// shoptで設定する真偽値のオプション
+#[derive(Debug)]
+struct ShellOptions {
+    autocd: bool,       // 真ならディレクトリ名のみのコマンドをcdとして実行
+    emacs: bool,        // 真ならemacsモードで行を編集。viとは排他
+    histconfirm: bool,  // 真ならヒストリの置換で変わった行を、差分を表示して確認してから実行
+    huponexit: bool,    // 真ならシェルの終了時にジョブへSIGHUPを送信
+    menucomplete: bool, // 真ならTabキーを押すたびに補完候補を順に挿入
+    noclobber: bool,    // 真なら>で既存のファイルを上書きしない
+    vi: bool,           // 真ならviモードで行を編集。emacsとは排他
+}
+
+impl Default for ShellOptions {
+    fn default() -> Self {
+        ShellOptions {
+            autocd: false,
+            emacs: true,
+            histconfirm: true,
+            huponexit: false,
+            menucomplete: false,
+            noclobber: false,
+            vi: false,
+        }
+    }
+}
+
+impl ShellOptions {
+    /// オプションの名前と値の一覧を名前順に返す
+    fn list(&self) -> [(&'static str, bool); 7] {
+        [
+            ("autocd", self.autocd),
+            ("emacs", self.emacs),
+            ("histconfirm", self.histconfirm),
+            ("huponexit", self.huponexit),
+            ("menucomplete", self.menucomplete),
+            ("noclobber", self.noclobber),
+            ("vi", self.vi),
+        ]
+    }
+
+    /// 名前がnameのオプションの値をonにする。不明なオプションなら偽を返す
+    ///
+    /// emacsとviは編集モードを表すため、一方を設定すると他方はその逆の値にする
+    fn set(&mut self, name: &str, on: bool) -> bool {
+        let flag = match name {
+            "autocd" => &mut self.autocd,
+            "emacs" => {
+                self.vi = !on;
+                &mut self.emacs
+            }
+            "histconfirm" => &mut self.histconfirm,
+            "huponexit" => &mut self.huponexit,
+            "menucomplete" => &mut self.menucomplete,
+            "noclobber" => &mut self.noclobber,
+            "vi" => {
+                self.emacs = !on;
+                &mut self.vi
+            }
+            _ => return false,
+        };
+        *flag = on;
+        true
+    }
+}
+
+/// hookコマンドで登録するフックの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hook {
+    Preexec, // コマンドの実行前
+    Precmd,  // プロンプトの表示前
+}
+
+impl Hook {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "preexec" => Some(Hook::Preexec),
+            "precmd" => Some(Hook::Precmd),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Hook::Preexec => "preexec",
+            Hook::Precmd => "precmd",
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Worker {
-    exit_val: i32,                                     // 終了コード
-    fg: Option<Pid>,                                   // フォアグラウンドのプロセスグループID
-    jobs: BTreeMap<usize, (Pid, String)>, // ジョブIDから(プロセスグループID, 実行コマンド)へのマップ
+    status: CmdStatus,          // フォアグラウンドで実行したコマンドの終了状態
+    fg: Option<Pid>,            // フォアグラウンドのプロセスグループID
+    jobs: BTreeMap<usize, Job>, // ジョブIDからジョブ情報へのマップ
     pgid_to_pids: HashMap<Pid, (usize, HashSet<Pid>)>, // プロセスグループIDから(ジョブID, プロセスID)へのマップ
     pid_to_info: HashMap<Pid, ProcInfo>,               // プロセスIDからプロセス情報へのマップ
     shell_pgid: Pid,                                   // シェルのプロセスグループID
+    path_cache: BTreeMap<String, PathBuf>, // コマンド名から実行ファイルの絶対パスへのマップ
+    notify_cmd: Option<String>,            // バックグラウンドジョブ終了時の通知フック
+    notify_threshold: Duration,            // 通知フックを実行する実行時間の閾値
+    pending: VecDeque<String>,             // sourceで読み込んだ、実行待ちのコマンド
+    run_next: bool,                        // 真ならpendingから次のコマンドを実行
+    interactive: bool,                     // 標準入力が端末なら真
+    current_job: Option<usize>,            // カレントジョブ(%%, %+)のジョブID
+    previous_job: Option<usize>,           // 直前のジョブ(%-)のジョブID
+    line_count: usize,                     // 実行したコマンドの行数
+    exit_warned: Option<usize>,            // ジョブが存在するためexitを警告した行
+    options: ShellOptions,                 // shoptで設定するオプション
+    saved_fds: Vec<(RawFd, Option<RawFd>)>, // 組み込みコマンドのリダイレクトで退避した(fd, 退避先)
+    history: Arc<Mutex<HistoryList>>,      // mainスレッドと共有する番号付きのヒストリ
+    bindings: Arc<Mutex<KeyBindings>>,     // mainスレッドと共有するキー割り当て
+    getopts: Getopts,                      // getoptsコマンドで解析中の位置
+    hooks: Vec<(Hook, Vec<String>)>,       // hookコマンドで登録した(種類, コマンドと引数)
+    last_cmd: Option<(String, Instant)>,   // precmdフックに渡す、入力された行と実行開始時刻
+    finished: VecDeque<(usize, String, CmdStatus, JobStats)>, // jobstatsで表示する、終了したジョブの(ID, 行, 終了状態, 統計)
+    scheduled: BTreeMap<usize, Scheduled>, // 予約IDから予約したコマンドへのマップ
+    next_schedule_id: usize,               // 次に予約するコマンドの予約ID
+    ignored_signals: Vec<Signal>,          // trap ''で設定した、子プロセスが無視するシグナル
 }
 
 impl Worker {
-    fn new() -> Self {
+    /// loginが真ならログインシェルとして、huponexitオプションを有効にする
+    ///
+    /// historyはhistoryコマンドで表示や消去を行う、mainスレッドと共有するヒストリ。
+    /// bindingsはbindコマンドで変更する、mainスレッドと共有するキー割り当て
+    fn new(
+        login: bool,
+        history: Arc<Mutex<HistoryList>>,
+        bindings: Arc<Mutex<KeyBindings>>,
+    ) -> Self {
         Worker {
-            exit_val: 0,
+            status: CmdStatus::Exited(0),
             fg: None, // フォアグラウンドはシェル
             jobs: BTreeMap::new(),
             pgid_to_pids: HashMap::new(),
@@ -195,164 +733,1583 @@ impl Worker {
             // ここでは、つまりシェルのプロセスグループIDを取得している
             // 自身のプロセスグループIDを取得するために、getpgidシステムコールも利用できるが、
             // tcgetpgrpを利用すると、シェルがフォアグラウンドであるかも検査できるため、こちらを利用している
-            shell_pgid: tcgetpgrp(libc::STDIN_FILENO).unwrap(),
+            // 標準入力が端末でない場合(スクリプトの非対話的な実行時)は自身のプロセスグループIDとする
+            shell_pgid: tcgetpgrp(libc::STDIN_FILENO).unwrap_or_else(|_| getpgrp()),
+            path_cache: BTreeMap::new(),
+            notify_cmd: env::var(NOTIFY_CMD_ENV)
+                .ok()
+                .filter(|s| !s.trim().is_empty()),
+            notify_threshold: Duration::from_secs(
+                env::var(NOTIFY_SECS_ENV)
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(NOTIFY_SECS_DEFAULT),
+            ),
+            pending: VecDeque::new(),
+            run_next: false,
+            interactive: isatty(libc::STDIN_FILENO).unwrap_or(false),
+            current_job: None,
+            previous_job: None,
+            line_count: 0,
+            exit_warned: None,
+            options: ShellOptions {
+                huponexit: login || env::var_os(HUPONEXIT_ENV).is_some_and(|v| !v.is_empty()),
+                ..Default::default()
+            },
+            saved_fds: Vec::new(),
+            history,
+            bindings,
+            getopts: Getopts::default(),
+            hooks: Vec::new(),
+            last_cmd: None,
+            finished: VecDeque::new(),
+            scheduled: BTreeMap::new(),
+            next_schedule_id: 1,
+            ignored_signals: Vec::new(),
         }
     }
 
     /// workerスレッドを起動
     fn spawn(mut self, worker_rx: Receiver<WorkerMsg>, shell_tx: SyncSender<ShellMsg>) {
-        thread::spawn(move || {
-            for msg in worker_rx.iter() {
-                match msg {
-                    WorkerMsg::Cmd(line) => {
-                        match parse_cmd(&line) {
-                            Ok(cmd) => {
-                                // 組み込みコマンドを実行
-                                // 組み込みコマンドとは、シェル内部のコマンドのこと
-                                if self.build_in_cmd(&cmd, &shell_tx) {
-                                    // 組み込みコマンドならworker_rxから取得
-                                    continue;
-                                }
-
-                                // 組み込みコマンドでない場合は、外部プログラムを実行
-                                if !self.spawn_child(&line, &cmd) {
-                                    // 子プロセス生成に失敗した場合、シェルからの入力を再開
-                                    shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap();
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("ZeroSh: {e}");
-                                // コマンドのパースに失敗した場合は入力を再開するためmainスレッドに通知
-                                shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap();
-                            }
-                        }
+        thread::spawn(move || loop {
+            // timeoutコマンドで実行中のジョブがある場合は、次の制限時間までに受信しなければタイムアウトさせる
+            let msg = match self.next_deadline() {
+                Some(deadline) => {
+                    match worker_rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    {
+                        Ok(msg) => Some(msg),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
                     }
-                    WorkerMsg::Signal(SIGCHILD) => {
-                        // SIGCHLDは、子プロセスの終了、停止時に親プロセスへ通知されるシグナル
-                        self.wait_child(&shell_tx); // 子プロセスの状態変化管理
+                }
+                None => match worker_rx.recv() {
+                    Ok(msg) => Some(msg),
+                    Err(_) => break,
+                },
+            };
+
+            match msg {
+                Some(WorkerMsg::Cmd(line, typed)) => {
+                    if typed {
+                        self.run_preexec(&line);
                     }
-                    _ => (), // 無視
+                    self.run_line(&line, &shell_tx);
+                }
+                Some(WorkerMsg::Jobs) => self.report_jobs(&shell_tx),
+                Some(WorkerMsg::Signal(SIGWINCH)) => self.window_resized(),
+                Some(WorkerMsg::Signal(SIGCHILD)) => {
+                    // SIGCHLDは、子プロセスの終了、停止時に親プロセスへ通知されるシグナル
+                    self.wait_child(&shell_tx); // 子プロセスの状態変化管理
+                }
+                _ => (), // 無視
+            }
+            self.check_timeouts();
+            self.run_scheduled();
+
+            // sourceで読み込んだコマンドが残っている場合は、続けて実行
+            while replace(&mut self.run_next, false) {
+                if let Some(line) = self.pending.pop_front() {
+                    self.run_line(&line, &shell_tx);
                 }
             }
         });
     }
 
+    /// timeoutコマンドで実行中のジョブがシグナルを送信する時刻と、
+    /// scheduleコマンドで予約したコマンドを実行する時刻のうち、最も早いものを返す
+    fn next_deadline(&self) -> Option<Instant> {
+        self.jobs
+            .values()
+            .filter_map(|job| job.timeout.as_ref())
+            .filter(|t| t.stage != TimeoutStage::Killed)
+            .map(|t| t.deadline)
+            .chain(self.scheduled.values().map(|s| s.deadline))
+            .min()
+    }
+
+    /// 実行時刻になった予約済みのコマンドを、バックグラウンドジョブとして実行する
+    ///
+    /// シェルへの入力の待ち受けとは非同期に実行されるため、$?は変更しない
+    fn run_scheduled(&mut self) {
+        let now = Instant::now();
+        let due: Vec<usize> = self
+            .scheduled
+            .iter()
+            .filter(|(_, s)| s.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in due {
+            let Some(scheduled) = self.scheduled.remove(&id) else {
+                continue;
+            };
+            eprintln!("{}", msg!(JobStarted, id, scheduled.line));
+            let status = self.status;
+            match parse_cmd(&scheduled.line) {
+                Ok(mut cmd) => {
+                    expand_args(&mut cmd, status.code());
+                    self.spawn_child(&scheduled.line, &cmd, &[], true, None);
+                }
+                Err(e) => eprintln!("ZeroSh: {e}"),
+            }
+            self.status = status;
+        }
+    }
+
+    /// 制限時間を超えたジョブにシグナルを送信する
+    ///
+    /// 1回目はSIGTERMを送信し、停止中のジョブも終了できるようにSIGCONTも送信する。
+    /// kill_afterが経過しても終了しない場合はSIGKILLを送信する。
+    /// ジョブの終了はwait_childで検知され、終了状態はTIMEOUT_STATUSとなる。
+    fn check_timeouts(&mut self) {
+        let now = Instant::now();
+        for (job_id, job) in self.jobs.iter_mut() {
+            let Some(t) = job.timeout.as_mut() else {
+                continue;
+            };
+            if t.stage == TimeoutStage::Killed || t.deadline > now {
+                continue;
+            }
+
+            let sigs: &[Signal] = if t.stage == TimeoutStage::Running {
+                eprintln!("{}", msg!(JobTimedOut, job_id, job.line));
+                t.stage = TimeoutStage::Terminating;
+                t.deadline = now + t.kill_after;
+                &[Signal::SIGTERM, Signal::SIGCONT]
+            } else {
+                eprintln!("{}", msg!(JobKilled, job_id, job.line));
+                t.stage = TimeoutStage::Killed;
+                &[Signal::SIGKILL]
+            };
+            for sig in sigs {
+                // すでに終了していて、まだwaitpidで回収していない場合は失敗する
+                match killpg(job.pgid, *sig) {
+                    Ok(()) | Err(nix::Error::ESRCH) => (),
+                    Err(e) => eprintln!("ZeroSh: {}", msg!(SignalFailed, sig, e)),
+                }
+            }
+        }
+    }
+
+    /// 1行のコマンドを実行
+    fn run_line(&mut self, line: &str, shell_tx: &SyncSender<ShellMsg>) {
+        self.line_count = self.line_count.wrapping_add(1);
+
+        // 末尾の&はバックグラウンド実行の指定
+        let (line, bg) = split_background(line);
+        // $(( 式 ))を計算結果に展開。式中の$?は直前のコマンドの終了コードとする
+        let status = self.status.code();
+        let lookup = |name: &str| match name {
+            "?" => Some(status.to_string()),
+            _ => env::var(name).ok(),
+        };
+        let line = &match arith::expand(line, &lookup) {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("ZeroSh: {e}");
+                self.status = CmdStatus::Exited(1);
+                self.resume(shell_tx);
+                return;
+            }
+        };
+
+        // プロセス置換のパイプを作成し、<(cmd)と>(cmd)を/dev/fd/Nに置き換える
+        // ジョブとして表示する行は置き換える前のものとする
+        let (expanded, substs) = match open_proc_substs(line) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("ZeroSh: {e}");
+                self.status = CmdStatus::Exited(2);
+                self.resume(shell_tx);
+                return;
+            }
+        };
+
+        match parse_cmd(&expanded) {
+            Ok(mut cmd) => {
+                expand_args(&mut cmd, status);
+                if !substs.is_empty() {
+                    if cmd.len() == 1 && BUILTINS.contains(&&*cmd[0].args[0]) {
+                        eprintln!("ZeroSh: {}", msg!(ProcSubstBuiltin));
+                        self.status = CmdStatus::Exited(2);
+                        self.resume(shell_tx);
+                        return;
+                    }
+                    // パイプはO_CLOEXECで作成しているため、execしても残るようにする
+                    for c in cmd.iter_mut() {
+                        let inherits = substs.iter().map(|s| Redirect {
+                            fd: s.fd,
+                            kind: RedirectKind::Inherit,
+                        });
+                        c.redirects.splice(0..0, inherits);
+                    }
+                }
+
+                // 組み込みコマンドを実行
+                // 組み込みコマンドとは、シェル内部のコマンドのこと
+                if self.build_in_cmd(line, &cmd, bg, shell_tx) {
+                    // 組み込みコマンドならworker_rxから取得
+                    return;
+                }
+
+                // 組み込みコマンドでない場合は、外部プログラムを実行
+                if !self.spawn_child(line, &cmd, &substs, bg, None) {
+                    // 子プロセス生成に失敗した場合、シェルからの入力を再開
+                    self.resume(shell_tx);
+                }
+            }
+            Err(e) => {
+                eprintln!("ZeroSh: {e}");
+                // コマンドのパースに失敗した場合は入力を再開するためmainスレッドに通知
+                self.resume(shell_tx);
+            }
+        }
+    }
+
+    /// コマンドの実行完了時に呼び出し、シェルからの入力を再開させる
+    ///
+    /// sourceで読み込んだ実行待ちのコマンドがある場合は入力を再開せず、
+    /// workerスレッドのループで次のコマンドを実行させる
+    fn resume(&mut self, shell_tx: &SyncSender<ShellMsg>) {
+        // プロンプトがリダイレクト先に表示されないように、入力の再開前に元に戻す
+        self.restore_fds();
+        if self.pending.is_empty() {
+            self.run_precmd();
+            send_shell_msg(shell_tx, ShellMsg::Continue(self.status, self.job_count()));
+        } else {
+            self.run_next = true;
+        }
+    }
+
     /// 組み込みコマンドの場合はtrueを返す
-    fn build_in_cmd(&mut self, cmd: &[(&str, Vec<&str>)], shell_tx: &SyncSender<ShellMsg>) -> bool {
+    ///
+    /// lineとbgは、ジョブを生成する組み込みコマンド(timeout)で利用する
+    fn build_in_cmd(
+        &mut self,
+        line: &str,
+        cmd: &[Cmd],
+        bg: bool,
+        shell_tx: &SyncSender<ShellMsg>,
+    ) -> bool {
         if cmd.len() > 1 {
-            return false; // 組み込みコマンドのパイプは非対応なのでエラー
+            return false; // パイプラインの中の組み込みコマンドはspawn_childで出力を生成して子プロセスから書き込む
+        }
+
+        // 組み込みコマンドのリダイレクトは、実行中のみシェル自身に適用する
+        let args = cmd[0].arg_strs();
+        let name = args[0];
+        if BUILTINS.contains(&name)
+            && !REDIRECT_SELF_BUILTINS.contains(&name)
+            && !cmd[0].redirects.is_empty()
+        {
+            if let Err(e) = self.redirect_builtin(&cmd[0].redirects) {
+                eprintln!("ZeroSh: {}", msg!(RedirectFailed, e));
+                self.status = CmdStatus::Exited(1);
+                self.resume(shell_tx);
+                return true;
+            }
         }
 
-        match cmd[0].0 {
-            "exit" => self.run_exit(&cmd[0].1, shell_tx),
-            "jobs" => self.run_jobs(shell_tx),
-            "fg" => self.run_fg(&cmd[0].1, shell_tx),
-            "cd" => self.run_cd(&cmd[0].1, shell_tx),
+        match name {
+            "exit" => self.run_exit(&args, shell_tx),
+            "jobs" => self.run_jobs(&args, shell_tx),
+            "jobstats" => self.run_jobstats(shell_tx),
+            "fg" => self.run_fg(&args, shell_tx),
+            "cd" => self.run_cd(&args, shell_tx),
+            "hash" => self.run_hash(&args, shell_tx),
+            "umask" => self.run_umask(&args, shell_tx),
+            "trap" => self.run_trap(&args, shell_tx),
+            "exec" => self.run_exec(&cmd[0], shell_tx),
+            "detach" => self.run_detach(&cmd[0], shell_tx),
+            "timeout" => self.run_timeout(line, &cmd[0], bg, shell_tx),
+            "schedule" => self.run_schedule(line, &args, shell_tx),
+            "source" | "." => self.run_source(&args, shell_tx),
+            "shopt" => self.run_shopt(&args, shell_tx),
+            "set" => self.run_set(&args, shell_tx),
+            "history" => self.run_history(&args, shell_tx),
+            "bind" => self.run_bind(&args, shell_tx),
+            "getopts" => self.run_getopts(&args, shell_tx),
+            "hook" => self.run_hook(&args, shell_tx),
+            _ if self.is_autocd(&cmd[0]) => self.run_cd(&["cd", name], shell_tx),
             _ => false,
         }
     }
 
-    /// eixtコマンドを実行
-    fn run_exit(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
-        // バックエンドで実行中のジョブがある場合は終了しない
-        if !self.jobs.is_empty() {
-            eprintln!("ジョブが実行中なので終了できません");
-            self.exit_val = 1; //　失敗
-            shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap(); // シェルを再開
-            return true;
+    /// 組み込みコマンドのリダイレクトを、元のファイルディスクリプタを退避してからシェル自身に適用する
+    ///
+    /// 退避したファイルディスクリプタはresumeで元に戻す。
+    /// 3以上のファイルディスクリプタはsignal_hookなどのシェルが利用しているものと衝突するため、
+    /// リダイレクトできるのは標準入出力と標準エラー出力のみとする
+    fn redirect_builtin(&mut self, redirects: &[Redirect]) -> Result<(), DynError> {
+        if let Some(r) = redirects.iter().find(|r| r.fd > libc::STDERR_FILENO) {
+            return Err(msg!(BuiltinRedirectFd, r.fd).into());
+        }
+
+        for r in redirects {
+            if self.saved_fds.iter().any(|(fd, _)| *fd == r.fd) {
+                continue;
+            }
+            // クローズされていた場合は、元に戻すときにクローズする
+            let saved = match syscall(|| fcntl(r.fd, FcntlArg::F_DUPFD_CLOEXEC(SAVED_FD_MIN))) {
+                Ok(saved) => Some(saved),
+                Err(nix::Error::EBADF) => None,
+                Err(e) => return Err(ShellError::Syscall("fcntl", e).into()),
+            };
+            self.saved_fds.push((r.fd, saved));
+        }
+        apply_redirects(redirects, self.options.noclobber)
+    }
+
+    /// redirect_builtinで退避したファイルディスクリプタを元に戻す
+    fn restore_fds(&mut self) {
+        if self.saved_fds.is_empty() {
+            return;
+        }
+        io::stdout().flush().ok();
+        for (fd, saved) in self.saved_fds.drain(..).rev() {
+            let result = match saved {
+                Some(saved) => {
+                    syscall(|| dup2(saved, fd)).and_then(|_| syscall(|| unistd::close(saved)))
+                }
+                None => syscall(|| unistd::close(fd)),
+            };
+            if let Err(e) = result {
+                eprintln!("ZeroSh: {}", msg!(RedirectRestoreFailed, fd, e));
+            }
         }
+    }
+
+    /// exitコマンドを実行
+    ///
+    /// ジョブが存在する場合はジョブの一覧とともに警告を表示して終了せず、
+    /// 直後に再度exitが実行された場合に終了する。
+    /// `exit -f`の場合は、すべてのジョブにSIGHUPとSIGTERMを送信してから終了する。
+    fn run_exit(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let (force, args) = match args.get(1) {
+            Some(&"-f") => (true, &args[2..]),
+            _ => (false, &args[1..]),
+        };
 
         // 終了コードを取得
-        let exit_val = if let Some(s) = args.get(1) {
+        let exit_val = if let Some(s) = args.first() {
             if let Ok(n) = (*s).parse::<i32>() {
                 n
             } else {
                 // 終了コードが整数ではない
-                eprintln!("{s}は不正な引数です");
-                self.exit_val = 1; // 失敗
-                shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap(); // シェルを再開
+                eprintln!("{}", msg!(InvalidArgument, s));
+                self.status = CmdStatus::Exited(1); // 失敗
+                self.resume(shell_tx); // シェルを再開
                 return true;
             }
         } else {
-            self.exit_val
+            self.status.code()
         };
 
-        shell_tx.send(ShellMsg::Quit(self.exit_val)).unwrap(); // シェルを終了
+        // バックグラウンドで実行中のジョブがある場合は、1回目は警告のみ
+        if !self.jobs.is_empty()
+            && !force
+            && self.exit_warned.map(|n| n.wrapping_add(1)) != Some(self.line_count)
+        {
+            if self.job_count().stopped > 0 {
+                eprintln!("{}", msg!(StoppedJobs));
+            } else {
+                eprintln!("{}", msg!(RunningJobs));
+            }
+            for line in self.job_list() {
+                eprintln!("{line}");
+            }
+            eprintln!("{}", msg!(ExitAgain));
+            eprintln!("{}", msg!(ExitForce));
+            self.exit_warned = Some(self.line_count);
+            self.status = CmdStatus::Exited(1); //　失敗
+            self.resume(shell_tx); // シェルを再開
+            return true;
+        }
+
+        self.shutdown(force);
+        send_shell_msg(shell_tx, ShellMsg::Quit(exit_val)); // シェルを終了
         true
     }
 
-    /// fgコマンドを実行
-    fn run_fg(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
-        self.exit_val = 1; // とりあえず失敗に設定
-
-        // 引数をチェック
-        if args.len() < 2 {
-            eprintln!("usage: fg 数字");
-            shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap();
-            return true;
+    /// Ctrl+dによる終了の前に、ジョブの数と一覧をmainスレッドに返す
+    ///
+    /// ジョブが存在する場合はmainスレッドが警告を表示するため、exitコマンドで警告した場合と同様に
+    /// 終了状態を失敗とし、直後のexitコマンドでは警告せずに終了するようにする
+    fn report_jobs(&mut self, shell_tx: &SyncSender<ShellMsg>) {
+        if !self.jobs.is_empty() {
+            self.exit_warned = Some(self.line_count);
+            self.status = CmdStatus::Exited(1); // 失敗
         }
+        send_shell_msg(shell_tx, ShellMsg::Jobs(self.job_count(), self.job_list()));
+    }
 
-        // ジョブIDを取得
-        if let Ok(n) = args[1].parse::<usize>() {
-            if let Some((pgid, cmd)) = self.jobs.get(&n) {
-                eprintln!("{n} 再開\t{cmd}");
-
-                // フォアグラウンドプロセスに設定
-                self.fg = Some(*pgid);
-                // tcsetpgrpはファイルディスクリプタとプロセスグループIDを受け取り、
-                // そのファイルディスクリプタに関連付けられたセッションの
-                // フォアグラウンドプロセスグループを指定されたプロセスグループとする
-                tcsetpgrp(libc::STDIN_FILENO, *pgid).unwrap();
-
-                // ジョブの実行を再開
-                // 引数で指定したプロセスグループに対してSIGCONTシグナルを送信する
-                // 停止中のプロセスがSIGCONTを受信すると、実行が再開される
-                // フォアグラウンドプロセスを変更した場合は、シェルの読み込みは再開しない
-                killpg(*pgid, Signal::SIGCONT).unwrap();
-                return true;
+    /// シェルの終了前に、残っているジョブの後始末を行う
+    ///
+    /// - `exit -f`の場合、またはhuponexitオプションが有効な場合はジョブを終了させる
+    /// - それ以外の場合は、停止中のジョブにのみSIGHUPを送信し、実行中のジョブは残したまま終了する
+    ///
+    /// 残されたジョブはシェルの終了後、initプロセス(またはサブリーパ)の子プロセスとなって実行を続ける。
+    /// 停止中のジョブは孤立したプロセスグループとなった時点でカーネルからもSIGHUPとSIGCONTが送信されるが、
+    /// プロセスグループ内に別のセッションの親を持つプロセスがあると送信されないため、シェルから送信しておく。
+    fn shutdown(&self, force: bool) {
+        if force {
+            self.kill_jobs();
+        } else if self.options.huponexit {
+            for (job_id, job) in self.jobs.iter() {
+                self.hangup_job(*job_id, job);
+            }
+        } else {
+            for (job_id, job) in self.jobs.iter() {
+                if self.is_group_stop(job.pgid) == Some(true) {
+                    self.hangup_job(*job_id, job);
+                } else {
+                    eprintln!("{}", msg!(JobContinues, job_id, job.line));
+                }
             }
         }
-
-        // 失敗
-        eprintln!("{}というジョブは見つかりませんでした。", args[1]);
-        shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap(); // シェルを再開
-        true
     }
 
-    /// jobsコマンドを実行
+    /// ジョブにSIGHUPを送信する
     ///
-    /// 現在シェルが管理して実行しているジョブ一覧を表示する
-    fn run_jobs(&mut self, shell_tx: &SyncSender<ShellMsg>) -> bool {
-        true // TODO
+    /// 停止中のジョブはシグナルを処理できないため、SIGCONTも送信する
+    fn hangup_job(&self, job_id: usize, job: &Job) {
+        eprintln!("{}", msg!(JobHangup, job_id, job.line));
+        for sig in [Signal::SIGHUP, Signal::SIGCONT] {
+            if let Err(e) = killpg(job.pgid, sig) {
+                eprintln!("ZeroSh: {}", msg!(SignalFailed, sig, e));
+                break;
+            }
+        }
     }
 
-    /// cdコマンドを実行
-    fn run_cd(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
-        true // TODO
+    /// すべてのジョブにSIGHUPとSIGTERMを送信して終了させる
+    ///
+    /// 停止中のジョブはシグナルを処理できないため、最後にSIGCONTを送信して再開させる
+    fn kill_jobs(&self) {
+        for (job_id, job) in self.jobs.iter() {
+            eprintln!("{}", msg!(JobTerminating, job_id, job.line));
+            for sig in [Signal::SIGHUP, Signal::SIGTERM, Signal::SIGCONT] {
+                if let Err(e) = killpg(job.pgid, sig) {
+                    eprintln!("ZeroSh: {}", msg!(SignalFailed, sig, e));
+                    break;
+                }
+            }
+        }
+    }
+
+    /// fgコマンドを実行
+    ///
+    /// 引数にはジョブID、またはジョブ指定(%n, %%, %+, %-, %文字列)を指定する。
+    /// 引数を省略した場合はカレントジョブを対象とする。
+    fn run_fg(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        self.status = CmdStatus::Exited(1); // とりあえず失敗に設定
+
+        // 引数をチェック
+        if args.len() > 2 {
+            eprintln!("{}", msg!(UsageFg));
+            self.resume(shell_tx);
+            return true;
+        }
+
+        // ジョブIDを取得
+        let spec = args.get(1).copied().unwrap_or("%%");
+        if let Err(e) = self.fg_job(spec) {
+            // 失敗した場合は、ジョブがフォアグラウンドになっていてもシェルに戻す
+            eprintln!("fg: {e}");
+            self.set_shell_fg(shell_tx);
+        }
+        true
+    }
+
+    /// ジョブをフォアグラウンドにして再開する
+    fn fg_job(&mut self, spec: &str) -> Result<(), ShellError> {
+        let n = self.parse_job_spec(spec)?;
+        let job = &self.jobs[&n];
+        let pgid = job.pgid;
+        eprintln!("{}", msg!(JobResumed, n, job.line));
+        self.set_current_job(n);
+
+        // フォアグラウンドプロセスに設定
+        self.fg = Some(pgid);
+        // tcsetpgrpはファイルディスクリプタとプロセスグループIDを受け取り、
+        // そのファイルディスクリプタに関連付けられたセッションの
+        // フォアグラウンドプロセスグループを指定されたプロセスグループとする
+        if self.interactive {
+            tcsetpgrp(libc::STDIN_FILENO, pgid).map_err(ShellError::syscall("tcsetpgrp"))?;
+        }
+
+        // 停止中に端末の大きさが変わった場合は、カーネルと同様にSIGWINCHで通知する
+        // 停止中のプロセスへのシグナルは、SIGCONTで再開した後に配送される
+        if replace(&mut self.jobs.get_mut(&n).unwrap().winch, false) {
+            killpg(pgid, Signal::SIGWINCH).map_err(ShellError::syscall("killpg"))?;
+        }
+
+        // ジョブの実行を再開
+        // 引数で指定したプロセスグループに対してSIGCONTシグナルを送信する
+        // 停止中のプロセスがSIGCONTを受信すると、実行が再開される
+        // フォアグラウンドプロセスを変更した場合は、シェルの読み込みは再開しない
+        // ジョブの終了直後で、まだwaitpidで回収していない場合はここで失敗する
+        killpg(pgid, Signal::SIGCONT).map_err(ShellError::syscall("killpg"))?;
+        Ok(())
+    }
+
+    /// 端末の大きさが変わった場合の処理
+    ///
+    /// カーネルはSIGWINCHを端末のフォアグラウンドプロセスグループにのみ送信するため、
+    /// 停止中やバックグラウンドのジョブは大きさの変更を知ることができない。
+    /// そこで、それらのジョブには印を付けておき、fgでフォアグラウンドにする際にSIGWINCHを送信する。
+    /// シェルの入力行の再描画は、rustylineが自身のSIGWINCHのハンドラで行う
+    fn window_resized(&mut self) {
+        for job in self.jobs.values_mut() {
+            if Some(job.pgid) != self.fg {
+                job.winch = true;
+            }
+        }
+    }
+
+    /// ジョブ指定を解釈し、ジョブIDを返す
+    ///
+    /// - 数字、%数字 : 指定したジョブIDのジョブ
+    /// - %%, %+, %  : カレントジョブ
+    /// - %-         : 直前のジョブ
+    /// - %文字列    : コマンドが指定した文字列で始まるジョブ。複数ある場合はエラー
+    fn parse_job_spec(&self, spec: &str) -> Result<usize, ShellError> {
+        let not_found = || ShellError::NoSuchJob(spec.to_string());
+
+        let job_id = match spec.strip_prefix('%') {
+            None => spec.parse::<usize>().map_err(|_| not_found())?,
+            Some("" | "%" | "+") => self.current_job.ok_or_else(not_found)?,
+            Some("-") => self.previous_job.ok_or_else(not_found)?,
+            Some(n) if n.bytes().all(|c| c.is_ascii_digit()) => {
+                n.parse::<usize>().map_err(|_| not_found())?
+            }
+            Some(prefix) => {
+                let mut it = self
+                    .jobs
+                    .iter()
+                    .filter(|(_, job)| job.line.starts_with(prefix));
+                let (job_id, _) = it.next().ok_or_else(not_found)?;
+                if it.next().is_some() {
+                    return Err(ShellError::AmbiguousJob(spec.to_string()));
+                }
+                *job_id
+            }
+        };
+
+        if self.jobs.contains_key(&job_id) {
+            Ok(job_id)
+        } else {
+            Err(not_found())
+        }
+    }
+
+    /// カレントジョブを設定し、それまでのカレントジョブを直前のジョブとする
+    fn set_current_job(&mut self, job_id: usize) {
+        if self.current_job != Some(job_id) {
+            self.previous_job = self.current_job;
+            self.current_job = Some(job_id);
+        }
+    }
+
+    /// jobsコマンドを実行
+    ///
+    /// - jobs    : 現在シェルが管理して実行しているジョブ一覧を表示
+    /// - jobs -l : プロセスグループID、経過時間、CPU時間、最大RSSも表示
+    /// - jobs -s : scheduleコマンドで予約したコマンドの一覧を表示
+    ///
+    /// ジョブIDの後の+はカレントジョブを、-は直前のジョブを示す
+    fn run_jobs(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let code = self.write_jobs(args, &mut io::stdout()).unwrap_or(1);
+        self.status = CmdStatus::Exited(code);
+        self.resume(shell_tx);
+        true
+    }
+
+    /// jobsコマンドの出力をoutに書き込み、終了コードを返す
+    fn write_jobs(&self, args: &[&str], out: &mut dyn Write) -> io::Result<i32> {
+        let long = match args {
+            [_] => false,
+            [_, "-l"] => true,
+            [_, "-s"] => {
+                // 予約したコマンドを、実行までの残り時間とともに表示
+                let now = Instant::now();
+                for (id, s) in self.scheduled.iter() {
+                    let rest = s.deadline.saturating_duration_since(now);
+                    writeln!(out, "{}", msg!(ScheduledIn, id, rest.as_secs(), s.line))?;
+                }
+                return Ok(0);
+            }
+            _ => {
+                eprintln!("{}", msg!(UsageJobs));
+                return Ok(2);
+            }
+        };
+
+        for (job_id, job) in self.jobs.iter() {
+            let mark = if self.current_job == Some(*job_id) {
+                '+'
+            } else if self.previous_job == Some(*job_id) {
+                '-'
+            } else {
+                ' '
+            };
+            let state = self.job_state(job.pgid);
+            if long {
+                writeln!(out, "[{job_id}]{mark} {} {state}\t{}", job.pgid, job.line)?;
+                writeln!(out, "    {}", job.stats())?;
+            } else {
+                writeln!(out, "[{job_id}]{mark} {state}\t{}", job.line)?;
+            }
+        }
+        Ok(0)
+    }
+
+    /// jobstatsコマンドを実行
+    ///
+    /// 最近終了したJOBSTATS_MAX個までのジョブと、実行中のジョブについて、
+    /// 経過時間、CPU時間、最大RSSを表示する
+    fn run_jobstats(&mut self, shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let code = self.write_jobstats(&mut io::stdout()).map_or(1, |()| 0);
+        self.status = CmdStatus::Exited(code);
+        self.resume(shell_tx);
+        true
+    }
+
+    /// jobstatsコマンドの出力をoutに書き込む
+    fn write_jobstats(&self, out: &mut dyn Write) -> io::Result<()> {
+        for (job_id, line, status, stats) in self.finished.iter() {
+            writeln!(out, "{}", msg!(JobDoneStatus, job_id, status.code(), line))?;
+            writeln!(out, "    {stats}")?;
+        }
+        for (job_id, job) in self.jobs.iter() {
+            let state = self.job_state(job.pgid);
+            writeln!(out, "[{job_id}] {state}\t{}", job.line)?;
+            writeln!(out, "    {}", job.stats())?;
+        }
+        Ok(())
+    }
+
+    /// cdコマンドを実行
+    ///
+    /// 引数を省略した場合はホームディレクトリに、`cd -`の場合は直前のディレクトリに移動する
+    fn run_cd(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let dst = match args.get(1) {
+            None => dirs::home_dir(),
+            Some(&"-") => env::var_os("OLDPWD").map(PathBuf::from),
+            Some(path) => Some(PathBuf::from(path)),
+        };
+
+        let code = match dst {
+            Some(dst) => {
+                let old = env::current_dir().ok();
+                match chdir(&dst) {
+                    Ok(()) => {
+                        // 子プロセスに引き継がれるPWDとOLDPWDを更新
+                        if let Some(old) = old {
+                            env::set_var("OLDPWD", old);
+                        }
+                        if let Ok(cwd) = env::current_dir() {
+                            env::set_var("PWD", cwd);
+                        }
+                        0
+                    }
+                    Err(e) => {
+                        eprintln!("cd: {}: {e}", dst.display());
+                        1
+                    }
+                }
+            }
+            None => {
+                eprintln!("cd: {}", msg!(CdNoDestination));
+                1
+            }
+        };
+        self.status = CmdStatus::Exited(code);
+
+        self.resume(shell_tx);
+        true
+    }
+
+    /// sourceコマンドを実行
+    ///
+    /// スクリプトファイルの各行を、子プロセスではなくこのworkerスレッドで実行する。
+    /// そのため、cdなどによるシェルの状態の変更はスクリプトの終了後も維持される。
+    /// 読み込んだ行は実行待ちのコマンドの先頭に追加されるため、
+    /// スクリプト内でsourceを呼び出した場合も記述順に実行される。
+    fn run_source(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let Some(path) = args.get(1) else {
+            eprintln!("{}", msg!(UsageSource));
+            self.status = CmdStatus::Exited(2);
+            self.resume(shell_tx);
+            return true;
+        };
+
+        match read_script(path) {
+            Ok(lines) => {
+                for line in lines.into_iter().rev() {
+                    self.pending.push_front(line);
+                }
+                self.status = CmdStatus::Exited(0);
+            }
+            Err(e) => {
+                eprintln!("source: {path}: {e}");
+                self.status = CmdStatus::Exited(1);
+            }
+        }
+
+        self.resume(shell_tx);
+        true
+    }
+
+    /// getoptsコマンドを実行
+    ///
+    /// getopts オプション文字列 変数名 引数...
+    ///
+    /// 引数から次のオプションを取り出して変数名の環境変数に設定し、
+    /// オプションの引数をOPTARGに、次に解析する引数の番号をOPTINDに設定する。
+    /// ZeroShには位置パラメータがないため、解析する引数は常に指定する。
+    /// 前回と異なる引数を指定した場合は、最初の引数から解析する。
+    /// オプションの終わりに達した場合は、変数に?を設定して終了コード1を返す
+    ///
+    /// オプション文字列が:で始まる場合はエラーを表示せず、
+    /// 不正なオプションなら?を、引数がない場合は:を変数に設定して、OPTARGにそのオプションを設定する
+    fn run_getopts(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let [_, optstring, name, rest @ ..] = args else {
+            eprintln!("{}", msg!(UsageGetopts));
+            self.status = CmdStatus::Exited(2);
+            self.resume(shell_tx);
+            return true;
+        };
+
+        let optind = env::var("OPTIND")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+        let (opt, optind) = self.getopts.next(optstring, rest, optind);
+        let silent = optstring.starts_with(':');
+        let end = opt == Opt::End;
+        let (value, optarg) = match opt {
+            Opt::Found(c, optarg) => (c, optarg),
+            Opt::Invalid(c) if silent => ('?', Some(c.to_string())),
+            Opt::Invalid(c) => {
+                eprintln!("getopts: {}", msg!(GetoptsIllegal, c));
+                ('?', None)
+            }
+            Opt::MissingArg(c) if silent => (':', Some(c.to_string())),
+            Opt::MissingArg(c) => {
+                eprintln!("getopts: {}", msg!(GetoptsNeedsArg, c));
+                ('?', None)
+            }
+            Opt::End => ('?', None),
+        };
+
+        env::set_var(name, value.to_string());
+        match optarg {
+            Some(optarg) => env::set_var("OPTARG", optarg),
+            None => env::remove_var("OPTARG"),
+        }
+        env::set_var("OPTIND", optind.to_string());
+
+        self.status = CmdStatus::Exited(if end { 1 } else { 0 });
+        self.resume(shell_tx);
+        true
+    }
+
+    /// hookコマンドを実行
+    ///
+    /// - hook                          : 登録したフックの一覧を表示
+    /// - hook preexec|precmd コマンド...: フックを追加
+    /// - hook -r preexec|precmd        : その種類のフックをすべて削除
+    ///
+    /// 一覧はそのままrcファイルに書ける形式で表示する
+    fn run_hook(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let (remove, kind, cmd) = match args {
+            [_] => {
+                for (hook, cmd) in self.hooks.iter() {
+                    println!("hook {} {}", hook.name(), cmd.join(" "));
+                }
+                self.status = CmdStatus::Exited(0);
+                self.resume(shell_tx);
+                return true;
+            }
+            [_, "-r", kind] => (true, kind, &[][..]),
+            [_, kind, cmd @ ..] if !cmd.is_empty() && !kind.starts_with('-') => (false, kind, cmd),
+            _ => {
+                eprintln!("{}", msg!(UsageHook));
+                self.status = CmdStatus::Exited(2);
+                self.resume(shell_tx);
+                return true;
+            }
+        };
+        let Some(hook) = Hook::parse(kind) else {
+            eprintln!("hook: {}", msg!(HookKind, kind));
+            self.status = CmdStatus::Exited(2);
+            self.resume(shell_tx);
+            return true;
+        };
+
+        if remove {
+            self.hooks.retain(|(h, _)| *h != hook);
+        } else {
+            let cmd = cmd.iter().map(|s| s.to_string()).collect();
+            self.hooks.push((hook, cmd));
+        }
+        self.status = CmdStatus::Exited(0);
+        self.resume(shell_tx);
+        true
+    }
+
+    /// 入力された行lineを実行する前にpreexecフックを実行し、precmdフックに渡す行と開始時刻を記録する
+    ///
+    /// フックには行を最後の引数として、また環境変数ZEROSH_CMDとして渡す。
+    /// 端末を利用できるように、フックはシェルのプロセスグループで終了まで待って実行する
+    fn run_preexec(&mut self, line: &str) {
+        for (_, cmd) in self.hooks.iter().filter(|(h, _)| *h == Hook::Preexec) {
+            let result = self
+                .hook_command(cmd)
+                .arg(line)
+                .env("ZEROSH_CMD", line)
+                .status();
+            if let Err(e) = result {
+                eprintln!("ZeroSh: {}", msg!(HookFailed, e));
+            }
+        }
+        self.last_cmd = Some((line.to_string(), Instant::now()));
+    }
+
+    /// プロンプトを表示する前にprecmdフックを実行する
+    ///
+    /// 直前に入力された行、終了コード、実行時間(ミリ秒)を、それぞれ環境変数
+    /// ZEROSH_CMD、ZEROSH_CMD_STATUS、ZEROSH_CMD_DURATION_MSとして渡す。
+    /// フックの終了コードは$?に影響しない
+    fn run_precmd(&mut self) {
+        let Some((line, start)) = self.last_cmd.take() else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        for (_, cmd) in self.hooks.iter().filter(|(h, _)| *h == Hook::Precmd) {
+            let result = self
+                .hook_command(cmd)
+                .env("ZEROSH_CMD", &line)
+                .env("ZEROSH_CMD_STATUS", self.status.code().to_string())
+                .env("ZEROSH_CMD_DURATION_MS", elapsed.as_millis().to_string())
+                .status();
+            if let Err(e) = result {
+                eprintln!("ZeroSh: {}", msg!(HookFailed, e));
+            }
+        }
+    }
+
+    /// フックcmdを実行するCommandを作成する
+    ///
+    /// シェルが無視しているSIGTTOUなどは、forkするジョブと同様にexec前にデフォルトに戻す
+    fn hook_command(&self, cmd: &[String]) -> Command {
+        let mut command = Command::new(&cmd[0]);
+        command.args(&cmd[1..]);
+        let ignored = self.ignored_signals.clone();
+        // reset_signalsはasync-signal-safeな関数のみを呼び出す
+        unsafe {
+            command.pre_exec(move || {
+                reset_signals(&ignored);
+                Ok(())
+            });
+        }
+        command
+    }
+
+    /// autocdオプションにより、cdとして実行するコマンドなら真
+    ///
+    /// 引数のないコマンドで、その名前が実行可能ファイルではなくディレクトリを指す場合にcdとして実行する
+    fn is_autocd(&mut self, cmd: &Cmd) -> bool {
+        let [name] = &cmd.args[..] else {
+            return false;
+        };
+        let name: &str = name;
+        self.options.autocd
+            && Path::new(name).is_dir()
+            && (name.contains('/') || self.lookup_cmd(name).is_none())
+    }
+
+    /// shoptコマンドを実行
+    ///
+    /// - shopt                    : すべてのオプションと値を表示
+    /// - shopt name...            : 指定したオプションの値を表示
+    /// - shopt -s [--save] name...: オプションを有効にする
+    /// - shopt -u [--save] name...: オプションを無効にする
+    ///
+    /// --saveを指定した場合は、次回の起動時にも同じ値となるようにrcファイルに書き込む
+    fn run_shopt(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let code = match args.get(1..).unwrap_or_default() {
+            [] => {
+                self.print_options();
+                0
+            }
+            [flag @ ("-s" | "-u"), rest @ ..] => {
+                let (save, names) = match rest {
+                    ["--save", names @ ..] => (true, names),
+                    names => (false, names),
+                };
+                if names.is_empty() {
+                    eprintln!("{}", msg!(UsageShopt));
+                    2
+                } else {
+                    self.set_options("shopt", names, *flag == "-s", save)
+                }
+            }
+            names => {
+                let mut code = 0;
+                for name in names {
+                    match self.options.list().iter().find(|(n, _)| n == name) {
+                        Some((name, on)) => println!("{name:<15}\t{}", on_off(*on)),
+                        None => {
+                            eprintln!("shopt: {}", msg!(UnknownOption, name));
+                            code = 1;
+                        }
+                    }
+                }
+                code
+            }
+        };
+        self.status = CmdStatus::Exited(code);
+        self.resume(shell_tx);
+        true
+    }
+
+    /// setコマンドを実行
+    ///
+    /// - set -o      : すべてのオプションと値を表示
+    /// - set -o name : オプションを有効にする
+    /// - set +o name : オプションを無効にする
+    /// - set -C      : set -o noclobberと同じ
+    /// - set +C      : set +o noclobberと同じ
+    /// - set lang    : メッセージの言語を表示
+    /// - set lang ja : メッセージの言語を変更する。jaかenを指定する
+    ///
+    /// langを除き、shoptと同じオプションを扱う
+    fn run_set(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let code = match args.get(1..).unwrap_or_default() {
+            ["-o"] => {
+                self.print_options();
+                0
+            }
+            [flag @ ("-o" | "+o"), names @ ..] => {
+                self.set_options("set", names, *flag == "-o", false)
+            }
+            [flag @ ("-C" | "+C")] => self.set_options("set", &["noclobber"], *flag == "-C", false),
+            ["lang"] => {
+                println!("{}", msg::lang());
+                0
+            }
+            ["lang", name] => match Lang::parse(name) {
+                Some(lang) => {
+                    msg::set_lang(lang);
+                    0
+                }
+                None => {
+                    eprintln!("set: {}", msg!(UnknownLang, name));
+                    1
+                }
+            },
+            _ => {
+                eprintln!("{}", msg!(UsageSet));
+                2
+            }
+        };
+        self.status = CmdStatus::Exited(code);
+        self.resume(shell_tx);
+        true
+    }
+
+    /// すべてのオプションと値を表示
+    fn print_options(&self) {
+        for (name, on) in self.options.list() {
+            println!("{name:<15}\t{}", on_off(on));
+        }
+    }
+
+    /// オプションを設定し、終了コードを返す
+    ///
+    /// 不明なオプションは無視してエラーを表示する。saveが真なら設定したオプションをrcファイルに書き込む
+    fn set_options(&mut self, cmd: &str, names: &[&str], on: bool, save: bool) -> i32 {
+        let mut code = 0;
+        let mut saved = Vec::new();
+        for name in names {
+            if self.options.set(name, on) {
+                saved.push(*name);
+            } else {
+                eprintln!("{cmd}: {}", msg!(UnknownOption, name));
+                code = 1;
+            }
+        }
+
+        // 行編集のオプションはmainスレッドのEditorに反映する
+        self.bindings
+            .lock()
+            .unwrap()
+            .set_modes(editor::modes(self.options.vi, self.options.menucomplete));
+        // 置換した行の確認はmainスレッドで行う
+        self.history
+            .lock()
+            .unwrap()
+            .set_confirm(self.options.histconfirm);
+
+        if save && !saved.is_empty() {
+            let Some(rc) = rc_path() else {
+                eprintln!("{cmd}: {}", msg!(NoHomeToSave));
+                return 1;
+            };
+            if let Err(e) = save_options(&rc, &saved, on) {
+                eprintln!("{cmd}: {}: {e}", rc.display());
+                return 1;
+            }
+        }
+        code
+    }
+
+    /// hashコマンドを実行
+    ///
+    /// - hash        : キャッシュされているコマンドとパスの一覧を表示
+    /// - hash -r     : キャッシュを破棄
+    /// - hash cmd... : cmdを$PATHから検索してキャッシュに登録
+    fn run_hash(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        self.status = CmdStatus::Exited(0);
+        match args.get(1) {
+            None => {
+                if self.path_cache.is_empty() {
+                    println!("hash: {}", msg!(HashEmpty));
+                }
+                for (name, path) in self.path_cache.iter() {
+                    println!("{name}\t{}", path.display());
+                }
+            }
+            Some(&"-r") => self.path_cache.clear(),
+            Some(_) => {
+                for name in args[1..].iter() {
+                    // 既存のエントリは捨てて必ず$PATHを再検索する
+                    self.path_cache.remove(*name);
+                    if self.lookup_cmd(name).is_none() {
+                        eprintln!("hash: {}", msg!(NotFound, name));
+                        self.status = CmdStatus::Exited(1);
+                    }
+                }
+            }
+        }
+        self.resume(shell_tx);
+        true
+    }
+
+    /// historyコマンドを実行
+    ///
+    /// - history    : ヒストリを番号付きで表示
+    /// - history 20 : 最後の20行を表示
+    /// - history -c : ヒストリを消去
+    ///
+    /// 番号は!nで参照する番号と同じ
+    fn run_history(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let code = if args.get(1) == Some(&"-c") {
+            self.history.lock().unwrap().clear();
+            0
+        } else {
+            self.write_history(args, &mut io::stdout()).unwrap_or(1)
+        };
+        self.status = CmdStatus::Exited(code);
+        self.resume(shell_tx);
+        true
+    }
+
+    /// historyコマンドの一覧をoutに書き込み、終了コードを返す
+    fn write_history(&self, args: &[&str], out: &mut dyn Write) -> io::Result<i32> {
+        let n = match args.get(1) {
+            None => usize::MAX,
+            Some(n) => match n.parse() {
+                Ok(n) => n,
+                Err(_) => {
+                    eprintln!("history: {}", msg!(HistoryCount, n));
+                    return Ok(2);
+                }
+            },
+        };
+        for (i, line) in self.history.lock().unwrap().last(n) {
+            writeln!(out, "{i:5}  {line}")?;
+        }
+        Ok(0)
+    }
+
+    /// bindコマンドを実行
+    ///
+    /// - bind                           : キー割り当ての一覧を表示
+    /// - bind -l                        : 割り当てられる機能の一覧を表示
+    /// - bind [--save] キー 機能        : キーに機能を割り当てる
+    /// - bind [--save] -s キー 文字列... : キーに文字列を挿入するマクロを割り当てる
+    /// - bind [--save] -r キー          : キーの割り当てを解除
+    ///
+    /// 一覧はそのままrcファイルに書ける形式で表示する。
+    /// --saveを指定した場合は、次回の起動時にも同じ割り当てとなるようにrcファイルに書き込む
+    fn run_bind(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let (save, rest) = match args.get(1..).unwrap_or_default() {
+            ["--save", rest @ ..] => (true, rest),
+            rest => (false, rest),
+        };
+        let code = match rest {
+            [] if !save => {
+                for (keys, action) in self.bindings.lock().unwrap().iter() {
+                    println!("{}", bind_command(keys, action));
+                }
+                0
+            }
+            ["-l"] if !save => {
+                for (name, description) in FUNCTIONS {
+                    println!("{name:<24}\t{}", msg::text(*description));
+                }
+                0
+            }
+            ["-r", keys] => self.set_binding(keys, None, save),
+            ["-s", keys, text @ ..] if !text.is_empty() => {
+                self.set_binding(keys, Some(Action::Macro(text.join(" "))), save)
+            }
+            [keys, name] if !keys.starts_with('-') => match Action::function(name) {
+                Some(action) => self.set_binding(keys, Some(action), save),
+                None => {
+                    eprintln!("bind: {}", msg!(BindUnknownFunction, name));
+                    1
+                }
+            },
+            _ => {
+                eprintln!("{}", msg!(UsageBind));
+                2
+            }
+        };
+        self.status = CmdStatus::Exited(code);
+        self.resume(shell_tx);
+        true
+    }
+
+    /// キー列keysに動作actionを割り当て、終了コードを返す。actionがNoneなら割り当てを解除する
+    ///
+    /// saveが真なら、割り当てをrcファイルに書き込む
+    fn set_binding(&mut self, keys: &str, action: Option<Action>, save: bool) -> i32 {
+        let result = match &action {
+            Some(action) => self.bindings.lock().unwrap().bind(keys, action.clone()),
+            None => self.bindings.lock().unwrap().unbind(keys),
+        };
+        let keys = match result {
+            Ok(keys) => keys,
+            Err(e) => {
+                eprintln!("bind: {e}");
+                return 1;
+            }
+        };
+
+        if save {
+            let Some(rc) = rc_path() else {
+                eprintln!("bind: {}", msg!(NoHomeToSave));
+                return 1;
+            };
+            if let Err(e) = save_binding(&rc, &keys, action.as_ref()) {
+                eprintln!("bind: {}: {e}", rc.display());
+                return 1;
+            }
+        }
+        0
+    }
+
+    /// umaskコマンドを実行
+    ///
+    /// - umask      : ファイル作成マスクを8進数で表示
+    /// - umask -S   : ファイル作成マスクを記号形式(u=rwx,g=rx,o=rx)で表示
+    /// - umask 022  : ファイル作成マスクを8進数で設定
+    ///
+    /// ファイル作成マスクはプロセスの属性であり、fork時に子プロセスに引き継がれるため、
+    /// 以降に実行するコマンドやリダイレクトで作成されるファイルにも適用される
+    fn run_umask(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        self.status = CmdStatus::Exited(0);
+        match args.get(1..) {
+            Some([]) | None => println!("{:04o}", get_umask().bits()),
+            Some(["-S"]) => println!("{}", umask_symbolic(get_umask())),
+            Some([mask]) => match u32::from_str_radix(mask, 8) {
+                Ok(bits) if bits <= 0o777 => {
+                    umask(Mode::from_bits_truncate(bits as libc::mode_t));
+                }
+                _ => {
+                    eprintln!("umask: {}", msg!(UmaskRange, mask));
+                    self.status = CmdStatus::Exited(1);
+                }
+            },
+            Some(_) => {
+                eprintln!("{}", msg!(UsageUmask));
+                self.status = CmdStatus::Exited(2);
+            }
+        }
+        self.resume(shell_tx);
+        true
+    }
+
+    /// trapコマンドを実行
+    ///
+    /// - trap            : 子プロセスが無視するシグナルの一覧を表示
+    /// - trap '' SIG...  : 子プロセスでSIGを無視する
+    /// - trap - SIG...   : 子プロセスでSIGをデフォルトの処理に戻す
+    ///
+    /// シグナルはINT、SIGINT、2のように指定する。引用符は解釈しないため、''と""をそのまま無視の指定とみなす。
+    /// シェル自身のシグナルの処理は変更せず、
+    /// 以降に起動する子プロセスに引き継ぐ無視のみを設定する。ハンドラとなるコマンドの指定はサポートしない
+    fn run_trap(&mut self, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        self.status = CmdStatus::Exited(0);
+        match args.get(1..) {
+            Some([]) | None => {
+                for sig in self.ignored_signals.iter() {
+                    println!("trap -- '' {}", sig.as_str());
+                }
+            }
+            Some([action @ ("''" | "\"\"" | "-"), sigs @ ..]) if !sigs.is_empty() => {
+                for name in sigs {
+                    let sig = match parse_signal(name) {
+                        Some(Signal::SIGKILL | Signal::SIGSTOP) => {
+                            eprintln!("trap: {}", msg!(TrapUncatchable, name));
+                            self.status = CmdStatus::Exited(1);
+                            continue;
+                        }
+                        Some(sig) => sig,
+                        None => {
+                            eprintln!("trap: {}", msg!(UnknownSignal, name));
+                            self.status = CmdStatus::Exited(1);
+                            continue;
+                        }
+                    };
+                    self.ignored_signals.retain(|s| *s != sig);
+                    if *action != "-" {
+                        self.ignored_signals.push(sig);
+                    }
+                }
+                self.ignored_signals.sort_by_key(|s| *s as i32);
+            }
+            Some(_) => {
+                eprintln!("{}", msg!(UsageTrap));
+                self.status = CmdStatus::Exited(2);
+            }
+        }
+        self.resume(shell_tx);
+        true
+    }
+
+    /// execコマンドを実行
+    ///
+    /// - exec cmd args... : forkせずにシェル自身をcmdに置き換える
+    /// - exec > file      : コマンドを指定しない場合はリダイレクトをシェル自身に恒久的に適用する
+    ///
+    /// 成功した場合、execの後にシェルへ制御が戻ることはない。
+    /// そのため、ヒストリファイルへの保存は行われない。
+    fn run_exec(&mut self, cmd: &Cmd, shell_tx: &SyncSender<ShellMsg>) -> bool {
+        self.status = CmdStatus::Exited(0);
+
+        // リダイレクトをシェル自身に適用
+        if let Err(e) = apply_redirects(&cmd.redirects, self.options.noclobber) {
+            eprintln!("exec: {e}");
+            self.status = CmdStatus::Exited(1);
+            self.resume(shell_tx);
+            return true;
+        }
+
+        let Some(name) = cmd.args.get(1) else {
+            // コマンドの指定がない場合はリダイレクトのみで終了
+            self.resume(shell_tx);
+            return true;
+        };
+
+        let Some(path) = self.lookup_cmd(name) else {
+            eprintln!("exec: {}", msg!(CommandNotFound, name));
+            self.status = CmdStatus::Exited(127);
+            self.resume(shell_tx);
+            return true;
+        };
+
+        let filename = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let args: Vec<CString> = cmd.args[1..]
+            .iter()
+            .map(|s| CString::new(s.as_bytes()).unwrap())
+            .collect();
+
+        // シェルが無視しているSIGTTOUはexec後も無視されたままになるため、デフォルトに戻す
+        unsafe { signal(Signal::SIGTTOU, SigHandler::SigDfl).unwrap() };
+
+        // 成功した場合は返らない
+        let e = execv(&filename, &args).unwrap_err();
+
+        unsafe { signal(Signal::SIGTTOU, SigHandler::SigIgn).unwrap() };
+        eprintln!("exec: {name}: {e}");
+        self.status = CmdStatus::Exited(126);
+        self.resume(shell_tx);
+        true
+    }
+
+    /// detachコマンドを実行
+    ///
+    /// - detach cmd args... : cmdを端末から切り離して実行する
+    ///
+    /// nohupと同様に、シェルの終了後も実行を続ける長時間のプロセスのために用いる。
+    /// 子プロセスはsetsidで新たなセッションを作成して制御端末を持たず、SIGHUPを無視し、
+    /// 標準入力は/dev/nullとなる(リダイレクトが指定された場合はそちらが優先される)。
+    /// ジョブとして管理しないため、jobsやfgの対象とはならず、終了はwait_childで回収される。
+    fn run_detach(&mut self, cmd: &Cmd, shell_tx: &SyncSender<ShellMsg>) -> bool {
+        let Some(name) = cmd.args.get(1) else {
+            eprintln!("{}", msg!(UsageDetach));
+            self.status = CmdStatus::Exited(2);
+            self.resume(shell_tx);
+            return true;
+        };
+
+        let Some(path) = self.lookup_cmd(name) else {
+            eprintln!("detach: {}", msg!(CommandNotFound, name));
+            self.status = CmdStatus::Exited(127);
+            self.resume(shell_tx);
+            return true;
+        };
+
+        let detached = Cmd {
+            args: cmd.args[1..].to_vec(),
+            redirects: cmd.redirects.clone(),
+        };
+        match fork_exec_detached(
+            &path,
+            &detached,
+            self.options.noclobber,
+            &self.ignored_signals,
+        ) {
+            Ok(child) => {
+                eprintln!("{}", msg!(Detached, child));
+                self.status = CmdStatus::Exited(0);
+            }
+            Err(e) => {
+                eprintln!("detach: {name}: {e}");
+                self.status = CmdStatus::Exited(126);
+            }
+        }
+        self.resume(shell_tx);
+        true
+    }
+
+    /// timeoutコマンドを実行
+    ///
+    /// - timeout 5 cmd args...      : cmdをジョブとして実行し、5秒を超えたら終了させる
+    /// - timeout -k 3 5 cmd args... : SIGTERMを送信してからSIGKILLを送信するまでの時間を3秒とする
+    ///
+    /// 時間には1.5や30s、2m、1hのように小数と単位(s、m、h、d)を指定できる。
+    /// 制限時間を超えるとSIGTERMを送信し、それでも終了しない場合はSIGKILLを送信する。
+    /// その場合、ジョブの終了状態はTIMEOUT_STATUS(124)となる。
+    /// 制限時間の管理はworkerスレッドで行うため、バックグラウンドジョブでも利用できる。
+    fn run_timeout(
+        &mut self,
+        line: &str,
+        cmd: &Cmd,
+        bg: bool,
+        shell_tx: &SyncSender<ShellMsg>,
+    ) -> bool {
+        let args = cmd.arg_strs();
+        let (kill_after, rest) = match &args[1..] {
+            ["-k", k, rest @ ..] => (parse_duration(k), rest),
+            rest => (Some(Duration::from_secs(TIMEOUT_KILL_AFTER_DEFAULT)), rest),
+        };
+        let parsed = match (kill_after, rest) {
+            (Some(kill_after), [duration, args @ ..]) if !args.is_empty() => {
+                parse_duration(duration).map(|d| (d, kill_after, args))
+            }
+            _ => None,
+        };
+        let Some((duration, kill_after, args)) = parsed else {
+            eprintln!("{}", msg!(UsageTimeout));
+            self.status = CmdStatus::Exited(2);
+            self.resume(shell_tx);
+            return true;
+        };
+
+        let timed = [Cmd {
+            args: args.iter().map(|&a| Cow::Borrowed(a)).collect(),
+            redirects: cmd.redirects.clone(),
+        }];
+        let timeout = Timeout {
+            deadline: Instant::now() + duration,
+            kill_after,
+            stage: TimeoutStage::Running,
+        };
+        if !self.spawn_child(line, &timed, &[], bg, Some(timeout)) {
+            self.resume(shell_tx);
+        }
+        true
+    }
+
+    /// scheduleコマンドを実行
+    ///
+    /// - schedule 時間 cmd args... : 時間の経過後にcmdをバックグラウンドジョブとして実行するよう予約
+    /// - schedule -c 予約ID        : 予約を取り消す
+    ///
+    /// 時間の形式はtimeoutコマンドと同じ。予約した時点で予約IDを表示し、予約の一覧はjobs -sで表示する。
+    /// 予約はworkerスレッドが保持し、シェルの入力を待っている間も実行時刻になれば実行する。
+    /// リダイレクトも含めて、時間より後の部分をそのまま実行するコマンドの行とする
+    fn run_schedule(&mut self, line: &str, args: &[&str], shell_tx: &SyncSender<ShellMsg>) -> bool {
+        if let [_, "-c", id] = args {
+            let id = id.strip_prefix('s').unwrap_or(id);
+            let code = match id.parse().ok().and_then(|id| self.scheduled.remove(&id)) {
+                Some(s) => {
+                    eprintln!("{}", msg!(ScheduleCanceled, id, s.line));
+                    0
+                }
+                None => {
+                    eprintln!("schedule: {}", msg!(NoSuchSchedule, id));
+                    1
+                }
+            };
+            self.status = CmdStatus::Exited(code);
+            self.resume(shell_tx);
+            return true;
+        }
+
+        // 時間の後のコマンドの行は、引数ではなく元の行から取り出す
+        let scheduled = match args {
+            [_, duration, _, ..] => parse_duration(duration).and_then(|d| {
+                let rest = line.trim_start().strip_prefix("schedule")?.trim_start();
+                let rest = rest.strip_prefix(duration)?.trim();
+                Some((d, rest))
+            }),
+            _ => None,
+        };
+        let Some((duration, cmd_line)) = scheduled else {
+            eprintln!("{}", msg!(UsageSchedule));
+            self.status = CmdStatus::Exited(2);
+            self.resume(shell_tx);
+            return true;
+        };
+
+        // 構文の誤りは予約時に検出する
+        let code = match parse_cmd(cmd_line) {
+            Ok(cmd) if cmd.len() == 1 && BUILTINS.contains(&&*cmd[0].args[0]) => {
+                eprintln!("schedule: {}", msg!(ScheduleBuiltin));
+                2
+            }
+            Ok(_) => {
+                let id = self.next_schedule_id;
+                self.next_schedule_id += 1;
+                self.scheduled.insert(
+                    id,
+                    Scheduled {
+                        deadline: Instant::now() + duration,
+                        line: cmd_line.to_string(),
+                    },
+                );
+                eprintln!("{}", msg!(Scheduled, id, cmd_line));
+                0
+            }
+            Err(e) => {
+                eprintln!("schedule: {e}");
+                2
+            }
+        };
+        self.status = CmdStatus::Exited(code);
+        self.resume(shell_tx);
+        true
+    }
+
+    /// コマンド名を実行ファイルの絶対パスに解決する
+    ///
+    /// '/'を含む場合はそのままパスとして扱う。
+    /// そうでない場合はキャッシュを参照し、なければ$PATHを先頭から検索してキャッシュに登録する。
+    /// キャッシュされたパスが実行できなくなっていた場合は再検索する。
+    fn lookup_cmd(&mut self, name: &str) -> Option<PathBuf> {
+        if name.contains('/') {
+            return Some(PathBuf::from(name));
+        }
+
+        if let Some(path) = self.path_cache.get(name) {
+            if is_executable(path) {
+                return Some(path.clone());
+            }
+            self.path_cache.remove(name); // 古いエントリを削除
+        }
+
+        let path = search_path(name)?;
+        self.path_cache.insert(name.to_string(), path.clone());
+        Some(path)
     }
 
     /// 子プロセスを生成。失敗した場合はシェルからの入力を再開させる必要あり。
-    fn spawn_child(&mut self, line: &str, cmd: &[(&str, Vec<&str>)]) -> bool {
+    ///
+    /// bgが真の場合はバックグラウンドジョブとして実行し、即座にシェルからの入力を再開させる。
+    /// timeoutを指定した場合は、制限時間を超えたジョブを終了させる。
+    fn spawn_child(
+        &mut self,
+        line: &str,
+        cmd: &[Cmd],
+        substs: &[ProcSubst],
+        bg: bool,
+        timeout: Option<Timeout>,
+    ) -> bool {
         assert_ne!(cmd.len(), 0); // コマンドが空でないか検査
 
         // ジョブIDを取得
         let job_id = if let Some(id) = self.get_new_job_id() {
             id
         } else {
-            eprintln!("ZeroSh: 管理可能なジョブの最大値に到達");
+            eprintln!("ZeroSh: {}", msg!(TooManyJobs));
             return false;
         };
 
-        if cmd.len() > 2 {
-            eprintln!("ZeroSh: 3つ以上のコマンドによるパイプはサポートしていません");
-            return false;
+        if cmd.len() > 2 {
+            eprintln!("ZeroSh: {}", msg!(PipeTooLong));
+            return false;
+        }
+
+        // プロセス置換の中のコマンドをパース
+        let mut subst_cmds = Vec::new();
+        for s in substs {
+            match parse_cmd(&s.cmd_line) {
+                Ok(c) if c.len() == 1 => subst_cmds.extend(c),
+                Ok(_) => {
+                    eprintln!("ZeroSh: {}", msg!(PipeInProcSubst));
+                    return false;
+                }
+                Err(e) => {
+                    eprintln!("ZeroSh: {e}");
+                    return false;
+                }
+            }
+        }
+
+        // fork前に実行ファイルのパスを解決しておく
+        // パイプラインやプロセス置換の中の組み込みコマンドはNoneとし、fork_cmdで出力を書き込む子プロセスを生成する
+        let mut paths = Vec::new();
+        for (i, c) in cmd.iter().chain(subst_cmds.iter()).enumerate() {
+            let name = &*c.args[0];
+            if (cmd.len() > 1 || i >= cmd.len()) && BUILTINS.contains(&name) {
+                if !PIPE_BUILTINS.contains(&name) {
+                    eprintln!("ZeroSh: {}", msg!(NotInPipeline, name));
+                    self.status = CmdStatus::Exited(1);
+                    return false;
+                }
+                paths.push(None);
+            } else if let Some(path) = self.lookup_cmd(name) {
+                paths.push(Some(path));
+            } else {
+                eprintln!("ZeroSh: {}", msg!(CommandNotFound, name));
+                let suggestions = suggest_cmd(name);
+                if !suggestions.is_empty() {
+                    let list: Vec<String> = suggestions.iter().map(|s| format!("`{s}`")).collect();
+                    eprintln!("ZeroSh: {}", msg!(DidYouMean, list.join(", ")));
+                }
+                self.status = CmdStatus::Exited(127);
+                return false;
+            }
         }
 
         let mut input = None; // 2つ目のプロセスの標準入力
         let mut output = None; // １つ目のプロセスの標準出力
         if cmd.len() == 2 {
             // パイプを作成
-            let p = pipe().unwrap();
+            // O_CLOEXECを指定し、dup2で標準入出力に複製した以外のディスクリプタはexec時にクローズされるようにする
+            let p = match pipe2(OFlag::O_CLOEXEC) {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("ZeroSh: {}", ShellError::Syscall("pipe2", e));
+                    return false;
+                }
+            };
             input = Some(p.0);
             output = Some(p.1);
         }
@@ -373,12 +2330,12 @@ impl Worker {
 
         // １つ目のプロセスを生成
         //
-        match fork_exec(Pid::from_raw(0), cmd[0].0, &cmd[0].1, None, output) {
+        match self.fork_cmd(Pid::from_raw(0), paths[0].as_deref(), &cmd[0], None, output) {
             Ok(child) => {
                 pgid = child;
             }
             Err(e) => {
-                eprintln!("ZeroSh: プロセス生成エラー: {e}");
+                eprintln!("ZeroSh: {}", msg!(SpawnFailed, e));
                 return false;
             }
         }
@@ -390,31 +2347,115 @@ impl Worker {
         };
         let mut pids = HashMap::new();
         pids.insert(pgid, info.clone()); // 1つ目のプロセスの情報
+        let mut last_pid = pgid; // パイプラインの最後のプロセス
 
         // 2つ目のプロセスを生成
         if cmd.len() == 2 {
-            match fork_exec(pgid, cmd[1].0, &cmd[1].1, input, None) {
+            match self.fork_cmd(pgid, paths[1].as_deref(), &cmd[1], input, None) {
                 Ok(child) => {
                     // 2つ目のプロセスの情報
                     pids.insert(child, info);
+                    last_pid = child;
                 }
                 Err(e) => {
-                    eprintln!("ZeroSh: プロセス生成エラー: {e}");
+                    eprintln!("ZeroSh: {}", msg!(SpawnFailed, e));
                     return false;
                 }
             }
         }
 
+        // プロセス置換のコマンドを、同じプロセスグループで生成
+        // <(cmd)ではcmdの標準出力を、>(cmd)ではcmdの標準入力をパイプとする
+        for ((s, c), path) in substs
+            .iter()
+            .zip(subst_cmds.iter())
+            .zip(&paths[cmd.len()..])
+        {
+            let (input, output) = if s.output {
+                (Some(s.peer), None)
+            } else {
+                (None, Some(s.peer))
+            };
+            match self.fork_cmd(pgid, path.as_deref(), c, input, output) {
+                Ok(child) => {
+                    pids.insert(
+                        child,
+                        ProcInfo {
+                            state: ProcState::Run,
+                            pgid,
+                        },
+                    );
+                }
+                Err(e) => eprintln!("ZeroSh: {}", msg!(SpawnFailed, e)),
+            }
+        }
+
         std::mem::drop(cleanup_pipe); // パイプをクローズ。ここでクローズしても、子プロセスでは残っている
 
-        // ジョブ情報を追加して子プロセスをフォアグラウンドプロセスグループにする
+        self.insert_job(job_id, pgid, last_pid, pids, line, timeout);
+
+        if bg {
+            // バックグラウンドジョブの場合はフォアグラウンドを変更せずに入力を再開
+            eprintln!("[{job_id}] {pgid}");
+            self.status = CmdStatus::Exited(0);
+            return false;
+        }
+
+        // 子プロセスをフォアグラウンドプロセスグループにする
+        // 失敗した場合もジョブは実行されているので、終了はwait_childで検知される
         self.fg = Some(pgid);
-        self.insert_job(job_id, pgid, pids, line);
-        tcsetpgrp(libc::STDIN_FILENO, pgid).unwrap();
+        if self.interactive {
+            if let Err(e) = tcsetpgrp(libc::STDIN_FILENO, pgid) {
+                eprintln!("ZeroSh: {}", ShellError::Syscall("tcsetpgrp", e));
+            }
+        }
 
         true
     }
 
+    /// パイプラインの1つのコマンドのプロセスを生成
+    ///
+    /// filenameがNoneの場合は組み込みコマンドとして、fork前に出力を生成し、子プロセスはそれを書き込んで終了する。
+    /// マルチスレッドのプロセスでforkした子プロセスでは、メモリの確保やロックを安全に行えないため
+    fn fork_cmd(
+        &mut self,
+        pgid: Pid,
+        filename: Option<&Path>,
+        cmd: &Cmd,
+        input: Option<i32>,
+        output: Option<i32>,
+    ) -> Result<Pid, ShellError> {
+        let noclobber = self.options.noclobber;
+        let ignored = self.ignored_signals.clone();
+        let Some(filename) = filename else {
+            let (out, code) = self.pipe_builtin_output(&cmd.arg_strs());
+            return fork_child(pgid, cmd, noclobber, &ignored, input, output, || {
+                // リダイレクトはfork_childで適用済み
+                let mut rest = &out[..];
+                while let Ok(n @ 1..) = syscall(|| unistd::write(libc::STDOUT_FILENO, rest)) {
+                    rest = &rest[n..];
+                }
+                unsafe { libc::_exit(code) };
+            });
+        };
+        fork_exec(pgid, filename, cmd, noclobber, &ignored, input, output)
+    }
+
+    /// パイプラインの中の組み込みコマンドの出力を生成し、終了コードとともに返す
+    ///
+    /// 誤った引数などのエラーメッセージは、fork前にシェル自身が表示する
+    fn pipe_builtin_output(&self, args: &[&str]) -> (Vec<u8>, i32) {
+        let mut out = Vec::new();
+        let code = match args[0] {
+            "jobs" => self.write_jobs(args, &mut out),
+            "jobstats" => self.write_jobstats(&mut out).map(|()| 0),
+            "history" => self.write_history(args, &mut out),
+            // PIPE_BUILTINS以外の組み込みコマンドはspawn_childで拒否している
+            name => unreachable!("{name}"),
+        };
+        (out, code.unwrap_or(1))
+    }
+
     /// 子プロセスの状態変化を管理
     fn wait_child(&mut self, shell_tx: &SyncSender<ShellMsg>) {
         // waitpidで検知する状態を設定するフラグ
@@ -427,201 +2468,900 @@ impl Worker {
         // それを呼び出したスレッドも子プロセスの状態変化が起きるまで待機状態となる
         // ノンブロッキングとすると、waitpidの呼び出し時点で子プロセスの状態変化がない場合は即座に返る
         // こうすることで、workerスレッドはシグナルとコマンドライン実行の両方を並行に処理できる
-        let flag = Some(WaitPidFlag::WUNTRACED | WaitPidFlag::WNOHANG | WaitPidFlag::WCONTINUED);
+        let flag = WaitPidFlag::WUNTRACED | WaitPidFlag::WNOHANG | WaitPidFlag::WCONTINUED;
         loop {
             // waitpidで子プロセスの状態変化を検知
             // 第一引数にプロセスIDを指定すると特定の子プロセスのみ指定可能で、
             // -1を指定した場合は任意の子プロセスの状態変化を検知する
             //
             // waitpidは終了したプロセスのリソース解放も行い、これを忘れるとゾンビプロセスとなり無駄にリソースを消費してしまう
-            match syscall(|| waitpid(Pid::from_raw(-1), flag)) {
+            // ここではjobstatsのためにリソース使用量も取得できるwait4を利用する
+            match syscall(|| wait_any(flag)) {
                 // プロセスが終了
-                Ok(WaitStatus::Exited(pid, status)) => {
-                    self.exit_val = status; // 終了コードを保存
-                    self.process_term(pid, shell_tx);
+                Ok((WaitStatus::Exited(pid, status), usage)) => {
+                    self.add_usage(pid, &usage);
+                    self.process_term(pid, CmdStatus::Exited(status), shell_tx);
                 }
                 // プロセスがシグナルにより終了
-                Ok(WaitStatus::Signaled(pid, sig, core)) => {
-                    eprint!(
-                        "\nZeroSh: 子プロセスがシグナルにより終了{}: pid = {pid}, signal = {sig}",
-                        if core { " (コアダンプ) " } else { "" }
-                    );
-                    self.exit_val = sig as i32 + 128; // 終了コードを保持
-                    self.process_term(pid, shell_tx);
+                Ok((WaitStatus::Signaled(pid, sig, core), usage)) => {
+                    self.add_usage(pid, &usage);
+                    let core = if core {
+                        msg::text(MsgId::CoreDumped)
+                    } else {
+                        ""
+                    };
+                    eprint!("\nZeroSh: {}", msg!(ChildSignaled, core, pid, sig));
+                    self.process_term(pid, CmdStatus::Signaled(sig), shell_tx);
                 }
                 // プロセスが停止
-                Ok(WaitStatus::Stopped(pid, _sig)) => self.process_stop(pid, shell_tx),
+                Ok((WaitStatus::Stopped(pid, sig), _)) => self.process_stop(pid, sig, shell_tx),
                 // プロセスが実行再開
-                Ok(WaitStatus::Continued(pid)) => self.process_continue(pid, shell_tx),
+                Ok((WaitStatus::Continued(pid), _)) => self.process_continue(pid, shell_tx),
                 // waitすべき子プロセスはいない
-                Ok(WaitStatus::StillAlive) => return,
+                Ok((WaitStatus::StillAlive, _)) => return,
                 // そもそも子プロセスがいない
                 Err(nix::Error::ECHILD) => return,
                 Err(e) => {
-                    eprintln!("\nZeroSh: waitが失敗: {e}");
+                    eprintln!("\nZeroSh: {}", msg!(WaitFailed, e));
                     exit(1); // 致命的なエラーとしてシェルを終了させる
                 }
                 #[cfg(any(target_os = "linux", target_os = "android"))]
-                Ok(WaitStatus::PtraceEvent(pid, _, _) | WaitStatus::PtraceSyscall(pid)) => {
-                    self.process_stop(pid, shell_tx)
+                Ok((WaitStatus::PtraceEvent(pid, _, _) | WaitStatus::PtraceSyscall(pid), _)) => {
+                    self.process_stop(pid, Signal::SIGTRAP, shell_tx)
                 }
             }
         }
     }
 
-    /// プロセスの終了処理
-    fn process_term(&mut self, pid: Pid, shell_tx: &SyncSender<ShellMsg>) {
+    /// 終了したプロセスpidのリソース使用量を、所属するジョブに加える
+    ///
+    /// ジョブとして管理していないプロセス(通知フックなど)は無視
+    fn add_usage(&mut self, pid: Pid, usage: &Usage) {
+        let Some(info) = self.pid_to_info.get(&pid) else {
+            return;
+        };
+        let Some((job_id, _)) = self.pgid_to_pids.get(&info.pgid) else {
+            return;
+        };
+        if let Some(job) = self.jobs.get_mut(job_id) {
+            job.usage.add(usage);
+        }
+    }
+
+    /// プロセスの終了処理。statusはプロセスの終了状態
+    fn process_term(&mut self, pid: Pid, status: CmdStatus, shell_tx: &SyncSender<ShellMsg>) {
         // プロセスのIDを削除し、必要ならフォアグラウンドプロセスをシェルに設定
         if let Some((job_id, pgid)) = self.remove_pid(pid) {
+            // パイプラインの終了状態は、最後のプロセスの終了状態とする
+            if let Some(job) = self.jobs.get_mut(&job_id) {
+                if job.last_pid == pid {
+                    job.status = status;
+                }
+            }
             self.manage_job(job_id, pgid, shell_tx);
         }
     }
 
-    /// プロセスの停止処理
-    fn process_stop(&mut self, pid: Pid, shell_tx: &SyncSender<ShellMsg>) {
-        self.set_pid_state(pid, ProcState::Stop); // プロセスを停止中に設定
-        let pgid = self.pid_to_info.get(&pid).unwrap().pgid; // プロセスグループIDを取得
-        let job_id = self.pgid_to_pids.get(&pgid).unwrap().0; // ジョブIDを取得
-        self.manage_job(job_id, pgid, shell_tx); // 必要ならフォアグラウンドプロセスをシェルに設定
+    /// プロセスの停止処理。sigはプロセスを停止させたシグナル
+    fn process_stop(&mut self, pid: Pid, sig: Signal, shell_tx: &SyncSender<ShellMsg>) {
+        // ジョブとして管理していないプロセス(通知フックなど)は無視
+        if self.set_pid_state(pid, ProcState::Stop).is_none() {
+            return;
+        }
+        let pgid = self.pid_to_info[&pid].pgid; // プロセスグループIDを取得
+        let job_id = self.pgid_to_pids[&pgid].0; // ジョブIDを取得
+        if self.fg == Some(pgid) {
+            // フォアグラウンドのジョブが停止した場合は、停止したことを終了状態とする
+            // ジョブが再開して終了した場合は、manage_jobで終了時の状態に上書きされる
+            self.status = CmdStatus::Stopped(sig);
+        }
+        self.manage_job(job_id, pgid, shell_tx); // 必要ならフォアグラウンドプロセスをシェルに設定
+    }
+
+    /// プロセスの再開処理
+    fn process_continue(&mut self, pid: Pid, shell_tx: &SyncSender<ShellMsg>) {
+        self.set_pid_state(pid, ProcState::Run);
+    }
+
+    /// ジョブの管理。引数には変化のあったジョブとプロセスグループを指定
+    ///
+    /// - フォアグラウンドプロセスが空の場合、シェルをフォアグラウンドに設定
+    /// - フォアグラウンドプロセスがすべて停止中の場合、シェルをフォアグラウンドに設定
+    fn manage_job(&mut self, job_id: usize, pgid: Pid, shell_tx: &SyncSender<ShellMsg>) {
+        // フォアグラウンドのプロセスか？を判定
+        let is_fg = self.fg.map_or(false, |x| pgid == x);
+
+        // jobsフィールドから、ジョブ実行時に指定されたコマンド実行の文字列を取得できる
+        let line = &self.jobs.get(&job_id).unwrap().line;
+
+        if is_fg {
+            // 状態が変化したプロセスはフォアグラウンドに設定
+            if self.is_group_empty(pgid) {
+                // フォアグラウンドプロセスが空の場合
+                // ジョブ情報を削除してシェルをフォアグラウンドに設定
+                eprintln!("{}", msg!(JobDone, job_id, line));
+                self.status = self.jobs.get(&job_id).unwrap().exit_status();
+                self.remove_job(job_id);
+                self.set_shell_fg(shell_tx);
+            } else if self.is_group_stop(pgid).unwrap() {
+                // フォアグラウンドプロセスがすべて停止中の場合
+                // シェルをフォアグラウンドに設定
+                eprintln!("{}", msg!(JobStopped, job_id, line));
+                self.set_current_job(job_id); // 停止したジョブはカレントジョブになる
+                self.set_shell_fg(shell_tx);
+            }
+        } else {
+            // プロセスグループが空の場合、ジョブ情報を削除
+            if self.is_group_empty(pgid) {
+                eprintln!("{}", msg!(JobDone, job_id, line));
+                self.notify_job(job_id);
+                self.remove_job(job_id);
+            }
+        }
+    }
+
+    /// バックグラウンドジョブの終了を通知フックで知らせる
+    ///
+    /// ジョブの実行時間が閾値以上の場合のみ、通知フックコマンドに
+    /// ジョブの概要を引数として渡し、ジョブの詳細を環境変数で渡して実行する。
+    /// 通知フックはジョブとして管理せず、終了はwait_childで回収される。
+    fn notify_job(&self, job_id: usize) {
+        let (Some(hook), Some(job)) = (&self.notify_cmd, self.jobs.get(&job_id)) else {
+            return;
+        };
+
+        let elapsed = job.start.elapsed();
+        if elapsed < self.notify_threshold {
+            return;
+        }
+
+        let mut words = hook.split_whitespace();
+        let Some(prog) = words.next() else {
+            return;
+        };
+        let summary = msg!(
+            JobDoneElapsed,
+            job_id,
+            job.exit_status().code(),
+            elapsed.as_secs(),
+            job.line
+        );
+
+        // 通知フックは独立したプロセスグループで実行し、端末からのシグナルを受け取らないようにする
+        let result = Command::new(prog)
+            .args(words)
+            .arg(&summary)
+            .env("ZEROSH_JOB_ID", job_id.to_string())
+            .env("ZEROSH_JOB_CMD", &job.line)
+            .env("ZEROSH_JOB_DURATION", elapsed.as_secs().to_string())
+            .env("ZEROSH_JOB_STATUS", job.exit_status().code().to_string())
+            .process_group(0)
+            .spawn();
+        if let Err(e) = result {
+            eprintln!("ZeroSh: {}", msg!(NotifyFailed, e));
+        }
+    }
+
+    /// 新たなジョブ情報を追加
+    fn insert_job(
+        &mut self,
+        job_id: usize,
+        pgid: Pid,
+        last_pid: Pid,
+        pids: HashMap<Pid, ProcInfo>,
+        line: &str,
+        timeout: Option<Timeout>,
+    ) {
+        // ジョブ情報を追加
+        assert!(!self.jobs.contains_key(&job_id));
+        self.jobs.insert(
+            job_id,
+            Job {
+                pgid,
+                line: line.to_string(),
+                start: Instant::now(),
+                last_pid,
+                status: CmdStatus::Exited(0),
+                timeout,
+                usage: Usage::default(),
+                winch: false,
+            },
+        );
+
+        self.set_current_job(job_id); // 新たなジョブはカレントジョブになる
+
+        // pgid_to_pidsへ追加するプロセス
+        let mut procs = HashSet::new();
+        for (pid, info) in pids {
+            procs.insert(pid);
+
+            assert!(!self.pid_to_info.contains_key(&pid));
+            self.pid_to_info.insert(pid, info); // プロセスの情報を追加
+        }
+
+        // プロセスグループの情報を追加
+        assert!(!self.pgid_to_pids.contains_key(&pgid));
+        self.pgid_to_pids.insert(pgid, (job_id, procs));
+    }
+
+    /// プロセスの実行状態を設定し、以前の状態を返す。
+    /// pidが存在しないプロセスの場合はNoneを返す。
+    fn set_pid_state(&mut self, pid: Pid, state: ProcState) -> Option<ProcState> {
+        let info = self.pid_to_info.get_mut(&pid)?;
+        Some(replace(&mut info.state, state))
+    }
+
+    /// プロセスの情報を削除し、削除できた場合はプロセスの所属する
+    /// (ジョブID, プロセスグループID)を返す。
+    /// 存在しないプロセスの場合はNoneを返す。
+    fn remove_pid(&mut self, pid: Pid) -> Option<(usize, Pid)> {
+        let pgid = self.pid_to_info.remove(&pid)?.pgid; // プロセスグループIDを取得
+        let it = self.pgid_to_pids.get_mut(&pgid)?;
+        it.1.remove(&pid); // プロセスグループからpidを削除
+        let job_id = it.0; // ジョブIDを取得
+        Some((job_id, pgid))
+    }
+
+    /// ジョブ情報を削除し、関連するプロセスグループの情報も削除
+    fn remove_job(&mut self, job_id: usize) {
+        if let Some(job) = self.jobs.remove(&job_id) {
+            if let Some((_, pids)) = self.pgid_to_pids.remove(&job.pgid) {
+                assert!(pids.is_empty()); // ジョブを削除するときはプロセスグループは空のはず
+            }
+
+            // jobstatsで表示するために、終了したジョブの統計を記録
+            if self.finished.len() == JOBSTATS_MAX {
+                self.finished.pop_front();
+            }
+            let (status, stats) = (job.exit_status(), job.stats());
+            self.finished.push_back((job_id, job.line, status, stats));
+        }
+
+        // カレントジョブと直前のジョブを更新
+        // 削除したジョブの代わりには、残りのジョブのうち最も新しいIDのものを用いる
+        if self.current_job == Some(job_id) {
+            self.current_job = self.previous_job.take();
+        } else if self.previous_job == Some(job_id) {
+            self.previous_job = None;
+        }
+        if self.current_job.is_none() {
+            self.current_job = self.jobs.keys().next_back().copied();
+        }
+        if self.previous_job.is_none() {
+            self.previous_job = self
+                .jobs
+                .keys()
+                .rev()
+                .find(|id| Some(**id) != self.current_job)
+                .copied();
+        }
+    }
+
+    /// ジョブの数と、そのうち停止中のジョブの数を返す
+    fn job_count(&self) -> JobCount {
+        let stopped = self
+            .jobs
+            .values()
+            .filter(|job| self.is_group_stop(job.pgid) == Some(true))
+            .count();
+        JobCount {
+            total: self.jobs.len(),
+            stopped,
+        }
+    }
+
+    /// 終了時の警告で表示する、`[1] 実行中\tsleep 10`のような各ジョブの状態を表す行
+    fn job_list(&self) -> Vec<String> {
+        self.jobs
+            .iter()
+            .map(|(job_id, job)| format!("[{job_id}] {}\t{}", self.job_state(job.pgid), job.line))
+            .collect()
+    }
+
+    /// jobsなどで表示する、プロセスグループpgidのジョブの状態
+    fn job_state(&self, pgid: Pid) -> &'static str {
+        if self.is_group_stop(pgid) == Some(true) {
+            msg::text(MsgId::StateStopped)
+        } else {
+            msg::text(MsgId::StateRunning)
+        }
+    }
+
+    /// 空のプロセスグループなら真
+    fn is_group_empty(&self, pgid: Pid) -> bool {
+        self.pgid_to_pids.get(&pgid).unwrap().1.is_empty()
+    }
+
+    /// プロセスグループのプロセスがすべて停止中なら真
+    fn is_group_stop(&self, pgid: Pid) -> Option<bool> {
+        for pid in self.pgid_to_pids.get(&pgid)?.1.iter() {
+            if self.pid_to_info.get(pid).unwrap().state == ProcState::Run {
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+
+    /// シェルをフォアグラウンドに設定
+    fn set_shell_fg(&mut self, shell_tx: &SyncSender<ShellMsg>) {
+        // シェルがフォアグラウンドであることを示すために、fgをNoneに設定する
+        self.fg = None;
+        if self.interactive {
+            if let Err(e) = tcsetpgrp(libc::STDIN_FILENO, self.shell_pgid) {
+                eprintln!("ZeroSh: {}", ShellError::Syscall("tcsetpgrp", e));
+            }
+        }
+        self.resume(shell_tx); // シェルの入力を再開させる
+    }
+
+    /// 新たなジョブIDを取得
+    fn get_new_job_id(&self) -> Option<usize> {
+        for i in 0..=usize::MAX {
+            if !self.jobs.contains_key(&i) {
+                return Some(i);
+            }
+        }
+        None
+    }
+}
+
+/// timeoutコマンドの時間を解釈する
+///
+/// 数値(小数も可)の後に単位としてs(秒)、m(分)、h(時間)、d(日)を指定でき、省略した場合は秒とする
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (num, unit) = match s.char_indices().last()? {
+        (i, 's') => (&s[..i], 1.0),
+        (i, 'm') => (&s[..i], 60.0),
+        (i, 'h') => (&s[..i], 60.0 * 60.0),
+        (i, 'd') => (&s[..i], 24.0 * 60.0 * 60.0),
+        _ => (s, 1.0),
+    };
+    let secs = num.parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(secs * unit).ok()
+}
+
+/// 現在のファイル作成マスクを取得する
+///
+/// umaskシステムコールは設定と同時に以前の値を返すので、一旦設定してから元に戻す
+fn get_umask() -> Mode {
+    let mask = umask(Mode::empty());
+    umask(mask);
+    mask
+}
+
+/// ファイル作成マスクを記号形式で表す
+///
+/// 記号形式ではマスクされずに許可される権限を表示する。例えば022はu=rwx,g=rx,o=rxとなる
+fn umask_symbolic(mask: Mode) -> String {
+    let allowed = !mask.bits() & 0o777;
+    ["u", "g", "o"]
+        .iter()
+        .enumerate()
+        .map(|(i, who)| {
+            let bits = allowed >> (6 - 3 * i);
+            let perm: String = [(0o4, 'r'), (0o2, 'w'), (0o1, 'x')]
+                .iter()
+                .filter(|(b, _)| bits & b != 0)
+                .map(|(_, c)| c)
+                .collect();
+            format!("{who}={perm}")
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// mainスレッドへメッセージを送信する
+///
+/// mainスレッドが終了している場合は送信できないが、workerスレッドを終了させる必要もないので、
+/// エラーを表示するのみとする
+fn send_shell_msg(shell_tx: &SyncSender<ShellMsg>, msg: ShellMsg) {
+    if shell_tx.send(msg).is_err() {
+        eprintln!("ZeroSh: {}", ShellError::Channel);
+    }
+}
+
+/// rcファイルのパスを返す
+fn rc_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(RC_FILE))
+}
+
+/// オプションの値の表示
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// rcファイルpathに、オプションnamesを設定するshoptコマンドを書き込む
+fn save_options(path: &Path, names: &[&str], on: bool) -> io::Result<()> {
+    let text = read_rc(path)?;
+    fs::write(path, update_rc(&text, names, on))
+}
+
+/// rcファイルpathに、キー列keysに動作actionを割り当てる行を書き込む
+///
+/// actionがNoneなら、keysを割り当てる行を取り除くのみとする
+fn save_binding(path: &Path, keys: &str, action: Option<&Action>) -> io::Result<()> {
+    let text = read_rc(path)?;
+    fs::write(path, update_rc_binding(&text, keys, action))
+}
+
+/// rcファイルpathの内容を返す。存在しない場合は空とする
+fn read_rc(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// rcファイルの内容textから、オプションnamesを設定する既存の行を取り除き、
+/// 末尾にnamesを設定する行を追加した内容を返す
+///
+/// 取り除くのは`shopt -s name`または`shopt -u name`のみの行で、それ以外の行は変更しない
+fn update_rc(text: &str, names: &[&str], on: bool) -> String {
+    let mut result = String::new();
+    for line in text.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let ["shopt", "-s" | "-u", name] = words[..] {
+            if names.contains(&name) {
+                continue;
+            }
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    let flag = if on { "-s" } else { "-u" };
+    for name in names {
+        result.push_str(&format!("shopt {flag} {name}\n"));
+    }
+    result
+}
+
+/// rcファイルの内容textから、キー列keysを割り当てる既存のbindの行を取り除き、
+/// actionがSomeなら末尾にkeysにactionを割り当てる行を追加した内容を返す
+fn update_rc_binding(text: &str, keys: &str, action: Option<&Action>) -> String {
+    let mut result = String::new();
+    for line in text.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["bind", "-s" | "-r", k, ..] | ["bind", k, _] if k == keys => continue,
+            _ => (),
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    if let Some(action) = action {
+        result.push_str(&bind_command(keys, action));
+        result.push('\n');
+    }
+    result
+}
+
+/// スクリプトファイルを読み込み、実行する行を返す
+///
+/// 空行と#で始まるコメント行は取り除く。
+/// source組み込みコマンドと、非対話的なスクリプトの実行で共通して利用する。
+///
+/// 完結していない行は、次の行と連結して1行とする
+fn read_script(path: &str) -> Result<Vec<String>, DynError> {
+    let content = fs::read_to_string(path)?;
+    let mut lines: Vec<String> = Vec::new();
+    let mut continued = false; // 直前の行が完結していなければ真
+    for line in content.lines().map(|line| line.trim()) {
+        if continued {
+            join_line(lines.last_mut().unwrap(), line);
+        } else if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else {
+            lines.push(line.to_string());
+        }
+        continued = lines.last().and_then(|l| incomplete(l)).is_some();
+    }
+
+    if continued {
+        return Err(msg!(IncompleteEof, path).into());
+    }
+    Ok(lines)
+}
+
+/// 入力行が完結していない理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Incomplete {
+    Backslash, // 行末が\
+    Pipe,      // 行末が|で、パイプの後のコマンドがない
+    Paren,     // $((や<(cmd)などの括弧が閉じられていない
+}
+
+/// 入力行lineが完結していなければ、その理由を返す
+///
+/// 閉じ括弧が多すぎるなどの不正な入力は完結しているものとし、実行時のパースでエラーとする
+fn incomplete(line: &str) -> Option<Incomplete> {
+    if line.ends_with('\\') {
+        return Some(Incomplete::Backslash);
+    }
+
+    let depth = line.chars().fold(0i32, |depth, c| match c {
+        '(' => depth + 1,
+        ')' => depth - 1,
+        _ => depth,
+    });
+    if depth > 0 {
+        return Some(Incomplete::Paren);
+    }
+
+    let line = line.trim_end();
+    if line.ends_with('|') && !line.ends_with(">|") && !line.ends_with("||") {
+        return Some(Incomplete::Pipe);
+    }
+    None
+}
+
+/// 完結していない行lineに、続きの行nextを連結する
+///
+/// 行末の\は取り除いて空白を挟まずに連結し、それ以外は空白を挟んで連結する
+fn join_line(line: &mut String, next: &str) {
+    if incomplete(line) == Some(Incomplete::Backslash) {
+        line.pop();
+    } else {
+        line.push(' ');
+    }
+    line.push_str(next);
+}
+
+/// 完結していない行lineの続きを、CONTINUATION_PROMPTを表示して読み込み、連結した行を返す
+///
+/// Ctrl+cで入力を取り消した場合や、途中でEOFとなった場合はNoneを返す
+fn read_continuation(
+    rl: &mut ShellEditor,
+    mut line: String,
+) -> Result<Option<String>, ReadlineError> {
+    while incomplete(&line).is_some() {
+        match rl.readline(CONTINUATION_PROMPT) {
+            Ok(next) => join_line(&mut line, &next),
+            Err(ReadlineError::Interrupted) => return Ok(None),
+            Err(ReadlineError::Eof) => {
+                eprintln!("ZeroSh: {}", msg!(InputEof));
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(line))
+}
+
+/// 置換した行を実行するか確認する。空行(Enterのみ)が入力された場合のみ真を返す
+fn confirm_subst(rl: &mut ShellEditor) -> Result<bool, ReadlineError> {
+    match rl.readline(&msg!(ConfirmSubst)) {
+        Ok(answer) => Ok(answer.trim().is_empty()),
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => Ok(false),
+        Err(e) => Err(e),
     }
+}
 
-    /// プロセスの再開処理
-    fn process_continue(&mut self, pid: Pid, shell_tx: &SyncSender<ShellMsg>) {
-        self.set_pid_state(pid, ProcState::Run);
+/// 行末の&を取り除き、バックグラウンド実行の指定があれば真を返す
+fn split_background(line: &str) -> (&str, bool) {
+    let line = line.trim_end();
+    match line.strip_suffix('&') {
+        Some(l) => (l, true),
+        None => (line, false),
     }
+}
 
-    /// ジョブの管理。引数には変化のあったジョブとプロセスグループを指定
-    ///
-    /// - フォアグラウンドプロセスが空の場合、シェルをフォアグラウンドに設定
-    /// - フォアグラウンドプロセスがすべて停止中の場合、シェルをフォアグラウンドに設定
-    fn manage_job(&mut self, job_id: usize, pgid: Pid, shell_tx: &SyncSender<ShellMsg>) {
-        // フォアグラウンドのプロセスか？を判定
-        let is_fg = self.fg.map_or(false, |x| pgid == x);
+/// リダイレクトの種類
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RedirectKind<'a> {
+    Read(&'a str),      // < file
+    Write(&'a str),     // > file。noclobberが有効な場合は既存のファイルを上書きしない
+    Clobber(&'a str),   // >| file。noclobberに関わらず上書きする
+    Append(&'a str),    // >> file
+    ReadWrite(&'a str), // <> file
+    Dup(RawFd),         // <&m, >&m。fdをmの複製にする
+    Close,              // <&-, >&-。fdをクローズする
+    Inherit, // fdのclose-on-execフラグを外し、execしたコマンドに引き継ぐ。プロセス置換で用いる
+}
 
-        // jobsフィールドから、ジョブ実行時に指定されたコマンド実行の文字列を取得できる
-        let line = &self.jobs.get(&job_id).unwrap().1;
+/// リダイレクト。fdをkindで示される先に置き換える
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Redirect<'a> {
+    fd: RawFd,
+    kind: RedirectKind<'a>,
+}
 
-        if is_fg {
-            // 状態が変化したプロセスはフォアグラウンドに設定
-            if self.is_group_empty(pgid) {
-                // フォアグラウンドプロセスが空の場合
-                // ジョブ情報を削除してシェルをフォアグラウンドに設定
-                eprintln!("[{job_id}] 終了\t{line}");
-                self.remove_job(job_id);
-                self.set_shell_fg(shell_tx);
-            } else if self.is_group_stop(pgid).unwrap() {
-                // フォアグラウンドプロセスがすべて停止中の場合
-                // シェルをフォアグラウンドに設定
-                eprintln!("[{job_id}] 停止\t{line}");
-                self.set_shell_fg(shell_tx);
-            }
-        } else {
-            // プロセスグループが空の場合、ジョブ情報を削除
-            if self.is_group_empty(pgid) {
-                eprintln!("[{job_id}] 終了\t{line}");
-                self.remove_job(job_id);
-            }
-        }
+/// パイプで区切られた1つのコマンド
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cmd<'a> {
+    args: Vec<Cow<'a, str>>,      // 引数。先頭はコマンド名
+    redirects: Vec<Redirect<'a>>, // リダイレクト。左から順に適用する
+}
+
+impl Cmd<'_> {
+    /// 組み込みコマンドに渡すため、引数を&strの一覧にする
+    fn arg_strs(&self) -> Vec<&str> {
+        self.args.iter().map(|a| a.as_ref()).collect()
     }
+}
 
-    /// 新たなジョブ情報を追加
-    fn insert_job(&mut self, job_id: usize, pgid: Pid, pids: HashMap<Pid, ProcInfo>, line: &str) {
-        // ジョブ情報を追加
-        assert!(!self.jobs.contains_key(&job_id));
-        self.jobs.insert(job_id, (pgid, line.to_string()));
+type CmdResult<'a> = Result<Vec<Cmd<'a>>, DynError>;
 
-        // pgid_to_pidsへ追加するプロセス
-        let mut procs = HashSet::new();
-        for (pid, info) in pids {
-            procs.insert(pid);
+/// コマンドをパース
+fn parse_cmd(line: &str) -> CmdResult {
+    let mut parsed_cmds = vec![];
 
-            assert!(!self.pid_to_info.contains_key(&pid));
-            self.pid_to_info.insert(pid, info); // プロセスの情報を追加
+    for cmd in split_pipeline(line) {
+        let cmd = cmd.trim();
+        if cmd.is_empty() {
+            return Err(msg!(EmptyCommand).into());
         }
+        parsed_cmds.push(parse_cmd_one(cmd)?);
+    }
+    Ok(parsed_cmds)
+}
 
-        // プロセスグループの情報を追加
-        assert!(!self.pgid_to_pids.contains_key(&pgid));
-        self.pgid_to_pids.insert(pgid, (job_id, procs));
+/// コマンドをパイプで分割する
+///
+/// `>|`の`|`はリダイレクト演算子の一部なので、パイプとしない
+fn split_pipeline(line: &str) -> Vec<&str> {
+    let mut cmds = Vec::new();
+    let mut start = 0;
+    for (i, c) in line.char_indices() {
+        if c == '|' && !line[..i].ends_with('>') {
+            cmds.push(&line[start..i]);
+            start = i + 1;
+        }
     }
+    cmds.push(&line[start..]);
+    cmds
+}
 
-    /// プロセスの実行状態を設定し、以前の状態を返す。
-    /// pidが存在しないプロセスの場合はNoneを返す。
-    fn set_pid_state(&mut self, pid: Pid, state: ProcState) -> Option<ProcState> {
-        let info = self.pid_to_info.get_mut(&pid)?;
-        Some(replace(&mut info.state, state))
+/// コマンドの各引数に含まれる$?と$NAMEを展開する
+///
+/// 行全体ではなく引数ごとに展開するため、プロセス置換の中のコマンドなどは展開しない
+fn expand_args(cmds: &mut [Cmd], status: i32) {
+    for arg in cmds.iter_mut().flat_map(|c| c.args.iter_mut()) {
+        if arg.contains('$') {
+            *arg = Cow::Owned(expand_vars(arg, &|name| match name {
+                "?" => Some(status.to_string()),
+                _ => env::var(name).ok(),
+            }));
+        }
     }
+}
 
-    /// プロセスの情報を削除し、削除できた場合はプロセスの所属する
-    /// (ジョブID, プロセスグループID)を返す。
-    /// 存在しないプロセスの場合はNoneを返す。
-    fn remove_pid(&mut self, pid: Pid) -> Option<(usize, Pid)> {
-        let pgid = self.pid_to_info.get(&pid)?.pgid; // プロセスグループIDを取得
-        let it = self.pgid_to_pids.get_mut(&pgid)?;
-        it.1.remove(&pid); // プロセスグループからpidを削除
-        let job_id = it.0; // ジョブIDを取得
-        Some((job_id, pgid))
+/// arg中の$?と$NAMEを、lookupで取得した値に展開する
+///
+/// NAMEは英字か_で始まり、英数字と_が続く名前とする。未定義の変数は空文字列に展開する。
+/// $の後が変数名でない場合は、$をそのまま残す
+fn expand_vars(arg: &str, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut result = String::new();
+    let mut rest = arg;
+    while let Some(i) = rest.find('$') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let len = if rest.starts_with('?') {
+            1
+        } else if rest.starts_with(|c: char| c == '_' || c.is_ascii_alphabetic()) {
+            rest.find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len())
+        } else {
+            result.push('$');
+            continue;
+        };
+        result.push_str(&lookup(&rest[..len]).unwrap_or_default());
+        rest = &rest[len..];
     }
+    result.push_str(rest);
+    result
+}
 
-    /// ジョブ情報を削除し、関連するプロセスグループの情報も削除
-    fn remove_job(&mut self, job_id: usize) {
-        if let Some((pgid, _)) = self.jobs.remove(&job_id) {
-            if let Some((_, pids)) = self.pgid_to_pids.remove(&pgid) {
-                assert!(!pids.is_empty()); // ジョブを削除するときはプロセスグループは空のはず
+/// パイプを含まない1つのコマンドをパース
+///
+/// `>file`のようにリダイレクト先が演算子に続いていても、
+/// `> file`のように空白で区切られていても良い。
+/// 演算子の前にはファイルディスクリプタの番号を指定でき(`2>file`、`3<&0`)、
+/// 省略した場合は`<`で始まる演算子は標準入力、`>`で始まる演算子は標準出力となる
+fn parse_cmd_one(cmd: &str) -> Result<Cmd<'_>, DynError> {
+    let mut args = Vec::new();
+    let mut redirects = Vec::new();
+    let mut tokens = cmd.split_whitespace();
+    while let Some(token) = tokens.next() {
+        // 先頭の数字はファイルディスクリプタの番号
+        let digits = token.len() - token.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let (num, token_op) = token.split_at(digits);
+
+        // リダイレクト演算子と、その後に続く文字列を取得
+        // 長い演算子から順に検査する
+        let Some((op, rest)) = [">>", ">|", "<>", ">&", "<&", ">", "<"]
+            .iter()
+            .find_map(|op| token_op.strip_prefix(op).map(|rest| (*op, rest)))
+        else {
+            args.push(Cow::Borrowed(token));
+            continue;
+        };
+
+        let fd = if num.is_empty() {
+            if op.starts_with('<') {
+                libc::STDIN_FILENO
+            } else {
+                libc::STDOUT_FILENO
             }
-        }
-    }
+        } else {
+            num.parse::<RawFd>().map_err(|_| msg!(BadFd, num))?
+        };
 
-    /// 空のプロセスグループなら真
-    fn is_group_empty(&self, pgid: Pid) -> bool {
-        self.pgid_to_pids.get(&pgid).unwrap().1.is_empty()
+        let target = if rest.is_empty() {
+            tokens.next().ok_or_else(|| msg!(RedirectNoFile, op))?
+        } else {
+            rest
+        };
+
+        let kind = match op {
+            ">>" => RedirectKind::Append(target),
+            ">" => RedirectKind::Write(target),
+            ">|" => RedirectKind::Clobber(target),
+            "<" => RedirectKind::Read(target),
+            "<>" => RedirectKind::ReadWrite(target),
+            _ if target == "-" => RedirectKind::Close,
+            _ => RedirectKind::Dup(
+                target
+                    .parse::<RawFd>()
+                    .map_err(|_| msg!(RedirectDupTarget, op, target))?,
+            ),
+        };
+        redirects.push(Redirect { fd, kind });
     }
 
-    /// プロセスグループのプロセスがすべて停止中なら真
-    fn is_group_stop(&self, pgid: Pid) -> Option<bool> {
-        for pid in self.pgid_to_pids.get(&pgid)?.1.iter() {
-            if self.pid_to_info.get(pid).unwrap().state == ProcState::Run {
-                return Some(false);
-            }
-        }
-        Some(true)
+    if args.is_empty() {
+        return Err(msg!(EmptyCommand).into());
     }
+    Ok(Cmd { args, redirects })
+}
 
-    /// シェルをフォアグラウンドに設定
-    fn set_shell_fg(&mut self, shell_tx: &SyncSender<ShellMsg>) {
-        // シェルがフォアグラウンドであることを示すために、fgをNoneに設定する
-        self.fg = None;
-        tcsetpgrp(libc::STDIN_FILENO, self.shell_pgid).unwrap();
-        shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap(); // シェルの入力を再開させる
+/// プロセス置換。<(cmd)または>(cmd)
+///
+/// cmdとの間のパイプを保持し、ドロップ時にシェル側のパイプをクローズする
+#[derive(Debug)]
+struct ProcSubst {
+    cmd_line: String, // 置換の中のコマンド
+    output: bool,     // >(cmd)なら真。置換先のコマンドはcmdの標準入力に書き込む
+    fd: RawFd,        // 置換先のコマンドに渡すパイプの端。/dev/fd/fdとして参照する
+    peer: RawFd,      // cmdの標準入出力とするパイプの端
+}
+
+impl Drop for ProcSubst {
+    fn drop(&mut self) {
+        let _ = syscall(|| unistd::close(self.fd));
+        let _ = syscall(|| unistd::close(self.peer));
     }
+}
 
-    /// 新たなジョブIDを取得
-    fn get_new_job_id(&self) -> Option<usize> {
-        for i in 0..=usize::MAX {
-            if !self.jobs.contains_key(&i) {
-                return Some(i);
-            }
+/// 行中のプロセス置換の位置。(置換の範囲, >(cmd)なら真, cmd)
+type ProcSubstPos<'a> = (Range<usize>, bool, &'a str);
+
+/// line中のプロセス置換の位置を探し、出現順に返す
+///
+/// プロセス置換は`<(`か`>(`で始まる単語で、対応する`)`までとする。入れ子にはできない
+fn find_proc_substs(line: &str) -> Result<Vec<ProcSubstPos<'_>>, DynError> {
+    let mut result = Vec::new();
+    let mut pos = 0;
+    while let Some(i) = line[pos..].find('(').map(|i| pos + i) {
+        pos = i + 1;
+        let op = &line[..i];
+        let output = if op.ends_with('>') {
+            true
+        } else if op.ends_with('<') {
+            false
+        } else {
+            continue;
+        };
+        // 演算子の前は行頭か空白でなければならない。2<(などはリダイレクトとする
+        let start = i - 1;
+        if !line[..start].is_empty() && !line[..start].ends_with(char::is_whitespace) {
+            continue;
         }
-        None
+
+        let len = line[pos..]
+            .find(')')
+            .ok_or_else(|| msg!(ProcSubstNoParen))?;
+        let inner = &line[pos..pos + len];
+        if inner.contains('(') {
+            return Err(msg!(ProcSubstNested).into());
+        }
+        if inner.trim().is_empty() {
+            return Err(msg!(ProcSubstEmpty).into());
+        }
+        pos += len + 1;
+        result.push((start..pos, output, inner.trim()));
     }
+    Ok(result)
 }
 
-type CmdResult<'a> = Result<Vec<(&'a str, Vec<&'a str>)>, DynError>;
+/// line中のプロセス置換ごとにパイプを作成し、置換を/dev/fd/Nに置き換えた行を返す
+fn open_proc_substs(line: &str) -> Result<(String, Vec<ProcSubst>), DynError> {
+    let mut expanded = String::new();
+    let mut substs = Vec::new();
+    let mut end = 0;
+    for (range, output, cmd_line) in find_proc_substs(line)? {
+        let (r, w) = syscall(|| pipe2(OFlag::O_CLOEXEC)).map_err(ShellError::syscall("pipe2"))?;
+        let (fd, peer) = if output { (w, r) } else { (r, w) };
+
+        // 子プロセスでクローズされる範囲を避けるため、SAVED_FD_MIN以上に移す
+        let moved = syscall(|| fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(SAVED_FD_MIN)));
+        let _ = syscall(|| unistd::close(fd));
+        let fd = match moved {
+            Ok(fd) => fd,
+            Err(e) => {
+                let _ = syscall(|| unistd::close(peer));
+                return Err(ShellError::Syscall("fcntl", e).into());
+            }
+        };
 
-/// コマンドをパース
-fn parse_cmd(line: &str) -> CmdResult {
-    let mut parsed_cmds = vec![];
+        expanded.push_str(&line[end..range.start]);
+        expanded.push_str(&format!("/dev/fd/{fd}"));
+        end = range.end;
+        substs.push(ProcSubst {
+            cmd_line: cmd_line.to_string(),
+            output,
+            fd,
+            peer,
+        });
+    }
+    expanded.push_str(&line[end..]);
+    Ok((expanded, substs))
+}
 
-    for cmd in line.split('|') {
-        let cmd = cmd.trim();
-        if cmd.is_empty() {
-            return Err("空のコマンド".into());
+/// リダイレクトを現在のプロセスに左から順に適用する
+///
+/// 子プロセスではexecの直前に、exec組み込みコマンドではシェル自身に対して呼び出される。
+/// noclobberが真の場合、`>`では既存の通常ファイルを上書きせずにエラーとする
+fn apply_redirects(redirects: &[Redirect], noclobber: bool) -> Result<(), DynError> {
+    for r in redirects {
+        let (path, flag) = match &r.kind {
+            RedirectKind::Read(path) => (path, OFlag::O_RDONLY),
+            RedirectKind::Write(path) if noclobber => {
+                // /dev/nullなどの通常ファイル以外には、切り詰めずに書き込める
+                // ファイルが存在しない場合は、確認後に作成された場合に備えてO_EXCLで作成する
+                match fs::metadata(path) {
+                    Ok(m) if m.is_file() => return Err(msg!(Noclobber, path).into()),
+                    Ok(_) => (path, OFlag::O_WRONLY),
+                    Err(_) => (path, OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_EXCL),
+                }
+            }
+            RedirectKind::Write(path) | RedirectKind::Clobber(path) => {
+                (path, OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC)
+            }
+            RedirectKind::Append(path) => {
+                (path, OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_APPEND)
+            }
+            RedirectKind::ReadWrite(path) => (path, OFlag::O_RDWR | OFlag::O_CREAT),
+            RedirectKind::Dup(fd) => {
+                // fdとr.fdが同じ場合もdup2はfdが有効かを検査する
+                syscall(|| dup2(*fd, r.fd)).map_err(|e| format!("{fd}: {e}"))?;
+                continue;
+            }
+            RedirectKind::Inherit => {
+                syscall(|| fcntl(r.fd, FcntlArg::F_SETFD(FdFlag::empty())))
+                    .map_err(|e| format!("{}: {e}", r.fd))?;
+                continue;
+            }
+            RedirectKind::Close => {
+                // すでにクローズされている場合(EBADF)はエラーとしない
+                match syscall(|| unistd::close(r.fd)) {
+                    Ok(()) | Err(nix::Error::EBADF) => continue,
+                    Err(e) => return Err(format!("{}: {e}", r.fd).into()),
+                }
+            }
+        };
+        let fd = syscall(|| open(*path, flag, Mode::from_bits_truncate(0o666)))
+            .map_err(|e| format!("{path}: {e}"))?;
+        if fd != r.fd {
+            syscall(|| dup2(fd, r.fd))?;
+            syscall(|| unistd::close(fd))?;
         }
-        let cmd_and_options: Vec<&str> = cmd.split_whitespace().collect();
-        let cmd = cmd_and_options[0];
-        let options = cmd_and_options[1..].to_vec();
-        parsed_cmds.push((cmd, options))
     }
-    Ok(parsed_cmds)
+    Ok(())
 }
 
 /// プロセスグループIDを指定してfork & exec
@@ -629,22 +3369,70 @@ fn parse_cmd(line: &str) -> CmdResult {
 ///
 /// - inputがSome(fd)の場合は、標準入力をfdと設定
 /// - outputがSome(fd)の場合は、標準出力をfdと設定
+/// - cmdのリダイレクトはパイプの設定後に、左から順に適用する。noclobberはapply_redirectsを参照
 fn fork_exec(
     pgid: Pid,
-    filename: &str,
-    args: &[&str],
+    filename: &Path,
+    cmd: &Cmd,
+    noclobber: bool,
+    ignored: &[Signal],
     input: Option<i32>,
     output: Option<i32>,
-) -> Result<Pid, DynError> {
-    let filename = CString::new(filename).unwrap();
-    let args: Vec<CString> = args.iter().map(|s| CString::new(*s).unwrap()).collect();
+) -> Result<Pid, ShellError> {
+    let filename = CString::new(filename.as_os_str().as_bytes())?;
+    let args = cmd
+        .args
+        .iter()
+        .map(|s| CString::new(s.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    fork_child(pgid, cmd, noclobber, ignored, input, output, || {
+        // 実行ファイルをメモリに読み込み
+        // nix::unistd::execv関数を呼び出し、実行ファイルを実行
+        // execvも同名のシステムコールのラッパであり、
+        // 第一引数に実行ファイルへのパスを、第２引数にコマンドライン引数を指定する
+        // パスは親プロセスで$PATHから解決済みなので、execvpではなくexecvを用いる
+        match execv(&filename, &args) {
+            Err(_) => {
+                // 標準エラー出力への書き込みにprintln!ではなく、write!を利用しているのは、
+                // fork後に安全に利用可能なシステムコールは限定されており、
+                // 内部でメモリ確保を行うprintln!の利用は避けるべきだからである。
+                // 詳細はman signal-safety
+                // https://qiita.com/rarul/items/090920b850acc4b7e910
+                unistd::write(libc::STDERR_FILENO, msg::text(MsgId::ExecFailed).as_bytes()).ok();
+                exit(1);
+            }
+            Ok(_) => unreachable!(),
+        }
+    })
+}
 
-    match syscall(|| unsafe { fork() })? {
+/// プロセスグループIDを指定してforkし、子プロセスでrunを実行
+///
+/// 子プロセスではrunの前に、fork_execと同様にプロセスグループ、標準入出力、リダイレクトを設定する。
+/// runはexecかexitを呼び出し、返らないようにする
+fn fork_child<F>(
+    pgid: Pid,
+    cmd: &Cmd,
+    noclobber: bool,
+    ignored: &[Signal],
+    input: Option<i32>,
+    output: Option<i32>,
+    run: F,
+) -> Result<Pid, ShellError>
+where
+    F: FnOnce(),
+{
+    match syscall(|| unsafe { fork() }).map_err(ShellError::syscall("fork"))? {
         // forkを呼び出し子プロセスを生成
         ForkResult::Parent { child, .. } => {
             // 子プロセスのプロセスグループIDをpgidに設定
-            setpgid(child, pgid).unwrap();
-            Ok(child)
+            // 子プロセスがすでにexecしている場合はEACCESとなるが、
+            // その場合は子プロセス側で設定済みなので問題ない
+            match setpgid(child, pgid) {
+                Ok(()) | Err(nix::Error::EACCES) => Ok(child),
+                Err(e) => Err(ShellError::Syscall("setpgid", e)),
+            }
         }
         ForkResult::Child => {
             // 子プロセスのプロセスグループIDをpgidに設定
@@ -653,6 +3441,9 @@ fn fork_exec(
             // 確実にプロセスグループIDを設定するためである
             setpgid(Pid::from_raw(0), pgid).unwrap();
 
+            // シェルが設定したシグナルの処理とマスクを、ignored以外はデフォルトに戻す
+            reset_signals(ignored);
+
             // 標準入出力を引数で与えられたものに置き換える
             // nix::unistd::dup2はシステムコールのラッパで、
             // 第一引数に元となるファイルディスクリプタを、
@@ -667,28 +3458,185 @@ fn fork_exec(
 
             // 標準入出力と標準エラー出力以外のファイルディスクリプタは不要なので
             // signal_hookで利用されるUnixドメインソケットとpipeをクローズ
+            // リダイレクトで3以上のファイルディスクリプタを設定できるように、リダイレクトの前に行う
             for i in 3..=6 {
                 let _ = syscall(|| unistd::close(i));
             }
 
-            // 実行ファイルをメモリに読み込み
-            // nix::unistd::execvp関数を呼び足、実行ファイルを実行
-            // execvpも同名のシステムコールのラッパであり、
-            // 第一引数に実行ファイルへのパスを、第２引数にコマンドライン引数を指定する
-            match execvp(&filename, &args) {
-                Err(_) => {
-                    // 標準エラー出力への書き込みにprintln!ではなく、write!を利用しているのは、
-                    // fork後に安全に利用可能なシステムコールは限定されており、
-                    // 内部でメモリ確保を行うprintln!の利用は避けるべきだからである。
-                    // 詳細はman signal-safety
-                    // https://qiita.com/rarul/items/090920b850acc4b7e910
-                    unistd::write(libc::STDERR_FILENO, "不明なコマンドを実行\n".as_bytes()).ok();
-                    exit(1);
+            // リダイレクトを適用
+            if let Err(e) = apply_redirects(&cmd.redirects, noclobber) {
+                let msg = format!("ZeroSh: {}\n", msg!(RedirectFailed, e));
+                unistd::write(libc::STDERR_FILENO, msg.as_bytes()).ok();
+                exit(1);
+            }
+
+            run();
+            exit(1);
+        }
+    }
+}
+
+/// 端末から切り離してfork & exec
+///
+/// 子プロセスはsetsidで新たなセッションのリーダーとなり、制御端末を持たない。
+/// また、SIGHUPを無視し、標準入力を/dev/nullとしてからcmdのリダイレクトを適用する。
+/// SIGHUPの無視はexec後も引き継がれる。
+fn fork_exec_detached(
+    filename: &Path,
+    cmd: &Cmd,
+    noclobber: bool,
+    ignored: &[Signal],
+) -> Result<Pid, ShellError> {
+    let filename = CString::new(filename.as_os_str().as_bytes())?;
+    let args = cmd
+        .args
+        .iter()
+        .map(|s| CString::new(s.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match syscall(|| unsafe { fork() }).map_err(ShellError::syscall("fork"))? {
+        ForkResult::Parent { child, .. } => Ok(child),
+        ForkResult::Child => {
+            // 新たなセッションを作成し、シェルの制御端末から切り離す
+            setsid().unwrap();
+
+            reset_signals(ignored);
+            unsafe { signal(Signal::SIGHUP, SigHandler::SigIgn).unwrap() };
+
+            // 標準入力を/dev/nullにする
+            if let Ok(fd) = open("/dev/null", OFlag::O_RDONLY, Mode::empty()) {
+                syscall(|| dup2(fd, libc::STDIN_FILENO)).unwrap();
+                let _ = syscall(|| unistd::close(fd));
+            }
+
+            // signal_hookで利用されるUnixドメインソケットなどをクローズ
+            for i in 3..=6 {
+                let _ = syscall(|| unistd::close(i));
+            }
+
+            if let Err(e) = apply_redirects(&cmd.redirects, noclobber) {
+                let msg = format!("ZeroSh: {}\n", msg!(RedirectFailed, e));
+                unistd::write(libc::STDERR_FILENO, msg.as_bytes()).ok();
+                exit(1);
+            }
+
+            let _ = execv(&filename, &args);
+            unistd::write(libc::STDERR_FILENO, msg::text(MsgId::ExecFailed).as_bytes()).ok();
+            exit(1);
+        }
+    }
+}
+
+/// fork後の子プロセスで、シグナルの処理をexecするコマンドのために設定し直す
+///
+/// CHILD_DEFAULT_SIGNALSをデフォルトの処理に戻してからignoredのシグナルを無視し、シグナルマスクを空にする。
+/// signalとsigprocmaskはasync-signal-safeなので、fork後の子プロセスで呼び出せる
+fn reset_signals(ignored: &[Signal]) {
+    for sig in CHILD_DEFAULT_SIGNALS {
+        let _ = unsafe { signal(*sig, SigHandler::SigDfl) };
+    }
+    for sig in ignored {
+        let _ = unsafe { signal(*sig, SigHandler::SigIgn) };
+    }
+    let _ = SigSet::empty().thread_set_mask();
+}
+
+/// シグナル名(INT、SIGINT)か番号(2)を解析する。大文字と小文字は区別しない
+fn parse_signal(s: &str) -> Option<Signal> {
+    if let Ok(n) = s.parse::<i32>() {
+        return Signal::try_from(n).ok();
+    }
+    let name = s.to_ascii_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{name}")
+    };
+    name.parse().ok()
+}
+
+/// $PATHを先頭から検索し、最初に見つかった実行可能ファイルのパスを返す
+fn search_path(name: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path))
+}
+
+/// 見つからなかったコマンド名nameに近い、組み込みコマンドと$PATH中のコマンドの名前を返す
+///
+/// 編集距離が最小のものを名前順に最大3つ返す。
+/// 編集距離が名前の長さの1/3(最低1)を超えるものは、打ち間違いではないとみなして候補にしない。
+/// 子プロセスではなく、fork前にシェルのプロセスで呼び出す
+fn suggest_cmd(name: &str) -> Vec<String> {
+    let max = (name.chars().count() / 3).max(1);
+
+    let mut names: Vec<String> = BUILTINS.iter().map(|s| s.to_string()).collect();
+    if let Some(paths) = env::var_os("PATH") {
+        for dir in env::split_paths(&paths) {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                if let Some(file) = entry.file_name().to_str() {
+                    if is_executable(&entry.path()) {
+                        names.push(file.to_string());
+                    }
                 }
-                Ok(_) => unreachable!(),
             }
         }
     }
+
+    let mut best = Vec::new();
+    let mut best_dist = max;
+    for candidate in names {
+        let d = edit_distance(name, &candidate);
+        if d < best_dist {
+            best_dist = d;
+            best.clear();
+        }
+        if d == best_dist {
+            best.push(candidate);
+        }
+    }
+    best.sort();
+    best.dedup();
+    best.truncate(3);
+    best
+}
+
+/// 文字列aとbの編集距離を返す
+///
+/// 1文字の挿入、削除、置換に加えて、打ち間違いで多い隣接する2文字の入れ替えも1回の操作とする
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // d[i][j]はaの先頭i文字とbの先頭j文字の編集距離
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, x) in d[0].iter_mut().enumerate() {
+        *x = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// 実行可能な通常ファイルなら真
+fn is_executable(path: &Path) -> bool {
+    path.is_file() && access(path, AccessFlags::X_OK).is_ok()
 }
 
 /// ドロップ時にクロージャfを呼び出す型
@@ -712,3 +3660,297 @@ where
         (self.f)()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cmd() {
+        let cmd = parse_cmd("ls -l | grep foo").unwrap();
+        assert_eq!(cmd.len(), 2);
+        assert_eq!(cmd[0].args, vec!["ls", "-l"]);
+        assert_eq!(cmd[1].args, vec!["grep", "foo"]);
+
+        assert!(parse_cmd("ls |").is_err());
+        assert!(parse_cmd("").is_err());
+    }
+
+    #[test]
+    fn test_incomplete() {
+        assert_eq!(incomplete("echo a \\"), Some(Incomplete::Backslash));
+        assert_eq!(incomplete("ls -l |"), Some(Incomplete::Pipe));
+        assert_eq!(incomplete("echo $((1 +"), Some(Incomplete::Paren));
+        assert_eq!(incomplete("cat <(ls"), Some(Incomplete::Paren));
+        assert_eq!(incomplete("echo $((1 + 2))"), None);
+        assert_eq!(incomplete("echo a >| b"), None);
+        assert_eq!(incomplete("echo a)"), None); // 不正な入力は実行時にエラー
+
+        let mut line = "echo a\\".to_string();
+        join_line(&mut line, "b |");
+        assert_eq!(line, "echo ab |");
+        join_line(&mut line, "wc -c");
+        assert_eq!(line, "echo ab | wc -c");
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("grep", "grep"), 0);
+        assert_eq!(edit_distance("gerp", "grep"), 1); // 入れ替え
+        assert_eq!(edit_distance("gre", "grep"), 1); // 削除
+        assert_eq!(edit_distance("grepp", "grep"), 1); // 挿入
+        assert_eq!(edit_distance("grap", "grep"), 1); // 置換
+        assert_eq!(edit_distance("", "ls"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_suggest_cmd() {
+        assert_eq!(suggest_cmd("hsah"), vec!["hash"]);
+        assert!(suggest_cmd("sourc").contains(&"source".to_string()));
+        assert!(suggest_cmd("zzzzzzzzzz").is_empty());
+    }
+
+    #[test]
+    fn test_update_rc() {
+        assert_eq!(update_rc("", &["autocd"], true), "shopt -s autocd\n");
+
+        // 同じオプションを設定する行は置き換え、それ以外の行は残す
+        let text = "cd /tmp\nshopt -u autocd\nshopt -s huponexit\n";
+        assert_eq!(
+            update_rc(text, &["autocd"], true),
+            "cd /tmp\nshopt -s huponexit\nshopt -s autocd\n"
+        );
+        assert_eq!(
+            update_rc(text, &["autocd", "huponexit"], false),
+            "cd /tmp\nshopt -u autocd\nshopt -u huponexit\n"
+        );
+    }
+
+    #[test]
+    fn test_shell_options() {
+        // emacsとviは排他
+        let mut options = ShellOptions::default();
+        assert!(options.emacs && !options.vi);
+        assert!(options.set("vi", true));
+        assert!(!options.emacs && options.vi);
+        assert!(options.set("vi", false));
+        assert!(options.emacs && !options.vi);
+        assert!(options.set("emacs", false));
+        assert!(!options.emacs && options.vi);
+        assert!(!options.set("nosuchopt", true));
+    }
+
+    #[test]
+    fn test_update_rc_binding() {
+        let text = "bind \\C-t kill-line\nbind -s \\M-g git status\nbind \\C-x undo\n";
+        let action = Action::Macro("ls -l".to_string());
+        assert_eq!(
+            update_rc_binding(text, "\\C-t", Some(&action)),
+            "bind -s \\M-g git status\nbind \\C-x undo\nbind -s \\C-t ls -l\n"
+        );
+        assert_eq!(
+            update_rc_binding(text, "\\M-g", None),
+            "bind \\C-t kill-line\nbind \\C-x undo\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("5"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86400)));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("s"), None);
+        assert_eq!(parse_duration("-1"), None);
+        assert_eq!(parse_duration("5x"), None);
+    }
+
+    #[test]
+    fn test_parse_redirect_fd() {
+        let cmd = parse_cmd("cmd 3<&0 2>&1 2>err 1>&- 4<>rw <&5").unwrap();
+        assert_eq!(cmd[0].args, vec!["cmd"]);
+        let redirects: Vec<(RawFd, RedirectKind)> = cmd[0]
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.kind.clone()))
+            .collect();
+        assert_eq!(
+            redirects,
+            vec![
+                (3, RedirectKind::Dup(0)),
+                (2, RedirectKind::Dup(1)),
+                (2, RedirectKind::Write("err")),
+                (1, RedirectKind::Close),
+                (4, RedirectKind::ReadWrite("rw")),
+                (0, RedirectKind::Dup(5)),
+            ]
+        );
+
+        // 数字のみの引数はリダイレクトではない
+        assert_eq!(parse_cmd("sleep 10").unwrap()[0].args, vec!["sleep", "10"]);
+        assert!(parse_cmd("cmd >&file").is_err());
+        assert!(parse_cmd("cmd 2>&").is_err());
+    }
+
+    #[test]
+    fn test_expand_args() {
+        let mut cmd = parse_cmd("echo $? x$?y | grep $??").unwrap();
+        expand_args(&mut cmd, 3);
+        assert_eq!(cmd[0].args, vec!["echo", "3", "x3y"]);
+        assert_eq!(cmd[1].args, vec!["grep", "3?"]);
+
+        let lookup = |name: &str| match name {
+            "?" => Some("1".to_string()),
+            "opt" => Some("v".to_string()),
+            _ => None,
+        };
+        assert_eq!(expand_vars("-$opt:$OPTARG:$?", &lookup), "-v::1");
+        assert_eq!(expand_vars("$opt_x${opt}", &lookup), "${opt}");
+        assert_eq!(expand_vars("$ $1 a$", &lookup), "$ $1 a$");
+    }
+
+    #[test]
+    fn test_find_proc_substs() {
+        let line = "diff <(sort a) <( sort b ) >(tee c)";
+        let substs = find_proc_substs(line).unwrap();
+        let found: Vec<(&str, bool, &str)> = substs
+            .iter()
+            .map(|(r, output, cmd)| (&line[r.clone()], *output, *cmd))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("<(sort a)", false, "sort a"),
+                ("<( sort b )", false, "sort b"),
+                (">(tee c)", true, "tee c"),
+            ]
+        );
+
+        // 単語の途中はプロセス置換ではない
+        assert!(find_proc_substs("cat 2<(a) x<(b)").unwrap().is_empty());
+        assert!(find_proc_substs("cat <(sort a").is_err());
+        assert!(find_proc_substs("cat <(sort <(a))").is_err());
+        assert!(find_proc_substs("cat <( )").is_err());
+    }
+
+    #[test]
+    fn test_parse_redirect() {
+        let cmd = parse_cmd("sort <in >out >> log").unwrap();
+        assert_eq!(cmd[0].args, vec!["sort"]);
+        assert_eq!(
+            cmd[0].redirects,
+            vec![
+                Redirect {
+                    fd: 0,
+                    kind: RedirectKind::Read("in")
+                },
+                Redirect {
+                    fd: 1,
+                    kind: RedirectKind::Write("out")
+                },
+                Redirect {
+                    fd: 1,
+                    kind: RedirectKind::Append("log")
+                },
+            ]
+        );
+
+        assert!(parse_cmd("exec >").is_err());
+        assert!(parse_cmd("> out").is_err());
+
+        // >|はパイプではない
+        let cmd = parse_cmd("echo a >|out | cat 2>| err").unwrap();
+        assert_eq!(cmd.len(), 2);
+        assert_eq!(cmd[0].redirects[0].kind, RedirectKind::Clobber("out"));
+        assert_eq!(
+            cmd[1].redirects[0],
+            Redirect {
+                fd: 2,
+                kind: RedirectKind::Clobber("err")
+            }
+        );
+    }
+
+    #[test]
+    fn test_umask_symbolic() {
+        assert_eq!(
+            umask_symbolic(Mode::from_bits_truncate(0o022)),
+            "u=rwx,g=rx,o=rx"
+        );
+        assert_eq!(
+            umask_symbolic(Mode::from_bits_truncate(0o077)),
+            "u=rwx,g=,o="
+        );
+        assert_eq!(
+            umask_symbolic(Mode::from_bits_truncate(0o000)),
+            "u=rwx,g=rwx,o=rwx"
+        );
+    }
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("INT"), Some(Signal::SIGINT));
+        assert_eq!(parse_signal("sighup"), Some(Signal::SIGHUP));
+        assert_eq!(parse_signal("SIGTTOU"), Some(Signal::SIGTTOU));
+        assert_eq!(parse_signal("15"), Some(Signal::SIGTERM));
+        assert_eq!(parse_signal("0"), None);
+        assert_eq!(parse_signal("NOSUCH"), None);
+    }
+
+    #[test]
+    fn test_cmd_status() {
+        assert_eq!(CmdStatus::Exited(3).code(), 3);
+        assert_eq!(CmdStatus::Signaled(Signal::SIGINT).code(), 130);
+        assert_eq!(CmdStatus::Stopped(Signal::SIGTSTP).code(), 148);
+    }
+
+    #[test]
+    fn test_job_count() {
+        let count = |total, stopped| JobCount { total, stopped }.to_string();
+        assert_eq!(count(0, 0), "");
+        assert_eq!(count(1, 0), "[ジョブ1個] ");
+        assert_eq!(count(2, 1), "[ジョブ2個, 停止中1個] ");
+    }
+
+    #[test]
+    fn test_parse_job_spec() {
+        let mut worker = Worker::new(false, Arc::default(), Arc::default());
+        for (job_id, line) in [(1, "sleep 100"), (2, "vim foo"), (3, "sleep 200")] {
+            worker.jobs.insert(
+                job_id,
+                Job {
+                    pgid: Pid::from_raw(job_id as i32),
+                    line: line.to_string(),
+                    start: Instant::now(),
+                    last_pid: Pid::from_raw(job_id as i32),
+                    status: CmdStatus::Exited(0),
+                    timeout: None,
+                    usage: Usage::default(),
+                    winch: false,
+                },
+            );
+            worker.set_current_job(job_id);
+        }
+
+        assert_eq!(worker.parse_job_spec("2"), Ok(2));
+        assert_eq!(worker.parse_job_spec("%1"), Ok(1));
+        assert_eq!(worker.parse_job_spec("%%"), Ok(3));
+        assert_eq!(worker.parse_job_spec("%+"), Ok(3));
+        assert_eq!(worker.parse_job_spec("%-"), Ok(2));
+        assert_eq!(worker.parse_job_spec("%vim"), Ok(2));
+        assert!(worker.parse_job_spec("%sleep").is_err()); // 複数のジョブに該当
+        assert!(worker.parse_job_spec("%emacs").is_err());
+        assert!(worker.parse_job_spec("%4").is_err());
+        assert!(worker.parse_job_spec("foo").is_err());
+
+        // カレントジョブを削除すると、直前のジョブがカレントジョブになる
+        worker.remove_job(3);
+        assert_eq!(worker.parse_job_spec("%%"), Ok(2));
+        assert_eq!(worker.parse_job_spec("%-"), Ok(1));
+        assert_eq!(worker.parse_job_spec("%sleep"), Ok(1));
+    }
+}
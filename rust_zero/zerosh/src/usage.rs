@@ -0,0 +1,91 @@
+//! ジョブのリソース使用量
+//!
+//! wait4で回収したプロセスのCPU時間と最大RSSを、ジョブごとに合計する。
+//! パイプラインの各プロセスのCPU時間は足し合わせ、最大RSSはその最大値とする。
+//! 実行中のジョブの使用量には、回収済みのプロセスの分のみが含まれる
+
+use crate::msg::msg;
+use nix::libc;
+use std::{fmt, time::Duration};
+
+/// ジョブのリソース使用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub utime: Duration, // ユーザモードのCPU時間
+    pub stime: Duration, // カーネルモードのCPU時間
+    pub maxrss: u64,     // 最大RSS(KB)
+}
+
+impl Usage {
+    /// wait4で取得したリソース使用量から生成
+    pub fn from_rusage(ru: &libc::rusage) -> Self {
+        let time = |tv: libc::timeval| {
+            Duration::from_secs(tv.tv_sec.max(0) as u64)
+                + Duration::from_micros(tv.tv_usec.max(0) as u64)
+        };
+        Usage {
+            utime: time(ru.ru_utime),
+            stime: time(ru.ru_stime),
+            maxrss: ru.ru_maxrss.max(0) as u64, // Linuxでは単位はKB
+        }
+    }
+
+    /// 同じジョブのプロセスの使用量otherを加える
+    pub fn add(&mut self, other: &Usage) {
+        self.utime += other.utime;
+        self.stime += other.stime;
+        self.maxrss = self.maxrss.max(other.maxrss);
+    }
+}
+
+/// jobs -lとjobstatsで表示する、経過時間とリソース使用量
+#[derive(Debug, Clone, Copy)]
+pub struct JobStats {
+    pub elapsed: Duration, // ジョブの開始からの経過時間
+    pub usage: Usage,      // 回収済みのプロセスのリソース使用量
+}
+
+impl fmt::Display for JobStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = |d: Duration| format!("{:.3}", d.as_secs_f64());
+        let stats = msg!(
+            JobStats,
+            secs(self.elapsed),
+            secs(self.usage.utime),
+            secs(self.usage.stime),
+            self.usage.maxrss
+        );
+        write!(f, "{stats}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage() {
+        let mut usage = Usage {
+            utime: Duration::from_millis(1500),
+            stime: Duration::from_millis(20),
+            maxrss: 2048,
+        };
+        usage.add(&Usage {
+            utime: Duration::from_millis(250),
+            stime: Duration::from_millis(5),
+            maxrss: 1024,
+        });
+        assert_eq!(usage.utime, Duration::from_millis(1750));
+        assert_eq!(usage.stime, Duration::from_millis(25));
+        assert_eq!(usage.maxrss, 2048);
+
+        let stats = JobStats {
+            elapsed: Duration::from_millis(2001),
+            usage,
+        };
+        assert_eq!(
+            stats.to_string(),
+            "経過 2.001秒, user 1.750秒, sys 0.025秒, 最大RSS 2048KB"
+        );
+    }
+}
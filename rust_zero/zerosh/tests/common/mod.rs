@@ -0,0 +1,303 @@
+//! 擬似端末(PTY)上でZeroShを実行し、入力を送って出力を検査するためのテストハーネス
+//!
+//! ジョブ制御は端末のフォアグラウンドプロセスグループやCtrl+Z、Ctrl+Cによるシグナルに
+//! 依存するため、パイプではなく擬似端末を制御端末としてZeroShを起動する。
+//!
+//! テストファイルごとに別のクレートとしてコンパイルされ、使われない関数があるので警告を抑制する
+
+#![allow(dead_code)]
+
+use nix::{
+    libc,
+    poll::{poll, PollFd, PollFlags},
+    pty::{openpty, Winsize},
+    sys::signal::{kill, Signal},
+    unistd::{self, setsid, tcgetpgrp, Pid},
+};
+use std::{
+    env, fs,
+    os::unix::{
+        io::{FromRawFd, RawFd},
+        process::CommandExt,
+    },
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+/// 出力を待つ時間の上限
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// プロンプトの末尾
+pub const PROMPT: &str = "&> ";
+
+/// Ctrl+C
+pub const CTRL_C: &str = "\x03";
+
+/// Ctrl+Z
+pub const CTRL_Z: &str = "\x1a";
+
+/// Ctrl+D
+pub const CTRL_D: &str = "\x04";
+
+/// テストごとに異なるHOMEディレクトリを作るための連番
+static HOME_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// 擬似端末上で実行中のZeroSh
+pub struct Zerosh {
+    child: Child,   // ZeroShのプロセス
+    master: RawFd,  // 擬似端末のマスタ側
+    home: PathBuf,  // ヒストリファイルを置くHOMEディレクトリ
+    output: String, // これまでに読み込んだ出力
+    pos: usize,     // outputのうち、expectで検査済みの位置
+}
+
+impl Zerosh {
+    /// ZeroShを起動し、最初のプロンプトが表示されるまで待つ
+    pub fn spawn() -> Self {
+        Self::spawn_with_env(&[])
+    }
+
+    /// 環境変数varsを設定してZeroShを起動し、最初のプロンプトが表示されるまで待つ
+    pub fn spawn_with_env(vars: &[(&str, &str)]) -> Self {
+        Self::launch(vars, &[], false)
+    }
+
+    /// rcファイルの内容をrcとしてZeroShを起動し、最初のプロンプトが表示されるまで待つ
+    pub fn spawn_with_rc(rc: &str) -> Self {
+        Self::launch(&[], &[(".zeroshrc", rc)], false)
+    }
+
+    /// プロファイルの内容をprofileとして、ZeroShをログインシェルとして起動し、
+    /// 最初のプロンプトが表示されるまで待つ
+    pub fn spawn_login(profile: &str) -> Self {
+        Self::launch(&[], &[(".zerosh_profile", profile)], true)
+    }
+
+    /// HOMEディレクトリにファイルfiles(名前, 内容)を作成してからZeroShを起動する
+    ///
+    /// loginが真ならargv[0]を-zeroshとし、ログインシェルとして起動する
+    fn launch(vars: &[(&str, &str)], files: &[(&str, &str)], login: bool) -> Self {
+        // ヒストリファイルやrcファイルがテスト間で共有されないように、HOMEを一時ディレクトリにする
+        let home = env::temp_dir().join(format!(
+            "zerosh-test-{}-{}",
+            std::process::id(),
+            HOME_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&home).unwrap();
+        for (name, content) in files {
+            fs::write(home.join(name), content).unwrap();
+        }
+
+        // 端末の幅が0だと、rustylineが長い行を折り返して再描画してしまうため、十分な大きさにする
+        let winsize = Winsize {
+            ws_row: 50,
+            ws_col: 200,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let pty = openpty(Some(&winsize), None).unwrap();
+        let slave = pty.slave;
+        let stdio = || unsafe { Stdio::from_raw_fd(unistd::dup(slave).unwrap()) };
+
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_zerosh"));
+        cmd.env("HOME", &home)
+            .env_remove("ZEROSH_NOTIFY_CMD")
+            .env_remove("ZEROSH_HUPONEXIT")
+            // メッセージは日本語で検査する
+            .env_remove("LC_ALL")
+            .env_remove("LC_MESSAGES")
+            .env("LANG", "ja_JP.UTF-8")
+            .envs(vars.iter().copied())
+            .stdin(stdio())
+            .stdout(stdio())
+            .stderr(stdio());
+        if login {
+            cmd.arg0("-zerosh");
+        }
+        unsafe {
+            cmd.pre_exec(move || {
+                // 新たなセッションを作成し、擬似端末を制御端末にする
+                setsid()?;
+                if libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = cmd.spawn().unwrap();
+        unistd::close(slave).unwrap();
+
+        let mut sh = Zerosh {
+            child,
+            master: pty.master,
+            home,
+            output: String::new(),
+            pos: 0,
+        };
+        sh.expect(PROMPT);
+        sh
+    }
+
+    /// テスト用のHOMEディレクトリにファイルを作成し、そのパスを返す
+    ///
+    /// ZeroShはクォートをサポートしていないため、空白を含む引数が必要な場合はスクリプトファイルを利用する
+    pub fn write_file(&self, name: &str, content: &str) -> String {
+        let path = self.home.join(name);
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// テスト用のHOMEディレクトリにあるファイルの内容を返す
+    pub fn read_file(&self, name: &str) -> String {
+        fs::read_to_string(self.home.join(name)).unwrap()
+    }
+
+    /// 入力を送信する
+    pub fn send(&mut self, s: &str) {
+        let mut buf = s.as_bytes();
+        while !buf.is_empty() {
+            let n = unistd::write(self.master, buf).unwrap();
+            buf = &buf[n..];
+        }
+    }
+
+    /// 1行のコマンドを送信し、そのエコーバックを読み飛ばす
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.send("\r");
+        self.expect(line);
+    }
+
+    /// 前回のexpect以降の出力にpatternが現れるまで待ち、patternまでの出力を返す
+    ///
+    /// TIMEOUTを過ぎても現れない場合は、それまでの出力を表示してパニックする
+    pub fn expect(&mut self, pattern: &str) -> String {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            if let Some(i) = self.output[self.pos..].find(pattern) {
+                let end = self.pos + i + pattern.len();
+                let s = self.output[self.pos..end].to_string();
+                self.pos = end;
+                return s;
+            }
+
+            let now = Instant::now();
+            if now >= deadline || !self.read(deadline - now) {
+                panic!(
+                    "\"{pattern}\"が出力されませんでした\n出力:\n{}",
+                    &self.output[self.pos..]
+                );
+            }
+        }
+    }
+
+    /// 出力を読み込んでoutputに追加する。読み込めなかった場合は偽を返す
+    fn read(&mut self, timeout: Duration) -> bool {
+        let mut fds = [PollFd::new(self.master, PollFlags::POLLIN)];
+        match poll(&mut fds, timeout.as_millis() as i32) {
+            Ok(n) if n > 0 => (),
+            _ => return false,
+        }
+
+        let mut buf = [0; 4096];
+        match unistd::read(self.master, &mut buf) {
+            Ok(n) if n > 0 => {
+                // 端末制御のエスケープシーケンスは検査の邪魔になるので取り除く
+                let s = String::from_utf8_lossy(&buf[..n]);
+                self.output.push_str(&strip_escape(&s));
+                true
+            }
+            _ => false, // EOFか、ZeroShが終了してスレーブ側が閉じられた(EIO)
+        }
+    }
+
+    /// 端末の大きさをrows行cols列に変更する
+    ///
+    /// カーネルは端末のフォアグラウンドプロセスグループにSIGWINCHを送信する
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        let winsize = Winsize {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        if unsafe { libc::ioctl(self.master, libc::TIOCSWINSZ, &winsize) } < 0 {
+            panic!(
+                "TIOCSWINSZに失敗しました: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
+    /// ジョブが端末のフォアグラウンドになるまで待つ
+    ///
+    /// Ctrl+ZやCtrl+Cは端末のフォアグラウンドプロセスグループに送られるため、
+    /// コマンドの送信直後に送ると、ジョブではなくシェルに届いてしまう。
+    /// Linuxではマスタ側に対するtcgetpgrpでスレーブ側のフォアグラウンドプロセスグループを取得できる
+    pub fn wait_foreground_job(&mut self) {
+        let shell = Pid::from_raw(self.child.id() as i32);
+        let deadline = Instant::now() + TIMEOUT;
+        while !matches!(tcgetpgrp(self.master), Ok(pgid) if pgid != shell) {
+            if Instant::now() >= deadline {
+                panic!(
+                    "ジョブがフォアグラウンドになりませんでした\n出力:\n{}",
+                    self.output
+                );
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// ZeroShの終了を待ち、終了ステータスを返す
+    pub fn wait(&mut self) -> ExitStatus {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            if Instant::now() >= deadline {
+                panic!("ZeroShが終了しませんでした\n出力:\n{}", self.output);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for Zerosh {
+    fn drop(&mut self) {
+        // 終了していない場合は、ZeroShとそのジョブを強制終了する
+        if let Ok(None) = self.child.try_wait() {
+            let pid = Pid::from_raw(self.child.id() as i32);
+            kill(pid, Signal::SIGKILL).ok();
+            self.child.wait().ok();
+        }
+        unistd::close(self.master).ok();
+        fs::remove_dir_all(&self.home).ok();
+    }
+}
+
+/// CSIエスケープシーケンスと、キャリッジリターンを取り除く
+fn strip_escape(s: &str) -> String {
+    let mut result = String::new();
+    let mut it = s.chars().peekable();
+    while let Some(c) = it.next() {
+        match c {
+            '\x1b' => {
+                if it.peek() == Some(&'[') {
+                    // ESC [ パラメータ 終端文字(0x40..=0x7e)
+                    it.next();
+                    for c in it.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\r' => (),
+            c => result.push(c),
+        }
+    }
+    result
+}
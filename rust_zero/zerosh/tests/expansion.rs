@@ -0,0 +1,74 @@
+//! 擬似端末上でZeroShを実行し、コマンドラインの展開を検査する
+
+mod common;
+
+use common::{Zerosh, PROMPT};
+
+#[test]
+fn test_arithmetic_expansion() {
+    let mut sh = Zerosh::spawn_with_env(&[("N", "41")]);
+
+    sh.send_line("echo $((1 + 2 * 3)) $(( (1 + 2) * 3 ))");
+    sh.expect("7 9");
+    sh.expect(PROMPT);
+
+    // 環境変数と$?を参照できる
+    sh.send_line("echo $((N + 1)) $(($N > 40))");
+    sh.expect("42 1");
+    sh.expect(PROMPT);
+    sh.send_line("false");
+    sh.expect(PROMPT);
+    sh.send_line("echo $(($? + 1))");
+    sh.expect("2");
+    sh.expect(PROMPT);
+
+    // $?は引数ごとに展開し、プロセス置換の中のコマンドは書き換えない
+    sh.send_line("false");
+    sh.expect(PROMPT);
+    sh.send_line("echo $? x$?y");
+    sh.expect("1 x1y");
+    sh.expect(PROMPT);
+    sh.send_line("false");
+    sh.expect(PROMPT);
+    sh.send_line("cat <(echo $?) | wc -c");
+    sh.expect("3");
+    sh.expect(PROMPT);
+
+    // エラーの場合はコマンドを実行しない
+    sh.send_line("echo $((1 / 0)) not executed");
+    let out = sh.expect(PROMPT);
+    assert!(out.contains("0による除算"), "{out}");
+    assert!(!out.contains("not executed"), "{out}");
+}
+
+#[test]
+fn test_line_continuation() {
+    let mut sh = Zerosh::spawn();
+
+    // 行末の\\は取り除いて次の行と連結する
+    sh.send_line("echo one\\");
+    sh.expect("> ");
+    sh.send_line("two three");
+    sh.expect("onetwo three");
+    sh.expect(PROMPT);
+
+    // 閉じられていない括弧や、行末の|の場合も続きを読み込む
+    sh.send_line("echo $((1 +");
+    sh.expect("> ");
+    sh.send_line("2)) |");
+    sh.expect("> ");
+    sh.send_line("cat");
+    sh.expect("3");
+    sh.expect(PROMPT);
+
+    // 連結した行をヒストリに追加する
+    sh.send_line("echo a \\");
+    sh.expect("> ");
+    sh.send_line("b");
+    sh.expect(PROMPT);
+    sh.send_line("exit");
+    sh.wait();
+    assert!(sh
+        .read_file(".zerosh_history")
+        .ends_with("echo a b\nexit\n"));
+}
@@ -0,0 +1,51 @@
+//! 擬似端末上でZeroShを実行し、getoptsコマンドによるオプションの解析を検査する
+
+mod common;
+
+use common::{Zerosh, PROMPT};
+
+#[test]
+fn test_getopts() {
+    let mut sh = Zerosh::spawn();
+
+    // 呼び出すたびにオプションを1つ取り出し、OPTARGとOPTINDを設定する
+    sh.send_line("getopts vo: opt -v -o out file");
+    sh.expect(PROMPT);
+    sh.send_line("printenv opt OPTIND");
+    sh.expect("v\n2\n");
+    sh.expect(PROMPT);
+    sh.send_line("getopts vo: opt -v -o out file");
+    sh.expect(PROMPT);
+    sh.send_line("printenv opt OPTARG");
+    sh.expect("o\nout\n");
+    sh.expect(PROMPT);
+    // 変数は$nameで参照できる
+    sh.send_line("echo opt=$opt,arg=$OPTARG,ind=$OPTIND");
+    sh.expect("opt=o,arg=out,ind=4");
+    sh.expect(PROMPT);
+    sh.send_line("echo $((OPTIND))");
+    sh.expect("4");
+    sh.expect(PROMPT);
+
+    // オプションの終わりでは?を設定して1を返す
+    sh.send_line("getopts vo: opt -v -o out file");
+    sh.expect(PROMPT);
+    sh.send_line("echo $? $((OPTIND))");
+    sh.expect("1 4");
+    sh.expect(PROMPT);
+    sh.send_line("printenv opt");
+    sh.expect("?\n");
+    sh.expect(PROMPT);
+
+    // 不正なオプションはエラーを表示する。:で始まる場合は表示せずOPTARGに設定する
+    // 前回と異なる引数は最初から解析する
+    sh.send_line("getopts vo: opt -x");
+    let out = sh.expect(PROMPT);
+    assert!(out.contains("不正なオプションです -- x"), "{out}");
+    sh.send_line("getopts :vo: opt -y");
+    let out = sh.expect(PROMPT);
+    assert!(!out.contains("不正なオプション"), "{out}");
+    sh.send_line("printenv opt OPTARG");
+    sh.expect("?\ny\n");
+    sh.expect(PROMPT);
+}
@@ -0,0 +1,114 @@
+//! 擬似端末上でZeroShを実行し、ヒストリファイルの保存を検査する
+
+mod common;
+
+use common::{Zerosh, PROMPT};
+use std::path::Path;
+
+#[test]
+fn test_concurrent_sessions() {
+    let mut sh1 = Zerosh::spawn();
+    sh1.send_line("echo old");
+    sh1.expect(PROMPT);
+    sh1.send_line("exit");
+    assert!(sh1.wait().success());
+
+    // 2つのセッションを同時に実行し、両方のヒストリを残す
+    let history = sh1.write_file("dummy", "");
+    let home = Path::new(&history).parent().unwrap().to_str().unwrap();
+    let mut sh2 = Zerosh::spawn_with_env(&[("HOME", home)]);
+    let mut sh3 = Zerosh::spawn_with_env(&[("HOME", home)]);
+    sh2.send_line("echo two");
+    sh2.expect(PROMPT);
+    sh3.send_line("echo three");
+    sh3.expect(PROMPT);
+    sh3.send_line("echo old");
+    sh3.expect(PROMPT);
+
+    sh2.send_line("exit");
+    assert!(sh2.wait().success());
+    sh3.send_line("exit");
+    assert!(sh3.wait().success());
+
+    // 同じ行は最後のもののみ残す
+    assert_eq!(
+        sh1.read_file(".zerosh_history"),
+        "#V2\necho two\necho three\necho old\nexit\n"
+    );
+}
+
+#[test]
+fn test_history_builtin() {
+    let mut sh = Zerosh::spawn();
+    sh.send_line("echo one");
+    sh.expect(PROMPT);
+    sh.send_line("echo two");
+    sh.expect(PROMPT);
+    sh.send_line("history");
+    sh.expect("    1  echo one");
+    sh.expect("    2  echo two");
+    sh.expect("    3  history");
+    sh.expect(PROMPT);
+
+    // !nは展開した行を表示してから実行する
+    sh.send_line("!1");
+    sh.expect("echo one");
+    sh.expect("one");
+    sh.expect(PROMPT);
+    sh.send_line("history 2");
+    sh.expect("    4  echo one");
+    sh.expect("    5  history 2");
+    sh.expect(PROMPT);
+
+    // 消去すると番号は1から振り直す
+    sh.send_line("history -c");
+    sh.expect(PROMPT);
+    sh.send_line("history");
+    sh.expect("    1  history");
+    sh.expect(PROMPT);
+    sh.send_line("!99");
+    sh.expect("!99: イベントが見つかりません");
+    sh.expect(PROMPT);
+
+    sh.send_line("exit");
+    assert!(sh.wait().success());
+}
+
+#[test]
+fn test_substitution_confirm() {
+    let mut sh = Zerosh::spawn();
+    sh.send_line("echo foo");
+    sh.expect(PROMPT);
+
+    // 置換した行は差分を表示し、Enterで実行する
+    sh.send_line("!!:s/foo/bar/");
+    sh.expect("- echo foo");
+    sh.expect("+ echo bar");
+    sh.expect("Ctrl+Cで取り消し: ");
+    sh.send_line("");
+    sh.expect("bar");
+    sh.expect(PROMPT);
+
+    // Enter以外を入力すると取り消し、ヒストリにも追加しない
+    sh.send_line("fc -s bar=baz");
+    sh.expect("Enterで実行");
+    sh.send_line("n");
+    sh.expect("置換した行の実行を取り消しました");
+    sh.expect(PROMPT);
+
+    // 確認しない設定では、展開した行を表示してそのまま実行する
+    sh.send_line("shopt -u histconfirm");
+    sh.expect(PROMPT);
+    sh.send_line("fc -s bar=qux echo");
+    sh.expect("echo qux");
+    sh.expect("qux");
+    sh.expect(PROMPT);
+    sh.send_line("history 4");
+    sh.expect("    2  echo bar");
+    sh.expect("    3  shopt -u histconfirm");
+    sh.expect("    4  echo qux");
+    sh.expect(PROMPT);
+
+    sh.send_line("exit");
+    assert!(sh.wait().success());
+}
@@ -0,0 +1,58 @@
+//! 擬似端末上でZeroShを実行し、hookコマンドで登録したpreexecとprecmdフックを検査する
+
+mod common;
+
+use common::{Zerosh, CTRL_D, PROMPT};
+
+#[test]
+fn test_hook() {
+    // rcファイルで登録したフックは、最初のプロンプトから有効になる
+    let mut sh = Zerosh::spawn_with_rc(
+        "hook preexec echo before:\nhook precmd printenv ZEROSH_CMD_STATUS ZEROSH_CMD\n",
+    );
+
+    // preexecは実行する行を引数に受け取り、precmdは終了コードと行を環境変数で受け取る
+    sh.send_line("ls /nonexistent");
+    sh.expect("before: ls /nonexistent\n");
+    sh.expect("2\nls /nonexistent\n");
+    sh.expect(PROMPT);
+
+    // フックの終了コードは$?に影響しない
+    sh.send_line("echo $?");
+    sh.expect("2");
+    sh.expect(PROMPT);
+
+    // 一覧はrcファイルに書ける形式で表示する
+    sh.send_line("hook");
+    sh.expect("hook preexec echo before:");
+    sh.expect("hook precmd printenv ZEROSH_CMD_STATUS ZEROSH_CMD");
+    sh.expect(PROMPT);
+
+    // -rでその種類のフックを削除する
+    sh.send_line("hook -r preexec");
+    sh.expect(PROMPT);
+    sh.send_line("echo hello");
+    let out = sh.expect(PROMPT);
+    assert!(!out.contains("before:"), "{out}");
+    assert!(out.contains("0\necho hello\n"), "{out}");
+
+    sh.send_line("hook postexec echo x");
+    let out = sh.expect(PROMPT);
+    assert!(out.contains("preexecかprecmdを指定してください"), "{out}");
+}
+
+#[test]
+fn test_hook_typed_only() {
+    // フックはプロンプトで入力された行に対してのみ実行する
+    // フックの引数は登録時に展開する
+    let mut sh = Zerosh::spawn_with_rc("hook preexec sh $HOME/log.sh\n");
+    sh.write_file("log.sh", "echo \"$1\" >> \"$HOME/preexec.log\"\n");
+
+    sh.send_line("echo hello");
+    sh.expect(PROMPT);
+
+    // Ctrl+dによるexitではpreexecを実行しない
+    sh.send(CTRL_D);
+    assert!(sh.wait().success());
+    assert_eq!(sh.read_file("preexec.log"), "echo hello\n");
+}
@@ -0,0 +1,531 @@
+//! 擬似端末上でZeroShを実行し、ジョブ制御の振る舞いを検査する
+
+mod common;
+
+use common::{Zerosh, CTRL_C, CTRL_D, CTRL_Z, PROMPT};
+
+#[test]
+fn test_run_command() {
+    let mut sh = Zerosh::spawn();
+    sh.send_line("echo hello");
+    sh.expect("hello");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_prompt_job_count() {
+    let mut sh = Zerosh::spawn();
+
+    // ジョブがない場合はジョブの数を表示しない
+    sh.send_line("echo hello");
+    sh.expect("hello\nZeroSh \u{1F642} &> ");
+
+    sh.send_line("sleep 10 &");
+    sh.expect("[ジョブ1個] &> ");
+
+    sh.send_line("sleep 10");
+    sh.wait_foreground_job();
+    sh.send(CTRL_Z);
+    sh.expect("[ジョブ2個, 停止中1個] &> ");
+}
+
+#[test]
+fn test_command_not_found() {
+    let mut sh = Zerosh::spawn();
+
+    // 組み込みコマンドと$PATH中のコマンドから、近い名前を提案する
+    sh.send_line("hsah");
+    sh.expect("コマンドが見つかりません: hsah");
+    sh.expect("もしかして: `hash`");
+    sh.expect(PROMPT);
+
+    sh.send_line("sleeep 1");
+    sh.expect("もしかして: `sleep`");
+    sh.expect(PROMPT);
+
+    sh.send_line("echo $?");
+    sh.expect("127");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_builtin_in_pipeline() {
+    let mut sh = Zerosh::spawn();
+
+    sh.send_line("sleep 10 &");
+    sh.expect(PROMPT);
+    sh.send_line("jobs | tr a-z A-Z");
+    sh.expect("[0]+ 実行中\tSLEEP 10");
+    sh.expect(PROMPT);
+
+    sh.send_line("history | tr a-z A-Z");
+    sh.expect("SLEEP 10 &");
+    sh.expect(PROMPT);
+
+    // 出力のみを行う組み込みコマンドに限り、プロセス置換の中でも実行できる
+    sh.send_line("cat <(jobstats) | grep -c sleep");
+    sh.expect("1");
+    sh.expect(PROMPT);
+
+    // シェルの状態を変更するコマンドは、パイプラインの中では効果がないため実行できない
+    sh.send_line("cd / | cat");
+    sh.expect("cdはパイプラインの中では実行できません");
+    sh.expect(PROMPT);
+
+    sh.send_line("exit | cat");
+    sh.expect("exitはパイプラインの中では実行できません");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_stop_and_fg() {
+    let mut sh = Zerosh::spawn();
+
+    // Ctrl+Zでフォアグラウンドのジョブを停止すると、シェルに制御が戻る
+    sh.send_line("sleep 10");
+    sh.wait_foreground_job();
+    sh.send(CTRL_Z);
+    sh.expect("[0] 停止\tsleep 10");
+    sh.expect(PROMPT);
+
+    // fgで再開すると、Ctrl+Cはシェルではなくジョブに送られる
+    sh.send_line("fg %%");
+    sh.expect("0 再開\tsleep 10");
+    sh.wait_foreground_job();
+    sh.send(CTRL_C);
+    sh.expect("[0] 終了\tsleep 10");
+    sh.expect(PROMPT);
+
+    // ジョブは削除されている
+    sh.send_line("fg %%");
+    sh.expect("%%というジョブは見つかりませんでした。");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_resize_stopped_job() {
+    let mut sh = Zerosh::spawn();
+    let script = sh.write_file(
+        "winch.sh",
+        "trap 'stty size' WINCH\necho ready\nwhile :; do sleep 0.1; done\n",
+    );
+
+    // 停止中に端末の大きさが変わると、SIGWINCHはシェルにのみ送られる
+    sh.send_line(&format!("sh {script}"));
+    sh.expect("ready");
+    sh.send(CTRL_Z);
+    sh.expect("停止");
+    sh.expect(PROMPT);
+    sh.resize(40, 120);
+
+    // fgで再開する際に、ジョブにもSIGWINCHを送る
+    sh.send_line("fg");
+    sh.expect("再開");
+    sh.expect("40 120");
+    sh.wait_foreground_job();
+    sh.send(CTRL_C);
+    sh.expect(PROMPT);
+
+    // 入力中の大きさの変更ではシェルは終了せず、そのまま入力を続けられる
+    sh.resize(50, 200);
+    sh.send_line("echo resized");
+    sh.expect("resized");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_background_job() {
+    let mut sh = Zerosh::spawn();
+
+    // バックグラウンドジョブの実行中もシェルの入力を受け付ける
+    sh.send_line("sleep 1 &");
+    sh.expect("[0] ");
+    sh.expect(PROMPT);
+    sh.send_line("echo foreground");
+    sh.expect("foreground");
+
+    // バックグラウンドジョブの終了は非同期に通知される
+    sh.expect("[0] 終了\tsleep 1");
+}
+
+#[test]
+fn test_job_stats() {
+    let mut sh = Zerosh::spawn();
+
+    // jobs -lは実行中のジョブのプロセスグループIDと経過時間を表示する
+    sh.send_line("sleep 5 &");
+    sh.expect(PROMPT);
+    sh.send_line("jobs -l");
+    sh.expect("実行中\tsleep 5");
+    sh.expect("    経過 ");
+    sh.expect(PROMPT);
+
+    // jobstatsは終了したジョブのリソース使用量も表示する
+    sh.send_line("ls /nonexistent | cat");
+    sh.expect(PROMPT);
+    sh.send_line("jobstats");
+    sh.expect("] 終了 (status = 0)\tls /nonexistent | cat");
+    sh.expect("最大RSS ");
+    sh.expect("] 実行中\tsleep 5");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_ctrl_c_at_prompt() {
+    let mut sh = Zerosh::spawn();
+
+    // プロンプトでのCtrl+Cではシェルは終了しない
+    sh.send(CTRL_C);
+    sh.expect("終了はCtrl+d");
+    sh.send_line("echo alive");
+    sh.expect("alive");
+}
+
+#[test]
+fn test_exit_with_jobs() {
+    let mut sh = Zerosh::spawn();
+    sh.send_line("sleep 10");
+    sh.wait_foreground_job();
+    sh.send(CTRL_Z);
+    sh.expect("[0] 停止\tsleep 10");
+    sh.expect(PROMPT);
+
+    // ジョブが存在する場合、1回目のexitはジョブの一覧とともに警告のみ
+    sh.send_line("exit");
+    sh.expect("停止中のジョブがあります");
+    sh.expect("[0] 停止中\tsleep 10");
+    sh.expect("もう一度exitを実行すると終了します");
+    sh.expect(PROMPT);
+
+    // exit -fでジョブを終了させてから終了する
+    sh.send_line("exit -f 3");
+    sh.expect("[0] 終了させます\tsleep 10");
+    assert_eq!(sh.wait().code(), Some(3));
+}
+
+#[test]
+fn test_exit_hangs_up_stopped_jobs() {
+    let mut sh = Zerosh::spawn();
+    sh.send_line("sleep 10");
+    sh.wait_foreground_job();
+    sh.send(CTRL_Z);
+    sh.expect("[0] 停止\tsleep 10");
+    sh.expect(PROMPT);
+    sh.send_line("sleep 1 &");
+    sh.expect(PROMPT);
+
+    // 続けてexitを実行すると、停止中のジョブにはSIGHUPを送信し、実行中のジョブは残す
+    sh.send_line("exit");
+    sh.expect("[1] 実行中\tsleep 1");
+    sh.expect(PROMPT);
+    sh.send_line("exit");
+    sh.expect("[0] SIGHUPを送信します\tsleep 10");
+    sh.expect("[1] 実行を継続します\tsleep 1");
+    assert_eq!(sh.wait().code(), Some(1));
+}
+
+#[test]
+fn test_eof() {
+    let mut sh = Zerosh::spawn();
+    sh.send_line("sleep 3 &");
+    sh.expect(PROMPT);
+
+    // Ctrl+Dはexitと同様に、ジョブが存在する場合はジョブの一覧を表示し、続けて2回目で終了する
+    sh.send(CTRL_D);
+    sh.expect("実行中のジョブがあります");
+    sh.expect("[0] 実行中\tsleep 3");
+    sh.expect("もう一度Ctrl+dを入力すると終了します");
+    sh.expect(PROMPT);
+
+    // 間にコマンドを実行した場合は、改めて警告する
+    sh.send_line("true");
+    sh.expect(PROMPT);
+    sh.send(CTRL_D);
+    sh.expect("もう一度Ctrl+dを入力すると終了します");
+    sh.expect(PROMPT);
+    sh.send(CTRL_D);
+    assert_eq!(sh.wait().code(), Some(1));
+}
+
+#[test]
+fn test_exit_status() {
+    let mut sh = Zerosh::spawn();
+
+    sh.send_line("false");
+    sh.expect(PROMPT);
+    sh.send_line("echo $?");
+    sh.expect("1\n");
+    sh.expect(PROMPT);
+
+    // パイプラインの終了状態は最後のコマンドの終了状態
+    sh.send_line("true | false");
+    sh.expect(PROMPT);
+    sh.send_line("echo $?");
+    sh.expect("1\n");
+    sh.expect(PROMPT);
+
+    // 停止した場合は128+SIGTSTP
+    sh.send_line("sleep 10");
+    sh.wait_foreground_job();
+    sh.send(CTRL_Z);
+    sh.expect("[0] 停止\tsleep 10");
+    sh.expect(PROMPT);
+    sh.send_line("echo $?");
+    sh.expect("148\n");
+    sh.expect(PROMPT);
+
+    // 再開したジョブがシグナルで終了した場合は128+SIGINT
+    sh.send_line("fg %%");
+    sh.wait_foreground_job();
+    sh.send(CTRL_C);
+    sh.expect("[0] 終了\tsleep 10");
+    sh.expect(PROMPT);
+    sh.send_line("echo $?");
+    sh.expect("130\n");
+    sh.expect(PROMPT);
+
+    // バックグラウンドジョブの終了は$?に影響しない
+    sh.send_line("sh -c \"exit 3\" &");
+    sh.expect("[0] 終了");
+    sh.send_line("echo $?");
+    sh.expect("0\n");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_trap_child_signals() {
+    let mut sh = Zerosh::spawn();
+    // 子プロセスが無視しているSIGHUP、SIGPIPE、SIGTTOUのビットを表示する
+    let script = sh.write_file(
+        "sigign.sh",
+        "ign=$(grep SigIgn /proc/self/status | cut -f2)\necho ignored=$((0x$ign & 0x201001))\n",
+    );
+
+    // シェルが無視しているSIGTTOUなどは子プロセスに引き継がず、シグナルマスクも空にする
+    sh.send_line(&format!("sh {script}"));
+    sh.expect("ignored=0\n");
+    sh.expect(PROMPT);
+    sh.send_line("grep SigBlk /proc/self/status");
+    sh.expect("SigBlk:\t0000000000000000");
+    sh.expect(PROMPT);
+
+    // trap ''で指定したシグナルのみを無視させる
+    sh.send_line("trap '' HUP SIGTTOU");
+    sh.expect(PROMPT);
+    sh.send_line("trap");
+    sh.expect("trap -- '' SIGHUP\ntrap -- '' SIGTTOU");
+    sh.expect(PROMPT);
+    sh.send_line(&format!("sh {script}"));
+    sh.expect(&format!("ignored={}\n", 0x200001));
+    sh.expect(PROMPT);
+
+    sh.send_line("trap - TTOU");
+    sh.expect(PROMPT);
+    sh.send_line(&format!("sh {script}"));
+    sh.expect("ignored=1\n");
+    sh.expect(PROMPT);
+
+    sh.send_line("trap '' KILL NOSUCH");
+    sh.expect("trap: KILL: 無視できないシグナルです");
+    sh.expect("trap: NOSUCH: 不明なシグナルです");
+    sh.expect(PROMPT);
+    sh.send_line("echo $?");
+    sh.expect("1");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_detach() {
+    let mut sh = Zerosh::spawn();
+    let out = sh.write_file("detach.txt", "");
+    let script = sh.write_file(
+        "detach.sh",
+        &format!("sleep 1\nread line || echo eof > {out}\n"),
+    );
+
+    // detachしたプロセスはジョブとして登録されず、シェルの終了後も実行を続ける
+    sh.send_line(&format!("detach sh {script}"));
+    sh.expect("[切り離し] ");
+    sh.expect(PROMPT);
+    sh.send_line("exit");
+    assert_eq!(sh.wait().code(), Some(0));
+
+    // 標準入力は/dev/nullとなる
+    wait_file(&out, "eof\n");
+}
+
+#[test]
+fn test_timeout() {
+    let mut sh = Zerosh::spawn();
+
+    // 制限時間内に終了した場合はコマンドの終了状態となる
+    sh.send_line("timeout 5 false");
+    sh.expect("[0] 終了\ttimeout 5 false");
+    sh.send_line("echo $?");
+    sh.expect("1\n");
+    sh.expect(PROMPT);
+
+    // 制限時間を超えるとSIGTERMで終了し、終了コードは124
+    sh.send_line("timeout 0.5 sleep 10");
+    sh.expect("[0] タイムアウト\ttimeout 0.5 sleep 10");
+    sh.expect("[0] 終了");
+    sh.send_line("echo $?");
+    sh.expect("124\n");
+    sh.expect(PROMPT);
+
+    // SIGTERMを無視する場合はSIGKILLで終了させる
+    let script = sh.write_file("ignore_term.sh", "trap '' TERM\nsleep 10\n");
+    sh.send_line(&format!("timeout -k 0.5 0.5 sh {script}"));
+    sh.expect("タイムアウト");
+    sh.expect("強制終了");
+    sh.expect("[0] 終了");
+    sh.send_line("echo $?");
+    sh.expect("124\n");
+    sh.expect(PROMPT);
+
+    sh.send_line("timeout 5");
+    sh.expect("usage: timeout");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_schedule() {
+    let mut sh = Zerosh::spawn();
+
+    // 予約したコマンドはjobs -sで表示され、取り消せる
+    sh.send_line("schedule 1h echo later");
+    sh.expect("[s1] 予約\techo later");
+    sh.expect(PROMPT);
+    sh.send_line("jobs -s");
+    sh.expect("[s1] 予約 (あと359");
+    sh.expect("秒)\techo later");
+    sh.expect(PROMPT);
+    sh.send_line("schedule -c s1");
+    sh.expect("[s1] 取り消し");
+    sh.expect(PROMPT);
+    sh.send_line("schedule -c 1");
+    sh.expect("そのような予約はありません");
+    sh.expect(PROMPT);
+
+    sh.send_line("schedule 1 cd /");
+    sh.expect("組み込みコマンドは予約できません");
+    sh.expect(PROMPT);
+
+    // 実行時刻になると、入力を待っている間にバックグラウンドジョブとして実行される
+    sh.send_line("schedule 0.5 echo scheduled");
+    sh.expect("[s2] 予約");
+    sh.expect(PROMPT);
+    sh.expect("[s2] 開始\techo scheduled");
+    sh.expect("scheduled\n");
+    sh.expect("[0] 終了\techo scheduled");
+}
+
+#[test]
+fn test_exit_leaves_jobs() {
+    let mut sh = Zerosh::spawn();
+    sh.send_line("sleep 1 &");
+    sh.expect(PROMPT);
+
+    // ZEROSH_HUPONEXITが設定されていない場合、ジョブは実行を継続する
+    sh.send_line("exit");
+    sh.expect(PROMPT);
+    sh.send_line("exit");
+    sh.expect("[0] 実行を継続します\tsleep 1");
+    assert_eq!(sh.wait().code(), Some(1));
+}
+
+#[test]
+fn test_hup_on_exit() {
+    let mut sh = Zerosh::spawn_with_env(&[("ZEROSH_HUPONEXIT", "1")]);
+    let out = sh.write_file("hup.txt", "");
+    let script = sh.write_file(
+        "hup.sh",
+        &format!(
+            "trap 'echo hup > {out}; exit' HUP\necho ready > {out}\nwhile :; do sleep 0.1; done\n"
+        ),
+    );
+    sh.send_line(&format!("sh {script} &"));
+    sh.expect(PROMPT);
+    wait_file(&out, "ready\n");
+
+    // ZEROSH_HUPONEXITが設定されている場合、終了時にジョブへSIGHUPを送信する
+    sh.send_line("exit");
+    sh.expect(PROMPT);
+    sh.send_line("exit");
+    sh.expect("[0] SIGHUPを送信します");
+    assert_eq!(sh.wait().code(), Some(1));
+    wait_file(&out, "hup\n");
+}
+
+#[test]
+fn test_shopt() {
+    let mut sh = Zerosh::spawn();
+
+    sh.send_line("shopt");
+    sh.expect("autocd         \toff");
+    sh.expect("huponexit      \toff");
+    sh.expect(PROMPT);
+
+    // autocdを有効にすると、ディレクトリ名のみのコマンドでcdする
+    let dir = sh.write_file("dir", "");
+    std::fs::remove_file(&dir).unwrap();
+    std::fs::create_dir(&dir).unwrap();
+    sh.send_line("shopt -s --save autocd");
+    sh.expect(PROMPT);
+    sh.send_line("set -o");
+    sh.expect("autocd         \ton");
+    sh.expect(PROMPT);
+    sh.send_line(&dir);
+    sh.expect(PROMPT);
+    sh.send_line("pwd");
+    sh.expect(&dir);
+    sh.expect(PROMPT);
+
+    // --saveを指定するとrcファイルに書き込まれる
+    sh.send_line("set +o autocd");
+    sh.expect(PROMPT);
+    assert_eq!(sh.read_file(".zeroshrc"), "shopt -s autocd\n");
+
+    sh.send_line("shopt -s nosuchopt");
+    sh.expect("shopt: nosuchopt: 不明なオプション");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_rc_file() {
+    // 起動時にrcファイルを実行する
+    let mut sh = Zerosh::spawn_with_rc("shopt -s huponexit\n");
+    sh.send_line("shopt huponexit");
+    sh.expect("huponexit      \ton");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_login_shell() {
+    // ログインシェルはプロファイルを実行し、huponexitが有効になる
+    let mut sh = Zerosh::spawn_login("shopt -s autocd\n");
+    sh.send_line("shopt");
+    sh.expect("autocd         \ton");
+    sh.expect("huponexit      \ton");
+    sh.expect(PROMPT);
+
+    sh.send_line("sleep 100 &");
+    sh.expect(PROMPT);
+    sh.send_line("exit");
+    sh.expect(PROMPT);
+    sh.send_line("exit");
+    sh.expect("[0] SIGHUPを送信します\tsleep 100");
+    assert_eq!(sh.wait().code(), Some(1));
+}
+
+/// ファイルpathの内容がcontentになるまで待つ
+fn wait_file(path: &str, content: &str) {
+    for _ in 0..50 {
+        if std::fs::read_to_string(path).unwrap() == content {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let actual = std::fs::read_to_string(path).unwrap();
+    panic!("{path}の内容が{content:?}になりませんでした: {actual:?}");
+}
@@ -0,0 +1,68 @@
+//! 擬似端末上でZeroShを実行し、bindコマンドによるキー割り当てを検査する
+
+mod common;
+
+use common::{Zerosh, PROMPT};
+
+#[test]
+fn test_bind() {
+    // rcファイルのbindコマンドは起動時に反映される
+    let mut sh = Zerosh::spawn_with_rc("bind -s \\C-o echo from-rc\n");
+    sh.send("\x0f\r"); // Ctrl+O
+    sh.expect("echo from-rc");
+    sh.expect("from-rc");
+    sh.expect(PROMPT);
+
+    // insert-last-argumentは直前の行の最後の引数を挿入する
+    sh.send_line("bind --save \\C-t insert-last-argument");
+    sh.expect(PROMPT);
+    sh.send_line("echo hello world");
+    sh.expect(PROMPT);
+    sh.send("echo \x14\r"); // Ctrl+T
+    sh.expect("echo world");
+    sh.expect("world");
+    sh.expect(PROMPT);
+
+    // 一覧はrcファイルに書ける形式で表示し、--saveを指定するとrcファイルに書き込まれる
+    sh.send_line("bind");
+    sh.expect("bind -s \\C-o echo from-rc");
+    sh.expect("bind \\C-t insert-last-argument");
+    sh.expect(PROMPT);
+    assert_eq!(
+        sh.read_file(".zeroshrc"),
+        "bind -s \\C-o echo from-rc\nbind \\C-t insert-last-argument\n"
+    );
+
+    sh.send_line("bind --save -r \\C-t");
+    sh.expect(PROMPT);
+    assert_eq!(sh.read_file(".zeroshrc"), "bind -s \\C-o echo from-rc\n");
+
+    sh.send_line("bind \\C-t nosuch-function");
+    sh.expect("bind: nosuch-function: 不明な機能です");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_edit_mode() {
+    let mut sh = Zerosh::spawn();
+    sh.send_line("set -o vi");
+    sh.expect(PROMPT);
+    sh.send_line("set -o");
+    sh.expect("emacs          \toff");
+    sh.expect("vi             \ton");
+    sh.expect(PROMPT);
+
+    // viモードではEscでコマンドモードになる
+    // Escの後にすぐ入力するとキー列と解釈されるため、少し待つ
+    sh.send("echo abc\x1b");
+    std::thread::sleep(std::time::Duration::from_millis(700));
+    sh.send("0wiX\r");
+    sh.expect("[0] 終了\techo Xabc");
+    sh.expect(PROMPT);
+
+    sh.send_line("set -o emacs");
+    sh.expect(PROMPT);
+    sh.send_line("shopt vi");
+    sh.expect("vi             \toff");
+    sh.expect(PROMPT);
+}
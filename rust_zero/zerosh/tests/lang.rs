@@ -0,0 +1,29 @@
+//! 擬似端末上でZeroShを実行し、メッセージの言語の切り替えを検査する
+
+mod common;
+
+use common::{Zerosh, PROMPT};
+
+#[test]
+fn test_lang() {
+    // LANGがenで始まる場合は英語のメッセージを表示する
+    let mut sh = Zerosh::spawn_with_env(&[("LANG", "en_US.UTF-8")]);
+    sh.send_line("nonexistent_cmd_zerosh");
+    sh.expect("command not found: nonexistent_cmd_zerosh");
+    sh.expect(PROMPT);
+
+    sh.send_line("set lang");
+    sh.expect("en\n");
+    sh.expect(PROMPT);
+
+    // set langで実行中に切り替えられる
+    sh.send_line("set lang ja");
+    sh.expect(PROMPT);
+    sh.send_line("nonexistent_cmd_zerosh");
+    sh.expect("コマンドが見つかりません: nonexistent_cmd_zerosh");
+    sh.expect(PROMPT);
+
+    sh.send_line("set lang fr");
+    sh.expect("set: fr: 不明な言語です (ja, en)");
+    sh.expect(PROMPT);
+}
@@ -0,0 +1,173 @@
+//! 擬似端末上でZeroShを実行し、リダイレクトの振る舞いを検査する
+
+mod common;
+
+use common::{Zerosh, PROMPT};
+use std::os::unix::fs::PermissionsExt;
+
+/// 標準出力にout、標準エラー出力にerrと出力するスクリプト
+const OUT_ERR: &str = "echo out\necho err >&2\n";
+
+#[test]
+fn test_dup_left_to_right() {
+    let mut sh = Zerosh::spawn();
+    let script = sh.write_file("out_err.sh", OUT_ERR);
+
+    // 2>&1の時点の標準出力(パイプ)に標準エラー出力を複製してから、標準出力を捨てる
+    sh.send_line(&format!("sh {script} 2>&1 >/dev/null | tr a-z A-Z"));
+    let out = sh.expect(PROMPT);
+    assert!(out.contains("ERR"), "{out}");
+    assert!(!out.contains("OUT"), "{out}");
+}
+
+#[test]
+fn test_swap_stdout_stderr() {
+    let mut sh = Zerosh::spawn();
+    let script = sh.write_file("out_err.sh", OUT_ERR);
+
+    // 3番を経由して標準出力と標準エラー出力を入れ替え、3番はクローズする
+    sh.send_line(&format!("sh {script} 3>&1 1>&2 2>&3 3>&- | tr a-z A-Z"));
+    let out = sh.expect(PROMPT);
+    assert!(out.contains("ERR"), "{out}");
+    assert!(out.contains("out"), "{out}");
+}
+
+#[test]
+fn test_close() {
+    let mut sh = Zerosh::spawn();
+    let script = sh.write_file("echo.sh", "echo hi || echo closed >&2\n");
+
+    // 標準出力をクローズすると書き込みに失敗する
+    sh.send_line(&format!("sh {script} >&-"));
+    let out = sh.expect(PROMPT);
+    assert!(out.contains("closed"), "{out}");
+
+    // 不正なファイルディスクリプタの複製は失敗する
+    sh.send_line("echo hi >&99");
+    sh.expect("リダイレクトに失敗");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_umask() {
+    let mut sh = Zerosh::spawn();
+    let file = sh.write_file("umask.txt", "");
+    std::fs::remove_file(&file).unwrap();
+
+    sh.send_line("umask 027");
+    sh.expect(PROMPT);
+    sh.send_line("umask");
+    sh.expect("0027");
+    sh.send_line("umask -S");
+    sh.expect("u=rwx,g=rx,o=");
+    sh.expect(PROMPT);
+
+    // リダイレクトで作成されるファイルにも適用される
+    sh.send_line(&format!("echo hi >{file}"));
+    sh.expect(PROMPT);
+    let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+}
+
+#[test]
+fn test_builtin_redirect() {
+    let mut sh = Zerosh::spawn();
+    let out = sh.write_file("out.txt", "");
+    let err = sh.write_file("err.txt", "");
+
+    // 組み込みコマンドの出力をリダイレクトしても、プロンプトは端末に表示される
+    sh.send_line(&format!("shopt autocd >{out}"));
+    sh.expect(PROMPT);
+    assert_eq!(sh.read_file("out.txt"), "autocd         \toff\n");
+
+    // 左から順に適用する
+    sh.send_line(&format!("cd /nonexistent 2>{err} >&2"));
+    sh.expect(PROMPT);
+    let msg = sh.read_file("err.txt");
+    assert!(msg.starts_with("cd: /nonexistent:"), "{msg}");
+
+    // リダイレクトは組み込みコマンドの実行中のみ適用される
+    sh.send_line("shopt autocd");
+    sh.expect("autocd");
+    sh.expect(PROMPT);
+    assert_eq!(sh.read_file("out.txt"), "autocd         \toff\n");
+
+    sh.send_line("umask 3>/dev/null");
+    sh.expect("0から2のみリダイレクトできます");
+    sh.expect(PROMPT);
+}
+
+#[test]
+fn test_noclobber() {
+    let mut sh = Zerosh::spawn();
+    let out = sh.write_file("out.txt", "old\n");
+    let new = format!("{out}.new");
+
+    sh.send_line("set -C");
+    sh.expect(PROMPT);
+
+    // 既存のファイルは>で上書きできない
+    sh.send_line(&format!("echo new >{out}"));
+    sh.expect("既存のファイルは上書きできません");
+    sh.expect(PROMPT);
+    assert_eq!(sh.read_file("out.txt"), "old\n");
+
+    // 組み込みコマンドでも同様
+    sh.send_line(&format!("shopt >{out}"));
+    sh.expect("既存のファイルは上書きできません");
+    sh.expect(PROMPT);
+
+    // >>による追記、新規作成、通常ファイル以外への書き込みはできる
+    sh.send_line(&format!("echo append >>{out}"));
+    sh.expect(PROMPT);
+    sh.send_line(&format!("echo new >{new}"));
+    sh.expect(PROMPT);
+    sh.send_line("echo null >/dev/null");
+    sh.expect(PROMPT);
+    assert_eq!(sh.read_file("out.txt"), "old\nappend\n");
+    assert_eq!(sh.read_file("out.txt.new"), "new\n");
+
+    // >|は上書きする
+    sh.send_line(&format!("echo clobber >| {out} | cat"));
+    sh.expect(PROMPT);
+    assert_eq!(sh.read_file("out.txt"), "clobber\n");
+
+    sh.send_line("set -o");
+    sh.expect("noclobber      \ton");
+    sh.expect(PROMPT);
+
+    sh.send_line("set +C");
+    sh.expect(PROMPT);
+    sh.send_line(&format!("echo new >{out}"));
+    sh.expect(PROMPT);
+    assert_eq!(sh.read_file("out.txt"), "new\n");
+}
+
+#[test]
+fn test_process_substitution() {
+    let mut sh = Zerosh::spawn();
+    let a = sh.write_file("a.txt", "b\na\nc\n");
+    let b = sh.write_file("b.txt", "c\nb\nd\n");
+
+    // ソートした結果どうしを比較する
+    sh.send_line(&format!("diff <(sort {a}) <(sort {b})"));
+    let out = sh.expect(PROMPT);
+    assert!(out.contains("< a"), "{out}");
+    assert!(out.contains("> d"), "{out}");
+    assert!(!out.contains("< b"), "{out}");
+
+    // >(cmd)への書き込みはcmdの標準入力となる
+    let out = format!("{a}.upper");
+    sh.send_line(&format!("cat {a} > >(tr a-z A-Z >{out})"));
+    sh.expect(PROMPT);
+    assert_eq!(sh.read_file("a.txt.upper"), "B\nA\nC\n");
+
+    // パイプラインと組み合わせられる
+    sh.send_line("cat <(echo piped) | tr a-z A-Z");
+    sh.expect("PIPED");
+    sh.expect(PROMPT);
+
+    sh.send_line("cd <(echo /)");
+    sh.expect("組み込みコマンドには利用できません");
+    sh.expect(PROMPT);
+}